@@ -0,0 +1,110 @@
+//! Random 4 KiB I/O and sequential reads on a 4 GiB dynamic VHD in the temp directory
+//!
+//! `cargo bench --bench sparse_vhd`. The single entry bitmap cache is the previous behavior,
+//! reading the image file as is gives the `dd` baseline.
+use rdisk::prelude::*;
+use rdisk::vhd::{VhdImage, BITMAP_CACHE_SIZE};
+use std::time::{Duration, Instant};
+
+const DISK_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+const IO_SIZE: usize = 4096;
+const COPY_CHUNK: usize = 1024 * 1024;
+/// Allocated blocks spread over the disk, the first half of each one is written
+const BLOCKS: u64 = 64;
+const RANDOM_IOS: usize = 100_000;
+
+/// xorshift64, the benchmark has to be reproducible
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn block_offset(block_size: u64, block: u64) -> u64 {
+    block * (DISK_SIZE / BLOCKS / block_size) * block_size
+}
+
+fn prepare(path: &str) -> u64 {
+    let disk = VhdImage::create_dynamic(path, DISK_SIZE).unwrap();
+    let block_size = disk.sparse_header().unwrap().block_size as u64;
+
+    let data = vec![0x5A_u8; block_size as usize / 2];
+    for block in 0..BLOCKS {
+        disk.write_all_at(block_offset(block_size, block), &data).unwrap();
+    }
+
+    block_size
+}
+
+fn random_io(disk: &VhdImage, block_size: u64) -> Duration {
+    let mut random = Random(0x2545_F491_4F6C_DD1D);
+    let mut buffer = vec![0_u8; IO_SIZE];
+
+    let start = Instant::now();
+    for _ in 0..RANDOM_IOS {
+        let value = random.next();
+        let block = value % BLOCKS;
+        let io = (value >> 8) % (block_size / IO_SIZE as u64);
+        let offset = block_offset(block_size, block) + io * IO_SIZE as u64;
+        if value & 0x80 == 0 {
+            disk.read_exact_at(offset, &mut buffer).unwrap();
+        } else {
+            disk.write_all_at(offset, &buffer).unwrap();
+        }
+    }
+    disk.flush().unwrap();
+
+    start.elapsed()
+}
+
+/// Reads the allocated blocks, `block_offset` maps the block number to its offset on the `device`
+fn sequential_read(device: &impl ReadAt, block_size: u64, block_offset: impl Fn(u64) -> u64) -> Duration {
+    let mut buffer = vec![0_u8; COPY_CHUNK];
+
+    let start = Instant::now();
+    for block in 0..BLOCKS {
+        let offset = block_offset(block);
+        for chunk in 0..block_size / COPY_CHUNK as u64 {
+            device.read_exact_at(offset + chunk * COPY_CHUNK as u64, &mut buffer).unwrap();
+        }
+    }
+
+    start.elapsed()
+}
+
+fn main() {
+    let path = std::env::temp_dir().join("rdisk_sparse_bench.vhd").to_string_lossy().to_string();
+    let block_size = prepare(&path);
+    let total_mib = (BLOCKS * block_size) as f64 / (1024.0 * 1024.0);
+
+    for &cache_size in &[1, BITMAP_CACHE_SIZE] {
+        let disk = VhdImage::open(path.as_str()).unwrap();
+        disk.set_bitmap_cache_size(cache_size).unwrap();
+
+        let random = random_io(&disk, block_size);
+        let sequential = sequential_read(&disk, block_size, |block| block_offset(block_size, block));
+        println!(
+            "bitmap cache {:>3}: random {} KiB {:>9.0} IOPS, sequential read {:>8.1} MiB/s",
+            cache_size,
+            IO_SIZE / 1024,
+            RANDOM_IOS as f64 / random.as_secs_f64(),
+            total_mib / sequential.as_secs_f64()
+        );
+    }
+
+    // the same amount of data read from the image file as is
+    let file = File::open(&path).unwrap();
+    let raw = sequential_read(&file, block_size, |block| block * block_size);
+    println!(
+        "image file:                                     sequential read {:>8.1} MiB/s",
+        total_mib / raw.as_secs_f64()
+    );
+
+    drop(file);
+    let _ = std::fs::remove_file(&path);
+}
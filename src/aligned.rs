@@ -0,0 +1,232 @@
+use crate::prelude::*;
+use crate::sync::RwLock;
+use alloc::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use core::ptr::NonNull;
+
+/// Heap buffer for direct (unbuffered) I/O, its address and length are multiples of the alignment
+///
+/// `Vec<u8>` from [`alloc_buffer`](crate::alloc_buffer) has no alignment guarantee,
+/// a file or device opened with [`OpenOptions::direct`] rejects such buffers.
+pub struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+// The buffer owns its memory like a `Vec<u8>`
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+impl AlignedBuffer {
+    /// Zero filled buffer, `len` is rounded up to `align` that must be a power of two
+    pub fn new(len: usize, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+
+        let len = math::round_up(len, align);
+        // zero sized allocations are not allowed
+        let layout = Layout::from_size_align(core::cmp::max(len, align), align).expect("buffer is too large");
+        let ptr = unsafe { alloc_zeroed(layout) };
+        match NonNull::new(ptr) {
+            Some(ptr) => Self { ptr, len, layout },
+            None => handle_alloc_error(layout),
+        }
+    }
+
+    /// Buffer aligned to the physical sector size of the disk
+    pub fn for_disk<D: Disk + ?Sized>(disk: &D, len: usize) -> Result<Self> {
+        Ok(Self::new(len, disk.physical_sector_size()? as usize))
+    }
+
+    pub fn alignment(&self) -> usize {
+        self.layout.align()
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+impl core::ops::Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl core::ops::DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+#[cfg(any(feature = "std", test))]
+impl core::fmt::Debug for AlignedBuffer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AlignedBuffer")
+            .field("len", &self.len)
+            .field("alignment", &self.alignment())
+            .finish()
+    }
+}
+
+/// The offset, the length and the buffer address are all multiples of `align`
+pub(crate) fn is_aligned(offset: u64, buffer: &[u8], align: usize) -> bool {
+    offset.is_multiple_of(align as u64) && buffer.len().is_multiple_of(align) && (buffer.as_ptr() as usize).is_multiple_of(align)
+}
+
+/// Reads through an aligned bounce buffer unless the request is aligned already,
+/// `read` gets aligned requests only
+pub(crate) fn read_bounced<F>(align: usize, offset: u64, buffer: &mut [u8], read: F) -> Result<usize>
+where
+    F: Fn(u64, &mut [u8]) -> Result<usize>,
+{
+    if is_aligned(offset, buffer, align) {
+        return read(offset, buffer);
+    }
+
+    let start = math::round_down(offset, align as u64);
+    let skip = (offset - start) as usize;
+    let mut bounce = AlignedBuffer::new(skip + buffer.len(), align);
+    let n = read(start, &mut bounce)?;
+    if n <= skip {
+        return Ok(0);
+    }
+
+    let len = core::cmp::min(buffer.len(), n - skip);
+    buffer[..len].copy_from_slice(&bounce[skip..skip + len]);
+    Ok(len)
+}
+
+/// Writes through an aligned bounce buffer unless the request is aligned already,
+/// the partial sectors at the edges are read first. `read` and `write` get aligned requests only.
+///
+/// The last partial sector is padded, a file grows past the request end then and the caller cuts the padding off,
+/// see [`padded_cut`]. The read-modify-write is not atomic, the caller serializes the writes by [`DirectWriteLock`].
+pub(crate) fn write_bounced<R, W>(align: usize, offset: u64, data: &[u8], read: R, write: W) -> Result<usize>
+where
+    R: Fn(u64, &mut [u8]) -> Result<usize>,
+    W: Fn(u64, &[u8]) -> Result<usize>,
+{
+    if is_aligned(offset, data, align) {
+        return write(offset, data);
+    }
+
+    let start = math::round_down(offset, align as u64);
+    let skip = (offset - start) as usize;
+    let mut bounce = AlignedBuffer::new(skip + data.len(), align);
+    let tail = bounce.len() - align;
+    // the sectors past the end of the storage stay zeroed
+    if skip != 0 || data.len() < align {
+        read(start, &mut bounce[..align])?;
+    }
+    if skip + data.len() != bounce.len() && tail != 0 {
+        read(start + tail as u64, &mut bounce[tail..])?;
+    }
+    bounce[skip..skip + data.len()].copy_from_slice(data);
+
+    let mut pos = 0;
+    while pos < bounce.len() {
+        match write(start + pos as u64, &bounce[pos..])? {
+            0 => return Err(Error::WriteZero),
+            n => pos += n,
+        }
+    }
+
+    Ok(data.len())
+}
+
+/// Serializes the [`write_bounced`] requests of one file: the ones with partial sectors hold it exclusively
+/// across the read, the write and the [cut](padded_cut), the whole sector ones share it
+#[derive(Default)]
+pub(crate) struct DirectWriteLock(RwLock<()>);
+
+impl DirectWriteLock {
+    pub fn write<T>(&self, align: usize, offset: u64, len: usize, write: impl FnOnce() -> Result<T>) -> Result<T> {
+        if offset.is_multiple_of(align as u64) && len.is_multiple_of(align) {
+            let _shared = self.0.read();
+            write()
+        } else {
+            let _exclusive = self.0.write();
+            write()
+        }
+    }
+}
+
+impl core::fmt::Debug for DirectWriteLock {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("DirectWriteLock")
+    }
+}
+
+/// The length to cut a `size` bytes file back to if the [`write_bounced`] request ending at `end`
+/// has grown it by the padding
+pub(crate) fn padded_cut(align: usize, end: u64, size: u64) -> Option<u64> {
+    if math::round_up(end, align as u64) > size {
+        Some(core::cmp::max(end, size))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStream;
+
+    #[test]
+    fn aligned_buffer_test() {
+        let buffer = AlignedBuffer::new(1000, 512);
+        assert_eq!(1024, buffer.len());
+        assert_eq!(512, buffer.alignment());
+        assert_eq!(0, buffer.as_ptr() as usize % 512);
+        assert!(buffer.iter().all(|&b| b == 0));
+
+        let disk = crate::MemoryDisk::new(MemoryStream::new(), 8192).with_sector_size(512, 4096);
+        let buffer = AlignedBuffer::for_disk(&disk, 1).unwrap();
+        assert_eq!(4096, buffer.len());
+        assert_eq!(0, buffer.as_ptr() as usize % 4096);
+
+        assert!(AlignedBuffer::new(0, 8).is_empty());
+    }
+
+    #[test]
+    fn bounce_test() {
+        let stream = MemoryStream::from_vec((0..2048).map(|i| (i / 512) as u8).collect());
+        // the stream must see aligned requests only
+        let read = |offset: u64, buffer: &mut [u8]| {
+            assert!(is_aligned(offset, buffer, 512));
+            stream.read_at(offset, buffer)
+        };
+        let write = |offset: u64, data: &[u8]| {
+            assert!(is_aligned(offset, data, 512));
+            stream.write_at(offset, data)
+        };
+
+        let mut buffer = vec![0_u8; 4];
+        assert_eq!(4, read_bounced(512, 510, &mut buffer, read).unwrap());
+        assert_eq!(&[0, 0, 1, 1], buffer.as_slice());
+        assert_eq!(2, read_bounced(512, 2046, &mut buffer, read).unwrap());
+        assert_eq!(0, read_bounced(512, 2048, &mut buffer, read).unwrap());
+
+        assert_eq!(4, write_bounced(512, 1022, b"edge", read, write).unwrap());
+        let data = stream.to_vec();
+        assert_eq!(b"edge", &data[1022..1026]);
+        assert!(data[512..1022].iter().all(|&b| b == 1));
+        assert!(data[1026..1536].iter().all(|&b| b == 2));
+        assert_eq!(2048, stream.size().unwrap());
+
+        // the padding is cut off only when it goes past the end of the file
+        assert_eq!(None, padded_cut(512, 1026, 2048));
+        assert_eq!(None, padded_cut(512, 2000, 2048));
+        assert_eq!(Some(2000), padded_cut(512, 1990, 2000));
+        assert_eq!(Some(2100), padded_cut(512, 2100, 2000));
+
+        let mut aligned = AlignedBuffer::new(512, 512);
+        aligned[0] = 0xAA;
+        assert_eq!(512, write_bounced(512, 0, &aligned, read, write).unwrap());
+        assert_eq!(0xAA, stream.to_vec()[0]);
+    }
+}
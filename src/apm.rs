@@ -0,0 +1,241 @@
+//! Apple Partition Map
+//!
+//! https://en.wikipedia.org/wiki/Apple_Partition_Map
+use crate::prelude::*;
+
+/// Driver Descriptor Record, block 0
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct DriverDescriptorRecord {
+    signature: u16,
+    block_size: u16,
+    block_count: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct PartitionMapEntryRecord {
+    signature: u16,
+    signature_pad: u16,
+    map_entries: u32,
+    first_block: u32,
+    block_count: u32,
+    name: [u8; 32],
+    kind: [u8; 32],
+    data_start: u32,
+    data_count: u32,
+    status: u32,
+    boot_start: u32,
+    boot_size: u32,
+    boot_address: u32,
+    boot_address2: u32,
+    boot_entry: u32,
+    boot_entry2: u32,
+    boot_checksum: u32,
+    processor: [u8; 16],
+}
+
+const DDR_SIGNATURE: u16 = 0x4552; // "ER"
+const ENTRY_SIGNATURE: u16 = 0x504D; // "PM"
+const DEFAULT_BLOCK_SIZE: u64 = 512;
+const MAX_MAP_ENTRIES: u32 = 256;
+
+pub const FREE_PARTITION_KIND: &str = "Apple_Free";
+pub const PARTITION_MAP_KIND: &str = "Apple_partition_map";
+
+// pmPartStatus bits
+pub const STATUS_VALID: u32 = 0x0000_0001;
+pub const STATUS_ALLOCATED: u32 = 0x0000_0002;
+pub const STATUS_IN_USE: u32 = 0x0000_0004;
+pub const STATUS_BOOTABLE: u32 = 0x0000_0008;
+pub const STATUS_READABLE: u32 = 0x0000_0010;
+pub const STATUS_WRITABLE: u32 = 0x0000_0020;
+
+pub struct PartitionInfo {
+    pub offset: u64,
+    pub length: u64,
+    pub name: String,
+    pub kind: String,
+    pub flags: u32,
+}
+
+impl PartitionInfo {
+    pub fn is_free(&self) -> bool {
+        self.kind == FREE_PARTITION_KIND
+    }
+}
+
+pub struct Layout {
+    block_size: u32,
+    block_count: u32,
+    partitions: Vec<PartitionInfo>,
+}
+
+impl Layout {
+    /// Returns `None` if there is no "ER" and "PM" signatures at the disk start.
+    pub(crate) fn read(disk: &impl Disk) -> Result<Option<Layout>> {
+        let ddr: DriverDescriptorRecord = tools::read_disk_struct(disk, 0)?;
+        if u16::from_be(ddr.signature) != DDR_SIGNATURE {
+            return Ok(None);
+        }
+
+        let block_count = u32::from_be(ddr.block_count);
+        let block_size = match u16::from_be(ddr.block_size) as u64 {
+            0 => DEFAULT_BLOCK_SIZE,
+            size => size,
+        };
+
+        let mut partitions = Vec::new();
+        let mut map_entries = 1_u32;
+        let mut index = 1_u32;
+        while index <= map_entries {
+            let entry: PartitionMapEntryRecord = tools::read_disk_struct(disk, index as u64 * block_size)?;
+            if u16::from_be(entry.signature) != ENTRY_SIGNATURE {
+                if index == 1 {
+                    return Ok(None);
+                }
+                // TODO: log warning
+                break;
+            }
+
+            if index == 1 {
+                map_entries = core::cmp::min(u32::from_be(entry.map_entries), MAX_MAP_ENTRIES);
+            }
+
+            partitions.push(PartitionInfo {
+                offset: u32::from_be(entry.first_block) as u64 * block_size,
+                length: u32::from_be(entry.block_count) as u64 * block_size,
+                name: tools::string_from_ascii_z(&entry.name),
+                kind: tools::string_from_ascii_z(&entry.kind),
+                flags: u32::from_be(entry.status),
+            });
+
+            index += 1;
+        }
+
+        Ok(Some(Layout {
+            block_size: block_size as u32,
+            block_count,
+            partitions,
+        }))
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    /// Disk size in blocks as reported by the Driver Descriptor Record
+    pub fn block_count(&self) -> u32 {
+        self.block_count
+    }
+
+    pub fn partitions(&self) -> &[PartitionInfo] {
+        &self.partitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+
+    fn ddr(block_size: u16, block_count: u32) -> Vec<u8> {
+        let mut bytes = vec![0_u8; 512];
+        bytes[..2].copy_from_slice(&DDR_SIGNATURE.to_be_bytes());
+        bytes[2..4].copy_from_slice(&block_size.to_be_bytes());
+        bytes[4..8].copy_from_slice(&block_count.to_be_bytes());
+        bytes
+    }
+
+    fn entry(map_entries: u32, first_block: u32, block_count: u32, name: &str, kind: &str, status: u32) -> Vec<u8> {
+        let mut bytes = vec![0_u8; 512];
+        bytes[..2].copy_from_slice(&ENTRY_SIGNATURE.to_be_bytes());
+        bytes[4..8].copy_from_slice(&map_entries.to_be_bytes());
+        bytes[8..12].copy_from_slice(&first_block.to_be_bytes());
+        bytes[12..16].copy_from_slice(&block_count.to_be_bytes());
+        bytes[16..16 + name.len()].copy_from_slice(name.as_bytes());
+        bytes[48..48 + kind.len()].copy_from_slice(kind.as_bytes());
+        bytes[88..92].copy_from_slice(&status.to_be_bytes());
+        bytes
+    }
+
+    /// The map `hdiutil create -layout SPUD` writes: the map itself, an HFS volume and the free space after it
+    fn hdiutil_map(block_size: u16, map_entries: u32) -> MemoryDisk {
+        let disk = MemoryDisk::with_capacity(1024 * 1024);
+        let step = match block_size {
+            0 => 512,
+            size => size as u64,
+        };
+        disk.write_all_at(0, &ddr(block_size, 2048)).unwrap();
+        let entries = [
+            entry(map_entries, 1, 63, "Apple", PARTITION_MAP_KIND, 0x0000_0003),
+            entry(map_entries, 64, 1974, "disk image", "Apple_HFS", 0x4000_0033),
+            entry(map_entries, 2038, 10, "", FREE_PARTITION_KIND, 0),
+        ];
+        for (i, bytes) in entries.iter().enumerate() {
+            disk.write_all_at((i as u64 + 1) * step, bytes).unwrap();
+        }
+        disk
+    }
+
+    #[test]
+    fn read_test() {
+        let layout = Layout::read(&hdiutil_map(512, 3)).unwrap().unwrap();
+        assert_eq!(512, layout.block_size());
+        assert_eq!(2048, layout.block_count());
+
+        let partitions = layout.partitions();
+        assert_eq!(3, partitions.len());
+        assert_eq!((512, 63 * 512), (partitions[0].offset, partitions[0].length));
+        assert_eq!("Apple", partitions[0].name);
+        assert_eq!(PARTITION_MAP_KIND, partitions[0].kind);
+        assert_eq!((64 * 512, 1974 * 512), (partitions[1].offset, partitions[1].length));
+        assert_eq!("disk image", partitions[1].name);
+        assert_eq!("Apple_HFS", partitions[1].kind);
+        assert_eq!(
+            STATUS_VALID | STATUS_ALLOCATED,
+            partitions[1].flags & (STATUS_VALID | STATUS_ALLOCATED)
+        );
+        assert_eq!(0, partitions[1].flags & STATUS_BOOTABLE);
+        assert!(!partitions[1].is_free());
+        assert!(partitions[2].is_free());
+
+        // a disk without the signatures is not APM
+        assert!(Layout::read(&MemoryDisk::with_capacity(4096)).unwrap().is_none());
+        let disk = MemoryDisk::with_capacity(4096);
+        disk.write_all_at(0, &ddr(512, 8)).unwrap();
+        assert!(Layout::read(&disk).unwrap().is_none());
+    }
+
+    #[test]
+    fn bounds_test() {
+        // the zero block size is 512 bytes
+        let layout = Layout::read(&hdiutil_map(0, 3)).unwrap().unwrap();
+        assert_eq!(512, layout.block_size());
+        assert_eq!(64 * 512, layout.partitions()[1].offset);
+
+        // CD images use 2 KiB blocks
+        let layout = Layout::read(&hdiutil_map(2048, 3)).unwrap().unwrap();
+        assert_eq!(2048, layout.block_size());
+        assert_eq!(3, layout.partitions().len());
+        assert_eq!(64 * 2048, layout.partitions()[1].offset);
+
+        // the map stops at the first entry without the signature
+        let layout = Layout::read(&hdiutil_map(512, u32::MAX)).unwrap().unwrap();
+        assert_eq!(3, layout.partitions().len());
+
+        // and is never longer than MAX_MAP_ENTRIES
+        let disk = MemoryDisk::with_capacity(1024 * 1024);
+        disk.write_all_at(0, &ddr(512, 2048)).unwrap();
+        for i in 1..=MAX_MAP_ENTRIES + 10 {
+            disk.write_all_at(i as u64 * 512, &entry(u32::MAX, i, 1, "", FREE_PARTITION_KIND, 0))
+                .unwrap();
+        }
+        let layout = Layout::read(&disk).unwrap().unwrap();
+        assert_eq!(MAX_MAP_ENTRIES as usize, layout.partitions().len());
+
+        // the map ends at the entry count of the first entry
+        let disk = hdiutil_map(512, 2);
+        assert_eq!(2, Layout::read(&disk).unwrap().unwrap().partitions().len());
+    }
+}
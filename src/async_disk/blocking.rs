@@ -0,0 +1,128 @@
+use super::*;
+use alloc::sync::Arc;
+
+/// Sync disk or stream run on the tokio blocking pool, the runtime threads never wait for its I/O
+///
+/// The pool tasks can not borrow the caller buffers, the data is copied through temporary ones.
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Blocking<T> {
+    inner: Arc<T>,
+}
+
+impl<T> Clone for Blocking<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Send + Sync + 'static> Blocking<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner: Arc::new(inner) }
+    }
+
+    /// Shares the disk with the sync code
+    pub fn from_arc(inner: Arc<T>) -> Self {
+        Self { inner }
+    }
+
+    /// For the sync calls that are cheap enough to run on a runtime thread
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    async fn run<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&T) -> Result<R> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || f(&inner))
+            .await
+            .map_err(|e| Error::Io(e.into()))?
+    }
+}
+
+impl<T: ReadAt + Send + Sync + 'static> AsyncReadAt for Blocking<T> {
+    async fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = buffer.len();
+        let data = self
+            .run(move |inner| {
+                let mut data = unsafe { tools::alloc_buffer(len) };
+                let n = inner.read_at(offset, &mut data)?;
+                data.truncate(n);
+                Ok(data)
+            })
+            .await?;
+
+        buffer[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    /// One pool task for the whole buffer
+    async fn read_exact_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        let len = buffer.len();
+        let data = self
+            .run(move |inner| {
+                let mut data = unsafe { tools::alloc_buffer(len) };
+                inner.read_exact_at(offset, &mut data)?;
+                Ok(data)
+            })
+            .await?;
+
+        buffer.copy_from_slice(&data);
+        Ok(())
+    }
+}
+
+impl<T: WriteAt + Send + Sync + 'static> AsyncWriteAt for Blocking<T> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> impl Future<Output = Result<usize>> + Send {
+        let data = data.to_vec();
+        self.run(move |inner| inner.write_at(offset, &data))
+    }
+
+    /// One pool task for the whole buffer
+    fn write_all_at(&self, offset: u64, data: &[u8]) -> impl Future<Output = Result<()>> + Send {
+        let data = data.to_vec();
+        self.run(move |inner| inner.write_all_at(offset, &data))
+    }
+}
+
+impl<T: Flush + Send + Sync + 'static> AsyncFlush for Blocking<T> {
+    fn flush(&self) -> impl Future<Output = Result<()>> + Send {
+        self.run(|inner| inner.flush())
+    }
+}
+
+impl<T: Stream + Send + Sync + 'static> AsyncStream for Blocking<T> {
+    fn size(&self) -> impl Future<Output = Result<u64>> + Send {
+        self.run(|inner| inner.size())
+    }
+}
+
+impl<T: Disk + Send + Sync + 'static> AsyncDisk for Blocking<T> {
+    fn geometry(&self) -> impl Future<Output = Result<Geometry>> + Send {
+        self.run(|inner| inner.geometry())
+    }
+
+    fn capacity(&self) -> impl Future<Output = Result<u64>> + Send {
+        self.run(|inner| inner.capacity())
+    }
+
+    fn physical_sector_size(&self) -> impl Future<Output = Result<u32>> + Send {
+        self.run(|inner| inner.physical_sector_size())
+    }
+
+    fn logical_sector_size(&self) -> impl Future<Output = Result<u32>> + Send {
+        self.run(|inner| inner.logical_sector_size())
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> impl Future<Output = Result<()>> + Send {
+        self.run(move |inner| inner.discard(offset, len))
+    }
+
+    fn write_zeroes(&self, offset: u64, len: u64) -> impl Future<Output = Result<()>> + Send {
+        self.run(move |inner| inner.write_zeroes(offset, len))
+    }
+}
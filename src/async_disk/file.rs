@@ -0,0 +1,99 @@
+use super::*;
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+/// File opened with tokio, the backing storage of the async images
+///
+/// tokio files have a cursor, the positioned requests seek under a lock and do not run in parallel.
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct AsyncFile {
+    file: Mutex<tokio::fs::File>,
+}
+
+impl AsyncFile {
+    pub async fn open(path: &str) -> Result<Self> {
+        Self::open_with(path, &OpenOptions::default()).await
+    }
+
+    /// [`OpenOptions::exclusive`] and [`OpenOptions::direct`] are not supported, the file is shared and cached
+    pub async fn open_with(path: &str, options: &OpenOptions) -> Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .write(!options.read_only)
+            .open(path)
+            .await?;
+
+        Ok(Self::from_tokio(file))
+    }
+
+    /// Creates a new file or truncates the existing one
+    pub async fn create(path: &str) -> Result<Self> {
+        let file = tokio::fs::File::create(path).await?;
+        Ok(Self::from_tokio(file))
+    }
+
+    pub fn from_tokio(file: tokio::fs::File) -> Self {
+        Self { file: Mutex::new(file) }
+    }
+}
+
+impl AsyncReadAt for AsyncFile {
+    async fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let mut file = self.file.lock().await;
+        file.seek(SeekFrom::Start(offset)).await?;
+        Ok(file.read(buffer).await?)
+    }
+}
+
+impl AsyncWriteAt for AsyncFile {
+    async fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let mut file = self.file.lock().await;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let n = file.write(data).await?;
+        // tokio completes the write in the background, its error is reported here and not by the next call
+        file.flush().await?;
+        Ok(n)
+    }
+}
+
+impl AsyncFlush for AsyncFile {
+    async fn flush(&self) -> Result<()> {
+        let mut file = self.file.lock().await;
+        file.flush().await?;
+        Ok(file.sync_data().await?)
+    }
+}
+
+impl AsyncStream for AsyncFile {
+    async fn size(&self) -> Result<u64> {
+        Ok(self.file.lock().await.metadata().await?.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn async_file_test() {
+        let path = std::env::temp_dir().join(format!("rdisk_async_file_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let file = AsyncFile::create(&path).await.unwrap();
+            file.write_all_at(4096, b"file").await.unwrap();
+            file.flush().await.unwrap();
+            assert_eq!(4100, file.size().await.unwrap());
+
+            let file = AsyncFile::open_with(&path, &OpenOptions::read_only()).await.unwrap();
+            let mut buffer = [0xFF_u8; 8];
+            file.read_exact_at(4092, &mut buffer).await.unwrap();
+            assert_eq!(b"\0\0\0\0file", &buffer);
+            assert!(matches!(file.read_exact_at(4096, &mut buffer).await, Err(Error::UnexpectedEOD)));
+            assert!(file.write_at(0, b"x").await.is_err());
+        });
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+//! Async mirrors of [`ReadAt`], [`WriteAt`], [`Flush`], [`Stream`] and [`Disk`] for async services
+//!
+//! The layout and VHD metadata parsers stay sync: they run against a [`ReplayDisk`] made of the chunks
+//! fetched so far, and are run again after the chunks missing in a run are read asynchronously.
+use crate::prelude::*;
+use alloc::collections::BTreeSet;
+use core::cell::RefCell;
+use core::future::Future;
+
+#[cfg(feature = "tokio")]
+mod blocking;
+#[cfg(feature = "tokio")]
+pub use blocking::Blocking;
+
+#[cfg(feature = "tokio")]
+mod file;
+#[cfg(feature = "tokio")]
+pub use file::AsyncFile;
+
+pub trait AsyncReadAt: Send + Sync {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> impl Future<Output = Result<usize>> + Send;
+
+    fn read_exact_at(&self, offset: u64, buffer: &mut [u8]) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            let mut offset = offset;
+            let mut buffer = buffer;
+            while !buffer.is_empty() {
+                match self.read_at(offset, buffer).await? {
+                    0 => return Err(Error::UnexpectedEOD),
+                    n => {
+                        buffer = &mut buffer[n..];
+                        offset += n as u64;
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+pub trait AsyncWriteAt: Send + Sync {
+    fn write_at(&self, offset: u64, data: &[u8]) -> impl Future<Output = Result<usize>> + Send;
+
+    fn write_all_at(&self, offset: u64, data: &[u8]) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            let mut offset = offset;
+            let mut data = data;
+            while !data.is_empty() {
+                match self.write_at(offset, data).await? {
+                    0 => return Err(Error::WriteZero),
+                    n => {
+                        data = &data[n..];
+                        offset += n as u64;
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+pub trait AsyncFlush: Send + Sync {
+    fn flush(&self) -> impl Future<Output = Result<()>> + Send;
+}
+
+pub trait AsyncStream: AsyncReadAt + AsyncWriteAt + AsyncFlush {
+    /// Current size in bytes, writes past the end extend the stream
+    fn size(&self) -> impl Future<Output = Result<u64>> + Send;
+}
+
+pub trait AsyncDisk: AsyncReadAt + AsyncWriteAt + AsyncFlush {
+    fn geometry(&self) -> impl Future<Output = Result<Geometry>> + Send;
+    fn capacity(&self) -> impl Future<Output = Result<u64>> + Send;
+    fn physical_sector_size(&self) -> impl Future<Output = Result<u32>> + Send;
+
+    fn logical_sector_size(&self) -> impl Future<Output = Result<u32>> + Send {
+        async move { Ok(self.geometry().await?.bytes_per_sector) }
+    }
+
+    /// Tells the disk the range is no longer needed (TRIM), its contents are undefined afterwards.
+    /// Does nothing by default.
+    fn discard(&self, offset: u64, len: u64) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            if !math::is_range_valid(self.capacity().await?, offset, len) {
+                return Err(Error::WriteBeyondEOD);
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Fills the range with zeroes, the disks that can do it without writing the data override it
+    fn write_zeroes(&self, offset: u64, len: u64) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            const CHUNK: u64 = 64 * 1024;
+            if !math::is_range_valid(self.capacity().await?, offset, len) {
+                return Err(Error::WriteBeyondEOD);
+            }
+
+            let zeroes = vec![0_u8; core::cmp::min(len, CHUNK) as usize];
+            let mut pos = 0;
+            while pos < len {
+                let n = core::cmp::min(len - pos, CHUNK) as usize;
+                self.write_all_at(offset + pos, &zeroes[..n]).await?;
+                pos += n as u64;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Piece of the source fetched at once for the sync parsers
+const REPLAY_CHUNK: u64 = 64 * 1024;
+
+/// Read-only disk of the chunks fetched so far, a read of missing chunks fails and remembers all of them
+pub(crate) struct ReplayDisk {
+    size: u64,
+    geometry: Geometry,
+    physical_sector_size: u32,
+    chunks: RefCell<BTreeMap<u64, Vec<u8>>>,
+    missing: RefCell<BTreeSet<u64>>,
+}
+
+impl ReplayDisk {
+    pub fn new(size: u64, geometry: Geometry, physical_sector_size: u32) -> Self {
+        Self {
+            size,
+            geometry,
+            physical_sector_size,
+            chunks: RefCell::new(BTreeMap::new()),
+            missing: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    /// 512 byte sectors and the VHD geometry, for the stream parsers
+    pub fn for_stream(size: u64) -> Self {
+        Self::new(size, Geometry::with_vhd_capacity(size), crate::sizes::SECTOR)
+    }
+}
+
+impl ReadAt for ReplayDisk {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = math::bound_to(self.size, offset, buffer.len()).ok_or(Error::ReadBeyondEOD)?;
+        let chunks = self.chunks.borrow();
+        if len == 0 {
+            return Ok(0);
+        }
+
+        // the whole range is fetched at once, a table is not read again for each of its chunks
+        let mut missing = self.missing.borrow_mut();
+        let mut complete = true;
+        for index in offset / REPLAY_CHUNK..=(offset + len as u64 - 1) / REPLAY_CHUNK {
+            if !chunks.contains_key(&index) {
+                missing.insert(index);
+                complete = false;
+            }
+        }
+        if !complete {
+            return Err(Error::UnexpectedEOD);
+        }
+
+        let mut pos = 0;
+        while pos < len {
+            let current = offset + pos as u64;
+            let chunk = &chunks[&(current / REPLAY_CHUNK)];
+            let skip = (current % REPLAY_CHUNK) as usize;
+            let n = core::cmp::min(len - pos, chunk.len() - skip);
+            buffer[pos..pos + n].copy_from_slice(&chunk[skip..skip + n]);
+            pos += n;
+        }
+
+        Ok(len)
+    }
+}
+
+impl WriteAt for ReplayDisk {
+    fn write_at(&self, _offset: u64, _data: &[u8]) -> Result<usize> {
+        Err(Error::ReadOnly)
+    }
+}
+
+impl Flush for ReplayDisk {
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Stream for ReplayDisk {
+    fn size(&self) -> Result<u64> {
+        Ok(self.size)
+    }
+}
+
+impl Disk for ReplayDisk {
+    fn geometry(&self) -> Result<Geometry> {
+        Ok(self.geometry)
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.size)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        Ok(self.physical_sector_size)
+    }
+}
+
+/// Runs the sync `parse` until all the data it reads has been fetched from the `source`.
+/// The result is dropped while anything is missing, a parser may swallow the read errors.
+/// All the chunks missing in a run are fetched before the next one, the adjacent ones by one read.
+pub(crate) async fn replay<S, T, F>(source: &S, disk: ReplayDisk, parse: F) -> Result<T>
+where
+    S: AsyncReadAt + ?Sized,
+    F: Fn(&ReplayDisk) -> Result<T>,
+{
+    loop {
+        let missing = {
+            let result = parse(&disk);
+            let missing = disk.missing.take();
+            if missing.is_empty() {
+                return result;
+            }
+            missing
+        };
+
+        let mut indices = missing.into_iter().peekable();
+        while let Some(first) = indices.next() {
+            let mut last = first;
+            while indices.next_if_eq(&(last + 1)).is_some() {
+                last += 1;
+            }
+
+            let offset = first * REPLAY_CHUNK;
+            let end = core::cmp::min((last + 1) * REPLAY_CHUNK, disk.size);
+            let mut run = vec![0_u8; (end - offset) as usize];
+            source.read_exact_at(offset, &mut run).await?;
+
+            let mut chunks = disk.chunks.borrow_mut();
+            for (index, chunk) in (first..=last).zip(run.chunks(REPLAY_CHUNK as usize)) {
+                chunks.insert(index, chunk.to_vec());
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use crate::{DiskLayout, MemoryDisk, MemoryStream};
+    use core::cell::Cell;
+
+    #[test]
+    fn blocking_disk_test() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let disk = Blocking::new(MemoryDisk::with_capacity(1024 * 1024));
+        runtime.block_on(async {
+            assert_eq!(1024 * 1024, disk.capacity().await.unwrap());
+            assert_eq!(512, disk.logical_sector_size().await.unwrap());
+
+            disk.write_all_at(1000, b"async").await.unwrap();
+            let mut buffer = [0_u8; 5];
+            disk.read_exact_at(1000, &mut buffer).await.unwrap();
+            assert_eq!(b"async", &buffer);
+            assert_eq!(b"async", &disk.inner().to_vec()[1000..1005]);
+
+            disk.write_zeroes(1002, 2).await.unwrap();
+            disk.read_exact_at(1000, &mut buffer).await.unwrap();
+            assert_eq!(b"as\0\0c", &buffer);
+            assert!(matches!(
+                disk.read_at(2 * 1024 * 1024, &mut buffer).await,
+                Err(Error::ReadBeyondEOD)
+            ));
+        });
+    }
+
+    #[test]
+    fn layout_read_async_test() {
+        let mut data = vec![0_u8; 512];
+        // two MBR partitions, the second one is near the end of a 64 MiB disk
+        data[450] = 0x07;
+        data[454..458].copy_from_slice(&2048_u32.to_le_bytes());
+        data[458..462].copy_from_slice(&4096_u32.to_le_bytes());
+        data[466] = 0x83;
+        data[470..474].copy_from_slice(&120_000_u32.to_le_bytes());
+        data[474..478].copy_from_slice(&10_000_u32.to_le_bytes());
+        data[510] = 0x55;
+        data[511] = 0xAA;
+        let disk = MemoryDisk::new(MemoryStream::from_vec(data), 64 * 1024 * 1024);
+
+        let expected: Vec<(u64, u64)> = DiskLayout::read(&disk)
+            .unwrap()
+            .partitions()
+            .map(|p| (p.offset, p.length))
+            .collect();
+        assert_eq!(2, expected.len());
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let layout = runtime.block_on(DiskLayout::read_async(&Blocking::new(disk))).unwrap();
+        let partitions: Vec<(u64, u64)> = layout.partitions().map(|p| (p.offset, p.length)).collect();
+        assert_eq!(expected, partitions);
+    }
+
+    #[test]
+    fn replay_test() {
+        let stream = MemoryStream::from_vec((0..200_000_u32).map(|i| i as u8).collect());
+        let source = Blocking::new(stream.clone());
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        // the read crosses the first and the second chunk, the last one is short
+        let parse = |disk: &ReplayDisk| {
+            let mut buffer = vec![0_u8; 10];
+            disk.read_exact_at(REPLAY_CHUNK - 5, &mut buffer)?;
+            let mut tail = vec![0_u8; 10];
+            assert_eq!(0, disk.read_at(200_000, &mut tail)?);
+            disk.read_exact_at(199_990, &mut tail)?;
+            Ok((buffer, tail))
+        };
+        let (buffer, tail) = runtime.block_on(replay(&source, ReplayDisk::for_stream(200_000), parse)).unwrap();
+        assert_eq!(
+            &stream.to_vec()[REPLAY_CHUNK as usize - 5..REPLAY_CHUNK as usize + 5],
+            buffer.as_slice()
+        );
+        assert_eq!(&stream.to_vec()[199_990..], tail.as_slice());
+
+        let error = runtime.block_on(replay(&source, ReplayDisk::for_stream(200_000), |disk| disk.write_at(0, b"x")));
+        assert!(matches!(error, Err(Error::ReadOnly)));
+    }
+
+    #[test]
+    fn replay_runs_test() {
+        let stream = MemoryStream::from_vec((0..1_000_000_u32).map(|i| (i % 251) as u8).collect());
+        let source = Blocking::new(stream.clone());
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        // a table of many chunks is fetched by one run, like the misses of the reads swallowing the errors
+        let runs = Cell::new(0);
+        let parse = |disk: &ReplayDisk| {
+            runs.set(runs.get() + 1);
+            let mut head = vec![0_u8; 10];
+            let head_result = disk.read_exact_at(0, &mut head);
+            // missed again in the same run
+            let again_result = disk.read_exact_at(5, &mut head);
+            let mut table = vec![0_u8; 700_000];
+            disk.read_exact_at(250_000, &mut table)?;
+            head_result.and(again_result)?;
+            Ok((head, table))
+        };
+        let (head, table) = runtime.block_on(replay(&source, ReplayDisk::for_stream(1_000_000), parse)).unwrap();
+        assert_eq!(2, runs.get());
+        assert_eq!(&stream.to_vec()[5..15], head.as_slice());
+        assert!(stream.to_vec()[250_000..950_000] == table[..]);
+    }
+}
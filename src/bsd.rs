@@ -0,0 +1,282 @@
+//! BSD disklabel
+//!
+//! The label is either inside an MBR slice (FreeBSD 0xA5, OpenBSD 0xA6, NetBSD 0xA9) or occupies the entire disk.
+use crate::prelude::*;
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct PartitionRecord {
+    size: u32,
+    offset: u32,
+    fragment_size: u32,
+    fs_type: u8,
+    fragments_per_block: u8,
+    cylinders_per_group: u16,
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct DiskLabelRecord {
+    magic: u32,
+    kind: u16,
+    subtype: u16,
+    type_name: [u8; 16],
+    pack_name: [u8; 16],
+    sector_size: u32,
+    sectors_per_track: u32,
+    tracks_per_cylinder: u32,
+    cylinders: u32,
+    sectors_per_cylinder: u32,
+    sectors_per_unit: u32,
+    spares_per_track: u16,
+    spares_per_cylinder: u16,
+    alternate_cylinders: u32,
+    rpm: u16,
+    interleave: u16,
+    track_skew: u16,
+    cylinder_skew: u16,
+    head_switch: u32,
+    track_seek: u32,
+    flags: u32,
+    drive_data: [u32; 5],
+    spare: [u32; 5],
+    magic2: u32,
+    checksum: u16,
+    partitions_count: u16,
+    boot_area_size: u32,
+    super_block_size: u32,
+    partitions: [PartitionRecord; MAX_PARTITIONS],
+}
+
+const MAGIC: u32 = 0x8256_4557;
+const MAX_PARTITIONS: usize = 22;
+const LABEL_HEADER_SIZE: usize = core::mem::size_of::<DiskLabelRecord>() - MAX_PARTITIONS * core::mem::size_of::<PartitionRecord>();
+
+/// Known label offsets from the slice start: sector 1 (i386, amd64), sector 0 at 64 (alpha) and at 128 (sparc)
+const LABEL_OFFSETS: [usize; 3] = [512, 64, 128];
+const RAW_PARTITION: usize = 2; // 'c'
+const FS_UNUSED: u8 = 0;
+
+// Only the values shared by all BSD flavours, the rest depends on the OS
+const FS_TYPE_NAMES: [&str; 14] = [
+    "unused",
+    "swap",
+    "Version 6",
+    "Version 7",
+    "System V",
+    "4.1BSD",
+    "Eighth Edition",
+    "4.2BSD",
+    "MSDOS",
+    "4.4LFS",
+    "unknown",
+    "HPFS",
+    "ISO9660",
+    "boot",
+];
+
+impl DiskLabelRecord {
+    fn checksum_is_valid(bytes: &[u8], partitions_count: usize) -> bool {
+        let end = LABEL_HEADER_SIZE + partitions_count * core::mem::size_of::<PartitionRecord>();
+        let mut sum = 0_u16;
+        for word in bytes[..end].chunks_exact(2) {
+            sum ^= u16::from_le_bytes([word[0], word[1]]);
+        }
+
+        sum == 0
+    }
+}
+
+pub struct PartitionInfo {
+    pub offset: u64,
+    pub length: u64,
+    pub letter: char,
+    pub fs_type: u8,
+}
+
+impl PartitionInfo {
+    pub fn fs_type_name(&self) -> &'static str {
+        FS_TYPE_NAMES.get(self.fs_type as usize).copied().unwrap_or("unknown")
+    }
+}
+
+pub struct Layout {
+    slice_offset: u64,
+    slice_length: u64,
+    flags: u32,
+    type_name: String,
+    pack_name: String,
+    partitions: Vec<PartitionInfo>,
+}
+
+impl Layout {
+    /// Looks for a disklabel at the beginning of the `slice_offset..slice_offset + slice_length` area.
+    pub(crate) fn read(disk: &impl Disk, slice_offset: u64, slice_length: u64) -> Result<Option<Layout>> {
+        const BUFFER_SIZE: usize = 1024;
+        if slice_length < BUFFER_SIZE as u64 {
+            return Ok(None);
+        }
+
+        let mut buffer = vec![0_u8; BUFFER_SIZE];
+        disk.read_exact_at(slice_offset, &mut buffer)?;
+
+        for &label_offset in &LABEL_OFFSETS {
+            let bytes = &buffer[label_offset..];
+            if bytes.len() < core::mem::size_of::<DiskLabelRecord>() {
+                continue;
+            }
+
+            let label = unsafe { core::ptr::read(bytes.as_ptr() as *const DiskLabelRecord) };
+            let partitions_count = label.partitions_count as usize;
+            if label.magic != MAGIC || label.magic2 != MAGIC || partitions_count > MAX_PARTITIONS {
+                continue;
+            }
+
+            if !DiskLabelRecord::checksum_is_valid(bytes, partitions_count) {
+                // TODO: log warning
+                continue;
+            }
+
+            return Ok(Some(Self::from_record(
+                &label,
+                slice_offset,
+                slice_length,
+                disk.logical_sector_size()?,
+            )));
+        }
+
+        Ok(None)
+    }
+
+    fn from_record(label: &DiskLabelRecord, slice_offset: u64, slice_length: u64, disk_sector_size: u32) -> Self {
+        let sector_size = match label.sector_size {
+            0 => disk_sector_size,
+            size => size,
+        } as u64;
+        let records = &label.partitions[..label.partitions_count as usize];
+
+        // Older labels store offsets relative to the disk start, the raw partition then starts exactly at the slice.
+        let slice_lba = slice_offset / sector_size;
+        let absolute = slice_lba != 0
+            && records
+                .get(RAW_PARTITION)
+                .map(|raw| raw.offset as u64 == slice_lba)
+                .unwrap_or(false);
+        let base = if absolute { 0 } else { slice_offset };
+
+        let partitions = records
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.fs_type != FS_UNUSED && r.size != 0)
+            .map(|(i, r)| PartitionInfo {
+                offset: base + r.offset as u64 * sector_size,
+                length: r.size as u64 * sector_size,
+                letter: (b'a' + i as u8) as char,
+                fs_type: r.fs_type,
+            })
+            .collect();
+
+        Self {
+            slice_offset,
+            slice_length,
+            flags: label.flags,
+            type_name: tools::string_from_ascii_z(&label.type_name),
+            pack_name: tools::string_from_ascii_z(&label.pack_name),
+            partitions,
+        }
+    }
+
+    pub fn slice_offset(&self) -> u64 {
+        self.slice_offset
+    }
+
+    pub fn slice_length(&self) -> u64 {
+        self.slice_length
+    }
+
+    /// Label flags (`d_flags`)
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn pack_name(&self) -> &str {
+        &self.pack_name
+    }
+
+    pub fn partitions(&self) -> &[PartitionInfo] {
+        &self.partitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(slice_lba: u32) -> Vec<u8> {
+        let mut label: DiskLabelRecord = unsafe { core::mem::zeroed() };
+        label.magic = MAGIC;
+        label.magic2 = MAGIC;
+        label.sector_size = 512;
+        label.partitions_count = 3;
+        label.partitions[0] = PartitionRecord {
+            size: 100,
+            offset: slice_lba + 16,
+            fragment_size: 0,
+            fs_type: 7,
+            fragments_per_block: 0,
+            cylinders_per_group: 0,
+        };
+        label.partitions[1] = PartitionRecord {
+            size: 50,
+            offset: slice_lba + 116,
+            fragment_size: 0,
+            fs_type: 1,
+            fragments_per_block: 0,
+            cylinders_per_group: 0,
+        };
+        label.partitions[2] = PartitionRecord {
+            size: 200,
+            offset: slice_lba,
+            fragment_size: 0,
+            fs_type: 0,
+            fragments_per_block: 0,
+            cylinders_per_group: 0,
+        };
+
+        let mut bytes =
+            unsafe { core::slice::from_raw_parts(&label as *const _ as *const u8, core::mem::size_of::<DiskLabelRecord>()) }.to_vec();
+        let mut sum = 0_u16;
+        for word in bytes[..LABEL_HEADER_SIZE + 3 * 16].chunks_exact(2) {
+            sum ^= u16::from_le_bytes([word[0], word[1]]);
+        }
+        bytes[136..138].copy_from_slice(&sum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn checksum_test() {
+        let mut bytes = label(63);
+        assert!(DiskLabelRecord::checksum_is_valid(&bytes, 3));
+        bytes[8] ^= 1;
+        assert!(!DiskLabelRecord::checksum_is_valid(&bytes, 3));
+    }
+
+    #[test]
+    fn absolute_offsets_test() {
+        let bytes = label(63);
+        let record = unsafe { core::ptr::read(bytes.as_ptr() as *const DiskLabelRecord) };
+        let layout = Layout::from_record(&record, 63 * 512, 200 * 512, 512);
+
+        // the raw partition is unused and skipped
+        assert_eq!(2, layout.partitions().len());
+        assert_eq!('a', layout.partitions()[0].letter);
+        assert_eq!((63 + 16) * 512, layout.partitions()[0].offset);
+        assert_eq!("4.2BSD", layout.partitions()[0].fs_type_name());
+        assert_eq!('b', layout.partitions()[1].letter);
+        assert_eq!("swap", layout.partitions()[1].fs_type_name());
+    }
+}
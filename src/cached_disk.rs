@@ -0,0 +1,599 @@
+use crate::prelude::*;
+use crate::sync::Mutex;
+
+#[derive(Copy, Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct CacheOptions {
+    /// Bytes, rounded up to the logical sector size so the disk I/O stays sector aligned
+    pub page_size: u32,
+    /// Maximum number of pages kept in memory
+    pub capacity: usize,
+    /// Pages read past the end of a sequential read, 0 disables read-ahead
+    pub read_ahead: u32,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            page_size: 4096,
+            capacity: 1024,
+            read_ahead: 16,
+        }
+    }
+}
+
+struct Page {
+    data: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Pages {
+    pages: BTreeMap<u64, Page>,
+    /// `last_used` to page index, the first one is the least recently used
+    lru: BTreeMap<u64, u64>,
+    tick: u64,
+    /// End of the previous read, a read starting there is sequential
+    read_end: u64,
+}
+
+impl Pages {
+    fn remove(&mut self, index: u64) {
+        if let Some(page) = self.pages.remove(&index) {
+            self.lru.remove(&page.last_used);
+        }
+    }
+
+    fn touch(&mut self, index: u64) {
+        self.tick += 1;
+        if let Some(page) = self.pages.get_mut(&index) {
+            self.lru.remove(&page.last_used);
+            page.last_used = self.tick;
+            self.lru.insert(self.tick, index);
+        }
+    }
+
+    /// Adds a clean page, the least recently used ones are evicted and the dirty ones returned to be written back
+    fn insert(&mut self, index: u64, data: Vec<u8>, max_pages: usize) -> Vec<(u64, Vec<u8>)> {
+        let mut evicted = Vec::new();
+        while self.pages.len() >= max_pages {
+            let (&tick, &victim) = self.lru.iter().next().unwrap();
+            self.lru.remove(&tick);
+            let page = self.pages.remove(&victim).unwrap();
+            if page.dirty {
+                evicted.push((victim, page.data));
+            }
+        }
+
+        self.tick += 1;
+        let page = Page {
+            data,
+            dirty: false,
+            last_used: self.tick,
+        };
+        self.lru.insert(page.last_used, index);
+        self.pages.insert(index, page);
+        evicted
+    }
+
+    /// Marks the page dirty again after a failed write, an evicted one is put back over the capacity
+    fn restore(&mut self, index: u64, data: Vec<u8>) {
+        match self.pages.get_mut(&index) {
+            Some(page) => page.dirty = true,
+            None => {
+                self.tick += 1;
+                let page = Page {
+                    data,
+                    dirty: true,
+                    last_used: self.tick,
+                };
+                self.lru.insert(page.last_used, index);
+                self.pages.insert(index, page);
+            }
+        }
+    }
+}
+
+/// Write-back page cache over any disk, for metadata-heavy workloads with many small reads
+///
+/// Reads and writes have the semantics of the underlying disk, the dirty pages are written out by
+/// [`flush`](Flush::flush) and on drop. The cache is shared between threads, the cached pages are
+/// accessed under one lock which is never held during the disk I/O.
+pub struct CachedDisk<D: Disk> {
+    disk: D,
+    capacity: u64,
+    page_size: u64,
+    max_pages: usize,
+    read_ahead: u64,
+    pages: Mutex<Pages>,
+    /// Serializes the disk I/O of the cache (loads, write-backs, flush),
+    /// a page is not loaded while its previous version is being written back
+    io: Mutex<()>,
+}
+
+impl<D: Disk> Drop for CachedDisk<D> {
+    fn drop(&mut self) {
+        let res = self.flush();
+        debug_assert!(res.is_ok());
+    }
+}
+
+impl<D: Disk> ReadAt for CachedDisk<D> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = math::bound_to(self.capacity, offset, buffer.len()).ok_or(Error::ReadBeyondEOD)?;
+        let end = offset + len as u64;
+
+        let sequential = offset != 0 && offset == self.pages.lock().read_end;
+        let mut pos = offset;
+        while pos < end {
+            let index = pos / self.page_size;
+            let count = {
+                let mut pages = self.pages.lock();
+                if pages.pages.contains_key(&index) {
+                    let page_offset = index * self.page_size;
+                    let start = (pos - page_offset) as usize;
+                    let n = core::cmp::min(self.page_len(index) - start, (end - pos) as usize);
+                    let data = &pages.pages[&index].data;
+                    let buffer_pos = (pos - offset) as usize;
+                    buffer[buffer_pos..buffer_pos + n].copy_from_slice(&data[start..start + n]);
+                    pages.touch(index);
+
+                    pos += n as u64;
+                    continue;
+                }
+
+                // load all the missing pages at once
+                let last = (end - 1) / self.page_size;
+                let mut count = 1;
+                while index + count <= last && !pages.pages.contains_key(&(index + count)) {
+                    count += 1;
+                }
+                if sequential && index + count > last {
+                    count += self.read_ahead;
+                }
+                count
+            };
+
+            let _io = self.io.lock();
+            self.load(index, count)?;
+        }
+
+        self.pages.lock().read_end = end;
+        Ok(len)
+    }
+}
+
+impl<D: Disk> WriteAt for CachedDisk<D> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let len = math::bound_to(self.capacity, offset, data.len()).ok_or(Error::WriteBeyondEOD)?;
+        let end = offset + len as u64;
+
+        let mut pos = offset;
+        while pos < end {
+            let index = pos / self.page_size;
+            let page_len = self.page_len(index);
+            let start = (pos - index * self.page_size) as usize;
+            let n = core::cmp::min(page_len - start, (end - pos) as usize);
+            let data_pos = (pos - offset) as usize;
+
+            {
+                let mut pages = self.pages.lock();
+                if let Some(page) = pages.pages.get_mut(&index) {
+                    page.data[start..start + n].copy_from_slice(&data[data_pos..data_pos + n]);
+                    page.dirty = true;
+                    pages.touch(index);
+
+                    pos += n as u64;
+                    continue;
+                }
+            }
+
+            let _io = self.io.lock();
+            if n == page_len {
+                // the whole page is overwritten, no need to read it
+                let evicted = {
+                    let mut pages = self.pages.lock();
+                    if pages.pages.contains_key(&index) {
+                        Vec::new()
+                    } else {
+                        pages.insert(index, data[data_pos..data_pos + n].to_vec(), self.max_pages)
+                    }
+                };
+                self.write_back(evicted)?;
+            } else {
+                self.load(index, 1)?;
+            }
+        }
+
+        Ok(len)
+    }
+}
+
+impl<D: Disk> Flush for CachedDisk<D> {
+    fn flush(&self) -> Result<()> {
+        let _io = self.io.lock();
+        let mut dirty: Vec<(u64, Vec<u8>)> = self
+            .pages
+            .lock()
+            .pages
+            .iter_mut()
+            .filter(|(_, page)| page.dirty)
+            .map(|(&index, page)| {
+                page.dirty = false;
+                (index, page.data.clone())
+            })
+            .collect();
+
+        // adjacent dirty pages are written at once, the pages are dirty again if the write fails
+        let mut run_start = 0;
+        while run_start < dirty.len() {
+            let mut run_end = run_start + 1;
+            while run_end < dirty.len() && dirty[run_end].0 == dirty[run_end - 1].0 + 1 {
+                run_end += 1;
+            }
+
+            let mut data = Vec::new();
+            for (_, page_data) in &dirty[run_start..run_end] {
+                data.extend_from_slice(page_data);
+            }
+            if let Err(err) = self.disk.write_all_at(dirty[run_start].0 * self.page_size, &data) {
+                let mut pages = self.pages.lock();
+                for (index, page_data) in dirty.drain(run_start..) {
+                    pages.restore(index, page_data);
+                }
+                return Err(err);
+            }
+
+            run_start = run_end;
+        }
+
+        self.disk.flush()
+    }
+}
+
+/// Geometry and sector sizes are the ones of the underlying disk
+impl<D: Disk> Disk for CachedDisk<D> {
+    fn geometry(&self) -> Result<Geometry> {
+        self.disk.geometry()
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.capacity)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        self.disk.physical_sector_size()
+    }
+
+    fn logical_sector_size(&self) -> Result<u32> {
+        self.disk.logical_sector_size()
+    }
+
+    /// The whole pages of the range are dropped from the cache, even the dirty ones
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        let _io = self.io.lock();
+        self.drop_pages(&mut self.pages.lock(), offset, len);
+        self.disk.discard(offset, len)
+    }
+
+    /// The partial pages are zeroed in the cache, the whole ones are dropped and zeroed by the disk
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        let end = offset + len;
+        let first = core::cmp::min(math::round_up(offset, self.page_size), end);
+        let last = core::cmp::max(math::round_down(end, self.page_size), first);
+        tools::write_zeroes(self, offset, first - offset)?;
+        tools::write_zeroes(self, last, end - last)?;
+
+        if first < last {
+            let _io = self.io.lock();
+            self.drop_pages(&mut self.pages.lock(), first, last - first);
+            self.disk.write_zeroes(first, last - first)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: Disk> CachedDisk<D> {
+    pub fn new(disk: D, options: &CacheOptions) -> Result<Self> {
+        let capacity = disk.capacity()?;
+        let page_size = math::round_up(core::cmp::max(options.page_size, 1), disk.logical_sector_size()?);
+
+        Ok(Self {
+            disk,
+            capacity,
+            page_size: page_size as u64,
+            max_pages: core::cmp::max(options.capacity, 1),
+            read_ahead: options.read_ahead as u64,
+            pages: Mutex::default(),
+            io: Mutex::default(),
+        })
+    }
+
+    pub fn disk(&self) -> &D {
+        &self.disk
+    }
+
+    /// Writes out the dirty pages and returns the underlying disk
+    pub fn into_inner(self) -> Result<D> {
+        self.flush()?;
+
+        let mut this = core::mem::ManuallyDrop::new(self);
+        // Drop is skipped, the pages are dropped here and the disk is moved out
+        unsafe {
+            core::ptr::drop_in_place(&mut this.pages);
+            core::ptr::drop_in_place(&mut this.io);
+            Ok(core::ptr::read(&this.disk))
+        }
+    }
+
+    /// Number of pages in memory
+    pub fn cached_pages(&self) -> usize {
+        self.pages.lock().pages.len()
+    }
+
+    /// Drops the pages that are entirely inside the range
+    fn drop_pages(&self, pages: &mut Pages, offset: u64, len: u64) {
+        let first = math::ceil(offset, self.page_size);
+        let end = offset + len;
+        let indexes: Vec<u64> = pages
+            .pages
+            .range(first..)
+            .map(|(&index, _)| index)
+            .take_while(|&index| index * self.page_size + self.page_len(index) as u64 <= end)
+            .collect();
+        for index in indexes {
+            pages.remove(index);
+        }
+    }
+
+    /// The last page may be shorter
+    fn page_len(&self, index: u64) -> usize {
+        core::cmp::min(self.page_size, self.capacity - index * self.page_size) as usize
+    }
+
+    /// Reads up to `count` pages starting from `first` with one disk read, the cached pages are kept as is.
+    /// The caller holds the I/O lock.
+    fn load(&self, first: u64, count: u64) -> Result<()> {
+        let last_page = (self.capacity - 1) / self.page_size;
+        let count = core::cmp::min(core::cmp::min(count, last_page + 1 - first), self.max_pages as u64);
+
+        let offset = first * self.page_size;
+        let len = core::cmp::min(count * self.page_size, self.capacity - offset) as usize;
+        let mut data = unsafe { tools::alloc_buffer(len) };
+        self.disk.read_exact_at(offset, &mut data)?;
+
+        let mut evicted = Vec::new();
+        {
+            let mut pages = self.pages.lock();
+            for (i, chunk) in data.chunks(self.page_size as usize).enumerate() {
+                let index = first + i as u64;
+                if !pages.pages.contains_key(&index) {
+                    evicted.extend(pages.insert(index, chunk.to_vec(), self.max_pages));
+                }
+            }
+        }
+
+        self.write_back(evicted)
+    }
+
+    /// Writes the evicted dirty pages, the ones failed to write are put back into the cache.
+    /// The caller holds the I/O lock.
+    fn write_back(&self, evicted: Vec<(u64, Vec<u8>)>) -> Result<()> {
+        let mut result = Ok(());
+        for (index, data) in evicted {
+            if let Err(err) = self.disk.write_all_at(index * self.page_size, &data) {
+                self.pages.lock().restore(index, data);
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::pattern;
+    use crate::MemoryDisk;
+    use core::cell::{Cell, RefCell};
+
+    struct Counting {
+        disk: MemoryDisk,
+        reads: Cell<usize>,
+        writes: Cell<usize>,
+    }
+
+    impl ReadAt for Counting {
+        fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.disk.read_at(offset, buffer)
+        }
+    }
+
+    impl WriteAt for Counting {
+        fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+            self.writes.set(self.writes.get() + 1);
+            self.disk.write_at(offset, data)
+        }
+    }
+
+    impl Flush for Counting {
+        fn flush(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Disk for Counting {
+        fn geometry(&self) -> Result<Geometry> {
+            self.disk.geometry()
+        }
+
+        fn capacity(&self) -> Result<u64> {
+            self.disk.capacity()
+        }
+
+        fn physical_sector_size(&self) -> Result<u32> {
+            self.disk.physical_sector_size()
+        }
+    }
+
+    fn counting(size: usize) -> Counting {
+        Counting {
+            disk: MemoryDisk::from_vec(pattern(size, 0)),
+            reads: Cell::new(0),
+            writes: Cell::new(0),
+        }
+    }
+
+    #[test]
+    fn cached_io_test() {
+        // the last page is a short one
+        let size = 10 * 4096 + 512;
+        let mirror = RefCell::new(pattern(size, 0));
+        let options = CacheOptions {
+            capacity: 3,
+            read_ahead: 2,
+            ..Default::default()
+        };
+        let disk = CachedDisk::new(counting(size), &options).unwrap();
+        assert_eq!(size as u64, disk.capacity().unwrap());
+
+        let mut seed = 12345_u64;
+        for _ in 0..2000 {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            let offset = (seed >> 16) % size as u64;
+            let len = core::cmp::min((seed >> 40) as usize % 9000, size - offset as usize);
+            if seed & 1 == 0 {
+                let mut buffer = vec![0_u8; len];
+                assert_eq!(len, disk.read_at(offset, &mut buffer).unwrap());
+                assert_eq!(&mirror.borrow()[offset as usize..offset as usize + len], buffer.as_slice());
+            } else {
+                let data: Vec<u8> = (0..len).map(|i| (seed as usize + i) as u8).collect();
+                assert_eq!(len, disk.write_at(offset, &data).unwrap());
+                mirror.borrow_mut()[offset as usize..offset as usize + len].copy_from_slice(&data);
+            }
+            assert!(disk.cached_pages() <= 3);
+        }
+
+        let mut buffer = vec![0_u8; 2];
+        assert_eq!(1, disk.read_at(size as u64 - 1, &mut buffer).unwrap());
+        assert_eq!(0, disk.read_at(size as u64, &mut buffer).unwrap());
+        match disk.read_at(size as u64 + 1, &mut buffer) {
+            Err(Error::ReadBeyondEOD) => (),
+            _ => panic!("read beyond the end expected"),
+        }
+        match disk.write_at(size as u64 + 1, &buffer) {
+            Err(Error::WriteBeyondEOD) => (),
+            _ => panic!("write beyond the end expected"),
+        }
+
+        let inner = disk.into_inner().unwrap();
+        assert!(*mirror.borrow() == inner.disk.to_vec());
+    }
+
+    #[test]
+    fn write_back_test() {
+        let disk = CachedDisk::new(counting(64 * 1024), &CacheOptions::default()).unwrap();
+
+        // a partial sector write reads the page first
+        disk.write_all_at(100, b"partial").unwrap();
+        assert_eq!(1, disk.disk().reads.get());
+        // a whole page is not read
+        disk.write_all_at(8192, &[0xEE; 4096]).unwrap();
+        assert_eq!(1, disk.disk().reads.get());
+        assert_eq!(0, disk.disk().writes.get());
+        assert_ne!(b"partial", &disk.disk().disk.to_vec()[100..107]);
+
+        let mut buffer = vec![0_u8; 8];
+        for _ in 0..10 {
+            disk.read_exact_at(8190, &mut buffer).unwrap();
+        }
+        // the page between was loaded once
+        assert_eq!(2, disk.disk().reads.get());
+        assert_eq!(&[0xEE; 6], &buffer[2..]);
+
+        disk.flush().unwrap();
+        assert_eq!(2, disk.disk().writes.get());
+        assert_eq!(b"partial", &disk.disk().disk.to_vec()[100..107]);
+        disk.flush().unwrap();
+        assert_eq!(2, disk.disk().writes.get());
+    }
+
+    #[test]
+    fn eviction_test() {
+        let options = CacheOptions {
+            page_size: 1000,
+            capacity: 2,
+            read_ahead: 0,
+        };
+        let disk = CachedDisk::new(counting(16 * 1024), &options).unwrap();
+        assert_eq!(1024, disk.page_size);
+
+        disk.write_all_at(0, b"dirty").unwrap();
+        let mut buffer = vec![0_u8; 4];
+        disk.read_exact_at(1024, &mut buffer).unwrap();
+        disk.read_exact_at(0, &mut buffer).unwrap();
+        assert_eq!(0, disk.disk().writes.get());
+
+        // the clean page 1 is the least recently used one
+        disk.read_exact_at(2048, &mut buffer).unwrap();
+        assert_eq!(3, disk.disk().reads.get());
+        assert_eq!(0, disk.disk().writes.get());
+
+        // now it is the dirty page 0, written back on eviction
+        disk.read_exact_at(1024, &mut buffer).unwrap();
+        assert_eq!(4, disk.disk().reads.get());
+        assert_eq!(1, disk.disk().writes.get());
+        assert_eq!(b"dirty", &disk.disk().disk.to_vec()[..5]);
+    }
+
+    #[test]
+    fn read_ahead_test() {
+        let options = CacheOptions {
+            read_ahead: 8,
+            ..Default::default()
+        };
+        let disk = CachedDisk::new(counting(256 * 1024), &options).unwrap();
+
+        let mut buffer = vec![0_u8; 512];
+        for sector in 0..256 {
+            disk.read_exact_at(sector * 512, &mut buffer).unwrap();
+        }
+
+        // the first page, then 9 pages per read
+        assert_eq!(5, disk.disk().reads.get());
+        assert_eq!(37, disk.cached_pages());
+    }
+
+    #[test]
+    fn write_zeroes_test() {
+        let disk = CachedDisk::new(counting(64 * 1024), &CacheOptions::default()).unwrap();
+        disk.write_all_at(4096, &[0xEE; 8192]).unwrap();
+        assert_eq!(2, disk.cached_pages());
+
+        // the whole page 2 is dropped with its changes and zeroed by the disk,
+        // the pages 1 and 3 are zeroed in the cache
+        disk.write_zeroes(6000, 6388).unwrap();
+        assert_eq!(2, disk.cached_pages());
+        assert!(disk.disk().disk.to_vec()[8192..12288].iter().all(|&b| b == 0));
+
+        disk.flush().unwrap();
+        let data = disk.disk().disk.to_vec();
+        assert!(data[4096..6000].iter().all(|&b| b == 0xEE));
+        assert!(data[6000..12388].iter().all(|&b| b == 0));
+        assert_eq!(pattern(64 * 1024, 0)[12388..], data[12388..]);
+    }
+}
@@ -0,0 +1,439 @@
+use crate::prelude::*;
+
+/// Sector sizes shared by the `disks`: (logical, physical), the largest physical one is reported
+fn common_sector_size<D: Disk>(disks: &[D]) -> Result<(u32, u32)> {
+    let first = disks.first().ok_or(Error::IncompatibleDisks)?;
+    let logical = first.logical_sector_size()?;
+    let mut physical = first.physical_sector_size()?;
+    for disk in &disks[1..] {
+        if disk.logical_sector_size()? != logical {
+            return Err(Error::IncompatibleDisks);
+        }
+
+        physical = core::cmp::max(physical, disk.physical_sector_size()?);
+    }
+
+    Ok((logical, physical))
+}
+
+/// A range of another disk, like a partition carved out by hand
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct SliceDisk<D: Disk> {
+    disk: D,
+    offset: u64,
+    length: u64,
+}
+
+impl<D: Disk> ReadAt for SliceDisk<D> {
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> Result<usize> {
+        match math::bound_to(self.length, offset, data.len()) {
+            Some(data_len) => self.disk.read_at(self.offset + offset, &mut data[..data_len]),
+            None => Err(Error::ReadBeyondEOD),
+        }
+    }
+}
+
+impl<D: Disk> WriteAt for SliceDisk<D> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        match math::bound_to(self.length, offset, data.len()) {
+            Some(data_len) => self.disk.write_at(self.offset + offset, &data[..data_len]),
+            None => Err(Error::WriteBeyondEOD),
+        }
+    }
+}
+
+impl<D: Disk> Flush for SliceDisk<D> {
+    fn flush(&self) -> Result<()> {
+        self.disk.flush()
+    }
+}
+
+/// Sector sizes are the ones of the underlying disk
+impl<D: Disk> Disk for SliceDisk<D> {
+    fn geometry(&self) -> Result<Geometry> {
+        Ok(Geometry::with_vhd_capacity_and_sector(
+            self.length,
+            self.disk.logical_sector_size()?,
+        ))
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.length)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        self.disk.physical_sector_size()
+    }
+
+    fn logical_sector_size(&self) -> Result<u32> {
+        self.disk.logical_sector_size()
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.length, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        self.disk.discard(self.offset + offset, len)
+    }
+
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.length, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        self.disk.write_zeroes(self.offset + offset, len)
+    }
+}
+
+impl<D: Disk> SliceDisk<D> {
+    /// `length` bytes of the `disk` starting from the `offset`, the range should be inside the disk
+    pub fn new(disk: D, offset: u64, length: u64) -> Result<Self> {
+        match offset.checked_add(length) {
+            Some(end) if end <= disk.capacity()? => Ok(Self { disk, offset, length }),
+            _ => Err(Error::SliceOutOfBounds),
+        }
+    }
+
+    pub fn disk(&self) -> &D {
+        &self.disk
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> D {
+        self.disk
+    }
+}
+
+/// Disks joined one after another, like split raw image segments
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct ConcatDisk<D: Disk> {
+    disks: Vec<D>,
+    /// Start of each disk
+    starts: Vec<u64>,
+    capacity: u64,
+    geometry: Geometry,
+    physical_sector_size: u32,
+}
+
+impl<D: Disk> ConcatDisk<D> {
+    /// Index of the disk at the `offset` and the offset inside it
+    fn map(&self, offset: u64) -> (usize, u64) {
+        // empty disks share the start with the next one, the last of them is taken
+        let index = self.starts.iter().rposition(|&start| start <= offset).unwrap_or(0);
+        (index, offset - self.starts[index])
+    }
+
+    /// Runs `f` on each piece of the `len` bytes at `offset`: (disk, disk offset, position, piece length)
+    fn for_each_piece(&self, offset: u64, len: u64, mut f: impl FnMut(&D, u64, usize, u64) -> Result<()>) -> Result<()> {
+        let mut pos = 0_u64;
+        while pos < len {
+            let (index, disk_offset) = self.map(offset + pos);
+            let disk_end = self.starts.get(index + 1).copied().unwrap_or(self.capacity) - self.starts[index];
+            let n = core::cmp::min(disk_end - disk_offset, len - pos);
+            f(&self.disks[index], disk_offset, pos as usize, n)?;
+            pos += n;
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: Disk> ReadAt for ConcatDisk<D> {
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> Result<usize> {
+        let data_len = math::bound_to(self.capacity, offset, data.len()).ok_or(Error::ReadBeyondEOD)?;
+        self.for_each_piece(offset, data_len as u64, |disk, disk_offset, pos, n| {
+            disk.read_exact_at(disk_offset, &mut data[pos..pos + n as usize])
+        })?;
+
+        Ok(data_len)
+    }
+}
+
+impl<D: Disk> WriteAt for ConcatDisk<D> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let data_len = math::bound_to(self.capacity, offset, data.len()).ok_or(Error::WriteBeyondEOD)?;
+        self.for_each_piece(offset, data_len as u64, |disk, disk_offset, pos, n| {
+            disk.write_all_at(disk_offset, &data[pos..pos + n as usize])
+        })?;
+
+        Ok(data_len)
+    }
+}
+
+impl<D: Disk> Flush for ConcatDisk<D> {
+    fn flush(&self) -> Result<()> {
+        for disk in &self.disks {
+            disk.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: Disk> Disk for ConcatDisk<D> {
+    fn geometry(&self) -> Result<Geometry> {
+        Ok(self.geometry)
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.capacity)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        Ok(self.physical_sector_size)
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        self.for_each_piece(offset, len, |disk, disk_offset, _, n| disk.discard(disk_offset, n))
+    }
+
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        self.for_each_piece(offset, len, |disk, disk_offset, _, n| disk.write_zeroes(disk_offset, n))
+    }
+}
+
+impl<D: Disk> ConcatDisk<D> {
+    /// All the `disks` should have the same logical sector size
+    pub fn new(disks: Vec<D>) -> Result<Self> {
+        let (logical, physical) = common_sector_size(&disks)?;
+
+        let mut starts = Vec::with_capacity(disks.len());
+        let mut capacity = 0_u64;
+        for disk in &disks {
+            starts.push(capacity);
+            capacity += disk.capacity()?;
+        }
+
+        Ok(Self {
+            disks,
+            starts,
+            capacity,
+            geometry: Geometry::with_vhd_capacity_and_sector(capacity, logical),
+            physical_sector_size: physical,
+        })
+    }
+
+    pub fn disks(&self) -> &[D] {
+        &self.disks
+    }
+
+    pub fn into_inner(self) -> Vec<D> {
+        self.disks
+    }
+}
+
+/// Disks interleaved by `chunk_size` pieces, as RAID0 does
+///
+/// The capacity is limited by the smallest disk, the rest of the bigger ones is not used.
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct StripeDisk<D: Disk> {
+    disks: Vec<D>,
+    chunk_size: u64,
+    capacity: u64,
+    geometry: Geometry,
+    physical_sector_size: u32,
+}
+
+impl<D: Disk> StripeDisk<D> {
+    /// Runs `f` on each chunk piece of the `len` bytes at `offset`: (disk, disk offset, position, piece length)
+    fn for_each_piece(&self, offset: u64, len: u64, mut f: impl FnMut(&D, u64, usize, u64) -> Result<()>) -> Result<()> {
+        let count = self.disks.len() as u64;
+        let mut pos = 0_u64;
+        while pos < len {
+            let offset = offset + pos;
+            let chunk = offset / self.chunk_size;
+            let offset_in_chunk = offset % self.chunk_size;
+            let disk_offset = (chunk / count) * self.chunk_size + offset_in_chunk;
+            let n = core::cmp::min(self.chunk_size - offset_in_chunk, len - pos);
+            f(&self.disks[(chunk % count) as usize], disk_offset, pos as usize, n)?;
+            pos += n;
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: Disk> ReadAt for StripeDisk<D> {
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> Result<usize> {
+        let data_len = math::bound_to(self.capacity, offset, data.len()).ok_or(Error::ReadBeyondEOD)?;
+        self.for_each_piece(offset, data_len as u64, |disk, disk_offset, pos, n| {
+            disk.read_exact_at(disk_offset, &mut data[pos..pos + n as usize])
+        })?;
+
+        Ok(data_len)
+    }
+}
+
+impl<D: Disk> WriteAt for StripeDisk<D> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let data_len = math::bound_to(self.capacity, offset, data.len()).ok_or(Error::WriteBeyondEOD)?;
+        self.for_each_piece(offset, data_len as u64, |disk, disk_offset, pos, n| {
+            disk.write_all_at(disk_offset, &data[pos..pos + n as usize])
+        })?;
+
+        Ok(data_len)
+    }
+}
+
+impl<D: Disk> Flush for StripeDisk<D> {
+    fn flush(&self) -> Result<()> {
+        for disk in &self.disks {
+            disk.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: Disk> Disk for StripeDisk<D> {
+    fn geometry(&self) -> Result<Geometry> {
+        Ok(self.geometry)
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.capacity)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        Ok(self.physical_sector_size)
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        self.for_each_piece(offset, len, |disk, disk_offset, _, n| disk.discard(disk_offset, n))
+    }
+
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        self.for_each_piece(offset, len, |disk, disk_offset, _, n| disk.write_zeroes(disk_offset, n))
+    }
+}
+
+impl<D: Disk> StripeDisk<D> {
+    /// All the `disks` should have the same logical sector size, `chunk_size` is a multiple of it
+    pub fn new(disks: Vec<D>, chunk_size: u64) -> Result<Self> {
+        let (logical, physical) = common_sector_size(&disks)?;
+        if chunk_size == 0 || !chunk_size.is_multiple_of(logical as u64) {
+            return Err(Error::InvalidChunkSize(chunk_size));
+        }
+
+        let mut smallest = u64::MAX;
+        for disk in &disks {
+            smallest = core::cmp::min(smallest, disk.capacity()?);
+        }
+        let capacity = math::round_down(smallest, chunk_size) * disks.len() as u64;
+
+        Ok(Self {
+            disks,
+            chunk_size,
+            capacity,
+            geometry: Geometry::with_vhd_capacity_and_sector(capacity, logical),
+            physical_sector_size: physical,
+        })
+    }
+
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    pub fn disks(&self) -> &[D] {
+        &self.disks
+    }
+
+    pub fn into_inner(self) -> Vec<D> {
+        self.disks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::pattern;
+    use crate::MemoryDisk;
+
+    #[test]
+    fn slice_disk_test() {
+        let disk = SliceDisk::new(MemoryDisk::from_vec(pattern(8192, 0)), 1024, 2048).unwrap();
+        assert_eq!(2048, disk.capacity().unwrap());
+        assert_eq!(512, disk.logical_sector_size().unwrap());
+
+        let mut buffer = vec![0_u8; 16];
+        assert_eq!(8, disk.read_at(2040, &mut buffer).unwrap());
+        assert_eq!(&pattern(8192, 0)[3064..3072], &buffer[..8]);
+        assert!(matches!(disk.read_at(2049, &mut buffer), Err(Error::ReadBeyondEOD)));
+
+        assert_eq!(2, disk.write_at(2046, b"slice").unwrap());
+        assert_eq!(b"sl", &disk.disk().to_vec()[3070..3072]);
+
+        assert!(matches!(
+            SliceDisk::new(MemoryDisk::with_capacity(4096), 1024, 4096),
+            Err(Error::SliceOutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn concat_disk_test() {
+        let parts = vec![
+            MemoryDisk::from_vec(pattern(1000, 0)),
+            MemoryDisk::from_vec(Vec::new()),
+            MemoryDisk::from_vec(pattern(3000, 1000)),
+        ];
+        let disk = ConcatDisk::new(parts).unwrap();
+        assert_eq!(4000, disk.capacity().unwrap());
+
+        let mut buffer = vec![0_u8; 4000];
+        disk.read_exact_at(0, &mut buffer).unwrap();
+        assert_eq!(pattern(4000, 0), buffer);
+
+        disk.write_all_at(990, &[0xEE; 20]).unwrap();
+        assert_eq!(&[0xEE; 10], &disk.disks()[0].to_vec()[990..]);
+        assert_eq!(&[0xEE; 10], &disk.disks()[2].to_vec()[..10]);
+        assert!(matches!(disk.write_at(4001, b"x"), Err(Error::WriteBeyondEOD)));
+
+        let disks = vec![
+            MemoryDisk::with_capacity(4096),
+            MemoryDisk::with_capacity(4096).with_sector_size(4096, 4096),
+        ];
+        assert!(matches!(ConcatDisk::new(disks), Err(Error::IncompatibleDisks)));
+    }
+
+    #[test]
+    fn stripe_disk_test() {
+        let disks = vec![MemoryDisk::with_capacity(4096), MemoryDisk::with_capacity(5000)];
+        let disk = StripeDisk::new(disks, 1024).unwrap();
+        assert_eq!(8192, disk.capacity().unwrap());
+
+        let data = pattern(8192, 3);
+        disk.write_all_at(0, &data).unwrap();
+        assert_eq!(&data[1024..2048], &disk.disks()[1].to_vec()[..1024]);
+        assert_eq!(&data[2048..3072], &disk.disks()[0].to_vec()[1024..2048]);
+
+        let mut buffer = vec![0_u8; 3000];
+        disk.read_exact_at(1000, &mut buffer).unwrap();
+        assert_eq!(&data[1000..4000], buffer.as_slice());
+
+        let disks = vec![MemoryDisk::with_capacity(4096)];
+        assert!(matches!(StripeDisk::new(disks, 1000), Err(Error::InvalidChunkSize(1000))));
+    }
+}
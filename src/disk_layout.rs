@@ -1,11 +1,13 @@
 use crate::prelude::*;
-use crate::{mbr, gpt};
+use crate::{apm, bsd, gpt, mbr};
 
 #[cfg_attr(any(feature = "std", test), derive(Debug))]
 pub enum PartitionKind {
     Free,
     Mbr{ kind: mbr::PartitionKind, boot: bool },
     Gpt{ kind: Uuid, id: Uuid, flags: u64, name: String },
+    Apm{ kind: String, name: String, flags: u32 },
+    Bsd{ kind: String, fs_type: u8, letter: char, flags: u32 },
 }
 
 impl From<&mbr::PartitionInfo> for PartitionKind {
@@ -27,6 +29,40 @@ impl From<&gpt::PartitionInfo> for PartitionKind {
     }
 }
 
+impl From<&apm::PartitionInfo> for PartitionKind {
+    fn from(info: &apm::PartitionInfo) -> Self {
+        if info.is_free() {
+            PartitionKind::Free
+        } else {
+            PartitionKind::Apm{ kind: info.kind.clone(), name: info.name.clone(), flags: info.flags }
+        }
+    }
+}
+
+impl PartitionKind {
+    fn from_bsd(info: &bsd::PartitionInfo, label: &bsd::Layout) -> Self {
+        PartitionKind::Bsd{ kind: info.fs_type_name().to_string(), fs_type: info.fs_type, letter: info.letter, flags: label.flags() }
+    }
+
+    /// The partition holds a Linux md RAID superblock
+    pub fn is_linux_raid(&self) -> bool {
+        match self {
+            PartitionKind::Mbr{ kind, .. } => kind.is_linux_raid(),
+            PartitionKind::Gpt{ kind, .. } => *kind == crate::md::gpt_partition_kind(),
+            _ => false,
+        }
+    }
+
+    /// The partition holds an LVM2 physical volume label
+    pub fn is_linux_lvm(&self) -> bool {
+        match self {
+            PartitionKind::Mbr{ kind, .. } => kind.is_linux_lvm(),
+            PartitionKind::Gpt{ kind, .. } => *kind == crate::lvm::gpt_partition_kind(),
+            _ => false,
+        }
+    }
+}
+
 pub struct PartitionInfo {
     pub offset: u64,
     pub length: u64,
@@ -53,7 +89,25 @@ impl From<&gpt::PartitionInfo> for PartitionInfo {
     }
 }
 
+impl From<&apm::PartitionInfo> for PartitionInfo {
+    fn from(info: &apm::PartitionInfo) -> Self {
+        PartitionInfo {
+            offset: info.offset,
+            length: info.length,
+            kind: PartitionKind::from(info),
+        }
+    }
+}
+
 impl PartitionInfo {
+    fn from_bsd(info: &bsd::PartitionInfo, label: &bsd::Layout) -> Self {
+        PartitionInfo {
+            offset: info.offset,
+            length: info.length,
+            kind: PartitionKind::from_bsd(info, label),
+        }
+    }
+
     pub fn raw(length: u64) -> Self {
         PartitionInfo {
             offset: 0,
@@ -66,6 +120,8 @@ impl PartitionInfo {
 pub enum DiskLayout {
     Mbr(mbr::Layout),
     Gpt(gpt::Layout),
+    Apm(apm::Layout),
+    Bsd(bsd::Layout), // disklabel on the entire disk, labels inside MBR slices are reported by mbr::Layout
     Raw(u64), // entire disk length
 }
 
@@ -74,18 +130,50 @@ impl DiskLayout {
         let mbr: mbr::MasterBootRecord = tools::read_disk_struct(disk, 0)?;
 
         if !mbr.is_valid() {
-            return Ok(DiskLayout::Raw(disk.capacity()?));
+            if let Some(layout) = apm::Layout::read(disk)? {
+                return Ok(DiskLayout::Apm(layout));
+            }
+
+            return Self::read_bsd_or_raw(disk);
         }
 
         if mbr.is_gpt_protective() {
-            let layout = gpt::Layout::read(disk, mbr)?;
-            return Ok(DiskLayout::Gpt(layout));
+            match gpt::Layout::read(disk, mbr) {
+                Ok(layout) => return Ok(DiskLayout::Gpt(layout)),
+                // the extra entries of a hybrid MBR still describe the disk when its GPT is damaged
+                Err(Error::InvalidGptHeader) | Err(Error::InvalidGptCrc) if mbr.is_hybrid() => (),
+                Err(err) => return Err(err),
+            }
         }
 
         let layout = mbr::Layout::read(disk, mbr)?;
+        if layout.partitions().is_empty() {
+            // "dangerously dedicated" BSD disks may have a boot sector with the MBR signature
+            return Self::read_bsd_or_raw(disk);
+        }
+
         Ok(DiskLayout::Mbr(layout))
     }
 
+    /// [`read`](Self::read) for async disks, only the sectors the layout occupies are read
+    #[cfg(feature = "async")]
+    pub async fn read_async(disk: &impl crate::AsyncDisk) -> Result<DiskLayout> {
+        let replay = crate::async_disk::ReplayDisk::new(
+            disk.capacity().await?,
+            disk.geometry().await?,
+            disk.physical_sector_size().await?,
+        );
+        crate::async_disk::replay(disk, replay, Self::read).await
+    }
+
+    fn read_bsd_or_raw(disk: &impl Disk) -> Result<DiskLayout> {
+        let capacity = disk.capacity()?;
+        match bsd::Layout::read(disk, 0, capacity)? {
+            Some(layout) => Ok(DiskLayout::Bsd(layout)),
+            None => Ok(DiskLayout::Raw(capacity)),
+        }
+    }
+
     pub fn partitions(&self) -> DiskLayoutParts<'_> {
         DiskLayoutParts { layout: self, index: 0 }
     }
@@ -105,10 +193,29 @@ impl core::iter::Iterator for DiskLayoutParts<'_> {
                 self.index += 1;
                 PartitionInfo::from(p)
             }),
-            DiskLayout::Mbr(mbr) => mbr.partitions().get(self.index).map(|p| {
+            DiskLayout::Mbr(mbr) => {
+                // primary and logical partitions first, then the ones from nested BSD labels
+                let info = match mbr.partitions().get(self.index) {
+                    Some(p) => Some(PartitionInfo::from(p)),
+                    None => mbr
+                        .bsd_labels()
+                        .iter()
+                        .flat_map(|label| label.partitions().iter().map(move |p| PartitionInfo::from_bsd(p, label)))
+                        .nth(self.index - mbr.partitions().len()),
+                };
+                if info.is_some() {
+                    self.index += 1;
+                }
+                info
+            }
+            DiskLayout::Apm(apm) => apm.partitions().get(self.index).map(|p| {
                 self.index += 1;
                 PartitionInfo::from(p)
             }),
+            DiskLayout::Bsd(bsd) => bsd.partitions().get(self.index).map(|p| {
+                self.index += 1;
+                PartitionInfo::from_bsd(p, bsd)
+            }),
             DiskLayout::Raw(disk_capacity) => match self.index {
                 0 => {
                     self.index += 1;
@@ -124,7 +231,11 @@ impl core::iter::ExactSizeIterator for DiskLayoutParts<'_> {
     fn len(&self) -> usize {
         match self.layout {
             DiskLayout::Gpt(gpt) => gpt.partitions().len(),
-            DiskLayout::Mbr(mbr) => mbr.partitions().len(),
+            DiskLayout::Mbr(mbr) => {
+                mbr.partitions().len() + mbr.bsd_labels().iter().map(|l| l.partitions().len()).sum::<usize>()
+            }
+            DiskLayout::Apm(apm) => apm.partitions().len(),
+            DiskLayout::Bsd(bsd) => bsd.partitions().len(),
             DiskLayout::Raw(_) => 1,
         }
     }
@@ -5,15 +5,33 @@ pub enum Error {
     InvalidGptMbr,
     InvalidGptHeader,
     InvalidGptCrc,
+    InvalidHybridMbr,
 
     ReadBeyondEOD,
     WriteBeyondEOD,
     UnexpectedEOD, //
     WriteZero,
+    ReadOnly,
+    SliceOutOfBounds,
+    IncompatibleDisks,
+    InvalidChunkSize(u64),
     NotFound(String),
+    NotASymlink(String),
+    UnknownFileSystem,
+    UnsupportedFileSystem(crate::FsKind),
 
     Platform(crate::platform::Error),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
     Vhd(crate::vhd::VhdError),
+    Ldm(crate::ldm::LdmError),
+    Md(crate::md::MdError),
+    Lvm(crate::lvm::LvmError),
+    Fat(crate::fat::FatError),
+    ExFat(crate::exfat::ExFatError),
+    Ntfs(crate::ntfs::NtfsError),
+    Ext(crate::ext::ExtError),
+    Iso(crate::iso9660::IsoError),
 }
 
 impl core::fmt::Display for Error {
@@ -22,13 +40,31 @@ impl core::fmt::Display for Error {
             Error::InvalidGptMbr => write!(f, "Invalid GPT protective MBR"),
             Error::InvalidGptHeader => write!(f, "Invalid GPT header"),
             Error::InvalidGptCrc => write!(f, "Invalid GPT CRC"),
+            Error::InvalidHybridMbr => write!(f, "Invalid hybrid MBR layout"),
             Error::UnexpectedEOD => write!(f, "Unexpected end of data"),
             Error::ReadBeyondEOD => write!(f, "Read beyound end of data"),
             Error::WriteBeyondEOD => write!(f, "Write beyound end of data"),
             Error::WriteZero => write!(f, "Failed to write whole buffer"),
+            Error::ReadOnly => write!(f, "The image is opened read-only"),
+            Error::SliceOutOfBounds => write!(f, "The slice is out of the disk bounds"),
+            Error::IncompatibleDisks => write!(f, "No disks or disks with different sector sizes"),
+            Error::InvalidChunkSize(size) => write!(f, "Invalid stripe chunk size {}", size),
             Error::NotFound(ref s) => write!(f, "'{}' not found", s),
+            Error::NotASymlink(ref s) => write!(f, "'{}' is not a symbolic link", s),
+            Error::UnknownFileSystem => write!(f, "Unknown filesystem"),
+            Error::UnsupportedFileSystem(kind) => write!(f, "{} filesystem is not supported", kind),
             Error::Platform(ref e) => e.fmt(f),
+            #[cfg(feature = "std")]
+            Error::Io(ref e) => e.fmt(f),
             Error::Vhd(ref e) => e.fmt(f),
+            Error::Ldm(ref e) => e.fmt(f),
+            Error::Md(ref e) => e.fmt(f),
+            Error::Lvm(ref e) => e.fmt(f),
+            Error::Fat(ref e) => e.fmt(f),
+            Error::ExFat(ref e) => e.fmt(f),
+            Error::Ntfs(ref e) => e.fmt(f),
+            Error::Ext(ref e) => e.fmt(f),
+            Error::Iso(ref e) => e.fmt(f),
         }
     }
 }
@@ -38,7 +74,15 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Error::Platform(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
             _ => None,
         }
     }
 }
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
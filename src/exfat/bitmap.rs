@@ -0,0 +1,40 @@
+use super::*;
+
+/// Cluster heap allocation bitmap, one bit per cluster starting with cluster 2
+pub struct AllocationBitmap {
+    data: Vec<u8>,
+    cluster_count: u32,
+}
+
+impl AllocationBitmap {
+    pub(crate) fn new(data: Vec<u8>, cluster_count: u32) -> Result<Self> {
+        if (data.len() as u64) * 8 < cluster_count as u64 {
+            return Err(Error::from(ExFatError::NoAllocationBitmap));
+        }
+
+        Ok(Self { data, cluster_count })
+    }
+
+    pub fn is_allocated(&self, cluster: u32) -> bool {
+        match cluster.checked_sub(FIRST_CLUSTER) {
+            Some(index) if index < self.cluster_count => self.data[index as usize / 8] & (1 << (index % 8)) != 0,
+            _ => false,
+        }
+    }
+
+    pub fn allocated_clusters(&self) -> u32 {
+        let full_bytes = self.cluster_count as usize / 8;
+        let full: u32 = self.data[..full_bytes].iter().map(|b| b.count_ones()).sum();
+        let tail_bits = self.cluster_count % 8;
+        let tail = match tail_bits {
+            0 => 0,
+            bits => (self.data[full_bytes] & ((1 << bits) - 1)).count_ones(),
+        };
+
+        full + tail
+    }
+
+    pub fn free_clusters(&self) -> u32 {
+        self.cluster_count - self.allocated_clusters()
+    }
+}
@@ -0,0 +1,180 @@
+use super::*;
+
+const FS_NAME: &[u8; 8] = b"EXFAT   ";
+/// Boot sector, 8 extended boot sectors, OEM parameters, reserved and checksum sectors
+const BOOT_REGION_SECTORS: u64 = 12;
+const CHECKSUM_SECTOR: usize = 11;
+const MAX_CLUSTER_COUNT: u32 = 0xFFFF_FFF5;
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct BootSectorRecord {
+    jump: [u8; 3],
+    fs_name: [u8; 8],
+    must_be_zero: [u8; 53],
+    partition_offset: u64,
+    volume_length: u64,
+    fat_offset: u32,
+    fat_length: u32,
+    cluster_heap_offset: u32,
+    cluster_count: u32,
+    root_cluster: u32,
+    serial_number: u32,
+    revision: u16,
+    volume_flags: u16,
+    bytes_per_sector_shift: u8,
+    sectors_per_cluster_shift: u8,
+    fat_count: u8,
+    drive_select: u8,
+    percent_in_use: u8,
+}
+
+/// Checks the signature only, `mbr::KnownPartitionKind::Ntfs` (0x07) is used by both NTFS and exFAT
+pub fn is_exfat(boot_sector: &[u8]) -> bool {
+    boot_sector.len() >= 512 && &boot_sector[3..11] == FS_NAME
+}
+
+/// Parsed main or backup boot sector
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct BootSector {
+    /// Sectors, informational only
+    pub partition_offset: u64,
+    /// Sectors
+    pub volume_length: u64,
+    /// Sectors
+    pub fat_offset: u32,
+    /// Sectors
+    pub fat_length: u32,
+    /// Sectors
+    pub cluster_heap_offset: u32,
+    pub cluster_count: u32,
+    pub root_cluster: u32,
+    pub serial_number: u32,
+    /// Major version in the high byte
+    pub revision: u16,
+    pub volume_flags: u16,
+    pub bytes_per_sector: u32,
+    pub sectors_per_cluster: u32,
+    /// 1 or 2 (TexFAT)
+    pub fat_count: u32,
+    /// 0-100 or 0xFF if unknown
+    pub percent_in_use: u8,
+    /// True if the main boot region was damaged and the backup one was used
+    pub from_backup: bool,
+}
+
+impl BootSector {
+    /// Reads and validates the main boot region, falls back to the backup one
+    pub fn read(device: &impl ReadAt) -> Result<Self> {
+        match Self::read_region(device, 0) {
+            Ok(boot) => Ok(boot),
+            Err(e) => match Self::read_region(device, BOOT_REGION_SECTORS) {
+                Ok(boot) => Ok(Self { from_backup: true, ..boot }),
+                Err(_) => Err(e),
+            },
+        }
+    }
+
+    fn read_region(device: &impl ReadAt, first_sector: u64) -> Result<Self> {
+        // sector size is unknown before the boot sector is parsed
+        let mut sector = [0_u8; 512];
+        device.read_exact_at(first_sector * 512, &mut sector)?;
+        let boot = Self::parse(&sector)?;
+
+        let sector_size = boot.bytes_per_sector as usize;
+        let mut region = vec![0_u8; sector_size * BOOT_REGION_SECTORS as usize];
+        device.read_exact_at(first_sector * sector_size as u64, &mut region)?;
+
+        let checksum = boot_checksum(&region[..CHECKSUM_SECTOR * sector_size]);
+        let valid = region[CHECKSUM_SECTOR * sector_size..]
+            .chunks_exact(4)
+            .all(|chunk| le_u32(chunk, 0) == checksum);
+        if !valid {
+            return Err(Error::from(ExFatError::InvalidBootChecksum));
+        }
+
+        Ok(boot)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if !is_exfat(bytes) || bytes[510..512] != [0x55, 0xAA] {
+            return Err(Error::from(ExFatError::InvalidBootSector));
+        }
+
+        let record: BootSectorRecord = tools::read_struct(bytes);
+        let valid = record.must_be_zero.iter().all(|&b| b == 0)
+            && (9..=12).contains(&record.bytes_per_sector_shift)
+            && record.sectors_per_cluster_shift <= 25 - record.bytes_per_sector_shift
+            && (1..=2).contains(&record.fat_count)
+            && record.cluster_count <= MAX_CLUSTER_COUNT
+            && record.root_cluster >= FIRST_CLUSTER
+            && record.root_cluster < record.cluster_count + FIRST_CLUSTER;
+        if !valid {
+            return Err(Error::from(ExFatError::InvalidBootSector));
+        }
+
+        Ok(Self {
+            partition_offset: record.partition_offset,
+            volume_length: record.volume_length,
+            fat_offset: record.fat_offset,
+            fat_length: record.fat_length,
+            cluster_heap_offset: record.cluster_heap_offset,
+            cluster_count: record.cluster_count,
+            root_cluster: record.root_cluster,
+            serial_number: record.serial_number,
+            revision: record.revision,
+            volume_flags: record.volume_flags,
+            bytes_per_sector: 1 << record.bytes_per_sector_shift,
+            sectors_per_cluster: 1 << record.sectors_per_cluster_shift,
+            fat_count: record.fat_count as u32,
+            percent_in_use: record.percent_in_use,
+            from_backup: false,
+        })
+    }
+
+    pub fn cluster_size(&self) -> u32 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    /// Index of the FAT and allocation bitmap in use
+    pub fn active_fat(&self) -> u32 {
+        (self.volume_flags & 0x01) as u32
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.volume_flags & 0x02 != 0
+    }
+
+    pub fn media_failure(&self) -> bool {
+        self.volume_flags & 0x04 != 0
+    }
+
+    /// Byte offset of the active FAT
+    pub(crate) fn fat_position(&self) -> u64 {
+        let fat = core::cmp::min(self.active_fat(), self.fat_count - 1);
+        (self.fat_offset as u64 + fat as u64 * self.fat_length as u64) * self.bytes_per_sector as u64
+    }
+
+    /// Byte offset of the cluster
+    pub(crate) fn cluster_offset(&self, cluster: u32) -> u64 {
+        debug_assert!(cluster >= FIRST_CLUSTER);
+
+        let sector = self.cluster_heap_offset as u64 + (cluster - FIRST_CLUSTER) as u64 * self.sectors_per_cluster as u64;
+        sector * self.bytes_per_sector as u64
+    }
+
+    /// The last valid cluster number
+    pub(crate) fn max_cluster(&self) -> u32 {
+        self.cluster_count + FIRST_CLUSTER - 1
+    }
+}
+
+/// Boot region checksum, `VolumeFlags` and `PercentInUse` are skipped as they change often
+pub(crate) fn boot_checksum(sectors: &[u8]) -> u32 {
+    sectors
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !matches!(i, 106 | 107 | 112))
+        .fold(0, |checksum, (_, &byte)| checksum32(checksum, byte))
+}
@@ -0,0 +1,229 @@
+use super::*;
+
+const END_OF_DIRECTORY: u8 = 0x00;
+const IN_USE: u8 = 0x80;
+const SECONDARY: u8 = 0x40;
+
+const ALLOCATION_BITMAP: u8 = 0x81;
+const UPCASE_TABLE: u8 = 0x82;
+const VOLUME_LABEL: u8 = 0x83;
+const FILE: u8 = 0x85;
+const STREAM_EXTENSION: u8 = 0xC0;
+const FILE_NAME: u8 = 0xC1;
+
+const NAME_CHARS_PER_ENTRY: usize = 15;
+const ALLOCATION_POSSIBLE: u8 = 0x01;
+const NO_FAT_CHAIN: u8 = 0x02;
+
+/// exFAT timestamp, local time with an optional offset from UTC
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Timestamp {
+    pub local: DateTime,
+    /// Minutes, `None` if the offset was not recorded
+    pub utc_offset: Option<i16>,
+}
+
+impl Timestamp {
+    /// Decodes the packed timestamp, 10 ms increment and UTC offset fields
+    pub fn from_raw(timestamp: u32, increment: u8, utc_offset: u8) -> Option<Self> {
+        let local = DateTime::from_fat((timestamp >> 16) as u16, timestamp as u16, increment)?;
+        let utc_offset = if utc_offset & 0x80 != 0 {
+            // signed 7 bit value in 15 minute intervals
+            Some(((utc_offset << 1) as i8 >> 1) as i16 * 15)
+        } else {
+            None
+        };
+
+        Some(Self { local, utc_offset })
+    }
+
+    /// Seconds since 1970-01-01 UTC, the local time is treated as UTC if the offset is unknown
+    pub fn to_unix_time(&self) -> i64 {
+        self.local.to_unix_time() - self.utc_offset.unwrap_or(0) as i64 * 60
+    }
+}
+
+/// File or directory described by a File/Stream Extension/File Name entry set
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct DirEntry {
+    pub name: String,
+    pub attributes: Attributes,
+    /// Allocated data length
+    pub size: u64,
+    /// Bytes actually written, the rest reads as zeros
+    pub valid_size: u64,
+    pub first_cluster: u32,
+    /// `NoFatChain`: clusters are contiguous and the FAT is not used
+    pub contiguous: bool,
+    pub created: Option<Timestamp>,
+    pub modified: Option<Timestamp>,
+    pub accessed: Option<Timestamp>,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.attributes.is_directory()
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.attributes.is_directory()
+    }
+
+    pub(crate) fn root(boot: &BootSector) -> Self {
+        Self {
+            name: String::new(),
+            attributes: Attributes(Attributes::DIRECTORY),
+            size: 0,
+            valid_size: 0,
+            first_cluster: boot.root_cluster,
+            contiguous: false,
+            created: None,
+            modified: None,
+            accessed: None,
+        }
+    }
+}
+
+/// Critical entries of the root directory and regular entry sets
+pub(crate) enum RawEntry {
+    Entry(DirEntry),
+    AllocationBitmap { index: u32, first_cluster: u32, size: u64 },
+    UpcaseTable { checksum: u32, first_cluster: u32, size: u64 },
+    VolumeLabel(String),
+}
+
+pub(crate) fn entry_set_checksum(set: &[u8]) -> u16 {
+    set.iter()
+        .enumerate()
+        .filter(|(i, _)| !matches!(i, 2 | 3))
+        .fold(0, |checksum, (_, &byte)| checksum16(checksum, byte))
+}
+
+fn utf16_string(chars: &[u16]) -> String {
+    core::char::decode_utf16(chars.iter().copied())
+        .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn parse_entry_set(set: &[u8], index: usize) -> Result<DirEntry> {
+    let invalid = |name: String| Error::from(ExFatError::InvalidEntrySet(name));
+    let stream = &set[DIR_ENTRY_SIZE..2 * DIR_ENTRY_SIZE];
+    if stream[0] != STREAM_EXTENSION {
+        return Err(invalid(format!("#{}", index)));
+    }
+
+    let name_length = stream[3] as usize;
+    let name_entries = math::ceil(name_length, NAME_CHARS_PER_ENTRY);
+    let mut chars = Vec::with_capacity(name_entries * NAME_CHARS_PER_ENTRY);
+    for entry in set[2 * DIR_ENTRY_SIZE..].chunks_exact(DIR_ENTRY_SIZE).take(name_entries) {
+        if entry[0] != FILE_NAME {
+            break;
+        }
+        chars.extend(entry[2..].chunks_exact(2).map(|c| le_u16(c, 0)));
+    }
+
+    if name_length == 0 || chars.len() < name_length {
+        return Err(invalid(format!("#{}", index)));
+    }
+    let name = utf16_string(&chars[..name_length]);
+
+    if entry_set_checksum(set) != le_u16(set, 2) {
+        return Err(Error::from(ExFatError::InvalidEntrySetChecksum(name)));
+    }
+
+    let flags = stream[1];
+    let size = le_u64(stream, 24);
+    let valid_size = le_u64(stream, 8);
+    if valid_size > size {
+        return Err(invalid(name));
+    }
+
+    let file = &set[..DIR_ENTRY_SIZE];
+    Ok(DirEntry {
+        name,
+        attributes: Attributes(le_u16(file, 4) as u8),
+        size,
+        valid_size,
+        first_cluster: if flags & ALLOCATION_POSSIBLE != 0 { le_u32(stream, 20) } else { 0 },
+        contiguous: flags & NO_FAT_CHAIN != 0,
+        created: Timestamp::from_raw(le_u32(file, 8), file[20], file[22]),
+        modified: Timestamp::from_raw(le_u32(file, 12), file[21], file[23]),
+        accessed: Timestamp::from_raw(le_u32(file, 16), 0, file[24]),
+    })
+}
+
+/// Parses all in-use entries up to the end of the directory, a broken entry set does not stop parsing
+pub(crate) fn parse_entries(data: &[u8]) -> Vec<Result<RawEntry>> {
+    let mut entries = Vec::new();
+    let mut index = 0;
+    while let Some(entry) = data.get(index * DIR_ENTRY_SIZE..(index + 1) * DIR_ENTRY_SIZE) {
+        let entry_type = entry[0];
+        if entry_type == END_OF_DIRECTORY {
+            break;
+        }
+
+        index += 1;
+        if entry_type & IN_USE == 0 || entry_type & SECONDARY != 0 {
+            continue; // deleted entries and secondary entries without a primary one
+        }
+
+        match entry_type {
+            ALLOCATION_BITMAP => entries.push(Ok(RawEntry::AllocationBitmap {
+                index: (entry[1] & 0x01) as u32,
+                first_cluster: le_u32(entry, 20),
+                size: le_u64(entry, 24),
+            })),
+            UPCASE_TABLE => entries.push(Ok(RawEntry::UpcaseTable {
+                checksum: le_u32(entry, 4),
+                first_cluster: le_u32(entry, 20),
+                size: le_u64(entry, 24),
+            })),
+            VOLUME_LABEL => {
+                let len = core::cmp::min(entry[1] as usize, 11);
+                let chars: Vec<u16> = entry[2..2 + len * 2].chunks_exact(2).map(|c| le_u16(c, 0)).collect();
+                entries.push(Ok(RawEntry::VolumeLabel(utf16_string(&chars))));
+            }
+            FILE => {
+                let secondary_count = entry[1] as usize;
+                let start = (index - 1) * DIR_ENTRY_SIZE;
+                match data.get(start..start + (secondary_count + 1) * DIR_ENTRY_SIZE) {
+                    Some(set) if secondary_count >= 2 => {
+                        entries.push(parse_entry_set(set, index - 1).map(RawEntry::Entry));
+                        index += secondary_count;
+                    }
+                    _ => entries.push(Err(Error::from(ExFatError::InvalidEntrySet(format!("#{}", index - 1))))),
+                }
+            }
+            _ => (), // volume GUID, TexFAT padding and unknown benign entries
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_test() {
+        // 2021-06-15 13:45:31.500 local, UTC+03:00
+        let date = ((2021 - 1980) << 9) | (6 << 5) | 15;
+        let time = (13 << 11) | (45 << 5) | (30 / 2);
+        let ts = Timestamp::from_raw((date << 16) | time, 150, 0x80 | 12).unwrap();
+        assert_eq!(31, ts.local.second);
+        assert_eq!(500, ts.local.millisecond);
+        assert_eq!(Some(180), ts.utc_offset);
+        assert_eq!(ts.local.to_unix_time() - 3 * 3600, ts.to_unix_time());
+
+        // UTC-04:30, 0x80 | -18 as 7 bits
+        let ts = Timestamp::from_raw((date << 16) | time, 0, 0x80 | (0x80 - 18)).unwrap();
+        assert_eq!(Some(-270), ts.utc_offset);
+
+        let ts = Timestamp::from_raw((date << 16) | time, 0, 0).unwrap();
+        assert_eq!(None, ts.utc_offset);
+        assert!(Timestamp::from_raw(0, 0, 0).is_none());
+    }
+}
@@ -0,0 +1,40 @@
+use crate::xstd::String;
+
+#[derive(Debug)]
+pub enum ExFatError {
+    InvalidBootSector,
+    InvalidBootChecksum,
+    InvalidCluster(u32),
+    ClusterLoop(u32), // chain start
+    InvalidEntrySet(String),
+    InvalidEntrySetChecksum(String),
+    NoAllocationBitmap,
+    NoUpcaseTable,
+    InvalidUpcaseTable,
+    NotADirectory(String),
+    NotAFile(String),
+}
+
+impl core::fmt::Display for ExFatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ExFatError::InvalidBootSector => f.write_str("Invalid exFAT boot sector"),
+            ExFatError::InvalidBootChecksum => f.write_str("Invalid exFAT boot region checksum"),
+            ExFatError::InvalidCluster(cluster) => write!(f, "Invalid exFAT cluster {}", cluster),
+            ExFatError::ClusterLoop(start) => write!(f, "exFAT cluster chain starting at {} is looped", start),
+            ExFatError::InvalidEntrySet(name) => write!(f, "Invalid exFAT directory entry set '{}'", name),
+            ExFatError::InvalidEntrySetChecksum(name) => write!(f, "Invalid exFAT entry set checksum of '{}'", name),
+            ExFatError::NoAllocationBitmap => f.write_str("No exFAT allocation bitmap"),
+            ExFatError::NoUpcaseTable => f.write_str("No exFAT up-case table"),
+            ExFatError::InvalidUpcaseTable => f.write_str("Invalid exFAT up-case table"),
+            ExFatError::NotADirectory(path) => write!(f, "'{}' is not a directory", path),
+            ExFatError::NotAFile(path) => write!(f, "'{}' is not a file", path),
+        }
+    }
+}
+
+impl From<ExFatError> for crate::Error {
+    fn from(e: ExFatError) -> Self {
+        Self::ExFat(e)
+    }
+}
@@ -0,0 +1,422 @@
+use super::dir::{parse_entries, RawEntry};
+use super::*;
+
+/// exFAT volume
+pub struct ExFatFileSystem<R: ReadAt> {
+    device: R,
+    boot: BootSector,
+    bitmap: AllocationBitmap,
+    upcase: UpcaseTable,
+    label: String,
+}
+
+impl<R: ReadAt> ExFatFileSystem<R> {
+    pub fn open(device: R) -> Result<Self> {
+        let boot = BootSector::read(&device)?;
+
+        let root = read_root(&device, &boot)?;
+
+        let mut bitmaps = Vec::new();
+        let mut upcase = None;
+        let mut label = String::new();
+        // broken entry sets are reported when the directory is listed
+        for entry in parse_entries(&root).into_iter().filter_map(|entry| entry.ok()) {
+            match entry {
+                RawEntry::AllocationBitmap {
+                    index,
+                    first_cluster,
+                    size,
+                } => bitmaps.push((index, first_cluster, size)),
+                RawEntry::UpcaseTable {
+                    checksum,
+                    first_cluster,
+                    size,
+                } => upcase = Some((checksum, first_cluster, size)),
+                RawEntry::VolumeLabel(text) => label = text,
+                RawEntry::Entry(_) => (),
+            }
+        }
+
+        // TexFAT volumes have a bitmap per FAT
+        let (_, first_cluster, size) = bitmaps
+            .iter()
+            .find(|(index, _, _)| *index == boot.active_fat())
+            .or_else(|| bitmaps.first())
+            .copied()
+            .ok_or_else(|| Error::from(ExFatError::NoAllocationBitmap))?;
+        let clusters = table::read_chain(&device, &boot, first_cluster)?;
+        let bitmap = AllocationBitmap::new(read_clusters(&device, &boot, &clusters, Some(size))?, boot.cluster_count)?;
+
+        let (checksum, first_cluster, size) = upcase.ok_or_else(|| Error::from(ExFatError::NoUpcaseTable))?;
+        let clusters = table::read_chain(&device, &boot, first_cluster)?;
+        let upcase = UpcaseTable::parse(&read_clusters(&device, &boot, &clusters, Some(size))?, checksum)?;
+
+        Ok(Self {
+            device,
+            boot,
+            bitmap,
+            upcase,
+            label,
+        })
+    }
+
+    pub fn device(&self) -> &R {
+        &self.device
+    }
+
+    pub fn into_inner(self) -> R {
+        self.device
+    }
+
+    pub fn boot_sector(&self) -> &BootSector {
+        &self.boot
+    }
+
+    pub fn volume_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn serial_number(&self) -> u32 {
+        self.boot.serial_number
+    }
+
+    pub fn allocation_bitmap(&self) -> &AllocationBitmap {
+        &self.bitmap
+    }
+
+    pub fn upcase_table(&self) -> &UpcaseTable {
+        &self.upcase
+    }
+
+    pub fn free_clusters(&self) -> u32 {
+        self.bitmap.free_clusters()
+    }
+
+    pub fn root(&self) -> DirEntry {
+        DirEntry::root(&self.boot)
+    }
+
+    fn clusters_of(&self, entry: &DirEntry) -> Result<Vec<u32>> {
+        if entry.contiguous {
+            table::contiguous_chain(&self.boot, entry.first_cluster, entry.size)
+        } else {
+            table::read_chain(&self.device, &self.boot, entry.first_cluster)
+        }
+    }
+
+    /// Lists the directory
+    pub fn read_dir_entry(&self, dir: &DirEntry) -> Result<Vec<DirEntry>> {
+        if !dir.is_dir() {
+            return Err(Error::from(ExFatError::NotADirectory(dir.name.clone())));
+        }
+
+        let clusters = self.clusters_of(dir)?;
+        let data = read_clusters(&self.device, &self.boot, &clusters, None)?;
+        let mut entries = Vec::new();
+        for entry in parse_entries(&data) {
+            if let RawEntry::Entry(entry) = entry? {
+                entries.push(entry);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let dir = self.entry(path)?;
+        self.read_dir_entry(&dir)
+    }
+
+    /// Looks the path up, `/` and `\` separators are accepted, names are compared using the up-case table
+    pub fn entry(&self, path: &str) -> Result<DirEntry> {
+        let mut stack = vec![self.root()];
+        for name in path.split(&['/', '\\'][..]) {
+            match name {
+                "" | "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+
+            let dir = stack.last().expect("root is always present");
+            if !dir.is_dir() {
+                return Err(Error::from(ExFatError::NotADirectory(path.to_string())));
+            }
+
+            let name: Vec<u16> = name.encode_utf16().collect();
+            let entry = self
+                .read_dir_entry(dir)?
+                .into_iter()
+                .find(|entry| self.upcase.same_name(&entry.name.encode_utf16().collect::<Vec<_>>(), &name))
+                .ok_or_else(|| Error::NotFound(path.to_string()))?;
+            stack.push(entry);
+        }
+
+        Ok(stack.pop().expect("root is always present"))
+    }
+
+    pub fn open_entry(&self, entry: &DirEntry) -> Result<File<'_, R>> {
+        if entry.is_dir() {
+            return Err(Error::from(ExFatError::NotAFile(entry.name.clone())));
+        }
+
+        let clusters = self.clusters_of(entry)?;
+        if (clusters.len() as u64) * (self.boot.cluster_size() as u64) < entry.size {
+            return Err(Error::from(ExFatError::InvalidCluster(entry.first_cluster)));
+        }
+
+        Ok(File::new(self, entry.clone(), clusters))
+    }
+
+    pub fn open_file(&self, path: &str) -> Result<File<'_, R>> {
+        let entry = self.entry(path)?;
+        self.open_entry(&entry)
+    }
+}
+
+/// Reads the clusters content, truncated to `size` if set
+fn read_root(device: &impl ReadAt, boot: &BootSector) -> Result<Vec<u8>> {
+    let clusters = table::read_chain(device, boot, DirEntry::root(boot).first_cluster)?;
+    read_clusters(device, boot, &clusters, None)
+}
+
+/// Reads the label from the root directory, without loading the allocation bitmap and the up-case table
+pub fn read_volume_label(device: &impl ReadAt, boot: &BootSector) -> Result<String> {
+    let label = parse_entries(&read_root(device, boot)?)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .find_map(|entry| match entry {
+            RawEntry::VolumeLabel(text) => Some(text),
+            _ => None,
+        });
+    Ok(label.unwrap_or_default())
+}
+
+fn read_clusters(device: &impl ReadAt, boot: &BootSector, clusters: &[u32], size: Option<u64>) -> Result<Vec<u8>> {
+    let cluster_size = boot.cluster_size() as usize;
+    let mut data = vec![0_u8; clusters.len() * cluster_size];
+    for (cluster, chunk) in clusters.iter().zip(data.chunks_exact_mut(cluster_size)) {
+        device.read_exact_at(boot.cluster_offset(*cluster), chunk)?;
+    }
+
+    if let Some(size) = size {
+        if size > data.len() as u64 {
+            return Err(Error::from(ExFatError::InvalidCluster(clusters.first().copied().unwrap_or(0))));
+        }
+        data.truncate(size as usize);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dir::entry_set_checksum;
+    use super::*;
+    use crate::MemoryDisk;
+
+    const SECTOR: usize = 512;
+    const FAT_OFFSET: usize = 24;
+    const HEAP_OFFSET: usize = 32;
+    const CLUSTER_COUNT: u32 = 32;
+
+    fn cluster(image: &mut [u8], cluster: u32) -> &mut [u8] {
+        let start = (HEAP_OFFSET + cluster as usize - 2) * SECTOR;
+        &mut image[start..start + SECTOR]
+    }
+
+    fn set_fat(image: &mut [u8], cluster: u32, value: u32) {
+        let pos = FAT_OFFSET * SECTOR + cluster as usize * 4;
+        image[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn entry_set(name: &str, attributes: u8, first_cluster: u32, size: u64, valid_size: u64, flags: u8) -> Vec<u8> {
+        let chars: Vec<u16> = name.encode_utf16().collect();
+        let name_entries = math::ceil(chars.len(), 15);
+        let mut set = vec![0_u8; (2 + name_entries) * DIR_ENTRY_SIZE];
+
+        set[0] = 0x85;
+        set[1] = (1 + name_entries) as u8;
+        set[4] = attributes;
+        // 2021-06-15 13:45:30, UTC+03:00
+        let timestamp: u32 = ((((2021 - 1980) << 9) | (6 << 5) | 15) << 16) | ((13 << 11) | (45 << 5) | 15);
+        set[12..16].copy_from_slice(&timestamp.to_le_bytes());
+        set[23] = 0x80 | 12;
+
+        let stream = &mut set[32..64];
+        stream[0] = 0xC0;
+        stream[1] = flags;
+        stream[3] = chars.len() as u8;
+        stream[8..16].copy_from_slice(&valid_size.to_le_bytes());
+        stream[20..24].copy_from_slice(&first_cluster.to_le_bytes());
+        stream[24..32].copy_from_slice(&size.to_le_bytes());
+
+        for (i, part) in chars.chunks(15).enumerate() {
+            let entry = &mut set[(2 + i) * DIR_ENTRY_SIZE..(3 + i) * DIR_ENTRY_SIZE];
+            entry[0] = 0xC1;
+            for (j, c) in part.iter().enumerate() {
+                entry[2 + j * 2..4 + j * 2].copy_from_slice(&c.to_le_bytes());
+            }
+        }
+
+        let checksum = entry_set_checksum(&set);
+        set[2..4].copy_from_slice(&checksum.to_le_bytes());
+        set
+    }
+
+    /// 512 byte clusters: 2 bitmap, 3 up-case table, 4 root, 5 `Photos`, 6-7 `IMG 0001.JPG`, 8 `notes.txt`
+    fn exfat_image() -> Vec<u8> {
+        let mut image = vec![0_u8; (HEAP_OFFSET + CLUSTER_COUNT as usize) * SECTOR];
+
+        let boot = &mut image[..SECTOR];
+        boot[0..3].copy_from_slice(&[0xEB, 0x76, 0x90]);
+        boot[3..11].copy_from_slice(b"EXFAT   ");
+        boot[72..80].copy_from_slice(&((HEAP_OFFSET as u64 + CLUSTER_COUNT as u64).to_le_bytes()));
+        boot[80..84].copy_from_slice(&(FAT_OFFSET as u32).to_le_bytes());
+        boot[84..88].copy_from_slice(&1_u32.to_le_bytes());
+        boot[88..92].copy_from_slice(&(HEAP_OFFSET as u32).to_le_bytes());
+        boot[92..96].copy_from_slice(&CLUSTER_COUNT.to_le_bytes());
+        boot[96..100].copy_from_slice(&4_u32.to_le_bytes());
+        boot[100..104].copy_from_slice(&0xCAFE_F00D_u32.to_le_bytes());
+        boot[104..106].copy_from_slice(&0x0100_u16.to_le_bytes());
+        boot[108] = 9;
+        boot[109] = 0;
+        boot[110] = 1;
+        boot[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        let checksum = boot::boot_checksum(&image[..11 * SECTOR]);
+        for chunk in image[11 * SECTOR..12 * SECTOR].chunks_exact_mut(4) {
+            chunk.copy_from_slice(&checksum.to_le_bytes());
+        }
+
+        set_fat(&mut image, 0, 0xFFFF_FFF8);
+        set_fat(&mut image, 1, 0xFFFF_FFFF);
+        for &c in &[2, 3, 4, 5, 7] {
+            set_fat(&mut image, c, 0xFFFF_FFFF);
+        }
+        set_fat(&mut image, 6, 7);
+
+        // clusters 2-8 are in use
+        cluster(&mut image, 2)[0] = 0x7F;
+
+        // a-z map to A-Z, everything else to itself
+        let mut upcase = vec![0xFFFF_u16, 97];
+        upcase.extend((b'A'..=b'Z').map(u16::from));
+        upcase.extend(&[0xFFFF, 0xFFFF - 122]);
+        let upcase: Vec<u8> = upcase.iter().flat_map(|c| c.to_le_bytes()).collect();
+        let upcase_checksum = upcase.iter().fold(0, |sum, &b| checksum32(sum, b));
+        cluster(&mut image, 3)[..upcase.len()].copy_from_slice(&upcase);
+
+        let mut root = Vec::new();
+        let mut label = [0_u8; 32];
+        label[0] = 0x83;
+        label[1] = 6;
+        for (i, c) in "SDCARD".encode_utf16().enumerate() {
+            label[2 + i * 2..4 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        root.extend_from_slice(&label);
+
+        let mut bitmap = [0_u8; 32];
+        bitmap[0] = 0x81;
+        bitmap[20..24].copy_from_slice(&2_u32.to_le_bytes());
+        bitmap[24..32].copy_from_slice(&4_u64.to_le_bytes());
+        root.extend_from_slice(&bitmap);
+
+        let mut table = [0_u8; 32];
+        table[0] = 0x82;
+        table[4..8].copy_from_slice(&upcase_checksum.to_le_bytes());
+        table[20..24].copy_from_slice(&3_u32.to_le_bytes());
+        table[24..32].copy_from_slice(&(upcase.len() as u64).to_le_bytes());
+        root.extend_from_slice(&table);
+
+        root.extend(entry_set("Photos", Attributes::DIRECTORY, 5, 512, 512, 0x03));
+        let mut deleted = entry_set("deleted.txt", 0x20, 8, 10, 10, 0x03);
+        for entry in deleted.chunks_exact_mut(DIR_ENTRY_SIZE) {
+            entry[0] &= 0x7F;
+        }
+        root.extend(deleted);
+        root.extend(entry_set("notes.txt", 0x20, 8, 11, 11, 0x03));
+        cluster(&mut image, 4)[..root.len()].copy_from_slice(&root);
+
+        // FAT chain, the tail is beyond the valid data length
+        let photos = entry_set("IMG 0001 with a long name.JPG", 0x20, 6, 1000, 700, 0x01);
+        cluster(&mut image, 5)[..photos.len()].copy_from_slice(&photos);
+        cluster(&mut image, 6).iter_mut().for_each(|b| *b = 0x11);
+        cluster(&mut image, 7).iter_mut().for_each(|b| *b = 0x22);
+
+        cluster(&mut image, 8)[..11].copy_from_slice(b"hello exfat");
+        image
+    }
+
+    #[test]
+    fn exfat_read_test() {
+        let fs = ExFatFileSystem::open(MemoryDisk::from_vec(exfat_image())).unwrap();
+        assert_eq!("SDCARD", fs.volume_label());
+        assert_eq!("SDCARD", read_volume_label(fs.device(), fs.boot_sector()).unwrap());
+        assert_eq!(0xCAFE_F00D, fs.serial_number());
+        assert!(!fs.boot_sector().from_backup);
+        assert_eq!(CLUSTER_COUNT - 7, fs.free_clusters());
+        assert!(fs.allocation_bitmap().is_allocated(8));
+        assert!(!fs.allocation_bitmap().is_allocated(9));
+
+        let root = fs.read_dir("/").unwrap();
+        assert_eq!(2, root.len());
+        assert!(root[0].is_dir());
+        assert_eq!("notes.txt", root[1].name);
+        assert!(root[1].contiguous);
+        let modified = root[1].modified.unwrap();
+        assert_eq!(Some(180), modified.utc_offset);
+        assert_eq!(10, modified.local.hour as i64 + modified.utc_offset.unwrap() as i64 / -60);
+
+        let file = fs.open_file("\\NOTES.TXT").unwrap();
+        let mut data = vec![0_u8; 11];
+        file.read_exact_at(0, &mut data).unwrap();
+        assert_eq!(b"hello exfat", data.as_slice());
+
+        let file = fs.open_file("photos/img 0001 WITH A LONG NAME.jpg").unwrap();
+        assert_eq!(&[6, 7], file.clusters());
+        let mut data = vec![0xFF_u8; 1000];
+        file.read_exact_at(0, &mut data).unwrap();
+        assert!(data[..512].iter().all(|&b| b == 0x11));
+        assert!(data[512..700].iter().all(|&b| b == 0x22));
+        assert!(data[700..].iter().all(|&b| b == 0));
+
+        match fs.open_file("/deleted.txt") {
+            Err(Error::NotFound(_)) => (),
+            _ => panic!("not found expected"),
+        }
+    }
+
+    #[test]
+    fn exfat_damaged_test() {
+        // the backup boot region is used if the main one is damaged
+        let mut image = exfat_image();
+        let (main, backup) = image.split_at_mut(12 * SECTOR);
+        backup[..12 * SECTOR].copy_from_slice(main);
+        image[100] ^= 0xFF;
+        let fs = ExFatFileSystem::open(MemoryDisk::from_vec(image)).unwrap();
+        assert!(fs.boot_sector().from_backup);
+        assert_eq!(0xCAFE_F00D, fs.serial_number());
+
+        let mut image = exfat_image();
+        image[100] ^= 0xFF;
+        match ExFatFileSystem::open(MemoryDisk::from_vec(image)) {
+            Err(Error::ExFat(ExFatError::InvalidBootChecksum)) => (),
+            _ => panic!("invalid checksum expected"),
+        }
+
+        // a broken entry set checksum
+        let mut image = exfat_image();
+        let pos = (HEAP_OFFSET + 2) * SECTOR + 3 * DIR_ENTRY_SIZE + 4; // `Photos` attributes
+        image[pos] ^= 0x01;
+        let fs = ExFatFileSystem::open(MemoryDisk::from_vec(image)).unwrap();
+        match fs.read_dir("/") {
+            Err(Error::ExFat(ExFatError::InvalidEntrySetChecksum(name))) => assert_eq!("Photos", name),
+            _ => panic!("invalid checksum expected"),
+        }
+    }
+}
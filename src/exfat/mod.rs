@@ -0,0 +1,43 @@
+//! exFAT filesystem, read only
+//!
+//! See "exFAT File System Specification" by Microsoft
+use crate::prelude::*;
+use crate::tools::{le_u16, le_u32, le_u64};
+
+mod error;
+pub use error::ExFatError;
+
+mod boot;
+pub use boot::{is_exfat, BootSector};
+
+mod table;
+
+mod upcase;
+pub use upcase::UpcaseTable;
+
+mod bitmap;
+pub use bitmap::AllocationBitmap;
+
+mod dir;
+pub use dir::{DirEntry, Timestamp};
+
+mod file;
+pub use file::File;
+
+mod fs;
+pub use fs::{read_volume_label, ExFatFileSystem};
+
+pub use crate::fat::{Attributes, DateTime};
+
+const DIR_ENTRY_SIZE: usize = 32;
+const FIRST_CLUSTER: u32 = 2;
+
+/// Checksum used by the boot region and the up-case table
+fn checksum32(checksum: u32, byte: u8) -> u32 {
+    checksum.rotate_right(1).wrapping_add(byte as u32)
+}
+
+/// Checksum used by directory entry sets and name hashes
+fn checksum16(checksum: u16, byte: u8) -> u16 {
+    checksum.rotate_right(1).wrapping_add(byte as u16)
+}
@@ -0,0 +1,46 @@
+use super::*;
+
+const BAD_CLUSTER: u32 = 0xFFFF_FFF7;
+const END_OF_CHAIN: u32 = 0xFFFF_FFFF;
+
+/// Collects all clusters of the chain
+pub(crate) fn read_chain(device: &impl ReadAt, boot: &BootSector, first: u32) -> Result<Vec<u32>> {
+    let mut chain = Vec::new();
+    if first == 0 {
+        return Ok(chain); // empty file
+    }
+
+    let mut cluster = first;
+    loop {
+        if cluster < FIRST_CLUSTER || cluster > boot.max_cluster() {
+            return Err(Error::from(ExFatError::InvalidCluster(cluster)));
+        }
+        if chain.len() > boot.cluster_count as usize {
+            return Err(Error::from(ExFatError::ClusterLoop(first)));
+        }
+
+        chain.push(cluster);
+        let mut raw = [0_u8; 4];
+        device.read_exact_at(boot.fat_position() + cluster as u64 * 4, &mut raw)?;
+        match u32::from_le_bytes(raw) {
+            END_OF_CHAIN => return Ok(chain),
+            0 | BAD_CLUSTER => return Err(Error::from(ExFatError::InvalidCluster(cluster))),
+            next => cluster = next,
+        }
+    }
+}
+
+/// Clusters of a contiguous `NoFatChain` allocation
+pub(crate) fn contiguous_chain(boot: &BootSector, first: u32, size: u64) -> Result<Vec<u32>> {
+    let count = math::ceil(size, boot.cluster_size() as u64);
+    if first == 0 || count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let last = first as u64 + count - 1;
+    if first < FIRST_CLUSTER || last > boot.max_cluster() as u64 {
+        return Err(Error::from(ExFatError::InvalidCluster(first)));
+    }
+
+    Ok((first..=last as u32).collect())
+}
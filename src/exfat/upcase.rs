@@ -0,0 +1,53 @@
+use super::*;
+
+const TABLE_SIZE: usize = 0x1_0000;
+/// Followed by the number of characters mapped to themselves
+const IDENTITY_RUN: u16 = 0xFFFF;
+
+/// Up-case table used for case insensitive name comparison
+pub struct UpcaseTable {
+    table: Vec<u16>,
+}
+
+impl UpcaseTable {
+    /// Expands the (possibly compressed) on-disk table, `checksum` is the one from the directory entry
+    pub fn parse(data: &[u8], checksum: u32) -> Result<Self> {
+        if data.len() % 2 == 1 || data.iter().fold(0, |sum, &b| checksum32(sum, b)) != checksum {
+            return Err(Error::from(ExFatError::InvalidUpcaseTable));
+        }
+
+        let mut table = Vec::with_capacity(TABLE_SIZE);
+        let mut values = data.chunks_exact(2).map(|c| le_u16(c, 0));
+        while let Some(value) = values.next() {
+            match (value, values.clone().next()) {
+                (IDENTITY_RUN, Some(count)) => {
+                    values.next();
+                    let start = table.len();
+                    table.extend((start..start + count as usize).map(|c| c as u16));
+                }
+                _ => table.push(value),
+            }
+
+            if table.len() > TABLE_SIZE {
+                return Err(Error::from(ExFatError::InvalidUpcaseTable));
+            }
+        }
+
+        Ok(Self { table })
+    }
+
+    /// Characters beyond the table are not changed
+    pub fn to_upper(&self, c: u16) -> u16 {
+        self.table.get(c as usize).copied().unwrap_or(c)
+    }
+
+    /// Case insensitive comparison of UTF-16 names
+    pub fn same_name(&self, a: &[u16], b: &[u16]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(&a, &b)| self.to_upper(a) == self.to_upper(b))
+    }
+
+    /// Hash stored in the stream extension entry
+    pub fn name_hash(&self, name: &[u16]) -> u16 {
+        name.iter().flat_map(|&c| self.to_upper(c).to_le_bytes()).fold(0, checksum16)
+    }
+}
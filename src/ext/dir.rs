@@ -0,0 +1,241 @@
+use super::hash::{dir_hash, HASH_LEGACY_UNSIGNED, HASH_TEA};
+use super::*;
+
+const ENTRY_HEADER_SIZE: usize = 8;
+/// `metadata_csum` leaf block tail, looks like an empty entry with a special file type
+const DIR_TAIL_SIZE: usize = 12;
+const DIR_TAIL_FILE_TYPE: u8 = 0xDE;
+/// The htree root info follows the `.` and `..` entries
+const DX_ROOT_INFO: usize = 24;
+/// Interior nodes start with an empty entry spanning the block
+const DX_NODE_HEADER: usize = 8;
+const DX_ENTRY_SIZE: usize = 8;
+const DX_TAIL_SIZE: usize = 8;
+/// 3 levels with `largedir`
+const DX_MAX_LEVELS: u8 = 3;
+/// Inline directories start with the parent inode
+const INLINE_PARENT_SIZE: usize = 4;
+/// Inline data continues in this attribute
+pub(crate) const INLINE_DATA_XATTR: &str = "system.data";
+
+/// Directory entry, `.` and `..` are not listed
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct DirEntry {
+    pub inode: u32,
+    /// `Unknown` without the `filetype` feature
+    pub file_type: FileType,
+    pub name: String,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+/// Values above 65532 encode 64 KiB blocks
+fn rec_len(raw: u16, block_size: usize) -> usize {
+    if raw == 0xFFFF || raw == 0 {
+        block_size
+    } else {
+        (raw as usize & 0xFFFC) | ((raw as usize & 3) << 16)
+    }
+}
+
+/// Linear entries of a block or an inline data region
+fn parse_entries(bytes: &[u8], inode: u32, file_types: bool, entries: &mut Vec<DirEntry>) -> Result<()> {
+    let invalid = || Error::from(ExtError::InvalidDirectory(inode));
+
+    let mut pos = 0;
+    while pos + ENTRY_HEADER_SIZE <= bytes.len() {
+        let header = &bytes[pos..pos + ENTRY_HEADER_SIZE];
+        let length = rec_len(le_u16(header, 4), bytes.len());
+        let (name_length, file_type) = if file_types {
+            (header[6] as usize, FileType::from_dir_entry(header[7]))
+        } else {
+            (le_u16(header, 6) as usize, FileType::Unknown)
+        };
+        if length < ENTRY_HEADER_SIZE || pos + length > bytes.len() || ENTRY_HEADER_SIZE + name_length > length {
+            return Err(invalid());
+        }
+
+        let name = &bytes[pos + ENTRY_HEADER_SIZE..pos + ENTRY_HEADER_SIZE + name_length];
+        let number = le_u32(header, 0);
+        if number != 0 && name != b"." && name != b".." {
+            entries.push(DirEntry {
+                inode: number,
+                file_type,
+                name: String::from_utf8_lossy(name).into_owned(),
+            });
+        }
+        pos += length;
+    }
+
+    Ok(())
+}
+
+impl<R: ReadAt> ExtFileSystem<R> {
+    fn has_file_types(&self) -> bool {
+        self.sb.has_incompat(Superblock::INCOMPAT_FILETYPE)
+    }
+
+    /// Reads the directory block and checks its tail checksum if there is one
+    fn read_dir_block(&self, dir: &File<'_, R>, index: u64, block: &mut [u8]) -> Result<()> {
+        let block_size = block.len();
+        dir.read_exact_at(index * block_size as u64, block)?;
+
+        let tail = block_size - DIR_TAIL_SIZE;
+        let has_tail =
+            le_u32(block, tail) == 0 && le_u16(block, tail + 4) as usize == DIR_TAIL_SIZE && block[tail + 7] == DIR_TAIL_FILE_TYPE;
+        if has_tail && self.verify_checksums() && crc::crc32c_raw(self.inode_seed(dir.inode()), &block[..tail]) != le_u32(block, tail + 8) {
+            return Err(Error::from(ExtError::InvalidDirectoryChecksum(dir.inode().number)));
+        }
+
+        Ok(())
+    }
+
+    pub fn read_dir_inode(&self, dir: &Inode) -> Result<Vec<DirEntry>> {
+        if !dir.is_dir() {
+            return Err(Error::from(ExtError::NotADirectory(dir.number.to_string())));
+        }
+
+        let mut entries = Vec::new();
+        if dir.has_flag(Inode::FLAG_INLINE_DATA) {
+            let size = core::cmp::min(dir.size as usize, INODE_BLOCK_SIZE);
+            parse_entries(
+                &dir.block[INLINE_PARENT_SIZE..size],
+                dir.number,
+                self.has_file_types(),
+                &mut entries,
+            )?;
+            if let Some(data) = self.xattr(dir, INLINE_DATA_XATTR)? {
+                parse_entries(&data, dir.number, self.has_file_types(), &mut entries)?;
+            }
+            return Ok(entries);
+        }
+
+        // htree nodes look like empty blocks to the linear scan
+        let file = self.open_inode(dir.clone())?;
+        let block_size = self.block_size() as u64;
+        let mut block = vec![0_u8; block_size as usize];
+        for index in 0..file.size() / block_size {
+            self.read_dir_block(&file, index, &mut block)?;
+            parse_entries(&block, dir.number, self.has_file_types(), &mut entries)?;
+        }
+
+        Ok(entries)
+    }
+
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let dir = self.open_path(path)?;
+        self.read_dir_inode(&dir)
+    }
+
+    /// Looks the name up using the hashed index if the directory has one, names are case sensitive
+    pub fn find(&self, dir: &Inode, name: &str) -> Result<Option<DirEntry>> {
+        if !dir.is_dir() {
+            return Err(Error::from(ExtError::NotADirectory(dir.number.to_string())));
+        }
+
+        let indexed = dir.has_flag(Inode::FLAG_INDEX) && !dir.has_flag(Inode::FLAG_INLINE_DATA);
+        if indexed && self.sb.feature_compat & Superblock::COMPAT_DIR_INDEX != 0 {
+            if let Some(found) = self.dx_find(dir, name.as_bytes())? {
+                return Ok(found);
+            }
+        }
+
+        let entries = self.read_dir_inode(dir)?;
+        Ok(entries.into_iter().find(|entry| entry.name.as_bytes() == name.as_bytes()))
+    }
+
+    fn verify_dx_node(&self, dir: &File<'_, R>, node: &[u8], offset: usize) -> Result<()> {
+        let limit = le_u16(node, offset) as usize;
+        let count = le_u16(node, offset + 2) as usize;
+        let tail = offset + limit * DX_ENTRY_SIZE;
+        if !self.verify_checksums() || tail + DX_TAIL_SIZE > node.len() {
+            return Ok(());
+        }
+
+        let crc = crc::crc32c_raw(self.inode_seed(dir.inode()), &node[..offset + count * DX_ENTRY_SIZE]);
+        let crc = crc::crc32c_raw(crc, &node[tail..tail + 4]);
+        let crc = crc::crc32c_raw(crc, &[0; 4]);
+        if crc == le_u32(node, tail + 4) {
+            Ok(())
+        } else {
+            Err(Error::from(ExtError::InvalidDirectoryChecksum(dir.inode().number)))
+        }
+    }
+
+    /// Descends the htree, `None` if the index can't be used and the linear scan is needed
+    fn dx_find(&self, dir: &Inode, name: &[u8]) -> Result<Option<Option<DirEntry>>> {
+        let invalid = || Error::from(ExtError::InvalidDirectory(dir.number));
+
+        let file = self.open_inode(dir.clone())?;
+        let block_size = self.block_size() as usize;
+        let mut node = vec![0_u8; block_size];
+        file.read_exact_at(0, &mut node)?;
+
+        let levels = node[DX_ROOT_INFO + 6];
+        let info_length = node[DX_ROOT_INFO + 5] as usize;
+        if le_u32(&node, DX_ROOT_INFO) != 0 || levels >= DX_MAX_LEVELS {
+            return Ok(None);
+        }
+
+        let mut version = node[DX_ROOT_INFO + 4];
+        if version <= HASH_TEA && self.sb.flags & Superblock::FLAGS_UNSIGNED_HASH != 0 {
+            version += HASH_LEGACY_UNSIGNED;
+        }
+        let hash = match dir_hash(name, version, &self.sb.hash_seed) {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+
+        // the first entry holds the limit and count instead of a hash
+        let mut offset = DX_ROOT_INFO + info_length;
+        let mut level = 0;
+        let (entries, index) = loop {
+            let count = node
+                .get(offset + 2..offset + 4)
+                .map(|c| le_u16(c, 0) as usize)
+                .ok_or_else(invalid)?;
+            if count == 0 || offset + count * DX_ENTRY_SIZE > block_size {
+                return Err(invalid());
+            }
+            self.verify_dx_node(&file, &node, offset)?;
+
+            let entries: Vec<(u32, u32)> = (0..count)
+                .map(|i| {
+                    let pos = offset + i * DX_ENTRY_SIZE;
+                    let entry_hash = if i == 0 { 0 } else { le_u32(&node, pos) };
+                    (entry_hash, le_u32(&node, pos + 4))
+                })
+                .collect();
+            let index = entries.iter().rposition(|&(entry_hash, _)| entry_hash <= hash).unwrap_or(0);
+            if level == levels {
+                break (entries, index);
+            }
+
+            self.read_dir_block(&file, entries[index].1 as u64, &mut node)?;
+            offset = DX_NODE_HEADER;
+            level += 1;
+        };
+
+        // hash collisions continue in the next leaf, marked by the low bit of its hash
+        let mut block = vec![0_u8; block_size];
+        for (i, &(_, leaf)) in entries.iter().enumerate().skip(index) {
+            if i > index && entries[i].0 != hash | 1 {
+                break;
+            }
+
+            self.read_dir_block(&file, leaf as u64, &mut block)?;
+            let mut found = Vec::new();
+            parse_entries(&block, dir.number, self.has_file_types(), &mut found)?;
+            if let Some(entry) = found.into_iter().find(|entry| entry.name.as_bytes() == name) {
+                return Ok(Some(Some(entry)));
+            }
+        }
+
+        Ok(Some(None))
+    }
+}
@@ -0,0 +1,56 @@
+use crate::xstd::String;
+
+#[derive(Debug)]
+pub enum ExtError {
+    InvalidSuperblock,
+    UnsupportedFeature(u32), // incompatible feature flags
+    InvalidGroup(u32),
+    InvalidInode(u32),
+    InvalidExtentTree(u32), // inode
+    InvalidBlockMap(u32),   // inode
+    InvalidDirectory(u32),  // inode
+    InvalidXattr(u32),      // inode
+    InvalidSymlink(u32),
+    InvalidSuperblockChecksum,
+    InvalidGroupChecksum(u32),
+    InvalidInodeChecksum(u32),
+    InvalidExtentChecksum(u32),    // inode
+    InvalidDirectoryChecksum(u32), // inode
+    InvalidXattrChecksum(u32),     // inode
+    NotADirectory(String),
+    NotAFile(String),
+    NotASymlink(String),
+    SymlinkLoop(String),
+}
+
+impl core::fmt::Display for ExtError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ExtError::InvalidSuperblock => f.write_str("Invalid ext superblock"),
+            ExtError::UnsupportedFeature(flags) => write!(f, "Unsupported ext incompatible features 0x{:X}", flags),
+            ExtError::InvalidGroup(group) => write!(f, "Invalid descriptor of block group {}", group),
+            ExtError::InvalidInode(inode) => write!(f, "Invalid inode {}", inode),
+            ExtError::InvalidExtentTree(inode) => write!(f, "Invalid extent tree of inode {}", inode),
+            ExtError::InvalidBlockMap(inode) => write!(f, "Invalid block map of inode {}", inode),
+            ExtError::InvalidDirectory(inode) => write!(f, "Invalid directory entries of inode {}", inode),
+            ExtError::InvalidXattr(inode) => write!(f, "Invalid extended attributes of inode {}", inode),
+            ExtError::InvalidSymlink(inode) => write!(f, "Invalid symbolic link inode {}", inode),
+            ExtError::InvalidSuperblockChecksum => f.write_str("Invalid ext superblock checksum"),
+            ExtError::InvalidGroupChecksum(group) => write!(f, "Invalid checksum of block group {}", group),
+            ExtError::InvalidInodeChecksum(inode) => write!(f, "Invalid checksum of inode {}", inode),
+            ExtError::InvalidExtentChecksum(inode) => write!(f, "Invalid extent block checksum of inode {}", inode),
+            ExtError::InvalidDirectoryChecksum(inode) => write!(f, "Invalid directory block checksum of inode {}", inode),
+            ExtError::InvalidXattrChecksum(inode) => write!(f, "Invalid extended attribute block checksum of inode {}", inode),
+            ExtError::NotADirectory(path) => write!(f, "'{}' is not a directory", path),
+            ExtError::NotAFile(path) => write!(f, "'{}' is not a file", path),
+            ExtError::NotASymlink(path) => write!(f, "'{}' is not a symbolic link", path),
+            ExtError::SymlinkLoop(path) => write!(f, "Too many levels of symbolic links in '{}'", path),
+        }
+    }
+}
+
+impl From<ExtError> for crate::Error {
+    fn from(e: ExtError) -> Self {
+        Self::Ext(e)
+    }
+}
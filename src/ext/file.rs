@@ -0,0 +1,109 @@
+use super::*;
+
+enum Data {
+    Extents(Vec<Extent>),
+    /// `inline_data` files: `i_block` followed by the `system.data` attribute
+    Inline(Vec<u8>),
+}
+
+/// File contents, holes and uninitialized extents read as zeros
+pub struct File<'f, R: ReadAt> {
+    fs: &'f ExtFileSystem<R>,
+    inode: Inode,
+    data: Data,
+}
+
+impl<'f, R: ReadAt> File<'f, R> {
+    pub(crate) fn new(fs: &'f ExtFileSystem<R>, inode: Inode) -> Result<Self> {
+        let data = if inode.has_flag(Inode::FLAG_INLINE_DATA) {
+            let mut data = inode.block.to_vec();
+            if let Some(rest) = fs.xattr(&inode, dir::INLINE_DATA_XATTR)? {
+                data.extend_from_slice(&rest);
+            }
+            data.resize(inode.size as usize, 0);
+            Data::Inline(data)
+        } else {
+            Data::Extents(fs.extents(&inode)?)
+        };
+
+        Ok(Self { fs, inode, data })
+    }
+
+    pub fn inode(&self) -> &Inode {
+        &self.inode
+    }
+
+    pub fn size(&self) -> u64 {
+        self.inode.size
+    }
+
+    /// Mapped blocks, empty for inline data
+    pub fn extents(&self) -> &[Extent] {
+        match &self.data {
+            Data::Extents(extents) => extents,
+            Data::Inline(_) => &[],
+        }
+    }
+
+    /// Reads the whole file
+    pub fn read_all(&self) -> Result<Vec<u8>> {
+        let mut data = vec![0_u8; self.size() as usize];
+        self.read_exact_at(0, &mut data)?;
+        Ok(data)
+    }
+}
+
+impl<'f, R: ReadAt> ReadAt for File<'f, R> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size(), offset, buffer.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::ReadBeyondEOD),
+        };
+        let buffer = &mut buffer[..len];
+
+        let extents = match &self.data {
+            Data::Inline(data) => {
+                let start = offset as usize;
+                buffer.copy_from_slice(&data[start..start + len]);
+                return Ok(len);
+            }
+            Data::Extents(extents) => extents,
+        };
+
+        let block_size = self.fs.block_size() as u64;
+        let block = offset / block_size;
+        let offset_in_block = offset % block_size;
+        let index = match extents.binary_search_by(|e| e.logical.cmp(&block)) {
+            Ok(index) => Some(index),
+            Err(0) => None,
+            Err(index) => Some(index - 1),
+        };
+
+        let extent = index.map(|i| extents[i]).filter(|e| block < e.logical + e.length as u64);
+        match extent {
+            Some(extent) => {
+                let available = (extent.logical + extent.length as u64 - block) * block_size - offset_in_block;
+                let len = core::cmp::min(len as u64, available) as usize;
+                if extent.uninitialized {
+                    buffer[..len].iter_mut().for_each(|b| *b = 0);
+                } else {
+                    let physical = (extent.physical + block - extent.logical) * block_size + offset_in_block;
+                    self.fs.device().read_exact_at(physical, &mut buffer[..len])?;
+                }
+                Ok(len)
+            }
+            None => {
+                // a hole up to the next extent
+                let next = index.map(|i| i + 1).unwrap_or(0);
+                let available = match extents.get(next) {
+                    Some(e) => (e.logical - block) * block_size - offset_in_block,
+                    None => len as u64,
+                };
+                let len = core::cmp::min(len as u64, available) as usize;
+                buffer[..len].iter_mut().for_each(|b| *b = 0);
+                Ok(len)
+            }
+        }
+    }
+}
@@ -0,0 +1,629 @@
+use super::group::{checksum, descriptor_position};
+use super::*;
+
+/// Linux follows at most 40 symbolic links while resolving a path
+const MAX_SYMLINKS: usize = 40;
+
+/// ext2, ext3 or ext4 volume
+pub struct ExtFileSystem<R: ReadAt> {
+    device: R,
+    pub(crate) sb: Superblock,
+}
+
+impl<R: ReadAt> ExtFileSystem<R> {
+    pub fn open(device: R) -> Result<Self> {
+        let mut bytes = [0_u8; SUPERBLOCK_SIZE];
+        device.read_exact_at(SUPERBLOCK_OFFSET, &mut bytes)?;
+        let sb = Superblock::parse(&bytes)?;
+
+        Ok(Self { device, sb })
+    }
+
+    pub fn device(&self) -> &R {
+        &self.device
+    }
+
+    pub fn into_inner(self) -> R {
+        self.device
+    }
+
+    pub fn superblock(&self) -> &Superblock {
+        &self.sb
+    }
+
+    pub fn volume_label(&self) -> &str {
+        &self.sb.volume_name
+    }
+
+    pub fn uuid(&self) -> Uuid {
+        self.sb.uuid
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.sb.block_size()
+    }
+
+    pub(crate) fn read_block(&self, block: u64, buffer: &mut [u8]) -> Result<()> {
+        self.device.read_exact_at(block * self.block_size() as u64, buffer)
+    }
+
+    /// Checksums are verified only with the `ext-checksums` feature
+    pub(crate) fn verify_checksums(&self) -> bool {
+        cfg!(feature = "ext-checksums") && self.sb.has_metadata_csum()
+    }
+
+    pub(crate) fn inode_seed(&self, inode: &Inode) -> u32 {
+        inode.csum_seed(self.sb.csum_seed())
+    }
+
+    pub fn group(&self, group: u32) -> Result<GroupDescriptor> {
+        if group >= self.sb.group_count() {
+            return Err(Error::from(ExtError::InvalidGroup(group)));
+        }
+
+        let mut bytes = vec![0_u8; self.sb.desc_size as usize];
+        self.device.read_exact_at(descriptor_position(&self.sb, group), &mut bytes)?;
+        let descriptor = GroupDescriptor::parse(&bytes);
+
+        if cfg!(feature = "ext-checksums") {
+            if let Some(expected) = checksum(&self.sb, group, &bytes) {
+                if expected != descriptor.checksum {
+                    return Err(Error::from(ExtError::InvalidGroupChecksum(group)));
+                }
+            }
+        }
+
+        Ok(descriptor)
+    }
+
+    pub fn inode(&self, number: u32) -> Result<Inode> {
+        if number == 0 || number > self.sb.inodes_count {
+            return Err(Error::from(ExtError::InvalidInode(number)));
+        }
+
+        let group = (number - 1) / self.sb.inodes_per_group;
+        let index = (number - 1) % self.sb.inodes_per_group;
+        let table = self.group(group)?.inode_table;
+
+        let mut bytes = vec![0_u8; self.sb.inode_size as usize];
+        let position = table * self.block_size() as u64 + index as u64 * self.sb.inode_size as u64;
+        self.device.read_exact_at(position, &mut bytes)?;
+        let inode = Inode::parse(&bytes, number)?;
+
+        if self.verify_checksums() && !inode::verify_checksum(&bytes, &inode, self.sb.csum_seed()) {
+            return Err(Error::from(ExtError::InvalidInodeChecksum(number)));
+        }
+
+        Ok(inode)
+    }
+
+    pub fn root(&self) -> Result<Inode> {
+        self.inode(ROOT_INODE)
+    }
+
+    /// Any inode can be read, not only regular files
+    pub fn open_inode(&self, inode: Inode) -> Result<File<'_, R>> {
+        File::new(self, inode)
+    }
+
+    /// Resolves the path following all symbolic links
+    pub fn open_path(&self, path: &str) -> Result<Inode> {
+        self.resolve(path, true)
+    }
+
+    pub fn open_file(&self, path: &str) -> Result<File<'_, R>> {
+        let inode = self.open_path(path)?;
+        if !inode.is_file() {
+            return Err(Error::from(ExtError::NotAFile(path.to_string())));
+        }
+
+        self.open_inode(inode)
+    }
+
+    /// Target of the symbolic link, the link itself is not followed
+    pub fn read_link(&self, path: &str) -> Result<String> {
+        let inode = self.resolve(path, false)?;
+        if !inode.is_symlink() {
+            return Err(Error::from(ExtError::NotASymlink(path.to_string())));
+        }
+
+        self.read_link_inode(&inode)
+    }
+
+    pub fn read_link_inode(&self, inode: &Inode) -> Result<String> {
+        if !inode.is_symlink() {
+            return Err(Error::from(ExtError::NotASymlink(inode.number.to_string())));
+        }
+
+        let target = if inode.is_fast_symlink(self.block_size()) {
+            inode.block[..inode.size as usize].to_vec()
+        } else if inode.size > self.block_size() as u64 {
+            return Err(Error::from(ExtError::InvalidSymlink(inode.number)));
+        } else {
+            self.open_inode(inode.clone())?.read_all()?
+        };
+
+        String::from_utf8(target).map_err(|_| Error::from(ExtError::InvalidSymlink(inode.number)))
+    }
+
+    /// Walks the path, `follow_last` also follows the final component if it is a link
+    pub(crate) fn resolve(&self, path: &str, follow_last: bool) -> Result<Inode> {
+        // components still to visit, in reverse order
+        let mut pending: Vec<String> = path.rsplit('/').map(|name| name.to_string()).collect();
+        let mut stack = vec![self.root()?];
+        let mut links = 0;
+
+        while let Some(name) = pending.pop() {
+            match name.as_str() {
+                "" | "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+
+            let dir = stack.last().expect("root is always present");
+            if !dir.is_dir() {
+                return Err(Error::from(ExtError::NotADirectory(path.to_string())));
+            }
+
+            let entry = self.find(dir, &name)?.ok_or_else(|| Error::NotFound(path.to_string()))?;
+            let inode = self.inode(entry.inode)?;
+            let is_last = pending.iter().all(|name| name.is_empty() || name == ".");
+            if inode.is_symlink() && (follow_last || !is_last) {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(Error::from(ExtError::SymlinkLoop(path.to_string())));
+                }
+
+                let target = self.read_link_inode(&inode)?;
+                if target.starts_with('/') {
+                    stack.truncate(1);
+                }
+                pending.extend(target.rsplit('/').map(|name| name.to_string()));
+                continue;
+            }
+
+            stack.push(inode);
+        }
+
+        Ok(stack.pop().expect("root is always present"))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+
+    const BLOCK: usize = 1024;
+    const BLOCKS: usize = 256;
+    const INODE_SIZE: usize = 256;
+    const INODE_TABLE: usize = 5;
+    const ROOT_BLOCK: usize = 20;
+    const SUB_BLOCK: usize = 21;
+    const HELLO_BLOCK: usize = 30;
+    const LEGACY_BLOCK: usize = 40;
+    const INDIRECT_BLOCK: usize = 60;
+    const SLOW_LINK_BLOCK: usize = 70;
+    const XATTR_BLOCK: usize = 80;
+    const TREE_LEAF_BLOCK: usize = 90;
+    const TREE_BLOCK: usize = 91;
+
+    const HELLO: u32 = 12;
+    const LEGACY: u32 = 13;
+    const LINK: u32 = 14;
+    const SUB: u32 = 15;
+    const BACK: u32 = 16;
+    const LOOP: u32 = 17;
+    const TREE: u32 = 18;
+    const SLOW: u32 = 19;
+
+    const UUID: [u8; 16] = [
+        0x5A, 0x3B, 0x11, 0x84, 0x22, 0xC7, 0x4E, 0x0D, 0x9A, 0x61, 0x7F, 0x02, 0xB3, 0x58, 0xE4, 0x19,
+    ];
+
+    fn put_u16(bytes: &mut [u8], pos: usize, value: u16) {
+        bytes[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(bytes: &mut [u8], pos: usize, value: u32) {
+        bytes[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn fs_seed() -> u32 {
+        crc::crc32c_raw(!0, &UUID)
+    }
+
+    fn inode_seed(number: u32) -> u32 {
+        let crc = crc::crc32c_raw(fs_seed(), &number.to_le_bytes());
+        crc::crc32c_raw(crc, &generation(number).to_le_bytes())
+    }
+
+    fn generation(number: u32) -> u32 {
+        number * 7
+    }
+
+    fn extent_header(block: &mut [u8], entries: u16, max: u16, depth: u16) {
+        put_u16(block, 0, 0xF30A);
+        put_u16(block, 2, entries);
+        put_u16(block, 4, max);
+        put_u16(block, 6, depth);
+    }
+
+    fn extent(block: &mut [u8], index: usize, logical: u32, length: u16, physical: u32) {
+        let pos = 12 + index * 12;
+        put_u32(block, pos, logical);
+        put_u16(block, pos + 4, length);
+        put_u32(block, pos + 8, physical);
+    }
+
+    fn extents_root(extents: &[(u32, u16, u32)]) -> [u8; INODE_BLOCK_SIZE] {
+        let mut block = [0_u8; INODE_BLOCK_SIZE];
+        extent_header(&mut block, extents.len() as u16, 4, 0);
+        for (i, &(logical, length, physical)) in extents.iter().enumerate() {
+            extent(&mut block, i, logical, length, physical);
+        }
+        block
+    }
+
+    fn write_inode(image: &mut [u8], number: u32, mode: u16, size: u64, flags: u32, block: &[u8], configure: impl FnOnce(&mut [u8])) {
+        let mut inode = vec![0_u8; INODE_SIZE];
+        put_u16(&mut inode, 0x00, mode);
+        put_u32(&mut inode, 0x04, size as u32);
+        put_u32(&mut inode, 0x10, 1_600_000_000);
+        put_u16(&mut inode, 0x1A, 1);
+        put_u32(&mut inode, 0x20, flags);
+        inode[0x28..0x28 + block.len()].copy_from_slice(block);
+        put_u32(&mut inode, 0x64, generation(number));
+        put_u16(&mut inode, 0x80, 32);
+        configure(&mut inode);
+
+        let crc = crc::crc32c_raw(inode_seed(number), &inode);
+        put_u16(&mut inode, 0x7C, crc as u16);
+        put_u16(&mut inode, 0x82, (crc >> 16) as u16);
+
+        let pos = INODE_TABLE * BLOCK + (number as usize - 1) * INODE_SIZE;
+        image[pos..pos + INODE_SIZE].copy_from_slice(&inode);
+    }
+
+    fn write_dir(image: &mut [u8], block: usize, dir: u32, parent: u32, entries: &[(u32, u8, &str)]) {
+        let mut data = vec![0_u8; BLOCK];
+        let all: Vec<(u32, u8, &str)> = [(dir, 2, "."), (parent, 2, "..")].iter().chain(entries).cloned().collect();
+        let mut pos = 0;
+        for (i, &(inode, kind, name)) in all.iter().enumerate() {
+            let length = if i + 1 == all.len() {
+                BLOCK - 12 - pos
+            } else {
+                (8 + name.len() + 3) & !3
+            };
+            put_u32(&mut data, pos, inode);
+            put_u16(&mut data, pos + 4, length as u16);
+            data[pos + 6] = name.len() as u8;
+            data[pos + 7] = kind;
+            data[pos + 8..pos + 8 + name.len()].copy_from_slice(name.as_bytes());
+            pos += length;
+        }
+
+        // checksum tail
+        put_u16(&mut data, pos + 4, 12);
+        data[pos + 7] = 0xDE;
+        let crc = crc::crc32c_raw(inode_seed(dir), &data[..pos]);
+        put_u32(&mut data, pos + 8, crc);
+
+        image[block * BLOCK..(block + 1) * BLOCK].copy_from_slice(&data);
+    }
+
+    pub fn image() -> Vec<u8> {
+        let mut image = vec![0_u8; BLOCKS * BLOCK];
+
+        let sb = &mut image[1024..2048];
+        put_u32(sb, 0x00, 32); // inodes
+        put_u32(sb, 0x04, BLOCKS as u32);
+        put_u32(sb, 0x14, 1); // first data block
+        put_u32(sb, 0x20, 8192); // blocks per group
+        put_u32(sb, 0x24, 8192);
+        put_u32(sb, 0x28, 32); // inodes per group
+        put_u16(sb, 0x38, 0xEF53);
+        put_u16(sb, 0x3A, Superblock::STATE_CLEAN);
+        put_u32(sb, 0x4C, 1); // dynamic revision
+        put_u32(sb, 0x54, 11);
+        put_u16(sb, 0x58, INODE_SIZE as u16);
+        put_u32(sb, 0x5C, Superblock::COMPAT_EXT_ATTR);
+        put_u32(sb, 0x60, Superblock::INCOMPAT_FILETYPE | Superblock::INCOMPAT_EXTENTS);
+        put_u32(sb, 0x64, Superblock::RO_COMPAT_SPARSE_SUPER | Superblock::RO_COMPAT_METADATA_CSUM);
+        sb[0x68..0x78].copy_from_slice(&UUID);
+        sb[0x78..0x7D].copy_from_slice(b"rdisk");
+        sb[0x175] = 1; // crc32c
+        let crc = crc::crc32c_raw(!0, &sb[..0x3FC]);
+        put_u32(sb, 0x3FC, crc);
+
+        let gd = &mut image[2048..2080];
+        put_u32(gd, 0x00, 3);
+        put_u32(gd, 0x04, 4);
+        put_u32(gd, 0x08, INODE_TABLE as u32);
+        let crc = crc::crc32c_raw(fs_seed(), &0_u32.to_le_bytes());
+        let crc = crc::crc32c_raw(crc, &gd[..0x1E]);
+        let crc = crc::crc32c_raw(crc, &[0; 2]);
+        put_u16(gd, 0x1E, crc as u16);
+
+        // root and a subdirectory with links
+        let dir = Inode::FLAG_EXTENTS;
+        write_inode(
+            &mut image,
+            ROOT_INODE,
+            0x41ED,
+            BLOCK as u64,
+            dir,
+            &extents_root(&[(0, 1, ROOT_BLOCK as u32)]),
+            |_| (),
+        );
+        write_dir(
+            &mut image,
+            ROOT_BLOCK,
+            ROOT_INODE,
+            ROOT_INODE,
+            &[
+                (HELLO, 1, "hello.txt"),
+                (LEGACY, 1, "legacy"),
+                (LINK, 7, "link"),
+                (SUB, 2, "sub"),
+                (TREE, 1, "tree"),
+            ],
+        );
+        write_inode(
+            &mut image,
+            SUB,
+            0x41ED,
+            BLOCK as u64,
+            dir,
+            &extents_root(&[(0, 1, SUB_BLOCK as u32)]),
+            |_| (),
+        );
+        write_dir(
+            &mut image,
+            SUB_BLOCK,
+            SUB,
+            ROOT_INODE,
+            &[(BACK, 7, "back"), (LOOP, 7, "loop"), (SLOW, 7, "slow")],
+        );
+
+        // extents with a hole and an uninitialized extent, in-inode attribute
+        let extents = extents_root(&[
+            (0, 1, HELLO_BLOCK as u32),
+            (2, 1, HELLO_BLOCK as u32 + 1),
+            (3, 0x8001, HELLO_BLOCK as u32 + 2),
+        ]);
+        write_inode(&mut image, HELLO, 0x81A4, 4000, Inode::FLAG_EXTENTS, &extents, |inode| {
+            put_u32(inode, 160, 0xEA02_0000);
+            inode[164] = 7; // name length
+            inode[165] = 1; // user.
+            put_u16(inode, 166, 32); // value offset
+            put_u32(inode, 172, 2); // value size
+            inode[180..187].copy_from_slice(b"comment");
+            inode[196..198].copy_from_slice(b"hi");
+        });
+        for (i, fill) in [b'a', b'c', b'x'].iter().enumerate() {
+            let start = (HELLO_BLOCK + i) * BLOCK;
+            image[start..start + BLOCK].iter_mut().for_each(|b| *b = *fill);
+        }
+
+        // block map with an indirect block and a hole, attribute block
+        let mut map = [0_u8; INODE_BLOCK_SIZE];
+        for i in 0..12 {
+            put_u32(&mut map, i * 4, (LEGACY_BLOCK + i) as u32);
+            let start = (LEGACY_BLOCK + i) * BLOCK;
+            image[start..start + BLOCK].iter_mut().for_each(|b| *b = i as u8 + 1);
+        }
+        put_u32(&mut map, 48, INDIRECT_BLOCK as u32);
+        put_u32(&mut image, INDIRECT_BLOCK * BLOCK, (LEGACY_BLOCK + 12) as u32);
+        let start = (LEGACY_BLOCK + 12) * BLOCK;
+        image[start..start + BLOCK].iter_mut().for_each(|b| *b = 13);
+        write_inode(&mut image, LEGACY, 0x81A4, 14 * BLOCK as u64 - 100, 0, &map, |inode| {
+            put_u32(inode, 0x68, XATTR_BLOCK as u32);
+        });
+
+        let xattr = &mut image[XATTR_BLOCK * BLOCK..(XATTR_BLOCK + 1) * BLOCK];
+        put_u32(xattr, 0, 0xEA02_0000);
+        put_u32(xattr, 4, 1); // references
+        put_u32(xattr, 8, 1); // blocks
+        xattr[32] = 4;
+        xattr[33] = 1;
+        put_u16(xattr, 34, 1000);
+        put_u32(xattr, 40, 10);
+        xattr[48..52].copy_from_slice(b"mime");
+        xattr[1000..1010].copy_from_slice(b"text/plain");
+        let crc = crc::crc32c_raw(fs_seed(), &(XATTR_BLOCK as u64).to_le_bytes());
+        let crc = crc::crc32c_raw(crc, xattr);
+        put_u32(xattr, 0x10, crc);
+
+        // extent tree with a leaf block
+        let mut root = [0_u8; INODE_BLOCK_SIZE];
+        extent_header(&mut root, 1, 4, 1);
+        put_u32(&mut root, 16, TREE_LEAF_BLOCK as u32);
+        write_inode(&mut image, TREE, 0x81A4, 2 * BLOCK as u64, Inode::FLAG_EXTENTS, &root, |_| ());
+        let leaf = &mut image[TREE_LEAF_BLOCK * BLOCK..(TREE_LEAF_BLOCK + 1) * BLOCK];
+        extent_header(leaf, 1, 84, 0);
+        extent(leaf, 0, 0, 2, TREE_BLOCK as u32);
+        let crc = crc::crc32c_raw(inode_seed(TREE), &leaf[..1020]);
+        put_u32(leaf, 1020, crc);
+        for (i, b) in image[TREE_BLOCK * BLOCK..(TREE_BLOCK + 2) * BLOCK].iter_mut().enumerate() {
+            *b = (i % 251) as u8;
+        }
+
+        // fast, absolute, looping and slow symbolic links
+        let fast_link = |target: &str| {
+            let mut block = [0_u8; INODE_BLOCK_SIZE];
+            block[..target.len()].copy_from_slice(target.as_bytes());
+            block
+        };
+        write_inode(&mut image, LINK, 0xA1FF, 16, 0, &fast_link("sub/../hello.txt"), |_| ());
+        write_inode(&mut image, BACK, 0xA1FF, 5, 0, &fast_link("/link"), |_| ());
+        write_inode(&mut image, LOOP, 0xA1FF, 4, 0, &fast_link("loop"), |_| ());
+        let target = format!("{}../tree", "./".repeat(30));
+        let extents = extents_root(&[(0, 1, SLOW_LINK_BLOCK as u32)]);
+        write_inode(
+            &mut image,
+            SLOW,
+            0xA1FF,
+            target.len() as u64,
+            Inode::FLAG_EXTENTS,
+            &extents,
+            |inode| {
+                put_u32(inode, 0x1C, 2); // sectors
+            },
+        );
+        image[SLOW_LINK_BLOCK * BLOCK..SLOW_LINK_BLOCK * BLOCK + target.len()].copy_from_slice(target.as_bytes());
+
+        image
+    }
+
+    #[test]
+    fn ext_test() {
+        let fs = ExtFileSystem::open(MemoryDisk::from_vec(image())).unwrap();
+        assert_eq!("rdisk", fs.volume_label());
+        assert_eq!(Uuid::from_bytes(UUID), fs.uuid());
+        assert_eq!(4, fs.superblock().version());
+        assert_eq!(1024, fs.block_size());
+        assert_eq!(1, fs.superblock().group_count());
+
+        let mut names: Vec<String> = fs.read_dir("/").unwrap().into_iter().map(|e| e.name).collect();
+        names.sort();
+        assert_eq!(vec!["hello.txt", "legacy", "link", "sub", "tree"], names);
+        let root = fs.root().unwrap();
+        let sub = fs.find(&root, "sub").unwrap().unwrap();
+        assert!(sub.is_dir());
+        assert_eq!(SUB, sub.inode);
+        assert!(fs.find(&root, "HELLO.TXT").unwrap().is_none());
+
+        let hello = fs.open_file("/hello.txt").unwrap();
+        assert_eq!(3, hello.extents().len());
+        assert!(hello.extents()[2].uninitialized);
+        let data = hello.read_all().unwrap();
+        assert!(data[..1024].iter().all(|&b| b == b'a'));
+        assert!(data[1024..2048].iter().all(|&b| b == 0));
+        assert!(data[2048..3072].iter().all(|&b| b == b'c'));
+        assert!(data[3072..].iter().all(|&b| b == 0));
+        let mut buffer = [0_u8; 8];
+        assert_eq!(4, hello.read_at(1020, &mut buffer).unwrap()); // up to the hole
+        hello.read_exact_at(1020, &mut buffer).unwrap();
+        assert_eq!(b"aaaa\0\0\0\0", &buffer);
+
+        let legacy = fs.open_file("legacy").unwrap();
+        assert_eq!(
+            vec![Extent {
+                logical: 0,
+                length: 13,
+                physical: LEGACY_BLOCK as u64,
+                uninitialized: false
+            }],
+            legacy.extents()
+        );
+        let data = legacy.read_all().unwrap();
+        assert_eq!(14 * 1024 - 100, data.len());
+        for (i, block) in data.chunks(1024).enumerate() {
+            let expected = if i < 13 { i as u8 + 1 } else { 0 };
+            assert!(block.iter().all(|&b| b == expected));
+        }
+
+        let tree = fs.open_file("tree").unwrap().read_all().unwrap();
+        assert!(tree.iter().enumerate().all(|(i, &b)| b == (i % 251) as u8));
+
+        let inode = fs.open_path("hello.txt").unwrap();
+        assert_eq!(b"hi".to_vec(), fs.xattr(&inode, "user.comment").unwrap().unwrap());
+        let inode = fs.open_path("legacy").unwrap();
+        let xattrs = fs.xattrs(&inode).unwrap();
+        assert_eq!(1, xattrs.len());
+        assert_eq!("user.mime", xattrs[0].name);
+        assert_eq!(b"text/plain", xattrs[0].value.as_slice());
+
+        assert_eq!("sub/../hello.txt", fs.read_link("link").unwrap());
+        assert_eq!("/link", fs.read_link("/sub/back").unwrap());
+        assert_eq!(HELLO, fs.open_path("sub/back").unwrap().number);
+        assert_eq!(4000, fs.open_file("sub/back").unwrap().size());
+        assert_eq!(tree, fs.open_file("sub/slow").unwrap().read_all().unwrap());
+        assert!(fs.open_path("link").unwrap().is_file());
+
+        match fs.open_file("sub/loop") {
+            Err(Error::Ext(ExtError::SymlinkLoop(_))) => (),
+            _ => panic!("symlink loop expected"),
+        }
+        match fs.read_link("sub") {
+            Err(Error::Ext(ExtError::NotASymlink(_))) => (),
+            _ => panic!("not a symlink expected"),
+        }
+        match fs.open_file("sub") {
+            Err(Error::Ext(ExtError::NotAFile(_))) => (),
+            _ => panic!("not a file expected"),
+        }
+        match fs.read_dir("hello.txt/x") {
+            Err(Error::Ext(ExtError::NotADirectory(_))) => (),
+            _ => panic!("not a directory expected"),
+        }
+        match fs.open_file("sub/missing") {
+            Err(Error::NotFound(_)) => (),
+            _ => panic!("not found expected"),
+        }
+    }
+
+    #[test]
+    fn desc_size_test() {
+        for &desc_size in &[48_u16, 2048, 4096] {
+            let mut image = image();
+            let sb = &mut image[1024..2048];
+            put_u32(
+                sb,
+                0x60,
+                Superblock::INCOMPAT_FILETYPE | Superblock::INCOMPAT_EXTENTS | Superblock::INCOMPAT_64BIT,
+            );
+            put_u16(sb, 0xFE, desc_size);
+            match ExtFileSystem::open(MemoryDisk::from_vec(image)) {
+                Err(Error::Ext(ExtError::InvalidSuperblock)) => (),
+                _ => panic!("invalid descriptor size {} accepted", desc_size),
+            }
+        }
+    }
+
+    #[cfg(feature = "ext-checksums")]
+    #[test]
+    fn ext_checksums_test() {
+        fn corrupted(pos: usize) -> ExtFileSystem<MemoryDisk> {
+            let mut image = image();
+            image[pos] ^= 1;
+            ExtFileSystem::open(MemoryDisk::from_vec(image)).unwrap()
+        }
+
+        match ExtFileSystem::open(MemoryDisk::from_vec({
+            let mut image = image();
+            image[1024 + 0x78] = b'R';
+            image
+        })) {
+            Err(Error::Ext(ExtError::InvalidSuperblockChecksum)) => (),
+            _ => panic!("superblock checksum error expected"),
+        }
+        match corrupted(2048 + 0x0C).root() {
+            Err(Error::Ext(ExtError::InvalidGroupChecksum(0))) => (),
+            _ => panic!("group checksum error expected"),
+        }
+        match corrupted(INODE_TABLE * BLOCK + (HELLO as usize - 1) * INODE_SIZE + 0x10).open_file("hello.txt") {
+            Err(Error::Ext(ExtError::InvalidInodeChecksum(HELLO))) => (),
+            _ => panic!("inode checksum error expected"),
+        }
+        match corrupted(SUB_BLOCK * BLOCK + 20).read_dir("sub") {
+            Err(Error::Ext(ExtError::InvalidDirectoryChecksum(SUB))) => (),
+            _ => panic!("directory checksum error expected"),
+        }
+        match corrupted(TREE_LEAF_BLOCK * BLOCK + 100).open_file("tree") {
+            Err(Error::Ext(ExtError::InvalidExtentChecksum(TREE))) => (),
+            _ => panic!("extent checksum error expected"),
+        }
+        let fs = corrupted(XATTR_BLOCK * BLOCK + 1001);
+        match fs.xattrs(&fs.open_path("legacy").unwrap()) {
+            Err(Error::Ext(ExtError::InvalidXattrChecksum(LEGACY))) => (),
+            _ => panic!("attribute checksum error expected"),
+        }
+    }
+}
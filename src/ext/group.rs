@@ -0,0 +1,79 @@
+use super::*;
+
+const CHECKSUM_OFFSET: usize = 0x1E;
+
+/// Block group descriptor, 32 or 64 bytes
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct GroupDescriptor {
+    pub block_bitmap: u64,
+    pub inode_bitmap: u64,
+    pub inode_table: u64,
+    pub free_blocks_count: u32,
+    pub free_inodes_count: u32,
+    pub used_dirs_count: u32,
+    /// `INODE_UNINIT`, `BLOCK_UNINIT`, `INODE_ZEROED`
+    pub flags: u16,
+    pub itable_unused: u32,
+    pub checksum: u16,
+}
+
+impl GroupDescriptor {
+    pub const INODE_UNINIT: u16 = 0x0001;
+    pub const BLOCK_UNINIT: u16 = 0x0002;
+    pub const INODE_ZEROED: u16 = 0x0004;
+
+    pub fn parse(bytes: &[u8]) -> Self {
+        let is_64bit = bytes.len() >= 64;
+        let high32 = |pos: usize| if is_64bit { (le_u32(bytes, pos) as u64) << 32 } else { 0 };
+        let high16 = |pos: usize| if is_64bit { (le_u16(bytes, pos) as u32) << 16 } else { 0 };
+
+        Self {
+            block_bitmap: le_u32(bytes, 0x00) as u64 | high32(0x20),
+            inode_bitmap: le_u32(bytes, 0x04) as u64 | high32(0x24),
+            inode_table: le_u32(bytes, 0x08) as u64 | high32(0x28),
+            free_blocks_count: le_u16(bytes, 0x0C) as u32 | high16(0x2C),
+            free_inodes_count: le_u16(bytes, 0x0E) as u32 | high16(0x2E),
+            used_dirs_count: le_u16(bytes, 0x10) as u32 | high16(0x30),
+            flags: le_u16(bytes, 0x12),
+            itable_unused: le_u16(bytes, 0x1C) as u32 | high16(0x32),
+            checksum: le_u16(bytes, CHECKSUM_OFFSET),
+        }
+    }
+}
+
+/// crc32c with `metadata_csum`, crc16 with `gdt_csum`
+pub(crate) fn checksum(sb: &Superblock, group: u32, bytes: &[u8]) -> Option<u16> {
+    let group = group.to_le_bytes();
+    if sb.has_metadata_csum() {
+        let crc = crc::crc32c_raw(sb.csum_seed(), &group);
+        let crc = crc::crc32c_raw(crc, &bytes[..CHECKSUM_OFFSET]);
+        let crc = crc::crc32c_raw(crc, &[0, 0]);
+        Some(crc::crc32c_raw(crc, &bytes[CHECKSUM_OFFSET + 2..]) as u16)
+    } else if sb.has_ro_compat(Superblock::RO_COMPAT_GDT_CSUM) {
+        let crc = crc::crc16_raw(!0, sb.uuid.as_bytes());
+        let crc = crc::crc16_raw(crc, &group);
+        let crc = crc::crc16_raw(crc, &bytes[..CHECKSUM_OFFSET]);
+        Some(crc::crc16_raw(crc, &bytes[CHECKSUM_OFFSET + 2..]))
+    } else {
+        None
+    }
+}
+
+/// Byte offset of the group descriptor
+pub(crate) fn descriptor_position(sb: &Superblock, group: u32) -> u64 {
+    let block_size = sb.block_size() as u64;
+    let per_block = block_size / sb.desc_size as u64;
+    let desc_block = group as u64 / per_block;
+
+    let block = if sb.has_incompat(Superblock::INCOMPAT_META_BG) && desc_block >= sb.first_meta_bg as u64 {
+        // each meta group keeps its descriptors in its first group
+        let first_group = (desc_block * per_block) as u32;
+        let has_super = sb.group_has_super(first_group) as u64;
+        sb.first_data_block as u64 + first_group as u64 * sb.blocks_per_group as u64 + has_super
+    } else {
+        sb.first_data_block as u64 + 1 + desc_block
+    };
+
+    block * block_size + (group as u64 % per_block) * sb.desc_size as u64
+}
@@ -0,0 +1,184 @@
+//! Directory index hashes, see linux/fs/ext4/hash.c
+use super::*;
+
+pub(crate) const HASH_LEGACY: u8 = 0;
+pub(crate) const HASH_HALF_MD4: u8 = 1;
+pub(crate) const HASH_TEA: u8 = 2;
+pub(crate) const HASH_LEGACY_UNSIGNED: u8 = 3;
+pub(crate) const HASH_HALF_MD4_UNSIGNED: u8 = 4;
+pub(crate) const HASH_TEA_UNSIGNED: u8 = 5;
+
+const HTREE_EOF_32BIT: u32 = 0x7FFF_FFFF;
+
+/// Name bytes widened as `char` would be: signed or unsigned
+fn widen(byte: u8, signed: bool) -> u32 {
+    if signed {
+        byte as i8 as i32 as u32
+    } else {
+        byte as u32
+    }
+}
+
+fn legacy(name: &[u8], signed: bool) -> u32 {
+    let (mut hash0, mut hash1) = (0x12A3_FE2D_u32, 0x37AB_E8F9_u32);
+    for &byte in name {
+        let mut hash = hash1.wrapping_add(hash0 ^ widen(byte, signed).wrapping_mul(7_152_373));
+        if hash & 0x8000_0000 != 0 {
+            hash = hash.wrapping_sub(0x7FFF_FFFF);
+        }
+        hash1 = hash0;
+        hash0 = hash;
+    }
+    hash0 << 1
+}
+
+/// Packs the name into `buffer` words padded with the length of the whole rest of the name
+fn str_to_hash_buffer(name: &[u8], buffer: &mut [u32], signed: bool) {
+    let length = name.len() as u32;
+    let mut pad = length | (length << 8);
+    pad |= pad << 16;
+
+    let mut value = pad;
+    let max = buffer.len() * 4;
+    let mut words = buffer.iter_mut();
+    for (i, &byte) in name.iter().take(max).enumerate() {
+        value = widen(byte, signed).wrapping_add(value << 8);
+        if i % 4 == 3 {
+            *words.next().expect("bounded by take") = value;
+            value = pad;
+        }
+    }
+
+    // `value` is the partial word or the pad if there is none
+    for word in words {
+        *word = value;
+        value = pad;
+    }
+}
+
+fn tea_transform(buffer: &mut [u32; 4], input: &[u32]) {
+    const DELTA: u32 = 0x9E37_79B9;
+    let (mut sum, mut b0, mut b1) = (0_u32, buffer[0], buffer[1]);
+    let (a, b, c, d) = (input[0], input[1], input[2], input[3]);
+    for _ in 0..16 {
+        sum = sum.wrapping_add(DELTA);
+        b0 = b0.wrapping_add((b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b));
+        b1 = b1.wrapping_add((b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d));
+    }
+    buffer[0] = buffer[0].wrapping_add(b0);
+    buffer[1] = buffer[1].wrapping_add(b1);
+}
+
+fn half_md4_transform(buffer: &mut [u32; 4], input: &[u32]) {
+    const K2: u32 = 0o13240474631;
+    const K3: u32 = 0o15666365641;
+    let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+    let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+    let [mut a, mut b, mut c, mut d] = *buffer;
+    macro_rules! round {
+        ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+            $a = $a.wrapping_add($f($b, $c, $d)).wrapping_add($x).rotate_left($s);
+        };
+    }
+
+    round!(f, a, b, c, d, input[0], 3);
+    round!(f, d, a, b, c, input[1], 7);
+    round!(f, c, d, a, b, input[2], 11);
+    round!(f, b, c, d, a, input[3], 19);
+    round!(f, a, b, c, d, input[4], 3);
+    round!(f, d, a, b, c, input[5], 7);
+    round!(f, c, d, a, b, input[6], 11);
+    round!(f, b, c, d, a, input[7], 19);
+
+    round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+    round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+    round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+    round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+    buffer[0] = buffer[0].wrapping_add(a);
+    buffer[1] = buffer[1].wrapping_add(b);
+    buffer[2] = buffer[2].wrapping_add(c);
+    buffer[3] = buffer[3].wrapping_add(d);
+}
+
+/// Major hash of the name, `None` for unsupported versions (like SipHash of casefolded directories)
+pub(crate) fn dir_hash(name: &[u8], version: u8, seed: &[u32; 4]) -> Option<u32> {
+    let mut buffer = if seed.iter().any(|&s| s != 0) {
+        *seed
+    } else {
+        [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476]
+    };
+
+    let hash = match version {
+        HASH_LEGACY | HASH_LEGACY_UNSIGNED => legacy(name, version == HASH_LEGACY),
+        HASH_HALF_MD4 | HASH_HALF_MD4_UNSIGNED => {
+            let mut input = [0_u32; 8];
+            for offset in (0..name.len()).step_by(32) {
+                str_to_hash_buffer(&name[offset..], &mut input, version == HASH_HALF_MD4);
+                half_md4_transform(&mut buffer, &input);
+            }
+            buffer[1]
+        }
+        HASH_TEA | HASH_TEA_UNSIGNED => {
+            let mut input = [0_u32; 4];
+            for offset in (0..name.len()).step_by(16) {
+                str_to_hash_buffer(&name[offset..], &mut input, version == HASH_TEA);
+                tea_transform(&mut buffer, &input);
+            }
+            buffer[0]
+        }
+        _ => return None,
+    };
+
+    let hash = hash & !1;
+    Some(if hash == HTREE_EOF_32BIT << 1 {
+        (HTREE_EOF_32BIT - 1) << 1
+    } else {
+        hash
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reference values from `debugfs -R "dx_hash -h <version> [-s <seed>] <name>"`
+    #[test]
+    fn dir_hash_test() {
+        const LONG: &[u8] = b"file_with_long_name_123456789_abcdefghijklmnop";
+        let zero = [0; 4];
+        assert_eq!(dir_hash(b"hello.txt", HASH_LEGACY, &zero), Some(0x65A0_5776));
+        assert_eq!(dir_hash(LONG, HASH_LEGACY, &zero), Some(0x31F6_C906));
+        assert_eq!(dir_hash(b"hello.txt", HASH_HALF_MD4, &zero), Some(0xA26E_1D86));
+        assert_eq!(dir_hash(LONG, HASH_HALF_MD4, &zero), Some(0xE96A_EFC0));
+        assert_eq!(dir_hash(b"hello.txt", HASH_TEA, &zero), Some(0x5107_C3F2));
+        assert_eq!(dir_hash(LONG, HASH_TEA, &zero), Some(0x7C71_0A84));
+
+        // 01234567-89ab-cdef-0123-456789abcdef
+        let seed = [0x6745_2301, 0xEFCD_AB89, 0x6745_2301, 0xEFCD_AB89];
+        assert_eq!(dir_hash(b"hello.txt", HASH_HALF_MD4, &seed), Some(0x42A8_5304));
+
+        // ASCII names hash the same either way
+        assert_eq!(dir_hash(LONG, HASH_TEA_UNSIGNED, &zero), dir_hash(LONG, HASH_TEA, &zero));
+        assert_ne!(
+            dir_hash("é".as_bytes(), HASH_LEGACY_UNSIGNED, &zero),
+            dir_hash("é".as_bytes(), HASH_LEGACY, &zero)
+        );
+        assert_eq!(dir_hash(b"x", 6, &zero), None);
+    }
+}
@@ -0,0 +1,228 @@
+use super::*;
+
+const GOOD_OLD_INODE_SIZE: usize = 128;
+const CHECKSUM_LO: usize = 0x7C;
+const CHECKSUM_HI: usize = 0x82;
+/// Size of `i_block`: block map, extent tree root, fast symlink target or inline data
+pub const INODE_BLOCK_SIZE: usize = 60;
+
+/// Seconds since the Unix epoch and nanoseconds
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Timestamp {
+    pub seconds: i64,
+    pub nanoseconds: u32,
+}
+
+impl Timestamp {
+    /// The low 2 bits of `extra` extend the signed 32 bit seconds, the rest are nanoseconds
+    fn new(seconds: u32, extra: Option<u32>) -> Self {
+        let seconds = seconds as i32 as i64;
+        match extra {
+            Some(extra) => Self {
+                seconds: seconds + (((extra & 3) as i64) << 32),
+                nanoseconds: extra >> 2,
+            },
+            None => Self { seconds, nanoseconds: 0 },
+        }
+    }
+}
+
+/// File type from the mode or the directory entry
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum FileType {
+    Unknown,
+    File,
+    Directory,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+    Symlink,
+}
+
+impl FileType {
+    fn from_mode(mode: u16) -> Self {
+        match mode & 0xF000 {
+            0x1000 => FileType::Fifo,
+            0x2000 => FileType::CharDevice,
+            0x4000 => FileType::Directory,
+            0x6000 => FileType::BlockDevice,
+            0x8000 => FileType::File,
+            0xA000 => FileType::Symlink,
+            0xC000 => FileType::Socket,
+            _ => FileType::Unknown,
+        }
+    }
+
+    /// Directory entry file type, requires the `filetype` feature
+    pub(crate) fn from_dir_entry(kind: u8) -> Self {
+        match kind {
+            1 => FileType::File,
+            2 => FileType::Directory,
+            3 => FileType::CharDevice,
+            4 => FileType::BlockDevice,
+            5 => FileType::Fifo,
+            6 => FileType::Socket,
+            7 => FileType::Symlink,
+            _ => FileType::Unknown,
+        }
+    }
+}
+
+/// On-disk inode
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Inode {
+    pub number: u32,
+    /// File type and permission bits
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub accessed: Timestamp,
+    pub changed: Timestamp,
+    pub modified: Timestamp,
+    pub deleted: u32,
+    /// Not available in 128 byte inodes
+    pub created: Option<Timestamp>,
+    pub links_count: u16,
+    /// Allocated 512 byte sectors, or blocks with the `HUGE_FILE` flag
+    pub blocks: u64,
+    /// `FLAG_*`
+    pub flags: u32,
+    pub block: [u8; INODE_BLOCK_SIZE],
+    pub generation: u32,
+    /// Extended attribute block
+    pub file_acl: u64,
+    /// In-inode extended attributes area, empty for 128 byte inodes
+    pub(crate) xattrs: Vec<u8>,
+}
+
+impl Inode {
+    pub const FLAG_SECRM: u32 = 0x0000_0001;
+    pub const FLAG_IMMUTABLE: u32 = 0x0000_0010;
+    pub const FLAG_APPEND: u32 = 0x0000_0020;
+    pub const FLAG_NODUMP: u32 = 0x0000_0040;
+    pub const FLAG_ENCRYPT: u32 = 0x0000_0800;
+    /// Hashed directory index
+    pub const FLAG_INDEX: u32 = 0x0000_1000;
+    pub const FLAG_HUGE_FILE: u32 = 0x0004_0000;
+    pub const FLAG_EXTENTS: u32 = 0x0008_0000;
+    pub const FLAG_EA_INODE: u32 = 0x0020_0000;
+    pub const FLAG_INLINE_DATA: u32 = 0x1000_0000;
+    pub const FLAG_CASEFOLD: u32 = 0x4000_0000;
+
+    /// `bytes` is the whole on-disk inode
+    pub fn parse(bytes: &[u8], number: u32) -> Result<Self> {
+        if bytes.len() < GOOD_OLD_INODE_SIZE {
+            return Err(Error::from(ExtError::InvalidInode(number)));
+        }
+
+        let extra_size = if bytes.len() > GOOD_OLD_INODE_SIZE {
+            le_u16(bytes, 0x80) as usize
+        } else {
+            0
+        };
+        let extra_end = GOOD_OLD_INODE_SIZE + extra_size;
+        if extra_end > bytes.len() || extra_size % 4 != 0 {
+            return Err(Error::from(ExtError::InvalidInode(number)));
+        }
+
+        let fits = |pos: usize, size: usize| pos + size <= extra_end;
+        let extra = |pos: usize| if fits(pos, 4) { Some(le_u32(bytes, pos)) } else { None };
+
+        let mut block = [0_u8; INODE_BLOCK_SIZE];
+        block.copy_from_slice(&bytes[0x28..0x28 + INODE_BLOCK_SIZE]);
+
+        Ok(Self {
+            number,
+            mode: le_u16(bytes, 0x00),
+            uid: le_u16(bytes, 0x02) as u32 | (le_u16(bytes, 0x78) as u32) << 16,
+            gid: le_u16(bytes, 0x18) as u32 | (le_u16(bytes, 0x7A) as u32) << 16,
+            size: le_u32(bytes, 0x04) as u64 | (le_u32(bytes, 0x6C) as u64) << 32,
+            accessed: Timestamp::new(le_u32(bytes, 0x08), extra(0x8C)),
+            changed: Timestamp::new(le_u32(bytes, 0x0C), extra(0x84)),
+            modified: Timestamp::new(le_u32(bytes, 0x10), extra(0x88)),
+            deleted: le_u32(bytes, 0x14),
+            created: extra(0x90).map(|seconds| Timestamp::new(seconds, extra(0x94))),
+            links_count: le_u16(bytes, 0x1A),
+            blocks: le_u32(bytes, 0x1C) as u64 | (le_u16(bytes, 0x74) as u64) << 32,
+            flags: le_u32(bytes, 0x20),
+            block,
+            generation: le_u32(bytes, 0x64),
+            file_acl: le_u32(bytes, 0x68) as u64 | (le_u16(bytes, 0x76) as u64) << 32,
+            xattrs: bytes[extra_end..].to_vec(),
+        })
+    }
+
+    pub fn file_type(&self) -> FileType {
+        FileType::from_mode(self.mode)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == FileType::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type() == FileType::File
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == FileType::Symlink
+    }
+
+    /// Permission bits including setuid, setgid and sticky
+    pub fn permissions(&self) -> u16 {
+        self.mode & 0o7777
+    }
+
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Allocated bytes
+    pub fn allocated_size(&self, block_size: u32) -> u64 {
+        if self.has_flag(Self::FLAG_HUGE_FILE) {
+            self.blocks * block_size as u64
+        } else {
+            self.blocks * 512
+        }
+    }
+
+    /// The target is stored in `i_block` instead of a data block
+    pub fn is_fast_symlink(&self, block_size: u32) -> bool {
+        let xattr_blocks = if self.file_acl != 0 { block_size as u64 } else { 0 };
+        self.is_symlink()
+            && !self.has_flag(Self::FLAG_INLINE_DATA)
+            && self.size < INODE_BLOCK_SIZE as u64
+            && self.allocated_size(block_size) <= xattr_blocks
+    }
+
+    /// Seed of the checksums of the inode and of its extent, directory and attribute blocks
+    pub(crate) fn csum_seed(&self, fs_seed: u32) -> u32 {
+        let crc = crc::crc32c_raw(fs_seed, &self.number.to_le_bytes());
+        crc::crc32c_raw(crc, &self.generation.to_le_bytes())
+    }
+}
+
+/// Checks `metadata_csum` inode checksum, both halves are zeroed while computing it
+pub(crate) fn verify_checksum(bytes: &[u8], inode: &Inode, fs_seed: u32) -> bool {
+    let has_hi = bytes.len() > GOOD_OLD_INODE_SIZE && GOOD_OLD_INODE_SIZE + le_u16(bytes, 0x80) as usize >= CHECKSUM_HI + 2;
+
+    let mut data = bytes.to_vec();
+    let mut stored = le_u16(bytes, CHECKSUM_LO) as u32;
+    data[CHECKSUM_LO..CHECKSUM_LO + 2].copy_from_slice(&[0, 0]);
+    if has_hi {
+        stored |= (le_u16(bytes, CHECKSUM_HI) as u32) << 16;
+        data[CHECKSUM_HI..CHECKSUM_HI + 2].copy_from_slice(&[0, 0]);
+    }
+
+    let crc = crc::crc32c_raw(inode.csum_seed(fs_seed), &data);
+    if has_hi {
+        crc == stored
+    } else {
+        crc & 0xFFFF == stored
+    }
+}
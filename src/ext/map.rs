@@ -0,0 +1,174 @@
+use super::*;
+
+const EXTENT_MAGIC: u16 = 0xF30A;
+const EXTENT_ENTRY_SIZE: usize = 12;
+/// Uninitialized extents have the length increased by this value
+const EXTENT_INIT_MAX_LEN: u16 = 0x8000;
+/// The kernel limit
+const MAX_EXTENT_DEPTH: u16 = 5;
+
+const DIRECT_BLOCKS: usize = 12;
+
+/// Contiguous run of file blocks
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Extent {
+    /// First file block
+    pub logical: u64,
+    pub length: u32,
+    /// First filesystem block
+    pub physical: u64,
+    /// Allocated but never written, reads as zeros
+    pub uninitialized: bool,
+}
+
+/// Appends the block to the last extent if it continues it
+fn push_block(extents: &mut Vec<Extent>, logical: u64, physical: u64) {
+    if let Some(last) = extents.last_mut() {
+        if last.logical + last.length as u64 == logical && last.physical + last.length as u64 == physical {
+            last.length += 1;
+            return;
+        }
+    }
+
+    extents.push(Extent {
+        logical,
+        length: 1,
+        physical,
+        uninitialized: false,
+    });
+}
+
+impl<R: ReadAt> ExtFileSystem<R> {
+    /// Maps the file blocks, holes are not included
+    pub fn extents(&self, inode: &Inode) -> Result<Vec<Extent>> {
+        let mut extents = Vec::new();
+        if inode.has_flag(Inode::FLAG_INLINE_DATA) || inode.is_fast_symlink(self.block_size()) {
+            return Ok(extents);
+        }
+
+        if inode.has_flag(Inode::FLAG_EXTENTS) {
+            self.walk_extents(inode, &inode.block, MAX_EXTENT_DEPTH + 1, &mut extents)?;
+        } else {
+            self.walk_block_map(inode, &mut extents)?;
+        }
+        Ok(extents)
+    }
+
+    /// `node` is the extent tree root in the inode or a tree block
+    fn walk_extents(&self, inode: &Inode, node: &[u8], max_depth: u16, extents: &mut Vec<Extent>) -> Result<()> {
+        let invalid = || Error::from(ExtError::InvalidExtentTree(inode.number));
+
+        let count = le_u16(node, 2) as usize;
+        let depth = le_u16(node, 6);
+        if le_u16(node, 0) != EXTENT_MAGIC || depth >= max_depth || 12 + count * EXTENT_ENTRY_SIZE > node.len() {
+            return Err(invalid());
+        }
+
+        for i in 0..count {
+            let entry = &node[12 + i * EXTENT_ENTRY_SIZE..12 + (i + 1) * EXTENT_ENTRY_SIZE];
+            let logical = le_u32(entry, 0) as u64;
+            if depth == 0 {
+                let length = le_u16(entry, 4);
+                let (length, uninitialized) = if length > EXTENT_INIT_MAX_LEN {
+                    (length - EXTENT_INIT_MAX_LEN, true)
+                } else {
+                    (length, false)
+                };
+                extents.push(Extent {
+                    logical,
+                    length: length as u32,
+                    physical: le_u32(entry, 8) as u64 | (le_u16(entry, 6) as u64) << 32,
+                    uninitialized,
+                });
+            } else {
+                let block = le_u32(entry, 4) as u64 | (le_u16(entry, 8) as u64) << 32;
+                let mut child = vec![0_u8; self.block_size() as usize];
+                self.read_block(block, &mut child)?;
+                self.verify_extent_block(inode, &child)?;
+                self.walk_extents(inode, &child, depth, extents)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn verify_extent_block(&self, inode: &Inode, block: &[u8]) -> Result<()> {
+        if !self.verify_checksums() {
+            return Ok(());
+        }
+
+        let tail = 12 + le_u16(block, 4) as usize * EXTENT_ENTRY_SIZE;
+        let valid = tail + 4 <= block.len() && crc::crc32c_raw(self.inode_seed(inode), &block[..tail]) == le_u32(block, tail);
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::from(ExtError::InvalidExtentChecksum(inode.number)))
+        }
+    }
+
+    /// Legacy direct, indirect, double and triple indirect blocks
+    fn walk_block_map(&self, inode: &Inode, extents: &mut Vec<Extent>) -> Result<()> {
+        let block_size = self.block_size() as u64;
+        let blocks = math::ceil(inode.size, block_size);
+        let per_block = block_size / 4;
+
+        let mut logical = 0;
+        for i in 0..DIRECT_BLOCKS {
+            if logical >= blocks {
+                return Ok(());
+            }
+            match le_u32(&inode.block, i * 4) {
+                0 => (),
+                physical => push_block(extents, logical, physical as u64),
+            }
+            logical += 1;
+        }
+
+        let mut span = per_block;
+        for level in 0..3 {
+            if logical >= blocks {
+                break;
+            }
+            let block = le_u32(&inode.block, (DIRECT_BLOCKS + level) * 4) as u64;
+            self.walk_indirect(inode, block, level, logical, blocks, extents)?;
+            logical += span;
+            span *= per_block;
+        }
+
+        Ok(())
+    }
+
+    /// `level` 0 block holds data block numbers, higher levels hold lower level blocks
+    fn walk_indirect(&self, inode: &Inode, block: u64, level: usize, first: u64, blocks: u64, extents: &mut Vec<Extent>) -> Result<()> {
+        if block == 0 {
+            return Ok(()); // hole
+        }
+        if block >= self.sb.blocks_count {
+            return Err(Error::from(ExtError::InvalidBlockMap(inode.number)));
+        }
+
+        let mut data = vec![0_u8; self.block_size() as usize];
+        self.read_block(block, &mut data)?;
+
+        let per_block = self.block_size() as u64 / 4;
+        let span = per_block.pow(level as u32);
+        for (i, entry) in data.chunks_exact(4).enumerate() {
+            let logical = first + i as u64 * span;
+            if logical >= blocks {
+                break;
+            }
+
+            let physical = le_u32(entry, 0) as u64;
+            if level == 0 {
+                if physical != 0 {
+                    push_block(extents, logical, physical);
+                }
+            } else {
+                self.walk_indirect(inode, physical, level - 1, logical, blocks, extents)?;
+            }
+        }
+
+        Ok(())
+    }
+}
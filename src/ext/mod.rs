@@ -0,0 +1,43 @@
+//! ext2/3/4 filesystem, read only
+//!
+//! Metadata checksums are verified with the `ext-checksums` feature.
+//! See https://www.kernel.org/doc/html/latest/filesystems/ext4/index.html
+use crate::prelude::*;
+use crate::tools::{le_u16, le_u32};
+
+mod error;
+pub use error::ExtError;
+
+mod superblock;
+pub use superblock::Superblock;
+
+mod group;
+pub use group::GroupDescriptor;
+
+mod inode;
+pub use inode::{FileType, Inode, Timestamp, INODE_BLOCK_SIZE};
+
+mod map;
+pub use map::Extent;
+
+mod hash;
+
+mod xattr;
+pub use xattr::Xattr;
+
+mod dir;
+pub use dir::DirEntry;
+
+mod file;
+pub use file::File;
+
+mod fs;
+pub use fs::ExtFileSystem;
+#[cfg(test)]
+pub(crate) use fs::tests::image as test_image;
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const GROUP_DESC_SIZE: usize = 32;
+pub const ROOT_INODE: u32 = 2;
+
@@ -0,0 +1,247 @@
+use super::*;
+
+const MAGIC: u16 = 0xEF53;
+const GOOD_OLD_INODE_SIZE: u16 = 128;
+const GOOD_OLD_FIRST_INODE: u32 = 11;
+/// The kernel limit, the minimal block size
+const MAX_DESC_SIZE: u16 = 1024;
+/// `s_checksum_type` for crc32c
+const CHECKSUM_CRC32C: u8 = 1;
+const CHECKSUM_OFFSET: usize = 0x3FC;
+
+/// Incompatible features this reader understands
+const SUPPORTED_INCOMPAT: u32 = Superblock::INCOMPAT_FILETYPE
+    | Superblock::INCOMPAT_RECOVER
+    | Superblock::INCOMPAT_META_BG
+    | Superblock::INCOMPAT_EXTENTS
+    | Superblock::INCOMPAT_64BIT
+    | Superblock::INCOMPAT_MMP
+    | Superblock::INCOMPAT_FLEX_BG
+    | Superblock::INCOMPAT_EA_INODE
+    | Superblock::INCOMPAT_CSUM_SEED
+    | Superblock::INCOMPAT_LARGEDIR
+    | Superblock::INCOMPAT_INLINE_DATA
+    | Superblock::INCOMPAT_ENCRYPT
+    | Superblock::INCOMPAT_CASEFOLD;
+
+/// The superblock, always 1024 bytes at offset 1024
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Superblock {
+    pub inodes_count: u32,
+    pub blocks_count: u64,
+    pub reserved_blocks_count: u64,
+    pub free_blocks_count: u64,
+    pub free_inodes_count: u32,
+    pub first_data_block: u32,
+    pub log_block_size: u32,
+    pub blocks_per_group: u32,
+    pub inodes_per_group: u32,
+    pub mount_time: u32,
+    pub write_time: u32,
+    pub mount_count: u16,
+    /// `STATE_*`
+    pub state: u16,
+    pub rev_level: u32,
+    pub first_inode: u32,
+    pub inode_size: u16,
+    pub feature_compat: u32,
+    pub feature_incompat: u32,
+    pub feature_ro_compat: u32,
+    pub uuid: Uuid,
+    pub volume_name: String,
+    pub last_mounted: String,
+    pub journal_inode: u32,
+    pub hash_seed: [u32; 4],
+    pub default_hash_version: u8,
+    /// Group descriptor size, 32 without the 64bit feature
+    pub desc_size: u16,
+    pub first_meta_bg: u32,
+    pub mkfs_time: u32,
+    /// `FLAGS_*`
+    pub flags: u32,
+    pub checksum_seed: u32,
+    pub checksum: u32,
+}
+
+impl Superblock {
+    pub const STATE_CLEAN: u16 = 0x0001;
+    pub const STATE_ERRORS: u16 = 0x0002;
+
+    pub const FLAGS_SIGNED_HASH: u32 = 0x0001;
+    pub const FLAGS_UNSIGNED_HASH: u32 = 0x0002;
+
+    pub const COMPAT_HAS_JOURNAL: u32 = 0x0004;
+    pub const COMPAT_EXT_ATTR: u32 = 0x0008;
+    pub const COMPAT_RESIZE_INODE: u32 = 0x0010;
+    pub const COMPAT_DIR_INDEX: u32 = 0x0020;
+    pub const COMPAT_SPARSE_SUPER2: u32 = 0x0200;
+
+    pub const INCOMPAT_COMPRESSION: u32 = 0x0001;
+    pub const INCOMPAT_FILETYPE: u32 = 0x0002;
+    /// The journal needs recovery, the last transactions are not applied
+    pub const INCOMPAT_RECOVER: u32 = 0x0004;
+    pub const INCOMPAT_JOURNAL_DEV: u32 = 0x0008;
+    pub const INCOMPAT_META_BG: u32 = 0x0010;
+    pub const INCOMPAT_EXTENTS: u32 = 0x0040;
+    pub const INCOMPAT_64BIT: u32 = 0x0080;
+    pub const INCOMPAT_MMP: u32 = 0x0100;
+    pub const INCOMPAT_FLEX_BG: u32 = 0x0200;
+    pub const INCOMPAT_EA_INODE: u32 = 0x0400;
+    pub const INCOMPAT_DIRDATA: u32 = 0x1000;
+    pub const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+    pub const INCOMPAT_LARGEDIR: u32 = 0x4000;
+    pub const INCOMPAT_INLINE_DATA: u32 = 0x8000;
+    pub const INCOMPAT_ENCRYPT: u32 = 0x1_0000;
+    pub const INCOMPAT_CASEFOLD: u32 = 0x2_0000;
+
+    pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+    pub const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+    pub const RO_COMPAT_HUGE_FILE: u32 = 0x0008;
+    pub const RO_COMPAT_GDT_CSUM: u32 = 0x0010;
+    pub const RO_COMPAT_DIR_NLINK: u32 = 0x0020;
+    pub const RO_COMPAT_EXTRA_ISIZE: u32 = 0x0040;
+    pub const RO_COMPAT_QUOTA: u32 = 0x0100;
+    pub const RO_COMPAT_BIGALLOC: u32 = 0x0200;
+    pub const RO_COMPAT_METADATA_CSUM: u32 = 0x0400;
+
+    /// `bytes` is the whole superblock
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < SUPERBLOCK_SIZE || le_u16(bytes, 0x38) != MAGIC {
+            return Err(Error::from(ExtError::InvalidSuperblock));
+        }
+
+        let incompat = le_u32(bytes, 0x60);
+        let is_64bit = incompat & Self::INCOMPAT_64BIT != 0;
+        let high = |pos: usize| if is_64bit { (le_u32(bytes, pos) as u64) << 32 } else { 0 };
+        let dynamic = le_u32(bytes, 0x4C) > 0;
+
+        let mut uuid = [0_u8; 16];
+        uuid.copy_from_slice(&bytes[0x68..0x78]);
+        let mut hash_seed = [0_u32; 4];
+        for (i, seed) in hash_seed.iter_mut().enumerate() {
+            *seed = le_u32(bytes, 0xEC + i * 4);
+        }
+
+        let sb = Self {
+            inodes_count: le_u32(bytes, 0x00),
+            blocks_count: le_u32(bytes, 0x04) as u64 | high(0x150),
+            reserved_blocks_count: le_u32(bytes, 0x08) as u64 | high(0x154),
+            free_blocks_count: le_u32(bytes, 0x0C) as u64 | high(0x158),
+            free_inodes_count: le_u32(bytes, 0x10),
+            first_data_block: le_u32(bytes, 0x14),
+            log_block_size: le_u32(bytes, 0x18),
+            blocks_per_group: le_u32(bytes, 0x20),
+            inodes_per_group: le_u32(bytes, 0x28),
+            mount_time: le_u32(bytes, 0x2C),
+            write_time: le_u32(bytes, 0x30),
+            mount_count: le_u16(bytes, 0x34),
+            state: le_u16(bytes, 0x3A),
+            rev_level: le_u32(bytes, 0x4C),
+            first_inode: if dynamic { le_u32(bytes, 0x54) } else { GOOD_OLD_FIRST_INODE },
+            inode_size: if dynamic { le_u16(bytes, 0x58) } else { GOOD_OLD_INODE_SIZE },
+            feature_compat: le_u32(bytes, 0x5C),
+            feature_incompat: incompat,
+            feature_ro_compat: le_u32(bytes, 0x64),
+            uuid: Uuid::from_bytes(uuid),
+            volume_name: tools::string_from_ascii_z(&bytes[0x78..0x88]),
+            last_mounted: tools::string_from_ascii_z(&bytes[0x88..0xC8]),
+            journal_inode: le_u32(bytes, 0xE0),
+            hash_seed,
+            default_hash_version: bytes[0xFC],
+            desc_size: if is_64bit { le_u16(bytes, 0xFE) } else { GROUP_DESC_SIZE as u16 },
+            first_meta_bg: le_u32(bytes, 0x104),
+            mkfs_time: le_u32(bytes, 0x108),
+            flags: le_u32(bytes, 0x160),
+            checksum_seed: le_u32(bytes, 0x270),
+            checksum: le_u32(bytes, CHECKSUM_OFFSET),
+        };
+
+        let valid = sb.log_block_size <= 6 // up to 64 KiB
+            && sb.blocks_per_group > 0
+            && sb.inodes_per_group > 0
+            && sb.inode_size >= GOOD_OLD_INODE_SIZE
+            && sb.inode_size.is_power_of_two()
+            && sb.inode_size as u32 <= sb.block_size()
+            && sb.desc_size as usize >= GROUP_DESC_SIZE
+            && sb.desc_size.is_power_of_two()
+            && sb.desc_size <= MAX_DESC_SIZE
+            && sb.desc_size as u32 <= sb.block_size()
+            && sb.blocks_count > sb.first_data_block as u64;
+        if !valid {
+            return Err(Error::from(ExtError::InvalidSuperblock));
+        }
+
+        if sb.has_metadata_csum() {
+            let invalid_type = bytes[0x175] != CHECKSUM_CRC32C;
+            if invalid_type || (cfg!(feature = "ext-checksums") && crc::crc32c_raw(!0, &bytes[..CHECKSUM_OFFSET]) != sb.checksum) {
+                return Err(Error::from(ExtError::InvalidSuperblockChecksum));
+            }
+        }
+
+        let unsupported = sb.feature_incompat & !SUPPORTED_INCOMPAT;
+        if unsupported != 0 {
+            return Err(Error::from(ExtError::UnsupportedFeature(unsupported)));
+        }
+
+        Ok(sb)
+    }
+
+    pub fn block_size(&self) -> u32 {
+        1024 << self.log_block_size
+    }
+
+    pub fn group_count(&self) -> u32 {
+        let blocks = self.blocks_count - self.first_data_block as u64;
+        math::ceil(blocks, self.blocks_per_group as u64) as u32
+    }
+
+    pub fn has_incompat(&self, feature: u32) -> bool {
+        self.feature_incompat & feature != 0
+    }
+
+    pub fn has_ro_compat(&self, feature: u32) -> bool {
+        self.feature_ro_compat & feature != 0
+    }
+
+    pub fn has_metadata_csum(&self) -> bool {
+        self.has_ro_compat(Self::RO_COMPAT_METADATA_CSUM)
+    }
+
+    /// ext2 has neither journal nor extents, ext3 adds the journal, ext4 the rest
+    pub fn version(&self) -> u8 {
+        const EXT4_INCOMPAT: u32 = Superblock::INCOMPAT_EXTENTS | Superblock::INCOMPAT_64BIT | Superblock::INCOMPAT_FLEX_BG;
+        const EXT4_RO_COMPAT: u32 = Superblock::RO_COMPAT_HUGE_FILE | Superblock::RO_COMPAT_GDT_CSUM | Superblock::RO_COMPAT_METADATA_CSUM;
+        if self.feature_incompat & EXT4_INCOMPAT != 0 || self.feature_ro_compat & EXT4_RO_COMPAT != 0 {
+            4
+        } else if self.feature_compat & Self::COMPAT_HAS_JOURNAL != 0 {
+            3
+        } else {
+            2
+        }
+    }
+
+    /// Whether the group holds a superblock backup and the group descriptors
+    pub fn group_has_super(&self, group: u32) -> bool {
+        fn power_of(mut value: u32, base: u32) -> bool {
+            while value > 1 && value / base * base == value {
+                value /= base;
+            }
+            value == 1
+        }
+
+        if group <= 1 || !self.has_ro_compat(Self::RO_COMPAT_SPARSE_SUPER) {
+            return true;
+        }
+        group & 1 == 1 && (power_of(group, 3) || power_of(group, 5) || power_of(group, 7))
+    }
+
+    /// Seed of all metadata checksums
+    pub(crate) fn csum_seed(&self) -> u32 {
+        if self.has_incompat(Self::INCOMPAT_CSUM_SEED) {
+            self.checksum_seed
+        } else {
+            crc::crc32c_raw(!0, self.uuid.as_bytes())
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use super::*;
+
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+const BLOCK_HEADER_SIZE: usize = 32;
+const BLOCK_CHECKSUM_OFFSET: usize = 0x10;
+const ENTRY_HEADER_SIZE: usize = 16;
+
+/// Extended attribute with the full name like `user.comment`
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Xattr {
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+fn name_prefix(index: u8) -> &'static str {
+    match index {
+        1 => "user.",
+        2 => "system.posix_acl_access",
+        3 => "system.posix_acl_default",
+        4 => "trusted.",
+        6 => "security.",
+        7 => "system.",
+        8 => "system.richacl",
+        _ => "",
+    }
+}
+
+/// Entry with the value location
+struct RawXattr {
+    name: String,
+    value_offset: usize,
+    value_inode: u32,
+    value_size: usize,
+}
+
+/// Entries up to the 4 zero bytes terminator
+fn parse_entries(bytes: &[u8], inode: u32) -> Result<Vec<RawXattr>> {
+    let invalid = || Error::from(ExtError::InvalidXattr(inode));
+
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    loop {
+        let header = bytes.get(pos..pos + 4).ok_or_else(invalid)?;
+        if le_u32(header, 0) == 0 {
+            return Ok(entries);
+        }
+
+        let header = bytes.get(pos..pos + ENTRY_HEADER_SIZE).ok_or_else(invalid)?;
+        let name_length = header[0] as usize;
+        let name = bytes
+            .get(pos + ENTRY_HEADER_SIZE..pos + ENTRY_HEADER_SIZE + name_length)
+            .ok_or_else(invalid)?;
+        entries.push(RawXattr {
+            name: format!("{}{}", name_prefix(header[1]), String::from_utf8_lossy(name)),
+            value_offset: le_u16(header, 2) as usize,
+            value_inode: le_u32(header, 4),
+            value_size: le_u32(header, 8) as usize,
+        });
+
+        pos += (ENTRY_HEADER_SIZE + name_length + 3) & !3;
+    }
+}
+
+impl<R: ReadAt> ExtFileSystem<R> {
+    /// In-inode attributes followed by the ones from the attribute block
+    pub fn xattrs(&self, inode: &Inode) -> Result<Vec<Xattr>> {
+        let mut xattrs = Vec::new();
+
+        // value offsets are relative to the first entry
+        if inode.xattrs.len() >= 4 && le_u32(&inode.xattrs, 0) == XATTR_MAGIC {
+            let values = &inode.xattrs[4..];
+            self.collect_xattrs(inode, values, values, &mut xattrs)?;
+        }
+
+        if inode.file_acl != 0 {
+            let mut block = vec![0_u8; self.block_size() as usize];
+            self.read_block(inode.file_acl, &mut block)?;
+            if le_u32(&block, 0) != XATTR_MAGIC || le_u32(&block, 8) != 1 {
+                return Err(Error::from(ExtError::InvalidXattr(inode.number)));
+            }
+            self.verify_xattr_block(inode, &block)?;
+
+            // value offsets are relative to the block
+            self.collect_xattrs(inode, &block[BLOCK_HEADER_SIZE..], &block, &mut xattrs)?;
+        }
+
+        Ok(xattrs)
+    }
+
+    pub fn xattr(&self, inode: &Inode, name: &str) -> Result<Option<Vec<u8>>> {
+        let xattrs = self.xattrs(inode)?;
+        Ok(xattrs.into_iter().find(|x| x.name == name).map(|x| x.value))
+    }
+
+    fn collect_xattrs(&self, inode: &Inode, entries: &[u8], values: &[u8], xattrs: &mut Vec<Xattr>) -> Result<()> {
+        for entry in parse_entries(entries, inode.number)? {
+            let value = if entry.value_inode != 0 {
+                // large value in its own inode, `ea_inode` feature
+                let value_inode = self.inode(entry.value_inode)?;
+                let mut value = vec![0_u8; entry.value_size];
+                self.open_inode(value_inode)?.read_exact_at(0, &mut value)?;
+                value
+            } else {
+                values
+                    .get(entry.value_offset..entry.value_offset + entry.value_size)
+                    .ok_or_else(|| Error::from(ExtError::InvalidXattr(inode.number)))?
+                    .to_vec()
+            };
+
+            xattrs.push(Xattr { name: entry.name, value });
+        }
+
+        Ok(())
+    }
+
+    fn verify_xattr_block(&self, inode: &Inode, block: &[u8]) -> Result<()> {
+        if !self.verify_checksums() {
+            return Ok(());
+        }
+
+        let crc = crc::crc32c_raw(self.sb.csum_seed(), &inode.file_acl.to_le_bytes());
+        let crc = crc::crc32c_raw(crc, &block[..BLOCK_CHECKSUM_OFFSET]);
+        let crc = crc::crc32c_raw(crc, &[0; 4]);
+        let crc = crc::crc32c_raw(crc, &block[BLOCK_CHECKSUM_OFFSET + 4..]);
+        if crc == le_u32(block, BLOCK_CHECKSUM_OFFSET) {
+            Ok(())
+        } else {
+            Err(Error::from(ExtError::InvalidXattrChecksum(inode.number)))
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use super::*;
+
+/// Common BIOS Parameter Block
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub(crate) struct BiosParameterBlock {
+    pub jump: [u8; 3],
+    pub oem_name: [u8; 8],
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub fat_count: u8,
+    pub root_entries: u16,
+    pub total_sectors_16: u16,
+    pub media: u8,
+    pub fat_size_16: u16,
+    pub sectors_per_track: u16,
+    pub heads: u16,
+    pub hidden_sectors: u32,
+    pub total_sectors_32: u32,
+}
+
+pub(crate) const BPB_SIZE: usize = core::mem::size_of::<BiosParameterBlock>();
+
+/// FAT12/16 extended BPB, follows `BiosParameterBlock`
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub(crate) struct ExtendedBpb16 {
+    pub drive_number: u8,
+    pub reserved: u8,
+    pub boot_signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub fs_type: [u8; 8],
+}
+
+/// FAT32 extended BPB, follows `BiosParameterBlock`
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub(crate) struct ExtendedBpb32 {
+    pub fat_size_32: u32,
+    pub ext_flags: u16,
+    pub fs_version: u16,
+    pub root_cluster: u32,
+    pub fs_info: u16,
+    pub backup_boot_sector: u16,
+    pub reserved: [u8; 12],
+    pub drive_number: u8,
+    pub reserved1: u8,
+    pub boot_signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub fs_type: [u8; 8],
+}
+
+pub(crate) const EXTENDED_BOOT_SIGNATURE: u8 = 0x29;
+
+/// Always `Debug`, as the [`FatError`] carrying it and [`Error`] are
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FatKind {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatKind {
+    /// The only correct way to determine the FAT type, see fatgen103
+    pub fn from_cluster_count(count: u32) -> Self {
+        if count < 4085 {
+            FatKind::Fat12
+        } else if count < 65525 {
+            FatKind::Fat16
+        } else {
+            FatKind::Fat32
+        }
+    }
+
+    /// The smallest value treated as the end of a cluster chain
+    pub(crate) fn end_of_chain(self) -> u32 {
+        match self {
+            FatKind::Fat12 => 0x0FF8,
+            FatKind::Fat16 => 0xFFF8,
+            FatKind::Fat32 => 0x0FFF_FFF8,
+        }
+    }
+
+    pub(crate) fn bad_cluster(self) -> u32 {
+        self.end_of_chain() - 1
+    }
+}
+
+impl core::fmt::Display for FatKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FatKind::Fat12 => f.write_str("FAT12"),
+            FatKind::Fat16 => f.write_str("FAT16"),
+            FatKind::Fat32 => f.write_str("FAT32"),
+        }
+    }
+}
+
+/// Parsed boot sector
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct BootSector {
+    pub kind: FatKind,
+    pub oem_name: String,
+    pub bytes_per_sector: u32,
+    pub sectors_per_cluster: u32,
+    pub reserved_sectors: u32,
+    pub fat_count: u32,
+    /// Fixed root directory entries, FAT12/16 only
+    pub root_entries: u32,
+    pub total_sectors: u32,
+    pub media: u8,
+    /// Sectors per FAT
+    pub fat_size: u32,
+    pub hidden_sectors: u32,
+    /// FAT32 only
+    pub root_cluster: u32,
+    /// FAT32 only
+    pub fs_info_sector: u32,
+    /// FAT32 only
+    pub backup_boot_sector: u32,
+    /// Active FAT if mirroring is disabled (FAT32 only)
+    pub active_fat: Option<u32>,
+    pub volume_id: Option<u32>,
+    pub volume_label: String,
+    pub fs_type: String,
+    pub cluster_count: u32,
+}
+
+impl BootSector {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 512 || !matches!(bytes[0], 0xEB | 0xE9) {
+            return Err(Error::from(FatError::InvalidBootSector));
+        }
+
+        let bpb: BiosParameterBlock = tools::read_struct(bytes);
+        let bytes_per_sector = bpb.bytes_per_sector as u32;
+        let sectors_per_cluster = bpb.sectors_per_cluster as u32;
+        if !bytes_per_sector.is_power_of_two()
+            || !(512..=4096).contains(&bytes_per_sector)
+            || !sectors_per_cluster.is_power_of_two()
+            || bpb.reserved_sectors == 0
+            || bpb.fat_count == 0
+        {
+            return Err(Error::from(FatError::InvalidBootSector));
+        }
+
+        let total_sectors = match bpb.total_sectors_16 {
+            0 => bpb.total_sectors_32,
+            sectors => sectors as u32,
+        };
+
+        let mut boot = Self {
+            kind: FatKind::Fat12,
+            oem_name: tools::string_from_ascii_z(&bpb.oem_name),
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors: bpb.reserved_sectors as u32,
+            fat_count: bpb.fat_count as u32,
+            root_entries: bpb.root_entries as u32,
+            total_sectors,
+            media: bpb.media,
+            fat_size: bpb.fat_size_16 as u32,
+            hidden_sectors: bpb.hidden_sectors,
+            root_cluster: 0,
+            fs_info_sector: 0,
+            backup_boot_sector: 0,
+            active_fat: None,
+            volume_id: None,
+            volume_label: String::new(),
+            fs_type: String::new(),
+            cluster_count: 0,
+        };
+
+        let (boot_signature, volume_id, volume_label, fs_type) = if bpb.fat_size_16 == 0 {
+            let ext: ExtendedBpb32 = tools::read_struct(&bytes[BPB_SIZE..]);
+            boot.fat_size = ext.fat_size_32;
+            boot.root_cluster = ext.root_cluster;
+            boot.fs_info_sector = ext.fs_info as u32;
+            boot.backup_boot_sector = ext.backup_boot_sector as u32;
+            if ext.ext_flags & 0x80 != 0 {
+                boot.active_fat = Some((ext.ext_flags & 0x0F) as u32);
+            }
+            (ext.boot_signature, ext.volume_id, ext.volume_label, ext.fs_type)
+        } else {
+            let ext: ExtendedBpb16 = tools::read_struct(&bytes[BPB_SIZE..]);
+            (ext.boot_signature, ext.volume_id, ext.volume_label, ext.fs_type)
+        };
+
+        if boot_signature == EXTENDED_BOOT_SIGNATURE {
+            boot.volume_id = Some(volume_id);
+            boot.volume_label = tools::string_from_ascii_z(&volume_label);
+            boot.fs_type = tools::string_from_ascii_z(&fs_type);
+        }
+
+        // the FATs end before the data region, so the offsets within the volume fit u32 sectors
+        let fats_size = boot.fat_count.checked_mul(boot.fat_size);
+        let data_start = match fats_size.and_then(|size| size.checked_add(boot.reserved_sectors + boot.root_dir_sectors())) {
+            Some(start) if boot.fat_size != 0 && start < total_sectors => start,
+            _ => return Err(Error::from(FatError::InvalidBootSector)),
+        };
+        if matches!(boot.active_fat, Some(fat) if fat >= boot.fat_count) {
+            return Err(Error::from(FatError::InvalidBootSector));
+        }
+
+        boot.cluster_count = (total_sectors - data_start) / sectors_per_cluster;
+        boot.kind = FatKind::from_cluster_count(boot.cluster_count);
+
+        // FAT32 requires the root cluster, FAT12/16 require the fixed root directory
+        let valid = match boot.kind {
+            FatKind::Fat32 => boot.root_cluster >= 2 && boot.root_entries == 0,
+            _ => boot.root_entries != 0,
+        };
+        if !valid {
+            return Err(Error::from(FatError::InvalidBootSector));
+        }
+
+        // the FAT must be able to address all the clusters
+        let fat_bits = match boot.kind {
+            FatKind::Fat12 => 12,
+            FatKind::Fat16 => 16,
+            FatKind::Fat32 => 32,
+        };
+        if (boot.cluster_count as u64 + 2) * fat_bits > boot.fat_size as u64 * bytes_per_sector as u64 * 8 {
+            return Err(Error::from(FatError::InvalidBootSector));
+        }
+
+        Ok(boot)
+    }
+
+    pub fn cluster_size(&self) -> u32 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    fn root_dir_sectors(&self) -> u32 {
+        math::ceil(self.root_entries * DIR_ENTRY_SIZE as u32, self.bytes_per_sector)
+    }
+
+    fn data_start_sector(&self) -> u32 {
+        self.reserved_sectors + self.fat_count * self.fat_size + self.root_dir_sectors()
+    }
+
+    /// Byte offset of the FAT copy
+    pub(crate) fn fat_offset(&self, copy: u32) -> u64 {
+        (self.reserved_sectors + copy * self.fat_size) as u64 * self.bytes_per_sector as u64
+    }
+
+    /// Byte offset and size of the FAT12/16 fixed root directory
+    pub(crate) fn root_dir_region(&self) -> (u64, u64) {
+        let offset = self.fat_offset(self.fat_count);
+        (offset, self.root_dir_sectors() as u64 * self.bytes_per_sector as u64)
+    }
+
+    /// Byte offset of the cluster
+    pub(crate) fn cluster_offset(&self, cluster: u32) -> u64 {
+        debug_assert!(cluster >= FIRST_CLUSTER);
+
+        let sector = self.data_start_sector() as u64 + (cluster - FIRST_CLUSTER) as u64 * self.sectors_per_cluster as u64;
+        sector * self.bytes_per_sector as u64
+    }
+
+    /// The last valid cluster number
+    pub(crate) fn max_cluster(&self) -> u32 {
+        self.cluster_count + FIRST_CLUSTER - 1
+    }
+}
+
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FS_INFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FS_INFO_TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+const FS_INFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+/// FAT32 FSInfo sector hints
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct FsInfoSector {
+    pub free_count: Option<u32>,
+    pub next_free: Option<u32>,
+}
+
+impl FsInfoSector {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 512
+            || le_u32(bytes, 0) != FS_INFO_LEAD_SIGNATURE
+            || le_u32(bytes, 484) != FS_INFO_STRUCT_SIGNATURE
+            || le_u32(bytes, 508) != FS_INFO_TRAIL_SIGNATURE
+        {
+            return None;
+        }
+
+        let hint = |value| if value == FS_INFO_UNKNOWN { None } else { Some(value) };
+        Some(Self {
+            free_count: hint(le_u32(bytes, 488)),
+            next_free: hint(le_u32(bytes, 492)),
+        })
+    }
+}
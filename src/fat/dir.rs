@@ -0,0 +1,515 @@
+use super::*;
+
+#[repr(C, packed)]
+#[derive(Copy, Clone, Default)]
+pub(crate) struct DirEntryRecord {
+    pub name: [u8; 11],
+    pub attributes: u8,
+    pub nt_flags: u8,
+    pub create_time_tenth: u8,
+    pub create_time: u16,
+    pub create_date: u16,
+    pub access_date: u16,
+    pub cluster_high: u16,
+    pub write_time: u16,
+    pub write_date: u16,
+    pub cluster_low: u16,
+    pub file_size: u32,
+}
+
+/// VFAT long name entry
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub(crate) struct LfnRecord {
+    pub order: u8,
+    pub name1: [u16; 5],
+    pub attributes: u8,
+    pub kind: u8,
+    pub checksum: u8,
+    pub name2: [u16; 6],
+    pub cluster: u16,
+    pub name3: [u16; 2],
+}
+
+impl DirEntryRecord {
+    pub fn new(name: [u8; 11], nt_flags: u8, attributes: u8, time: Option<DateTime>) -> Self {
+        let (date, time, tenth) = time.map(|t| t.to_fat()).unwrap_or((0, 0, 0));
+        Self {
+            name,
+            attributes,
+            nt_flags,
+            create_time_tenth: tenth,
+            create_time: time,
+            create_date: date,
+            access_date: date,
+            write_time: time,
+            write_date: date,
+            ..Default::default()
+        }
+    }
+
+    pub fn first_cluster(&self) -> u32 {
+        ((self.cluster_high as u32) << 16) | self.cluster_low as u32
+    }
+
+    pub fn set_first_cluster(&mut self, cluster: u32) {
+        self.cluster_high = (cluster >> 16) as u16;
+        self.cluster_low = cluster as u16;
+    }
+
+    pub fn set_modified(&mut self, time: Option<DateTime>) {
+        if let Some(time) = time {
+            let (date, time, _) = time.to_fat();
+            self.write_date = date;
+            self.write_time = time;
+            self.access_date = date;
+        }
+    }
+}
+
+impl LfnRecord {
+    /// Long name entries in the on-disk order (the last part first)
+    pub fn for_name(chars: &[u16], checksum: u8) -> Vec<Self> {
+        let count = math::ceil(chars.len(), LFN_CHARS);
+        (1..=count)
+            .rev()
+            .map(|order| {
+                let mut part = [0xFFFF_u16; LFN_CHARS];
+                let start = (order - 1) * LFN_CHARS;
+                let len = core::cmp::min(LFN_CHARS, chars.len() - start);
+                part[..len].copy_from_slice(&chars[start..start + len]);
+                if len < LFN_CHARS {
+                    part[len] = 0;
+                }
+
+                let mut name1 = [0_u16; 5];
+                let mut name2 = [0_u16; 6];
+                let mut name3 = [0_u16; 2];
+                name1.copy_from_slice(&part[..5]);
+                name2.copy_from_slice(&part[5..11]);
+                name3.copy_from_slice(&part[11..]);
+                Self {
+                    order: order as u8 | if order == count { LFN_LAST } else { 0 },
+                    name1,
+                    attributes: Attributes::LONG_NAME,
+                    kind: 0,
+                    checksum,
+                    name2,
+                    cluster: 0,
+                    name3,
+                }
+            })
+            .collect()
+    }
+}
+
+pub(crate) const LFN_CHARS: usize = 13;
+pub(crate) const LFN_LAST: u8 = 0x40;
+pub(crate) const LFN_ORDER_MASK: u8 = 0x3F;
+
+pub(crate) const END_OF_DIRECTORY: u8 = 0x00;
+pub(crate) const DELETED: u8 = 0xE5;
+/// The first name byte 0xE5 is stored as 0x05
+const KANJI_E5: u8 = 0x05;
+
+pub(crate) const NT_LOWERCASE_BASE: u8 = 0x08;
+pub(crate) const NT_LOWERCASE_EXT: u8 = 0x10;
+
+pub(crate) const DOT_NAME: &[u8; 11] = b".          ";
+pub(crate) const DOT_DOT_NAME: &[u8; 11] = b"..         ";
+
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Attributes(pub u8);
+
+impl Attributes {
+    pub const READ_ONLY: u8 = 0x01;
+    pub const HIDDEN: u8 = 0x02;
+    pub const SYSTEM: u8 = 0x04;
+    pub const VOLUME_ID: u8 = 0x08;
+    pub const DIRECTORY: u8 = 0x10;
+    pub const ARCHIVE: u8 = 0x20;
+    pub const LONG_NAME: u8 = 0x0F;
+
+    pub fn contains(self, flags: u8) -> bool {
+        self.0 & flags == flags
+    }
+
+    pub fn is_directory(self) -> bool {
+        self.contains(Self::DIRECTORY)
+    }
+
+    pub fn is_read_only(self) -> bool {
+        self.contains(Self::READ_ONLY)
+    }
+
+    pub fn is_hidden(self) -> bool {
+        self.contains(Self::HIDDEN)
+    }
+
+    pub fn is_system(self) -> bool {
+        self.contains(Self::SYSTEM)
+    }
+
+    pub fn is_archive(self) -> bool {
+        self.contains(Self::ARCHIVE)
+    }
+
+    pub(crate) fn is_long_name(self) -> bool {
+        self.0 & 0x3F == Self::LONG_NAME
+    }
+
+    pub(crate) fn is_volume_label(self) -> bool {
+        !self.is_long_name() && self.contains(Self::VOLUME_ID)
+    }
+}
+
+/// FAT timestamp, local time without time zone
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millisecond: u16,
+}
+
+impl DateTime {
+    /// Decodes the packed date and time, `tenth` are 10 ms units (0-199)
+    pub fn from_fat(date: u16, time: u16, tenth: u8) -> Option<Self> {
+        let month = ((date >> 5) & 0x0F) as u8;
+        let day = (date & 0x1F) as u8;
+        if date == 0 || month == 0 || month > 12 || day == 0 {
+            return None;
+        }
+
+        let tenth = core::cmp::min(tenth, 199) as u16;
+        Some(Self {
+            year: 1980 + (date >> 9),
+            month,
+            day,
+            hour: (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3F) as u8,
+            second: ((time & 0x1F) * 2 + tenth / 100) as u8,
+            millisecond: (tenth % 100) * 10,
+        })
+    }
+
+    /// Encodes to (date, time, tenth)
+    pub fn to_fat(&self) -> (u16, u16, u8) {
+        let date = ((self.year.saturating_sub(1980)) << 9) | ((self.month as u16) << 5) | self.day as u16;
+        let time = ((self.hour as u16) << 11) | ((self.minute as u16) << 5) | (self.second as u16 / 2);
+        let tenth = (self.second % 2) as u16 * 100 + self.millisecond / 10;
+        (date, time, tenth as u8)
+    }
+
+    /// Inverse of `to_unix_time`
+    pub fn from_unix_time(time: i64) -> Self {
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = time.div_euclid(86400) + 719_468;
+        let seconds = time.rem_euclid(86400);
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+            millisecond: 0,
+        }
+    }
+
+    /// Seconds since 1970-01-01 as if the time were UTC
+    pub fn to_unix_time(&self) -> i64 {
+        // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let (year, month) = if self.month <= 2 {
+            (self.year as i64 - 1, self.month as i64 + 9)
+        } else {
+            (self.year as i64, self.month as i64 - 3)
+        };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+}
+
+/// Position of the entry slots in the parent directory
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub(crate) struct Location {
+    /// The first cluster of the parent, 0 for the FAT12/16 root directory
+    pub dir: u32,
+    /// The first long name slot or the short entry one if there is no long name
+    pub first: usize,
+    /// The short entry slot
+    pub last: usize,
+}
+
+/// Directory entry with the long name already assembled
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct DirEntry {
+    /// Long name if present, the short one otherwise
+    pub name: String,
+    /// 8.3 name as `NAME.EXT`
+    pub short_name: String,
+    pub attributes: Attributes,
+    pub size: u32,
+    pub first_cluster: u32,
+    pub created: Option<DateTime>,
+    pub modified: Option<DateTime>,
+    pub accessed: Option<DateTime>,
+    pub(crate) location: Location,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.attributes.is_directory()
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.attributes.is_directory()
+    }
+
+    pub(crate) fn root(boot: &BootSector) -> Self {
+        Self {
+            name: String::new(),
+            short_name: String::new(),
+            attributes: Attributes(Attributes::DIRECTORY),
+            size: 0,
+            first_cluster: boot.root_cluster,
+            created: None,
+            modified: None,
+            accessed: None,
+            location: Location::default(),
+        }
+    }
+
+    pub(crate) fn is_root(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    pub(crate) fn from_record(record: &DirEntryRecord, long_name: Option<String>, location: Location) -> Self {
+        let short_name = short_name(record);
+        Self {
+            name: long_name.unwrap_or_else(|| short_name.clone()),
+            short_name,
+            attributes: Attributes(record.attributes),
+            size: record.file_size,
+            first_cluster: record.first_cluster(),
+            created: DateTime::from_fat(record.create_date, record.create_time, record.create_time_tenth),
+            modified: DateTime::from_fat(record.write_date, record.write_time, 0),
+            accessed: DateTime::from_fat(record.access_date, 0, 0),
+            location,
+        }
+    }
+}
+
+pub(crate) fn short_name_checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0_u8, |sum, &c| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c))
+}
+
+fn oem_string(bytes: &[u8], lowercase: bool) -> String {
+    bytes
+        .iter()
+        .map(|&c| if lowercase { c.to_ascii_lowercase() } else { c } as char)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+fn short_name(record: &DirEntryRecord) -> String {
+    let mut raw = record.name;
+    if raw[0] == KANJI_E5 {
+        raw[0] = DELETED;
+    }
+
+    let mut name = oem_string(&raw[..8], record.nt_flags & NT_LOWERCASE_BASE != 0);
+    let ext = oem_string(&raw[8..], record.nt_flags & NT_LOWERCASE_EXT != 0);
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(&ext);
+    }
+
+    name
+}
+
+/// Long name parts collected before the short entry
+struct LongName {
+    checksum: u8,
+    expected: u8,
+    chars: Vec<u16>,
+}
+
+impl LongName {
+    fn start(lfn: &LfnRecord) -> Self {
+        let count = lfn.order & LFN_ORDER_MASK;
+        Self {
+            checksum: lfn.checksum,
+            expected: count,
+            chars: vec![0xFFFF; count as usize * LFN_CHARS],
+        }
+    }
+
+    /// Returns false if the entry does not belong to the sequence
+    fn add(&mut self, lfn: &LfnRecord) -> bool {
+        let order = lfn.order & LFN_ORDER_MASK;
+        if order == 0 || order != self.expected || lfn.checksum != self.checksum {
+            return false;
+        }
+
+        let pos = (order as usize - 1) * LFN_CHARS;
+        let (name1, name2, name3) = (lfn.name1, lfn.name2, lfn.name3);
+        let parts = name1.iter().chain(name2.iter()).chain(name3.iter());
+        for (i, &c) in parts.enumerate() {
+            self.chars[pos + i] = c;
+        }
+
+        self.expected -= 1;
+        true
+    }
+
+    fn finish(self, short: &DirEntryRecord) -> Option<String> {
+        if self.expected != 0 || self.checksum != short_name_checksum(&short.name) {
+            return None;
+        }
+
+        let len = self.chars.iter().position(|&c| c == 0 || c == 0xFFFF).unwrap_or(self.chars.len());
+        Some(String::from_utf16_lossy(&self.chars[..len]))
+    }
+}
+
+/// Directory record found while scanning the raw directory data
+pub(crate) enum RawEntry {
+    Entry(DirEntry),
+    VolumeLabel(String),
+}
+
+/// Parses raw directory data, stops at the end of directory marker.
+///
+/// `.` and `..` entries are skipped.
+pub(crate) fn parse_entries(data: &[u8], dir: u32) -> Vec<RawEntry> {
+    let mut entries = Vec::new();
+    let mut long_name: Option<LongName> = None;
+    let mut long_name_start = 0;
+
+    for (index, raw) in data.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+        match raw[0] {
+            END_OF_DIRECTORY => break,
+            DELETED => {
+                long_name = None;
+                continue;
+            }
+            _ => (),
+        }
+
+        let record: DirEntryRecord = tools::read_struct(raw);
+        let attributes = Attributes(record.attributes);
+        if attributes.is_long_name() {
+            let lfn: LfnRecord = tools::read_struct(raw);
+            if lfn.order & LFN_LAST != 0 {
+                long_name = Some(LongName::start(&lfn));
+                long_name_start = index;
+            }
+
+            let valid = long_name.as_mut().map(|name| name.add(&lfn)).unwrap_or(false);
+            if !valid {
+                long_name = None; // orphaned entry
+            }
+            continue;
+        }
+
+        let name = long_name.take().and_then(|name| name.finish(&record));
+        let location = Location {
+            dir,
+            first: if name.is_some() { long_name_start } else { index },
+            last: index,
+        };
+
+        if attributes.is_volume_label() {
+            entries.push(RawEntry::VolumeLabel(oem_string(&record.name, false)));
+        } else if &record.name != DOT_NAME && &record.name != DOT_DOT_NAME {
+            entries.push(RawEntry::Entry(DirEntry::from_record(&record, name, location)));
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_test() {
+        assert_eq!(0x73, short_name_checksum(b"README  TXT"));
+        assert_eq!(0xD4, short_name_checksum(b"LONGFI~1TXT"));
+    }
+
+    #[test]
+    fn date_time_test() {
+        // 2021-07-14 13:45:31.120
+        let date = (41 << 9) | (7 << 5) | 14;
+        let time = (13 << 11) | (45 << 5) | 15;
+        let dt = DateTime::from_fat(date, time, 112).unwrap();
+        assert_eq!(
+            (2021, 7, 14, 13, 45, 31, 120),
+            (dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second, dt.millisecond)
+        );
+        assert_eq!((date, time, 112), dt.to_fat());
+        assert_eq!(1_626_270_331, dt.to_unix_time());
+
+        assert!(DateTime::from_fat(0, 0, 0).is_none());
+
+        let dt = DateTime::from_unix_time(1_626_270_331);
+        assert_eq!(
+            (2021, 7, 14, 13, 45, 31),
+            (dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second)
+        );
+        assert_eq!(1_709_164_800, DateTime::from_unix_time(1_709_164_800).to_unix_time());
+        // 2024-02-29
+    }
+
+    #[test]
+    fn lfn_records_test() {
+        let name: Vec<u16> = "Long file name.txt".encode_utf16().collect();
+        let checksum = short_name_checksum(b"LONGFI~1TXT");
+        let records = LfnRecord::for_name(&name, checksum);
+        assert_eq!(2, records.len());
+        assert_eq!(LFN_LAST | 2, records[0].order);
+        assert_eq!(1, records[1].order);
+
+        let mut data = vec![0_u8; 3 * DIR_ENTRY_SIZE];
+        tools::write_struct(&mut data[..], records[0]);
+        tools::write_struct(&mut data[DIR_ENTRY_SIZE..], records[1]);
+        tools::write_struct(
+            &mut data[2 * DIR_ENTRY_SIZE..],
+            DirEntryRecord::new(*b"LONGFI~1TXT", 0, Attributes::ARCHIVE, None),
+        );
+
+        match &parse_entries(&data, 7)[..] {
+            [RawEntry::Entry(entry)] => {
+                assert_eq!("Long file name.txt", entry.name);
+                assert_eq!(Location { dir: 7, first: 0, last: 2 }, entry.location);
+            }
+            _ => panic!("single entry expected"),
+        }
+    }
+}
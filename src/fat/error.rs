@@ -0,0 +1,44 @@
+use crate::xstd::String;
+
+#[derive(Debug)]
+pub enum FatError {
+    InvalidBootSector,
+    InvalidCluster(u32),
+    ClusterLoop(u32),    // chain start
+    TruncatedChain(u32), // chain start
+    NotADirectory(String),
+    NotAFile(String),
+    InvalidName(String),
+    AlreadyExists(String),
+    DirectoryNotEmpty(String),
+    DirectoryFull,
+    DiskFull,
+    FileTooLarge,
+    ClusterCountOutOfRange(super::FatKind, u32),
+}
+
+impl core::fmt::Display for FatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FatError::InvalidBootSector => f.write_str("Invalid FAT boot sector"),
+            FatError::InvalidCluster(cluster) => write!(f, "Invalid FAT cluster {}", cluster),
+            FatError::ClusterLoop(start) => write!(f, "FAT cluster chain starting at {} is looped", start),
+            FatError::TruncatedChain(start) => write!(f, "FAT cluster chain starting at {} is shorter than the file", start),
+            FatError::NotADirectory(path) => write!(f, "'{}' is not a directory", path),
+            FatError::NotAFile(path) => write!(f, "'{}' is not a file", path),
+            FatError::InvalidName(name) => write!(f, "'{}' is not a valid FAT name", name),
+            FatError::AlreadyExists(path) => write!(f, "'{}' already exists", path),
+            FatError::DirectoryNotEmpty(path) => write!(f, "'{}' is not empty", path),
+            FatError::DirectoryFull => f.write_str("FAT directory is full"),
+            FatError::DiskFull => f.write_str("No free FAT clusters"),
+            FatError::FileTooLarge => f.write_str("FAT files are limited to 4 GiB"),
+            FatError::ClusterCountOutOfRange(kind, count) => write!(f, "{} clusters do not fit {}", count, kind),
+        }
+    }
+}
+
+impl From<FatError> for crate::Error {
+    fn from(e: FatError) -> Self {
+        Self::Fat(e)
+    }
+}
@@ -0,0 +1,58 @@
+use super::*;
+
+/// Opened file
+pub struct File<'f, R: ReadAt> {
+    fs: &'f FatFileSystem<R>,
+    entry: DirEntry,
+    clusters: Vec<u32>,
+}
+
+impl<'f, R: ReadAt> File<'f, R> {
+    pub(crate) fn new(fs: &'f FatFileSystem<R>, entry: DirEntry, clusters: Vec<u32>) -> Result<Self> {
+        let allocated = clusters.len() as u64 * fs.boot_sector().cluster_size() as u64;
+        if allocated < entry.size as u64 {
+            return Err(Error::from(FatError::TruncatedChain(entry.first_cluster)));
+        }
+
+        Ok(Self { fs, entry, clusters })
+    }
+
+    pub fn entry(&self) -> &DirEntry {
+        &self.entry
+    }
+
+    pub fn size(&self) -> u64 {
+        self.entry.size as u64
+    }
+
+    pub fn clusters(&self) -> &[u32] {
+        &self.clusters
+    }
+}
+
+impl<'f, R: ReadAt> ReadAt for File<'f, R> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size(), offset, buffer.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::ReadBeyondEOD),
+        };
+
+        let boot = self.fs.boot_sector();
+        let cluster_size = boot.cluster_size() as u64;
+        let index = (offset / cluster_size) as usize;
+        let offset_in_cluster = offset % cluster_size;
+
+        // read contiguous clusters at once
+        let mut available = cluster_size - offset_in_cluster;
+        let mut next = index + 1;
+        while available < len as u64 && next < self.clusters.len() && self.clusters[next] == self.clusters[next - 1] + 1 {
+            available += cluster_size;
+            next += 1;
+        }
+
+        let len = core::cmp::min(len as u64, available) as usize;
+        let position = boot.cluster_offset(self.clusters[index]) + offset_in_cluster;
+        self.fs.device().read_at(position, &mut buffer[..len])
+    }
+}
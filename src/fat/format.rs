@@ -0,0 +1,442 @@
+//! FAT12/16/32 formatting, defaults follow fatgen103 and Windows `format`
+use super::boot::{BiosParameterBlock, ExtendedBpb16, ExtendedBpb32, BPB_SIZE, EXTENDED_BOOT_SIGNATURE};
+use super::dir::DirEntryRecord;
+use super::table::FatEntry;
+use super::*;
+use crate::sizes::{GIB, MIB};
+
+const FAT12_MAX_SIZE: u64 = 16 * MIB;
+const FAT16_MAX_SIZE: u64 = 512 * MIB;
+
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FS_INFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FS_INFO_TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+const FAT32_BACKUP_BOOT_SECTOR: u16 = 6;
+
+/// `hlt` followed by `jmp $-1`, non-bootable volumes just stop
+const BOOT_CODE: [u8; 3] = [0xF4, 0xEB, 0xFD];
+
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct FormatOptions {
+    /// Selected by the volume size if `None`
+    pub kind: Option<FatKind>,
+    /// Bytes per cluster, selected by the volume size if `None`
+    pub cluster_size: Option<u32>,
+    /// Up to 11 characters, converted to upper case
+    pub label: String,
+    pub oem_name: String,
+    /// Default is 32 for FAT32 and 1 otherwise
+    pub reserved_sectors: Option<u16>,
+    pub fat_count: u8,
+    /// FAT12/16 fixed root directory size
+    pub root_entries: u16,
+    /// FAT32 only
+    pub backup_boot_sector: bool,
+    /// Random if `None`
+    pub volume_id: Option<u32>,
+    pub media: u8,
+    /// Volume position on the disk in sectors
+    pub hidden_sectors: u32,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            kind: None,
+            cluster_size: None,
+            label: String::new(),
+            oem_name: "MSWIN4.1".to_string(),
+            reserved_sectors: None,
+            fat_count: 2,
+            root_entries: 512,
+            backup_boot_sector: true,
+            volume_id: None,
+            media: 0xF8,
+            hidden_sectors: 0,
+        }
+    }
+}
+
+fn default_kind(size: u64) -> FatKind {
+    if size <= FAT12_MAX_SIZE {
+        FatKind::Fat12
+    } else if size <= FAT16_MAX_SIZE {
+        FatKind::Fat16
+    } else {
+        FatKind::Fat32
+    }
+}
+
+fn default_cluster_size(kind: FatKind, size: u64) -> u32 {
+    let table: &[(u64, u32)] = match kind {
+        FatKind::Fat12 => return 0,
+        FatKind::Fat16 => &[
+            (16 * MIB, 1024),
+            (128 * MIB, 2048),
+            (256 * MIB, 4096),
+            (512 * MIB, 8192),
+            (GIB, 16384),
+            (2 * GIB, 32768),
+        ],
+        FatKind::Fat32 => &[(260 * MIB, 512), (8 * GIB, 4096), (16 * GIB, 8192), (32 * GIB, 16384)],
+    };
+
+    let largest = if kind == FatKind::Fat16 { 65536 } else { 32768 };
+    table
+        .iter()
+        .find(|(limit, _)| size <= *limit)
+        .map(|(_, cluster_size)| *cluster_size)
+        .unwrap_or(largest)
+}
+
+fn label_bytes(label: &str) -> Result<Option<[u8; 11]>> {
+    if label.is_empty() {
+        return Ok(None);
+    }
+
+    let valid = label.len() <= 11
+        && !label.starts_with(' ')
+        && label
+            .bytes()
+            .all(|c| c == b' ' || c.is_ascii_alphanumeric() || b"$%'-_@~`!(){}^#&".contains(&c));
+    if !valid {
+        return Err(Error::from(FatError::InvalidName(label.to_string())));
+    }
+
+    let mut bytes = [b' '; 11];
+    for (b, c) in bytes.iter_mut().zip(label.bytes()) {
+        *b = c.to_ascii_uppercase();
+    }
+    Ok(Some(bytes))
+}
+
+struct Layout {
+    sectors_per_cluster: u32,
+    reserved_sectors: u32,
+    fat_size: u32,
+    cluster_count: u32,
+}
+
+fn layout(kind: FatKind, options: &FormatOptions, total_sectors: u32, sector_size: u32, cluster_size: u32) -> Layout {
+    let reserved_sectors = options.reserved_sectors.map(|r| r as u32).unwrap_or(match kind {
+        FatKind::Fat32 => 32,
+        _ => 1,
+    });
+    let root_dir_sectors = match kind {
+        FatKind::Fat32 => 0,
+        _ => math::ceil(options.root_entries as u32 * DIR_ENTRY_SIZE as u32, sector_size),
+    };
+    let entry_bits = match kind {
+        FatKind::Fat12 => 12,
+        FatKind::Fat16 => 16,
+        FatKind::Fat32 => 32,
+    };
+    let sectors_per_cluster = core::cmp::max(cluster_size / sector_size, 1);
+    let fat_count = options.fat_count as u64;
+
+    // the FAT size depends on the cluster count and vice versa, a few iterations converge
+    let mut fat_size = 1_u64;
+    loop {
+        let used = reserved_sectors as u64 + fat_count * fat_size + root_dir_sectors as u64;
+        let cluster_count = (total_sectors as u64).saturating_sub(used) / sectors_per_cluster as u64;
+        let needed = math::ceil((cluster_count + 2) * entry_bits, sector_size as u64 * 8);
+        if needed <= fat_size {
+            return Layout {
+                sectors_per_cluster,
+                reserved_sectors,
+                fat_size: fat_size as u32,
+                cluster_count: cluster_count as u32,
+            };
+        }
+        fat_size = needed;
+    }
+}
+
+/// Creates an empty FAT filesystem on the whole disk (or partition)
+pub fn format(disk: &impl Disk, options: &FormatOptions) -> Result<BootSector> {
+    let sector_size = disk.logical_sector_size()?;
+    let capacity = disk.capacity()?;
+    let total_sectors = core::cmp::min(capacity / sector_size as u64, u32::MAX as u64) as u32;
+
+    let kind = options.kind.unwrap_or_else(|| default_kind(capacity));
+    let layout = match options.cluster_size {
+        Some(cluster_size) => layout(kind, options, total_sectors, sector_size, cluster_size),
+        None if kind == FatKind::Fat12 => {
+            // the smallest cluster giving a valid FAT12 cluster count
+            let mut cluster_size = sector_size;
+            loop {
+                let layout = layout(kind, options, total_sectors, sector_size, cluster_size);
+                if layout.cluster_count < 4085 || layout.sectors_per_cluster >= 128 {
+                    break layout;
+                }
+                cluster_size *= 2;
+            }
+        }
+        None => layout(kind, options, total_sectors, sector_size, default_cluster_size(kind, capacity)),
+    };
+
+    // the FAT32 FSInfo sector and the backup boot sector with its FSInfo copy are in the reserved area
+    let min_reserved_sectors = match kind {
+        FatKind::Fat32 if options.backup_boot_sector => FAT32_BACKUP_BOOT_SECTOR as u32 + 2,
+        FatKind::Fat32 => 2,
+        _ => 1,
+    };
+    let cluster_size = layout.sectors_per_cluster * sector_size;
+    let valid = layout.sectors_per_cluster.is_power_of_two()
+        && layout.sectors_per_cluster <= 128
+        && cluster_size <= 64 * 1024
+        && options.fat_count != 0
+        && layout.reserved_sectors >= min_reserved_sectors;
+    if !valid {
+        return Err(Error::from(FatError::InvalidBootSector));
+    }
+
+    let valid_count = match kind {
+        FatKind::Fat32 => layout.cluster_count <= 0x0FFF_FFF5,
+        _ => true,
+    };
+    if layout.cluster_count == 0 || FatKind::from_cluster_count(layout.cluster_count) != kind || !valid_count {
+        return Err(Error::from(FatError::ClusterCountOutOfRange(kind, layout.cluster_count)));
+    }
+
+    let label = label_bytes(&options.label)?;
+    let volume_id = options.volume_id.unwrap_or_else(|| {
+        let id = Uuid::new_v4();
+        let bytes = id.as_bytes();
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    });
+
+    let geometry = disk.geometry()?;
+    let mut oem_name = [b' '; 8];
+    for (b, c) in oem_name.iter_mut().zip(options.oem_name.bytes()) {
+        *b = c;
+    }
+
+    let small = total_sectors <= u16::MAX as u32 && kind != FatKind::Fat32;
+    let bpb = BiosParameterBlock {
+        jump: match kind {
+            FatKind::Fat32 => [0xEB, 0x58, 0x90],
+            _ => [0xEB, 0x3C, 0x90],
+        },
+        oem_name,
+        bytes_per_sector: sector_size as u16,
+        sectors_per_cluster: layout.sectors_per_cluster as u8,
+        reserved_sectors: layout.reserved_sectors as u16,
+        fat_count: options.fat_count,
+        root_entries: if kind == FatKind::Fat32 { 0 } else { options.root_entries },
+        total_sectors_16: if small { total_sectors as u16 } else { 0 },
+        media: options.media,
+        fat_size_16: if kind == FatKind::Fat32 { 0 } else { layout.fat_size as u16 },
+        sectors_per_track: core::cmp::min(geometry.sectors_per_track, u16::MAX as u32) as u16,
+        heads: core::cmp::min(geometry.heads_per_cylinder, u16::MAX as u32) as u16,
+        hidden_sectors: options.hidden_sectors,
+        total_sectors_32: if small { 0 } else { total_sectors },
+    };
+
+    let volume_label = label.unwrap_or(*b"NO NAME    ");
+    let mut sector = vec![0_u8; sector_size as usize];
+    tools::write_struct(&mut sector[..], bpb);
+    let code_offset = match kind {
+        FatKind::Fat32 => {
+            tools::write_struct(
+                &mut sector[BPB_SIZE..],
+                ExtendedBpb32 {
+                    fat_size_32: layout.fat_size,
+                    ext_flags: 0,
+                    fs_version: 0,
+                    root_cluster: FIRST_CLUSTER,
+                    fs_info: 1,
+                    backup_boot_sector: if options.backup_boot_sector { FAT32_BACKUP_BOOT_SECTOR } else { 0 },
+                    reserved: [0; 12],
+                    drive_number: 0x80,
+                    reserved1: 0,
+                    boot_signature: EXTENDED_BOOT_SIGNATURE,
+                    volume_id,
+                    volume_label,
+                    fs_type: *b"FAT32   ",
+                },
+            );
+            BPB_SIZE + core::mem::size_of::<ExtendedBpb32>()
+        }
+        _ => {
+            tools::write_struct(
+                &mut sector[BPB_SIZE..],
+                ExtendedBpb16 {
+                    drive_number: 0x80,
+                    reserved: 0,
+                    boot_signature: EXTENDED_BOOT_SIGNATURE,
+                    volume_id,
+                    volume_label,
+                    fs_type: if kind == FatKind::Fat12 { *b"FAT12   " } else { *b"FAT16   " },
+                },
+            );
+            BPB_SIZE + core::mem::size_of::<ExtendedBpb16>()
+        }
+    };
+    sector[code_offset..code_offset + BOOT_CODE.len()].copy_from_slice(&BOOT_CODE);
+    sector[510] = 0x55;
+    sector[511] = 0xAA;
+
+    let boot = BootSector::parse(&sector)?;
+
+    // reserved sectors, FATs and the fixed root directory (or the FAT32 root cluster)
+    let system_area = match kind {
+        FatKind::Fat32 => boot.cluster_offset(FIRST_CLUSTER) + cluster_size as u64,
+        _ => boot.cluster_offset(FIRST_CLUSTER),
+    };
+    tools::write_zeroes(disk, 0, system_area)?;
+
+    disk.write_all_at(0, &sector)?;
+    if kind == FatKind::Fat32 {
+        let mut fs_info = vec![0_u8; sector_size as usize];
+        fs_info[0..4].copy_from_slice(&FS_INFO_LEAD_SIGNATURE.to_le_bytes());
+        fs_info[484..488].copy_from_slice(&FS_INFO_STRUCT_SIGNATURE.to_le_bytes());
+        fs_info[488..492].copy_from_slice(&(layout.cluster_count - 1).to_le_bytes());
+        fs_info[492..496].copy_from_slice(&(FIRST_CLUSTER + 1).to_le_bytes());
+        fs_info[508..512].copy_from_slice(&FS_INFO_TRAIL_SIGNATURE.to_le_bytes());
+        disk.write_all_at(sector_size as u64, &fs_info)?;
+
+        if options.backup_boot_sector {
+            let backup = FAT32_BACKUP_BOOT_SECTOR as u64 * sector_size as u64;
+            disk.write_all_at(backup, &sector)?;
+            disk.write_all_at(backup + sector_size as u64, &fs_info)?;
+        }
+    }
+
+    // FAT[0] holds the media byte, FAT[1] is the end of chain marker
+    let head: &[u8] = match kind {
+        FatKind::Fat12 => &[options.media, 0xFF, 0xFF],
+        FatKind::Fat16 => &[options.media, 0xFF, 0xFF, 0xFF],
+        FatKind::Fat32 => &[options.media, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F],
+    };
+    for copy in 0..boot.fat_count {
+        disk.write_all_at(boot.fat_offset(copy), head)?;
+    }
+    if kind == FatKind::Fat32 {
+        table::write_entry(disk, &boot, FIRST_CLUSTER, FatEntry::End)?;
+    }
+
+    if let Some(label) = label {
+        let root = match kind {
+            FatKind::Fat32 => boot.cluster_offset(FIRST_CLUSTER),
+            _ => boot.root_dir_region().0,
+        };
+        let mut raw = [0_u8; DIR_ENTRY_SIZE];
+        tools::write_struct(&mut raw[..], DirEntryRecord::new(label, 0, Attributes::VOLUME_ID, None));
+        disk.write_all_at(root, &raw)?;
+    }
+
+    disk.flush()?;
+    Ok(boot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+
+    fn formatted(size: usize, options: &FormatOptions) -> FatFileSystem<MemoryDisk> {
+        let disk = MemoryDisk::from_vec(vec![0xAA_u8; size]);
+        format(&disk, options).unwrap();
+        FatFileSystem::open(disk).unwrap()
+    }
+
+    #[test]
+    fn default_layout_test() {
+        assert_eq!(FatKind::Fat12, default_kind(MIB));
+        assert_eq!(FatKind::Fat16, default_kind(64 * MIB));
+        assert_eq!(FatKind::Fat32, default_kind(GIB));
+        assert_eq!(2048, default_cluster_size(FatKind::Fat16, 64 * MIB));
+        assert_eq!(65536, default_cluster_size(FatKind::Fat16, 4 * GIB));
+        assert_eq!(4096, default_cluster_size(FatKind::Fat32, GIB));
+        assert_eq!(32768, default_cluster_size(FatKind::Fat32, 64 * GIB));
+    }
+
+    #[test]
+    fn format_test() {
+        let options = FormatOptions {
+            label: "test vol".to_string(),
+            volume_id: Some(0x1234_5678),
+            ..Default::default()
+        };
+
+        let fs = formatted(MIB as usize, &options);
+        assert_eq!(FatKind::Fat12, fs.kind());
+        assert_eq!("TEST VOL", fs.volume_label());
+        assert_eq!(Some(0x1234_5678), fs.volume_id());
+        assert_eq!(fs.boot_sector().cluster_count, fs.free_clusters().unwrap());
+        assert!(fs.read_dir("/").unwrap().is_empty());
+
+        let fs = formatted(32 * MIB as usize, &options);
+        assert_eq!(FatKind::Fat16, fs.kind());
+        assert_eq!(2048, fs.boot_sector().cluster_size());
+        assert_eq!(fs.boot_sector().cluster_count, fs.free_clusters().unwrap());
+
+        let options = FormatOptions {
+            kind: Some(FatKind::Fat32),
+            ..options
+        };
+        let fs = formatted(64 * MIB as usize, &options);
+        assert_eq!(FatKind::Fat32, fs.kind());
+        assert_eq!("TEST VOL", fs.volume_label());
+        let free = fs.boot_sector().cluster_count - 1; // root directory
+        assert_eq!(free, fs.free_clusters().unwrap());
+        assert_eq!(Some(free), fs.fs_info().and_then(|info| info.free_count));
+        assert!(fs.read_dir("/").unwrap().is_empty());
+
+        // the backup boot sector is a copy of the primary one
+        let image = fs.device().to_vec();
+        assert_eq!(&image[..512], &image[6 * 512..7 * 512]);
+        assert_eq!(&image[512..1024], &image[7 * 512..8 * 512]);
+    }
+
+    #[test]
+    fn format_errors_test() {
+        let disk = MemoryDisk::from_vec(vec![0_u8; MIB as usize]);
+        let options = FormatOptions {
+            kind: Some(FatKind::Fat32),
+            ..Default::default()
+        };
+        match format(&disk, &options) {
+            Err(Error::Fat(FatError::ClusterCountOutOfRange(FatKind::Fat32, _))) => (),
+            _ => panic!("cluster count out of range expected"),
+        }
+
+        let options = FormatOptions {
+            label: "too long label".to_string(),
+            ..Default::default()
+        };
+        match format(&disk, &options) {
+            Err(Error::Fat(FatError::InvalidName(_))) => (),
+            _ => panic!("invalid name expected"),
+        }
+    }
+
+    #[test]
+    fn fat32_reserved_sectors_test() {
+        let disk = MemoryDisk::from_vec(vec![0_u8; 64 * MIB as usize]);
+        let options = |reserved_sectors, backup_boot_sector| FormatOptions {
+            kind: Some(FatKind::Fat32),
+            reserved_sectors: Some(reserved_sectors),
+            backup_boot_sector,
+            ..Default::default()
+        };
+
+        // the FSInfo sector, the backup boot sector and its FSInfo copy would go into the FAT
+        for (reserved_sectors, backup_boot_sector) in [(1, false), (1, true), (7, true)].iter() {
+            match format(&disk, &options(*reserved_sectors, *backup_boot_sector)) {
+                Err(Error::Fat(FatError::InvalidBootSector)) => (),
+                _ => panic!("invalid boot sector expected"),
+            }
+        }
+
+        for (reserved_sectors, backup_boot_sector) in [(2, false), (8, true)].iter() {
+            let boot = format(&disk, &options(*reserved_sectors, *backup_boot_sector)).unwrap();
+            assert_eq!(*reserved_sectors as u32, boot.reserved_sectors);
+            let fs = FatFileSystem::open(&disk).unwrap();
+            assert_eq!(boot.cluster_count - 1, fs.free_clusters().unwrap());
+            assert!(fs.fs_info().is_some());
+        }
+    }
+}
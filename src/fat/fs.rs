@@ -0,0 +1,394 @@
+use super::dir::{parse_entries, RawEntry};
+use super::*;
+
+const NO_NAME: &str = "NO NAME";
+
+/// FAT12/16/32 volume
+pub struct FatFileSystem<R: ReadAt> {
+    pub(crate) device: R,
+    pub(crate) boot: BootSector,
+    pub(crate) fs_info: Option<FsInfoSector>,
+    label: String,
+    time: Option<DateTime>,
+}
+
+/// Raw directory content
+pub(crate) struct Directory {
+    /// 0 for the FAT12/16 root directory
+    pub first_cluster: u32,
+    /// Empty for the FAT12/16 root directory
+    pub clusters: Vec<u32>,
+    pub data: Vec<u8>,
+}
+
+impl Directory {
+    pub fn entries(&self) -> Vec<DirEntry> {
+        parse_entries(&self.data, self.first_cluster)
+            .into_iter()
+            .filter_map(|entry| match entry {
+                RawEntry::Entry(entry) => Some(entry),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn same_name(a: &str, b: &str) -> bool {
+    a.chars().flat_map(char::to_lowercase).eq(b.chars().flat_map(char::to_lowercase))
+}
+
+impl<R: ReadAt> FatFileSystem<R> {
+    pub fn open(device: R) -> Result<Self> {
+        let mut sector = [0_u8; 512];
+        device.read_exact_at(0, &mut sector)?;
+        let boot = BootSector::parse(&sector)?;
+
+        let fs_info = if boot.kind == FatKind::Fat32 && boot.fs_info_sector != 0 {
+            let mut buffer = vec![0_u8; boot.bytes_per_sector as usize];
+            device.read_exact_at(boot.fs_info_sector as u64 * boot.bytes_per_sector as u64, &mut buffer)?;
+            FsInfoSector::parse(&buffer)
+        } else {
+            None
+        };
+
+        let mut fs = Self {
+            device,
+            label: String::new(),
+            boot,
+            fs_info,
+            time: None,
+        };
+
+        // the root directory label wins over the boot sector one
+        let root = fs.load_directory(fs.boot.root_cluster)?;
+        let label = parse_entries(&root.data, root.first_cluster)
+            .into_iter()
+            .find_map(|entry| match entry {
+                RawEntry::VolumeLabel(label) => Some(label),
+                _ => None,
+            });
+        fs.label = match label {
+            Some(label) => label,
+            None if fs.boot.volume_label != NO_NAME => fs.boot.volume_label.clone(),
+            None => String::new(),
+        };
+
+        Ok(fs)
+    }
+
+    pub fn device(&self) -> &R {
+        &self.device
+    }
+
+    pub fn into_inner(self) -> R {
+        self.device
+    }
+
+    pub fn boot_sector(&self) -> &BootSector {
+        &self.boot
+    }
+
+    pub fn kind(&self) -> FatKind {
+        self.boot.kind
+    }
+
+    pub fn volume_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn volume_id(&self) -> Option<u32> {
+        self.boot.volume_id
+    }
+
+    /// FAT32 FSInfo hints, may be stale
+    pub fn fs_info(&self) -> Option<&FsInfoSector> {
+        self.fs_info.as_ref()
+    }
+
+    pub fn root(&self) -> DirEntry {
+        DirEntry::root(&self.boot)
+    }
+
+    /// Timestamp for the new and modified entries.
+    ///
+    /// Without it the current system time (as UTC) is used in `std` builds, and no time is stored otherwise.
+    pub fn set_current_time(&mut self, time: Option<DateTime>) {
+        self.time = time;
+    }
+
+    pub(crate) fn now(&self) -> Option<DateTime> {
+        #[cfg(feature = "std")]
+        {
+            self.time.or_else(|| {
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok()?;
+                Some(DateTime::from_unix_time(now.as_secs() as i64))
+            })
+        }
+
+        #[cfg(not(feature = "std"))]
+        {
+            self.time
+        }
+    }
+
+    /// Counts free clusters scanning the whole FAT
+    pub fn free_clusters(&self) -> Result<u32> {
+        let mut free = 0;
+        table::scan(&self.device, &self.boot, FIRST_CLUSTER, |_, entry| {
+            if entry == table::FatEntry::Free {
+                free += 1;
+            }
+            true
+        })?;
+
+        Ok(free)
+    }
+
+    /// Reads the whole directory content, `first_cluster` is 0 for the root directory
+    pub(crate) fn load_directory(&self, first_cluster: u32) -> Result<Directory> {
+        // `..` entries use 0 for the root directory
+        let first_cluster = match first_cluster {
+            0 => self.boot.root_cluster,
+            cluster => cluster,
+        };
+
+        if first_cluster == 0 {
+            let (offset, size) = self.boot.root_dir_region();
+            let mut data = vec![0_u8; size as usize];
+            self.device.read_exact_at(offset, &mut data)?;
+            return Ok(Directory {
+                first_cluster,
+                clusters: Vec::new(),
+                data,
+            });
+        }
+
+        let clusters = table::read_chain(&self.device, &self.boot, first_cluster)?;
+        let cluster_size = self.boot.cluster_size() as usize;
+        let mut data = vec![0_u8; clusters.len() * cluster_size];
+        for (cluster, chunk) in clusters.iter().zip(data.chunks_exact_mut(cluster_size)) {
+            self.device.read_exact_at(self.boot.cluster_offset(*cluster), chunk)?;
+        }
+
+        Ok(Directory {
+            first_cluster,
+            clusters,
+            data,
+        })
+    }
+
+    /// Lists the directory, `.` and `..` are skipped
+    pub fn read_dir_entry(&self, dir: &DirEntry) -> Result<Vec<DirEntry>> {
+        if !dir.is_dir() {
+            return Err(Error::from(FatError::NotADirectory(dir.name.clone())));
+        }
+
+        Ok(self.load_directory(dir.first_cluster)?.entries())
+    }
+
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let dir = self.entry(path)?;
+        self.read_dir_entry(&dir)
+    }
+
+    /// Looks the path up, `/` and `\` separators are accepted, names are case insensitive
+    pub fn entry(&self, path: &str) -> Result<DirEntry> {
+        let mut stack = vec![self.root()];
+        for name in path.split(&['/', '\\'][..]) {
+            match name {
+                "" | "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+
+            let dir = stack.last().expect("root is always present");
+            if !dir.is_dir() {
+                return Err(Error::from(FatError::NotADirectory(path.to_string())));
+            }
+
+            let entry = self
+                .read_dir_entry(dir)?
+                .into_iter()
+                .find(|entry| same_name(&entry.name, name) || same_name(&entry.short_name, name))
+                .ok_or_else(|| Error::NotFound(path.to_string()))?;
+            stack.push(entry);
+        }
+
+        Ok(stack.pop().expect("root is always present"))
+    }
+
+    pub fn open_entry(&self, entry: &DirEntry) -> Result<File<'_, R>> {
+        if entry.is_dir() {
+            return Err(Error::from(FatError::NotAFile(entry.name.clone())));
+        }
+
+        let clusters = table::read_chain(&self.device, &self.boot, entry.first_cluster)?;
+        File::new(self, entry.clone(), clusters)
+    }
+
+    pub fn open_file(&self, path: &str) -> Result<File<'_, R>> {
+        let entry = self.entry(path)?;
+        self.open_entry(&entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+
+    fn put_u16(image: &mut [u8], pos: usize, value: u16) {
+        image[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_entry(image: &mut [u8], pos: usize, name: &[u8; 11], attributes: u8, nt_flags: u8, cluster: u16, size: u32) {
+        image[pos..pos + 11].copy_from_slice(name);
+        image[pos + 11] = attributes;
+        image[pos + 12] = nt_flags;
+        put_u16(image, pos + 24, (41 << 9) | (7 << 5) | 14); // write date
+        put_u16(image, pos + 26, cluster);
+        image[pos + 28..pos + 32].copy_from_slice(&size.to_le_bytes());
+    }
+
+    fn put_lfn(image: &mut [u8], pos: usize, order: u8, checksum: u8, chars: &[u16]) {
+        const OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+        image[pos] = order;
+        image[pos + 11] = Attributes::LONG_NAME;
+        image[pos + 13] = checksum;
+        for (i, offset) in OFFSETS.iter().enumerate() {
+            let c = match i {
+                i if i < chars.len() => chars[i],
+                i if i == chars.len() => 0,
+                _ => 0xFFFF,
+            };
+            put_u16(image, pos + offset, c);
+        }
+    }
+
+    /// 32 KiB FAT12 volume: a long named file of 2 clusters in the root and a subdirectory with a small file
+    pub(crate) fn fat12_image() -> Vec<u8> {
+        let mut image = vec![0_u8; 64 * 512];
+        image[..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        image[3..11].copy_from_slice(b"MSDOS5.0");
+        put_u16(&mut image, 11, 512);
+        image[13] = 1; // sectors per cluster
+        put_u16(&mut image, 14, 1); // reserved
+        image[16] = 2; // FATs
+        put_u16(&mut image, 17, 16); // root entries
+        put_u16(&mut image, 19, 64); // total sectors
+        image[21] = 0xF8;
+        put_u16(&mut image, 22, 1); // FAT size
+        image[38] = 0x29;
+        image[39..43].copy_from_slice(&0x1234_ABCD_u32.to_le_bytes());
+        image[43..54].copy_from_slice(b"NO NAME    ");
+        image[54..62].copy_from_slice(b"FAT12   ");
+        image[510] = 0x55;
+        image[511] = 0xAA;
+
+        // clusters: 2 -> 3, 4 (SUB), 5 (a.txt)
+        let fat = [0xF8, 0xFF, 0xFF, 0x03, 0xF0, 0xFF, 0xFF, 0xFF, 0xFF];
+        image[512..512 + fat.len()].copy_from_slice(&fat);
+        image[1024..1024 + fat.len()].copy_from_slice(&fat);
+
+        let root = 3 * 512;
+        put_entry(&mut image, root, b"TESTVOL    ", Attributes::VOLUME_ID, 0, 0, 0);
+        let long_name: Vec<u16> = "Long file name.txt".encode_utf16().collect();
+        let checksum = dir::short_name_checksum(b"LONGFI~1TXT");
+        put_lfn(&mut image, root + 32, dir::LFN_LAST | 2, checksum, &long_name[13..]);
+        put_lfn(&mut image, root + 64, 1, checksum, &long_name[..13]);
+        put_entry(&mut image, root + 96, b"LONGFI~1TXT", Attributes::ARCHIVE, 0, 2, 600);
+        put_entry(&mut image, root + 128, b"SUB        ", Attributes::DIRECTORY, 0, 4, 0);
+        put_entry(&mut image, root + 160, b"\xE5ELETED TXT", Attributes::ARCHIVE, 0, 0, 0);
+
+        let sub = 4 * 512 + 2 * 512;
+        put_entry(&mut image, sub, b".          ", Attributes::DIRECTORY, 0, 4, 0);
+        put_entry(&mut image, sub + 32, b"..         ", Attributes::DIRECTORY, 0, 0, 0);
+        put_entry(&mut image, sub + 64, b"A       TXT", Attributes::ARCHIVE, 0x18, 5, 5);
+
+        for (i, b) in image[4 * 512..5 * 512].iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        for (i, b) in image[5 * 512..6 * 512].iter_mut().enumerate() {
+            *b = !(i as u8);
+        }
+        image[7 * 512..7 * 512 + 5].copy_from_slice(b"hello");
+
+        image
+    }
+
+    #[test]
+    fn fat12_read_test() {
+        let fs = FatFileSystem::open(MemoryDisk::from_vec(fat12_image())).unwrap();
+        assert_eq!(FatKind::Fat12, fs.kind());
+        assert_eq!("TESTVOL", fs.volume_label());
+        assert_eq!(Some(0x1234_ABCD), fs.volume_id());
+
+        let root = fs.read_dir("/").unwrap();
+        assert_eq!(2, root.len());
+        assert_eq!("Long file name.txt", root[0].name);
+        assert_eq!("LONGFI~1.TXT", root[0].short_name);
+        assert_eq!(Some(2021), root[0].modified.map(|t| t.year));
+        assert!(root[1].is_dir());
+
+        let file = fs.open_file("LONG FILE NAME.TXT").unwrap();
+        assert_eq!(&[2, 3], file.clusters());
+        let mut data = vec![0_u8; 600];
+        file.read_exact_at(0, &mut data).unwrap();
+        assert_eq!(0x00, data[0]);
+        assert_eq!(0xFF, data[255]);
+        assert_eq!(0xFF, data[512]);
+        assert_eq!(0, file.read_at(600, &mut data).unwrap());
+
+        let entries = fs.read_dir("sub").unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!("a.txt", entries[0].name);
+
+        let file = fs.open_file("\\SUB\\..\\sub\\A.TXT").unwrap();
+        let mut data = vec![0_u8; 5];
+        file.read_exact_at(0, &mut data).unwrap();
+        assert_eq!(b"hello", data.as_slice());
+
+        match fs.open_file("/sub/missing") {
+            Err(Error::NotFound(_)) => (),
+            _ => panic!("not found expected"),
+        }
+        match fs.open_file("/sub") {
+            Err(Error::Fat(FatError::NotAFile(_))) => (),
+            _ => panic!("not a file expected"),
+        }
+    }
+
+    #[test]
+    fn corrupt_boot_sector_test() {
+        let fat32 = |fat_count: u8, fat_size: u32, ext_flags: u16| {
+            let mut image = fat12_image();
+            image[16] = fat_count;
+            put_u16(&mut image, 17, 0); // root entries
+            put_u16(&mut image, 19, 0);
+            image[32..36].copy_from_slice(&u32::MAX.to_le_bytes()); // total sectors
+            put_u16(&mut image, 22, 0);
+            image[36..40].copy_from_slice(&fat_size.to_le_bytes());
+            put_u16(&mut image, 40, ext_flags);
+            image[44..48].copy_from_slice(&2_u32.to_le_bytes()); // root cluster
+            image
+        };
+
+        // the FATs size overflows u32 sectors
+        for (fat_count, fat_size) in [(2, 0x8000_0000), (255, 0x0200_0000), (1, u32::MAX)].iter() {
+            match BootSector::parse(&fat32(*fat_count, *fat_size, 0)) {
+                Err(Error::Fat(FatError::InvalidBootSector)) => (),
+                _ => panic!("invalid boot sector expected"),
+            }
+        }
+
+        // the active FAT past the FAT count
+        assert!(BootSector::parse(&fat32(2, 0x0200_0000, 0)).is_ok());
+        assert!(BootSector::parse(&fat32(2, 0x0200_0000, 0x81)).is_ok());
+        assert!(BootSector::parse(&fat32(2, 0x0200_0000, 0x82)).is_err());
+    }
+}
@@ -0,0 +1,32 @@
+//! FAT12/16/32 filesystem
+//!
+//! See "Microsoft Extensible Firmware Initiative FAT32 File System Specification" (fatgen103)
+use crate::prelude::*;
+use crate::tools::le_u32;
+
+mod error;
+pub use error::FatError;
+
+mod boot;
+pub use boot::{BootSector, FatKind, FsInfoSector};
+
+mod table;
+
+mod dir;
+pub use dir::{Attributes, DateTime, DirEntry};
+
+mod file;
+pub use file::File;
+
+mod fs;
+pub use fs::FatFileSystem;
+
+mod name;
+mod write;
+
+mod format;
+pub use format::{format, FormatOptions};
+
+const DIR_ENTRY_SIZE: usize = 32;
+const FIRST_CLUSTER: u32 = 2;
+
@@ -0,0 +1,170 @@
+//! Long name validation and 8.3 short name generation
+use super::dir::{NT_LOWERCASE_BASE, NT_LOWERCASE_EXT};
+use super::*;
+
+const MAX_LONG_NAME: usize = 255;
+const INVALID_CHARS: &str = "\"*/:<>?\\|";
+
+fn is_short_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"$%'-_@~`!(){}^#&".contains(&c)
+}
+
+/// Returns UTF-16 long name
+pub(crate) fn validate_long_name(name: &str) -> Result<Vec<u16>> {
+    let chars: Vec<u16> = name.encode_utf16().collect();
+    let valid = !chars.is_empty()
+        && chars.len() <= MAX_LONG_NAME
+        && name != "."
+        && name != ".."
+        && !name.ends_with(' ')
+        && !name.ends_with('.')
+        && !name.chars().any(|c| (c as u32) < 0x20 || INVALID_CHARS.contains(c));
+
+    if !valid {
+        return Err(Error::from(FatError::InvalidName(name.to_string())));
+    }
+
+    Ok(chars)
+}
+
+fn pack(base: &[u8], ext: &[u8]) -> [u8; 11] {
+    let mut name = [b' '; 11];
+    name[..base.len()].copy_from_slice(base);
+    name[8..8 + ext.len()].copy_from_slice(ext);
+    if name[0] == dir::DELETED {
+        name[0] = 0x05;
+    }
+
+    name
+}
+
+/// Case of the whole name part: Some(true) if lowercase, Some(false) if uppercase or caseless, None if mixed
+fn part_case(part: &[u8]) -> Option<bool> {
+    let lower = part.iter().any(u8::is_ascii_lowercase);
+    let upper = part.iter().any(u8::is_ascii_uppercase);
+    match (lower, upper) {
+        (true, true) => None,
+        (lower, _) => Some(lower),
+    }
+}
+
+/// Returns the 8.3 name and NT case flags if the name can be stored without a long name
+pub(crate) fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let bytes = name.as_bytes();
+    let (base, ext) = match bytes.iter().position(|&c| c == b'.') {
+        Some(dot) => (&bytes[..dot], &bytes[dot + 1..]),
+        None => (bytes, &bytes[bytes.len()..]),
+    };
+
+    let fits = !base.is_empty()
+        && base.len() <= 8
+        && ext.len() <= 3
+        && ext.is_empty() != bytes.contains(&b'.')
+        && base.iter().chain(ext.iter()).all(|&c| is_short_char(c));
+    if !fits {
+        return None;
+    }
+
+    let mut flags = 0;
+    if part_case(base)? {
+        flags |= NT_LOWERCASE_BASE;
+    }
+    if part_case(ext)? {
+        flags |= NT_LOWERCASE_EXT;
+    }
+
+    Some((pack(&base.to_ascii_uppercase(), &ext.to_ascii_uppercase()), flags))
+}
+
+/// Converts a long name part to short name characters, returns true if something was lost
+fn to_short_chars(part: &str, out: &mut Vec<u8>) -> bool {
+    let mut lossy = false;
+    for c in part.chars() {
+        match c {
+            '.' | ' ' => lossy = true,
+            c if c.is_ascii() && is_short_char(c as u8) => out.push(c.to_ascii_uppercase() as u8),
+            _ => {
+                lossy = true;
+                out.push(b'_');
+            }
+        }
+    }
+
+    lossy
+}
+
+/// Generates a unique 8.3 name with a `~N` numeric tail as Windows does
+pub(crate) fn generate_short_name(name: &str, exists: impl Fn(&[u8; 11]) -> bool) -> Result<[u8; 11]> {
+    let mut lossy = name.starts_with('.');
+    let name = name.trim_start_matches('.');
+    let (base_part, ext_part) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+
+    let mut base = Vec::new();
+    let mut ext = Vec::new();
+    lossy |= to_short_chars(base_part, &mut base);
+    lossy |= to_short_chars(ext_part, &mut ext);
+    lossy |= base.len() > 8 || ext.len() > 3;
+    ext.truncate(3);
+    if base.is_empty() {
+        base.push(b'_');
+        lossy = true;
+    }
+
+    if !lossy {
+        let candidate = pack(&base, &ext);
+        if !exists(&candidate) {
+            return Ok(candidate);
+        }
+    }
+
+    for n in 1..1_000_000_u32 {
+        let tail = format!("~{}", n);
+        let len = core::cmp::min(base.len(), 8 - tail.len());
+        let mut candidate_base = base[..len].to_vec();
+        candidate_base.extend_from_slice(tail.as_bytes());
+
+        let candidate = pack(&candidate_base, &ext);
+        if !exists(&candidate) {
+            return Ok(candidate);
+        }
+    }
+
+    Err(Error::from(FatError::DirectoryFull))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_short_name_test() {
+        assert_eq!(Some((*b"README  TXT", 0)), exact_short_name("README.TXT"));
+        assert_eq!(Some((*b"README  TXT", NT_LOWERCASE_BASE)), exact_short_name("readme.TXT"));
+        assert_eq!(Some((*b"MAKEFILE   ", NT_LOWERCASE_BASE)), exact_short_name("makefile"));
+        assert_eq!(None, exact_short_name("ReadMe.txt"));
+        assert_eq!(None, exact_short_name("long_name.txt"));
+        assert_eq!(None, exact_short_name("a.b.c"));
+        assert_eq!(None, exact_short_name("a."));
+        assert_eq!(None, exact_short_name("a b.txt"));
+    }
+
+    #[test]
+    fn generate_short_name_test() {
+        let none = |_: &[u8; 11]| false;
+        assert_eq!(*b"README  TXT", generate_short_name("ReadMe.txt", none).unwrap());
+        assert_eq!(*b"LONGFI~1TXT", generate_short_name("Long file name.txt", none).unwrap());
+        assert_eq!(*b"BASHRC~1   ", generate_short_name(".bashrc", none).unwrap());
+        assert_eq!(*b"A_B~1   C  ", generate_short_name("a+b.c", |_| false).unwrap());
+        assert_eq!(*b"ARCHIV~1GZ ", generate_short_name("archive.tar.gz", none).unwrap());
+        assert_eq!(*b"______~1TXT", generate_short_name("Приветы.txt", none).unwrap());
+
+        let taken = |name: &[u8; 11]| name == b"LONGFI~1TXT" || name == b"LONGFI~2TXT";
+        assert_eq!(*b"LONGFI~3TXT", generate_short_name("Long file name.txt", taken).unwrap());
+
+        let taken = |name: &[u8; 11]| name[7] != b'0' && name != b"LONGF~10TXT";
+        assert_eq!(*b"LONGF~10TXT", generate_short_name("Long file name.txt", taken).unwrap());
+    }
+}
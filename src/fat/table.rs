@@ -0,0 +1,162 @@
+use super::*;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub(crate) enum FatEntry {
+    Free,
+    Next(u32),
+    Bad,
+    End,
+}
+
+/// Byte offset of the cluster entry within a FAT and the entry size
+fn entry_position(kind: FatKind, cluster: u32) -> (u64, usize) {
+    match kind {
+        FatKind::Fat12 => (cluster as u64 + cluster as u64 / 2, 2),
+        FatKind::Fat16 => (cluster as u64 * 2, 2),
+        FatKind::Fat32 => (cluster as u64 * 4, 4),
+    }
+}
+
+pub(crate) fn decode_entry(kind: FatKind, cluster: u32, raw: u32) -> FatEntry {
+    let value = match kind {
+        FatKind::Fat12 if cluster & 1 == 1 => (raw >> 4) & 0x0FFF,
+        FatKind::Fat12 => raw & 0x0FFF,
+        FatKind::Fat16 => raw & 0xFFFF,
+        FatKind::Fat32 => raw & 0x0FFF_FFFF,
+    };
+
+    match value {
+        0 => FatEntry::Free,
+        v if v >= kind.end_of_chain() => FatEntry::End,
+        v if v == kind.bad_cluster() => FatEntry::Bad,
+        v => FatEntry::Next(v),
+    }
+}
+
+fn encode_entry(kind: FatKind, entry: FatEntry) -> u32 {
+    match entry {
+        FatEntry::Free => 0,
+        FatEntry::Next(next) => next,
+        FatEntry::Bad => kind.bad_cluster(),
+        FatEntry::End => kind.end_of_chain() | 0x0F,
+    }
+}
+
+/// Updates the entry in all FAT copies (or in the active one if mirroring is disabled)
+pub(crate) fn write_entry(device: &(impl ReadAt + WriteAt), boot: &BootSector, cluster: u32, entry: FatEntry) -> Result<()> {
+    if cluster < FIRST_CLUSTER || cluster > boot.max_cluster() {
+        return Err(Error::from(FatError::InvalidCluster(cluster)));
+    }
+
+    let value = encode_entry(boot.kind, entry);
+    let (offset, size) = entry_position(boot.kind, cluster);
+    let copies = match boot.active_fat {
+        Some(active) => active..active + 1,
+        None => 0..boot.fat_count,
+    };
+
+    for copy in copies {
+        let position = boot.fat_offset(copy) + offset;
+        let mut raw = [0_u8; 4];
+        device.read_exact_at(position, &mut raw[..size])?;
+        let old = u32::from_le_bytes(raw);
+
+        let new = match boot.kind {
+            FatKind::Fat12 if cluster & 1 == 1 => (old & 0xFFFF_000F) | (value << 4),
+            FatKind::Fat12 => (old & 0xFFFF_F000) | value,
+            FatKind::Fat16 => value,
+            // the upper 4 bits are reserved
+            FatKind::Fat32 => (old & 0xF000_0000) | value,
+        };
+
+        device.write_all_at(position, &new.to_le_bytes()[..size])?;
+    }
+
+    Ok(())
+}
+
+/// Calls `f` for every cluster starting from `start` till it returns false
+pub(crate) fn scan(device: &impl ReadAt, boot: &BootSector, start: u32, mut f: impl FnMut(u32, FatEntry) -> bool) -> Result<()> {
+    const CHUNK: u32 = 4096;
+
+    let fat_offset = boot.fat_offset(boot.active_fat.unwrap_or(0));
+    let mut buffer = Vec::new();
+    let mut first = start;
+    while first <= boot.max_cluster() {
+        let last = core::cmp::min(first + CHUNK - 1, boot.max_cluster());
+        let begin = entry_position(boot.kind, first).0;
+        let (end, size) = entry_position(boot.kind, last);
+
+        buffer.clear();
+        buffer.resize((end - begin) as usize + size, 0);
+        device.read_exact_at(fat_offset + begin, &mut buffer)?;
+
+        for cluster in first..=last {
+            let pos = (entry_position(boot.kind, cluster).0 - begin) as usize;
+            let mut raw = [0_u8; 4];
+            raw[..size].copy_from_slice(&buffer[pos..pos + size]);
+            if !f(cluster, decode_entry(boot.kind, cluster, u32::from_le_bytes(raw))) {
+                return Ok(());
+            }
+        }
+
+        first = last + 1;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn read_entry(device: &impl ReadAt, boot: &BootSector, cluster: u32) -> Result<FatEntry> {
+    if cluster < FIRST_CLUSTER || cluster > boot.max_cluster() {
+        return Err(Error::from(FatError::InvalidCluster(cluster)));
+    }
+
+    let (offset, size) = entry_position(boot.kind, cluster);
+    let mut raw = [0_u8; 4];
+    device.read_exact_at(boot.fat_offset(boot.active_fat.unwrap_or(0)) + offset, &mut raw[..size])?;
+
+    Ok(decode_entry(boot.kind, cluster, u32::from_le_bytes(raw)))
+}
+
+/// Collects all clusters of the chain
+pub(crate) fn read_chain(device: &impl ReadAt, boot: &BootSector, first: u32) -> Result<Vec<u32>> {
+    let mut chain = Vec::new();
+    if first == 0 {
+        return Ok(chain); // empty file
+    }
+
+    let mut cluster = first;
+    loop {
+        if chain.len() > boot.cluster_count as usize {
+            return Err(Error::from(FatError::ClusterLoop(first)));
+        }
+
+        chain.push(cluster);
+        match read_entry(device, boot, cluster)? {
+            FatEntry::Next(next) => cluster = next,
+            FatEntry::End => return Ok(chain),
+            FatEntry::Free | FatEntry::Bad => return Err(Error::from(FatError::InvalidCluster(cluster))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_entry_test() {
+        // FAT12 entries 2 and 3 share 3 bytes: 0x003, 0xFFF
+        let raw = u32::from_le_bytes([0x03, 0xF0, 0xFF, 0x00]);
+        assert_eq!((3, 2), entry_position(FatKind::Fat12, 2));
+        assert_eq!(FatEntry::Next(3), decode_entry(FatKind::Fat12, 2, raw));
+        assert_eq!((4, 2), entry_position(FatKind::Fat12, 3));
+        assert_eq!(FatEntry::End, decode_entry(FatKind::Fat12, 3, raw >> 8));
+
+        assert_eq!(FatEntry::Bad, decode_entry(FatKind::Fat16, 2, 0xFFF7));
+        assert_eq!(FatEntry::Free, decode_entry(FatKind::Fat32, 2, 0xF000_0000));
+        assert_eq!(FatEntry::End, decode_entry(FatKind::Fat32, 2, 0x0FFF_FFFF));
+        assert_eq!(FatEntry::Next(0x0123_4567), decode_entry(FatKind::Fat32, 2, 0x0123_4567));
+    }
+}
@@ -0,0 +1,625 @@
+//! Write operations. Both FAT copies and the FSInfo hints are kept up to date.
+use super::dir::{DirEntryRecord, LfnRecord, Location, DELETED, DOT_DOT_NAME, DOT_NAME, END_OF_DIRECTORY};
+use super::fs::{same_name, Directory};
+use super::table::FatEntry;
+use super::*;
+
+/// FAT directories are limited to 65536 entries
+const MAX_DIR_ENTRIES: usize = 65536;
+
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches(&['/', '\\'][..]);
+    match path.rfind(&['/', '\\'][..]) {
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("", path),
+    }
+}
+
+fn record_bytes<T: Copy>(record: T) -> [u8; DIR_ENTRY_SIZE] {
+    let mut raw = [0_u8; DIR_ENTRY_SIZE];
+    tools::write_struct(&mut raw[..], record);
+    raw
+}
+
+impl<R: ReadAt + WriteAt> FatFileSystem<R> {
+    /// Finds `count` free clusters and links them into a chain appended to `previous`
+    fn allocate(&mut self, count: usize, previous: Option<u32>) -> Result<Vec<u32>> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let max_cluster = self.boot.max_cluster();
+        let hint = self
+            .fs_info
+            .and_then(|info| info.next_free)
+            .filter(|&cluster| cluster >= FIRST_CLUSTER && cluster <= max_cluster)
+            .unwrap_or(FIRST_CLUSTER);
+
+        let mut clusters = Vec::with_capacity(count);
+        table::scan(&self.device, &self.boot, hint, |cluster, entry| {
+            if entry == FatEntry::Free {
+                clusters.push(cluster);
+            }
+            clusters.len() < count
+        })?;
+        if clusters.len() < count {
+            table::scan(&self.device, &self.boot, FIRST_CLUSTER, |cluster, entry| {
+                if entry == FatEntry::Free && cluster < hint {
+                    clusters.push(cluster);
+                }
+                cluster < hint && clusters.len() < count
+            })?;
+        }
+
+        if clusters.len() < count {
+            return Err(Error::from(FatError::DiskFull));
+        }
+
+        // link from the end, so the chain never points to free clusters
+        for (i, &cluster) in clusters.iter().enumerate().rev() {
+            let entry = match clusters.get(i + 1) {
+                Some(&next) => FatEntry::Next(next),
+                None => FatEntry::End,
+            };
+            table::write_entry(&self.device, &self.boot, cluster, entry)?;
+        }
+        if let Some(previous) = previous {
+            table::write_entry(&self.device, &self.boot, previous, FatEntry::Next(clusters[0]))?;
+        }
+
+        if let Some(info) = self.fs_info.as_mut() {
+            let last = clusters[count - 1];
+            info.free_count = info.free_count.map(|free| free.saturating_sub(count as u32));
+            info.next_free = Some(if last < max_cluster { last + 1 } else { FIRST_CLUSTER });
+        }
+
+        Ok(clusters)
+    }
+
+    fn release(&mut self, clusters: &[u32]) -> Result<()> {
+        for &cluster in clusters {
+            table::write_entry(&self.device, &self.boot, cluster, FatEntry::Free)?;
+        }
+
+        if let Some(info) = self.fs_info.as_mut() {
+            info.free_count = info.free_count.map(|free| free + clusters.len() as u32);
+        }
+
+        Ok(())
+    }
+
+    fn write_fs_info(&self) -> Result<()> {
+        let info = match self.fs_info {
+            Some(info) if self.boot.kind == FatKind::Fat32 => info,
+            _ => return Ok(()),
+        };
+
+        const UNKNOWN: u32 = 0xFFFF_FFFF;
+        let position = self.boot.fs_info_sector as u64 * self.boot.bytes_per_sector as u64;
+        self.device
+            .write_all_at(position + 488, &info.free_count.unwrap_or(UNKNOWN).to_le_bytes())?;
+        self.device
+            .write_all_at(position + 492, &info.next_free.unwrap_or(UNKNOWN).to_le_bytes())
+    }
+
+    fn zero_clusters(&self, clusters: &[u32]) -> Result<()> {
+        let zeros = vec![0_u8; self.boot.cluster_size() as usize];
+        for &cluster in clusters {
+            self.device.write_all_at(self.boot.cluster_offset(cluster), &zeros)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes data to the chain, the chain must be long enough
+    fn write_chain(&self, clusters: &[u32], offset: u64, data: &[u8]) -> Result<()> {
+        let cluster_size = self.boot.cluster_size() as u64;
+        let mut offset = offset;
+        let mut data = data;
+        while !data.is_empty() {
+            let index = (offset / cluster_size) as usize;
+            let offset_in_cluster = offset % cluster_size;
+            let len = core::cmp::min(data.len() as u64, cluster_size - offset_in_cluster) as usize;
+
+            self.device
+                .write_all_at(self.boot.cluster_offset(clusters[index]) + offset_in_cluster, &data[..len])?;
+            offset += len as u64;
+            data = &data[len..];
+        }
+
+        Ok(())
+    }
+
+    fn write_slot(&self, dir: &mut Directory, index: usize, raw: &[u8; DIR_ENTRY_SIZE]) -> Result<()> {
+        let pos = index * DIR_ENTRY_SIZE;
+        let position = if dir.clusters.is_empty() {
+            self.boot.root_dir_region().0 + pos as u64
+        } else {
+            let cluster_size = self.boot.cluster_size() as usize;
+            self.boot.cluster_offset(dir.clusters[pos / cluster_size]) + (pos % cluster_size) as u64
+        };
+
+        dir.data[pos..pos + DIR_ENTRY_SIZE].copy_from_slice(raw);
+        self.device.write_all_at(position, raw)
+    }
+
+    fn read_record(&self, location: &Location) -> Result<DirEntryRecord> {
+        let dir = self.load_directory(location.dir)?;
+        Ok(tools::read_struct(&dir.data[location.last * DIR_ENTRY_SIZE..]))
+    }
+
+    fn update_record(&self, location: &Location, update: impl FnOnce(&mut DirEntryRecord)) -> Result<()> {
+        let mut dir = self.load_directory(location.dir)?;
+        let mut record: DirEntryRecord = tools::read_struct(&dir.data[location.last * DIR_ENTRY_SIZE..]);
+        update(&mut record);
+        self.write_slot(&mut dir, location.last, &record_bytes(record))
+    }
+
+    fn delete_slots(&self, location: &Location) -> Result<()> {
+        let mut dir = self.load_directory(location.dir)?;
+        for index in location.first..=location.last {
+            let mut raw = [0_u8; DIR_ENTRY_SIZE];
+            raw.copy_from_slice(&dir.data[index * DIR_ENTRY_SIZE..(index + 1) * DIR_ENTRY_SIZE]);
+            raw[0] = DELETED;
+            self.write_slot(&mut dir, index, &raw)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the first of `count` consecutive free slots, extends the directory if needed
+    fn find_free_slots(&mut self, dir: &mut Directory, count: usize) -> Result<usize> {
+        let total = dir.data.len() / DIR_ENTRY_SIZE;
+        let mut run = 0;
+        let mut tail = total;
+        for index in 0..total {
+            match dir.data[index * DIR_ENTRY_SIZE] {
+                END_OF_DIRECTORY => {
+                    // all the following slots are free as well
+                    tail = index - run;
+                    break;
+                }
+                DELETED => {
+                    run += 1;
+                    if run == count {
+                        return Ok(index + 1 - count);
+                    }
+                }
+                _ => run = 0,
+            }
+        }
+        if tail == total {
+            tail -= run;
+        }
+
+        if total - tail >= count {
+            return Ok(tail);
+        }
+
+        let cluster_size = self.boot.cluster_size() as usize;
+        let missing = count - (total - tail);
+        if dir.clusters.is_empty() || total + missing > MAX_DIR_ENTRIES {
+            return Err(Error::from(FatError::DirectoryFull));
+        }
+
+        let new = self.allocate(math::ceil(missing * DIR_ENTRY_SIZE, cluster_size), dir.clusters.last().copied())?;
+        self.zero_clusters(&new)?;
+        dir.data.resize(dir.data.len() + new.len() * cluster_size, 0);
+        dir.clusters.extend(new);
+
+        Ok(tail)
+    }
+
+    /// Adds the entry to the directory. `renamed` is the location of the entry being renamed.
+    fn add_entry(&mut self, parent: u32, name: &str, mut record: DirEntryRecord, renamed: Option<Location>) -> Result<DirEntry> {
+        let chars = name::validate_long_name(name)?;
+        let mut dir = self.load_directory(parent)?;
+
+        let entries = dir.entries();
+        let conflict = entries
+            .iter()
+            .filter(|entry| Some(entry.location) != renamed)
+            .any(|entry| same_name(&entry.name, name) || same_name(&entry.short_name, name));
+        if conflict {
+            return Err(Error::from(FatError::AlreadyExists(name.to_string())));
+        }
+
+        let renamed_slot = renamed
+            .filter(|location| location.dir == dir.first_cluster)
+            .map(|location| location.last);
+        let short_names: Vec<[u8; 11]> = dir
+            .data
+            .chunks_exact(DIR_ENTRY_SIZE)
+            .enumerate()
+            .take_while(|(_, raw)| raw[0] != END_OF_DIRECTORY)
+            .filter(|(index, raw)| raw[0] != DELETED && !Attributes(raw[11]).is_long_name() && Some(*index) != renamed_slot)
+            .map(|(_, raw)| {
+                let mut name = [0_u8; 11];
+                name.copy_from_slice(&raw[..11]);
+                name
+            })
+            .collect();
+        let exists = |candidate: &[u8; 11]| short_names.contains(candidate);
+
+        let (short_name, nt_flags, long_name) = match name::exact_short_name(name) {
+            Some((short_name, nt_flags)) if !exists(&short_name) => (short_name, nt_flags, None),
+            _ => (name::generate_short_name(name, exists)?, 0, Some(chars)),
+        };
+        record.name = short_name;
+        record.nt_flags = nt_flags;
+
+        let mut slots: Vec<[u8; DIR_ENTRY_SIZE]> = match &long_name {
+            Some(chars) => LfnRecord::for_name(chars, dir::short_name_checksum(&short_name))
+                .into_iter()
+                .map(record_bytes)
+                .collect(),
+            None => Vec::new(),
+        };
+        slots.push(record_bytes(record));
+
+        let first = self.find_free_slots(&mut dir, slots.len())?;
+        for (i, raw) in slots.iter().enumerate() {
+            self.write_slot(&mut dir, first + i, raw)?;
+        }
+        self.write_fs_info()?;
+
+        let location = Location {
+            dir: dir.first_cluster,
+            first,
+            last: first + slots.len() - 1,
+        };
+        let long_name = long_name.map(|_| name.to_string());
+        Ok(DirEntry::from_record(&record, long_name, location))
+    }
+
+    fn parent_of<'p>(&self, path: &'p str) -> Result<(DirEntry, &'p str)> {
+        let (parent_path, name) = split_path(path);
+        let parent = self.entry(parent_path)?;
+        if !parent.is_dir() {
+            return Err(Error::from(FatError::NotADirectory(parent_path.to_string())));
+        }
+
+        Ok((parent, name))
+    }
+
+    /// Writes `.` and `..` entries to the new directory cluster
+    fn init_directory(&self, cluster: u32, parent: &DirEntry, time: Option<DateTime>) -> Result<()> {
+        self.zero_clusters(&[cluster])?;
+
+        let mut dot = DirEntryRecord::new(*DOT_NAME, 0, Attributes::DIRECTORY, time);
+        dot.set_first_cluster(cluster);
+        let mut dot_dot = DirEntryRecord::new(*DOT_DOT_NAME, 0, Attributes::DIRECTORY, time);
+        dot_dot.set_first_cluster(if parent.is_root() { 0 } else { parent.first_cluster });
+
+        let position = self.boot.cluster_offset(cluster);
+        self.device.write_all_at(position, &record_bytes(dot))?;
+        self.device.write_all_at(position + DIR_ENTRY_SIZE as u64, &record_bytes(dot_dot))
+    }
+
+    /// Changes the chain length for the new file size, new space is zeroed
+    fn resize(&mut self, entry: &DirEntry, size: u64) -> Result<Vec<u32>> {
+        let cluster_size = self.boot.cluster_size() as u64;
+        let mut clusters = table::read_chain(&self.device, &self.boot, entry.first_cluster)?;
+        let allocated = clusters.len() as u64 * cluster_size;
+        let needed = math::ceil(size, cluster_size) as usize;
+
+        if needed < clusters.len() {
+            let extra = clusters.split_off(needed);
+            if let Some(&last) = clusters.last() {
+                table::write_entry(&self.device, &self.boot, last, FatEntry::End)?;
+            }
+            self.release(&extra)?;
+        } else if needed > clusters.len() {
+            let new = self.allocate(needed - clusters.len(), clusters.last().copied())?;
+            self.zero_clusters(&new)?;
+            clusters.extend(new);
+        }
+
+        // the tail of the last cluster may keep the data of a previously truncated file
+        let old_size = entry.size as u64;
+        let gap_end = core::cmp::min(size, allocated);
+        if gap_end > old_size {
+            self.write_chain(&clusters, old_size, &vec![0_u8; (gap_end - old_size) as usize])?;
+        }
+
+        self.write_fs_info()?;
+        Ok(clusters)
+    }
+
+    fn file_entry(&self, path: &str) -> Result<DirEntry> {
+        let entry = self.entry(path)?;
+        if entry.is_dir() {
+            return Err(Error::from(FatError::NotAFile(path.to_string())));
+        }
+
+        Ok(entry)
+    }
+
+    fn set_size(&self, entry: &DirEntry, clusters: &[u32], size: u64) -> Result<()> {
+        let now = self.now();
+        self.update_record(&entry.location, |record| {
+            record.set_first_cluster(clusters.first().copied().unwrap_or(0));
+            record.file_size = size as u32;
+            record.attributes |= Attributes::ARCHIVE;
+            record.set_modified(now);
+        })
+    }
+
+    pub fn create_file(&mut self, path: &str) -> Result<DirEntry> {
+        let (parent, name) = self.parent_of(path)?;
+        let record = DirEntryRecord::new([b' '; 11], 0, Attributes::ARCHIVE, self.now());
+        self.add_entry(parent.first_cluster, name, record, None)
+    }
+
+    pub fn create_dir(&mut self, path: &str) -> Result<DirEntry> {
+        let (parent, name) = self.parent_of(path)?;
+        name::validate_long_name(name)?;
+
+        let now = self.now();
+        let cluster = self.allocate(1, None)?[0];
+        let mut record = DirEntryRecord::new([b' '; 11], 0, Attributes::DIRECTORY, now);
+        record.set_first_cluster(cluster);
+
+        let created = self
+            .init_directory(cluster, &parent, now)
+            .and_then(|_| self.add_entry(parent.first_cluster, name, record, None));
+        if created.is_err() {
+            self.release(&[cluster])?;
+            self.write_fs_info()?;
+        }
+
+        created
+    }
+
+    /// Writes the data at the offset, the file grows if needed
+    pub fn write(&mut self, path: &str, offset: u64, data: &[u8]) -> Result<()> {
+        let entry = self.file_entry(path)?;
+        let end = offset + data.len() as u64;
+        if end > u32::MAX as u64 {
+            return Err(Error::from(FatError::FileTooLarge));
+        }
+
+        let size = core::cmp::max(entry.size as u64, end);
+        let clusters = self.resize(&entry, size)?;
+        self.write_chain(&clusters, offset, data)?;
+        self.set_size(&entry, &clusters, size)
+    }
+
+    /// Creates or replaces the file content
+    pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        match self.entry(path) {
+            Ok(_) => self.truncate(path, 0)?,
+            Err(Error::NotFound(_)) => {
+                self.create_file(path)?;
+            }
+            Err(e) => return Err(e),
+        }
+
+        self.write(path, 0, data)
+    }
+
+    pub fn truncate(&mut self, path: &str, size: u64) -> Result<()> {
+        let entry = self.file_entry(path)?;
+        if size > u32::MAX as u64 {
+            return Err(Error::from(FatError::FileTooLarge));
+        }
+
+        let clusters = self.resize(&entry, size)?;
+        self.set_size(&entry, &clusters, size)
+    }
+
+    /// Removes the file or the empty directory
+    pub fn remove(&mut self, path: &str) -> Result<()> {
+        let entry = self.entry(path)?;
+        if entry.is_root() {
+            return Err(Error::from(FatError::InvalidName(path.to_string())));
+        }
+        if entry.is_dir() && !self.read_dir_entry(&entry)?.is_empty() {
+            return Err(Error::from(FatError::DirectoryNotEmpty(path.to_string())));
+        }
+
+        let clusters = table::read_chain(&self.device, &self.boot, entry.first_cluster)?;
+        self.delete_slots(&entry.location)?;
+        self.release(&clusters)?;
+        self.write_fs_info()
+    }
+
+    /// Renames or moves the file or directory
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<DirEntry> {
+        let entry = self.entry(from)?;
+        if entry.is_root() {
+            return Err(Error::from(FatError::InvalidName(from.to_string())));
+        }
+
+        let (parent, name) = self.parent_of(to)?;
+        if entry.is_dir() {
+            // a directory cannot be moved into itself
+            let mut current = parent.first_cluster;
+            let mut depth = 0;
+            while current != 0 && current != self.boot.root_cluster {
+                if current == entry.first_cluster || depth > self.boot.cluster_count {
+                    return Err(Error::from(FatError::InvalidName(to.to_string())));
+                }
+
+                let dir = self.load_directory(current)?;
+                let dot_dot: DirEntryRecord = tools::read_struct(&dir.data[DIR_ENTRY_SIZE..]);
+                current = dot_dot.first_cluster();
+                depth += 1;
+            }
+        }
+
+        let record = self.read_record(&entry.location)?;
+        let renamed = self.add_entry(parent.first_cluster, name, record, Some(entry.location))?;
+        // add_entry fills free slots only, so the old location is still valid
+        self.delete_slots(&entry.location)?;
+
+        if entry.is_dir() && entry.location.dir != renamed.location.dir {
+            let mut dir = self.load_directory(entry.first_cluster)?;
+            let mut dot_dot: DirEntryRecord = tools::read_struct(&dir.data[DIR_ENTRY_SIZE..]);
+            dot_dot.set_first_cluster(if parent.is_root() { 0 } else { parent.first_cluster });
+            self.write_slot(&mut dir, 1, &record_bytes(dot_dot))?;
+        }
+
+        Ok(renamed)
+    }
+}
+
+impl<R: ReadAt + WriteAt + Flush> FatFileSystem<R> {
+    pub fn flush(&self) -> Result<()> {
+        self.device.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fat::{format, FormatOptions};
+    use crate::MemoryDisk;
+
+    fn sizes_mib(count: usize) -> usize {
+        count * crate::sizes::MIB as usize
+    }
+
+    fn formatted(size: usize, kind: FatKind, root_entries: u16) -> FatFileSystem<MemoryDisk> {
+        let disk = MemoryDisk::from_vec(vec![0_u8; size]);
+        let options = FormatOptions {
+            kind: Some(kind),
+            root_entries,
+            volume_id: Some(1),
+            ..Default::default()
+        };
+        format(&disk, &options).unwrap();
+
+        let mut fs = FatFileSystem::open(disk).unwrap();
+        fs.set_current_time(Some(DateTime::from_unix_time(1_600_000_000)));
+        fs
+    }
+
+    fn read(fs: &FatFileSystem<MemoryDisk>, path: &str) -> Vec<u8> {
+        let file = fs.open_file(path).unwrap();
+        let mut data = vec![0_u8; file.size() as usize];
+        file.read_exact_at(0, &mut data).unwrap();
+        data
+    }
+
+    fn check_tables(fs: &FatFileSystem<MemoryDisk>) {
+        let boot = fs.boot_sector();
+        let image = fs.device().to_vec();
+        let fat_bytes = (boot.fat_size * boot.bytes_per_sector) as usize;
+        let first = boot.fat_offset(0) as usize;
+        let second = boot.fat_offset(1) as usize;
+        assert!(image[first..first + fat_bytes] == image[second..second + fat_bytes]);
+        drop(image);
+
+        if let Some(free) = fs.fs_info().and_then(|info| info.free_count) {
+            assert_eq!(free, fs.free_clusters().unwrap());
+        }
+    }
+
+    fn round_trip(mut fs: FatFileSystem<MemoryDisk>) {
+        let cluster_size = fs.boot_sector().cluster_size() as usize;
+        let free = fs.free_clusters().unwrap();
+        let data: Vec<u8> = (0..cluster_size * 3 + 100).map(|i| i as u8).collect();
+
+        fs.create_dir("docs").unwrap();
+        fs.create_dir("/docs/Nested Dir").unwrap();
+        fs.write_file("/docs/Nested Dir/Long file name.txt", &data).unwrap();
+        fs.write_file("README.TXT", b"hello").unwrap();
+        fs.write_file("readme.md", b"lower").unwrap();
+        check_tables(&fs);
+
+        assert_eq!(data, read(&fs, "DOCS/nested dir/LONG FILE NAME.TXT"));
+        assert_eq!(b"hello", read(&fs, "readme.txt").as_slice());
+        assert_eq!(b"lower", read(&fs, "README.MD").as_slice());
+
+        let root = fs.read_dir("/").unwrap();
+        assert_eq!(3, root.len());
+        assert_eq!("docs", root[0].name);
+        assert_eq!("docs", root[0].short_name);
+        assert_eq!("readme.md", root[2].name);
+        assert_eq!("readme.md", root[2].short_name);
+        assert_eq!(Some(2020), root[1].modified.map(|t| t.year));
+        let nested = fs.read_dir("docs").unwrap();
+        assert_eq!("NESTED~1", nested[0].short_name);
+
+        match fs.create_file("Readme.txt") {
+            Err(Error::Fat(FatError::AlreadyExists(_))) => (),
+            _ => panic!("already exists expected"),
+        }
+        match fs.create_file("bad?name") {
+            Err(Error::Fat(FatError::InvalidName(_))) => (),
+            _ => panic!("invalid name expected"),
+        }
+
+        // grow in the middle of the file, then shrink
+        fs.write("README.TXT", 10, b"world").unwrap();
+        assert_eq!(b"hello\0\0\0\0\0world", read(&fs, "README.TXT").as_slice());
+        fs.truncate("/docs/Nested Dir/Long file name.txt", 10).unwrap();
+        assert_eq!(&data[..10], read(&fs, "/docs/Nested Dir/Long file name.txt").as_slice());
+        fs.truncate("/docs/Nested Dir/Long file name.txt", cluster_size as u64).unwrap();
+        let grown = read(&fs, "/docs/Nested Dir/Long file name.txt");
+        assert!(grown[10..].iter().all(|&b| b == 0));
+        check_tables(&fs);
+
+        // moving a directory updates its `..` entry
+        fs.rename("/docs/Nested Dir", "/Moved").unwrap();
+        assert_eq!(&data[..10], &read(&fs, "/moved/../Moved/Long file name.txt")[..10]);
+        assert!(fs.read_dir("docs").unwrap().is_empty());
+        match fs.rename("/Moved", "/Moved/inner") {
+            Err(Error::Fat(FatError::InvalidName(_))) => (),
+            _ => panic!("invalid name expected"),
+        }
+        fs.rename("README.TXT", "Read Me Later.txt").unwrap();
+        assert_eq!(b"hello", &read(&fs, "read me later.txt")[..5]);
+
+        match fs.remove("Moved") {
+            Err(Error::Fat(FatError::DirectoryNotEmpty(_))) => (),
+            _ => panic!("directory not empty expected"),
+        }
+        fs.remove("Moved/Long file name.txt").unwrap();
+        fs.remove("Moved").unwrap();
+        fs.remove("docs").unwrap();
+        fs.remove("Read Me Later.txt").unwrap();
+        fs.remove("readme.md").unwrap();
+        assert!(fs.read_dir("/").unwrap().is_empty());
+        assert_eq!(free, fs.free_clusters().unwrap());
+        check_tables(&fs);
+    }
+
+    #[test]
+    fn fat12_write_test() {
+        round_trip(formatted(sizes_mib(1), FatKind::Fat12, 512));
+    }
+
+    #[test]
+    fn fat16_write_test() {
+        round_trip(formatted(sizes_mib(8), FatKind::Fat16, 512));
+    }
+
+    #[test]
+    fn fat32_write_test() {
+        round_trip(formatted(sizes_mib(40), FatKind::Fat32, 0));
+    }
+
+    #[test]
+    fn directory_growth_test() {
+        let mut fs = formatted(sizes_mib(1), FatKind::Fat12, 16);
+        fs.create_dir("F0 dir").unwrap(); // a long name and a short entry
+        for i in 1..15 {
+            fs.create_file(&format!("F{}", i)).unwrap();
+        }
+        match fs.create_file("F15") {
+            Err(Error::Fat(FatError::DirectoryFull)) => (),
+            _ => panic!("directory full expected"),
+        }
+
+        // subdirectories grow cluster by cluster
+        for i in 0..100 {
+            fs.create_file(&format!("F0 dir/file number {}.txt", i)).unwrap();
+        }
+        let entries = fs.read_dir("f0 dir").unwrap();
+        assert_eq!(100, entries.len());
+        assert_eq!("file number 99.txt", entries[99].name);
+        check_tables(&fs);
+    }
+}
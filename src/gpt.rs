@@ -1,5 +1,6 @@
-use crate::mbr::MasterBootRecord;
+use crate::mbr::{self, MasterBootRecord, PartitionRecord};
 use crate::prelude::*;
+use crate::AsByteSlice;
 
 // #[repr(C, packed)]
 // #[derive(Copy, Clone)]
@@ -32,7 +33,12 @@ struct Header {
 
 const SIGNATURE: u64 = 0x5452_4150_2049_4645_u64;
 const HEADER_SIZE: u32 = 92;
+#[cfg(test)]
 const REVISION: u32 = 0x0001_0000;
+/// The partition entries are at least 128 bytes long, the size is a multiple of 8
+const MIN_ENTRY_SIZE: u32 = 128;
+/// Bound for the partition array allocation, the usual array is 16 KiB
+const MAX_ARRAY_SIZE: u32 = 4 * 1024 * 1024;
 
 impl Header {
     pub fn is_valid(&self) -> bool {
@@ -46,7 +52,7 @@ impl Header {
     }
 }
 
-impl crate::AsByteSlice for Header {
+impl AsByteSlice for Header {
     unsafe fn as_byte_slice(&self) -> &[u8] {
         core::slice::from_raw_parts(self as *const Self as *const u8, core::mem::size_of::<Self>())
     }
@@ -71,30 +77,85 @@ pub struct PartitionInfo {
     pub name: String,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum ProtectiveMbrKind {
+    /// The only used MBR entry is the 0xEE one
+    Protective,
+    /// There are extra MBR entries besides the 0xEE one, usually mirroring GPT partitions
+    Hybrid,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum ProtectiveMbrIssue {
+    /// The 0xEE entry should start at LBA 1
+    WrongStartLba(u32),
+    /// The 0xEE entry of a non-hybrid MBR should cover the whole disk (or 0xFFFFFFFF sectors for large disks)
+    WrongSize { expected: u32, actual: u32 },
+}
+
+/// An extra entry of a hybrid MBR
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct HybridMbrEntry {
+    pub mbr_index: usize,
+    /// Index of the GPT partition with the same offset and length, if any
+    pub gpt_index: Option<usize>,
+    pub kind: mbr::PartitionKind,
+    pub boot: bool,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// A GPT partition to be mirrored into a hybrid MBR
+pub struct HybridPartition {
+    pub gpt_index: usize,
+    pub kind: mbr::PartitionKind,
+    pub boot: bool,
+}
+
 pub struct Layout {
-    _protective_mbr: MasterBootRecord,
+    protective_mbr: MasterBootRecord,
     disk_id: Uuid,
     partitions: Vec<PartitionInfo>,
+    mbr_issues: Vec<ProtectiveMbrIssue>,
+    hybrid_entries: Vec<HybridMbrEntry>,
 }
 
 fn read_partitions(disk: &impl Disk, header: &Header) -> Result<Vec<PartitionInfo>> {
+    let entry_size = header.partition_entry_size;
+    if entry_size < MIN_ENTRY_SIZE || !entry_size.is_multiple_of(8) {
+        return Err(Error::InvalidGptHeader);
+    }
+
     unsafe {
         let sector_size = disk.logical_sector_size()?;
-        let buffer_size = math::round_up(header.partition_count * header.partition_entry_size, sector_size);
-        let mut buffer = crate::alloc_buffer(buffer_size as usize);
-        disk.read_exact_at(sector_size as u64 * header.partition_table_lba, buffer.as_mut_slice())?;
+        let array_size = match header.partition_count.checked_mul(entry_size) {
+            Some(size) if size <= MAX_ARRAY_SIZE => size,
+            _ => return Err(Error::InvalidGptHeader),
+        };
+        let offset = header
+            .partition_table_lba
+            .checked_mul(sector_size as u64)
+            .ok_or(Error::InvalidGptHeader)?;
+        let mut buffer = crate::alloc_buffer(math::round_up(array_size as u64, sector_size as u64) as usize);
+        disk.read_exact_at(offset, buffer.as_mut_slice())?;
 
-        let crc = crc::crc32(&buffer);
+        // the CRC covers the entries only, not the sector padding
+        let crc = crc::crc32(&buffer[..array_size as usize]);
         if crc != header.partition_array_crc32 {
-            todo!("Invalid partition table crc error"); // InvalidGptCrc
+            return Err(Error::InvalidGptCrc);
         }
 
         let mut partitions = Vec::<PartitionInfo>::new();
-        for chunk in buffer.chunks_exact(header.partition_entry_size as usize) {
+        for chunk in buffer[..array_size as usize].chunks_exact(entry_size as usize) {
             let raw = &*(chunk.as_ptr() as *const RawPartitionRecord);
             if raw.partition_id == Uuid::nil() {
                 break;
             }
+            if raw.last_lba < raw.first_lba || raw.last_lba.checked_mul(sector_size as u64).is_none() {
+                return Err(Error::InvalidGptHeader);
+            }
 
             let offset = raw.first_lba * sector_size as u64;
             let length = (raw.last_lba - raw.first_lba + 1) * sector_size as u64;
@@ -105,7 +166,7 @@ fn read_partitions(disk: &impl Disk, header: &Header) -> Result<Vec<PartitionInf
                 offset,
                 length,
                 flags: raw.flags,
-                name: String::from_utf16_lossy(&raw.name).trim_end_matches('\0').to_string(), // TODO: FromWide trait
+                name: String::from_utf16_lossy(&{ raw.name }).trim_end_matches('\0').to_string(), // TODO: FromWide trait
             });
         }
 
@@ -116,7 +177,7 @@ fn read_partitions(disk: &impl Disk, header: &Header) -> Result<Vec<PartitionInf
 impl Layout {
     pub(crate) fn read(disk: &impl Disk, mbr: MasterBootRecord) -> Result<Layout> {
         if !mbr.is_gpt_protective() {
-            todo!("Return invalid MBR error") // InvalidGptMbr
+            return Err(Error::InvalidGptMbr);
         }
 
         let sector_size = disk.logical_sector_size()? as u64;
@@ -127,30 +188,30 @@ impl Layout {
 
             // let's try second one
             let size = disk.capacity()?;
-            let secondary_header: Header = tools::read_disk_struct(disk, size - sector_size)?;
-            if secondary_header.is_valid() {
-                header = secondary_header;
-                valid = true;
+            if size >= 2 * sector_size {
+                let secondary_header: Header = tools::read_disk_struct(disk, size - sector_size)?;
+                if secondary_header.is_valid() {
+                    header = secondary_header;
+                    valid = true;
+                }
             }
         }
 
-        if !valid {
-            todo!("Return invalid GPT header error") // InvalidGptHeader
-        }
-
-        if header.header_size != HEADER_SIZE {
-            todo!("Log warning: unexpected header size")
-        }
-        if header.revision != REVISION {
-            todo!("Log warning: unexpected revision")
+        // the header may grow in the later revisions, but not past its sector
+        if !valid || header.header_size < HEADER_SIZE || header.header_size as u64 > sector_size {
+            return Err(Error::InvalidGptHeader);
         }
 
         let partitions = read_partitions(disk, &header)?;
+        let total_sectors = disk.capacity()? / sector_size;
+        let (mbr_issues, hybrid_entries) = analyze_mbr(&mbr, sector_size, total_sectors, &partitions);
 
         Ok(Layout {
-            _protective_mbr: mbr,
+            protective_mbr: mbr,
             disk_id: header.disk_id,
             partitions,
+            mbr_issues,
+            hybrid_entries,
         })
     }
 
@@ -162,3 +223,372 @@ impl Layout {
         &self.partitions
     }
 }
+
+fn analyze_mbr(
+    mbr: &MasterBootRecord,
+    sector_size: u64,
+    total_sectors: u64,
+    partitions: &[PartitionInfo],
+) -> (Vec<ProtectiveMbrIssue>, Vec<HybridMbrEntry>) {
+    let protective_index = mbr.gpt_protective_index();
+
+    let mut hybrid_entries = Vec::new();
+    for (mbr_index, record) in mbr.partition_table.iter().enumerate() {
+        if Some(mbr_index) == protective_index || record.is_unused() {
+            continue;
+        }
+
+        let info = mbr::PartitionInfo::new(record, sector_size, 0);
+        let gpt_index = partitions.iter().position(|p| p.offset == info.offset && p.length == info.length);
+
+        hybrid_entries.push(HybridMbrEntry {
+            mbr_index,
+            gpt_index,
+            kind: info.kind,
+            boot: info.boot_indicator,
+            offset: info.offset,
+            length: info.length,
+        });
+    }
+
+    let mut issues = Vec::new();
+    if let Some(index) = protective_index {
+        let record = &mbr.partition_table[index];
+        let first_sector_lba = record.first_sector_lba;
+        if first_sector_lba != 1 {
+            issues.push(ProtectiveMbrIssue::WrongStartLba(first_sector_lba));
+        }
+
+        // A hybrid MBR 0xEE entry covers only the GPT structures, not the whole disk
+        if hybrid_entries.is_empty() {
+            let expected = core::cmp::min(total_sectors.saturating_sub(1), u32::MAX as u64) as u32;
+            let actual = record.partition_size_in_sectors;
+            if actual != expected {
+                issues.push(ProtectiveMbrIssue::WrongSize { expected, actual });
+            }
+        }
+    }
+
+    (issues, hybrid_entries)
+}
+
+impl Layout {
+    pub fn mbr_kind(&self) -> ProtectiveMbrKind {
+        if self.hybrid_entries.is_empty() {
+            ProtectiveMbrKind::Protective
+        } else {
+            ProtectiveMbrKind::Hybrid
+        }
+    }
+
+    /// Problems found in the 0xEE entry of the protective MBR
+    pub fn protective_mbr_issues(&self) -> &[ProtectiveMbrIssue] {
+        &self.mbr_issues
+    }
+
+    /// Extra entries of a hybrid MBR, empty for a pure protective one
+    pub fn hybrid_entries(&self) -> &[HybridMbrEntry] {
+        &self.hybrid_entries
+    }
+
+    /// Index of the MBR entry mirroring the GPT partition, if any
+    pub fn mbr_mirror_of(&self, gpt_index: usize) -> Option<usize> {
+        self.hybrid_entries
+            .iter()
+            .find(|e| e.gpt_index == Some(gpt_index))
+            .map(|e| e.mbr_index)
+    }
+
+    /// Replaces the MBR with a hybrid one mirroring up to three GPT partitions.
+    ///
+    /// The 0xEE entry covers the sectors from LBA 1 up to the first mirrored partition.
+    /// Boot code and disk signature are preserved.
+    pub fn create_hybrid_mbr(&mut self, disk: &impl Disk, partitions: &[HybridPartition], protective_first: bool) -> Result<()> {
+        if partitions.is_empty() || partitions.len() > 3 {
+            return Err(Error::InvalidHybridMbr);
+        }
+
+        let sector_size = disk.logical_sector_size()? as u64;
+        let geometry = disk.geometry()?;
+
+        let mut records = Vec::with_capacity(partitions.len());
+        let mut first_partition_lba = u32::MAX;
+        for (i, hybrid) in partitions.iter().enumerate() {
+            let gpt_partition = self.partitions.get(hybrid.gpt_index).ok_or(Error::InvalidHybridMbr)?;
+            if partitions[..i].iter().any(|p| p.gpt_index == hybrid.gpt_index) {
+                return Err(Error::InvalidHybridMbr);
+            }
+
+            let first_lba = gpt_partition.offset / sector_size;
+            let sectors = gpt_partition.length / sector_size;
+            if first_lba + sectors > u32::MAX as u64 || hybrid.kind.id() == mbr::KnownPartitionKind::Empty as u8 {
+                return Err(Error::InvalidHybridMbr);
+            }
+
+            first_partition_lba = core::cmp::min(first_partition_lba, first_lba as u32);
+            records.push(PartitionRecord::new(
+                hybrid.kind.id(),
+                hybrid.boot,
+                first_lba as u32,
+                sectors as u32,
+                &geometry,
+            ));
+        }
+
+        if first_partition_lba <= 1 {
+            return Err(Error::InvalidHybridMbr);
+        }
+
+        let protective = PartitionRecord::new(
+            mbr::KnownPartitionKind::GptProtectiveMBR as u8,
+            false,
+            1,
+            first_partition_lba - 1,
+            &geometry,
+        );
+        if protective_first {
+            records.insert(0, protective);
+        } else {
+            records.push(protective);
+        }
+
+        self.write_mbr(disk, &records)
+    }
+
+    /// Replaces the MBR with a clean protective one.
+    ///
+    /// May also be used to fix the reported protective MBR issues.
+    pub fn remove_hybrid_mbr(&mut self, disk: &impl Disk) -> Result<()> {
+        let sector_size = disk.logical_sector_size()? as u64;
+        let total_sectors = disk.capacity()? / sector_size;
+        let sectors = core::cmp::min(total_sectors.saturating_sub(1), u32::MAX as u64) as u32;
+
+        let protective = PartitionRecord::new(
+            mbr::KnownPartitionKind::GptProtectiveMBR as u8,
+            false,
+            1,
+            sectors,
+            &disk.geometry()?,
+        );
+        self.write_mbr(disk, &[protective])
+    }
+
+    fn write_mbr(&mut self, disk: &impl Disk, records: &[PartitionRecord]) -> Result<()> {
+        let mut mbr = self.protective_mbr;
+        for (i, slot) in mbr.partition_table.iter_mut().enumerate() {
+            *slot = records.get(i).copied().unwrap_or_else(PartitionRecord::empty);
+        }
+
+        disk.write_all_at(0, unsafe { mbr.as_byte_slice() })?;
+
+        let sector_size = disk.logical_sector_size()? as u64;
+        let total_sectors = disk.capacity()? / sector_size;
+        let (mbr_issues, hybrid_entries) = analyze_mbr(&mbr, sector_size, total_sectors, &self.partitions);
+        self.protective_mbr = mbr;
+        self.mbr_issues = mbr_issues;
+        self.hybrid_entries = hybrid_entries;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiskLayout, MemoryDisk};
+
+    fn partition(first_lba: u64, sectors: u64) -> PartitionInfo {
+        PartitionInfo {
+            id: Uuid::nil(),
+            kind: Uuid::nil(),
+            offset: first_lba * 512,
+            length: sectors * 512,
+            flags: 0,
+            name: String::new(),
+        }
+    }
+
+    fn mbr(records: &[PartitionRecord]) -> MasterBootRecord {
+        let mut mbr: MasterBootRecord = unsafe { core::mem::zeroed() };
+        mbr.signature = 0xAA55;
+        for (slot, record) in mbr.partition_table.iter_mut().zip(records) {
+            *slot = *record;
+        }
+        mbr
+    }
+
+    #[test]
+    fn protective_mbr_test() {
+        let geometry = Geometry::chs(1024, 255, 63);
+        let partitions = vec![partition(2048, 4096)];
+
+        let good = mbr(&[PartitionRecord::new(0xEE, false, 1, 8191, &geometry)]);
+        let (issues, hybrid) = analyze_mbr(&good, 512, 8192, &partitions);
+        assert!(issues.is_empty());
+        assert!(hybrid.is_empty());
+
+        let bad = mbr(&[PartitionRecord::new(0xEE, false, 0, 100, &geometry)]);
+        let (issues, _) = analyze_mbr(&bad, 512, 8192, &partitions);
+        assert_eq!(
+            issues,
+            vec![
+                ProtectiveMbrIssue::WrongStartLba(0),
+                ProtectiveMbrIssue::WrongSize {
+                    expected: 8191,
+                    actual: 100
+                }
+            ]
+        );
+    }
+
+    /// Protective MBR, a GPT with two partitions and no secondary header
+    fn gpt_disk() -> MemoryDisk {
+        let disk = MemoryDisk::with_capacity(8192 * 512);
+        let mut entries = vec![0_u8; 128 * 128];
+        for (i, (first_lba, last_lba)) in [(2048_u64, 6143_u64), (6144, 7167)].iter().enumerate() {
+            let entry = &mut entries[i * 128..(i + 1) * 128];
+            entry[..16].copy_from_slice(&[0xA2; 16]);
+            entry[16..32].copy_from_slice(&[i as u8 + 1; 16]);
+            entry[32..40].copy_from_slice(&first_lba.to_le_bytes());
+            entry[40..48].copy_from_slice(&last_lba.to_le_bytes());
+        }
+        disk.write_all_at(2 * 512, &entries).unwrap();
+
+        let mut header = Header {
+            signature: SIGNATURE,
+            revision: REVISION,
+            header_size: HEADER_SIZE,
+            header_crc32: 0,
+            reserved: 0,
+            current_lba: 1,
+            copy_lba: 8191,
+            first_usable_lba: 34,
+            last_usable_lba: 8158,
+            disk_id: Uuid::nil(),
+            partition_table_lba: 2,
+            partition_count: 128,
+            partition_entry_size: 128,
+            partition_array_crc32: crc::crc32(&entries),
+        };
+        header.header_crc32 = header.crc();
+        disk.write_all_at(512, unsafe { header.as_byte_slice() }).unwrap();
+
+        let protective = mbr(&[PartitionRecord::new(0xEE, false, 1, 8191, &disk.geometry().unwrap())]);
+        disk.write_all_at(0, unsafe { protective.as_byte_slice() }).unwrap();
+        disk
+    }
+
+    fn read_gpt(disk: &MemoryDisk) -> Layout {
+        match DiskLayout::read(disk).unwrap() {
+            DiskLayout::Gpt(layout) => layout,
+            _ => panic!("GPT layout expected"),
+        }
+    }
+
+    #[test]
+    fn create_remove_hybrid_mbr_test() {
+        let disk = gpt_disk();
+        let mut layout = read_gpt(&disk);
+        assert_eq!(2, layout.partitions().len());
+        assert_eq!(ProtectiveMbrKind::Protective, layout.mbr_kind());
+        assert!(layout.protective_mbr_issues().is_empty());
+
+        let fat = |gpt_index, boot| HybridPartition {
+            gpt_index,
+            kind: mbr::PartitionKind::Unknown(0x0C),
+            boot,
+        };
+        for invalid in [vec![], vec![fat(2, false)], vec![fat(1, false), fat(1, true)]].iter() {
+            assert!(matches!(
+                layout.create_hybrid_mbr(&disk, invalid, false),
+                Err(Error::InvalidHybridMbr)
+            ));
+        }
+
+        layout.create_hybrid_mbr(&disk, &[fat(1, true)], false).unwrap();
+        for layout in [layout, read_gpt(&disk)].iter() {
+            assert_eq!(ProtectiveMbrKind::Hybrid, layout.mbr_kind());
+            assert!(layout.protective_mbr_issues().is_empty());
+            assert_eq!(Some(0), layout.mbr_mirror_of(1));
+            assert_eq!(None, layout.mbr_mirror_of(0));
+            assert!(layout.hybrid_entries()[0].boot);
+            assert_eq!(6144 * 512, layout.hybrid_entries()[0].offset);
+        }
+
+        // the 0xEE entry goes after the mirrored ones and covers the sectors up to the first of them
+        let mbr: MasterBootRecord = tools::read_disk_struct(&disk, 0).unwrap();
+        assert_eq!(Some(1), mbr.gpt_protective_index());
+        let protective_size = mbr.partition_table[1].partition_size_in_sectors;
+        assert_eq!(6143, protective_size);
+
+        let mut layout = read_gpt(&disk);
+        layout.remove_hybrid_mbr(&disk).unwrap();
+        let layout = read_gpt(&disk);
+        assert_eq!(ProtectiveMbrKind::Protective, layout.mbr_kind());
+        assert!(layout.protective_mbr_issues().is_empty());
+        assert!(layout.hybrid_entries().is_empty());
+    }
+
+    #[test]
+    fn damaged_gpt_test() {
+        // a bad partition array CRC
+        let disk = gpt_disk();
+        disk.write_all_at(2 * 512 + 100, b"damage").unwrap();
+        assert!(matches!(DiskLayout::read(&disk), Err(Error::InvalidGptCrc)));
+
+        // a bad header CRC without the secondary header
+        let disk = gpt_disk();
+        disk.write_all_at(512 + 40, b"damage").unwrap();
+        assert!(matches!(DiskLayout::read(&disk), Err(Error::InvalidGptHeader)));
+
+        // a hybrid MBR is used instead then
+        let mut layout = read_gpt(&gpt_disk());
+        let disk = gpt_disk();
+        layout
+            .create_hybrid_mbr(
+                &disk,
+                &[HybridPartition {
+                    gpt_index: 0,
+                    kind: mbr::PartitionKind::Unknown(0x07),
+                    boot: false,
+                }],
+                true,
+            )
+            .unwrap();
+        disk.write_all_at(512 + 40, b"damage").unwrap();
+        match DiskLayout::read(&disk).unwrap() {
+            DiskLayout::Mbr(layout) => assert!(layout.partitions().iter().any(|p| p.offset == 2048 * 512)),
+            _ => panic!("MBR layout expected"),
+        }
+
+        // an unsupported entry size
+        let disk = gpt_disk();
+        let mut header: Header = tools::read_disk_struct(&disk, 512).unwrap();
+        header.partition_entry_size = 0;
+        header.header_crc32 = header.crc();
+        disk.write_all_at(512, unsafe { header.as_byte_slice() }).unwrap();
+        assert!(matches!(DiskLayout::read(&disk), Err(Error::InvalidGptHeader)));
+    }
+
+    #[test]
+    fn hybrid_mbr_test() {
+        let geometry = Geometry::chs(1024, 255, 63);
+        let partitions = vec![partition(2048, 4096), partition(6144, 1024)];
+
+        let hybrid = mbr(&[
+            PartitionRecord::new(0x0C, true, 6144, 1024, &geometry),
+            PartitionRecord::new(0x83, false, 7168, 1024, &geometry),
+            PartitionRecord::new(0xEE, false, 1, 2047, &geometry),
+        ]);
+        assert!(hybrid.is_gpt_protective());
+
+        let (issues, entries) = analyze_mbr(&hybrid, 512, 8192, &partitions);
+        assert!(issues.is_empty());
+        assert_eq!(2, entries.len());
+        assert_eq!(0, entries[0].mbr_index);
+        assert_eq!(Some(1), entries[0].gpt_index);
+        assert!(entries[0].boot);
+        assert_eq!(1, entries[1].mbr_index);
+        assert_eq!(None, entries[1].gpt_index);
+    }
+}
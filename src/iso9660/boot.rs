@@ -0,0 +1,212 @@
+use super::*;
+
+const ENTRY_SIZE: usize = 32;
+const VALIDATION_HEADER: u8 = 0x01;
+const KEY: [u8; 2] = [0x55, 0xAA];
+const BOOTABLE: u8 = 0x88;
+const SECTION_HEADER: u8 = 0x90;
+const FINAL_SECTION_HEADER: u8 = 0x91;
+const SECTION_EXTENSION: u8 = 0x44;
+const VIRTUAL_SECTOR: u64 = 512;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum Platform {
+    X86,
+    PowerPc,
+    Mac,
+    Efi,
+    Unknown(u8),
+}
+
+impl From<u8> for Platform {
+    fn from(id: u8) -> Self {
+        match id {
+            0x00 => Platform::X86,
+            0x01 => Platform::PowerPc,
+            0x02 => Platform::Mac,
+            0xEF => Platform::Efi,
+            id => Platform::Unknown(id),
+        }
+    }
+}
+
+/// How the BIOS presents the image
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum BootMedia {
+    NoEmulation,
+    Floppy1200,
+    Floppy1440,
+    Floppy2880,
+    HardDisk,
+    Unknown(u8),
+}
+
+impl From<u8> for BootMedia {
+    fn from(kind: u8) -> Self {
+        match kind & 0x0F {
+            0 => BootMedia::NoEmulation,
+            1 => BootMedia::Floppy1200,
+            2 => BootMedia::Floppy1440,
+            3 => BootMedia::Floppy2880,
+            4 => BootMedia::HardDisk,
+            kind => BootMedia::Unknown(kind),
+        }
+    }
+}
+
+impl BootMedia {
+    /// Emulated floppy size in bytes
+    pub fn floppy_size(self) -> Option<u64> {
+        match self {
+            BootMedia::Floppy1200 => Some(1_228_800),
+            BootMedia::Floppy1440 => Some(1_474_560),
+            BootMedia::Floppy2880 => Some(2_949_120),
+            _ => None,
+        }
+    }
+}
+
+/// Initial/default or section entry
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct BootEntry {
+    pub platform: Platform,
+    pub bootable: bool,
+    pub media: BootMedia,
+    pub load_segment: u16,
+    /// Partition type of hard disk images
+    pub system_type: u8,
+    /// 512 byte sectors loaded by the BIOS, not always the whole image
+    pub sector_count: u16,
+    /// Image block
+    pub load_rba: u32,
+}
+
+impl BootEntry {
+    fn parse(bytes: &[u8], platform: Platform) -> Self {
+        Self {
+            platform,
+            bootable: bytes[0] == BOOTABLE,
+            media: BootMedia::from(bytes[1]),
+            load_segment: le_u16(bytes, 2),
+            system_type: bytes[4],
+            sector_count: le_u16(bytes, 6),
+            load_rba: le_u32(bytes, 8),
+        }
+    }
+
+    /// What the BIOS loads for no emulation images
+    pub fn loaded_size(&self) -> u64 {
+        self.sector_count as u64 * VIRTUAL_SECTOR
+    }
+}
+
+/// El Torito boot catalog
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct BootCatalog {
+    /// Platform of the default entry
+    pub platform: Platform,
+    pub id: String,
+    /// The default entry first
+    pub entries: Vec<BootEntry>,
+}
+
+impl BootCatalog {
+    /// Entries past the first catalog sector are ignored
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let invalid = || Error::from(IsoError::InvalidBootCatalog);
+        if bytes.len() < 2 * ENTRY_SIZE || bytes[0] != VALIDATION_HEADER || bytes[30..32] != KEY {
+            return Err(invalid());
+        }
+
+        let checksum = bytes[..ENTRY_SIZE]
+            .chunks_exact(2)
+            .fold(0_u16, |sum, word| sum.wrapping_add(le_u16(word, 0)));
+        if checksum != 0 {
+            return Err(invalid());
+        }
+
+        let platform = Platform::from(bytes[1]);
+        let mut entries = vec![BootEntry::parse(&bytes[ENTRY_SIZE..], platform)];
+
+        let mut records = bytes[2 * ENTRY_SIZE..].chunks_exact(ENTRY_SIZE);
+        while let Some(header) = records.next() {
+            if header[0] != SECTION_HEADER && header[0] != FINAL_SECTION_HEADER {
+                break;
+            }
+
+            let platform = Platform::from(header[1]);
+            let mut count = le_u16(header, 2);
+            while count > 0 {
+                let entry = match records.next() {
+                    Some(entry) => entry,
+                    None => break,
+                };
+                if entry[0] != SECTION_EXTENSION {
+                    entries.push(BootEntry::parse(entry, platform));
+                    count -= 1;
+                }
+            }
+
+            if header[0] == FINAL_SECTION_HEADER {
+                break;
+            }
+        }
+
+        Ok(Self {
+            platform,
+            id: descriptor::text(&bytes[4..28], false),
+            entries,
+        })
+    }
+}
+
+/// Boot image contents
+pub struct BootImage<'f, R: ReadAt> {
+    device: &'f R,
+    offset: u64,
+    size: u64,
+}
+
+impl<'f, R: ReadAt> BootImage<'f, R> {
+    pub(crate) fn new(device: &'f R, offset: u64, size: u64) -> Self {
+        Self { device, offset, size }
+    }
+
+    /// Offset of the image on the device
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl<'f, R: ReadAt> ReadAt for BootImage<'f, R> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size, offset, buffer.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::ReadBeyondEOD),
+        };
+
+        self.device.read_at(self.offset + offset, &mut buffer[..len])
+    }
+}
+
+/// Hard disk images end with the last partition of their MBR
+pub(crate) fn hard_disk_size(mbr: &[u8]) -> Option<u64> {
+    if mbr[510..512] != KEY {
+        return None;
+    }
+
+    mbr[446..510]
+        .chunks_exact(16)
+        .map(|entry| (le_u32(entry, 8) as u64 + le_u32(entry, 12) as u64) * VIRTUAL_SECTOR)
+        .max()
+        .filter(|&size| size > 0)
+}
@@ -0,0 +1,233 @@
+use super::dir::Record;
+use super::*;
+
+const TYPE_BOOT_RECORD: u8 = 0;
+const TYPE_PRIMARY: u8 = 1;
+const TYPE_SUPPLEMENTARY: u8 = 2;
+const TYPE_PARTITION: u8 = 3;
+const TYPE_TERMINATOR: u8 = 255;
+const EL_TORITO_ID: &[u8] = b"EL TORITO SPECIFICATION";
+/// UCS-2 escape sequences of Joliet levels 1, 2 and 3
+const JOLIET_ESCAPES: [&[u8]; 3] = [b"%/@", b"%/C", b"%/E"];
+const ROOT_RECORD: usize = 156;
+const ROOT_RECORD_SIZE: usize = 34;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum VolumeKind {
+    BootRecord,
+    Primary,
+    Supplementary,
+    Partition,
+    Terminator,
+    Unknown(u8),
+}
+
+impl From<u8> for VolumeKind {
+    fn from(kind: u8) -> Self {
+        match kind {
+            TYPE_BOOT_RECORD => VolumeKind::BootRecord,
+            TYPE_PRIMARY => VolumeKind::Primary,
+            TYPE_SUPPLEMENTARY => VolumeKind::Supplementary,
+            TYPE_PARTITION => VolumeKind::Partition,
+            TYPE_TERMINATOR => VolumeKind::Terminator,
+            kind => VolumeKind::Unknown(kind),
+        }
+    }
+}
+
+/// ISO 9660 timestamp, local time with the offset from UTC
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Timestamp {
+    pub local: DateTime,
+    /// Minutes
+    pub utc_offset: i16,
+}
+
+impl Timestamp {
+    /// 17 byte volume descriptor form: `YYYYMMDDHHMMSScc` digits and the offset, `None` if not specified
+    pub fn from_digits(bytes: &[u8]) -> Option<Self> {
+        let number = |range: core::ops::Range<usize>| {
+            bytes[range].iter().try_fold(0_u16, |value, &digit| match digit {
+                b'0'..=b'9' => Some(value * 10 + (digit - b'0') as u16),
+                _ => None,
+            })
+        };
+
+        let month = number(4..6)? as u8;
+        let day = number(6..8)? as u8;
+        if month == 0 || month > 12 || day == 0 {
+            return None;
+        }
+
+        let local = DateTime {
+            year: number(0..4)?,
+            month,
+            day,
+            hour: number(8..10)? as u8,
+            minute: number(10..12)? as u8,
+            second: number(12..14)? as u8,
+            millisecond: number(14..16)? * 10,
+        };
+        Some(Self {
+            local,
+            utc_offset: bytes[16] as i8 as i16 * 15,
+        })
+    }
+
+    /// 7 byte directory record form: years since 1900, month, day, hour, minute, second and the offset
+    pub fn from_record(bytes: &[u8]) -> Option<Self> {
+        if bytes[1] == 0 || bytes[1] > 12 || bytes[2] == 0 {
+            return None;
+        }
+
+        let local = DateTime {
+            year: 1900 + bytes[0] as u16,
+            month: bytes[1],
+            day: bytes[2],
+            hour: bytes[3],
+            minute: bytes[4],
+            second: bytes[5],
+            millisecond: 0,
+        };
+        Some(Self {
+            local,
+            utc_offset: bytes[6] as i8 as i16 * 15,
+        })
+    }
+
+    /// Seconds since 1970-01-01 UTC
+    pub fn to_unix_time(&self) -> i64 {
+        self.local.to_unix_time() - self.utc_offset as i64 * 60
+    }
+}
+
+/// Primary or supplementary volume descriptor
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct VolumeDescriptor {
+    pub kind: VolumeKind,
+    /// 1-3 for Joliet supplementary descriptors
+    pub joliet_level: Option<u8>,
+    pub system_id: String,
+    pub volume_id: String,
+    /// Logical blocks
+    pub volume_space_size: u32,
+    pub volume_set_size: u16,
+    pub volume_sequence_number: u16,
+    pub block_size: u16,
+    pub path_table_size: u32,
+    /// Block of the little endian path table
+    pub path_table: u32,
+    pub volume_set_id: String,
+    pub publisher_id: String,
+    pub preparer_id: String,
+    pub application_id: String,
+    pub created: Option<Timestamp>,
+    pub modified: Option<Timestamp>,
+    pub(crate) root: Record,
+}
+
+impl VolumeDescriptor {
+    pub(crate) fn parse(bytes: &[u8], sector: u32) -> Result<Self> {
+        let invalid = || Error::from(IsoError::InvalidVolumeDescriptor(sector));
+
+        let kind = VolumeKind::from(bytes[0]);
+        let joliet_level = match kind {
+            VolumeKind::Supplementary => JOLIET_ESCAPES
+                .iter()
+                .position(|escape| &bytes[88..91] == *escape)
+                .map(|level| level as u8 + 1),
+            _ => None,
+        };
+        let joliet = joliet_level.is_some();
+        let text = |range: core::ops::Range<usize>| text(&bytes[range], joliet);
+
+        let block_size = le_u16(bytes, 128);
+        let root = Record::parse(&bytes[ROOT_RECORD..ROOT_RECORD + ROOT_RECORD_SIZE]).ok_or_else(invalid)?;
+        if !block_size.is_power_of_two() || block_size < 512 || !root.is_dir() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            kind,
+            joliet_level,
+            system_id: text(8..40),
+            volume_id: text(40..72),
+            volume_space_size: le_u32(bytes, 80),
+            volume_set_size: le_u16(bytes, 120),
+            volume_sequence_number: le_u16(bytes, 124),
+            block_size,
+            path_table_size: le_u32(bytes, 132),
+            path_table: le_u32(bytes, 140),
+            volume_set_id: text(190..318),
+            publisher_id: text(318..446),
+            preparer_id: text(446..574),
+            application_id: text(574..702),
+            created: Timestamp::from_digits(&bytes[813..830]),
+            modified: Timestamp::from_digits(&bytes[830..847]),
+            root,
+        })
+    }
+
+    pub fn is_joliet(&self) -> bool {
+        self.joliet_level.is_some()
+    }
+}
+
+/// What the volume descriptor set holds
+pub(crate) struct Descriptors {
+    pub primary: VolumeDescriptor,
+    pub joliet: Option<VolumeDescriptor>,
+    /// El Torito boot catalog block
+    pub boot_catalog: Option<u32>,
+}
+
+/// Reads the descriptors up to the terminator
+pub(crate) fn read_descriptors(device: &impl ReadAt) -> Result<Descriptors> {
+    // a sane limit for the set
+    const MAX_DESCRIPTORS: u32 = 64;
+
+    let mut primary = None;
+    let mut joliet = None;
+    let mut boot_catalog = None;
+    let mut bytes = vec![0_u8; SECTOR_SIZE];
+    for sector in FIRST_DESCRIPTOR..FIRST_DESCRIPTOR + MAX_DESCRIPTORS {
+        device.read_exact_at(sector as u64 * SECTOR_SIZE as u64, &mut bytes)?;
+        if &bytes[1..6] != STANDARD_ID {
+            return Err(Error::from(IsoError::InvalidVolumeDescriptor(sector)));
+        }
+
+        match VolumeKind::from(bytes[0]) {
+            VolumeKind::Primary if primary.is_none() => primary = Some(VolumeDescriptor::parse(&bytes, sector)?),
+            VolumeKind::Supplementary if joliet.is_none() => {
+                let descriptor = VolumeDescriptor::parse(&bytes, sector)?;
+                if descriptor.is_joliet() {
+                    joliet = Some(descriptor);
+                }
+            }
+            VolumeKind::BootRecord if bytes[7..7 + EL_TORITO_ID.len()] == *EL_TORITO_ID => {
+                boot_catalog = Some(le_u32(&bytes, 71));
+            }
+            VolumeKind::Terminator => break,
+            _ => (),
+        }
+    }
+
+    Ok(Descriptors {
+        primary: primary.ok_or_else(|| Error::from(IsoError::NoPrimaryVolumeDescriptor))?,
+        joliet,
+        boot_catalog,
+    })
+}
+
+/// a/d-characters or UCS-2 for Joliet, trailing spaces are trimmed
+pub(crate) fn text(bytes: &[u8], joliet: bool) -> String {
+    if joliet {
+        let chars: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&chars).trim_end_matches(&[' ', '\0'][..]).to_string()
+    } else {
+        String::from_utf8_lossy(bytes).trim_end_matches(&[' ', '\0'][..]).to_string()
+    }
+}
@@ -0,0 +1,149 @@
+use super::*;
+
+const MIN_RECORD_SIZE: usize = 33;
+
+/// Directory record as stored
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub(crate) struct Record {
+    pub extent: u32,
+    /// Extended attribute record blocks before the data
+    pub ear_length: u8,
+    pub size: u32,
+    pub recorded: Option<Timestamp>,
+    pub flags: u8,
+    /// Interleaving is used if not 0
+    pub unit_size: u8,
+    pub identifier: Vec<u8>,
+    pub system_use: Vec<u8>,
+}
+
+impl Record {
+    /// The record must start the slice, `None` if it does not fit
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let length = *bytes.first()? as usize;
+        let id_length = *bytes.get(32)? as usize;
+        if length < MIN_RECORD_SIZE + id_length || length > bytes.len() {
+            return None;
+        }
+
+        // the identifier is padded to an even record offset
+        let system_use = MIN_RECORD_SIZE + id_length + (1 - id_length % 2);
+        Some(Self {
+            extent: le_u32(bytes, 2),
+            ear_length: bytes[1],
+            size: le_u32(bytes, 10),
+            recorded: Timestamp::from_record(&bytes[18..25]),
+            flags: bytes[25],
+            unit_size: bytes[26],
+            identifier: bytes[MIN_RECORD_SIZE..MIN_RECORD_SIZE + id_length].to_vec(),
+            system_use: bytes.get(system_use..length).unwrap_or(&[]).to_vec(),
+        })
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.flags & DirEntry::FLAG_DIRECTORY != 0
+    }
+
+    /// `.` and `..` are stored as 0 and 1
+    pub fn is_self_or_parent(&self) -> bool {
+        self.identifier == [0] || self.identifier == [1]
+    }
+}
+
+/// Splits the directory data into records, they do not cross sector boundaries
+pub(crate) fn parse_records(data: &[u8], extent: u32) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for sector in data.chunks(SECTOR_SIZE) {
+        let mut pos = 0;
+        // the rest of the sector is zero padded
+        while pos < sector.len() && sector[pos] != 0 {
+            let record = Record::parse(&sector[pos..]).ok_or_else(|| Error::from(IsoError::InvalidDirectory(extent)))?;
+            pos += sector[pos] as usize;
+            records.push(record);
+        }
+    }
+
+    Ok(records)
+}
+
+/// Contiguous part of the file data
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Extent {
+    pub block: u32,
+    /// Bytes
+    pub length: u32,
+}
+
+/// Directory entry, `.` and `..` are not listed
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct DirEntry {
+    /// Rock Ridge, Joliet or ISO 9660 name without the `;1` version
+    pub name: String,
+    pub flags: u8,
+    /// Several for files over 4 GiB
+    pub extents: Vec<Extent>,
+    pub recorded: Option<Timestamp>,
+    pub rock_ridge: Option<RockRidge>,
+    pub(crate) interleaved: bool,
+}
+
+impl DirEntry {
+    pub const FLAG_HIDDEN: u8 = 0x01;
+    pub const FLAG_DIRECTORY: u8 = 0x02;
+    /// Apple resource forks and the like
+    pub const FLAG_ASSOCIATED: u8 = 0x04;
+    /// The file continues in the next record
+    pub const FLAG_MULTI_EXTENT: u8 = 0x80;
+
+    pub(crate) fn new(record: &Record, name: String, rock_ridge: Option<RockRidge>) -> Self {
+        Self {
+            name,
+            flags: record.flags,
+            extents: vec![Extent {
+                block: record.extent + record.ear_length as u32,
+                length: record.size,
+            }],
+            recorded: record.recorded,
+            rock_ridge,
+            interleaved: record.unit_size != 0,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.flags & Self::FLAG_DIRECTORY != 0
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.rock_ridge.as_ref().map(|rr| rr.symlink.is_some()).unwrap_or(false)
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.is_dir() && !self.is_symlink()
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.flags & Self::FLAG_HIDDEN != 0
+    }
+
+    pub fn size(&self) -> u64 {
+        self.extents.iter().map(|extent| extent.length as u64).sum()
+    }
+
+    /// The first data block
+    pub fn block(&self) -> u32 {
+        self.extents[0].block
+    }
+}
+
+/// Strips the `;1` version and the trailing dot of names without extension
+pub(crate) fn iso_name(identifier: &[u8], joliet: bool) -> String {
+    let name = descriptor::text(identifier, joliet);
+    let name = match name.rfind(';') {
+        Some(pos) => &name[..pos],
+        None => &name,
+    };
+    name.strip_suffix('.').unwrap_or(name).to_string()
+}
@@ -0,0 +1,42 @@
+use crate::xstd::String;
+
+#[derive(Debug)]
+pub enum IsoError {
+    InvalidVolumeDescriptor(u32), // sector
+    NoPrimaryVolumeDescriptor,
+    InvalidDirectory(u32), // extent
+    InvalidSystemUse(u32), // directory extent
+    InvalidPathTable,
+    InvalidBootCatalog,
+    NoJoliet,
+    NoRockRidge,
+    InterleavedFile(String),
+    NotADirectory(String),
+    NotAFile(String),
+    SymlinkLoop(String),
+}
+
+impl core::fmt::Display for IsoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IsoError::InvalidVolumeDescriptor(sector) => write!(f, "Invalid ISO 9660 volume descriptor in sector {}", sector),
+            IsoError::NoPrimaryVolumeDescriptor => f.write_str("No ISO 9660 primary volume descriptor"),
+            IsoError::InvalidDirectory(extent) => write!(f, "Invalid ISO 9660 directory at block {}", extent),
+            IsoError::InvalidSystemUse(extent) => write!(f, "Invalid Rock Ridge entries in the directory at block {}", extent),
+            IsoError::InvalidPathTable => f.write_str("Invalid ISO 9660 path table"),
+            IsoError::InvalidBootCatalog => f.write_str("Invalid El Torito boot catalog"),
+            IsoError::NoJoliet => f.write_str("No Joliet volume descriptor"),
+            IsoError::NoRockRidge => f.write_str("No Rock Ridge extensions"),
+            IsoError::InterleavedFile(name) => write!(f, "Interleaved file '{}' is not supported", name),
+            IsoError::NotADirectory(path) => write!(f, "'{}' is not a directory", path),
+            IsoError::NotAFile(path) => write!(f, "'{}' is not a file", path),
+            IsoError::SymlinkLoop(path) => write!(f, "Too many levels of symbolic links in '{}'", path),
+        }
+    }
+}
+
+impl From<IsoError> for crate::Error {
+    fn from(e: IsoError) -> Self {
+        Self::Iso(e)
+    }
+}
@@ -0,0 +1,53 @@
+use super::*;
+
+/// Opened file
+pub struct File<'f, R: ReadAt> {
+    fs: &'f IsoFileSystem<R>,
+    entry: DirEntry,
+}
+
+impl<'f, R: ReadAt> File<'f, R> {
+    pub(crate) fn new(fs: &'f IsoFileSystem<R>, entry: DirEntry) -> Self {
+        Self { fs, entry }
+    }
+
+    pub fn entry(&self) -> &DirEntry {
+        &self.entry
+    }
+
+    pub fn size(&self) -> u64 {
+        self.entry.size()
+    }
+
+    /// Reads the whole file
+    pub fn read_all(&self) -> Result<Vec<u8>> {
+        let mut data = vec![0_u8; self.size() as usize];
+        self.read_exact_at(0, &mut data)?;
+        Ok(data)
+    }
+}
+
+impl<'f, R: ReadAt> ReadAt for File<'f, R> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size(), offset, buffer.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::ReadBeyondEOD),
+        };
+
+        // the read stops at the extent end
+        let mut start = 0;
+        for extent in &self.entry.extents {
+            let end = start + extent.length as u64;
+            if offset < end {
+                let len = core::cmp::min(len as u64, end - offset) as usize;
+                let position = extent.block as u64 * self.fs.block_size() as u64 + offset - start;
+                self.fs.device().read_exact_at(position, &mut buffer[..len])?;
+                return Ok(len);
+            }
+            start = end;
+        }
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,854 @@
+use super::descriptor::read_descriptors;
+use super::dir::{iso_name, parse_records, Record};
+use super::rock_ridge::{susp_skip, Parser};
+use super::*;
+use crate::fat;
+
+const MAX_SYMLINKS: usize = 40;
+/// Continuation areas of one record
+const MAX_CONTINUATIONS: usize = 16;
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+
+/// Which directory tree and names are used
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum Names {
+    /// The primary tree, usually upper case 8.3 names
+    Iso,
+    /// The UCS-2 names of the Joliet tree
+    Joliet,
+    /// POSIX names, permissions and symbolic links of the primary tree
+    RockRidge,
+}
+
+/// ISO 9660 volume
+pub struct IsoFileSystem<R: ReadAt> {
+    device: R,
+    primary: VolumeDescriptor,
+    joliet: Option<VolumeDescriptor>,
+    boot_catalog: Option<u32>,
+    /// System use bytes skipped by every record, `Some` if Rock Ridge is present
+    rock_ridge: Option<u8>,
+    names: Names,
+}
+
+impl<R: ReadAt> IsoFileSystem<R> {
+    /// Rock Ridge names are preferred to the Joliet ones
+    pub fn open(device: R) -> Result<Self> {
+        let descriptors = read_descriptors(&device)?;
+        let mut fs = Self {
+            device,
+            primary: descriptors.primary,
+            joliet: descriptors.joliet,
+            boot_catalog: descriptors.boot_catalog,
+            rock_ridge: None,
+            names: Names::Iso,
+        };
+
+        fs.rock_ridge = fs.detect_rock_ridge()?;
+        fs.names = if fs.rock_ridge.is_some() {
+            Names::RockRidge
+        } else if fs.joliet.is_some() {
+            Names::Joliet
+        } else {
+            Names::Iso
+        };
+
+        Ok(fs)
+    }
+
+    /// `SP` and a Rock Ridge `ER` in the root `.` record
+    fn detect_rock_ridge(&self) -> Result<Option<u8>> {
+        let root = &self.primary.root;
+        let records = self.read_records(root.extent + root.ear_length as u32, self.block_size())?;
+        let dot = match records.first() {
+            Some(dot) => dot,
+            None => return Ok(None),
+        };
+
+        match susp_skip(&dot.system_use) {
+            Some(skip) if self.system_use(&dot.system_use, root.extent)?.is_rock_ridge() => Ok(Some(skip)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn device(&self) -> &R {
+        &self.device
+    }
+
+    pub fn into_inner(self) -> R {
+        self.device
+    }
+
+    pub fn primary_descriptor(&self) -> &VolumeDescriptor {
+        &self.primary
+    }
+
+    pub fn joliet_descriptor(&self) -> Option<&VolumeDescriptor> {
+        self.joliet.as_ref()
+    }
+
+    pub fn has_rock_ridge(&self) -> bool {
+        self.rock_ridge.is_some()
+    }
+
+    pub fn names(&self) -> Names {
+        self.names
+    }
+
+    /// Switches to another directory tree if the volume has it
+    pub fn set_names(&mut self, names: Names) -> Result<()> {
+        match names {
+            Names::Joliet if self.joliet.is_none() => Err(Error::from(IsoError::NoJoliet)),
+            Names::RockRidge if self.rock_ridge.is_none() => Err(Error::from(IsoError::NoRockRidge)),
+            _ => {
+                self.names = names;
+                Ok(())
+            }
+        }
+    }
+
+    /// The descriptor of the tree in use
+    fn descriptor(&self) -> &VolumeDescriptor {
+        match (self.names, &self.joliet) {
+            (Names::Joliet, Some(joliet)) => joliet,
+            _ => &self.primary,
+        }
+    }
+
+    pub fn volume_label(&self) -> &str {
+        &self.descriptor().volume_id
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.primary.block_size as u32
+    }
+
+    /// Reads blocks of the volume
+    fn read_extent(&self, block: u32, size: u32) -> Result<Vec<u8>> {
+        let block_size = self.block_size() as u64;
+        let blocks = math::ceil(size as u64, block_size);
+        if block as u64 + blocks > self.primary.volume_space_size as u64 {
+            return Err(Error::ReadBeyondEOD);
+        }
+
+        let mut data = vec![0_u8; size as usize];
+        self.device.read_exact_at(block as u64 * block_size, &mut data)?;
+        Ok(data)
+    }
+
+    fn read_records(&self, extent: u32, size: u32) -> Result<Vec<Record>> {
+        let data = self.read_extent(extent, size).map_err(|e| match e {
+            Error::ReadBeyondEOD => Error::from(IsoError::InvalidDirectory(extent)),
+            e => e,
+        })?;
+
+        parse_records(&data, extent)
+    }
+
+    /// Parses the system use area following the continuation areas
+    fn system_use(&self, area: &[u8], extent: u32) -> Result<Parser> {
+        let mut parser = Parser::default();
+        let mut next = parser.parse(area);
+        let mut continuations = 0;
+        while let Some((block, offset, length)) = next {
+            continuations += 1;
+            if continuations > MAX_CONTINUATIONS || offset + length > self.block_size() {
+                return Err(Error::from(IsoError::InvalidSystemUse(extent)));
+            }
+
+            let data = self.read_extent(block, offset + length)?;
+            next = parser.parse(&data[offset as usize..]);
+        }
+
+        Ok(parser)
+    }
+
+    pub fn root(&self) -> Result<DirEntry> {
+        let mut root = DirEntry::new(&self.descriptor().root, String::new(), None);
+        if self.names == Names::RockRidge {
+            // the attributes are in the `.` record, which starts with `SP`
+            let records = self.read_records(root.block(), self.block_size())?;
+            if let Some(dot) = records.first() {
+                root.rock_ridge = Some(self.system_use(&dot.system_use, root.block())?.finish());
+            }
+        }
+
+        Ok(root)
+    }
+
+    /// `None` for the Rock Ridge relocated directories, they are listed where they belong
+    fn dir_entry(&self, record: &Record, dir: u32) -> Result<Option<DirEntry>> {
+        let name = iso_name(&record.identifier, self.names == Names::Joliet);
+        if self.names != Names::RockRidge {
+            return Ok(Some(DirEntry::new(record, name, None)));
+        }
+
+        let skip = self.rock_ridge.unwrap_or(0) as usize;
+        let rock_ridge = self.system_use(record.system_use.get(skip..).unwrap_or(&[]), dir)?.finish();
+        if rock_ridge.relocated {
+            return Ok(None);
+        }
+
+        let mut entry = DirEntry::new(record, rock_ridge.name.clone().unwrap_or(name), None);
+        if let Some(child) = rock_ridge.child_link {
+            // the size of the moved directory is in its `.` record
+            let dot = self.read_records(child, self.block_size())?;
+            let dot = dot.first().ok_or_else(|| Error::from(IsoError::InvalidDirectory(child)))?;
+            entry.flags |= DirEntry::FLAG_DIRECTORY;
+            entry.extents = vec![Extent {
+                block: child,
+                length: dot.size,
+            }];
+        }
+        entry.rock_ridge = Some(rock_ridge);
+
+        Ok(Some(entry))
+    }
+
+    /// Lists the directory, `.`, `..` and associated files are skipped
+    pub fn read_dir_entry(&self, dir: &DirEntry) -> Result<Vec<DirEntry>> {
+        if !dir.is_dir() {
+            return Err(Error::from(IsoError::NotADirectory(dir.name.clone())));
+        }
+
+        let extent = dir.block();
+        let mut entries: Vec<DirEntry> = Vec::new();
+        // files over 4 GiB continue in the next records
+        let mut continues = false;
+        for record in self.read_records(extent, dir.extents[0].length)? {
+            if record.is_self_or_parent() || record.flags & DirEntry::FLAG_ASSOCIATED != 0 {
+                continue;
+            }
+
+            if continues {
+                if let Some(last) = entries.last_mut() {
+                    last.extents.push(Extent {
+                        block: record.extent + record.ear_length as u32,
+                        length: record.size,
+                    });
+                }
+            } else if let Some(entry) = self.dir_entry(&record, extent)? {
+                entries.push(entry);
+            }
+            continues = record.flags & DirEntry::FLAG_MULTI_EXTENT != 0;
+        }
+
+        Ok(entries)
+    }
+
+    /// Symbolic links are followed
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let dir = self.resolve(path, true)?;
+        self.read_dir_entry(&dir)
+    }
+
+    /// Looks the path up, the final symbolic link is not followed.
+    ///
+    /// Rock Ridge names are case sensitive, Joliet and ISO 9660 ones are not.
+    pub fn entry(&self, path: &str) -> Result<DirEntry> {
+        self.resolve(path, false)
+    }
+
+    pub fn open_entry(&self, entry: &DirEntry) -> Result<File<'_, R>> {
+        if !entry.is_file() {
+            return Err(Error::from(IsoError::NotAFile(entry.name.clone())));
+        }
+        if entry.interleaved {
+            return Err(Error::from(IsoError::InterleavedFile(entry.name.clone())));
+        }
+
+        Ok(File::new(self, entry.clone()))
+    }
+
+    /// Symbolic links are followed
+    pub fn open_file(&self, path: &str) -> Result<File<'_, R>> {
+        let entry = self.resolve(path, true)?;
+        self.open_entry(&entry)
+    }
+
+    /// Target of the Rock Ridge symbolic link
+    pub fn read_link(&self, path: &str) -> Result<String> {
+        let entry = self.resolve(path, false)?;
+        match entry.rock_ridge.and_then(|rock_ridge| rock_ridge.symlink) {
+            Some(target) => Ok(target),
+            None => Err(Error::NotASymlink(path.to_string())),
+        }
+    }
+
+    fn same_name(&self, a: &str, b: &str) -> bool {
+        match self.names {
+            Names::RockRidge => a == b,
+            _ => a.chars().flat_map(char::to_lowercase).eq(b.chars().flat_map(char::to_lowercase)),
+        }
+    }
+
+    /// Walks the path, `follow_last` also follows the final component if it is a link
+    fn resolve(&self, path: &str, follow_last: bool) -> Result<DirEntry> {
+        // components still to visit, in reverse order
+        let mut pending: Vec<String> = path.rsplit('/').map(|name| name.to_string()).collect();
+        let mut stack = vec![self.root()?];
+        let mut links = 0;
+
+        while let Some(name) = pending.pop() {
+            match name.as_str() {
+                "" | "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+
+            let dir = stack.last().expect("root is always present");
+            if !dir.is_dir() {
+                return Err(Error::from(IsoError::NotADirectory(path.to_string())));
+            }
+
+            let entry = self
+                .read_dir_entry(dir)?
+                .into_iter()
+                .find(|entry| self.same_name(&entry.name, &name))
+                .ok_or_else(|| Error::NotFound(path.to_string()))?;
+            let is_last = pending.iter().all(|name| name.is_empty() || name == ".");
+            let target = entry.rock_ridge.as_ref().and_then(|rock_ridge| rock_ridge.symlink.clone());
+            match target {
+                Some(target) if follow_last || !is_last => {
+                    links += 1;
+                    if links > MAX_SYMLINKS {
+                        return Err(Error::from(IsoError::SymlinkLoop(path.to_string())));
+                    }
+
+                    if target.starts_with('/') {
+                        stack.truncate(1);
+                    }
+                    pending.extend(target.rsplit('/').map(|name| name.to_string()));
+                }
+                _ => stack.push(entry),
+            }
+        }
+
+        Ok(stack.pop().expect("root is always present"))
+    }
+
+    /// Directories of the tree in use, level by level
+    pub fn path_table(&self) -> Result<Vec<PathTableEntry>> {
+        let descriptor = self.descriptor();
+        let bytes = self.read_extent(descriptor.path_table, descriptor.path_table_size)?;
+        path_table::parse(&bytes, descriptor.is_joliet())
+    }
+
+    /// `None` if the volume is not bootable
+    pub fn boot_catalog(&self) -> Result<Option<BootCatalog>> {
+        match self.boot_catalog {
+            Some(block) => Ok(Some(BootCatalog::parse(&self.read_extent(block, SECTOR_SIZE as u32)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Floppy images have the emulated size and hard disk ones end with their last partition.
+    ///
+    /// The catalog does not store the size of no emulation images: FAT images (EFI system partitions)
+    /// take the size from their boot sector, other ones are as large as the BIOS loads.
+    pub fn open_boot_image(&self, entry: &BootEntry) -> Result<BootImage<'_, R>> {
+        let offset = entry.load_rba as u64 * self.block_size() as u64;
+        let mut first = vec![0_u8; 512];
+        self.device.read_exact_at(offset, &mut first)?;
+
+        let size = match entry.media {
+            BootMedia::HardDisk => boot::hard_disk_size(&first),
+            media => media.floppy_size(),
+        };
+        let size = size.unwrap_or_else(|| match fat::BootSector::parse(&first) {
+            Ok(boot) => core::cmp::max(boot.total_sectors as u64 * boot.bytes_per_sector as u64, entry.loaded_size()),
+            Err(_) => entry.loaded_size(),
+        });
+
+        Ok(BootImage::new(&self.device, offset, size))
+    }
+
+    /// The system area holds an MBR or a GPT, so the device can be read as a partitioned disk too
+    pub fn is_hybrid(&self) -> Result<bool> {
+        let mut sectors = [0_u8; 1024];
+        self.device.read_exact_at(0, &mut sectors)?;
+
+        let mbr = sectors[510..512] == MBR_SIGNATURE && sectors[446..510].chunks_exact(16).any(|entry| entry[4] != 0);
+        Ok(mbr || &sectors[512..520] == GPT_SIGNATURE)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+    use crate::{DiskLayout, Partition};
+
+    const BLOCK: usize = SECTOR_SIZE;
+    const PVD: usize = 16;
+    const BOOT_RECORD: usize = 17;
+    const SVD: usize = 18;
+    const TERMINATOR: usize = 19;
+    const PATH_TABLE: usize = 20;
+    const JOLIET_PATH_TABLE: usize = 21;
+    const CATALOG: usize = 22;
+    const ROOT: usize = 23;
+    const DIR: usize = 24;
+    const JOLIET_ROOT: usize = 25;
+    const JOLIET_DIR: usize = 26;
+    const CONTINUATION: usize = 27;
+    const HELLO: usize = 28;
+    const BIG: usize = 29;
+    const DEEP: usize = 31;
+    const RR_MOVED: usize = 32;
+    const LOADER: usize = 33;
+    const EFI: usize = 34;
+    const EFI_SIZE: usize = 256 * 1024;
+    const BLOCKS: usize = EFI + EFI_SIZE / BLOCK;
+
+    const HELLO_DATA: &[u8] = b"Hello, World!";
+    const BIG_TAIL: u32 = 100;
+    /// 2021-01-02 12:30:45 +02:00
+    const RECORDED: [u8; 7] = [121, 1, 2, 12, 30, 45, 8];
+    const RECORDED_UNIX: i64 = 1_609_583_445;
+
+    fn both_u16(bytes: &mut [u8], pos: usize, value: u16) {
+        bytes[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+        bytes[pos + 2..pos + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn both_u32(bytes: &mut [u8], pos: usize, value: u32) {
+        bytes[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+        bytes[pos + 4..pos + 8].copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn both(value: u32) -> [u8; 8] {
+        let mut bytes = [0_u8; 8];
+        both_u32(&mut bytes, 0, value);
+        bytes
+    }
+
+    fn ucs2(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|c| c.to_be_bytes().to_vec()).collect()
+    }
+
+    fn susp(signature: &[u8; 2], data: &[u8]) -> Vec<u8> {
+        let mut entry = vec![signature[0], signature[1], 4 + data.len() as u8, 1];
+        entry.extend_from_slice(data);
+        entry
+    }
+
+    fn px(mode: u32, inode: u32) -> Vec<u8> {
+        let data: Vec<u8> = [mode, 1, 1000, 100, inode].iter().flat_map(|&value| both(value).to_vec()).collect();
+        susp(b"PX", &data)
+    }
+
+    fn nm(flags: u8, name: &str) -> Vec<u8> {
+        let mut data = vec![flags];
+        data.extend_from_slice(name.as_bytes());
+        susp(b"NM", &data)
+    }
+
+    fn sl(flags: u8, components: &[(u8, &str)]) -> Vec<u8> {
+        let mut data = vec![flags];
+        for (flags, content) in components {
+            data.extend_from_slice(&[*flags, content.len() as u8]);
+            data.extend_from_slice(content.as_bytes());
+        }
+        susp(b"SL", &data)
+    }
+
+    fn cl(block: usize) -> Vec<u8> {
+        susp(b"CL", &both(block as u32))
+    }
+
+    /// Writes the directory record, returns the position of the next one
+    fn record(dir: &mut [u8], pos: usize, extent: usize, size: u32, flags: u8, id: &[u8], system_use: &[u8]) -> usize {
+        let id_end = 33 + id.len() + (1 - id.len() % 2);
+        let length = id_end + system_use.len();
+        let length = length + length % 2;
+        let record = &mut dir[pos..pos + length];
+        record[0] = length as u8;
+        both_u32(record, 2, extent as u32);
+        both_u32(record, 10, size);
+        record[18..25].copy_from_slice(&RECORDED);
+        record[25] = flags;
+        both_u16(record, 28, 1);
+        record[32] = id.len() as u8;
+        record[33..33 + id.len()].copy_from_slice(id);
+        record[id_end..id_end + system_use.len()].copy_from_slice(system_use);
+        pos + length
+    }
+
+    fn dir_block(image: &mut [u8], block: usize) -> &mut [u8] {
+        &mut image[block * BLOCK..(block + 1) * BLOCK]
+    }
+
+    fn descriptor(image: &mut [u8], sector: usize, kind: u8, volume_id: &[u8], root: usize, path_table: usize, path_table_size: u32) {
+        let bytes = dir_block(image, sector);
+        bytes[0] = kind;
+        bytes[1..6].copy_from_slice(STANDARD_ID);
+        bytes[6] = 1;
+        // Joliet pads with UCS-2 spaces
+        let space = if kind == 2 { [0, b' '] } else { [b' ', b' '] };
+        bytes[8..72].chunks_exact_mut(2).for_each(|pair| pair.copy_from_slice(&space));
+        bytes[40..40 + volume_id.len()].copy_from_slice(volume_id);
+        both_u32(bytes, 80, BLOCKS as u32);
+        both_u16(bytes, 120, 1);
+        both_u16(bytes, 124, 1);
+        both_u16(bytes, 128, BLOCK as u16);
+        both_u32(bytes, 132, path_table_size);
+        bytes[140..144].copy_from_slice(&(path_table as u32).to_le_bytes());
+        record(bytes, 156, root, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[0], &[]);
+        bytes[813..830].copy_from_slice(b"2021010212304500\x08");
+        bytes[881] = 1;
+    }
+
+    fn path_table(image: &mut [u8], block: usize, entries: &[(&[u8], usize, u16)]) -> u32 {
+        let table = dir_block(image, block);
+        let mut pos = 0;
+        for (id, extent, parent) in entries {
+            table[pos] = id.len() as u8;
+            table[pos + 2..pos + 6].copy_from_slice(&(*extent as u32).to_le_bytes());
+            table[pos + 6..pos + 8].copy_from_slice(&parent.to_le_bytes());
+            table[pos + 8..pos + 8 + id.len()].copy_from_slice(id);
+            pos += 8 + id.len() + id.len() % 2;
+        }
+        pos as u32
+    }
+
+    fn boot_catalog(image: &mut [u8]) {
+        let catalog = dir_block(image, CATALOG);
+        catalog[0] = 1;
+        catalog[4..9].copy_from_slice(b"RDISK");
+        catalog[30] = 0x55;
+        catalog[31] = 0xAA;
+        let sum = catalog[..32]
+            .chunks_exact(2)
+            .fold(0_u16, |sum, word| sum.wrapping_add(u16::from_le_bytes([word[0], word[1]])));
+        catalog[28..30].copy_from_slice(&0_u16.wrapping_sub(sum).to_le_bytes());
+
+        // isolinux like loader
+        catalog[32] = 0x88;
+        catalog[38..40].copy_from_slice(&4_u16.to_le_bytes());
+        catalog[40..44].copy_from_slice(&(LOADER as u32).to_le_bytes());
+
+        // EFI system partition image
+        catalog[64] = 0x91;
+        catalog[65] = 0xEF;
+        catalog[66..68].copy_from_slice(&1_u16.to_le_bytes());
+        catalog[96] = 0x88;
+        catalog[102..104].copy_from_slice(&1_u16.to_le_bytes());
+        catalog[104..108].copy_from_slice(&(EFI as u32).to_le_bytes());
+    }
+
+    fn rock_ridge_tree(image: &mut [u8]) {
+        let er = {
+            let mut data = vec![10, 0, 0, 1];
+            data.extend_from_slice(b"RRIP_1991A");
+            susp(b"ER", &data)
+        };
+        let continuation = dir_block(image, CONTINUATION);
+        continuation[100..100 + er.len()].copy_from_slice(&er);
+
+        let mut dot = susp(b"SP", &[0xBE, 0xEF, 0]);
+        let mut ce = both(CONTINUATION as u32).to_vec();
+        ce.extend_from_slice(&both(100));
+        ce.extend_from_slice(&both(er.len() as u32));
+        dot.extend(susp(b"CE", &ce));
+        dot.extend(px(0o040_755, 1));
+
+        let hello = [
+            nm(1, "hello "),
+            nm(0, "world.txt"),
+            px(0o100_644, 2),
+            susp(b"TF", &[0x02, 121, 1, 2, 12, 30, 45, 8]),
+        ]
+        .concat();
+        let big = [nm(0, "big.bin"), px(0o100_644, 3)].concat();
+        let link = [
+            nm(0, "link"),
+            px(0o120_777, 4),
+            sl(1, &[(0, "dir"), (0x04, "")]),
+            sl(0, &[(1, "hello wo"), (0, "rld.txt")]),
+        ]
+        .concat();
+        let looped = [nm(0, "loop"), sl(0, &[(0, "loop")])].concat();
+
+        let root = dir_block(image, ROOT);
+        let pos = record(root, 0, ROOT, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[0], &dot);
+        let pos = record(root, pos, ROOT, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[1], &[]);
+        let pos = record(root, pos, BIG, BLOCK as u32, DirEntry::FLAG_MULTI_EXTENT, b"BIG.BIN;1", &big);
+        let pos = record(root, pos, BIG + 1, BIG_TAIL, 0, b"BIG.BIN;1", &big);
+        let pos = record(
+            root,
+            pos,
+            DIR,
+            BLOCK as u32,
+            DirEntry::FLAG_DIRECTORY,
+            b"DIR",
+            &[nm(0, "dir"), px(0o040_755, 5)].concat(),
+        );
+        let pos = record(root, pos, HELLO, HELLO_DATA.len() as u32, 0, b"HELLO.TXT;1", &hello);
+        let pos = record(root, pos, 0, 0, 0, b"LINK.;1", &link);
+        let pos = record(root, pos, 0, 0, 0, b"LOOP.;1", &looped);
+        record(
+            root,
+            pos,
+            RR_MOVED,
+            BLOCK as u32,
+            DirEntry::FLAG_DIRECTORY,
+            b"RR_MOVED",
+            &nm(0, "rr_moved"),
+        );
+
+        // `deep` is relocated to `rr_moved` and linked from `dir`
+        let dir = dir_block(image, DIR);
+        let pos = record(dir, 0, DIR, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[0], &[]);
+        let pos = record(dir, pos, ROOT, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[1], &[]);
+        record(dir, pos, 0, 0, 0, b"DEEP.;1", &[nm(0, "deep"), cl(DEEP)].concat());
+
+        let deep = dir_block(image, DEEP);
+        let pos = record(deep, 0, DEEP, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[0], &[]);
+        let pos = record(deep, pos, RR_MOVED, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[1], &[]);
+        record(deep, pos, HELLO, HELLO_DATA.len() as u32, 0, b"FILE.TXT;1", &nm(0, "file.txt"));
+
+        let moved = dir_block(image, RR_MOVED);
+        let pos = record(moved, 0, RR_MOVED, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[0], &[]);
+        let pos = record(moved, pos, ROOT, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[1], &[]);
+        record(
+            moved,
+            pos,
+            DEEP,
+            BLOCK as u32,
+            DirEntry::FLAG_DIRECTORY,
+            b"DEEP",
+            &[nm(0, "deep"), susp(b"RE", &[])].concat(),
+        );
+    }
+
+    fn joliet_tree(image: &mut [u8]) {
+        let root = dir_block(image, JOLIET_ROOT);
+        let pos = record(root, 0, JOLIET_ROOT, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[0], &[]);
+        let pos = record(root, pos, JOLIET_ROOT, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[1], &[]);
+        let pos = record(root, pos, BIG, BLOCK as u32, DirEntry::FLAG_MULTI_EXTENT, &ucs2("big.bin;1"), &[]);
+        let pos = record(root, pos, BIG + 1, BIG_TAIL, 0, &ucs2("big.bin;1"), &[]);
+        let pos = record(root, pos, JOLIET_DIR, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &ucs2("dir"), &[]);
+        record(root, pos, HELLO, HELLO_DATA.len() as u32, 0, &ucs2("hello world.txt;1"), &[]);
+
+        let dir = dir_block(image, JOLIET_DIR);
+        let pos = record(dir, 0, JOLIET_DIR, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[0], &[]);
+        record(dir, pos, JOLIET_ROOT, BLOCK as u32, DirEntry::FLAG_DIRECTORY, &[1], &[]);
+    }
+
+    pub fn image() -> Vec<u8> {
+        let mut image = vec![0_u8; BLOCKS * BLOCK];
+
+        let size = path_table(
+            &mut image,
+            PATH_TABLE,
+            &[(&[0], ROOT, 1), (b"DIR", DIR, 1), (b"RR_MOVED", RR_MOVED, 1), (b"DEEP", DEEP, 3)],
+        );
+        descriptor(&mut image, PVD, 1, b"RDISK_TEST", ROOT, PATH_TABLE, size);
+        let size = path_table(
+            &mut image,
+            JOLIET_PATH_TABLE,
+            &[(&[0], JOLIET_ROOT, 1), (&ucs2("dir"), JOLIET_DIR, 1)],
+        );
+        descriptor(&mut image, SVD, 2, &ucs2("rdisk test"), JOLIET_ROOT, JOLIET_PATH_TABLE, size);
+        dir_block(&mut image, SVD)[88..91].copy_from_slice(b"%/E");
+
+        let boot_record = dir_block(&mut image, BOOT_RECORD);
+        boot_record[1..6].copy_from_slice(STANDARD_ID);
+        boot_record[6] = 1;
+        boot_record[7..30].copy_from_slice(b"EL TORITO SPECIFICATION");
+        boot_record[71..75].copy_from_slice(&(CATALOG as u32).to_le_bytes());
+        let terminator = dir_block(&mut image, TERMINATOR);
+        terminator[0] = 255;
+        terminator[1..6].copy_from_slice(STANDARD_ID);
+        terminator[6] = 1;
+
+        boot_catalog(&mut image);
+        rock_ridge_tree(&mut image);
+        joliet_tree(&mut image);
+
+        image[HELLO * BLOCK..HELLO * BLOCK + HELLO_DATA.len()].copy_from_slice(HELLO_DATA);
+        image[BIG * BLOCK..(BIG + 1) * BLOCK].iter_mut().for_each(|b| *b = 1);
+        image[(BIG + 1) * BLOCK..(BIG + 1) * BLOCK + BIG_TAIL as usize]
+            .iter_mut()
+            .for_each(|b| *b = 2);
+        image[LOADER * BLOCK..LOADER * BLOCK + 4].copy_from_slice(b"BOOT");
+
+        let efi = MemoryDisk::from_vec(vec![0_u8; EFI_SIZE]);
+        let options = fat::FormatOptions {
+            label: "EFIBOOT".to_string(),
+            volume_id: Some(1),
+            ..Default::default()
+        };
+        fat::format(&efi, &options).unwrap();
+        image[EFI * BLOCK..].copy_from_slice(&efi.to_vec());
+
+        image
+    }
+
+    fn names(entries: Vec<DirEntry>) -> Vec<String> {
+        let mut names: Vec<String> = entries.into_iter().map(|entry| entry.name).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn iso9660_test() {
+        let mut fs = IsoFileSystem::open(MemoryDisk::from_vec(image())).unwrap();
+        assert_eq!(Names::RockRidge, fs.names());
+        assert_eq!("RDISK_TEST", fs.volume_label());
+        assert_eq!(2048, fs.block_size());
+        assert_eq!(Some(3), fs.joliet_descriptor().unwrap().joliet_level);
+        let created = fs.primary_descriptor().created.unwrap();
+        assert_eq!(120, created.utc_offset);
+        assert_eq!(RECORDED_UNIX, created.to_unix_time());
+        assert!(!fs.is_hybrid().unwrap());
+
+        let root = fs.root().unwrap();
+        assert_eq!(Some(0o040_755), root.rock_ridge.unwrap().mode);
+        assert_eq!(
+            vec!["big.bin", "dir", "hello world.txt", "link", "loop", "rr_moved"],
+            names(fs.read_dir("/").unwrap())
+        );
+
+        let hello = fs.entry("/hello world.txt").unwrap();
+        assert!(hello.is_file());
+        assert_eq!(HELLO_DATA.len() as u64, hello.size());
+        assert_eq!(RECORDED_UNIX, hello.recorded.unwrap().to_unix_time());
+        let rock_ridge = hello.rock_ridge.unwrap();
+        assert_eq!(Some(0o100_644), rock_ridge.mode);
+        assert_eq!((Some(1000), Some(100), Some(2)), (rock_ridge.uid, rock_ridge.gid, rock_ridge.inode));
+        assert_eq!(Some(RECORDED_UNIX), rock_ridge.modified.map(|time| time.to_unix_time()));
+        assert!(rock_ridge.created.is_none());
+        assert_eq!(HELLO_DATA, fs.open_file("hello world.txt").unwrap().read_all().unwrap().as_slice());
+        match fs.entry("HELLO WORLD.TXT") {
+            Err(Error::NotFound(_)) => (),
+            _ => panic!("not found expected"),
+        }
+
+        let big = fs.open_file("big.bin").unwrap();
+        assert_eq!(2, big.entry().extents.len());
+        assert_eq!(BLOCK as u64 + BIG_TAIL as u64, big.size());
+        let data = big.read_all().unwrap();
+        assert!(data[..BLOCK].iter().all(|&b| b == 1));
+        assert!(data[BLOCK..].iter().all(|&b| b == 2));
+        let mut buffer = [0_u8; 16];
+        assert_eq!(8, big.read_at(BLOCK as u64 - 8, &mut buffer).unwrap());
+
+        assert!(fs.entry("link").unwrap().is_symlink());
+        assert_eq!("dir/../hello world.txt", fs.read_link("link").unwrap());
+        assert_eq!(HELLO_DATA, fs.open_file("link").unwrap().read_all().unwrap().as_slice());
+        match fs.open_file("loop") {
+            Err(Error::Iso(IsoError::SymlinkLoop(_))) => (),
+            _ => panic!("symlink loop expected"),
+        }
+        match fs.read_link("dir") {
+            Err(Error::NotASymlink(_)) => (),
+            _ => panic!("not a symlink expected"),
+        }
+
+        // relocated directories
+        assert_eq!(vec!["deep"], names(fs.read_dir("dir").unwrap()));
+        assert!(fs.entry("dir/deep").unwrap().is_dir());
+        assert!(fs.read_dir("rr_moved").unwrap().is_empty());
+        assert_eq!(
+            HELLO_DATA,
+            fs.open_file("dir/deep/file.txt").unwrap().read_all().unwrap().as_slice()
+        );
+
+        fs.set_names(Names::Joliet).unwrap();
+        assert_eq!("rdisk test", fs.volume_label());
+        assert_eq!(vec!["big.bin", "dir", "hello world.txt"], names(fs.read_dir("/").unwrap()));
+        assert_eq!(HELLO_DATA, fs.open_file("/HELLO World.txt").unwrap().read_all().unwrap().as_slice());
+        assert_eq!(BLOCK as u64 + BIG_TAIL as u64, fs.open_file("big.bin").unwrap().size());
+        let table = fs.path_table().unwrap();
+        assert_eq!(vec!["", "dir"], table.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>());
+
+        fs.set_names(Names::Iso).unwrap();
+        assert_eq!(
+            vec!["BIG.BIN", "DIR", "HELLO.TXT", "LINK", "LOOP", "RR_MOVED"],
+            names(fs.read_dir("/").unwrap())
+        );
+        assert_eq!(HELLO_DATA, fs.open_file("hello.txt").unwrap().read_all().unwrap().as_slice());
+        let table = fs.path_table().unwrap();
+        assert_eq!(
+            vec![("", ROOT, 1), ("DIR", DIR, 1), ("RR_MOVED", RR_MOVED, 1), ("DEEP", DEEP, 3)],
+            table
+                .iter()
+                .map(|entry| (entry.name.as_str(), entry.extent as usize, entry.parent))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn el_torito_test() {
+        let fs = IsoFileSystem::open(MemoryDisk::from_vec(image())).unwrap();
+        let catalog = fs.boot_catalog().unwrap().unwrap();
+        assert_eq!(Platform::X86, catalog.platform);
+        assert_eq!("RDISK", catalog.id);
+        assert_eq!(2, catalog.entries.len());
+
+        let loader = &catalog.entries[0];
+        assert!(loader.bootable);
+        assert_eq!(BootMedia::NoEmulation, loader.media);
+        let image = fs.open_boot_image(loader).unwrap();
+        assert_eq!(2048, image.size());
+        let mut magic = [0_u8; 4];
+        image.read_exact_at(0, &mut magic).unwrap();
+        assert_eq!(b"BOOT", &magic);
+
+        let efi = &catalog.entries[1];
+        assert_eq!(Platform::Efi, efi.platform);
+        assert_eq!(512, efi.loaded_size());
+        let image = fs.open_boot_image(efi).unwrap();
+        assert_eq!(EFI_SIZE as u64, image.size());
+        assert_eq!((EFI * BLOCK) as u64, image.offset());
+        let efi = fat::FatFileSystem::open(image).unwrap();
+        assert_eq!("EFIBOOT", efi.volume_label());
+
+        let mut image = self::image();
+        dir_block(&mut image, CATALOG)[4] ^= 1;
+        let fs = IsoFileSystem::open(MemoryDisk::from_vec(image)).unwrap();
+        match fs.boot_catalog() {
+            Err(Error::Iso(IsoError::InvalidBootCatalog)) => (),
+            _ => panic!("invalid catalog expected"),
+        }
+    }
+
+    #[test]
+    fn hybrid_test() {
+        // isohybrid: the whole image and the EFI image as MBR partitions
+        let mut image = image();
+        let mbr = &mut image[..512];
+        mbr[446] = 0x80;
+        mbr[450] = 0x17;
+        mbr[458..462].copy_from_slice(&((BLOCKS * 4) as u32).to_le_bytes());
+        mbr[466] = 0xEF;
+        mbr[470..474].copy_from_slice(&((EFI * 4) as u32).to_le_bytes());
+        mbr[474..478].copy_from_slice(&((EFI_SIZE / 512) as u32).to_le_bytes());
+        mbr[510] = 0x55;
+        mbr[511] = 0xAA;
+        let disk = MemoryDisk::from_vec(image);
+
+        // partitions starting at the first sector are not listed
+        let layout = DiskLayout::read(&disk).unwrap();
+        let partitions: Vec<PartitionInfo> = layout.partitions().collect();
+        assert_eq!(1, partitions.len());
+        assert_eq!((EFI * BLOCK) as u64, partitions[0].offset);
+        let partition = Partition::new(&disk, partitions.into_iter().next().unwrap());
+        assert_eq!(Some(crate::FsKind::Fat12), partition.filesystem().map(|info| info.kind));
+
+        let fs = IsoFileSystem::open(&disk).unwrap();
+        assert!(fs.is_hybrid().unwrap());
+        assert_eq!(HELLO_DATA, fs.open_file("hello world.txt").unwrap().read_all().unwrap().as_slice());
+        let catalog = fs.boot_catalog().unwrap().unwrap();
+        assert_eq!(partition.offset(), fs.open_boot_image(&catalog.entries[1]).unwrap().offset());
+    }
+}
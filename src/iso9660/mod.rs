@@ -0,0 +1,40 @@
+//! ISO 9660 filesystem with the Joliet and Rock Ridge extensions and El Torito boot images, read only
+//!
+//! The system area (the first 16 sectors) is not used by ISO 9660, hybrid images keep an MBR or a GPT there,
+//! so `DiskLayout` and `IsoFileSystem` can read the same device.
+//! See ECMA-119, the Joliet specification, IEEE P1281 (SUSP), P1282 (RRIP) and the El Torito specification
+use crate::prelude::*;
+use crate::tools::{le_u16, le_u32};
+
+mod error;
+pub use error::IsoError;
+
+mod descriptor;
+pub use descriptor::{Timestamp, VolumeDescriptor, VolumeKind};
+
+mod path_table;
+pub use path_table::PathTableEntry;
+
+mod rock_ridge;
+pub use rock_ridge::RockRidge;
+
+mod dir;
+pub use dir::{DirEntry, Extent};
+
+mod boot;
+pub use boot::{BootCatalog, BootEntry, BootImage, BootMedia, Platform};
+
+mod file;
+pub use file::File;
+
+mod fs;
+#[cfg(test)]
+pub(crate) use fs::tests::image as test_image;
+pub use fs::{IsoFileSystem, Names};
+
+pub use crate::fat::DateTime;
+
+pub const SECTOR_SIZE: usize = 2048;
+const FIRST_DESCRIPTOR: u32 = 16;
+const STANDARD_ID: &[u8] = b"CD001";
+
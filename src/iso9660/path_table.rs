@@ -0,0 +1,41 @@
+use super::*;
+
+const ENTRY_HEADER_SIZE: usize = 8;
+
+/// Path table record, directories are listed level by level
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct PathTableEntry {
+    /// Empty for the root directory
+    pub name: String,
+    pub extent: u32,
+    /// 1-based index of the parent entry, the root is its own parent
+    pub parent: u16,
+}
+
+/// Little endian table
+pub(crate) fn parse(bytes: &[u8], joliet: bool) -> Result<Vec<PathTableEntry>> {
+    let invalid = || Error::from(IsoError::InvalidPathTable);
+
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos + ENTRY_HEADER_SIZE <= bytes.len() {
+        let id_length = bytes[pos] as usize;
+        let id = bytes
+            .get(pos + ENTRY_HEADER_SIZE..pos + ENTRY_HEADER_SIZE + id_length)
+            .ok_or_else(invalid)?;
+        let parent = le_u16(bytes, pos + 6);
+        if id_length == 0 || parent == 0 || parent as usize > entries.len() + 1 {
+            return Err(invalid());
+        }
+
+        entries.push(PathTableEntry {
+            name: if id == [0] { String::new() } else { descriptor::text(id, joliet) },
+            extent: le_u32(bytes, pos + 2) + bytes[pos + 1] as u32,
+            parent,
+        });
+        pos += ENTRY_HEADER_SIZE + id_length + id_length % 2;
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,190 @@
+use super::*;
+
+const ENTRY_HEADER_SIZE: usize = 4;
+const SP_CHECK: [u8; 2] = [0xBE, 0xEF];
+/// RRIP 1.10, 1.12 and the RRIP 1.09 one used by old mkisofs
+const RRIP_IDS: [&[u8]; 3] = [b"RRIP_1991A", b"IEEE_P1282", b"IEEE_1282"];
+
+const NAME_CONTINUE: u8 = 0x01;
+const NAME_CURRENT: u8 = 0x02;
+const NAME_PARENT: u8 = 0x04;
+const COMPONENT_ROOT: u8 = 0x08;
+
+const TF_CREATION: u8 = 0x01;
+const TF_MODIFY: u8 = 0x02;
+const TF_ACCESS: u8 = 0x04;
+const TF_ATTRIBUTES: u8 = 0x08;
+const TF_LONG_FORM: u8 = 0x80;
+
+/// POSIX attributes from the Rock Ridge entries of a directory record
+#[derive(Clone, Default)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct RockRidge {
+    /// `st_mode`: file type and permission bits
+    pub mode: Option<u32>,
+    pub links: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// RRIP 1.12 file serial number
+    pub inode: Option<u32>,
+    pub name: Option<String>,
+    pub symlink: Option<String>,
+    pub created: Option<Timestamp>,
+    pub modified: Option<Timestamp>,
+    pub accessed: Option<Timestamp>,
+    /// Attribute change time
+    pub changed: Option<Timestamp>,
+    /// `CL`: the directory was moved to this block to limit the depth
+    pub(crate) child_link: Option<u32>,
+    /// `RE`: the moved directory, listed through its `CL` entry instead
+    pub(crate) relocated: bool,
+}
+
+/// Continuation area: block, offset and length
+pub(crate) type Continuation = (u32, u32, u32);
+
+/// Assembles the names and links split across entries and continuation areas
+#[derive(Default)]
+pub(crate) struct Parser {
+    rock_ridge: RockRidge,
+    name: Option<String>,
+    name_continues: bool,
+    symlink: Option<String>,
+    component_continues: bool,
+    rrip: bool,
+}
+
+impl Parser {
+    /// Parses a system use area, returns the continuation area if there is one
+    pub fn parse(&mut self, area: &[u8]) -> Option<Continuation> {
+        let mut continuation = None;
+        let mut pos = 0;
+        while pos + ENTRY_HEADER_SIZE <= area.len() {
+            let length = area[pos + 2] as usize;
+            if length < ENTRY_HEADER_SIZE || pos + length > area.len() {
+                break; // padding or garbage
+            }
+
+            let entry = &area[pos..pos + length];
+            match &entry[..2] {
+                b"ST" => break,
+                b"CE" if length >= 28 => continuation = Some((le_u32(entry, 4), le_u32(entry, 12), le_u32(entry, 20))),
+                b"PX" if length >= 36 => {
+                    self.rrip = true;
+                    let rr = &mut self.rock_ridge;
+                    rr.mode = Some(le_u32(entry, 4));
+                    rr.links = Some(le_u32(entry, 12));
+                    rr.uid = Some(le_u32(entry, 20));
+                    rr.gid = Some(le_u32(entry, 28));
+                    rr.inode = if length >= 44 { Some(le_u32(entry, 36)) } else { None };
+                }
+                b"NM" if length >= 5 => self.name_entry(entry[4], &entry[5..]),
+                b"SL" if length >= 5 => self.symlink_entry(&entry[5..]),
+                b"TF" if length >= 5 => self.timestamps(entry[4], &entry[5..]),
+                b"CL" if length >= 12 => self.rock_ridge.child_link = Some(le_u32(entry, 4)),
+                b"RE" => self.rock_ridge.relocated = true,
+                b"RR" => self.rrip = true,
+                b"ER" if length >= 8 => {
+                    let id = entry.get(8..8 + entry[4] as usize).unwrap_or(&[]);
+                    self.rrip |= RRIP_IDS.contains(&id);
+                }
+                _ => (),
+            }
+            pos += length;
+        }
+
+        continuation
+    }
+
+    fn name_entry(&mut self, flags: u8, content: &[u8]) {
+        let name = self.name.get_or_insert_with(String::new);
+        if !self.name_continues {
+            name.clear();
+        }
+
+        if flags & NAME_CURRENT != 0 {
+            name.push('.');
+        } else if flags & NAME_PARENT != 0 {
+            name.push_str("..");
+        } else {
+            name.push_str(&String::from_utf8_lossy(content));
+        }
+        self.name_continues = flags & NAME_CONTINUE != 0;
+    }
+
+    /// Component records: flags, length and content
+    fn symlink_entry(&mut self, mut components: &[u8]) {
+        let target = self.symlink.get_or_insert_with(String::new);
+        while components.len() >= 2 {
+            let (flags, length) = (components[0], components[1] as usize);
+            let content = match components.get(2..2 + length) {
+                Some(content) => content,
+                None => break,
+            };
+
+            if !self.component_continues && !target.is_empty() && !target.ends_with('/') {
+                target.push('/');
+            }
+            if flags & COMPONENT_ROOT != 0 {
+                target.push('/');
+            } else if flags & NAME_CURRENT != 0 {
+                target.push('.');
+            } else if flags & NAME_PARENT != 0 {
+                target.push_str("..");
+            } else {
+                target.push_str(&String::from_utf8_lossy(content));
+            }
+
+            self.component_continues = flags & NAME_CONTINUE != 0;
+            components = &components[2 + length..];
+        }
+    }
+
+    /// The stamps follow in the order of the flag bits
+    fn timestamps(&mut self, flags: u8, mut stamps: &[u8]) {
+        let size = if flags & TF_LONG_FORM != 0 { 17 } else { 7 };
+        for bit in 0..7 {
+            let flag = 1 << bit;
+            if flags & flag == 0 {
+                continue;
+            }
+            if stamps.len() < size {
+                break;
+            }
+
+            let stamp = if size == 17 {
+                Timestamp::from_digits(&stamps[..size])
+            } else {
+                Timestamp::from_record(&stamps[..size])
+            };
+            match flag {
+                TF_CREATION => self.rock_ridge.created = stamp,
+                TF_MODIFY => self.rock_ridge.modified = stamp,
+                TF_ACCESS => self.rock_ridge.accessed = stamp,
+                TF_ATTRIBUTES => self.rock_ridge.changed = stamp,
+                _ => (), // backup, expiration and effective times
+            }
+            stamps = &stamps[size..];
+        }
+    }
+
+    /// `ER` entry naming a Rock Ridge version, some old images only have `RR` or `PX`
+    pub fn is_rock_ridge(&self) -> bool {
+        self.rrip
+    }
+
+    pub fn finish(mut self) -> RockRidge {
+        self.rock_ridge.name = self.name;
+        self.rock_ridge.symlink = self.symlink;
+        self.rock_ridge
+    }
+}
+
+/// `SP` at the start of the root `.` record: the system use bytes skipped by other records
+pub(crate) fn susp_skip(area: &[u8]) -> Option<u8> {
+    if area.len() >= 7 && &area[..2] == b"SP" && area[4..6] == SP_CHECK {
+        Some(area[6])
+    } else {
+        None
+    }
+}
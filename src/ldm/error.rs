@@ -0,0 +1,38 @@
+use crate::xstd::String;
+
+#[derive(Debug)]
+pub enum LdmError {
+    NoPrivateHeader,
+    InvalidPrivateHeader,
+    UnsupportedVersion(u16, u16),
+    InvalidTocBlock,
+    InvalidVmdb,
+    InvalidVblk(u32),    // VBLK sequence number
+    IncompleteVblk(u32), // VBLK group number
+    UnsupportedVolume(String),
+    MissingDisk(u64),      // LDM disk object id
+    MissingComponent(u64), // volume object id
+}
+
+impl core::fmt::Display for LdmError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LdmError::NoPrivateHeader => f.write_str("No LDM private header"),
+            LdmError::InvalidPrivateHeader => f.write_str("Invalid LDM private header"),
+            LdmError::UnsupportedVersion(major, minor) => write!(f, "Unsupported LDM version {}.{}", major, minor),
+            LdmError::InvalidTocBlock => f.write_str("Invalid LDM TOCBLOCK"),
+            LdmError::InvalidVmdb => f.write_str("Invalid LDM VMDB"),
+            LdmError::InvalidVblk(seq) => write!(f, "Invalid LDM VBLK #{}", seq),
+            LdmError::IncompleteVblk(group) => write!(f, "Incomplete LDM VBLK group '{}'", group),
+            LdmError::UnsupportedVolume(kind) => write!(f, "Unsupported LDM volume type '{}'", kind),
+            LdmError::MissingDisk(id) => write!(f, "LDM disk '{}' not found", id),
+            LdmError::MissingComponent(id) => write!(f, "No readable component for LDM volume '{}'", id),
+        }
+    }
+}
+
+impl From<LdmError> for crate::Error {
+    fn from(e: LdmError) -> Self {
+        Self::Ldm(e)
+    }
+}
@@ -0,0 +1,171 @@
+use super::*;
+
+const PRIVHEAD_MAGIC: &[u8] = b"PRIVHEAD";
+const TOCBLOCK_MAGIC: &[u8] = b"TOCBLOCK";
+const VMDB_MAGIC: &[u8] = b"VMDB";
+const CONFIG_BITMAP_NAME: &str = "config";
+
+/// The first private header position on MBR disks (in sectors)
+pub(crate) const PRIVHEAD_LBA: u64 = 6;
+/// TOCBLOCK copies relative to the database start (in sectors)
+const TOCBLOCK_LBAS: [u64; 2] = [1, 2];
+/// Default VMDB position relative to the database start (in sectors)
+const DEFAULT_VMDB_LBA: u64 = 17;
+
+/// LDM private header (PRIVHEAD), one per dynamic disk
+pub struct PrivateHeader {
+    pub version_major: u16,
+    pub version_minor: u16,
+    pub disk_id: Uuid,
+    pub host_id: String,
+    pub disk_group_id: String,
+    pub disk_group_name: String,
+    /// The start of the area available for volumes, in sectors
+    pub logical_disk_start: u64,
+    pub logical_disk_size: u64,
+    /// The LDM database position, in sectors
+    pub config_start: u64,
+    pub config_size: u64,
+}
+
+impl PrivateHeader {
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.get(..8) != Some(PRIVHEAD_MAGIC) {
+            return Err(Error::from(LdmError::InvalidPrivateHeader));
+        }
+
+        let version_major = try_be_u16(bytes, 0x0C)?;
+        let version_minor = try_be_u16(bytes, 0x0E)?;
+        if version_major != 2 || (version_minor != 11 && version_minor != 12) {
+            return Err(Error::from(LdmError::UnsupportedVersion(version_major, version_minor)));
+        }
+
+        if bytes.len() < 0x13B {
+            return Err(Error::UnexpectedEOD);
+        }
+
+        let disk_id = tools::string_from_ascii_z(&bytes[0x30..0x70]);
+        let disk_id = Uuid::parse_str(&disk_id).map_err(|_| Error::from(LdmError::InvalidPrivateHeader))?;
+
+        let header = Self {
+            version_major,
+            version_minor,
+            disk_id,
+            host_id: tools::string_from_ascii_z(&bytes[0x70..0xB0]),
+            disk_group_id: tools::string_from_ascii_z(&bytes[0xB0..0xF0]),
+            disk_group_name: tools::string_from_ascii_z(&bytes[0xF0..0x110]),
+            logical_disk_start: try_be_u64(bytes, 0x11B)?,
+            logical_disk_size: try_be_u64(bytes, 0x123)?,
+            config_start: try_be_u64(bytes, 0x12B)?,
+            config_size: try_be_u64(bytes, 0x133)?,
+        };
+
+        // the values are untrusted, the sums must not overflow
+        let logical_disk_end = header.logical_disk_start.checked_add(header.logical_disk_size);
+        let config_end = header.config_start.checked_add(header.config_size);
+        match (logical_disk_end, config_end) {
+            (Some(end), Some(_)) if header.logical_disk_size != 0 && end <= header.config_start => Ok(header),
+            _ => Err(Error::from(LdmError::InvalidPrivateHeader)),
+        }
+    }
+
+    /// Byte position of the `lba` sector relative to the database start
+    pub(crate) fn config_position(&self, lba: u64, sector_size: u64) -> Result<u64> {
+        self.config_start
+            .checked_add(lba)
+            .and_then(|sector| sector.checked_mul(sector_size))
+            .ok_or_else(|| Error::from(LdmError::InvalidPrivateHeader))
+    }
+
+    /// Looks for the private header of a dynamic disk.
+    ///
+    /// MBR disks keep it in the sector 6, GPT disks in the last sector of the LDM metadata partition.
+    pub fn read(disk: &impl Disk) -> Result<Self> {
+        let sector_size = disk.logical_sector_size()? as u64;
+        let position = match DiskLayout::read(disk)? {
+            DiskLayout::Mbr(layout) => layout
+                .partitions()
+                .iter()
+                .find(|p| p.kind.is_dynamic())
+                .map(|_| PRIVHEAD_LBA * sector_size),
+            DiskLayout::Gpt(layout) => layout
+                .partitions()
+                .iter()
+                .find(|p| p.kind == metadata_partition_kind())
+                .map(|p| p.offset + p.length - sector_size),
+            _ => None,
+        };
+
+        let position = position.ok_or_else(|| Error::from(LdmError::NoPrivateHeader))?;
+        let mut buffer = vec![0_u8; sector_size as usize];
+        disk.read_exact_at(position, &mut buffer)?;
+
+        Self::parse(&buffer)
+    }
+}
+
+/// Returns VMDB position relative to the database start, in sectors
+pub(crate) fn parse_toc_block(bytes: &[u8]) -> Result<u64> {
+    if bytes.get(..8) != Some(TOCBLOCK_MAGIC) {
+        return Err(Error::from(LdmError::InvalidTocBlock));
+    }
+
+    // two bitmap descriptors: "config" and "log"
+    for &pos in &[0x24_usize, 0x46] {
+        let name = tools::string_from_ascii_z(bytes.get(pos..pos + 8).ok_or(Error::UnexpectedEOD)?);
+        let start = try_be_u64(bytes, pos + 10)?;
+        if name == CONFIG_BITMAP_NAME {
+            return Ok(start);
+        }
+    }
+
+    Err(Error::from(LdmError::InvalidTocBlock))
+}
+
+/// Reads TOCBLOCK and returns VMDB position relative to the database start, in sectors
+pub(crate) fn read_vmdb_position(disk: &impl Disk, header: &PrivateHeader) -> Result<u64> {
+    let sector_size = disk.logical_sector_size()? as u64;
+    let mut buffer = vec![0_u8; sector_size as usize];
+    for lba in &TOCBLOCK_LBAS {
+        disk.read_exact_at(header.config_position(*lba, sector_size)?, &mut buffer)?;
+        if let Ok(position) = parse_toc_block(&buffer) {
+            return Ok(position);
+        }
+        // TODO: log warning
+    }
+
+    Ok(DEFAULT_VMDB_LBA)
+}
+
+/// Volume Manager DataBase header
+pub(crate) struct Vmdb {
+    pub(crate) last_vblk_seq: u32,
+    pub(crate) vblk_size: u32,
+    pub(crate) vblk_offset: u32,
+}
+
+impl Vmdb {
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.get(..4) != Some(VMDB_MAGIC) {
+            return Err(Error::from(LdmError::InvalidVmdb));
+        }
+
+        let version_major = try_be_u16(bytes, 0x12)?;
+        let version_minor = try_be_u16(bytes, 0x14)?;
+        if version_major != 4 || version_minor != 10 {
+            return Err(Error::from(LdmError::UnsupportedVersion(version_major, version_minor)));
+        }
+
+        let vmdb = Self {
+            last_vblk_seq: try_be_u32(bytes, 0x04)?,
+            vblk_size: try_be_u32(bytes, 0x08)?,
+            vblk_offset: try_be_u32(bytes, 0x0C)?,
+        };
+
+        if vmdb.vblk_size < VBLK_HEADER_SIZE as u32 || vmdb.vblk_offset < vmdb.vblk_size {
+            return Err(Error::from(LdmError::InvalidVmdb));
+        }
+
+        Ok(vmdb)
+    }
+}
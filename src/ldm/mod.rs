@@ -0,0 +1,452 @@
+//! Windows Logical Disk Manager (dynamic disks)
+//!
+//! The LDM database is stored at the end of every dynamic disk and describes all volumes of the disk group.
+//! See https://github.com/mdbooth/libldm and linux/block/partitions/ldm.c
+use crate::prelude::*;
+use crate::tools::{try_be_u16, try_be_u32, try_be_u64};
+use crate::DiskLayout;
+
+mod error;
+pub use error::LdmError;
+
+mod header;
+pub use header::PrivateHeader;
+use header::Vmdb;
+
+mod vblk;
+use vblk::{RawVblk, Record};
+
+mod volume;
+pub use volume::VolumeStream;
+
+const VBLK_HEADER_SIZE: usize = 0x10;
+
+/// GPT type of the partition holding the LDM database
+pub fn metadata_partition_kind() -> Uuid {
+    Uuid::from_u128(0x5808_C8AA_7E8F_42E0_85D2_E1E9_0434_CFB3)
+}
+
+/// GPT type of the partition holding dynamic volumes data
+pub fn data_partition_kind() -> Uuid {
+    Uuid::from_u128(0xAF9B_60A0_1431_4F62_BC68_3311_714A_69AD)
+}
+
+pub struct DiskGroup {
+    pub id: u64,
+    pub name: String,
+}
+
+pub struct DiskInfo {
+    pub id: u64,
+    pub name: String,
+    /// Matches `PrivateHeader::disk_id` of the disk
+    pub guid: Uuid,
+}
+
+pub struct Volume {
+    pub id: u64,
+    pub name: String,
+    /// "gen" for simple, spanned, striped and mirrored volumes or "raid5"
+    pub kind: String,
+    pub state: String,
+    /// Number of components, more than one for mirrored volumes
+    pub children: u64,
+    pub size: u64,
+    pub partition_type: u8,
+    pub guid: Uuid,
+    pub drive_hint: String,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum ComponentKind {
+    Striped,
+    /// Simple and spanned volumes
+    Concatenated,
+    Raid5,
+    Unknown(u8),
+}
+
+impl From<u8> for ComponentKind {
+    fn from(kind: u8) -> Self {
+        match kind {
+            1 => ComponentKind::Striped,
+            2 => ComponentKind::Concatenated,
+            3 => ComponentKind::Raid5,
+            _ => ComponentKind::Unknown(kind),
+        }
+    }
+}
+
+pub struct Component {
+    pub id: u64,
+    pub name: String,
+    pub state: String,
+    pub kind: ComponentKind,
+    /// Number of extents
+    pub children: u64,
+    pub volume_id: u64,
+    /// Zero for non striped components
+    pub stripe_size: u64,
+    pub columns: u64,
+}
+
+/// A part of a dynamic disk used by a component, known as "partition" in the LDM database
+pub struct Extent {
+    pub id: u64,
+    pub name: String,
+    pub component_id: u64,
+    pub disk_id: u64,
+    /// Offset from the disk start
+    pub offset: u64,
+    pub volume_offset: u64,
+    pub length: u64,
+    /// Column index for striped components
+    pub index: u32,
+}
+
+pub struct Database {
+    header: PrivateHeader,
+    disk_groups: Vec<DiskGroup>,
+    disks: Vec<DiskInfo>,
+    volumes: Vec<Volume>,
+    components: Vec<Component>,
+    extents: Vec<Extent>,
+}
+
+impl Database {
+    /// Reads the LDM database from any disk of the disk group.
+    pub fn read(disk: &impl Disk) -> Result<Self> {
+        let header = PrivateHeader::read(disk)?;
+        let sector_size = disk.logical_sector_size()? as u64;
+
+        let vmdb_pos = header.config_position(header::read_vmdb_position(disk, &header)?, sector_size)?;
+        let mut buffer = vec![0_u8; sector_size as usize];
+        disk.read_exact_at(vmdb_pos, &mut buffer)?;
+        let vmdb = Vmdb::parse(&buffer)?;
+
+        let vblk_size = vmdb.vblk_size as u64;
+        let database_end = header.config_position(header.config_size, sector_size)?;
+        if database_end > disk.capacity()? {
+            return Err(Error::from(LdmError::InvalidPrivateHeader));
+        }
+
+        let vblks_end = core::cmp::min(vmdb_pos.saturating_add(vblk_size * (vmdb.last_vblk_seq as u64 + 1)), database_end);
+        let vblks_start = vmdb_pos.saturating_add(vmdb.vblk_offset as u64);
+        if vblks_end <= vblks_start {
+            return Err(Error::from(LdmError::InvalidVmdb));
+        }
+
+        let mut data = vec![0_u8; (vblks_end - vblks_start) as usize];
+        disk.read_exact_at(vblks_start, &mut data)?;
+
+        let mut this = Self {
+            header,
+            disk_groups: Vec::new(),
+            disks: Vec::new(),
+            volumes: Vec::new(),
+            components: Vec::new(),
+            extents: Vec::new(),
+        };
+
+        let mut fragments = BTreeMap::<u32, Vec<Option<&[u8]>>>::new();
+        for vblk in data.chunks_exact(vblk_size as usize) {
+            if vblk.iter().all(|&b| b == 0) {
+                continue; // unused slot
+            }
+
+            match vblk::parse_raw_vblk(vblk)? {
+                RawVblk::Empty => (),
+                RawVblk::Record(record) => this.add_record(record, sector_size)?,
+                RawVblk::Fragment { group, index, count } => {
+                    let parts = fragments.entry(group).or_insert_with(|| vec![None; count as usize]);
+                    if let Some(part) = parts.get_mut(index as usize) {
+                        *part = Some(vblk);
+                    }
+                }
+            }
+        }
+
+        for (group, parts) in fragments {
+            let mut record = Vec::with_capacity(parts.len() * vblk_size as usize);
+            for (index, part) in parts.into_iter().enumerate() {
+                let part = part.ok_or_else(|| Error::from(LdmError::IncompleteVblk(group)))?;
+                if index == 0 {
+                    record.extend_from_slice(&part[..VBLK_HEADER_SIZE]);
+                }
+                record.extend_from_slice(&part[VBLK_HEADER_SIZE..]);
+            }
+
+            this.add_record(&record, sector_size)?;
+        }
+
+        Ok(this)
+    }
+
+    fn add_record(&mut self, bytes: &[u8], sector_size: u64) -> Result<()> {
+        match vblk::parse_record(bytes, sector_size)? {
+            Record::Volume(volume) => self.volumes.push(volume),
+            Record::Component(component) => self.components.push(component),
+            Record::Extent(mut extent) => {
+                extent.offset = self
+                    .header
+                    .logical_disk_start
+                    .checked_mul(sector_size)
+                    .and_then(|start| start.checked_add(extent.offset))
+                    .ok_or_else(|| Error::from(LdmError::InvalidPrivateHeader))?;
+                self.extents.push(extent);
+            }
+            Record::Disk(disk) => self.disks.push(disk),
+            Record::DiskGroup(group) => self.disk_groups.push(group),
+            Record::Unknown => (), // TODO: log warning
+        }
+
+        Ok(())
+    }
+
+    /// The private header of the disk the database was read from
+    pub fn header(&self) -> &PrivateHeader {
+        &self.header
+    }
+
+    pub fn disk_groups(&self) -> &[DiskGroup] {
+        &self.disk_groups
+    }
+
+    pub fn disks(&self) -> &[DiskInfo] {
+        &self.disks
+    }
+
+    pub fn volumes(&self) -> &[Volume] {
+        &self.volumes
+    }
+
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    pub fn extents(&self) -> &[Extent] {
+        &self.extents
+    }
+
+    pub fn volume_components(&self, volume: &Volume) -> impl Iterator<Item = &Component> + '_ {
+        let id = volume.id;
+        self.components.iter().filter(move |c| c.volume_id == id)
+    }
+
+    /// Component extents ordered by column index and volume offset
+    pub fn component_extents(&self, component: &Component) -> Vec<&Extent> {
+        let mut extents: Vec<&Extent> = self.extents.iter().filter(|e| e.component_id == component.id).collect();
+        extents.sort_by_key(|e| (e.index, e.volume_offset));
+        extents
+    }
+
+    /// Opens a volume using the disks of the disk group, the order of `disks` does not matter.
+    ///
+    /// Mirrored volumes are read from the first component with all disks available.
+    pub fn open_volume<'d, D: Disk>(&self, volume: &Volume, disks: &'d [D]) -> Result<VolumeStream<'d, D>> {
+        let mut members = Vec::with_capacity(disks.len());
+        for disk in disks {
+            if let Ok(header) = PrivateHeader::read(disk) {
+                if let Some(info) = self.disks.iter().find(|info| info.guid == header.disk_id) {
+                    members.push((info.id, disk));
+                }
+            }
+        }
+
+        let mut missing_disk = None;
+        for component in self.volume_components(volume) {
+            let extents = self.component_extents(component);
+            let mut segments = Vec::with_capacity(extents.len());
+            for extent in extents {
+                match members.iter().find(|(id, _)| *id == extent.disk_id) {
+                    Some((_, disk)) => segments.push(volume::Segment::new(*disk, extent)),
+                    None => {
+                        missing_disk = Some(extent.disk_id);
+                        break;
+                    }
+                }
+            }
+
+            if segments.len() as u64 != component.children || segments.is_empty() {
+                continue;
+            }
+
+            return match component.kind {
+                ComponentKind::Concatenated => Ok(VolumeStream::concatenated(volume.size, segments)),
+                ComponentKind::Striped if component.stripe_size != 0 => {
+                    Ok(VolumeStream::striped(volume.size, component.stripe_size, segments))
+                }
+                _ => Err(Error::from(LdmError::UnsupportedVolume(volume.kind.clone()))),
+            };
+        }
+
+        match missing_disk {
+            Some(id) => Err(Error::from(LdmError::MissingDisk(id))),
+            None => Err(Error::from(LdmError::MissingComponent(volume.id))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+
+    const DISK_GUID: &str = "01234567-89ab-cdef-0123-456789abcdef";
+    const CONFIG_START: u64 = 7000;
+    const LOGICAL_DISK_START: u64 = 63;
+    const VBLK_SIZE: usize = 128;
+
+    /// Writes the length prefixed field, returns its size
+    fn var(vblk: &mut [u8], pos: usize, data: &[u8]) -> usize {
+        vblk[pos] = data.len() as u8;
+        vblk[pos + 1..pos + 1 + data.len()].copy_from_slice(data);
+        data.len() + 1
+    }
+
+    /// VBLK with the record header, the id and the name, returns the name end offset (`r_name`)
+    fn vblk(seq: u32, kind: u8, id: u8, name: &[u8]) -> (Vec<u8>, usize) {
+        let mut vblk = vec![0_u8; VBLK_SIZE];
+        vblk[..4].copy_from_slice(b"VBLK");
+        vblk[0x04..0x08].copy_from_slice(&seq.to_be_bytes());
+        vblk[0x0E..0x10].copy_from_slice(&1_u16.to_be_bytes());
+        vblk[0x13] = kind;
+        let r_id = var(&mut vblk, 0x18, &[id]);
+        let r_name = r_id + var(&mut vblk, 0x18 + r_id, name);
+        (vblk, r_name)
+    }
+
+    fn private_header() -> Vec<u8> {
+        let mut bytes = vec![0_u8; 512];
+        bytes[..8].copy_from_slice(b"PRIVHEAD");
+        bytes[0x0C..0x0E].copy_from_slice(&2_u16.to_be_bytes());
+        bytes[0x0E..0x10].copy_from_slice(&12_u16.to_be_bytes());
+        bytes[0x30..0x30 + DISK_GUID.len()].copy_from_slice(DISK_GUID.as_bytes());
+        bytes[0xF0..0xF6].copy_from_slice(b"WinDg0");
+        bytes[0x11B..0x123].copy_from_slice(&LOGICAL_DISK_START.to_be_bytes());
+        bytes[0x123..0x12B].copy_from_slice(&6000_u64.to_be_bytes());
+        bytes[0x12B..0x133].copy_from_slice(&CONFIG_START.to_be_bytes());
+        bytes[0x133..0x13B].copy_from_slice(&1192_u64.to_be_bytes());
+        bytes
+    }
+
+    /// 4 MiB MBR dynamic disk with a simple 1 MiB volume at the sector 100 of the logical disk
+    fn dynamic_disk() -> Vec<u8> {
+        let mut data = vec![0_u8; 8192 * 512];
+        data[450] = 0x42;
+        data[454..458].copy_from_slice(&(LOGICAL_DISK_START as u32).to_le_bytes());
+        data[458..462].copy_from_slice(&(8192 - LOGICAL_DISK_START as u32).to_le_bytes());
+        data[510] = 0x55;
+        data[511] = 0xAA;
+        data[6 * 512..7 * 512].copy_from_slice(&private_header());
+
+        let toc = (CONFIG_START as usize + 1) * 512;
+        data[toc..toc + 8].copy_from_slice(b"TOCBLOCK");
+        data[toc + 0x24..toc + 0x2A].copy_from_slice(b"config");
+        data[toc + 0x2E..toc + 0x36].copy_from_slice(&17_u64.to_be_bytes());
+
+        let vmdb = (CONFIG_START as usize + 17) * 512;
+        data[vmdb..vmdb + 4].copy_from_slice(b"VMDB");
+        data[vmdb + 0x04..vmdb + 0x08].copy_from_slice(&8_u32.to_be_bytes());
+        data[vmdb + 0x08..vmdb + 0x0C].copy_from_slice(&(VBLK_SIZE as u32).to_be_bytes());
+        data[vmdb + 0x0C..vmdb + 0x10].copy_from_slice(&512_u32.to_be_bytes());
+        data[vmdb + 0x12..vmdb + 0x14].copy_from_slice(&4_u16.to_be_bytes());
+        data[vmdb + 0x14..vmdb + 0x16].copy_from_slice(&10_u16.to_be_bytes());
+
+        let (group, _) = vblk(4, 0x45, 1, b"WinDg0");
+
+        let (mut disk, r_name) = vblk(5, 0x44, 2, b"Disk1");
+        disk[0x18 + r_name..0x28 + r_name].copy_from_slice(Uuid::parse_str(DISK_GUID).unwrap().as_bytes());
+
+        let (mut volume, r_name) = vblk(6, 0x51, 3, b"Volume1");
+        let r_kind = r_name + var(&mut volume, 0x18 + r_name, b"gen");
+        let r_no_letter = r_kind + var(&mut volume, 0x18 + r_kind, b"");
+        volume[0x18 + r_no_letter..0x1E + r_no_letter].copy_from_slice(b"ACTIVE");
+        let r_children = r_no_letter + var(&mut volume, 0x2D + r_no_letter, &[1]);
+        let r_size = r_children + var(&mut volume, 0x3D + r_children, &[0x08, 0x00]);
+        volume[0x41 + r_size] = 0x07;
+
+        let (mut component, r_name) = vblk(7, 0x32, 4, b"Volume1-01");
+        let r_state = r_name + var(&mut component, 0x18 + r_name, b"ACTIVE");
+        component[0x18 + r_state] = 2;
+        let r_children = r_state + var(&mut component, 0x1D + r_state, &[1]);
+        var(&mut component, 0x2D + r_children, &[3]);
+
+        let (mut extent, r_name) = vblk(8, 0x33, 5, b"Disk1-01");
+        extent[0x24 + r_name..0x2C + r_name].copy_from_slice(&100_u64.to_be_bytes());
+        let r_size = r_name + var(&mut extent, 0x34 + r_name, &[0x08, 0x00]);
+        let r_parent = r_size + var(&mut extent, 0x34 + r_size, &[4]);
+        var(&mut extent, 0x34 + r_parent, &[2]);
+
+        for (i, record) in [group, disk, volume, component, extent].iter().enumerate() {
+            let pos = vmdb + (4 + i) * VBLK_SIZE;
+            data[pos..pos + VBLK_SIZE].copy_from_slice(record);
+        }
+
+        data
+    }
+
+    #[test]
+    fn database_test() {
+        let disk = MemoryDisk::from_vec(dynamic_disk());
+        let header = PrivateHeader::read(&disk).unwrap();
+        assert_eq!(Uuid::parse_str(DISK_GUID).unwrap(), header.disk_id);
+        assert_eq!("WinDg0", header.disk_group_name);
+
+        let database = Database::read(&disk).unwrap();
+        assert_eq!("WinDg0", database.disk_groups()[0].name);
+        assert_eq!(header.disk_id, database.disks()[0].guid);
+        let volume = &database.volumes()[0];
+        assert_eq!(
+            ("Volume1", "gen", 1024 * 1024),
+            (volume.name.as_str(), volume.kind.as_str(), volume.size)
+        );
+        let component = database.volume_components(volume).next().unwrap();
+        assert_eq!(ComponentKind::Concatenated, component.kind);
+        let extents = database.component_extents(component);
+        assert_eq!((LOGICAL_DISK_START + 100) * 512, extents[0].offset);
+
+        disk.write_all_at(extents[0].offset + 10, b"ldm").unwrap();
+        let stream = database.open_volume(volume, core::slice::from_ref(&disk)).unwrap();
+        let mut buffer = [0_u8; 3];
+        stream.read_exact_at(10, &mut buffer).unwrap();
+        assert_eq!(b"ldm", &buffer);
+    }
+
+    #[test]
+    fn corrupt_database_test() {
+        assert!(PrivateHeader::parse(&private_header()).is_ok());
+        assert!(PrivateHeader::parse(&private_header()[..0x120]).is_err());
+        assert!(PrivateHeader::parse(&private_header()[..4]).is_err());
+
+        // the logical disk end overflows
+        let mut bytes = private_header();
+        bytes[0x11B..0x123].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(
+            PrivateHeader::parse(&bytes),
+            Err(Error::Ldm(LdmError::InvalidPrivateHeader))
+        ));
+
+        // the database is beyond the disk end
+        let mut data = dynamic_disk();
+        data[6 * 512 + 0x12B..6 * 512 + 0x133].copy_from_slice(&(u64::MAX / 2).to_be_bytes());
+        assert!(Database::read(&MemoryDisk::from_vec(data)).is_err());
+
+        // a broken VBLK signature
+        let mut data = dynamic_disk();
+        data[(CONFIG_START as usize + 17) * 512 + 5 * VBLK_SIZE] = b'X';
+        assert!(matches!(
+            Database::read(&MemoryDisk::from_vec(data)),
+            Err(Error::Ldm(LdmError::InvalidVblk(5)))
+        ));
+
+        // the VMDB is cut off by the database end
+        let mut data = dynamic_disk();
+        data[6 * 512 + 0x133..6 * 512 + 0x13B].copy_from_slice(&17_u64.to_be_bytes());
+        assert!(Database::read(&MemoryDisk::from_vec(data)).is_err());
+
+        assert!(header::parse_toc_block(b"TOCBLOCK").is_err());
+        assert!(Vmdb::parse(b"VM").is_err());
+    }
+}
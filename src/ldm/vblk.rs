@@ -0,0 +1,217 @@
+use super::*;
+
+const VBLK_MAGIC: &[u8] = b"VBLK";
+
+// record types
+const VOLUME: u8 = 0x51;
+const COMPONENT: u8 = 0x32;
+const PARTITION: u8 = 0x33;
+const DISK_V3: u8 = 0x34;
+const DISK_V4: u8 = 0x44;
+const DISK_GROUP_V3: u8 = 0x35;
+const DISK_GROUP_V4: u8 = 0x45;
+
+// record flags
+const FLAG_COMPONENT_STRIPE: u8 = 0x10;
+const FLAG_PARTITION_INDEX: u8 = 0x08;
+const FLAG_VOLUME_ID1: u8 = 0x08;
+const FLAG_VOLUME_ID2: u8 = 0x20;
+const FLAG_VOLUME_SIZE: u8 = 0x80;
+const FLAG_VOLUME_DRIVE_HINT: u8 = 0x02;
+
+/// Big endian number with the length prefix
+fn vnum(bytes: &[u8], pos: usize) -> Result<u64> {
+    let len = *bytes.get(pos).ok_or(Error::UnexpectedEOD)? as usize;
+    if len == 0 || len > 8 {
+        return Err(Error::UnexpectedEOD);
+    }
+
+    let data = bytes.get(pos + 1..pos + 1 + len).ok_or(Error::UnexpectedEOD)?;
+    Ok(data.iter().fold(0_u64, |acc, &b| (acc << 8) | b as u64))
+}
+
+/// ASCII string with the length prefix
+fn vstr(bytes: &[u8], pos: usize) -> Result<String> {
+    let len = *bytes.get(pos).ok_or(Error::UnexpectedEOD)? as usize;
+    let data = bytes.get(pos + 1..pos + 1 + len).ok_or(Error::UnexpectedEOD)?;
+    Ok(tools::string_from_ascii_z(data))
+}
+
+/// Converts the untrusted sector count to bytes
+fn sectors(count: u64, sector_size: u64, seq: u32) -> Result<u64> {
+    count
+        .checked_mul(sector_size)
+        .ok_or_else(|| Error::from(LdmError::InvalidVblk(seq)))
+}
+
+/// Returns the offset after the variable length field at `base + offset`
+fn skip(bytes: &[u8], base: usize, offset: usize) -> Result<usize> {
+    let len = *bytes.get(base + offset).ok_or(Error::UnexpectedEOD)? as usize;
+    Ok(offset + len + 1)
+}
+
+/// Either a complete record or a fragment of the record spanned several VBLKs
+pub(crate) enum RawVblk<'a> {
+    Empty,
+    Record(&'a [u8]),
+    Fragment { group: u32, index: u16, count: u16 },
+}
+
+pub(crate) fn parse_raw_vblk(bytes: &[u8]) -> Result<RawVblk<'_>> {
+    if bytes.get(..4) != Some(VBLK_MAGIC) {
+        return Err(Error::from(LdmError::InvalidVblk(try_be_u32(bytes, 0x04).unwrap_or(0))));
+    }
+
+    let group = try_be_u32(bytes, 0x08)?;
+    let index = try_be_u16(bytes, 0x0C)?;
+    let count = try_be_u16(bytes, 0x0E)?;
+    Ok(match count {
+        0 => RawVblk::Empty,
+        1 => RawVblk::Record(bytes),
+        _ => RawVblk::Fragment { group, index, count },
+    })
+}
+
+pub(crate) enum Record {
+    Volume(Volume),
+    Component(Component),
+    Extent(Extent),
+    Disk(DiskInfo),
+    DiskGroup(DiskGroup),
+    Unknown,
+}
+
+/// Parses a complete record, all offsets are relative to the VBLK start
+pub(crate) fn parse_record(bytes: &[u8], sector_size: u64) -> Result<Record> {
+    if bytes.len() < VBLK_HEADER_SIZE + 8 {
+        return Err(Error::UnexpectedEOD);
+    }
+
+    let seq = try_be_u32(bytes, 0x04)?;
+    let flags = bytes[0x12];
+    let kind = bytes[0x13];
+    let r_id = skip(bytes, 0x18, 0)?;
+    let r_name = skip(bytes, 0x18, r_id)?;
+    let id = vnum(bytes, 0x18)?;
+    let name = vstr(bytes, 0x18 + r_id)?;
+
+    let record = match kind {
+        VOLUME => {
+            let r_kind = skip(bytes, 0x18, r_name)?;
+            let r_no_letter = skip(bytes, 0x18, r_kind)?; // "disable drive letter" field
+            let r_children = skip(bytes, 0x2D, r_no_letter)?;
+            let r_size = skip(bytes, 0x3D, r_children)?;
+            let mut r_next = r_size;
+            for &flag in &[FLAG_VOLUME_ID1, FLAG_VOLUME_ID2, FLAG_VOLUME_SIZE] {
+                if flags & flag != 0 {
+                    r_next = skip(bytes, 0x52, r_next)?;
+                }
+            }
+
+            let guid_bytes = bytes.get(0x42 + r_size..0x52 + r_size).ok_or(Error::UnexpectedEOD)?;
+            let mut guid = [0_u8; 16];
+            guid.copy_from_slice(guid_bytes);
+
+            Record::Volume(Volume {
+                id,
+                name,
+                kind: vstr(bytes, 0x18 + r_name)?,
+                state: tools::string_from_ascii_z(bytes.get(0x18 + r_no_letter..0x28 + r_no_letter).ok_or(Error::UnexpectedEOD)?),
+                children: vnum(bytes, 0x2D + r_no_letter)?,
+                size: sectors(vnum(bytes, 0x3D + r_children)?, sector_size, seq)?,
+                partition_type: *bytes.get(0x41 + r_size).ok_or(Error::UnexpectedEOD)?,
+                guid: Uuid::from_bytes(guid),
+                drive_hint: if flags & FLAG_VOLUME_DRIVE_HINT != 0 {
+                    vstr(bytes, 0x52 + r_next)?
+                } else {
+                    String::new()
+                },
+            })
+        }
+        COMPONENT => {
+            let r_state = skip(bytes, 0x18, r_name)?;
+            let r_children = skip(bytes, 0x1D, r_state)?;
+            let r_parent = skip(bytes, 0x2D, r_children)?;
+            let (stripe_size, columns) = if flags & FLAG_COMPONENT_STRIPE != 0 {
+                let r_stripe = skip(bytes, 0x2E, r_parent)?;
+                (
+                    sectors(vnum(bytes, 0x2E + r_parent)?, sector_size, seq)?,
+                    vnum(bytes, 0x2E + r_stripe)?,
+                )
+            } else {
+                (0, 0)
+            };
+
+            Record::Component(Component {
+                id,
+                name,
+                state: vstr(bytes, 0x18 + r_name)?,
+                kind: ComponentKind::from(*bytes.get(0x18 + r_state).ok_or(Error::UnexpectedEOD)?),
+                children: vnum(bytes, 0x1D + r_state)?,
+                volume_id: vnum(bytes, 0x2D + r_children)?,
+                stripe_size,
+                columns,
+            })
+        }
+        PARTITION => {
+            let r_size = skip(bytes, 0x34, r_name)?;
+            let r_parent = skip(bytes, 0x34, r_size)?;
+            let r_disk = skip(bytes, 0x34, r_parent)?;
+
+            Record::Extent(Extent {
+                id,
+                name,
+                // relative to the logical disk start, fixed up later
+                offset: sectors(try_be_u64(bytes, 0x24 + r_name)?, sector_size, seq)?,
+                volume_offset: sectors(try_be_u64(bytes, 0x2C + r_name)?, sector_size, seq)?,
+                length: sectors(vnum(bytes, 0x34 + r_name)?, sector_size, seq)?,
+                component_id: vnum(bytes, 0x34 + r_size)?,
+                disk_id: vnum(bytes, 0x34 + r_parent)?,
+                index: if flags & FLAG_PARTITION_INDEX != 0 {
+                    *bytes.get(0x35 + r_disk).ok_or(Error::UnexpectedEOD)? as u32
+                } else {
+                    0
+                },
+            })
+        }
+        DISK_V3 => {
+            let guid = vstr(bytes, 0x18 + r_name)?;
+            Record::Disk(DiskInfo {
+                id,
+                name,
+                guid: Uuid::parse_str(&guid).map_err(|_| Error::from(LdmError::InvalidVblk(seq)))?,
+            })
+        }
+        DISK_V4 => {
+            let guid_bytes = bytes.get(0x18 + r_name..0x28 + r_name).ok_or(Error::UnexpectedEOD)?;
+            let mut guid = [0_u8; 16];
+            guid.copy_from_slice(guid_bytes);
+
+            Record::Disk(DiskInfo {
+                id,
+                name,
+                guid: Uuid::from_bytes(guid),
+            })
+        }
+        DISK_GROUP_V3 | DISK_GROUP_V4 => Record::DiskGroup(DiskGroup { id, name }),
+        _ => Record::Unknown,
+    };
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variable_fields_test() {
+        let bytes = [0x02, 0x01, 0x02, 0x03, b'a', b'b', b'c', 0x01, 0xFF];
+        assert_eq!(0x0102, vnum(&bytes, 0).unwrap());
+        assert_eq!(3, skip(&bytes, 0, 0).unwrap());
+        assert_eq!("abc", vstr(&bytes, 3).unwrap());
+        assert_eq!(7, skip(&bytes, 0, 3).unwrap());
+        assert_eq!(0xFF, vnum(&bytes, 7).unwrap());
+        assert!(vnum(&bytes, 9).is_err());
+    }
+}
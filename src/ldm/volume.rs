@@ -0,0 +1,135 @@
+use super::*;
+
+pub(crate) struct Segment<'d, D: Disk> {
+    disk: &'d D,
+    disk_offset: u64,
+    volume_offset: u64,
+    length: u64,
+}
+
+impl<'d, D: Disk> Segment<'d, D> {
+    pub(crate) fn new(disk: &'d D, extent: &Extent) -> Self {
+        Self {
+            disk,
+            disk_offset: extent.offset,
+            volume_offset: extent.volume_offset,
+            length: extent.length,
+        }
+    }
+}
+
+/// Maps the volume offset to (column, offset in column, bytes till the stripe end)
+fn stripe_position(offset: u64, stripe_size: u64, columns: u64) -> (usize, u64, u64) {
+    let stripe = offset / stripe_size;
+    let offset_in_stripe = offset % stripe_size;
+    let column = (stripe % columns) as usize;
+    let row = stripe / columns;
+
+    (column, row * stripe_size + offset_in_stripe, stripe_size - offset_in_stripe)
+}
+
+/// Dynamic volume data assembled from the disk extents
+pub struct VolumeStream<'d, D: Disk> {
+    size: u64,
+    stripe_size: Option<u64>,
+    segments: Vec<Segment<'d, D>>,
+}
+
+impl<'d, D: Disk> VolumeStream<'d, D> {
+    pub(crate) fn concatenated(size: u64, mut segments: Vec<Segment<'d, D>>) -> Self {
+        segments.sort_by_key(|s| s.volume_offset);
+        Self {
+            size,
+            stripe_size: None,
+            segments,
+        }
+    }
+
+    /// `segments` should be ordered by column index
+    pub(crate) fn striped(size: u64, stripe_size: u64, segments: Vec<Segment<'d, D>>) -> Self {
+        Self {
+            size,
+            stripe_size: Some(stripe_size),
+            segments,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_striped(&self) -> bool {
+        self.stripe_size.is_some()
+    }
+
+    fn read_concatenated(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|s| s.volume_offset <= offset && offset < s.volume_offset + s.length);
+
+        match segment {
+            Some(s) => {
+                let len = core::cmp::min(buffer.len() as u64, s.volume_offset + s.length - offset) as usize;
+                s.disk.read_at(s.disk_offset + offset - s.volume_offset, &mut buffer[..len])
+            }
+            None => {
+                // a gap between extents, should not happen for consistent databases
+                let next = self
+                    .segments
+                    .iter()
+                    .map(|s| s.volume_offset)
+                    .filter(|&start| start > offset)
+                    .min()
+                    .unwrap_or(self.size);
+                let len = core::cmp::min(buffer.len() as u64, next - offset) as usize;
+                for b in &mut buffer[..len] {
+                    *b = 0;
+                }
+                Ok(len)
+            }
+        }
+    }
+
+    fn read_striped(&self, stripe_size: u64, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let (column, column_offset, available) = stripe_position(offset, stripe_size, self.segments.len() as u64);
+        let segment = &self.segments[column];
+        if column_offset >= segment.length {
+            return Err(Error::ReadBeyondEOD);
+        }
+
+        let len = core::cmp::min(buffer.len() as u64, available) as usize;
+        segment.disk.read_at(segment.disk_offset + column_offset, &mut buffer[..len])
+    }
+}
+
+impl<'d, D: Disk> ReadAt for VolumeStream<'d, D> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size, offset, buffer.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::ReadBeyondEOD),
+        };
+
+        let buffer = &mut buffer[..len];
+        match self.stripe_size {
+            Some(stripe_size) => self.read_striped(stripe_size, offset, buffer),
+            None => self.read_concatenated(offset, buffer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stripe_position_test() {
+        assert_eq!((0, 0, 64), stripe_position(0, 64, 3));
+        assert_eq!((0, 10, 54), stripe_position(10, 64, 3));
+        assert_eq!((1, 0, 64), stripe_position(64, 64, 3));
+        assert_eq!((2, 63, 1), stripe_position(191, 64, 3));
+        assert_eq!((0, 64, 64), stripe_position(192, 64, 3));
+        assert_eq!((1, 65, 63), stripe_position(257, 64, 3));
+    }
+}
@@ -2,6 +2,8 @@
 
 pub(crate) use rdisk_shared::*;
 
+extern crate alloc;
+
 #[macro_use]
 extern crate num_derive;
 
@@ -41,14 +43,25 @@ pub mod sizes {
     pub const GIB: u64 = 1024 * MIB;
 }
 
+pub mod apm;
+pub mod bsd;
 pub mod crc;
+pub mod exfat;
+pub mod ext;
+pub mod fat;
 pub mod gpt;
+pub mod iso9660;
+pub mod ldm;
+pub mod lvm;
 pub mod math;
 pub mod mbr;
+pub mod md;
+pub mod ntfs;
 
 pub mod qcow;
 pub mod raw;
 pub mod vdi;
+pub mod vfs;
 pub mod vhd;
 pub mod vhdx;
 pub mod vmdk;
@@ -71,19 +84,58 @@ pub use partition::*;
 mod partitioned_disk;
 pub use partitioned_disk::*;
 
+mod stream_disk;
+pub use stream_disk::*;
+
+mod cached_disk;
+pub use cached_disk::*;
+
+mod composite_disk;
+pub use composite_disk::*;
+
+mod memory;
+pub use memory::*;
+
+mod overlay_disk;
+pub use overlay_disk::*;
+
+mod open_options;
+pub use open_options::OpenOptions;
+
+mod aligned;
+pub use aligned::AlignedBuffer;
+
+#[cfg(feature = "async")]
+mod async_disk;
+#[cfg(feature = "async")]
+pub use async_disk::*;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod uring;
+
+mod probe;
+pub use probe::{probe_filesystem, FsInfo, FsKind};
+pub use vfs::{open_filesystem, FileSystem};
+
 pub(crate) mod platform;
-pub use platform::{File, PhysicalDisk};
+pub(crate) mod sync;
+#[cfg(test)]
+pub(crate) mod test_utils;
+pub use platform::File;
+#[cfg(windows)]
+pub use platform::PhysicalDisk;
 
 pub mod prelude {
     pub use crate::Uuid;
     pub(crate) use crate::{crc, math, tools, ImageExtentOps, UuidEx};
-    pub use crate::{Disk, DiskImage, Error, File, Flush, Geometry, ImageExtent, ReadAt, Result, WriteAt};
+    pub use crate::{Disk, DiskImage, Error, File, Flush, Geometry, ImageExtent, OpenOptions, ReadAt, Result, Stream, WriteAt};
     pub use crate::{Partition, PartitionInfo, PartitionKind, PartitionedDisk};
     pub(crate) use rdisk_shared::xstd::*;
 }
 
 pub(crate) mod tools {
     pub use super::*;
+    use rdisk_shared::xstd::{vec, String, ToString};
 
     pub fn read_disk_struct<T, D>(disk: &D, offset: u64) -> Result<T>
     where
@@ -102,4 +154,97 @@ pub(crate) mod tools {
             Ok(core::ptr::read(buffer.as_ptr() as *const T))
         }
     }
+
+    /// Reads a packed structure from the start of the byte slice
+    pub fn read_struct<T: Sized>(bytes: &[u8]) -> T {
+        assert!(bytes.len() >= core::mem::size_of::<T>());
+
+        unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const T) }
+    }
+
+    /// Writes a packed structure to the start of the byte slice
+    pub fn write_struct<T: Copy>(bytes: &mut [u8], value: T) {
+        assert!(bytes.len() >= core::mem::size_of::<T>());
+
+        unsafe { core::ptr::write_unaligned(bytes.as_mut_ptr() as *mut T, value) }
+    }
+
+    /// Writes `len` zero bytes at `offset` by 64 KiB pieces
+    pub fn write_zeroes<W: WriteAt + ?Sized>(target: &W, offset: u64, len: u64) -> Result<()> {
+        const CHUNK: u64 = 64 * 1024;
+        let zeroes = vec![0_u8; core::cmp::min(len, CHUNK) as usize];
+        let mut pos = 0;
+        while pos < len {
+            let n = core::cmp::min(CHUNK, len - pos);
+            target.write_all_at(offset + pos, &zeroes[..n as usize])?;
+            pos += n;
+        }
+
+        Ok(())
+    }
+
+    /// Little endian on-disk integers, panic if `bytes` is too short
+    pub fn le_u16(bytes: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+    }
+
+    pub fn le_u32(bytes: &[u8], pos: usize) -> u32 {
+        let mut raw = [0_u8; 4];
+        raw.copy_from_slice(&bytes[pos..pos + 4]);
+        u32::from_le_bytes(raw)
+    }
+
+    pub fn le_u64(bytes: &[u8], pos: usize) -> u64 {
+        let mut raw = [0_u8; 8];
+        raw.copy_from_slice(&bytes[pos..pos + 8]);
+        u64::from_le_bytes(raw)
+    }
+
+    /// Big endian on-disk integers, panic if `bytes` is too short
+    pub fn be_u16(bytes: &[u8], pos: usize) -> u16 {
+        u16::from_be_bytes([bytes[pos], bytes[pos + 1]])
+    }
+
+    pub fn be_u32(bytes: &[u8], pos: usize) -> u32 {
+        let mut raw = [0_u8; 4];
+        raw.copy_from_slice(&bytes[pos..pos + 4]);
+        u32::from_be_bytes(raw)
+    }
+
+    pub fn be_u64(bytes: &[u8], pos: usize) -> u64 {
+        let mut raw = [0_u8; 8];
+        raw.copy_from_slice(&bytes[pos..pos + 8]);
+        u64::from_be_bytes(raw)
+    }
+
+    /// `bytes[pos..pos + len]`, `Error::UnexpectedEOD` if the position comes from the data itself and is out of bounds
+    fn field(bytes: &[u8], pos: usize, len: usize) -> Result<&[u8]> {
+        pos.checked_add(len).and_then(|end| bytes.get(pos..end)).ok_or(Error::UnexpectedEOD)
+    }
+
+    pub fn try_le_u32(bytes: &[u8], pos: usize) -> Result<u32> {
+        Ok(le_u32(field(bytes, pos, 4)?, 0))
+    }
+
+    pub fn try_le_u64(bytes: &[u8], pos: usize) -> Result<u64> {
+        Ok(le_u64(field(bytes, pos, 8)?, 0))
+    }
+
+    pub fn try_be_u16(bytes: &[u8], pos: usize) -> Result<u16> {
+        Ok(be_u16(field(bytes, pos, 2)?, 0))
+    }
+
+    pub fn try_be_u32(bytes: &[u8], pos: usize) -> Result<u32> {
+        Ok(be_u32(field(bytes, pos, 4)?, 0))
+    }
+
+    pub fn try_be_u64(bytes: &[u8], pos: usize) -> Result<u64> {
+        Ok(be_u64(field(bytes, pos, 8)?, 0))
+    }
+
+    /// Converts zero terminated (or zero padded) ASCII bytes to a String
+    pub fn string_from_ascii_z(bytes: &[u8]) -> String {
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..len]).trim_end().to_string()
+    }
 }
@@ -0,0 +1,36 @@
+use crate::xstd::String;
+
+#[derive(Debug)]
+pub enum LvmError {
+    NoLabel,
+    InvalidLabel,
+    InvalidMetadataHeader,
+    InvalidMetadataChecksum,
+    InvalidMetadata(usize), // line number
+    NoMetadata,
+    MissingPhysicalVolume(String),
+    UnsupportedSegment(String),
+    UnknownLogicalVolume(String),
+}
+
+impl core::fmt::Display for LvmError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LvmError::NoLabel => f.write_str("No LVM2 label"),
+            LvmError::InvalidLabel => f.write_str("Invalid LVM2 label"),
+            LvmError::InvalidMetadataHeader => f.write_str("Invalid LVM2 metadata area header"),
+            LvmError::InvalidMetadataChecksum => f.write_str("LVM2 metadata checksum mismatch"),
+            LvmError::InvalidMetadata(line) => write!(f, "Invalid LVM2 metadata at line {}", line),
+            LvmError::NoMetadata => f.write_str("No LVM2 metadata found"),
+            LvmError::MissingPhysicalVolume(id) => write!(f, "LVM2 physical volume '{}' is missing", id),
+            LvmError::UnsupportedSegment(kind) => write!(f, "Unsupported LVM2 segment type '{}'", kind),
+            LvmError::UnknownLogicalVolume(name) => write!(f, "Unknown LVM2 logical volume '{}'", name),
+        }
+    }
+}
+
+impl From<LvmError> for crate::Error {
+    fn from(e: LvmError) -> Self {
+        Self::Lvm(e)
+    }
+}
@@ -0,0 +1,225 @@
+use super::*;
+
+/// Assembled volume group
+pub struct VolumeGroup<R: ReadAt> {
+    info: VolumeGroupInfo,
+    devices: Vec<Option<R>>, // indexed as info.physical_volumes
+}
+
+impl<R: ReadAt> VolumeGroup<R> {
+    /// Assembles the volume group from its physical volumes (disks, partitions, md arrays, etc.).
+    ///
+    /// The most recent metadata wins, devices that do not belong to the group are dropped.
+    pub fn assemble(devices: Vec<R>) -> Result<Self> {
+        let mut labeled = Vec::with_capacity(devices.len());
+        let mut info: Option<VolumeGroupInfo> = None;
+
+        for device in devices {
+            let label = Label::read(&device)?.ok_or(LvmError::NoLabel)?;
+            if let Some(text) = label.read_metadata(&device)? {
+                let candidate = VolumeGroupInfo::parse(&text)?;
+                let newer = match &info {
+                    Some(info) => candidate.seqno > info.seqno,
+                    None => true,
+                };
+                if newer {
+                    info = Some(candidate);
+                }
+            }
+
+            labeled.push((label.pv_id, device));
+        }
+
+        let info = info.ok_or(LvmError::NoMetadata)?;
+        let mut slots: Vec<Option<R>> = info.physical_volumes.iter().map(|_| None).collect();
+        for (pv_id, device) in labeled {
+            if let Some(index) = info.physical_volumes.iter().position(|pv| pv.id == pv_id) {
+                slots[index] = Some(device);
+            }
+        }
+
+        Ok(Self { info, devices: slots })
+    }
+
+    pub fn info(&self) -> &VolumeGroupInfo {
+        &self.info
+    }
+
+    /// Opens a logical volume, all its physical volumes must be present
+    pub fn open(&self, name: &str) -> Result<LogicalVolume<'_, R>> {
+        let info = self
+            .info
+            .logical_volume(name)
+            .ok_or_else(|| LvmError::UnknownLogicalVolume(name.to_string()))?;
+
+        for segment in &info.segments {
+            match &segment.kind {
+                SegmentKind::Striped { stripes, .. } => {
+                    for stripe in stripes {
+                        if self.devices[stripe.physical_volume].is_none() {
+                            let name = self.info.physical_volumes[stripe.physical_volume].name.clone();
+                            return Err(Error::from(LvmError::MissingPhysicalVolume(name)));
+                        }
+                    }
+                }
+                SegmentKind::Zero => (),
+                SegmentKind::Other(kind) => return Err(Error::from(LvmError::UnsupportedSegment(kind.clone()))),
+            }
+        }
+
+        Ok(LogicalVolume {
+            group: self,
+            info,
+            size: info.extent_count() * self.info.extent_size,
+        })
+    }
+}
+
+/// Maps the offset relative to the segment start to (stripe index, offset within the stripe area, bytes till the stripe chunk end)
+fn stripe_position(segment: &SegmentInfo, extent_size: u64, stripe_size: u64, stripes: u64, offset: u64) -> (usize, u64, u64) {
+    if stripes == 1 {
+        return (0, offset, segment.extent_count * extent_size - offset);
+    }
+
+    let chunk = offset / stripe_size;
+    let offset_in_chunk = offset % stripe_size;
+    (
+        (chunk % stripes) as usize,
+        (chunk / stripes) * stripe_size + offset_in_chunk,
+        stripe_size - offset_in_chunk,
+    )
+}
+
+enum Target<'g, R> {
+    Zero,
+    Device(&'g R, u64),
+}
+
+/// LVM2 logical volume
+pub struct LogicalVolume<'g, R: ReadAt> {
+    group: &'g VolumeGroup<R>,
+    info: &'g LogicalVolumeInfo,
+    size: u64,
+}
+
+impl<'g, R: ReadAt> LogicalVolume<'g, R> {
+    pub fn info(&self) -> &LogicalVolumeInfo {
+        self.info
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Maps the volume offset to the target and the max contiguous length
+    fn map(&self, offset: u64, len: usize) -> (Target<'g, R>, usize) {
+        let extent_size = self.group.info.extent_size;
+        let segment = self
+            .info
+            .segments
+            .iter()
+            .find(|s| offset < (s.start_extent + s.extent_count) * extent_size)
+            .expect("offset is bounded by the volume size");
+
+        let relative = offset - segment.start_extent * extent_size;
+        match &segment.kind {
+            SegmentKind::Striped { stripe_size, stripes } => {
+                let (index, stripe_offset, available) = stripe_position(segment, extent_size, *stripe_size, stripes.len() as u64, relative);
+                let stripe = &stripes[index];
+                let pv = &self.group.info.physical_volumes[stripe.physical_volume];
+                let device = self.group.devices[stripe.physical_volume].as_ref().expect("checked in open");
+
+                let device_offset = pv.pe_start + stripe.start_extent * extent_size + stripe_offset;
+                (
+                    Target::Device(device, device_offset),
+                    core::cmp::min(len as u64, available) as usize,
+                )
+            }
+            _ => {
+                let available = (segment.start_extent + segment.extent_count) * extent_size - offset;
+                (Target::Zero, core::cmp::min(len as u64, available) as usize)
+            }
+        }
+    }
+}
+
+impl<'g, R: ReadAt> ReadAt for LogicalVolume<'g, R> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size, offset, buffer.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::ReadBeyondEOD),
+        };
+
+        match self.map(offset, len) {
+            (Target::Device(device, device_offset), len) => device.read_at(device_offset, &mut buffer[..len]),
+            (Target::Zero, len) => {
+                buffer[..len].iter_mut().for_each(|b| *b = 0);
+                Ok(len)
+            }
+        }
+    }
+}
+
+impl<'g, R: ReadAt + WriteAt> WriteAt for LogicalVolume<'g, R> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size, offset, data.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::WriteBeyondEOD),
+        };
+
+        match self.map(offset, len) {
+            (Target::Device(device, device_offset), len) => device.write_at(device_offset, &data[..len]),
+            // the zero target discards writes
+            (Target::Zero, len) => Ok(len),
+        }
+    }
+}
+
+impl<'g, R: ReadAt + Flush> Flush for LogicalVolume<'g, R> {
+    fn flush(&self) -> Result<()> {
+        for device in self.group.devices.iter().flatten() {
+            device.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'g, R: ReadAt + WriteAt + Flush> Disk for LogicalVolume<'g, R> {
+    fn geometry(&self) -> Result<Geometry> {
+        Ok(Geometry::with_vhd_capacity(self.size))
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.size)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        Ok(crate::sizes::SECTOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stripe_position_test() {
+        let segment = SegmentInfo {
+            start_extent: 0,
+            extent_count: 8,
+            kind: SegmentKind::Zero,
+        };
+
+        // linear
+        assert_eq!((0, 100, 8 * 4096 - 100), stripe_position(&segment, 4096, 0, 1, 100));
+
+        // 2 stripes of 1 KiB
+        assert_eq!((0, 10, 1014), stripe_position(&segment, 4096, 1024, 2, 10));
+        assert_eq!((1, 0, 1024), stripe_position(&segment, 4096, 1024, 2, 1024));
+        assert_eq!((0, 1024 + 1, 1023), stripe_position(&segment, 4096, 1024, 2, 2048 + 1));
+        assert_eq!((1, 1024 + 1023, 1), stripe_position(&segment, 4096, 1024, 2, 3 * 1024 + 1023));
+    }
+}
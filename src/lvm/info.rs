@@ -0,0 +1,380 @@
+use super::metadata::{self, Section};
+use super::*;
+use core::convert::TryFrom;
+
+/// Physical volume as described in the volume group metadata
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct PhysicalVolumeInfo {
+    /// Metadata name, like `pv0`
+    pub name: String,
+    pub id: String,
+    /// Device path at the time the metadata was written
+    pub device: String,
+    pub pe_start: u64,
+    pub pe_count: u64,
+}
+
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Stripe {
+    /// Index in `VolumeGroupInfo::physical_volumes`
+    pub physical_volume: usize,
+    pub start_extent: u64,
+}
+
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum SegmentKind {
+    /// Linear segments are striped ones with a single stripe
+    Striped {
+        stripe_size: u64,
+        stripes: Vec<Stripe>,
+    },
+    Zero,
+    Other(String),
+}
+
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct SegmentInfo {
+    pub start_extent: u64,
+    pub extent_count: u64,
+    pub kind: SegmentKind,
+}
+
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct LogicalVolumeInfo {
+    pub name: String,
+    pub id: String,
+    pub status: Vec<String>,
+    pub segments: Vec<SegmentInfo>,
+}
+
+impl LogicalVolumeInfo {
+    /// Size in extents
+    pub fn extent_count(&self) -> u64 {
+        self.segments.iter().map(|s| s.extent_count).sum()
+    }
+
+    /// Only visible volumes are meant to be opened by users, others are internal parts of thin pools, mirrors, etc.
+    pub fn is_visible(&self) -> bool {
+        self.status.iter().any(|s| s == "VISIBLE")
+    }
+}
+
+/// Volume group metadata
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct VolumeGroupInfo {
+    pub name: String,
+    pub id: String,
+    pub seqno: u64,
+    pub extent_size: u64,
+    pub physical_volumes: Vec<PhysicalVolumeInfo>,
+    pub logical_volumes: Vec<LogicalVolumeInfo>,
+}
+
+fn invalid() -> Error {
+    Error::from(LvmError::InvalidMetadata(0))
+}
+
+fn required_int(section: &Section, name: &str) -> Result<u64> {
+    match section.int(name) {
+        Some(value) if value >= 0 => Ok(value as u64),
+        _ => Err(invalid()),
+    }
+}
+
+fn required_sectors(section: &Section, name: &str) -> Result<u64> {
+    required_int(section, name)?.checked_mul(SECTOR).ok_or_else(invalid)
+}
+
+fn required_str(section: &Section, name: &str) -> Result<String> {
+    section.string(name).map(ToString::to_string).ok_or_else(invalid)
+}
+
+fn strings(section: &Section, name: &str) -> Vec<String> {
+    section
+        .array(name)
+        .map(|values| values.iter().filter_map(|v| v.as_str()).map(ToString::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn parse_segment(section: &Section, physical_volumes: &[PhysicalVolumeInfo]) -> Result<SegmentInfo> {
+    let kind = match section.string("type").ok_or_else(invalid)? {
+        "striped" => {
+            let values = section.array("stripes").ok_or_else(invalid)?;
+            let mut stripes = Vec::with_capacity(values.len() / 2);
+            for pair in values.chunks(2) {
+                let name = pair[0].as_str().ok_or_else(invalid)?;
+                let start_extent = pair
+                    .get(1)
+                    .and_then(|v| v.as_int())
+                    .and_then(|v| u64::try_from(v).ok())
+                    .ok_or_else(invalid)?;
+                let physical_volume = physical_volumes
+                    .iter()
+                    .position(|pv| pv.name == name)
+                    .ok_or_else(|| LvmError::MissingPhysicalVolume(name.to_string()))?;
+                stripes.push(Stripe {
+                    physical_volume,
+                    start_extent,
+                });
+            }
+
+            if stripes.is_empty() {
+                return Err(invalid());
+            }
+
+            let stripe_size = match stripes.len() {
+                1 => 0,
+                _ => match required_sectors(section, "stripe_size")? {
+                    0 => return Err(invalid()),
+                    size => size,
+                },
+            };
+
+            SegmentKind::Striped { stripe_size, stripes }
+        }
+        "zero" => SegmentKind::Zero,
+        other => SegmentKind::Other(other.to_string()),
+    };
+
+    Ok(SegmentInfo {
+        start_extent: required_int(section, "start_extent")?,
+        extent_count: required_int(section, "extent_count")?,
+        kind,
+    })
+}
+
+/// The segments sorted by `start_extent` must follow each other from the volume start,
+/// the stripe areas must fit their physical volumes
+fn check_segments(segments: &[SegmentInfo], extent_size: u64, physical_volumes: &[PhysicalVolumeInfo]) -> Result<()> {
+    let mut end = 0_u64;
+    for segment in segments {
+        if segment.start_extent != end || segment.extent_count == 0 {
+            return Err(invalid());
+        }
+        end = end.checked_add(segment.extent_count).ok_or_else(invalid)?;
+
+        if let SegmentKind::Striped { stripes, .. } = &segment.kind {
+            let count = stripes.len() as u64;
+            if !segment.extent_count.is_multiple_of(count) {
+                return Err(invalid());
+            }
+
+            for stripe in stripes {
+                match stripe.start_extent.checked_add(segment.extent_count / count) {
+                    Some(stripe_end) if stripe_end <= physical_volumes[stripe.physical_volume].pe_count => (),
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+    }
+
+    // the volume size in bytes
+    end.checked_mul(extent_size).map(|_| ()).ok_or_else(invalid)
+}
+
+impl VolumeGroupInfo {
+    /// Parses the metadata text as read by `Label::read_metadata`
+    pub fn parse(text: &str) -> Result<Self> {
+        let root = metadata::parse(text)?;
+        let (name, vg) = root.sections().next().ok_or(LvmError::NoMetadata)?;
+        let extent_size = required_sectors(vg, "extent_size")?;
+        if extent_size == 0 {
+            return Err(invalid());
+        }
+
+        let mut physical_volumes = Vec::new();
+        if let Some(section) = vg.section("physical_volumes") {
+            for (name, pv) in section.sections() {
+                let pe_start = required_sectors(pv, "pe_start")?;
+                let pe_count = required_int(pv, "pe_count")?;
+                // the extents are addressed by their device offsets
+                pe_count
+                    .checked_mul(extent_size)
+                    .and_then(|size| size.checked_add(pe_start))
+                    .ok_or_else(invalid)?;
+
+                physical_volumes.push(PhysicalVolumeInfo {
+                    name: name.to_string(),
+                    id: required_str(pv, "id")?,
+                    device: pv.string("device").unwrap_or_default().to_string(),
+                    pe_start,
+                    pe_count,
+                });
+            }
+        }
+
+        let mut logical_volumes = Vec::new();
+        if let Some(section) = vg.section("logical_volumes") {
+            for (name, lv) in section.sections() {
+                let mut segments = lv
+                    .sections()
+                    .filter(|(key, _)| key.starts_with("segment"))
+                    .map(|(_, segment)| parse_segment(segment, &physical_volumes))
+                    .collect::<Result<Vec<_>>>()?;
+                segments.sort_by_key(|s| s.start_extent);
+                check_segments(&segments, extent_size, &physical_volumes)?;
+
+                logical_volumes.push(LogicalVolumeInfo {
+                    name: name.to_string(),
+                    id: required_str(lv, "id")?,
+                    status: strings(lv, "status"),
+                    segments,
+                });
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            id: required_str(vg, "id")?,
+            seqno: required_int(vg, "seqno")?,
+            extent_size,
+            physical_volumes,
+            logical_volumes,
+        })
+    }
+
+    pub fn logical_volume(&self, name: &str) -> Option<&LogicalVolumeInfo> {
+        self.logical_volumes.iter().find(|lv| lv.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"vg0 {
+id = "Q3Fh1z-9dNV-bG0E-Z1Xd-3bPm-3Akg-1tXy0x"
+seqno = 4
+format = "lvm2"
+status = ["RESIZEABLE", "READ", "WRITE"]
+extent_size = 8
+max_lv = 0
+max_pv = 0
+
+physical_volumes {
+pv0 {
+id = "AAAAAA-AAAA-AAAA-AAAA-AAAA-AAAA-AAAAAA"
+device = "/dev/sdb1"
+status = ["ALLOCATABLE"]
+dev_size = 2048
+pe_start = 16
+pe_count = 250
+}
+pv1 {
+id = "BBBBBB-BBBB-BBBB-BBBB-BBBB-BBBB-BBBBBB"
+device = "/dev/sdc1"
+dev_size = 2048
+pe_start = 16
+pe_count = 250
+}
+}
+
+logical_volumes {
+linear {
+id = "lv0000-0000-0000-0000-0000-0000-000000"
+status = ["READ", "WRITE", "VISIBLE"]
+segment_count = 2
+segment2 {
+start_extent = 10
+extent_count = 5
+type = "striped"
+stripe_count = 1
+stripes = [
+"pv1", 0
+]
+}
+segment1 {
+start_extent = 0
+extent_count = 10
+type = "striped"
+stripe_count = 1
+stripes = [
+"pv0", 0
+]
+}
+}
+striped {
+id = "lv1111-1111-1111-1111-1111-1111-111111"
+status = ["READ", "WRITE", "VISIBLE"]
+segment_count = 1
+segment1 {
+start_extent = 0
+extent_count = 8
+type = "striped"
+stripe_count = 2
+stripe_size = 2
+stripes = [
+"pv0", 10,
+"pv1", 5
+]
+}
+}
+}
+}
+contents = "Text Format Volume Group"
+version = 1
+"#;
+
+    #[test]
+    fn volume_group_info_test() {
+        let vg = VolumeGroupInfo::parse(METADATA).unwrap();
+        assert_eq!("vg0", vg.name);
+        assert_eq!(4, vg.seqno);
+        assert_eq!(8 * 512, vg.extent_size);
+        assert_eq!(2, vg.physical_volumes.len());
+        assert_eq!(16 * 512, vg.physical_volumes[1].pe_start);
+
+        let linear = vg.logical_volume("linear").unwrap();
+        assert!(linear.is_visible());
+        assert_eq!(15, linear.extent_count());
+        assert_eq!(0, linear.segments[0].start_extent);
+        match &linear.segments[1].kind {
+            SegmentKind::Striped { stripes, .. } => assert_eq!(1, stripes[0].physical_volume),
+            _ => panic!("striped segment expected"),
+        }
+
+        let striped = vg.logical_volume("striped").unwrap();
+        match &striped.segments[0].kind {
+            SegmentKind::Striped { stripe_size, stripes } => {
+                assert_eq!(1024, *stripe_size);
+                assert_eq!(10, stripes[0].start_extent);
+                assert_eq!(5, stripes[1].start_extent);
+            }
+            _ => panic!("striped segment expected"),
+        }
+    }
+
+    #[test]
+    fn corrupt_metadata_test() {
+        let corruptions = [
+            // no stripe size for 2 stripes
+            ("stripe_size = 2", "stripe_size = 0"),
+            // a gap between the segments
+            ("start_extent = 10", "start_extent = 11"),
+            // overlapping segments
+            ("start_extent = 10", "start_extent = 9"),
+            // a negative stripe start
+            ("\"pv1\", 5", "\"pv1\", -5"),
+            // the stripe area past the physical volume end
+            ("\"pv1\", 5", "\"pv1\", 247"),
+            // the byte offsets overflow
+            (
+                "pe_start = 16\npe_count = 250\n}\npv1",
+                "pe_start = 36028797018963968\npe_count = 250\n}\npv1",
+            ),
+            ("extent_size = 8", "extent_size = 36028797018963968"),
+            ("extent_size = 8", "extent_size = 0"),
+            ("stripe_size = 2", "stripe_size = 36028797018963968"),
+            ("pe_count = 250\n}\n}", "pe_count = 9223372036854775807\n}\n}"),
+        ];
+
+        for (from, to) in corruptions.iter() {
+            assert!(METADATA.contains(from), "{}", from);
+            let text = METADATA.replacen(from, to, 1);
+            match VolumeGroupInfo::parse(&text) {
+                Err(Error::Lvm(LvmError::InvalidMetadata(_))) => (),
+                other => panic!("'{}' is accepted: {:?}", to, other.map(|vg| vg.name)),
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use super::*;
+
+const LABEL_ID: &[u8] = b"LABELONE";
+const LABEL_TYPE: &[u8] = b"LVM2 001";
+const LABEL_SCAN_SECTORS: u64 = 4;
+
+const MDA_MAGIC: &[u8] = b" LVM2 x[5A%r0N*>";
+const MDA_HEADER_SIZE: usize = 512;
+const MDA_VERSION: u32 = 1;
+const RAW_LOCN_IGNORED: u32 = 0x0000_0001;
+
+const PV_ID_LENGTH: usize = 32;
+
+/// Data or metadata area of a physical volume, in bytes
+#[derive(Copy, Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Area {
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// LVM2 physical volume label
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Label {
+    /// Formatted the same way as in metadata: `xxxxxx-xxxx-xxxx-xxxx-xxxx-xxxx-xxxxxx`
+    pub pv_id: String,
+    pub device_size: u64,
+    pub data_areas: Vec<Area>,
+    pub metadata_areas: Vec<Area>,
+}
+
+fn format_pv_id(raw: &[u8]) -> String {
+    let mut id = String::with_capacity(PV_ID_LENGTH + 6);
+    for (i, &c) in raw.iter().enumerate() {
+        if matches!(i, 6 | 10 | 14 | 18 | 22 | 26) {
+            id.push('-');
+        }
+        id.push(c as char);
+    }
+
+    id
+}
+
+/// Reads null-terminated list of areas, returns the list and the position after the terminator
+fn read_areas(bytes: &[u8], mut pos: usize) -> Result<(Vec<Area>, usize)> {
+    let mut areas = Vec::new();
+    loop {
+        let offset = try_le_u64(bytes, pos)?;
+        let size = try_le_u64(bytes, pos + 8)?;
+        pos += 16;
+        if offset == 0 {
+            return Ok((areas, pos));
+        }
+
+        areas.push(Area { offset, size });
+    }
+}
+
+impl Label {
+    /// Looks for the label in the first 4 sectors of the device
+    pub fn read(device: &impl ReadAt) -> Result<Option<Self>> {
+        let mut sector = [0_u8; SECTOR as usize];
+        for index in 0..LABEL_SCAN_SECTORS {
+            device.read_exact_at(index * SECTOR, &mut sector)?;
+            if &sector[..8] != LABEL_ID || try_le_u64(&sector, 8)? != index {
+                continue;
+            }
+
+            if try_le_u32(&sector, 16)? != crc::crc32_raw(INITIAL_CRC, &sector[20..]) || &sector[24..32] != LABEL_TYPE {
+                return Err(Error::from(LvmError::InvalidLabel));
+            }
+
+            let pv_header = try_le_u32(&sector, 20)? as usize;
+            let raw_id = sector.get(pv_header..pv_header + PV_ID_LENGTH).ok_or(LvmError::InvalidLabel)?;
+            let pv_id = format_pv_id(raw_id);
+            let device_size = try_le_u64(&sector, pv_header + PV_ID_LENGTH)?;
+
+            let (data_areas, pos) = read_areas(&sector, pv_header + PV_ID_LENGTH + 8)?;
+            let (metadata_areas, _) = read_areas(&sector, pos)?;
+
+            return Ok(Some(Self {
+                pv_id,
+                device_size,
+                data_areas,
+                metadata_areas,
+            }));
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the most recent metadata text from the first usable metadata area.
+    ///
+    /// Returns `None` if the physical volume has no metadata areas (`pvcreate --metadatacopies 0`).
+    pub fn read_metadata(&self, device: &impl ReadAt) -> Result<Option<String>> {
+        for area in &self.metadata_areas {
+            if let Some(text) = read_metadata_area(device, area)? {
+                return Ok(Some(text));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn read_metadata_area(device: &impl ReadAt, area: &Area) -> Result<Option<String>> {
+    let mut header = [0_u8; MDA_HEADER_SIZE];
+    device.read_exact_at(area.offset, &mut header)?;
+
+    if try_le_u32(&header, 0)? != crc::crc32_raw(INITIAL_CRC, &header[4..])
+        || &header[4..20] != MDA_MAGIC
+        || try_le_u32(&header, 20)? != MDA_VERSION
+    {
+        return Err(Error::from(LvmError::InvalidMetadataHeader));
+    }
+
+    let start = try_le_u64(&header, 24)?;
+    let size = try_le_u64(&header, 32)?;
+
+    // the first raw_locn points to the committed metadata
+    let offset = try_le_u64(&header, 40)?;
+    let length = try_le_u64(&header, 48)?;
+    let checksum = try_le_u32(&header, 56)?;
+    let flags = try_le_u32(&header, 60)?;
+    if offset == 0 || length == 0 || flags & RAW_LOCN_IGNORED != 0 {
+        return Ok(None);
+    }
+
+    if offset >= size || length > size - MDA_HEADER_SIZE as u64 {
+        return Err(Error::from(LvmError::InvalidMetadataHeader));
+    }
+
+    // the metadata area is a circular buffer, the text may wrap to the start just after the header
+    let mut text = vec![0_u8; length as usize];
+    let first = core::cmp::min(length, size - offset) as usize;
+    device.read_exact_at(start + offset, &mut text[..first])?;
+    if first < text.len() {
+        device.read_exact_at(start + MDA_HEADER_SIZE as u64, &mut text[first..])?;
+    }
+
+    if crc::crc32_raw(INITIAL_CRC, &text) != checksum {
+        return Err(Error::from(LvmError::InvalidMetadataChecksum));
+    }
+
+    while text.last() == Some(&0) {
+        text.pop();
+    }
+
+    Ok(Some(String::from_utf8_lossy(&text).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pv_id_test() {
+        assert_eq!(
+            "ABCDEF-GHIJ-KLMN-OPQR-STUV-WXYZ-012345",
+            format_pv_id(b"ABCDEFGHIJKLMNOPQRSTUVWXYZ012345")
+        );
+    }
+}
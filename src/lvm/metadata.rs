@@ -0,0 +1,276 @@
+//! LVM2 text metadata parser
+//!
+//! The format is a tree of `name { ... }` sections and `name = value` entries,
+//! where a value is an integer, a quoted string or an array of them. `#` starts a comment.
+use super::*;
+
+pub(crate) enum Value {
+    Int(i64),
+    Str(String),
+    Array(Vec<Value>),
+    Section(Section),
+}
+
+impl Value {
+    pub(crate) fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Section {
+    entries: Vec<(String, Value)>,
+}
+
+impl Section {
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.entries.iter().find(|(key, _)| key == name).map(|(_, value)| value)
+    }
+
+    pub(crate) fn int(&self, name: &str) -> Option<i64> {
+        self.get(name).and_then(Value::as_int)
+    }
+
+    pub(crate) fn string(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(Value::as_str)
+    }
+
+    pub(crate) fn array(&self, name: &str) -> Option<&[Value]> {
+        match self.get(name) {
+            Some(Value::Array(values)) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn section(&self, name: &str) -> Option<&Section> {
+        match self.get(name) {
+            Some(Value::Section(section)) => Some(section),
+            _ => None,
+        }
+    }
+
+    /// Nested sections in the metadata order
+    pub(crate) fn sections(&self) -> impl Iterator<Item = (&str, &Section)> {
+        self.entries.iter().filter_map(|(key, value)| match value {
+            Value::Section(section) => Some((key.as_str(), section)),
+            _ => None,
+        })
+    }
+}
+
+struct Parser<'t> {
+    text: &'t [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'t> Parser<'t> {
+    fn error(&self) -> Error {
+        Error::from(LvmError::InvalidMetadata(self.line))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                b'\n' => self.line += 1,
+                b'#' => {
+                    while !matches!(self.peek(), None | Some(b'\n')) {
+                        self.pos += 1;
+                    }
+                    continue;
+                }
+                c if c.is_ascii_whitespace() => (),
+                _ => return,
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_whitespace() || b"={}[],\"#".contains(&c) {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        if start == self.pos {
+            return Err(self.error());
+        }
+
+        Ok(String::from_utf8_lossy(&self.text[start..self.pos]).into_owned())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1; // opening quote
+        let mut value = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error()),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    value.push(self.peek().ok_or_else(|| self.error())?);
+                }
+                Some(c) => {
+                    if c == b'\n' {
+                        self.line += 1;
+                    }
+                    value.push(c);
+                }
+            }
+            self.pos += 1;
+        }
+        self.pos += 1; // closing quote
+
+        Ok(String::from_utf8_lossy(&value).into_owned())
+    }
+
+    fn int(&mut self) -> Result<i64> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+
+        core::str::from_utf8(&self.text[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| self.error())
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some(b'"') => Ok(Value::Str(self.string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b']') => break,
+                        Some(b',') => self.pos += 1,
+                        _ => values.push(self.value()?),
+                    }
+                }
+                self.pos += 1;
+                Ok(Value::Array(values))
+            }
+            Some(c) if c == b'-' || c.is_ascii_digit() => Ok(Value::Int(self.int()?)),
+            _ => Err(self.error()),
+        }
+    }
+
+    /// Parses entries till the closing brace or the end of text
+    fn section(&mut self, nested: bool) -> Result<Section> {
+        let mut section = Section::default();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None if nested => return Err(self.error()),
+                None => return Ok(section),
+                Some(b'}') if nested => {
+                    self.pos += 1;
+                    return Ok(section);
+                }
+                _ => (),
+            }
+
+            let name = self.identifier()?;
+            self.skip_whitespace();
+            let value = match self.peek() {
+                Some(b'{') => {
+                    self.pos += 1;
+                    Value::Section(self.section(true)?)
+                }
+                Some(b'=') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    self.value()?
+                }
+                _ => return Err(self.error()),
+            };
+
+            section.entries.push((name, value));
+        }
+    }
+}
+
+pub(crate) fn parse(text: &str) -> Result<Section> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+        line: 1,
+    };
+
+    parser.section(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let root = parse(
+            r#"vg0 {
+id = "1hKWIO-cJ3t-k4sm-u2DB-OmVm-Dp1X-0LbW7S"
+seqno = 3
+status = ["RESIZEABLE", "READ", "WRITE"]
+extent_size = 8192  # 4 Megabytes
+
+physical_volumes {
+pv0 {
+id = "say \"hi\""
+pe_start = 2048
+}
+}
+}
+# Generated by LVM2
+contents = "Text Format Volume Group"
+version = -1
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(Some("Text Format Volume Group"), root.string("contents"));
+        assert_eq!(Some(-1), root.int("version"));
+
+        let (name, vg) = root.sections().next().unwrap();
+        assert_eq!("vg0", name);
+        assert_eq!(Some(3), vg.int("seqno"));
+        assert_eq!(Some(8192), vg.int("extent_size"));
+        assert_eq!(3, vg.array("status").unwrap().len());
+        assert_eq!(Some("READ"), vg.array("status").unwrap()[1].as_str());
+
+        let pv = vg.section("physical_volumes").unwrap().section("pv0").unwrap();
+        assert_eq!(Some("say \"hi\""), pv.string("id"));
+        assert_eq!(Some(2048), pv.int("pe_start"));
+    }
+
+    #[test]
+    fn parse_error_test() {
+        match parse("vg0 {\nid = \n}") {
+            Err(Error::Lvm(LvmError::InvalidMetadata(line))) => assert_eq!(3, line),
+            _ => panic!("error expected"),
+        }
+        assert!(parse("vg0 {\nseqno = 1\n").is_err());
+    }
+}
@@ -0,0 +1,32 @@
+//! Linux Logical Volume Manager (LVM2)
+//!
+//! Physical volumes carry a label with the text metadata of the whole volume group.
+//! Linear and striped logical volumes can be read and written.
+//! See https://github.com/lvmteam/lvm2/blob/master/lib/format_text/layout.h
+use crate::prelude::*;
+use crate::tools::{try_le_u32, try_le_u64};
+
+mod error;
+pub use error::LvmError;
+
+mod label;
+pub use label::{Area, Label};
+
+mod metadata;
+
+mod info;
+pub use info::{LogicalVolumeInfo, PhysicalVolumeInfo, SegmentInfo, SegmentKind, Stripe, VolumeGroupInfo};
+
+mod group;
+pub use group::{LogicalVolume, VolumeGroup};
+
+const SECTOR: u64 = crate::sizes::SECTOR_U64;
+
+/// Initial value of all LVM2 CRCs
+const INITIAL_CRC: u32 = 0xf597_a6cf;
+
+/// GPT type of Linux LVM partitions
+pub fn gpt_partition_kind() -> Uuid {
+    Uuid::from_u128(0xE6D6_D379_F507_44C2_A23C_238F_2A3D_F928)
+}
+
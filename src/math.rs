@@ -26,6 +26,14 @@ pub fn bound_to(data_size: u64, offset: u64, len: usize) -> Option<usize> {
     None
 }
 
+/// The `len` bytes at `offset` are inside the data
+pub fn is_range_valid(data_size: u64, offset: u64, len: u64) -> bool {
+    match offset.checked_add(len) {
+        Some(end) => end <= data_size,
+        None => false,
+    }
+}
+
 pub fn rest(data_size: u64, offset: u64, len: usize) -> usize {
     match bound_to(data_size, offset, len) {
         Some(sz) => sz,
@@ -65,6 +73,14 @@ mod tests {
         assert_eq!(None, bound_to(512, 513, 10));
     }
 
+    #[test]
+    fn is_range_valid_test() {
+        assert!(is_range_valid(512, 0, 512));
+        assert!(is_range_valid(512, 512, 0));
+        assert!(!is_range_valid(512, 511, 2));
+        assert!(!is_range_valid(512, u64::MAX, 2));
+    }
+
     #[test]
     fn rest_test() {
         assert_eq!(0, rest(512, 512, 10));
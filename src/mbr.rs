@@ -20,7 +20,7 @@ pub enum KnownPartitionKind {
     // Also used for FAT12 and FAT16 volumes in primary partitions if they are not residing in first physical 32 MB of disk
     Fat16BCHS = 0x06,
 
-    // May be exFAT!!!
+    // Also used by exFAT, see `exfat::is_exfat`
     Ntfs = 0x07,
 
     // FAT32 with CHS addressing
@@ -41,6 +41,17 @@ pub enum KnownPartitionKind {
     // Something related to MS Dynamic disks
     DynamicExtendedPartition = 0x42,
 
+    // BSD slices with a nested disklabel
+    FreeBsd = 0xA5,
+    OpenBsd = 0xA6,
+    NetBsd = 0xA9,
+
+    // Linux LVM2 physical volume
+    LinuxLvm = 0x8E,
+
+    // Linux md RAID member with autodetect
+    LinuxRaid = 0xFD,
+
     // GPT
     GptProtectiveMBR = 0xEE,
 
@@ -78,6 +89,14 @@ impl From<u8> for PartitionKind {
 }
 
 impl PartitionKind {
+    /// The raw partition type byte
+    pub fn id(self) -> u8 {
+        match self {
+            PartitionKind::Known(k) => k as u8,
+            PartitionKind::Unknown(id) => id,
+        }
+    }
+
     pub fn is_extended(self) -> bool {
         match self {
             PartitionKind::Known(KnownPartitionKind::ExtendedLBA) => true,
@@ -85,9 +104,35 @@ impl PartitionKind {
             _ => false,
         }
     }
+
+    /// The disk is a Windows dynamic disk, see `ldm` module
+    pub fn is_dynamic(self) -> bool {
+        matches!(self, PartitionKind::Known(KnownPartitionKind::DynamicExtendedPartition))
+    }
+
+    /// The partition contains a BSD disklabel
+    pub fn is_bsd(self) -> bool {
+        matches!(
+            self,
+            PartitionKind::Known(KnownPartitionKind::FreeBsd)
+                | PartitionKind::Known(KnownPartitionKind::OpenBsd)
+                | PartitionKind::Known(KnownPartitionKind::NetBsd)
+        )
+    }
+
+    /// The partition is a Linux md RAID member, see `md` module
+    pub fn is_linux_raid(self) -> bool {
+        matches!(self, PartitionKind::Known(KnownPartitionKind::LinuxRaid))
+    }
+
+    /// The partition is an LVM2 physical volume, see `lvm` module
+    pub fn is_linux_lvm(self) -> bool {
+        matches!(self, PartitionKind::Known(KnownPartitionKind::LinuxLvm))
+    }
 }
 
 #[repr(C, packed)]
+#[derive(Copy, Clone)]
 pub(crate) struct PartitionRecord {
     pub(crate) bootstrap_flags: u8,
     pub(crate) starting_head: u8,
@@ -102,6 +147,7 @@ pub(crate) struct PartitionRecord {
 }
 
 #[repr(C, packed)]
+#[derive(Copy, Clone)]
 pub(crate) struct MasterBootRecord {
     pub(crate) boot_code: [u8; 440],
     pub(crate) disk_signature: u32,
@@ -117,8 +163,81 @@ impl MasterBootRecord {
         SIGNATURE == self.signature
     }
 
+    /// Hybrid MBRs may place the 0xEE entry into any slot, not only the first one.
     pub fn is_gpt_protective(&self) -> bool {
-        self.is_valid() && self.partition_table[0].partition_kind == KnownPartitionKind::GptProtectiveMBR as u8
+        self.is_valid() && self.gpt_protective_index().is_some()
+    }
+
+    /// There are used entries besides the 0xEE one
+    pub(crate) fn is_hybrid(&self) -> bool {
+        let protective_index = self.gpt_protective_index();
+        self.partition_table
+            .iter()
+            .enumerate()
+            .any(|(i, r)| Some(i) != protective_index && !r.is_unused())
+    }
+
+    pub(crate) fn gpt_protective_index(&self) -> Option<usize> {
+        self.partition_table
+            .iter()
+            .position(|r| r.partition_kind == KnownPartitionKind::GptProtectiveMBR as u8)
+    }
+}
+
+impl crate::AsByteSlice for MasterBootRecord {
+    unsafe fn as_byte_slice(&self) -> &[u8] {
+        core::slice::from_raw_parts(self as *const Self as *const u8, core::mem::size_of::<Self>())
+    }
+}
+
+/// CHS value for the addresses that do not fit into 1024 cylinders
+const CHS_OVERFLOW: (u8, u8, u8) = (0xFE, 0xFF, 0xFF);
+
+/// Converts LBA to the packed (head, sector, cylinder) triple used by the partition record.
+pub(crate) fn lba_to_chs(lba: u32, geometry: &Geometry) -> (u8, u8, u8) {
+    let (heads, sectors) = if (1..=255).contains(&geometry.heads_per_cylinder) && (1..=63).contains(&geometry.sectors_per_track) {
+        (geometry.heads_per_cylinder, geometry.sectors_per_track)
+    } else {
+        (255, 63)
+    };
+
+    let cylinder = lba / (heads * sectors);
+    if cylinder > 1023 {
+        return CHS_OVERFLOW;
+    }
+
+    let head = (lba / sectors) % heads;
+    let sector = lba % sectors + 1;
+
+    (head as u8, (sector as u8) | ((cylinder >> 2) as u8 & 0xC0), cylinder as u8)
+}
+
+impl PartitionRecord {
+    pub(crate) fn empty() -> Self {
+        unsafe { core::mem::zeroed() }
+    }
+
+    pub(crate) fn new(kind: u8, boot: bool, first_sector_lba: u32, sectors: u32, geometry: &Geometry) -> Self {
+        let (starting_head, starting_sector, starting_cylinder) = lba_to_chs(first_sector_lba, geometry);
+        let last_lba = first_sector_lba.saturating_add(sectors.saturating_sub(1));
+        let (end_head, end_sector, end_cylinder) = lba_to_chs(last_lba, geometry);
+
+        Self {
+            bootstrap_flags: if boot { 0x80 } else { 0 },
+            starting_head,
+            starting_sector,
+            starting_cylinder,
+            partition_kind: kind,
+            end_head,
+            end_sector,
+            end_cylinder,
+            first_sector_lba,
+            partition_size_in_sectors: sectors,
+        }
+    }
+
+    pub(crate) fn is_unused(&self) -> bool {
+        self.partition_kind == KnownPartitionKind::Empty as u8 || self.partition_size_in_sectors == 0
     }
 }
 
@@ -130,7 +249,7 @@ pub struct PartitionInfo {
 }
 
 impl PartitionInfo {
-    fn new(record: &PartitionRecord, sector_size: u64, relative_offset: u64) -> Self {
+    pub(crate) fn new(record: &PartitionRecord, sector_size: u64, relative_offset: u64) -> Self {
         let offset = record.first_sector_lba as u64 * sector_size + relative_offset;
         let length = record.partition_size_in_sectors as u64 * sector_size;
         let boot = (record.bootstrap_flags & 0x80) == 0x80;
@@ -148,6 +267,7 @@ pub struct Layout {
     mbr: MasterBootRecord,
     extended_partitions: Vec<PartitionInfo>,
     partitions: Vec<PartitionInfo>,
+    bsd_labels: Vec<crate::bsd::Layout>,
 }
 
 fn read_extended_partition(
@@ -212,10 +332,18 @@ impl Layout {
             }
         }
 
+        let mut bsd_labels = Vec::new();
+        for info in partitions.iter().filter(|p| p.kind.is_bsd()) {
+            if let Some(label) = crate::bsd::Layout::read(disk, info.offset, info.length)? {
+                bsd_labels.push(label);
+            }
+        }
+
         Ok(Layout {
             mbr,
             extended_partitions,
             partitions,
+            bsd_labels,
         })
     }
 
@@ -234,4 +362,31 @@ impl Layout {
     pub fn extended_partitions(&self) -> &[PartitionInfo] {
         &self.extended_partitions
     }
+
+    /// Disklabels found inside BSD slices
+    pub fn bsd_labels(&self) -> &[crate::bsd::Layout] {
+        &self.bsd_labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lba_to_chs_test() {
+        let geometry = Geometry::chs(1024, 255, 63);
+        assert_eq!((0, 1, 0), lba_to_chs(0, &geometry));
+        assert_eq!((0, 2, 0), lba_to_chs(1, &geometry));
+        assert_eq!((1, 1, 0), lba_to_chs(63, &geometry));
+        assert_eq!((0, 1, 1), lba_to_chs(255 * 63, &geometry));
+        assert_eq!((254, 0xFF, 0xFF), lba_to_chs(1024 * 255 * 63 - 1, &geometry));
+        assert_eq!(CHS_OVERFLOW, lba_to_chs(1024 * 255 * 63, &geometry));
+    }
+
+    #[test]
+    fn partition_kind_id_test() {
+        assert_eq!(0xEE, PartitionKind::from(0xEE).id());
+        assert_eq!(0x83, PartitionKind::from(0x83).id());
+    }
 }
@@ -0,0 +1,303 @@
+use super::*;
+
+struct Member<R: ReadAt> {
+    device: R,
+    superblock: Superblock,
+}
+
+/// A part of the array striped across the same set of members.
+///
+/// raid0 members of different sizes produce several zones, linear arrays have one zone per member.
+struct Zone {
+    start: u64,
+    length: u64,
+    /// Offset from the member data start
+    member_offset: u64,
+    chunk_size: u64,
+    slots: Vec<usize>,
+}
+
+impl Zone {
+    /// Maps the array offset to (slot, member offset, bytes till the chunk end)
+    fn map(&self, offset: u64) -> (usize, u64, u64) {
+        let relative = offset - self.start;
+        let chunk = relative / self.chunk_size;
+        let offset_in_chunk = relative % self.chunk_size;
+        let count = self.slots.len() as u64;
+
+        let slot = self.slots[(chunk % count) as usize];
+        let member_offset = self.member_offset + (chunk / count) * self.chunk_size + offset_in_chunk;
+        (slot, member_offset, self.chunk_size - offset_in_chunk)
+    }
+}
+
+fn raid0_zones(sizes: &[u64], chunk_size: u64) -> Vec<Zone> {
+    let mut zones = Vec::new();
+    let mut start = 0_u64;
+    let mut member_offset = 0_u64;
+    loop {
+        let slots: Vec<usize> = (0..sizes.len()).filter(|&i| sizes[i] > member_offset).collect();
+        let zone_end = match slots.iter().map(|&i| sizes[i]).min() {
+            Some(end) => end,
+            None => break,
+        };
+
+        let length = (zone_end - member_offset) * slots.len() as u64;
+        zones.push(Zone {
+            start,
+            length,
+            member_offset,
+            chunk_size,
+            slots,
+        });
+
+        start += length;
+        member_offset = zone_end;
+    }
+
+    zones
+}
+
+fn linear_zones(sizes: &[u64]) -> Vec<Zone> {
+    let mut start = 0_u64;
+    sizes
+        .iter()
+        .enumerate()
+        .filter(|(_, &size)| size != 0)
+        .map(|(slot, &size)| {
+            let zone = Zone {
+                start,
+                length: size,
+                member_offset: 0,
+                chunk_size: size,
+                slots: vec![slot],
+            };
+            start += size;
+            zone
+        })
+        .collect()
+}
+
+/// Assembled md array
+pub struct Array<R: ReadAt> {
+    level: i32,
+    uuid: Uuid,
+    name: String,
+    size: u64,
+    members: Vec<Option<Member<R>>>, // indexed by slot
+    zones: Vec<Zone>,
+}
+
+impl<R: ReadAt> Array<R> {
+    /// Assembles an array from its member devices, `(device, device size)`.
+    ///
+    /// raid1 arrays may be assembled in degraded mode, other levels require all members.
+    pub fn assemble(devices: Vec<(R, u64)>) -> Result<Self> {
+        let mut members: Vec<Option<Member<R>>> = Vec::new();
+        let mut array_uuid = None;
+
+        for (device, size) in devices {
+            let superblock = Superblock::read(&device, size)?.ok_or_else(|| Error::from(MdError::NoSuperblock))?;
+            match array_uuid {
+                None => array_uuid = Some(superblock.array_uuid),
+                Some(uuid) if uuid != superblock.array_uuid => return Err(Error::from(MdError::ArrayMismatch)),
+                _ => (),
+            }
+
+            let slot = match superblock.role {
+                Some(slot) if slot < superblock.raid_disks => slot as usize,
+                _ => continue, // spare or faulty
+            };
+
+            if members.len() < superblock.raid_disks as usize {
+                members.resize_with(superblock.raid_disks as usize, || None);
+            }
+
+            // keep the most recent member if the slot is already taken
+            let newer = match &members[slot] {
+                Some(existing) => superblock.events > existing.superblock.events,
+                None => true,
+            };
+            if newer {
+                members[slot] = Some(Member { device, superblock });
+            }
+        }
+
+        let first = &members
+            .iter()
+            .flatten()
+            .next()
+            .ok_or_else(|| Error::from(MdError::NoSuperblock))?
+            .superblock;
+        let (level, name, chunk_size) = (first.level, first.name.clone(), first.chunk_size);
+        let uuid = array_uuid.unwrap_or_else(Uuid::nil);
+
+        if level == LEVEL_LINEAR || level == LEVEL_RAID0 {
+            if let Some(slot) = members.iter().position(|m| m.is_none()) {
+                return Err(Error::from(MdError::MissingMember(slot as u32)));
+            }
+        }
+
+        let sizes: Vec<u64> = members
+            .iter()
+            .map(|m| m.as_ref().map(|m| m.superblock.data_size).unwrap_or(0))
+            .collect();
+
+        let zones = match level {
+            LEVEL_LINEAR => linear_zones(&sizes),
+            LEVEL_RAID0 if chunk_size != 0 => {
+                let sizes: Vec<u64> = sizes.iter().map(|&s| math::round_down(s, chunk_size)).collect();
+                raid0_zones(&sizes, chunk_size)
+            }
+            LEVEL_RAID1 => Vec::new(),
+            _ => return Err(Error::from(MdError::UnsupportedLevel(level))),
+        };
+
+        let size = match level {
+            LEVEL_RAID1 => sizes.iter().copied().filter(|&s| s != 0).min().unwrap_or(0),
+            _ => zones.iter().map(|z| z.length).sum(),
+        };
+
+        Ok(Self {
+            level,
+            uuid,
+            name,
+            size,
+            members,
+            zones,
+        })
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Number of slots and number of present members
+    pub fn members_count(&self) -> (usize, usize) {
+        (self.members.len(), self.members.iter().flatten().count())
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.members.iter().any(|m| m.is_none())
+    }
+
+    /// Maps the array offset to (member, member device offset, max length)
+    fn map(&self, offset: u64, len: usize) -> Result<(&Member<R>, u64, usize)> {
+        let zone = self
+            .zones
+            .iter()
+            .find(|z| z.start <= offset && offset < z.start + z.length)
+            .ok_or(Error::ReadBeyondEOD)?;
+
+        let (slot, member_offset, available) = zone.map(offset);
+        let member = self.members[slot]
+            .as_ref()
+            .ok_or_else(|| Error::from(MdError::MissingMember(slot as u32)))?;
+        let len = core::cmp::min(len as u64, available) as usize;
+
+        Ok((member, member.superblock.data_offset + member_offset, len))
+    }
+}
+
+impl<R: ReadAt> ReadAt for Array<R> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size, offset, buffer.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::ReadBeyondEOD),
+        };
+
+        if self.level == LEVEL_RAID1 {
+            let member = self.members.iter().flatten().next().ok_or(Error::ReadBeyondEOD)?;
+            return member.device.read_at(member.superblock.data_offset + offset, &mut buffer[..len]);
+        }
+
+        let (member, member_offset, len) = self.map(offset, len)?;
+        member.device.read_at(member_offset, &mut buffer[..len])
+    }
+}
+
+impl<R: ReadAt + WriteAt> WriteAt for Array<R> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let len = match math::bound_to(self.size, offset, data.len()) {
+            Some(0) => return Ok(0),
+            Some(len) => len,
+            None => return Err(Error::WriteBeyondEOD),
+        };
+
+        if self.level == LEVEL_RAID1 {
+            for member in self.members.iter().flatten() {
+                member.device.write_all_at(member.superblock.data_offset + offset, &data[..len])?;
+            }
+            return Ok(len);
+        }
+
+        let (member, member_offset, len) = self.map(offset, len)?;
+        member.device.write_at(member_offset, &data[..len])
+    }
+}
+
+impl<R: ReadAt + Flush> Flush for Array<R> {
+    fn flush(&self) -> Result<()> {
+        for member in self.members.iter().flatten() {
+            member.device.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: ReadAt + WriteAt + Flush> Disk for Array<R> {
+    fn geometry(&self) -> Result<Geometry> {
+        Ok(Geometry::with_vhd_capacity(self.size))
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.size)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        Ok(crate::sizes::SECTOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raid0_zones_test() {
+        // 3 members, the last one is bigger
+        let zones = raid0_zones(&[4 * 64, 4 * 64, 6 * 64], 64);
+        assert_eq!(2, zones.len());
+        assert_eq!(12 * 64, zones[0].length);
+        assert_eq!(2 * 64, zones[1].length);
+
+        assert_eq!((0, 0, 64), zones[0].map(0));
+        assert_eq!((1, 10, 54), zones[0].map(64 + 10));
+        assert_eq!((2, 64, 64), zones[0].map(5 * 64));
+        assert_eq!((2, 4 * 64 + 1, 63), zones[1].map(12 * 64 + 1));
+        assert_eq!((2, 5 * 64, 64), zones[1].map(13 * 64));
+    }
+
+    #[test]
+    fn linear_zones_test() {
+        let zones = linear_zones(&[100, 50]);
+        assert_eq!(2, zones.len());
+        assert_eq!((0, 99, 1), zones[0].map(99));
+        assert_eq!((1, 0, 50), zones[1].map(100));
+        assert_eq!((1, 49, 1), zones[1].map(149));
+    }
+}
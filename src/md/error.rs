@@ -0,0 +1,26 @@
+#[derive(Debug)]
+pub enum MdError {
+    NoSuperblock,
+    InvalidSuperblock,
+    UnsupportedLevel(i32),
+    ArrayMismatch,      // members belong to different arrays
+    MissingMember(u32), // raid slot
+}
+
+impl core::fmt::Display for MdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MdError::NoSuperblock => f.write_str("No md superblock"),
+            MdError::InvalidSuperblock => f.write_str("Invalid md superblock"),
+            MdError::UnsupportedLevel(level) => write!(f, "Unsupported md raid level '{}'", level),
+            MdError::ArrayMismatch => f.write_str("Devices belong to different md arrays"),
+            MdError::MissingMember(slot) => write!(f, "md array member #{} is missing", slot),
+        }
+    }
+}
+
+impl From<MdError> for crate::Error {
+    fn from(e: MdError) -> Self {
+        Self::Md(e)
+    }
+}
@@ -0,0 +1,26 @@
+//! Linux software RAID (md) arrays
+//!
+//! Supports v0.90 and v1.x superblocks and linear, raid0 and raid1 levels.
+//! See https://raid.wiki.kernel.org/index.php/RAID_superblock_formats
+use crate::prelude::*;
+use rdisk_shared::AsByteSliceMut;
+
+const SECTOR: u64 = crate::sizes::SECTOR_U64;
+
+mod error;
+pub use error::MdError;
+
+mod superblock;
+pub use superblock::Superblock;
+
+mod array;
+pub use array::Array;
+
+pub const LEVEL_LINEAR: i32 = -1;
+pub const LEVEL_RAID0: i32 = 0;
+pub const LEVEL_RAID1: i32 = 1;
+
+/// GPT type of Linux RAID partitions
+pub fn gpt_partition_kind() -> Uuid {
+    Uuid::from_u128(0xA19D_880F_05FC_4D3B_A006_743F_0F84_911E)
+}
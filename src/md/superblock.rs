@@ -0,0 +1,221 @@
+use super::*;
+
+const MAGIC: u32 = 0xA92B_4EFC;
+
+/// v0.90 superblock is 4 KiB long and lives in the last 64 KiB aligned block of the device
+const SB_0_90_RESERVED: u64 = 64 * 1024;
+const SB_0_90_WORDS: usize = 1024;
+const SB_0_90_THIS_DISK: usize = 992;
+
+/// v1.x superblock header, followed by `max_dev` u16 device roles
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct SuperblockRecord1 {
+    magic: u32,
+    major_version: u32,
+    feature_map: u32,
+    pad0: u32,
+    set_uuid: [u8; 16],
+    set_name: [u8; 32],
+    ctime: u64,
+    level: i32,
+    layout: u32,
+    size: u64,
+    chunk_size: u32,
+    raid_disks: u32,
+    bitmap_offset: u32,
+    new_level: u32,
+    reshape_position: u64,
+    delta_disks: u32,
+    new_layout: u32,
+    new_chunk: u32,
+    new_offset: u32,
+    data_offset: u64,
+    data_size: u64,
+    super_offset: u64,
+    recovery_offset: u64,
+    dev_number: u32,
+    cnt_corrected_read: u32,
+    device_uuid: [u8; 16],
+    devflags: u8,
+    bblog_shift: u8,
+    bblog_size: u16,
+    bblog_offset: u32,
+    utime: u64,
+    events: u64,
+    resync_offset: u64,
+    sb_csum: u32,
+    max_dev: u32,
+    pad3: [u8; 32],
+}
+
+const SB_1_SIZE: usize = core::mem::size_of::<SuperblockRecord1>();
+const SB_1_MAX_SIZE: usize = 4096;
+const ROLE_SPARE: u16 = 0xFFFF;
+const ROLE_FAULTY: u16 = 0xFFFE;
+
+fn calc_sb_1_checksum(bytes: &[u8]) -> u32 {
+    let mut sum = 0_u64;
+    for (i, word) in bytes.chunks(4).enumerate() {
+        let value = match word.len() {
+            4 => u32::from_le_bytes([word[0], word[1], word[2], word[3]]),
+            2 => u16::from_le_bytes([word[0], word[1]]) as u32,
+            _ => 0,
+        };
+        // the checksum field itself is treated as zero
+        if i != 216 / 4 {
+            sum += value as u64;
+        }
+    }
+
+    ((sum & 0xFFFF_FFFF) + (sum >> 32)) as u32
+}
+
+/// md member device superblock, either v0.90 or v1.x
+pub struct Superblock {
+    pub major_version: u32,
+    /// 90 for v0.90, 0, 1 or 2 for v1.x
+    pub minor_version: u32,
+    pub array_uuid: Uuid,
+    pub name: String,
+    /// -1 for linear, 0, 1, 4, 5, 6, 10 for the corresponding raid levels
+    pub level: i32,
+    pub layout: u32,
+    pub chunk_size: u64,
+    pub raid_disks: u32,
+    /// Slot of the device in the array, `None` for spare and faulty ones
+    pub role: Option<u32>,
+    /// Array data position on the device
+    pub data_offset: u64,
+    /// Array data size on the device
+    pub data_size: u64,
+    pub events: u64,
+}
+
+impl Superblock {
+    /// Looks for all known superblock positions of the `size` bytes long device.
+    pub fn read(device: &impl ReadAt, size: u64) -> Result<Option<Self>> {
+        // v1.1 at the start, v1.2 at 4K and v1.0 near the end
+        let end_position = size.saturating_sub(8 * 1024) & !(4 * 1024 - 1);
+        for &(minor_version, position) in &[(1_u32, 0_u64), (2, 4 * 1024), (0, end_position)] {
+            if position + SB_1_MAX_SIZE as u64 > size {
+                continue;
+            }
+
+            if let Some(sb) = Self::read_1(device, position, minor_version)? {
+                return Ok(Some(sb));
+            }
+        }
+
+        if size >= SB_0_90_RESERVED * 2 {
+            let position = (size & !(SB_0_90_RESERVED - 1)) - SB_0_90_RESERVED;
+            return Self::read_0_90(device, position);
+        }
+
+        Ok(None)
+    }
+
+    fn read_1(device: &impl ReadAt, position: u64, minor_version: u32) -> Result<Option<Self>> {
+        let mut buffer = vec![0_u8; SB_1_MAX_SIZE];
+        device.read_exact_at(position, &mut buffer)?;
+
+        let sb = unsafe { core::ptr::read(buffer.as_ptr() as *const SuperblockRecord1) };
+        if sb.magic != MAGIC || sb.major_version != 1 {
+            return Ok(None);
+        }
+
+        let sb_size = SB_1_SIZE + sb.max_dev as usize * 2;
+        if sb_size > buffer.len() || calc_sb_1_checksum(&buffer[..sb_size]) != sb.sb_csum {
+            return Err(Error::from(MdError::InvalidSuperblock));
+        }
+
+        let role = if sb.dev_number < sb.max_dev {
+            let pos = SB_1_SIZE + sb.dev_number as usize * 2;
+            match u16::from_le_bytes([buffer[pos], buffer[pos + 1]]) {
+                ROLE_SPARE | ROLE_FAULTY => None,
+                role => Some(role as u32),
+            }
+        } else {
+            None
+        };
+
+        let data_size = sb.data_size * SECTOR;
+        Ok(Some(Self {
+            major_version: 1,
+            minor_version,
+            array_uuid: Uuid::from_bytes(sb.set_uuid),
+            name: tools::string_from_ascii_z(&sb.set_name),
+            level: sb.level,
+            layout: sb.layout,
+            chunk_size: sb.chunk_size as u64 * SECTOR,
+            raid_disks: sb.raid_disks,
+            role,
+            data_offset: sb.data_offset * SECTOR,
+            // `size` is the used part of each device for redundant levels
+            data_size: match sb.size * SECTOR {
+                used if used != 0 && used < data_size && sb.level > 0 => used,
+                _ => data_size,
+            },
+            events: sb.events,
+        }))
+    }
+
+    fn read_0_90(device: &impl ReadAt, position: u64) -> Result<Option<Self>> {
+        let mut words = vec![0_u32; SB_0_90_WORDS];
+        device.read_exact_at(position, unsafe { words.as_byte_slice_mut() })?;
+        let word = |i: usize| u32::from_le(words[i]);
+
+        if word(0) != MAGIC || word(1) != 0 || word(2) != 90 {
+            return Ok(None);
+        }
+
+        let mut uuid = [0_u8; 16];
+        for (chunk, &i) in uuid.chunks_exact_mut(4).zip(&[5_usize, 13, 14, 15]) {
+            chunk.copy_from_slice(&word(i).to_le_bytes());
+        }
+
+        let raid_disks = word(10);
+        let role = word(SB_0_90_THIS_DISK + 3);
+        let level = word(7) as i32;
+        let size = word(8) as u64 * 1024;
+
+        Ok(Some(Self {
+            major_version: 0,
+            minor_version: 90,
+            array_uuid: Uuid::from_bytes(uuid),
+            name: String::new(),
+            level,
+            layout: word(64),
+            chunk_size: word(65) as u64,
+            raid_disks,
+            role: if role < raid_disks { Some(role) } else { None },
+            data_offset: 0,
+            // linear and raid0 use everything before the superblock
+            data_size: if level > 0 && size != 0 && size < position {
+                size
+            } else {
+                position
+            },
+            events: (word(40) as u64) << 32 | word(39) as u64,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sb_1_checksum_test() {
+        let mut bytes = vec![0_u8; SB_1_SIZE + 4];
+        bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[216..220].copy_from_slice(&0xDEAD_BEEF_u32.to_le_bytes()); // ignored
+        bytes[SB_1_SIZE..SB_1_SIZE + 2].copy_from_slice(&0xFFFF_u16.to_le_bytes());
+        bytes[SB_1_SIZE + 2..SB_1_SIZE + 4].copy_from_slice(&0x0001_u16.to_le_bytes());
+
+        let expected = MAGIC as u64 + 0x0001_FFFF;
+        let expected = ((expected & 0xFFFF_FFFF) + (expected >> 32)) as u32;
+        assert_eq!(expected, calc_sb_1_checksum(&bytes));
+        assert_eq!(expected + 0x1234, calc_sb_1_checksum(&[&bytes[..], &[0x34, 0x12]].concat()));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::prelude::*;
+use crate::sync::RwLock;
+use crate::StreamDisk;
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+
+/// Growable in-memory stream
+///
+/// Clones share the data, like the platform [`File`](crate::File) handles do,
+/// so an image written to the stream can be reopened from a clone.
+#[derive(Clone, Default)]
+pub struct MemoryStream(Arc<RwLock<Vec<u8>>>);
+
+#[cfg(any(feature = "std", test))]
+impl core::fmt::Debug for MemoryStream {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MemoryStream").field("size", &self.0.read().len()).finish()
+    }
+}
+
+impl ReadAt for MemoryStream {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let data = self.0.read();
+        // reading past the end is EOF, as for files
+        let len = math::rest(data.len() as u64, offset, buffer.len());
+        if len != 0 {
+            let offset = offset as usize;
+            buffer[..len].copy_from_slice(&data[offset..offset + len]);
+        }
+
+        Ok(len)
+    }
+}
+
+impl WriteAt for MemoryStream {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let offset = usize::try_from(offset).map_err(|_| Error::WriteBeyondEOD)?;
+        let end = offset.checked_add(data.len()).ok_or(Error::WriteBeyondEOD)?;
+        let mut buffer = self.0.write();
+        if end > buffer.len() {
+            buffer.resize(end, 0);
+        }
+
+        buffer[offset..end].copy_from_slice(data);
+        Ok(data.len())
+    }
+}
+
+impl Flush for MemoryStream {
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Stream for MemoryStream {
+    fn size(&self) -> Result<u64> {
+        Ok(self.0.read().len() as u64)
+    }
+
+    /// The part past the end is ignored
+    fn zero_range(&self, offset: u64, len: u64) -> Result<()> {
+        let mut data = self.0.write();
+        let size = data.len() as u64;
+        if offset < size {
+            let end = core::cmp::min(offset.saturating_add(len), size);
+            for b in data[offset as usize..end as usize].iter_mut() {
+                *b = 0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl MemoryStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        Self(Arc::new(RwLock::new(data)))
+    }
+
+    /// Copy of the current contents
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.read().clone()
+    }
+}
+
+/// Disk kept in memory, useful for tests and for staging images before writing them out
+///
+/// The memory is allocated up to the last written byte, the rest reads as zeroes.
+pub type MemoryDisk = StreamDisk<MemoryStream>;
+
+impl StreamDisk<MemoryStream> {
+    /// Zero filled disk
+    pub fn with_capacity(capacity: u64) -> Self {
+        Self::new(MemoryStream::new(), capacity)
+    }
+
+    /// Disk with the given contents
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        let capacity = data.len() as u64;
+        Self::new(MemoryStream::from_vec(data), capacity)
+    }
+
+    /// Copy of the current contents, shorter than the capacity if the end was never written
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.stream().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sizes;
+
+    #[test]
+    fn memory_stream_test() {
+        let stream = MemoryStream::new();
+        assert_eq!(0, stream.size().unwrap());
+
+        stream.write_all_at(10, b"data").unwrap();
+        assert_eq!(14, stream.size().unwrap());
+
+        let copy = stream.clone();
+        let mut buffer = vec![0xFF_u8; 8];
+        assert_eq!(8, copy.read_at(0, &mut buffer).unwrap());
+        assert_eq!(&[0, 0, 0, 0, 0, 0, 0, 0], buffer.as_slice());
+        assert_eq!(4, copy.read_at(10, &mut buffer).unwrap());
+        assert_eq!(b"data", &buffer[..4]);
+        assert_eq!(0, copy.read_at(100, &mut buffer).unwrap());
+        assert_eq!(14, copy.to_vec().len());
+
+        stream.zero_range(12, 100).unwrap();
+        assert_eq!(b"da\0\0", &stream.to_vec()[10..]);
+
+        // the end does not fit in memory
+        assert!(matches!(stream.write_at(u64::MAX - 1, b"data"), Err(Error::WriteBeyondEOD)));
+        assert_eq!(14, stream.size().unwrap());
+    }
+
+    #[test]
+    fn vectored_test() {
+        let stream = MemoryStream::new();
+        assert_eq!(6, stream.write_vectored_at(2, &[b"vec", b"", b"tor"]).unwrap());
+        assert_eq!(8, stream.size().unwrap());
+
+        let mut head = [0_u8; 4];
+        let mut tail = [0xFF_u8; 8];
+        // stops at the end of the stream
+        assert_eq!(8, stream.read_vectored_at(0, &mut [&mut head, &mut tail]).unwrap());
+        assert_eq!(b"\0\0ve", &head);
+        assert_eq!(b"ctor", &tail[..4]);
+        assert_eq!(0xFF, tail[4]);
+    }
+
+    #[test]
+    fn memory_disk_test() {
+        let disk = MemoryDisk::with_capacity(16 * sizes::MIB);
+        assert_eq!(16 * sizes::MIB, disk.capacity().unwrap());
+        assert_eq!(sizes::SECTOR, disk.logical_sector_size().unwrap());
+        assert_eq!(
+            Geometry::with_vhd_capacity(16 * sizes::MIB).cylinders,
+            disk.geometry().unwrap().cylinders
+        );
+
+        disk.write_all_at(sizes::MIB, b"sector").unwrap();
+        assert_eq!(sizes::MIB as usize + 6, disk.to_vec().len());
+
+        let mut buffer = vec![0xFF_u8; 512];
+        disk.read_exact_at(15 * sizes::MIB, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|&b| b == 0));
+        disk.read_exact_at(sizes::MIB, &mut buffer).unwrap();
+        assert_eq!(b"sector", &buffer[..6]);
+
+        // the memory is not allocated for the zeroes
+        disk.write_zeroes(sizes::MIB + 2, 8 * sizes::MIB).unwrap();
+        assert_eq!(sizes::MIB as usize + 6, disk.to_vec().len());
+        let start = sizes::MIB as usize;
+        assert_eq!(b"se\0\0", &disk.to_vec()[start..start + 4]);
+        match disk.write_zeroes(15 * sizes::MIB, 2 * sizes::MIB) {
+            Err(Error::WriteBeyondEOD) => (),
+            _ => panic!("write beyond the end expected"),
+        }
+
+        let disk = MemoryDisk::from_vec(vec![1_u8; 8192]).with_sector_size(4096, 4096);
+        assert_eq!(8192, disk.capacity().unwrap());
+        assert_eq!(4096, disk.logical_sector_size().unwrap());
+        assert_eq!(4096, disk.physical_sector_size().unwrap());
+    }
+}
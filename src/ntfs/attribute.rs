@@ -0,0 +1,147 @@
+use super::*;
+
+/// Windows FILETIME: 100 ns intervals since 1601-01-01 UTC
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct FileTime(pub u64);
+
+impl FileTime {
+    /// Seconds between 1601-01-01 and 1970-01-01
+    const UNIX_EPOCH: i64 = 11_644_473_600;
+
+    pub fn to_unix_time(self) -> i64 {
+        (self.0 / 10_000_000) as i64 - Self::UNIX_EPOCH
+    }
+
+    pub fn from_unix_time(time: i64) -> Self {
+        Self(((time + Self::UNIX_EPOCH) as u64) * 10_000_000)
+    }
+}
+
+/// `$STANDARD_INFORMATION`
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct StandardInformation {
+    pub created: FileTime,
+    pub modified: FileTime,
+    pub mft_modified: FileTime,
+    pub accessed: FileTime,
+    /// `FILE_ATTRIBUTE_*` flags
+    pub file_attributes: u32,
+    /// NTFS 3.0+ only
+    pub owner_id: Option<u32>,
+    pub security_id: Option<u32>,
+    pub quota_charged: Option<u64>,
+    pub usn: Option<u64>,
+}
+
+impl StandardInformation {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 48 {
+            return None;
+        }
+
+        let extended = bytes.len() >= 72;
+        Some(Self {
+            created: FileTime(le_u64(bytes, 0)),
+            modified: FileTime(le_u64(bytes, 8)),
+            mft_modified: FileTime(le_u64(bytes, 16)),
+            accessed: FileTime(le_u64(bytes, 24)),
+            file_attributes: le_u32(bytes, 32),
+            owner_id: if extended { Some(le_u32(bytes, 48)) } else { None },
+            security_id: if extended { Some(le_u32(bytes, 52)) } else { None },
+            quota_charged: if extended { Some(le_u64(bytes, 56)) } else { None },
+            usn: if extended { Some(le_u64(bytes, 64)) } else { None },
+        })
+    }
+}
+
+/// `$FILE_NAME`, also the key of directory index entries
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct FileName {
+    pub parent: FileReference,
+    pub created: FileTime,
+    pub modified: FileTime,
+    pub mft_modified: FileTime,
+    pub accessed: FileTime,
+    /// Sizes are updated only on some operations, the data attribute is authoritative
+    pub allocated_size: u64,
+    pub data_size: u64,
+    pub flags: u32,
+    pub namespace: u8,
+    pub name: String,
+}
+
+impl FileName {
+    pub const NAMESPACE_POSIX: u8 = 0;
+    pub const NAMESPACE_WIN32: u8 = 1;
+    pub const NAMESPACE_DOS: u8 = 2;
+    pub const NAMESPACE_WIN32_AND_DOS: u8 = 3;
+
+    /// `FILE_ATTRIBUTE_DIRECTORY` as stored in file names and indexes
+    const DIRECTORY: u32 = 0x1000_0000;
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let name_length = *bytes.get(64)? as usize;
+        let name = bytes.get(66..66 + name_length * 2)?;
+
+        Some(Self {
+            parent: FileReference(le_u64(bytes, 0)),
+            created: FileTime(le_u64(bytes, 8)),
+            modified: FileTime(le_u64(bytes, 16)),
+            mft_modified: FileTime(le_u64(bytes, 24)),
+            accessed: FileTime(le_u64(bytes, 32)),
+            allocated_size: le_u64(bytes, 40),
+            data_size: le_u64(bytes, 48),
+            flags: le_u32(bytes, 56),
+            namespace: bytes[65],
+            name: utf16_string(name),
+        })
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.flags & Self::DIRECTORY != 0
+    }
+
+    /// DOS names duplicate the long ones
+    pub fn is_dos_only(&self) -> bool {
+        self.namespace == Self::NAMESPACE_DOS
+    }
+}
+
+/// `$ATTRIBUTE_LIST` entry
+pub(crate) struct AttributeListEntry {
+    pub kind: u32,
+    pub name: String,
+    pub lowest_vcn: u64,
+    pub reference: FileReference,
+    pub id: u16,
+}
+
+impl AttributeListEntry {
+    pub fn parse_all(bytes: &[u8], record: u64) -> Result<Vec<Self>> {
+        let invalid = || Error::from(NtfsError::InvalidAttributeList(record));
+
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos + 26 <= bytes.len() {
+            let length = le_u16(bytes, pos + 4) as usize;
+            let entry = bytes.get(pos..pos + length).filter(|_| length >= 26).ok_or_else(invalid)?;
+
+            let name_length = entry[6] as usize;
+            let name_offset = entry[7] as usize;
+            let name = entry.get(name_offset..name_offset + name_length * 2).ok_or_else(invalid)?;
+            entries.push(Self {
+                kind: le_u32(entry, 0),
+                name: utf16_string(name),
+                lowest_vcn: le_u64(entry, 8),
+                reference: FileReference(le_u64(entry, 16)),
+                id: le_u16(entry, 24),
+            });
+            pos += length;
+        }
+
+        Ok(entries)
+    }
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+const OEM_ID: &[u8; 8] = b"NTFS    ";
+
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct BootSector {
+    pub bytes_per_sector: u32,
+    pub sectors_per_cluster: u32,
+    pub total_sectors: u64,
+    pub mft_cluster: u64,
+    pub mft_mirror_cluster: u64,
+    pub file_record_size: u32,
+    pub index_record_size: u32,
+    pub serial_number: u64,
+}
+
+/// Positive values are clusters, negative ones are log2 of bytes
+fn record_size(raw: u8, cluster_size: u32) -> u32 {
+    match raw as i8 {
+        size if size > 0 => size as u32 * cluster_size,
+        size => 1_u32.checked_shl((-(size as i32)) as u32).unwrap_or(0),
+    }
+}
+
+impl BootSector {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 512 || &bytes[3..11] != OEM_ID || bytes[510..512] != [0x55, 0xAA] {
+            return Err(Error::from(NtfsError::InvalidBootSector));
+        }
+
+        let bytes_per_sector = le_u16(bytes, 0x0B) as u32;
+        let sectors_per_cluster = match bytes[0x0D] {
+            // 4.0+ shift for clusters above 64K
+            spc if spc > 0x80 => 1_u32.checked_shl(256 - spc as u32).unwrap_or(0),
+            spc => spc as u32,
+        };
+        let cluster_size = bytes_per_sector * sectors_per_cluster;
+
+        let boot = Self {
+            bytes_per_sector,
+            sectors_per_cluster,
+            total_sectors: le_u64(bytes, 0x28),
+            mft_cluster: le_u64(bytes, 0x30),
+            mft_mirror_cluster: le_u64(bytes, 0x38),
+            file_record_size: record_size(bytes[0x40], cluster_size),
+            index_record_size: record_size(bytes[0x44], cluster_size),
+            serial_number: le_u64(bytes, 0x48),
+        };
+
+        let valid = bytes_per_sector.is_power_of_two()
+            && (256..=4096).contains(&bytes_per_sector)
+            && sectors_per_cluster.is_power_of_two()
+            && boot.file_record_size.is_power_of_two()
+            && (256..=65536).contains(&boot.file_record_size)
+            && boot.index_record_size.is_power_of_two()
+            && (256..=65536).contains(&boot.index_record_size);
+        if !valid {
+            return Err(Error::from(NtfsError::InvalidBootSector));
+        }
+
+        Ok(boot)
+    }
+
+    pub fn cluster_size(&self) -> u32 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+}
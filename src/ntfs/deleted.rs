@@ -0,0 +1,104 @@
+use super::*;
+
+/// Path prefix for files whose parent directory record was reused
+pub const ORPHAN_FILES: &str = "$OrphanFiles";
+
+/// Protects against parent reference loops
+const MAX_PATH_DEPTH: usize = 1024;
+
+/// Iterator over unused base MFT records that still have a `$FILE_NAME`
+///
+/// Records that were never used or are damaged are skipped, only read errors are reported.
+/// Clusters of a deleted file may be reallocated already, so its data is not guaranteed to be intact.
+pub struct DeletedFiles<'f, R: ReadAt> {
+    fs: &'f NtfsFileSystem<R>,
+    next: u64,
+    count: u64,
+}
+
+impl<'f, R: ReadAt> Iterator for DeletedFiles<'f, R> {
+    type Item = Result<File<'f, R>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.count {
+            let number = self.next;
+            self.next += 1;
+
+            let mut data = match self.fs.read_record_data(number) {
+                Ok(data) => data,
+                Err(e) => return Some(Err(e)),
+            };
+            let record = match FileRecord::parse(&mut data, number) {
+                Ok(record) => record,
+                Err(_) => continue,
+            };
+
+            let deleted = !record.is_in_use() && record.base_record.is_none();
+            if !deleted || !record.attributes.iter().any(|a| a.kind == ATTR_FILE_NAME) {
+                continue;
+            }
+
+            // extension records may be reused already, the base record is all we have then
+            let attributes = self.fs.load_attributes(&record).unwrap_or_else(|_| record.attributes.clone());
+            return Some(Ok(File::new(self.fs, record, attributes)));
+        }
+
+        None
+    }
+}
+
+impl<R: ReadAt> NtfsFileSystem<R> {
+    /// Enumerates deleted files and directories, see [`DeletedFiles`]
+    pub fn deleted_files(&self) -> DeletedFiles<'_, R> {
+        DeletedFiles {
+            fs: self,
+            next: 0,
+            count: self.record_count(),
+        }
+    }
+
+    /// Rebuilds the full path of the name following the parent references.
+    ///
+    /// Deleted parents are accepted while their record is not reused,
+    /// otherwise the path starts with [`ORPHAN_FILES`].
+    pub fn path_of(&self, name: &FileName) -> Result<String> {
+        let mut names = vec![name.name.clone()];
+        let mut parent = name.parent;
+        while parent.record() != RECORD_ROOT {
+            if names.len() > MAX_PATH_DEPTH {
+                names.push(ORPHAN_FILES.to_string());
+                break;
+            }
+
+            let dir = match self.file(parent.record()) {
+                Ok(dir) => dir,
+                Err(Error::Ntfs(_)) => {
+                    names.push(ORPHAN_FILES.to_string());
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+
+            // deletion increments the sequence number
+            let sequence = dir.record().sequence;
+            let same = sequence == parent.sequence() || (!dir.is_in_use() && sequence == parent.sequence().wrapping_add(1));
+            match dir.name() {
+                Some(dir_name) if same && dir.is_dir() => {
+                    names.push(dir_name.name);
+                    parent = dir_name.parent;
+                }
+                _ => {
+                    names.push(ORPHAN_FILES.to_string());
+                    break;
+                }
+            }
+        }
+
+        let mut path = String::new();
+        for name in names.iter().rev() {
+            path.push('/');
+            path.push_str(name);
+        }
+        Ok(path)
+    }
+}
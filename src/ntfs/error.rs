@@ -0,0 +1,52 @@
+use crate::xstd::String;
+
+#[derive(Debug)]
+pub enum NtfsError {
+    InvalidBootSector,
+    InvalidRecord(u64),
+    InvalidFixup(u64),     // record
+    InvalidAttribute(u64), // record
+    InvalidRunList(u64),   // record
+    InvalidAttributeList(u64),
+    InvalidIndex(u64), // directory record
+    InvalidCompressedData,
+    MissingAttribute(u64, u32), // record, attribute type
+    StreamNotFound(String),
+    EncryptedStream(String),
+    NotADirectory(String),
+    NotAFile(String),
+    InvalidUsnRecord(u64), // journal offset
+    InvalidLogFile,
+    InvalidSecurityDescriptor(u32), // security id
+}
+
+impl core::fmt::Display for NtfsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            NtfsError::InvalidBootSector => f.write_str("Invalid NTFS boot sector"),
+            NtfsError::InvalidRecord(record) => write!(f, "Invalid MFT record {}", record),
+            NtfsError::InvalidFixup(record) => write!(f, "Invalid update sequence of MFT record {}", record),
+            NtfsError::InvalidAttribute(record) => write!(f, "Invalid attribute in MFT record {}", record),
+            NtfsError::InvalidRunList(record) => write!(f, "Invalid run list in MFT record {}", record),
+            NtfsError::InvalidAttributeList(record) => write!(f, "Invalid attribute list of MFT record {}", record),
+            NtfsError::InvalidIndex(record) => write!(f, "Invalid index of MFT record {}", record),
+            NtfsError::InvalidCompressedData => f.write_str("Invalid LZNT1 compressed data"),
+            NtfsError::MissingAttribute(record, kind) => {
+                write!(f, "MFT record {} has no attribute 0x{:X}", record, kind)
+            }
+            NtfsError::StreamNotFound(name) => write!(f, "Data stream '{}' not found", name),
+            NtfsError::EncryptedStream(name) => write!(f, "Data stream '{}' is encrypted", name),
+            NtfsError::NotADirectory(path) => write!(f, "'{}' is not a directory", path),
+            NtfsError::NotAFile(path) => write!(f, "'{}' is not a file", path),
+            NtfsError::InvalidUsnRecord(offset) => write!(f, "Invalid USN record at offset {}", offset),
+            NtfsError::InvalidLogFile => f.write_str("Invalid $LogFile restart area"),
+            NtfsError::InvalidSecurityDescriptor(id) => write!(f, "Invalid security descriptor {}", id),
+        }
+    }
+}
+
+impl From<NtfsError> for crate::Error {
+    fn from(e: NtfsError) -> Self {
+        Self::Ntfs(e)
+    }
+}
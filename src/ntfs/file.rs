@@ -0,0 +1,109 @@
+use super::*;
+
+/// File or directory with the attributes of all its MFT records
+pub struct File<'f, R: ReadAt> {
+    fs: &'f NtfsFileSystem<R>,
+    record: FileRecord,
+    attributes: Vec<Attribute>,
+}
+
+impl<'f, R: ReadAt> File<'f, R> {
+    pub(crate) fn new(fs: &'f NtfsFileSystem<R>, record: FileRecord, attributes: Vec<Attribute>) -> Self {
+        Self { fs, record, attributes }
+    }
+
+    /// The base MFT record
+    pub fn record(&self) -> &FileRecord {
+        &self.record
+    }
+
+    pub fn number(&self) -> u64 {
+        self.record.number
+    }
+
+    pub fn reference(&self) -> FileReference {
+        self.record.reference()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.record.is_dir()
+    }
+
+    pub fn is_in_use(&self) -> bool {
+        self.record.is_in_use()
+    }
+
+    /// All attributes, non-resident ones split across records are merged
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    pub fn attribute(&self, kind: u32, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|a| a.kind == kind && a.name == name)
+    }
+
+    fn resident_values(&self, kind: u32) -> impl Iterator<Item = &[u8]> {
+        self.attributes
+            .iter()
+            .filter(move |a| a.kind == kind)
+            .filter_map(|a| match &a.value {
+                AttributeValue::Resident(data) => Some(data.as_slice()),
+                _ => None,
+            })
+    }
+
+    pub fn standard_information(&self) -> Option<StandardInformation> {
+        self.resident_values(ATTR_STANDARD_INFORMATION).find_map(StandardInformation::parse)
+    }
+
+    /// All names including hard links and DOS names
+    pub fn file_names(&self) -> Vec<FileName> {
+        self.resident_values(ATTR_FILE_NAME).filter_map(FileName::parse).collect()
+    }
+
+    /// The long name, falls back to the DOS one
+    pub fn name(&self) -> Option<FileName> {
+        let mut names = self.file_names();
+        let index = names.iter().position(|name| !name.is_dos_only()).unwrap_or(0);
+        if names.is_empty() {
+            None
+        } else {
+            Some(names.swap_remove(index))
+        }
+    }
+
+    /// Names of the data streams, the unnamed one is an empty string
+    pub fn stream_names(&self) -> Vec<&str> {
+        self.attributes
+            .iter()
+            .filter(|a| a.kind == ATTR_DATA)
+            .map(|a| a.name.as_str())
+            .collect()
+    }
+
+    /// Size of the unnamed data stream
+    pub fn size(&self) -> u64 {
+        self.attribute(ATTR_DATA, "").map(Attribute::size).unwrap_or(0)
+    }
+
+    /// Opens any attribute value as a stream
+    pub fn open_attribute(&self, kind: u32, name: &str) -> Result<DataStream<'f, R>> {
+        let attribute = self
+            .attribute(kind, name)
+            .ok_or_else(|| Error::from(NtfsError::MissingAttribute(self.number(), kind)))?;
+
+        Ok(DataStream::new(self.fs, attribute.clone()))
+    }
+
+    /// Opens the unnamed data stream if `name` is empty or an alternate data stream otherwise
+    pub fn data(&self, name: &str) -> Result<DataStream<'f, R>> {
+        let attribute = self
+            .attribute(ATTR_DATA, name)
+            .ok_or_else(|| Error::from(NtfsError::StreamNotFound(name.to_string())))?;
+        if attribute.is_encrypted() {
+            return Err(Error::from(NtfsError::EncryptedStream(name.to_string())));
+        }
+
+        Ok(DataStream::new(self.fs, attribute.clone()))
+    }
+}
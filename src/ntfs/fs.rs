@@ -0,0 +1,883 @@
+use super::attribute::AttributeListEntry;
+use super::stream::read_value;
+use super::*;
+use core::cmp::Ordering;
+
+const UPCASE_SIZE: usize = 0x1_0000;
+
+/// Directory index entry
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct DirEntry {
+    pub reference: FileReference,
+    pub file_name: FileName,
+}
+
+impl DirEntry {
+    pub fn name(&self) -> &str {
+        &self.file_name.name
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_name.is_dir()
+    }
+}
+
+/// NTFS volume
+pub struct NtfsFileSystem<R: ReadAt> {
+    device: R,
+    boot: BootSector,
+    /// `$MFT` unnamed data attribute
+    mft: Attribute,
+    upcase: Vec<u16>,
+}
+
+fn unnamed_data(attributes: &[Attribute], record: u64) -> Result<Attribute> {
+    attributes
+        .iter()
+        .find(|a| a.kind == ATTR_DATA && a.name.is_empty())
+        .cloned()
+        .ok_or_else(|| Error::from(NtfsError::MissingAttribute(record, ATTR_DATA)))
+}
+
+fn volume_name(attributes: &[Attribute]) -> String {
+    match attributes.iter().find(|a| a.kind == ATTR_VOLUME_NAME && a.name.is_empty()).map(|a| &a.value) {
+        Some(AttributeValue::Resident(name)) => utf16_string(name),
+        _ => String::new(),
+    }
+}
+
+/// Reads the label without opening the volume, the first 16 MFT records are always in the first `$MFT` extent
+pub fn read_volume_label(device: &impl ReadAt, boot: &BootSector) -> Result<String> {
+    let size = boot.file_record_size as u64;
+    let offset = boot
+        .mft_cluster
+        .checked_mul(boot.cluster_size() as u64)
+        .and_then(|mft| mft.checked_add(RECORD_VOLUME * size))
+        .ok_or_else(|| Error::from(NtfsError::InvalidBootSector))?;
+    let mut data = vec![0_u8; size as usize];
+    device.read_exact_at(offset, &mut data)?;
+    let record = FileRecord::parse(&mut data, RECORD_VOLUME)?;
+    Ok(volume_name(&record.attributes))
+}
+
+fn lowest_vcn(attribute: &Attribute) -> u64 {
+    match attribute.value {
+        AttributeValue::NonResident { lowest_vcn, .. } => lowest_vcn,
+        AttributeValue::Resident(_) => 0,
+    }
+}
+
+/// Appends the runs of an extension to the attribute it continues
+fn merge_attribute(attributes: &mut Vec<Attribute>, attribute: Attribute) {
+    let extension = match attribute.value {
+        AttributeValue::NonResident {
+            lowest_vcn,
+            highest_vcn,
+            ref runs,
+            ..
+        } if lowest_vcn > 0 => Some((highest_vcn, runs)),
+        _ => None,
+    };
+
+    if let Some((extension_highest_vcn, extension_runs)) = extension {
+        let base = attributes
+            .iter_mut()
+            .find(|a| a.kind == attribute.kind && a.name == attribute.name && !a.is_resident());
+        if let Some(Attribute {
+            value: AttributeValue::NonResident { highest_vcn, runs, .. },
+            ..
+        }) = base
+        {
+            runs.extend_from_slice(extension_runs);
+            *highest_vcn = core::cmp::max(*highest_vcn, extension_highest_vcn);
+            return;
+        }
+    }
+
+    attributes.push(attribute);
+}
+
+impl<R: ReadAt> NtfsFileSystem<R> {
+    pub fn open(device: R) -> Result<Self> {
+        let mut sector = [0_u8; 512];
+        device.read_exact_at(0, &mut sector)?;
+        let boot = BootSector::parse(&sector)?;
+
+        // $MFT describes itself, its first extent is always in the first record
+        let mut data = vec![0_u8; boot.file_record_size as usize];
+        device.read_exact_at(boot.mft_cluster * boot.cluster_size() as u64, &mut data)?;
+        let record = FileRecord::parse(&mut data, RECORD_MFT)?;
+        let mft = unnamed_data(&record.attributes, RECORD_MFT)?;
+
+        let mut fs = Self {
+            device,
+            boot,
+            mft,
+            upcase: Vec::new(),
+        };
+
+        if record.attributes.iter().any(|a| a.kind == ATTR_ATTRIBUTE_LIST) {
+            let attributes = fs.load_attributes(&record)?;
+            fs.mft = unnamed_data(&attributes, RECORD_MFT)?;
+        }
+
+        let upcase = fs.file(RECORD_UPCASE)?.data("")?.read_all()?;
+        fs.upcase = upcase.chunks_exact(2).take(UPCASE_SIZE).map(|c| le_u16(c, 0)).collect();
+
+        Ok(fs)
+    }
+
+    pub fn device(&self) -> &R {
+        &self.device
+    }
+
+    pub fn into_inner(self) -> R {
+        self.device
+    }
+
+    pub fn boot_sector(&self) -> &BootSector {
+        &self.boot
+    }
+
+    pub fn serial_number(&self) -> u64 {
+        self.boot.serial_number
+    }
+
+    /// Number of records the MFT has room for, including unused ones
+    pub fn record_count(&self) -> u64 {
+        self.mft.size() / self.boot.file_record_size as u64
+    }
+
+    pub(crate) fn read_attribute_exact(&self, attribute: &Attribute, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        let cluster_size = self.boot.cluster_size() as u64;
+        let mut done = 0;
+        while done < buffer.len() {
+            match read_value(&self.device, cluster_size, attribute, offset + done as u64, &mut buffer[done..])? {
+                0 => return Err(Error::UnexpectedEOD),
+                read => done += read,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the MFT record as is, including unused and extension records
+    pub fn read_record(&self, number: u64) -> Result<FileRecord> {
+        let mut data = self.read_record_data(number)?;
+        FileRecord::parse(&mut data, number)
+    }
+
+    /// Raw record bytes, fixups are not applied
+    pub(crate) fn read_record_data(&self, number: u64) -> Result<Vec<u8>> {
+        let size = self.boot.file_record_size as u64;
+        let mut data = vec![0_u8; size as usize];
+        self.read_attribute_exact(&self.mft, number * size, &mut data)?;
+        Ok(data)
+    }
+
+    /// Collects the attributes of the base record and of all its extension records
+    pub(crate) fn load_attributes(&self, record: &FileRecord) -> Result<Vec<Attribute>> {
+        let list = match record.attributes.iter().find(|a| a.kind == ATTR_ATTRIBUTE_LIST) {
+            Some(list) => list,
+            None => return Ok(record.attributes.clone()),
+        };
+
+        let mut data = vec![0_u8; list.size() as usize];
+        self.read_attribute_exact(list, 0, &mut data)?;
+
+        let entries = AttributeListEntry::parse_all(&data, record.number)?;
+        let mut extensions = BTreeMap::<u64, FileRecord>::new();
+        for entry in &entries {
+            let number = entry.reference.record();
+            if number != record.number && !extensions.contains_key(&number) {
+                extensions.insert(number, self.read_record(number)?);
+            }
+        }
+
+        let mut attributes = vec![list.clone()];
+        for entry in entries {
+            let number = entry.reference.record();
+            let source = extensions.get(&number).unwrap_or(record);
+
+            let attribute = source
+                .attributes
+                .iter()
+                .find(|a| a.kind == entry.kind && a.id == entry.id && a.name == entry.name && lowest_vcn(a) == entry.lowest_vcn)
+                .ok_or_else(|| Error::from(NtfsError::InvalidAttributeList(record.number)))?;
+            merge_attribute(&mut attributes, attribute.clone());
+        }
+
+        Ok(attributes)
+    }
+
+    /// Opens the file by its MFT record number, the record may be unused
+    pub fn file(&self, number: u64) -> Result<File<'_, R>> {
+        let record = self.read_record(number)?;
+        let attributes = self.load_attributes(&record)?;
+        Ok(File::new(self, record, attributes))
+    }
+
+    pub fn root(&self) -> Result<File<'_, R>> {
+        self.file(RECORD_ROOT)
+    }
+
+    pub fn volume_label(&self) -> Result<String> {
+        Ok(volume_name(self.file(RECORD_VOLUME)?.attributes()))
+    }
+
+    /// (major, minor) NTFS version, like (3, 1)
+    pub fn version(&self) -> Result<(u8, u8)> {
+        let volume = self.file(RECORD_VOLUME)?;
+        match volume.attribute(ATTR_VOLUME_INFORMATION, "").map(|a| &a.value) {
+            Some(AttributeValue::Resident(info)) if info.len() >= 10 => Ok((info[8], info[9])),
+            _ => Err(Error::from(NtfsError::MissingAttribute(RECORD_VOLUME, ATTR_VOLUME_INFORMATION))),
+        }
+    }
+
+    fn upcase(&self, c: u16) -> u16 {
+        self.upcase.get(c as usize).copied().unwrap_or(c)
+    }
+
+    /// Lists the directory, DOS names are skipped
+    pub fn read_dir_file(&self, dir: &File<'_, R>) -> Result<Vec<DirEntry>> {
+        if !dir.is_dir() {
+            return Err(Error::from(NtfsError::NotADirectory(dir.number().to_string())));
+        }
+
+        let entries = self
+            .index_entries(dir, I30)?
+            .into_iter()
+            .filter_map(|entry| {
+                let file_name = entry.file_name()?;
+                let skip = file_name.is_dos_only() || entry.reference.record() == dir.number();
+                if skip {
+                    None // DOS duplicates and the root `.` entry
+                } else {
+                    Some(DirEntry {
+                        reference: entry.reference,
+                        file_name,
+                    })
+                }
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let dir = self.open_path(path)?;
+        self.read_dir_file(&dir)
+    }
+
+    /// Looks the name up in the directory index using the volume up-case table
+    pub fn find(&self, dir: &File<'_, R>, name: &str) -> Result<Option<DirEntry>> {
+        if !dir.is_dir() {
+            return Err(Error::from(NtfsError::NotADirectory(dir.number().to_string())));
+        }
+
+        let name: Vec<u16> = name.encode_utf16().map(|c| self.upcase(c)).collect();
+        let entry = self.find_index_entry(dir, I30, |key| match FileName::parse(key) {
+            Some(key) => name.iter().copied().cmp(key.name.encode_utf16().map(|c| self.upcase(c))),
+            None => Ordering::Greater,
+        })?;
+
+        Ok(entry.and_then(|entry| {
+            Some(DirEntry {
+                reference: entry.reference,
+                file_name: entry.file_name()?,
+            })
+        }))
+    }
+
+    /// Opens the file or directory, `/` and `\` separators are accepted, names are case insensitive
+    pub fn open_path(&self, path: &str) -> Result<File<'_, R>> {
+        let mut stack = vec![self.root()?];
+        for name in path.split(&['/', '\\'][..]) {
+            match name {
+                "" | "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+
+            let dir = stack.last().expect("root is always present");
+            if !dir.is_dir() {
+                return Err(Error::from(NtfsError::NotADirectory(path.to_string())));
+            }
+
+            let entry = self.find(dir, name)?.ok_or_else(|| Error::NotFound(path.to_string()))?;
+            let file = self.file(entry.reference.record())?;
+            if !file.is_in_use() || file.record().sequence != entry.reference.sequence() {
+                return Err(Error::NotFound(path.to_string())); // stale index entry
+            }
+            stack.push(file);
+        }
+
+        Ok(stack.pop().expect("root is always present"))
+    }
+
+    /// Opens the unnamed data stream, `path:name` opens an alternate data stream
+    pub fn open_file(&self, path: &str) -> Result<DataStream<'_, R>> {
+        let name_start = path.rfind(&['/', '\\'][..]).map(|pos| pos + 1).unwrap_or(0);
+        let (path, stream) = match path[name_start..].find(':') {
+            Some(pos) => (&path[..name_start + pos], &path[name_start + pos + 1..]),
+            None => (path, ""),
+        };
+
+        self.open_stream(path, stream)
+    }
+
+    pub fn open_stream(&self, path: &str, stream: &str) -> Result<DataStream<'_, R>> {
+        let file = self.open_path(path)?;
+        if file.is_dir() && stream.is_empty() {
+            return Err(Error::from(NtfsError::NotAFile(path.to_string())));
+        }
+
+        file.data(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+
+    const CLUSTER: usize = 512;
+    const RECORD: usize = 1024;
+    const MFT_CLUSTER: usize = 16;
+    const MFT_RECORDS: usize = 24;
+    const UPCASE_CLUSTER: usize = 64;
+    const INDX_CLUSTER: usize = 320;
+    const BIG_CLUSTER: usize = 322;
+    const COMPRESSED_CLUSTER: usize = 330;
+
+    fn align8(value: usize) -> usize {
+        (value + 7) & !7
+    }
+
+    fn utf16(name: &str) -> Vec<u8> {
+        name.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    fn resident(kind: u32, name: &str, id: u16, value: &[u8]) -> Vec<u8> {
+        let name = utf16(name);
+        let value_offset = align8(24 + name.len());
+        let length = align8(value_offset + value.len());
+        let mut attr = vec![0_u8; length];
+        attr[0..4].copy_from_slice(&kind.to_le_bytes());
+        attr[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        attr[9] = (name.len() / 2) as u8;
+        attr[10..12].copy_from_slice(&24_u16.to_le_bytes());
+        attr[14..16].copy_from_slice(&id.to_le_bytes());
+        attr[16..20].copy_from_slice(&(value.len() as u32).to_le_bytes());
+        attr[20..22].copy_from_slice(&(value_offset as u16).to_le_bytes());
+        attr[24..24 + name.len()].copy_from_slice(&name);
+        attr[value_offset..value_offset + value.len()].copy_from_slice(value);
+        attr
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn non_resident(kind: u32, name: &str, id: u16, flags: u16, vcns: (u64, u64), runs: &[u8], unit: u8, sizes: (u64, u64)) -> Vec<u8> {
+        let name = utf16(name);
+        let header = if flags & Attribute::COMPRESSED != 0 { 72 } else { 64 };
+        let runs_offset = align8(header + name.len());
+        let length = align8(runs_offset + runs.len() + 1);
+        let mut attr = vec![0_u8; length];
+        attr[0..4].copy_from_slice(&kind.to_le_bytes());
+        attr[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        attr[8] = 1;
+        attr[9] = (name.len() / 2) as u8;
+        attr[10..12].copy_from_slice(&(header as u16).to_le_bytes());
+        attr[12..14].copy_from_slice(&flags.to_le_bytes());
+        attr[14..16].copy_from_slice(&id.to_le_bytes());
+        attr[16..24].copy_from_slice(&vcns.0.to_le_bytes());
+        attr[24..32].copy_from_slice(&vcns.1.to_le_bytes());
+        attr[32..34].copy_from_slice(&(runs_offset as u16).to_le_bytes());
+        attr[34] = unit;
+        let allocated = (vcns.1 + 1) * CLUSTER as u64;
+        attr[40..48].copy_from_slice(&allocated.to_le_bytes());
+        attr[48..56].copy_from_slice(&sizes.0.to_le_bytes());
+        attr[56..64].copy_from_slice(&sizes.1.to_le_bytes());
+        attr[header..header + name.len()].copy_from_slice(&name);
+        attr[runs_offset..runs_offset + runs.len()].copy_from_slice(runs);
+        attr
+    }
+
+    fn file_name(parent: u64, name: &str, directory: bool, namespace: u8) -> Vec<u8> {
+        let chars = utf16(name);
+        let mut value = vec![0_u8; 66 + chars.len()];
+        value[0..8].copy_from_slice(&FileReference::new(parent, parent as u16).0.to_le_bytes());
+        value[16..24].copy_from_slice(&FileTime::from_unix_time(1_600_000_000).0.to_le_bytes());
+        value[56..60].copy_from_slice(&(if directory { 0x1000_0000_u32 } else { 0x20 }).to_le_bytes());
+        value[64] = (chars.len() / 2) as u8;
+        value[65] = namespace;
+        value[66..].copy_from_slice(&chars);
+        value
+    }
+
+    fn standard_information() -> Vec<u8> {
+        let mut value = vec![0_u8; 72];
+        value[8..16].copy_from_slice(&FileTime::from_unix_time(1_600_000_000).0.to_le_bytes());
+        value[52..56].copy_from_slice(&0x100_u32.to_le_bytes()); // security id
+        value
+    }
+
+    /// Sequence number equals the record number
+    fn record(number: usize, flags: u16, attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0_u8; RECORD];
+        data[0..4].copy_from_slice(b"FILE");
+        data[4..6].copy_from_slice(&48_u16.to_le_bytes());
+        data[6..8].copy_from_slice(&3_u16.to_le_bytes());
+        data[16..18].copy_from_slice(&(number as u16).to_le_bytes());
+        data[18..20].copy_from_slice(&1_u16.to_le_bytes());
+        data[20..22].copy_from_slice(&56_u16.to_le_bytes());
+        data[22..24].copy_from_slice(&flags.to_le_bytes());
+        data[28..32].copy_from_slice(&(RECORD as u32).to_le_bytes());
+        data[44..48].copy_from_slice(&(number as u32).to_le_bytes());
+
+        let mut pos = 56;
+        for attribute in attributes {
+            data[pos..pos + attribute.len()].copy_from_slice(attribute);
+            pos += attribute.len();
+        }
+        data[pos..pos + 4].copy_from_slice(&0xFFFF_FFFF_u32.to_le_bytes());
+        data[24..28].copy_from_slice(&(pos as u32 + 8).to_le_bytes());
+
+        protect(&mut data, 48);
+        data
+    }
+
+    /// Moves the last bytes of every 512 byte block to the update sequence array
+    fn protect(data: &mut [u8], usa_offset: usize) {
+        data[usa_offset..usa_offset + 2].copy_from_slice(&[0x01, 0x00]);
+        for i in 1..=data.len() / 512 {
+            let pos = i * 512 - 2;
+            let (saved_0, saved_1) = (data[pos], data[pos + 1]);
+            data[usa_offset + i * 2] = saved_0;
+            data[usa_offset + i * 2 + 1] = saved_1;
+            data[pos..pos + 2].copy_from_slice(&[0x01, 0x00]);
+        }
+    }
+
+    fn index_entries(entries: &[(u64, Vec<u8>, Option<u64>)], last_subnode: Option<u64>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut push = |reference: u64, key: &[u8], subnode: Option<u64>, last: bool| {
+            let length = align8(16 + key.len()) + subnode.map(|_| 8).unwrap_or(0);
+            let mut entry = vec![0_u8; length];
+            entry[0..8].copy_from_slice(&FileReference::new(reference, reference as u16).0.to_le_bytes());
+            entry[8..10].copy_from_slice(&(length as u16).to_le_bytes());
+            entry[10..12].copy_from_slice(&(key.len() as u16).to_le_bytes());
+            let flags = if subnode.is_some() { 1 } else { 0 } | if last { 2 } else { 0 };
+            entry[12..14].copy_from_slice(&(flags as u16).to_le_bytes());
+            entry[16..16 + key.len()].copy_from_slice(key);
+            if let Some(vcn) = subnode {
+                entry[length - 8..].copy_from_slice(&vcn.to_le_bytes());
+            }
+            bytes.extend(entry);
+        };
+
+        for (reference, key, subnode) in entries {
+            push(*reference, key, *subnode, false);
+        }
+        push(0, &[], last_subnode, true);
+        bytes
+    }
+
+    fn index_root(entries: &[u8], large: bool) -> Vec<u8> {
+        let mut value = vec![0_u8; 32];
+        value[0..4].copy_from_slice(&ATTR_FILE_NAME.to_le_bytes());
+        value[4..8].copy_from_slice(&1_u32.to_le_bytes()); // file name collation
+        value[8..12].copy_from_slice(&(RECORD as u32).to_le_bytes());
+        value[12] = (RECORD / CLUSTER) as u8;
+        value[16..20].copy_from_slice(&16_u32.to_le_bytes());
+        value[20..24].copy_from_slice(&(16 + entries.len() as u32).to_le_bytes());
+        value[24..28].copy_from_slice(&(16 + entries.len() as u32).to_le_bytes());
+        value[28] = large as u8;
+        value.extend_from_slice(entries);
+        value
+    }
+
+    fn indx(vcn: u64, entries: &[u8]) -> Vec<u8> {
+        let mut data = vec![0_u8; RECORD];
+        data[0..4].copy_from_slice(b"INDX");
+        data[4..6].copy_from_slice(&40_u16.to_le_bytes());
+        data[6..8].copy_from_slice(&3_u16.to_le_bytes());
+        data[16..24].copy_from_slice(&vcn.to_le_bytes());
+        data[24..28].copy_from_slice(&40_u32.to_le_bytes());
+        data[28..32].copy_from_slice(&(40 + entries.len() as u32).to_le_bytes());
+        data[32..36].copy_from_slice(&(RECORD as u32 - 24).to_le_bytes());
+        data[64..64 + entries.len()].copy_from_slice(entries);
+        protect(&mut data, 40);
+        data
+    }
+
+    fn put(image: &mut [u8], cluster: usize, data: &[u8]) {
+        image[cluster * CLUSTER..cluster * CLUSTER + data.len()].copy_from_slice(data);
+    }
+
+    fn put_record(image: &mut [u8], number: usize, data: &[u8]) {
+        put(image, MFT_CLUSTER + number * RECORD / CLUSTER, data);
+    }
+
+    fn attribute_list_entry(kind: u32, lowest_vcn: u64, record: u64, id: u16) -> Vec<u8> {
+        let mut entry = vec![0_u8; 32];
+        entry[0..4].copy_from_slice(&kind.to_le_bytes());
+        entry[4..6].copy_from_slice(&32_u16.to_le_bytes());
+        entry[7] = 26;
+        entry[8..16].copy_from_slice(&lowest_vcn.to_le_bytes());
+        entry[16..24].copy_from_slice(&FileReference::new(record, record as u16).0.to_le_bytes());
+        entry[24..26].copy_from_slice(&id.to_le_bytes());
+        entry
+    }
+
+    /// Root: `big.bin` (18), `hello.txt` (16), `Windows` (17).
+    /// `Windows` has a two level index: `m.txt` (19) in the root node, `a.log` (20) in the subnode.
+    fn ntfs_image() -> Vec<u8> {
+        let mut image = vec![0_u8; (COMPRESSED_CLUSTER + 2) * CLUSTER];
+
+        let boot = &mut image[..512];
+        boot[0..3].copy_from_slice(&[0xEB, 0x52, 0x90]);
+        boot[3..11].copy_from_slice(b"NTFS    ");
+        boot[0x0B..0x0D].copy_from_slice(&512_u16.to_le_bytes());
+        boot[0x0D] = 1;
+        boot[0x28..0x30].copy_from_slice(&((COMPRESSED_CLUSTER + 2) as u64).to_le_bytes());
+        boot[0x30..0x38].copy_from_slice(&(MFT_CLUSTER as u64).to_le_bytes());
+        boot[0x38..0x40].copy_from_slice(&2_u64.to_le_bytes());
+        boot[0x40] = 0xF6; // 1024 bytes
+        boot[0x44] = 0xF6;
+        boot[0x48..0x50].copy_from_slice(&0x1122_3344_5566_7788_u64.to_le_bytes());
+        boot[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        let mft_clusters = (MFT_RECORDS * RECORD / CLUSTER) as u8;
+        let mft_size = (MFT_RECORDS * RECORD) as u64;
+        let mft_data = non_resident(
+            ATTR_DATA,
+            "",
+            1,
+            0,
+            (0, mft_clusters as u64 - 1),
+            &[0x11, mft_clusters, MFT_CLUSTER as u8],
+            0,
+            (mft_size, mft_size),
+        );
+        put_record(&mut image, 0, &record(0, 1, &[mft_data]));
+
+        let mut info = vec![0_u8; 12];
+        info[8] = 3;
+        info[9] = 1;
+        let volume = [
+            resident(ATTR_VOLUME_NAME, "", 1, &utf16("TESTNTFS")),
+            resident(ATTR_VOLUME_INFORMATION, "", 2, &info),
+        ];
+        put_record(&mut image, 3, &record(3, 1, &volume));
+
+        // `a-z` map to `A-Z`
+        let upcase: Vec<u8> = (0..0x1_0000_u32)
+            .map(|c| if (0x61..=0x7A).contains(&c) { c - 0x20 } else { c } as u16)
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        put(&mut image, UPCASE_CLUSTER, &upcase);
+        let upcase_runs = [0x22, 0x00, 0x01, UPCASE_CLUSTER as u8, 0x00];
+        let upcase_data = non_resident(ATTR_DATA, "", 1, 0, (0, 255), &upcase_runs, 0, (0x2_0000, 0x2_0000));
+        put_record(&mut image, 10, &record(10, 1, &[upcase_data]));
+
+        let root_entries = index_entries(
+            &[
+                (18, file_name(5, "big.bin", false, 1), None),
+                (16, file_name(5, "hello.txt", false, 3), None),
+                (17, file_name(5, "Windows", true, 1), None),
+                (17, file_name(5, "WINDOWS~1", true, 2), None),
+            ],
+            None,
+        );
+        let root = [
+            resident(ATTR_FILE_NAME, "", 1, &file_name(5, ".", true, 3)),
+            resident(ATTR_INDEX_ROOT, I30, 2, &index_root(&root_entries, false)),
+        ];
+        put_record(&mut image, 5, &record(5, 3, &root));
+
+        let hello = [
+            resident(ATTR_STANDARD_INFORMATION, "", 0, &standard_information()),
+            resident(ATTR_FILE_NAME, "", 1, &file_name(5, "hello.txt", false, 3)),
+            resident(ATTR_DATA, "", 2, b"hello ntfs"),
+            resident(ATTR_DATA, "Zone.Identifier", 3, b"[ZoneTransfer]"),
+        ];
+        put_record(&mut image, 16, &record(16, 1, &hello));
+
+        let windows_root = index_entries(&[(19, file_name(17, "m.txt", false, 1), Some(0))], None);
+        let windows = [
+            resident(ATTR_FILE_NAME, "", 1, &file_name(5, "Windows", true, 1)),
+            resident(ATTR_INDEX_ROOT, I30, 2, &index_root(&windows_root, true)),
+            non_resident(
+                ATTR_INDEX_ALLOCATION,
+                I30,
+                3,
+                0,
+                (0, 1),
+                &[0x21, 0x02, INDX_CLUSTER as u8, (INDX_CLUSTER >> 8) as u8],
+                0,
+                (1024, 1024),
+            ),
+        ];
+        put_record(&mut image, 17, &record(17, 3, &windows));
+        let node = index_entries(&[(20, file_name(17, "a.log", false, 1), None)], None);
+        put(&mut image, INDX_CLUSTER, &indx(0, &node));
+
+        // data split into two extension records: 2 clusters, 2 sparse clusters, 1 cluster
+        let list: Vec<u8> = [
+            attribute_list_entry(ATTR_STANDARD_INFORMATION, 0, 18, 0),
+            attribute_list_entry(ATTR_FILE_NAME, 0, 18, 1),
+            attribute_list_entry(ATTR_DATA, 0, 21, 0),
+            attribute_list_entry(ATTR_DATA, 2, 22, 0),
+        ]
+        .concat();
+        let big = [
+            resident(ATTR_STANDARD_INFORMATION, "", 0, &standard_information()),
+            resident(ATTR_FILE_NAME, "", 1, &file_name(5, "big.bin", false, 1)),
+            resident(ATTR_ATTRIBUTE_LIST, "", 2, &list),
+        ];
+        put_record(&mut image, 18, &record(18, 1, &big));
+        let first = non_resident(
+            ATTR_DATA,
+            "",
+            0,
+            Attribute::SPARSE,
+            (0, 1),
+            &[0x21, 0x02, 0x42, 0x01],
+            0,
+            (2300, 2200),
+        );
+        put_record(&mut image, 21, &record(21, 1, &[first]));
+        let second = non_resident(
+            ATTR_DATA,
+            "",
+            0,
+            Attribute::SPARSE,
+            (2, 4),
+            &[0x01, 0x02, 0x21, 0x01, 0x44, 0x01],
+            0,
+            (0, 0),
+        );
+        put_record(&mut image, 22, &record(22, 1, &[second]));
+        for (cluster, value) in &[(0, 0x11), (1, 0x22), (2, 0x55)] {
+            put(&mut image, BIG_CLUSTER + cluster, &[*value; CLUSTER]);
+        }
+
+        put_record(&mut image, 19, &record(19, 1, &[resident(ATTR_DATA, "", 0, b"m")]));
+
+        // 16 cluster compression units: LZNT1 in 1 cluster + 15 sparse, then a sparse unit
+        let unit = [
+            0x21,
+            0x01,
+            COMPRESSED_CLUSTER as u8,
+            (COMPRESSED_CLUSTER >> 8) as u8,
+            0x01,
+            0x0F,
+            0x01,
+            0x10,
+        ];
+        let compressed = non_resident(ATTR_DATA, "", 0, Attribute::COMPRESSED, (0, 31), &unit, 4, (8292, 8292));
+        put_record(&mut image, 20, &record(20, 1, &[compressed]));
+        put(&mut image, COMPRESSED_CLUSTER, &[0x03, 0xB0, 0x02, b'a', 0x06, 0x00]);
+
+        image
+    }
+
+    #[test]
+    fn ntfs_read_test() {
+        let fs = NtfsFileSystem::open(MemoryDisk::from_vec(ntfs_image())).unwrap();
+        assert_eq!("TESTNTFS", fs.volume_label().unwrap());
+        assert_eq!("TESTNTFS", read_volume_label(fs.device(), fs.boot_sector()).unwrap());
+        assert_eq!((3, 1), fs.version().unwrap());
+        assert_eq!(0x1122_3344_5566_7788, fs.serial_number());
+        assert_eq!(MFT_RECORDS as u64, fs.record_count());
+
+        let root: Vec<String> = fs.read_dir("/").unwrap().into_iter().map(|e| e.file_name.name).collect();
+        assert_eq!(vec!["big.bin", "hello.txt", "Windows"], root);
+
+        let hello = fs.open_path("HELLO.TXT").unwrap();
+        let info = hello.standard_information().unwrap();
+        assert_eq!(1_600_000_000, info.modified.to_unix_time());
+        assert_eq!(Some(0x100), info.security_id);
+        assert_eq!("hello.txt", hello.name().unwrap().name);
+        assert_eq!(vec!["", "Zone.Identifier"], hello.stream_names());
+        assert_eq!(b"hello ntfs", fs.open_file("/hello.txt").unwrap().read_all().unwrap().as_slice());
+        assert_eq!(
+            b"[ZoneTransfer]",
+            fs.open_file("hello.txt:Zone.Identifier").unwrap().read_all().unwrap().as_slice()
+        );
+        match fs.open_file("hello.txt:missing") {
+            Err(Error::Ntfs(NtfsError::StreamNotFound(_))) => (),
+            _ => panic!("stream not found expected"),
+        }
+
+        // B+ tree descent into the INDX record
+        let windows: Vec<String> = fs.read_dir("windows").unwrap().into_iter().map(|e| e.file_name.name).collect();
+        assert_eq!(vec!["a.log", "m.txt"], windows);
+        assert_eq!(b"m", fs.open_file("\\Windows\\M.TXT").unwrap().read_all().unwrap().as_slice());
+        match fs.open_file("/Windows/z.txt") {
+            Err(Error::NotFound(_)) => (),
+            _ => panic!("not found expected"),
+        }
+
+        // compressed unit followed by a sparse one
+        let data = fs.open_file("/windows/../Windows/a.log").unwrap().read_all().unwrap();
+        assert_eq!(8292, data.len());
+        assert_eq!(b"aaaaaaaaaa", &data[..10]);
+        assert!(data[10..].iter().all(|&b| b == 0));
+
+        // attribute list with two extents, sparse runs and uninitialized tail
+        let big = fs.open_path("big.bin").unwrap();
+        assert_eq!(2300, big.size());
+        let data = fs.open_file("big.bin").unwrap().read_all().unwrap();
+        assert!(data[..512].iter().all(|&b| b == 0x11));
+        assert!(data[512..1024].iter().all(|&b| b == 0x22));
+        assert!(data[1024..2048].iter().all(|&b| b == 0));
+        assert!(data[2048..2200].iter().all(|&b| b == 0x55));
+        assert!(data[2200..].iter().all(|&b| b == 0));
+    }
+
+    fn usn_record(version: u16, reference: u64, parent: u64, usn: u64, reason: u32, name: &str) -> Vec<u8> {
+        let name = utf16(name);
+        let pos = if version == 2 { 24 } else { 40 };
+        let mut bytes = vec![0_u8; align8(pos + 36 + name.len())];
+        let length = bytes.len() as u32;
+        bytes[0..4].copy_from_slice(&length.to_le_bytes());
+        bytes[4..6].copy_from_slice(&version.to_le_bytes());
+        bytes[8..16].copy_from_slice(&FileReference::new(reference, reference as u16).0.to_le_bytes());
+        let parent_pos = if version == 2 { 16 } else { 24 };
+        bytes[parent_pos..parent_pos + 8].copy_from_slice(&FileReference::new(parent, parent as u16).0.to_le_bytes());
+        bytes[pos..pos + 8].copy_from_slice(&usn.to_le_bytes());
+        bytes[pos + 16..pos + 20].copy_from_slice(&reason.to_le_bytes());
+        bytes[pos + 32..pos + 34].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes[pos + 34..pos + 36].copy_from_slice(&(pos as u16 + 36).to_le_bytes());
+        bytes[pos + 36..pos + 36 + name.len()].copy_from_slice(&name);
+        bytes
+    }
+
+    /// `ntfs_image` with `$Secure`, `$Extend\$UsnJrnl` and the deleted `/Windows/old.txt` (23)
+    fn forensics_image() -> Vec<u8> {
+        const USN_CLUSTER: usize = COMPRESSED_CLUSTER + 2;
+
+        let mut image = ntfs_image();
+        image.resize((USN_CLUSTER + 8) * CLUSTER, 0);
+
+        let root_entries = index_entries(
+            &[
+                (11, file_name(5, "$Extend", true, 3), None),
+                (18, file_name(5, "big.bin", false, 1), None),
+                (16, file_name(5, "hello.txt", false, 3), None),
+                (17, file_name(5, "Windows", true, 1), None),
+                (17, file_name(5, "WINDOWS~1", true, 2), None),
+            ],
+            None,
+        );
+        let root = [
+            resident(ATTR_FILE_NAME, "", 1, &file_name(5, ".", true, 3)),
+            resident(ATTR_INDEX_ROOT, I30, 2, &index_root(&root_entries, false)),
+        ];
+        put_record(&mut image, 5, &record(5, 3, &root));
+
+        // owner only descriptor: S-1-5-18
+        let mut descriptor = vec![1, 0, 0x00, 0x80, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        descriptor.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0]);
+        let mut sds = vec![0_u8; 20];
+        sds[4..8].copy_from_slice(&0x100_u32.to_le_bytes());
+        sds[16..20].copy_from_slice(&(20 + descriptor.len() as u32).to_le_bytes());
+        sds.extend_from_slice(&descriptor);
+
+        // view index entry: data offset and size instead of the file reference
+        let mut sii = vec![0_u8; 40 + 16];
+        sii[0..2].copy_from_slice(&20_u16.to_le_bytes());
+        sii[2..4].copy_from_slice(&20_u16.to_le_bytes());
+        sii[8..10].copy_from_slice(&40_u16.to_le_bytes());
+        sii[10..12].copy_from_slice(&4_u16.to_le_bytes());
+        sii[16..20].copy_from_slice(&0x100_u32.to_le_bytes());
+        sii[20..40].copy_from_slice(&sds[..20]);
+        sii[48..50].copy_from_slice(&16_u16.to_le_bytes());
+        sii[52..54].copy_from_slice(&2_u16.to_le_bytes());
+        let mut sii_root = index_root(&sii, false);
+        sii_root[0..4].copy_from_slice(&0_u32.to_le_bytes());
+        sii_root[4..8].copy_from_slice(&0x10_u32.to_le_bytes()); // ULONG collation
+        let secure = [
+            resident(ATTR_FILE_NAME, "", 1, &file_name(5, "$Secure", false, 3)),
+            resident(ATTR_DATA, "$SDS", 2, &sds),
+            resident(ATTR_INDEX_ROOT, "$SII", 3, &sii_root),
+        ];
+        put_record(&mut image, 9, &record(9, 1, &secure));
+
+        let extend_entries = index_entries(&[(12, file_name(11, "$UsnJrnl", false, 3), None)], None);
+        let extend = [
+            resident(ATTR_FILE_NAME, "", 1, &file_name(5, "$Extend", true, 3)),
+            resident(ATTR_INDEX_ROOT, I30, 2, &index_root(&extend_entries, false)),
+        ];
+        put_record(&mut image, 11, &record(11, 3, &extend));
+
+        // the first journal page is purged
+        let journal_runs = [0x01, 0x08, 0x21, 0x08, USN_CLUSTER as u8, (USN_CLUSTER >> 8) as u8];
+        let journal = [
+            resident(ATTR_FILE_NAME, "", 1, &file_name(11, "$UsnJrnl", false, 3)),
+            resident(ATTR_DATA, "$Max", 2, &[0_u8; 32]),
+            non_resident(ATTR_DATA, "$J", 3, Attribute::SPARSE, (0, 15), &journal_runs, 0, (8192, 8192)),
+        ];
+        put_record(&mut image, 12, &record(12, 1, &journal));
+        let created = usn_record(2, 16, 5, 4096, UsnRecord::REASON_FILE_CREATE, "hello.txt");
+        let deleted = usn_record(3, 23, 17, 4096 + created.len() as u64, UsnRecord::REASON_FILE_DELETE, "old.txt");
+        put(&mut image, USN_CLUSTER, &[created, deleted].concat());
+
+        let old = [
+            resident(ATTR_STANDARD_INFORMATION, "", 0, &standard_information()),
+            resident(ATTR_FILE_NAME, "", 1, &file_name(17, "old.txt", false, 1)),
+            resident(ATTR_DATA, "", 2, b"gone"),
+        ];
+        put_record(&mut image, 23, &record(23, 0, &old));
+
+        image
+    }
+
+    #[test]
+    fn ntfs_forensics_test() {
+        let fs = NtfsFileSystem::open(MemoryDisk::from_vec(forensics_image())).unwrap();
+
+        let deleted: Vec<File<'_, _>> = fs.deleted_files().collect::<Result<_>>().unwrap();
+        assert_eq!(1, deleted.len());
+        assert_eq!(23, deleted[0].number());
+        assert!(!deleted[0].is_in_use());
+        assert_eq!(b"gone", deleted[0].data("").unwrap().read_all().unwrap().as_slice());
+        assert_eq!("/Windows/old.txt", fs.path_of(&deleted[0].name().unwrap()).unwrap());
+        match fs.open_path("/Windows/old.txt") {
+            Err(Error::NotFound(_)) => (),
+            _ => panic!("not found expected"),
+        }
+
+        // the parent reference does not match the reused record
+        let mut orphan = deleted[0].name().unwrap();
+        orphan.parent = FileReference::new(17, 1);
+        assert_eq!("/$OrphanFiles/old.txt", fs.path_of(&orphan).unwrap());
+
+        let journal: Vec<UsnRecord> = fs.usn_journal().unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(2, journal.len());
+        assert_eq!(2, journal[0].major_version);
+        assert_eq!(4096, journal[0].usn);
+        assert_eq!(16, journal[0].reference.record());
+        assert_eq!("hello.txt", journal[0].name);
+        assert_eq!(3, journal[1].major_version);
+        assert_eq!(UsnRecord::REASON_FILE_DELETE, journal[1].reason);
+        assert_eq!(17, journal[1].parent.record());
+        assert_eq!("old.txt", journal[1].name);
+
+        let hello = fs.open_path("hello.txt").unwrap();
+        let descriptor = fs.security_descriptor(&hello).unwrap().unwrap();
+        assert_eq!("S-1-5-18", descriptor.owner.unwrap().to_string());
+        assert!(descriptor.dacl.is_none());
+        assert!(fs.security_descriptor_by_id(0x101).unwrap().is_none());
+
+        // no security id in the short `$STANDARD_INFORMATION`
+        assert!(fs.security_descriptor(&fs.root().unwrap()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use super::record::apply_fixups;
+use super::*;
+use core::cmp::Ordering;
+
+const INDX_SIGNATURE: &[u8; 4] = b"INDX";
+const INDX_NODE_HEADER: usize = 24;
+const INDEX_ROOT_NODE_HEADER: usize = 16;
+
+const ENTRY_HAS_SUBNODE: u16 = 0x0001;
+const ENTRY_LAST: u16 = 0x0002;
+
+/// Protects against loops in damaged indexes
+const MAX_DEPTH: usize = 32;
+
+/// B+ tree index entry, the key is a `$FILE_NAME` value for directories
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct IndexEntry {
+    /// File reference for directory indexes, data offset and size for view indexes
+    pub reference: FileReference,
+    pub key: Vec<u8>,
+    /// View index data (like `$Secure:$SII`), empty for directory indexes
+    pub data: Vec<u8>,
+    subnode: Option<u64>,
+}
+
+impl IndexEntry {
+    pub fn file_name(&self) -> Option<FileName> {
+        FileName::parse(&self.key)
+    }
+}
+
+/// Node entries and the subnode of the terminating entry
+struct Node {
+    entries: Vec<IndexEntry>,
+    last_subnode: Option<u64>,
+}
+
+fn parse_node(bytes: &[u8], header: usize, view: bool, id: u64) -> Result<Node> {
+    let invalid = || Error::from(NtfsError::InvalidIndex(id));
+
+    let node = bytes.get(header..header + 16).ok_or_else(invalid)?;
+    let end = header + le_u32(node, 4) as usize;
+    let mut pos = header + le_u32(node, 0) as usize;
+
+    let mut entries = Vec::new();
+    loop {
+        let entry = bytes.get(pos..pos + 16).filter(|_| pos + 16 <= end).ok_or_else(invalid)?;
+        let length = le_u16(entry, 8) as usize;
+        let key_length = le_u16(entry, 10) as usize;
+        let flags = le_u16(entry, 12);
+        let entry = bytes
+            .get(pos..pos + length)
+            .filter(|_| length >= 16 && pos + length <= end && 16 + key_length <= length)
+            .ok_or_else(invalid)?;
+
+        let subnode = if flags & ENTRY_HAS_SUBNODE != 0 {
+            if length < 24 {
+                return Err(invalid());
+            }
+            Some(le_u64(entry, length - 8))
+        } else {
+            None
+        };
+
+        if flags & ENTRY_LAST != 0 {
+            return Ok(Node {
+                entries,
+                last_subnode: subnode,
+            });
+        }
+
+        let data = if view {
+            let offset = le_u16(entry, 0) as usize;
+            let size = le_u16(entry, 2) as usize;
+            entry.get(offset..offset + size).ok_or_else(invalid)?.to_vec()
+        } else {
+            Vec::new()
+        };
+
+        entries.push(IndexEntry {
+            reference: FileReference(le_u64(entry, 0)),
+            key: entry[16..16 + key_length].to_vec(),
+            data,
+            subnode,
+        });
+        pos += length;
+    }
+}
+
+/// Root and allocation attributes of a single index
+struct Index<'a> {
+    number: u64,
+    root: &'a [u8],
+    allocation: Option<&'a Attribute>,
+    record_size: usize,
+    /// Subnode VCNs are in clusters or in 512 byte blocks for small index records
+    vcn_size: u64,
+    view: bool,
+}
+
+impl<R: ReadAt> NtfsFileSystem<R> {
+    fn index<'a>(&self, file: &'a File<'_, R>, name: &str) -> Result<Index<'a>> {
+        let root = match file.attribute(ATTR_INDEX_ROOT, name).map(|a| &a.value) {
+            Some(AttributeValue::Resident(root)) if root.len() >= 32 => root,
+            _ => return Err(Error::from(NtfsError::MissingAttribute(file.number(), ATTR_INDEX_ROOT))),
+        };
+
+        let record_size = le_u32(root, 8) as usize;
+        let cluster_size = self.boot_sector().cluster_size() as u64;
+        Ok(Index {
+            number: file.number(),
+            root,
+            allocation: file.attribute(ATTR_INDEX_ALLOCATION, name),
+            record_size,
+            vcn_size: if record_size as u64 >= cluster_size { cluster_size } else { 512 },
+            // directory indexes have the `$FILE_NAME` type, view indexes have none
+            view: le_u32(root, 0) == 0,
+        })
+    }
+
+    fn read_node(&self, index: &Index, vcn: u64) -> Result<Node> {
+        let invalid = || Error::from(NtfsError::InvalidIndex(index.number));
+
+        let allocation = index.allocation.ok_or_else(invalid)?;
+        if index.record_size < INDX_NODE_HEADER + 16 {
+            return Err(invalid());
+        }
+
+        let mut buffer = vec![0_u8; index.record_size];
+        let offset = vcn.checked_mul(index.vcn_size).ok_or_else(invalid)?;
+        self.read_attribute_exact(allocation, offset, &mut buffer)?;
+        if &buffer[..4] != INDX_SIGNATURE {
+            return Err(invalid());
+        }
+        apply_fixups(&mut buffer, index.number)?;
+
+        parse_node(&buffer, INDX_NODE_HEADER, index.view, index.number)
+    }
+
+    fn walk(&self, index: &Index, node: Node, depth: usize, entries: &mut Vec<IndexEntry>) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(Error::from(NtfsError::InvalidIndex(index.number)));
+        }
+
+        for entry in node.entries {
+            if let Some(vcn) = entry.subnode {
+                self.walk(index, self.read_node(index, vcn)?, depth + 1, entries)?;
+            }
+            entries.push(entry);
+        }
+
+        match node.last_subnode {
+            Some(vcn) => self.walk(index, self.read_node(index, vcn)?, depth + 1, entries),
+            None => Ok(()),
+        }
+    }
+
+    /// All index entries in the collation order
+    pub fn index_entries(&self, file: &File<'_, R>, name: &str) -> Result<Vec<IndexEntry>> {
+        let index = self.index(file, name)?;
+        let root = parse_node(index.root, INDEX_ROOT_NODE_HEADER, index.view, index.number)?;
+
+        let mut entries = Vec::new();
+        self.walk(&index, root, 0, &mut entries)?;
+        Ok(entries)
+    }
+
+    /// Descends the B+ tree, `compare` returns the ordering of the looked up key relative to the entry key
+    pub fn find_index_entry(&self, file: &File<'_, R>, name: &str, compare: impl Fn(&[u8]) -> Ordering) -> Result<Option<IndexEntry>> {
+        let index = self.index(file, name)?;
+        let mut node = parse_node(index.root, INDEX_ROOT_NODE_HEADER, index.view, index.number)?;
+
+        for _ in 0..MAX_DEPTH {
+            let mut next = node.last_subnode;
+            for entry in node.entries {
+                match compare(&entry.key) {
+                    Ordering::Equal => return Ok(Some(entry)),
+                    Ordering::Less => {
+                        next = entry.subnode;
+                        break;
+                    }
+                    Ordering::Greater => (),
+                }
+            }
+
+            match next {
+                Some(vcn) => node = self.read_node(&index, vcn)?,
+                None => return Ok(None),
+            }
+        }
+
+        Err(Error::from(NtfsError::InvalidIndex(index.number)))
+    }
+}
@@ -0,0 +1,532 @@
+use super::record::apply_fixups;
+use super::*;
+
+const RESTART_SIGNATURE: &[u8; 4] = b"RSTR";
+/// Restart page modified by chkdsk
+const CHKDSK_SIGNATURE: &[u8; 4] = b"CHKD";
+const RECORD_PAGE_SIGNATURE: &[u8; 4] = b"RCRD";
+
+const RESTART_PAGE_HEADER: usize = 30;
+const RESTART_AREA_SIZE: usize = 44;
+const CLIENT_RECORD_SIZE: usize = 0xA0;
+const RECORD_HEADER_SIZE: usize = 0x30;
+const CLIENT_DATA_HEADER_SIZE: usize = 0x20;
+/// The second restart page offset if the first one is damaged
+const DEFAULT_PAGE_SIZE: u32 = 0x1000;
+/// No client
+const LFS_NO_CLIENT: u16 = 0xFFFF;
+
+/// `$LogFile` client, NTFS itself is the only one
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct LogClient {
+    pub oldest_lsn: u64,
+    /// The LSN the checkpoint restart starts at
+    pub restart_lsn: u64,
+    pub name: String,
+}
+
+/// Restart page header with its restart area, the most recent one of the two copies
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct RestartArea {
+    pub chkdsk_lsn: u64,
+    pub system_page_size: u32,
+    pub log_page_size: u32,
+    pub major_version: i16,
+    pub minor_version: i16,
+    pub current_lsn: u64,
+    /// `RESTART_*` flags
+    pub flags: u16,
+    pub sequence_number_bits: u32,
+    pub file_size: u64,
+    pub record_header_length: u16,
+    /// Offset of the first log record in a record page
+    pub page_data_offset: u16,
+    pub open_count: u32,
+    /// Clients in use
+    pub clients: Vec<LogClient>,
+}
+
+impl RestartArea {
+    /// The volume was dismounted cleanly
+    pub const RESTART_VOLUME_IS_CLEAN: u16 = 0x0002;
+
+    fn parse(page: &[u8]) -> Option<Self> {
+        let area_offset = le_u16(page, 24) as usize;
+        let area = page.get(area_offset..area_offset + RESTART_AREA_SIZE)?;
+
+        let client_count = le_u16(area, 8) as usize;
+        let clients_offset = area_offset + le_u16(area, 22) as usize;
+        let client =
+            |index: usize| page.get(clients_offset + index * CLIENT_RECORD_SIZE..clients_offset + (index + 1) * CLIENT_RECORD_SIZE);
+
+        // follow the in use list, the client count bounds damaged lists
+        let mut clients = Vec::new();
+        let mut index = le_u16(area, 12);
+        while index != LFS_NO_CLIENT && clients.len() < client_count {
+            let record = client(index as usize)?;
+            let name_length = core::cmp::min(le_u32(record, 28) as usize, CLIENT_RECORD_SIZE - 32);
+            clients.push(LogClient {
+                oldest_lsn: le_u64(record, 0),
+                restart_lsn: le_u64(record, 8),
+                name: utf16_string(&record[32..32 + name_length]),
+            });
+            index = le_u16(record, 18);
+        }
+
+        Some(Self {
+            chkdsk_lsn: le_u64(page, 8),
+            system_page_size: le_u32(page, 16),
+            log_page_size: le_u32(page, 20),
+            minor_version: le_u16(page, 26) as i16,
+            major_version: le_u16(page, 28) as i16,
+            current_lsn: le_u64(area, 0),
+            flags: le_u16(area, 14),
+            sequence_number_bits: le_u32(area, 16),
+            file_size: le_u64(area, 24),
+            record_header_length: le_u16(area, 36),
+            page_data_offset: le_u16(area, 38),
+            open_count: le_u32(area, 40),
+            clients,
+        })
+    }
+}
+
+/// Log record, redo and undo fields are set for client records only
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct LogRecord {
+    pub lsn: u64,
+    pub previous_lsn: u64,
+    pub undo_next_lsn: u64,
+    /// `RECORD_*`
+    pub record_type: u32,
+    pub transaction_id: u32,
+    pub flags: u16,
+    /// `OP_*`
+    pub redo_operation: u16,
+    pub undo_operation: u16,
+    pub target_attribute: u16,
+    pub target_vcn: u64,
+    pub record_offset: u16,
+    pub attribute_offset: u16,
+    pub cluster_block_offset: u16,
+    pub lcns: Vec<u64>,
+    pub redo_data: Vec<u8>,
+    pub undo_data: Vec<u8>,
+}
+
+impl LogRecord {
+    pub const RECORD_CLIENT: u32 = 1;
+    pub const RECORD_CLIENT_RESTART: u32 = 2;
+
+    /// The record continues on the next page
+    pub const FLAG_MULTI_PAGE: u16 = 0x0001;
+
+    pub const OP_NOOP: u16 = 0x00;
+    pub const OP_COMPENSATION_LOG_RECORD: u16 = 0x01;
+    pub const OP_INITIALIZE_FILE_RECORD_SEGMENT: u16 = 0x02;
+    pub const OP_DEALLOCATE_FILE_RECORD_SEGMENT: u16 = 0x03;
+    pub const OP_WRITE_END_OF_FILE_RECORD_SEGMENT: u16 = 0x04;
+    pub const OP_CREATE_ATTRIBUTE: u16 = 0x05;
+    pub const OP_DELETE_ATTRIBUTE: u16 = 0x06;
+    pub const OP_UPDATE_RESIDENT_VALUE: u16 = 0x07;
+    pub const OP_UPDATE_NONRESIDENT_VALUE: u16 = 0x08;
+    pub const OP_UPDATE_MAPPING_PAIRS: u16 = 0x09;
+    pub const OP_DELETE_DIRTY_CLUSTERS: u16 = 0x0A;
+    pub const OP_SET_NEW_ATTRIBUTE_SIZES: u16 = 0x0B;
+    pub const OP_ADD_INDEX_ENTRY_ROOT: u16 = 0x0C;
+    pub const OP_DELETE_INDEX_ENTRY_ROOT: u16 = 0x0D;
+    pub const OP_ADD_INDEX_ENTRY_ALLOCATION: u16 = 0x0E;
+    pub const OP_DELETE_INDEX_ENTRY_ALLOCATION: u16 = 0x0F;
+    pub const OP_WRITE_END_OF_INDEX_BUFFER: u16 = 0x10;
+    pub const OP_SET_INDEX_ENTRY_VCN_ROOT: u16 = 0x11;
+    pub const OP_SET_INDEX_ENTRY_VCN_ALLOCATION: u16 = 0x12;
+    pub const OP_UPDATE_FILE_NAME_ROOT: u16 = 0x13;
+    pub const OP_UPDATE_FILE_NAME_ALLOCATION: u16 = 0x14;
+    pub const OP_SET_BITS_IN_NONRESIDENT_BITMAP: u16 = 0x15;
+    pub const OP_CLEAR_BITS_IN_NONRESIDENT_BITMAP: u16 = 0x16;
+    pub const OP_HOT_FIX: u16 = 0x17;
+    pub const OP_END_TOP_LEVEL_ACTION: u16 = 0x18;
+    pub const OP_PREPARE_TRANSACTION: u16 = 0x19;
+    pub const OP_COMMIT_TRANSACTION: u16 = 0x1A;
+    pub const OP_FORGET_TRANSACTION: u16 = 0x1B;
+    pub const OP_OPEN_NONRESIDENT_ATTRIBUTE: u16 = 0x1C;
+    pub const OP_OPEN_ATTRIBUTE_TABLE_DUMP: u16 = 0x1D;
+    pub const OP_ATTRIBUTE_NAMES_DUMP: u16 = 0x1E;
+    pub const OP_DIRTY_PAGE_TABLE_DUMP: u16 = 0x1F;
+    pub const OP_TRANSACTION_TABLE_DUMP: u16 = 0x20;
+    pub const OP_UPDATE_RECORD_DATA_ROOT: u16 = 0x21;
+    pub const OP_UPDATE_RECORD_DATA_ALLOCATION: u16 = 0x22;
+
+    /// `bytes` is the whole record with its client data
+    fn parse(bytes: &[u8]) -> Self {
+        let mut record = Self {
+            lsn: le_u64(bytes, 0),
+            previous_lsn: le_u64(bytes, 8),
+            undo_next_lsn: le_u64(bytes, 16),
+            record_type: le_u32(bytes, 32),
+            transaction_id: le_u32(bytes, 36),
+            flags: le_u16(bytes, 40),
+            redo_operation: 0,
+            undo_operation: 0,
+            target_attribute: 0,
+            target_vcn: 0,
+            record_offset: 0,
+            attribute_offset: 0,
+            cluster_block_offset: 0,
+            lcns: Vec::new(),
+            redo_data: Vec::new(),
+            undo_data: Vec::new(),
+        };
+
+        let data = &bytes[RECORD_HEADER_SIZE..];
+        if record.record_type != Self::RECORD_CLIENT || data.len() < CLIENT_DATA_HEADER_SIZE {
+            return record;
+        }
+
+        let part = |offset: usize, length: usize| data.get(offset..offset + length).map(|p| p.to_vec()).unwrap_or_default();
+        let lcn_count = le_u16(data, 14) as usize;
+        record.redo_operation = le_u16(data, 0);
+        record.undo_operation = le_u16(data, 2);
+        record.redo_data = part(le_u16(data, 4) as usize, le_u16(data, 6) as usize);
+        record.undo_data = part(le_u16(data, 8) as usize, le_u16(data, 10) as usize);
+        record.target_attribute = le_u16(data, 12);
+        record.record_offset = le_u16(data, 16);
+        record.attribute_offset = le_u16(data, 18);
+        record.cluster_block_offset = le_u16(data, 20);
+        record.target_vcn = le_u64(data, 24);
+        record.lcns = part(CLIENT_DATA_HEADER_SIZE, lcn_count * 8)
+            .chunks_exact(8)
+            .map(|c| le_u64(c, 0))
+            .collect();
+        record
+    }
+}
+
+/// `$LogFile` reader, records are collected from all record pages to build a timeline
+pub struct LogFile<S: ReadAt> {
+    stream: S,
+    size: u64,
+    restart: RestartArea,
+}
+
+impl<S: ReadAt> LogFile<S> {
+    pub fn open(stream: S, size: u64) -> Result<Self> {
+        let first = Self::read_restart(&stream, size, 0)?;
+        let second_offset = first.as_ref().map(|area| area.system_page_size).unwrap_or(DEFAULT_PAGE_SIZE);
+        let second = Self::read_restart(&stream, size, second_offset as u64)?;
+
+        let restart = match (first, second) {
+            (Some(first), Some(second)) if second.current_lsn > first.current_lsn => second,
+            (Some(first), _) => first,
+            (None, Some(second)) => second,
+            (None, None) => return Err(Error::from(NtfsError::InvalidLogFile)),
+        };
+
+        let bits = restart.sequence_number_bits;
+        let page_size = restart.log_page_size as usize;
+        let valid =
+            (3..64).contains(&bits) && page_size >= 512 && page_size.is_power_of_two() && (restart.page_data_offset as usize) < page_size;
+        if !valid {
+            return Err(Error::from(NtfsError::InvalidLogFile));
+        }
+
+        Ok(Self { stream, size, restart })
+    }
+
+    fn read_restart(stream: &S, size: u64, offset: u64) -> Result<Option<RestartArea>> {
+        let mut header = [0_u8; RESTART_PAGE_HEADER];
+        if offset + RESTART_PAGE_HEADER as u64 > size {
+            return Ok(None);
+        }
+        stream.read_exact_at(offset, &mut header)?;
+        if &header[..4] != RESTART_SIGNATURE && &header[..4] != CHKDSK_SIGNATURE {
+            return Ok(None);
+        }
+
+        let page_size = le_u32(&header, 16) as u64;
+        if page_size < 512 || !page_size.is_power_of_two() || offset + page_size > size {
+            return Ok(None);
+        }
+
+        let mut page = vec![0_u8; page_size as usize];
+        stream.read_exact_at(offset, &mut page)?;
+        if apply_fixups(&mut page, RECORD_LOG_FILE).is_err() {
+            return Ok(None); // torn write
+        }
+
+        Ok(RestartArea::parse(&page))
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    pub fn restart_area(&self) -> &RestartArea {
+        &self.restart
+    }
+
+    /// File offset of the LSN, the high bits of an LSN is the wrap count
+    pub fn lsn_to_offset(&self, lsn: u64) -> u64 {
+        let bits = self.restart.sequence_number_bits;
+        (lsn << bits) >> (bits - 3)
+    }
+
+    fn page_size(&self) -> u64 {
+        self.restart.log_page_size as u64
+    }
+
+    /// Record pages start after the two restart pages
+    fn first_page(&self) -> u64 {
+        2 * self.restart.system_page_size as u64
+    }
+
+    /// Reads the record page, `None` for unused and torn pages
+    fn read_page(&self, offset: u64) -> Result<Option<Vec<u8>>> {
+        let mut page = vec![0_u8; self.page_size() as usize];
+        self.stream.read_exact_at(offset, &mut page)?;
+        if &page[..4] != RECORD_PAGE_SIGNATURE || apply_fixups(&mut page, RECORD_LOG_FILE).is_err() {
+            return Ok(None);
+        }
+        Ok(Some(page))
+    }
+
+    /// Collects a record spanning several pages, the log wraps to the first record page
+    fn read_spanning(&self, mut offset: u64, mut record: Vec<u8>, length: usize) -> Result<Option<Vec<u8>>> {
+        let data_offset = self.restart.page_data_offset as usize;
+        while record.len() < length {
+            offset += self.page_size();
+            if offset + self.page_size() > self.size {
+                offset = self.first_page();
+            }
+
+            let page = match self.read_page(offset)? {
+                Some(page) => page,
+                None => return Ok(None),
+            };
+            let take = core::cmp::min(length - record.len(), page.len() - data_offset);
+            record.extend_from_slice(&page[data_offset..data_offset + take]);
+        }
+
+        Ok(Some(record))
+    }
+
+    /// All log records found in the record pages ordered by LSN.
+    ///
+    /// A record is accepted only if its LSN maps to its own position, so the leftovers
+    /// of overwritten records and the continuations of spanning ones are skipped.
+    pub fn records(&self) -> Result<Vec<LogRecord>> {
+        let page_size = self.page_size();
+        let data_offset = self.restart.page_data_offset as usize;
+        let max_length = self.size as usize;
+
+        let mut records = BTreeMap::new();
+        let mut page_offset = self.first_page();
+        while page_offset + page_size <= self.size {
+            let page = match self.read_page(page_offset)? {
+                Some(page) => page,
+                None => {
+                    page_offset += page_size;
+                    continue;
+                }
+            };
+
+            let mut pos = data_offset;
+            while pos + RECORD_HEADER_SIZE <= page.len() {
+                let lsn = le_u64(&page, pos);
+                if lsn == 0 || self.lsn_to_offset(lsn) != page_offset + pos as u64 {
+                    pos += 8;
+                    continue;
+                }
+
+                let length = RECORD_HEADER_SIZE + le_u32(&page, pos + 24) as usize;
+                if length > max_length {
+                    pos += 8;
+                    continue;
+                }
+
+                let end = core::cmp::min(pos + length, page.len());
+                let bytes = if end - pos == length {
+                    Some(page[pos..end].to_vec())
+                } else {
+                    self.read_spanning(page_offset, page[pos..end].to_vec(), length)?
+                };
+                if let Some(bytes) = bytes {
+                    records.entry(lsn).or_insert_with(|| LogRecord::parse(&bytes));
+                }
+
+                pos += (length + 7) & !7;
+            }
+
+            page_offset += page_size;
+        }
+
+        Ok(records.into_values().collect())
+    }
+}
+
+impl<R: ReadAt> NtfsFileSystem<R> {
+    pub fn log_file(&self) -> Result<LogFile<DataStream<'_, R>>> {
+        let stream = self.file(RECORD_LOG_FILE)?.data("")?;
+        let size = stream.size();
+        LogFile::open(stream, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+
+    const PAGE: usize = 1024;
+    const SEQUENCE_BITS: u32 = 44;
+
+    /// Same as the MFT record fixups: 512 byte blocks
+    fn protect(page: &mut [u8]) {
+        page[4..6].copy_from_slice(&0x28_u16.to_le_bytes());
+        let usa_count = 1 + page.len() as u16 / 512;
+        page[6..8].copy_from_slice(&usa_count.to_le_bytes());
+        page[0x28..0x2A].copy_from_slice(&[0x07, 0x00]);
+        for i in 1..=page.len() / 512 {
+            let pos = i * 512 - 2;
+            let usa = 0x28 + i * 2;
+            page[usa] = page[pos];
+            page[usa + 1] = page[pos + 1];
+            page[pos..pos + 2].copy_from_slice(&[0x07, 0x00]);
+        }
+    }
+
+    fn restart_page(current_lsn: u64) -> Vec<u8> {
+        let mut page = vec![0_u8; PAGE];
+        page[0..4].copy_from_slice(RESTART_SIGNATURE);
+        page[16..20].copy_from_slice(&(PAGE as u32).to_le_bytes());
+        page[20..24].copy_from_slice(&(PAGE as u32).to_le_bytes());
+        page[24..26].copy_from_slice(&0x30_u16.to_le_bytes());
+        page[26..28].copy_from_slice(&1_u16.to_le_bytes());
+        page[28..30].copy_from_slice(&1_u16.to_le_bytes());
+
+        let area = &mut page[0x30..];
+        area[0..8].copy_from_slice(&current_lsn.to_le_bytes());
+        area[8..10].copy_from_slice(&1_u16.to_le_bytes());
+        area[10..12].copy_from_slice(&LFS_NO_CLIENT.to_le_bytes());
+        area[12..14].copy_from_slice(&0_u16.to_le_bytes());
+        area[14..16].copy_from_slice(&RestartArea::RESTART_VOLUME_IS_CLEAN.to_le_bytes());
+        area[16..20].copy_from_slice(&SEQUENCE_BITS.to_le_bytes());
+        area[22..24].copy_from_slice(&0x30_u16.to_le_bytes());
+        area[24..32].copy_from_slice(&(6 * PAGE as u64).to_le_bytes());
+        area[36..38].copy_from_slice(&(RECORD_HEADER_SIZE as u16).to_le_bytes());
+        area[38..40].copy_from_slice(&0x40_u16.to_le_bytes());
+
+        let client = &mut area[0x30..];
+        client[0..8].copy_from_slice(&0x1000_u64.to_le_bytes());
+        client[8..16].copy_from_slice(&0x1010_u64.to_le_bytes());
+        client[16..18].copy_from_slice(&LFS_NO_CLIENT.to_le_bytes());
+        client[18..20].copy_from_slice(&LFS_NO_CLIENT.to_le_bytes());
+        client[28..32].copy_from_slice(&8_u32.to_le_bytes());
+        client[32..40].copy_from_slice(&[b'N', 0, b'T', 0, b'F', 0, b'S', 0]);
+
+        protect(&mut page);
+        page
+    }
+
+    /// LSN with the wrap count 1 pointing at the file offset
+    fn lsn(offset: usize) -> u64 {
+        (1 << (64 - SEQUENCE_BITS)) | (offset as u64 >> 3)
+    }
+
+    fn log_record(offset: usize, previous: usize, redo: u16, redo_data: &[u8]) -> Vec<u8> {
+        let client_length = CLIENT_DATA_HEADER_SIZE + 8 + redo_data.len();
+        let mut record = vec![0_u8; RECORD_HEADER_SIZE + client_length];
+        record[0..8].copy_from_slice(&lsn(offset).to_le_bytes());
+        if previous != 0 {
+            record[8..16].copy_from_slice(&lsn(previous).to_le_bytes());
+        }
+        record[24..28].copy_from_slice(&(client_length as u32).to_le_bytes());
+        record[32..36].copy_from_slice(&LogRecord::RECORD_CLIENT.to_le_bytes());
+        record[36..40].copy_from_slice(&0x18_u32.to_le_bytes());
+
+        let data = &mut record[RECORD_HEADER_SIZE..];
+        data[0..2].copy_from_slice(&redo.to_le_bytes());
+        data[4..6].copy_from_slice(&((CLIENT_DATA_HEADER_SIZE + 8) as u16).to_le_bytes());
+        data[6..8].copy_from_slice(&(redo_data.len() as u16).to_le_bytes());
+        data[8..10].copy_from_slice(&((CLIENT_DATA_HEADER_SIZE + 8) as u16).to_le_bytes());
+        data[12..14].copy_from_slice(&0x18_u16.to_le_bytes());
+        data[14..16].copy_from_slice(&1_u16.to_le_bytes());
+        data[24..32].copy_from_slice(&7_u64.to_le_bytes());
+        data[32..40].copy_from_slice(&0x1234_u64.to_le_bytes());
+        data[40..].copy_from_slice(redo_data);
+        record
+    }
+
+    fn record_page() -> Vec<u8> {
+        let mut page = vec![0_u8; PAGE];
+        page[0..4].copy_from_slice(RECORD_PAGE_SIGNATURE);
+        page
+    }
+
+    #[test]
+    fn log_file_test() {
+        let mut log = vec![0_u8; 6 * PAGE];
+        log[..PAGE].copy_from_slice(&restart_page(lsn(2 * PAGE + 0x40)));
+        log[PAGE..2 * PAGE].copy_from_slice(&restart_page(lsn(4 * PAGE + 0x40)));
+
+        // page 2: a small record then one spanning into page 3, page 4 is a leftover
+        let first_offset = 2 * PAGE + 0x40;
+        let first = log_record(first_offset, 0, LogRecord::OP_INITIALIZE_FILE_RECORD_SEGMENT, b"redo");
+        let second_offset = first_offset + ((first.len() + 7) & !7);
+        let second = log_record(second_offset, first_offset, LogRecord::OP_UPDATE_RESIDENT_VALUE, &[0xAB; 1200]);
+
+        let mut page = record_page();
+        page[0x40..0x40 + first.len()].copy_from_slice(&first);
+        let split = PAGE - (second_offset - 2 * PAGE);
+        page[second_offset - 2 * PAGE..].copy_from_slice(&second[..split]);
+        protect(&mut page);
+        log[2 * PAGE..3 * PAGE].copy_from_slice(&page);
+
+        let mut page = record_page();
+        page[0x40..0x40 + second.len() - split].copy_from_slice(&second[split..]);
+        protect(&mut page);
+        log[3 * PAGE..4 * PAGE].copy_from_slice(&page);
+
+        let mut page = record_page();
+        let stale = log_record(0x40, 0, LogRecord::OP_NOOP, &[]);
+        page[0x80..0x80 + stale.len()].copy_from_slice(&stale);
+        protect(&mut page);
+        log[4 * PAGE..5 * PAGE].copy_from_slice(&page);
+
+        let size = log.len() as u64;
+        let log = LogFile::open(MemoryDisk::from_vec(log), size).unwrap();
+        let restart = log.restart_area();
+        assert_eq!(lsn(4 * PAGE + 0x40), restart.current_lsn);
+        assert_eq!(PAGE as u32, restart.log_page_size);
+        assert_eq!(1, restart.clients.len());
+        assert_eq!("NTFS", restart.clients[0].name);
+        assert_eq!(0x1010, restart.clients[0].restart_lsn);
+        assert_eq!(first_offset as u64, log.lsn_to_offset(lsn(first_offset)));
+
+        let records = log.records().unwrap();
+        assert_eq!(2, records.len());
+        assert_eq!(lsn(first_offset), records[0].lsn);
+        assert_eq!(LogRecord::OP_INITIALIZE_FILE_RECORD_SEGMENT, records[0].redo_operation);
+        assert_eq!(b"redo", records[0].redo_data.as_slice());
+        assert_eq!(0x18, records[0].target_attribute);
+        assert_eq!(7, records[0].target_vcn);
+        assert_eq!(vec![0x1234], records[0].lcns);
+
+        assert_eq!(lsn(first_offset), records[1].previous_lsn);
+        assert_eq!(LogRecord::OP_UPDATE_RESIDENT_VALUE, records[1].redo_operation);
+        assert_eq!(vec![0xAB; 1200], records[1].redo_data);
+        assert!(records[1].undo_data.is_empty());
+
+        let mut damaged = restart_page(0);
+        damaged[510] = 0;
+        let mut log = vec![0_u8; 6 * PAGE];
+        log[..PAGE].copy_from_slice(&damaged);
+        match LogFile::open(MemoryDisk::from_vec(log), size) {
+            Err(Error::Ntfs(NtfsError::InvalidLogFile)) => (),
+            _ => panic!("invalid log file expected"),
+        }
+    }
+}
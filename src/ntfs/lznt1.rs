@@ -0,0 +1,116 @@
+//! LZNT1 decompression used by compressed NTFS attributes
+use super::*;
+
+const CHUNK_SIZE: usize = 4096;
+const CHUNK_COMPRESSED: u16 = 0x8000;
+
+/// Appends up to `limit` decompressed bytes to `output`
+pub(crate) fn decompress(input: &[u8], output: &mut Vec<u8>, limit: usize) -> Result<()> {
+    let invalid = || Error::from(NtfsError::InvalidCompressedData);
+
+    let end = output.len() + limit;
+    let mut pos = 0;
+    while pos + 2 <= input.len() && output.len() < end {
+        let header = le_u16(input, pos);
+        if header == 0 {
+            break;
+        }
+
+        let size = (header & 0x0FFF) as usize + 1;
+        let chunk = input.get(pos + 2..pos + 2 + size).ok_or_else(invalid)?;
+        let start = output.len();
+        if header & CHUNK_COMPRESSED == 0 {
+            output.extend_from_slice(chunk);
+        } else {
+            decompress_chunk(chunk, output)?;
+        }
+
+        // short chunks are padded with zeros
+        if output.len() - start < CHUNK_SIZE {
+            output.resize(start + CHUNK_SIZE, 0);
+        }
+        pos += 2 + size;
+    }
+
+    output.truncate(core::cmp::min(output.len(), end));
+    Ok(())
+}
+
+fn decompress_chunk(chunk: &[u8], output: &mut Vec<u8>) -> Result<()> {
+    let invalid = || Error::from(NtfsError::InvalidCompressedData);
+
+    let start = output.len();
+    let mut pos = 0;
+    while pos < chunk.len() {
+        let flags = chunk[pos];
+        pos += 1;
+
+        for bit in 0..8 {
+            if pos >= chunk.len() {
+                break;
+            }
+
+            if flags & (1 << bit) == 0 {
+                output.push(chunk[pos]);
+                pos += 1;
+                continue;
+            }
+
+            let token = le_u16(chunk.get(pos..pos + 2).ok_or_else(invalid)?, 0);
+            pos += 2;
+
+            // the more is decompressed, the more bits are used for the offset
+            let written = output.len() - start;
+            if written == 0 {
+                return Err(invalid());
+            }
+            let mut shift = 0;
+            let mut i = written - 1;
+            while i >= 0x10 {
+                i >>= 1;
+                shift += 1;
+            }
+
+            let back = (token >> (12 - shift)) as usize + 1;
+            let length = (token & (0x0FFF >> shift)) as usize + 3;
+            if back > written || written + length > CHUNK_SIZE {
+                return Err(invalid());
+            }
+
+            // the source may overlap the output
+            for _ in 0..length {
+                output.push(output[output.len() - back]);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_test() {
+        // 'a' literal and a 9 byte back reference
+        let input = [0x03, 0xB0, 0x02, b'a', 0x06, 0x00];
+        let mut output = Vec::new();
+        decompress(&input, &mut output, 10).unwrap();
+        assert_eq!(b"aaaaaaaaaa", output.as_slice());
+
+        // padded to the chunk size, followed by an uncompressed chunk
+        let mut input = input.to_vec();
+        input.extend_from_slice(&[0xFF, 0x3F]);
+        input.extend(vec![0x55; CHUNK_SIZE]);
+        let mut output = Vec::new();
+        decompress(&input, &mut output, 2 * CHUNK_SIZE).unwrap();
+        assert_eq!(2 * CHUNK_SIZE, output.len());
+        assert_eq!(0, output[10]);
+        assert_eq!(0x55, output[CHUNK_SIZE]);
+
+        // back reference before the chunk start
+        let mut output = Vec::new();
+        assert!(decompress(&[0x01, 0xB0, 0x01, 0x00], &mut output, 10).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+//! NTFS filesystem, read only, with access to the metadata used in forensics
+//!
+//! See https://flatcap.github.io/linux-ntfs/ntfs/ and linux/fs/ntfs
+use crate::prelude::*;
+use crate::tools::{le_u16, le_u32, le_u64};
+
+mod error;
+pub use error::NtfsError;
+
+mod boot;
+pub use boot::BootSector;
+
+mod record;
+pub use record::{Attribute, AttributeValue, FileRecord, FileReference, Run};
+
+mod attribute;
+pub use attribute::{FileName, FileTime, StandardInformation};
+
+mod lznt1;
+
+mod stream;
+pub use stream::DataStream;
+
+mod index;
+pub use index::IndexEntry;
+
+mod file;
+pub use file::File;
+
+mod fs;
+pub use fs::{read_volume_label, DirEntry, NtfsFileSystem};
+
+mod deleted;
+pub use deleted::{DeletedFiles, ORPHAN_FILES};
+
+mod usn;
+pub use usn::{UsnJournal, UsnRecord};
+
+mod logfile;
+pub use logfile::{LogClient, LogFile, LogRecord, RestartArea};
+
+mod security;
+pub use security::{Ace, Acl, SecurityDescriptor, Sid};
+
+pub const ATTR_STANDARD_INFORMATION: u32 = 0x10;
+pub const ATTR_ATTRIBUTE_LIST: u32 = 0x20;
+pub const ATTR_FILE_NAME: u32 = 0x30;
+pub const ATTR_OBJECT_ID: u32 = 0x40;
+pub const ATTR_SECURITY_DESCRIPTOR: u32 = 0x50;
+pub const ATTR_VOLUME_NAME: u32 = 0x60;
+pub const ATTR_VOLUME_INFORMATION: u32 = 0x70;
+pub const ATTR_DATA: u32 = 0x80;
+pub const ATTR_INDEX_ROOT: u32 = 0x90;
+pub const ATTR_INDEX_ALLOCATION: u32 = 0xA0;
+pub const ATTR_BITMAP: u32 = 0xB0;
+pub const ATTR_REPARSE_POINT: u32 = 0xC0;
+pub const ATTR_LOGGED_UTILITY_STREAM: u32 = 0x100;
+
+/// Well-known MFT records
+pub const RECORD_MFT: u64 = 0;
+pub const RECORD_MFT_MIRROR: u64 = 1;
+pub const RECORD_LOG_FILE: u64 = 2;
+pub const RECORD_VOLUME: u64 = 3;
+pub const RECORD_ROOT: u64 = 5;
+pub const RECORD_BITMAP: u64 = 6;
+pub const RECORD_SECURE: u64 = 9;
+pub const RECORD_UPCASE: u64 = 10;
+pub const RECORD_EXTEND: u64 = 11;
+
+/// Directory index name
+const I30: &str = "$I30";
+
+fn utf16_string(bytes: &[u8]) -> String {
+    let chars = bytes.chunks_exact(2).map(|c| le_u16(c, 0));
+    core::char::decode_utf16(chars)
+        .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+        .collect()
+}
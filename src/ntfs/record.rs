@@ -0,0 +1,323 @@
+use super::*;
+
+const FILE_SIGNATURE: &[u8; 4] = b"FILE";
+/// Update sequence array protects every 512 bytes regardless of the sector size
+const FIXUP_STRIDE: usize = 512;
+const END_MARKER: u32 = 0xFFFF_FFFF;
+
+const RECORD_IN_USE: u16 = 0x0001;
+const RECORD_DIRECTORY: u16 = 0x0002;
+
+/// MFT record number (48 bits) and sequence number (16 bits)
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct FileReference(pub u64);
+
+impl FileReference {
+    pub fn new(record: u64, sequence: u16) -> Self {
+        Self((record & 0xFFFF_FFFF_FFFF) | (sequence as u64) << 48)
+    }
+
+    pub fn record(self) -> u64 {
+        self.0 & 0xFFFF_FFFF_FFFF
+    }
+
+    pub fn sequence(self) -> u16 {
+        (self.0 >> 48) as u16
+    }
+}
+
+/// Restores the last two bytes of every 512 byte block, `id` is used for errors only
+pub(crate) fn apply_fixups(data: &mut [u8], id: u64) -> Result<()> {
+    let usa_offset = le_u16(data, 4) as usize;
+    let usa_count = le_u16(data, 6) as usize;
+    if usa_count == 0 || usa_offset + usa_count * 2 > data.len() || (usa_count - 1) * FIXUP_STRIDE > data.len() {
+        return Err(Error::from(NtfsError::InvalidFixup(id)));
+    }
+
+    let usn = [data[usa_offset], data[usa_offset + 1]];
+    for i in 1..usa_count {
+        let pos = i * FIXUP_STRIDE - 2;
+        if data[pos..pos + 2] != usn {
+            return Err(Error::from(NtfsError::InvalidFixup(id)));
+        }
+
+        let fixup = [data[usa_offset + i * 2], data[usa_offset + i * 2 + 1]];
+        data[pos..pos + 2].copy_from_slice(&fixup);
+    }
+
+    Ok(())
+}
+
+/// Contiguous part of a non-resident attribute
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Run {
+    pub vcn: u64,
+    pub length: u64,
+    /// `None` for sparse runs
+    pub lcn: Option<u64>,
+}
+
+/// Decodes mapping pairs, `id` is used for errors only
+pub(crate) fn decode_runs(bytes: &[u8], start_vcn: u64, id: u64) -> Result<Vec<Run>> {
+    let invalid = || Error::from(NtfsError::InvalidRunList(id));
+
+    let mut runs = Vec::new();
+    let mut vcn = start_vcn;
+    let mut lcn = 0_i64;
+    let mut pos = 0;
+    while let Some(&header) = bytes.get(pos) {
+        if header == 0 {
+            break;
+        }
+
+        let length_size = (header & 0x0F) as usize;
+        let offset_size = (header >> 4) as usize;
+        let field = bytes.get(pos + 1..pos + 1 + length_size + offset_size).ok_or_else(invalid)?;
+        if length_size == 0 || length_size > 8 || offset_size > 8 {
+            return Err(invalid());
+        }
+
+        let mut length = [0_u8; 8];
+        length[..length_size].copy_from_slice(&field[..length_size]);
+        let length = u64::from_le_bytes(length);
+
+        let run_lcn = if offset_size == 0 {
+            None
+        } else {
+            // sign extended delta from the previous run
+            let delta = &field[length_size..];
+            let fill = if delta[offset_size - 1] & 0x80 != 0 { 0xFF } else { 0 };
+            let mut raw = [fill; 8];
+            raw[..offset_size].copy_from_slice(delta);
+            lcn = lcn
+                .checked_add(i64::from_le_bytes(raw))
+                .filter(|&lcn| lcn >= 0)
+                .ok_or_else(invalid)?;
+            Some(lcn as u64)
+        };
+
+        runs.push(Run { vcn, length, lcn: run_lcn });
+        vcn = vcn.checked_add(length).ok_or_else(invalid)?;
+        pos += 1 + length_size + offset_size;
+    }
+
+    Ok(runs)
+}
+
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum AttributeValue {
+    Resident(Vec<u8>),
+    NonResident {
+        lowest_vcn: u64,
+        highest_vcn: u64,
+        runs: Vec<Run>,
+        /// log2 of clusters per compression unit, 0 if not compressed
+        compression_unit: u8,
+        allocated_size: u64,
+        data_size: u64,
+        initialized_size: u64,
+    },
+}
+
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Attribute {
+    pub kind: u32,
+    pub name: String,
+    pub flags: u16,
+    pub id: u16,
+    pub value: AttributeValue,
+}
+
+impl Attribute {
+    pub const COMPRESSED: u16 = 0x0001;
+    pub const ENCRYPTED: u16 = 0x4000;
+    pub const SPARSE: u16 = 0x8000;
+
+    pub fn is_resident(&self) -> bool {
+        matches!(self.value, AttributeValue::Resident(_))
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & Self::COMPRESSED != 0
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & Self::ENCRYPTED != 0
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        self.flags & Self::SPARSE != 0
+    }
+
+    /// Logical size of the value
+    pub fn size(&self) -> u64 {
+        match &self.value {
+            AttributeValue::Resident(data) => data.len() as u64,
+            AttributeValue::NonResident { data_size, .. } => *data_size,
+        }
+    }
+
+    fn parse(bytes: &[u8], record: u64) -> Result<Self> {
+        let invalid = || Error::from(NtfsError::InvalidAttribute(record));
+
+        let name_length = bytes[9] as usize;
+        let name_offset = le_u16(bytes, 10) as usize;
+        let name = bytes.get(name_offset..name_offset + name_length * 2).ok_or_else(invalid)?;
+
+        let value = if bytes[8] == 0 {
+            let length = le_u32(bytes, 16) as usize;
+            let offset = le_u16(bytes, 20) as usize;
+            AttributeValue::Resident(bytes.get(offset..offset + length).ok_or_else(invalid)?.to_vec())
+        } else {
+            if bytes.len() < 64 {
+                return Err(invalid());
+            }
+
+            let lowest_vcn = le_u64(bytes, 16);
+            let runs_offset = le_u16(bytes, 32) as usize;
+            AttributeValue::NonResident {
+                lowest_vcn,
+                highest_vcn: le_u64(bytes, 24),
+                runs: decode_runs(bytes.get(runs_offset..).ok_or_else(invalid)?, lowest_vcn, record)?,
+                compression_unit: bytes[34],
+                allocated_size: le_u64(bytes, 40),
+                data_size: le_u64(bytes, 48),
+                initialized_size: le_u64(bytes, 56),
+            }
+        };
+
+        Ok(Self {
+            kind: le_u32(bytes, 0),
+            name: utf16_string(name),
+            flags: le_u16(bytes, 12),
+            id: le_u16(bytes, 14),
+            value,
+        })
+    }
+}
+
+/// Single MFT record, attributes of extension records are not included
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct FileRecord {
+    pub number: u64,
+    pub sequence: u16,
+    pub link_count: u16,
+    pub flags: u16,
+    pub lsn: u64,
+    /// Set for extension records
+    pub base_record: Option<FileReference>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl FileRecord {
+    /// Applies the fixups in place and parses the record
+    pub fn parse(data: &mut [u8], number: u64) -> Result<Self> {
+        if data.len() < 48 || &data[..4] != FILE_SIGNATURE {
+            return Err(Error::from(NtfsError::InvalidRecord(number)));
+        }
+        apply_fixups(data, number)?;
+
+        let used = core::cmp::min(le_u32(data, 24) as usize, data.len());
+        let base_record = match le_u64(data, 32) {
+            0 => None,
+            base => Some(FileReference(base)),
+        };
+
+        let mut attributes = Vec::new();
+        let mut pos = le_u16(data, 20) as usize;
+        while pos + 8 <= used {
+            if le_u32(data, pos) == END_MARKER {
+                break;
+            }
+
+            let length = le_u32(data, pos + 4) as usize;
+            if length < 24 || pos + length > used {
+                return Err(Error::from(NtfsError::InvalidAttribute(number)));
+            }
+
+            attributes.push(Attribute::parse(&data[pos..pos + length], number)?);
+            pos += length;
+        }
+
+        Ok(Self {
+            number,
+            sequence: le_u16(data, 16),
+            link_count: le_u16(data, 18),
+            flags: le_u16(data, 22),
+            lsn: le_u64(data, 8),
+            base_record,
+            attributes,
+        })
+    }
+
+    pub fn reference(&self) -> FileReference {
+        FileReference::new(self.number, self.sequence)
+    }
+
+    pub fn is_in_use(&self) -> bool {
+        self.flags & RECORD_IN_USE != 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.flags & RECORD_DIRECTORY != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_runs_test() {
+        // 0x18 clusters at 0x5634, 0x10 sparse, 0x20 at 0x5634 - 0x34
+        let bytes = [0x21, 0x18, 0x34, 0x56, 0x01, 0x10, 0x11, 0x20, 0xCC, 0x00];
+        let runs = decode_runs(&bytes, 0, 0).unwrap();
+        assert_eq!(
+            vec![
+                Run {
+                    vcn: 0,
+                    length: 0x18,
+                    lcn: Some(0x5634)
+                },
+                Run {
+                    vcn: 0x18,
+                    length: 0x10,
+                    lcn: None
+                },
+                Run {
+                    vcn: 0x28,
+                    length: 0x20,
+                    lcn: Some(0x5600)
+                },
+            ],
+            runs
+        );
+
+        assert!(decode_runs(&[0x21, 0x18, 0x34], 0, 0).is_err());
+        assert!(decode_runs(&[0x11, 0x01, 0x80], 0, 0).is_err()); // negative lcn
+    }
+
+    #[test]
+    fn fixups_test() {
+        let mut data = vec![0_u8; 1024];
+        data[4..6].copy_from_slice(&0x30_u16.to_le_bytes());
+        data[6..8].copy_from_slice(&3_u16.to_le_bytes());
+        data[0x30..0x36].copy_from_slice(&[0x07, 0x00, 0xAA, 0xBB, 0xCC, 0xDD]);
+        data[510..512].copy_from_slice(&[0x07, 0x00]);
+        data[1022..1024].copy_from_slice(&[0x07, 0x00]);
+
+        apply_fixups(&mut data, 0).unwrap();
+        assert_eq!([0xAA, 0xBB], data[510..512]);
+        assert_eq!([0xCC, 0xDD], data[1022..1024]);
+
+        // torn write
+        data[510..512].copy_from_slice(&[0x08, 0x00]);
+        data[1022..1024].copy_from_slice(&[0x07, 0x00]);
+        assert!(apply_fixups(&mut data, 0).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use super::*;
+use core::cmp::Ordering;
+
+/// `$Secure` index of the descriptors by security id
+const SII: &str = "$SII";
+/// `$Secure` stream with the descriptors
+const SDS: &str = "$SDS";
+/// `$SDS` entry header: hash, security id, offset, length
+const SDS_HEADER_SIZE: usize = 20;
+
+/// Security identifier
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Sid {
+    pub revision: u8,
+    /// 48 bit identifier authority
+    pub authority: u64,
+    pub sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let count = *bytes.get(1)? as usize;
+        let bytes = bytes.get(..8 + count * 4)?;
+        let authority = bytes[2..8].iter().fold(0_u64, |value, &b| (value << 8) | b as u64);
+
+        Some(Self {
+            revision: bytes[0],
+            authority,
+            sub_authorities: bytes[8..].chunks_exact(4).map(|c| le_u32(c, 0)).collect(),
+        })
+    }
+}
+
+/// `S-1-5-32-544` form
+impl core::fmt::Display for Sid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.authority >> 32 == 0 {
+            write!(f, "S-{}-{}", self.revision, self.authority)?;
+        } else {
+            write!(f, "S-{}-0x{:012X}", self.revision, self.authority)?;
+        }
+
+        for sub_authority in &self.sub_authorities {
+            write!(f, "-{}", sub_authority)?;
+        }
+        Ok(())
+    }
+}
+
+/// Access control entry, object ACE GUIDs are skipped
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Ace {
+    /// `ACCESS_*`, `SYSTEM_*`
+    pub kind: u8,
+    /// `*_ACE` flags
+    pub flags: u8,
+    pub mask: u32,
+    /// `None` for unknown ACE types
+    pub sid: Option<Sid>,
+}
+
+impl Ace {
+    pub const ACCESS_ALLOWED: u8 = 0x00;
+    pub const ACCESS_DENIED: u8 = 0x01;
+    pub const SYSTEM_AUDIT: u8 = 0x02;
+    pub const SYSTEM_ALARM: u8 = 0x03;
+    pub const ACCESS_ALLOWED_OBJECT: u8 = 0x05;
+    pub const ACCESS_DENIED_OBJECT: u8 = 0x06;
+    pub const SYSTEM_AUDIT_OBJECT: u8 = 0x07;
+    pub const SYSTEM_ALARM_OBJECT: u8 = 0x08;
+
+    pub const OBJECT_INHERIT_ACE: u8 = 0x01;
+    pub const CONTAINER_INHERIT_ACE: u8 = 0x02;
+    pub const NO_PROPAGATE_INHERIT_ACE: u8 = 0x04;
+    pub const INHERIT_ONLY_ACE: u8 = 0x08;
+    pub const INHERITED_ACE: u8 = 0x10;
+
+    /// Object type GUIDs present flags of object ACEs
+    const OBJECT_TYPE_PRESENT: u32 = 0x1;
+    const INHERITED_OBJECT_TYPE_PRESENT: u32 = 0x2;
+
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let kind = bytes[0];
+        let sid_offset = match kind {
+            Self::ACCESS_ALLOWED_OBJECT..=Self::SYSTEM_ALARM_OBJECT => {
+                let flags = le_u32(bytes.get(..12)?, 8);
+                let mut offset = 12;
+                if flags & Self::OBJECT_TYPE_PRESENT != 0 {
+                    offset += 16;
+                }
+                if flags & Self::INHERITED_OBJECT_TYPE_PRESENT != 0 {
+                    offset += 16;
+                }
+                Some(offset)
+            }
+            // basic and callback ACEs
+            0x00..=0x04 | 0x09..=0x11 => Some(8),
+            _ => None,
+        };
+
+        Some(Self {
+            kind,
+            flags: bytes[1],
+            mask: le_u32(bytes.get(..8)?, 4),
+            sid: sid_offset.and_then(|offset| Sid::parse(bytes.get(offset..)?)),
+        })
+    }
+}
+
+/// Access control list
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Acl {
+    pub revision: u8,
+    pub aces: Vec<Ace>,
+}
+
+impl Acl {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let header = bytes.get(..8)?;
+        let bytes = bytes.get(..le_u16(header, 2) as usize)?;
+
+        let mut aces = Vec::new();
+        let mut pos = 8;
+        for _ in 0..le_u16(header, 4) {
+            let ace = bytes.get(pos..pos + 4)?;
+            let size = le_u16(ace, 2) as usize;
+            aces.push(Ace::parse(bytes.get(pos..pos + size).filter(|_| size >= 8)?)?);
+            pos += size;
+        }
+
+        Some(Self { revision: header[0], aces })
+    }
+}
+
+/// Self-relative security descriptor
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct SecurityDescriptor {
+    pub revision: u8,
+    /// `SE_*` flags
+    pub control: u16,
+    pub owner: Option<Sid>,
+    pub group: Option<Sid>,
+    /// Absent and empty lists differ: no DACL grants everything, an empty one nothing
+    pub sacl: Option<Acl>,
+    pub dacl: Option<Acl>,
+}
+
+impl SecurityDescriptor {
+    pub const SE_OWNER_DEFAULTED: u16 = 0x0001;
+    pub const SE_GROUP_DEFAULTED: u16 = 0x0002;
+    pub const SE_DACL_PRESENT: u16 = 0x0004;
+    pub const SE_DACL_DEFAULTED: u16 = 0x0008;
+    pub const SE_SACL_PRESENT: u16 = 0x0010;
+    pub const SE_SACL_DEFAULTED: u16 = 0x0020;
+    pub const SE_DACL_PROTECTED: u16 = 0x1000;
+    pub const SE_SACL_PROTECTED: u16 = 0x2000;
+    pub const SE_SELF_RELATIVE: u16 = 0x8000;
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let header = bytes.get(..20)?;
+        let control = le_u16(header, 2);
+
+        // zero offset means the part is absent
+        let part = |pos: usize| match le_u32(header, pos) as usize {
+            0 => Some(None),
+            offset => bytes.get(offset..).map(Some),
+        };
+        let sid = |pos: usize| match part(pos)? {
+            Some(bytes) => Sid::parse(bytes).map(Some),
+            None => Some(None),
+        };
+        let acl = |pos: usize, present: u16| match part(pos)? {
+            Some(bytes) if control & present != 0 => Acl::parse(bytes).map(Some),
+            _ => Some(None),
+        };
+
+        Some(Self {
+            revision: header[0],
+            control,
+            owner: sid(4)?,
+            group: sid(8)?,
+            sacl: acl(12, Self::SE_SACL_PRESENT)?,
+            dacl: acl(16, Self::SE_DACL_PRESENT)?,
+        })
+    }
+}
+
+impl<R: ReadAt> NtfsFileSystem<R> {
+    /// The descriptor of the file: its own `$SECURITY_DESCRIPTOR` (NTFS 1.x)
+    /// or the shared one from `$Secure` referenced by `$STANDARD_INFORMATION`
+    pub fn security_descriptor(&self, file: &File<'_, R>) -> Result<Option<SecurityDescriptor>> {
+        if let Some(attribute) = file.attribute(ATTR_SECURITY_DESCRIPTOR, "") {
+            let mut bytes = vec![0_u8; attribute.size() as usize];
+            self.read_attribute_exact(attribute, 0, &mut bytes)?;
+            return match SecurityDescriptor::parse(&bytes) {
+                Some(descriptor) => Ok(Some(descriptor)),
+                None => Err(Error::from(NtfsError::InvalidAttribute(file.number()))),
+            };
+        }
+
+        match file.standard_information().and_then(|info| info.security_id) {
+            Some(id) if id != 0 => self.security_descriptor_by_id(id),
+            _ => Ok(None),
+        }
+    }
+
+    /// Looks the descriptor up in `$Secure:$SII` and reads it from `$Secure:$SDS`
+    pub fn security_descriptor_by_id(&self, id: u32) -> Result<Option<SecurityDescriptor>> {
+        let invalid = || Error::from(NtfsError::InvalidSecurityDescriptor(id));
+
+        let secure = self.file(RECORD_SECURE)?;
+        let entry = self.find_index_entry(&secure, SII, |key| match key.get(..4) {
+            Some(key) => id.cmp(&le_u32(key, 0)),
+            None => Ordering::Greater,
+        })?;
+        let header = match entry {
+            Some(entry) if entry.data.len() >= SDS_HEADER_SIZE => entry.data,
+            Some(_) => return Err(invalid()),
+            None => return Ok(None),
+        };
+
+        let offset = le_u64(&header, 8);
+        let length = le_u32(&header, 16) as usize;
+        if length < SDS_HEADER_SIZE {
+            return Err(invalid());
+        }
+
+        let sds = secure
+            .attribute(ATTR_DATA, SDS)
+            .ok_or_else(|| Error::from(NtfsError::StreamNotFound(SDS.to_string())))?;
+        let mut bytes = vec![0_u8; length];
+        self.read_attribute_exact(sds, offset, &mut bytes)?;
+        if le_u32(&bytes, 4) != id {
+            return Err(invalid());
+        }
+
+        SecurityDescriptor::parse(&bytes[SDS_HEADER_SIZE..]).map(Some).ok_or_else(invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sid(authority: u8, sub_authorities: &[u32]) -> Vec<u8> {
+        let mut bytes = vec![1, sub_authorities.len() as u8, 0, 0, 0, 0, 0, authority];
+        for sub_authority in sub_authorities {
+            bytes.extend_from_slice(&sub_authority.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn security_descriptor_test() {
+        let everyone = sid(1, &[0]);
+        let mut ace = vec![Ace::ACCESS_ALLOWED, Ace::INHERITED_ACE, 0, 0];
+        ace.extend_from_slice(&0x001F_01FF_u32.to_le_bytes());
+        ace.extend_from_slice(&everyone);
+        let ace_size = ace.len() as u16;
+        ace[2..4].copy_from_slice(&ace_size.to_le_bytes());
+
+        let mut acl = vec![2, 0, 0, 0, 1, 0, 0, 0];
+        acl.extend_from_slice(&ace);
+        let acl_size = acl.len() as u16;
+        acl[2..4].copy_from_slice(&acl_size.to_le_bytes());
+
+        let owner = sid(5, &[32, 544]);
+        let group = sid(5, &[18]);
+        let control = SecurityDescriptor::SE_SELF_RELATIVE | SecurityDescriptor::SE_DACL_PRESENT;
+        let mut bytes = vec![0_u8; 20];
+        bytes[0] = 1;
+        bytes[2..4].copy_from_slice(&control.to_le_bytes());
+        bytes[4..8].copy_from_slice(&20_u32.to_le_bytes());
+        bytes[8..12].copy_from_slice(&((20 + owner.len()) as u32).to_le_bytes());
+        bytes[16..20].copy_from_slice(&((20 + owner.len() + group.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(&owner);
+        bytes.extend_from_slice(&group);
+        bytes.extend_from_slice(&acl);
+
+        let descriptor = SecurityDescriptor::parse(&bytes).unwrap();
+        assert_eq!("S-1-5-32-544", descriptor.owner.unwrap().to_string());
+        assert_eq!("S-1-5-18", descriptor.group.unwrap().to_string());
+        assert!(descriptor.sacl.is_none());
+
+        let dacl = descriptor.dacl.unwrap();
+        assert_eq!(1, dacl.aces.len());
+        assert_eq!(Ace::ACCESS_ALLOWED, dacl.aces[0].kind);
+        assert_eq!(Ace::INHERITED_ACE, dacl.aces[0].flags);
+        assert_eq!(0x001F_01FF, dacl.aces[0].mask);
+        assert_eq!("S-1-1-0", dacl.aces[0].sid.as_ref().unwrap().to_string());
+
+        // DACL beyond the descriptor
+        bytes.truncate(bytes.len() - 4);
+        assert!(SecurityDescriptor::parse(&bytes).is_none());
+    }
+}
@@ -0,0 +1,148 @@
+use super::*;
+
+/// Reads the attribute value, sparse runs and data beyond the initialized size read as zeros
+pub(crate) fn read_value(device: &impl ReadAt, cluster_size: u64, attribute: &Attribute, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+    let (runs, compression_unit, data_size, initialized_size) = match &attribute.value {
+        AttributeValue::Resident(data) => {
+            let len = match math::bound_to(data.len() as u64, offset, buffer.len()) {
+                Some(len) => len,
+                None => return Err(Error::ReadBeyondEOD),
+            };
+            buffer[..len].copy_from_slice(&data[offset as usize..offset as usize + len]);
+            return Ok(len);
+        }
+        AttributeValue::NonResident {
+            runs,
+            compression_unit,
+            data_size,
+            initialized_size,
+            ..
+        } => (runs, *compression_unit, *data_size, *initialized_size),
+    };
+
+    let len = match math::bound_to(data_size, offset, buffer.len()) {
+        Some(0) => return Ok(0),
+        Some(len) => len,
+        None => return Err(Error::ReadBeyondEOD),
+    };
+
+    if offset >= initialized_size {
+        buffer[..len].iter_mut().for_each(|b| *b = 0);
+        return Ok(len);
+    }
+    let len = core::cmp::min(len as u64, initialized_size - offset) as usize;
+
+    if attribute.is_compressed() && compression_unit != 0 {
+        return read_compressed(device, cluster_size, runs, compression_unit, offset, &mut buffer[..len]);
+    }
+
+    read_runs(device, cluster_size, runs, offset, &mut buffer[..len])
+}
+
+/// Finds the run containing the cluster
+fn find_run(runs: &[Run], vcn: u64) -> Result<&Run> {
+    runs.iter()
+        .find(|run| vcn >= run.vcn && vcn - run.vcn < run.length)
+        .ok_or(Error::UnexpectedEOD)
+}
+
+/// Reads a part of a single run
+fn read_runs(device: &impl ReadAt, cluster_size: u64, runs: &[Run], offset: u64, buffer: &mut [u8]) -> Result<usize> {
+    let vcn = offset / cluster_size;
+    let run = find_run(runs, vcn)?;
+
+    let position = (vcn - run.vcn) * cluster_size + offset % cluster_size;
+    let len = core::cmp::min(buffer.len() as u64, run.length * cluster_size - position) as usize;
+    match run.lcn {
+        Some(lcn) => device.read_exact_at(lcn * cluster_size + position, &mut buffer[..len])?,
+        None => buffer[..len].iter_mut().for_each(|b| *b = 0),
+    }
+
+    Ok(len)
+}
+
+/// Reads a part of a single compression unit
+fn read_compressed(
+    device: &impl ReadAt,
+    cluster_size: u64,
+    runs: &[Run],
+    compression_unit: u8,
+    offset: u64,
+    buffer: &mut [u8],
+) -> Result<usize> {
+    let unit_clusters = 1_u64 << compression_unit;
+    let unit_size = unit_clusters * cluster_size;
+    let unit_vcn = offset / unit_size * unit_clusters;
+    let offset_in_unit = (offset % unit_size) as usize;
+    let len = core::cmp::min(buffer.len(), unit_size as usize - offset_in_unit);
+
+    // compressed units are followed by sparse clusters, a fully allocated unit is stored as is
+    let mut allocated = 0;
+    while allocated < unit_clusters {
+        match find_run(runs, unit_vcn + allocated) {
+            Ok(Run { lcn: Some(_), .. }) => allocated += 1,
+            _ => break,
+        }
+    }
+
+    if allocated == unit_clusters {
+        return read_runs(device, cluster_size, runs, offset, &mut buffer[..len]);
+    }
+    if allocated == 0 {
+        buffer[..len].iter_mut().for_each(|b| *b = 0);
+        return Ok(len);
+    }
+
+    let mut compressed = vec![0_u8; (allocated * cluster_size) as usize];
+    let mut done = 0;
+    while done < compressed.len() {
+        done += read_runs(
+            device,
+            cluster_size,
+            runs,
+            unit_vcn * cluster_size + done as u64,
+            &mut compressed[done..],
+        )?;
+    }
+
+    let mut data = Vec::with_capacity(unit_size as usize);
+    lznt1::decompress(&compressed, &mut data, unit_size as usize)?;
+    data.resize(unit_size as usize, 0);
+    buffer[..len].copy_from_slice(&data[offset_in_unit..offset_in_unit + len]);
+
+    Ok(len)
+}
+
+/// Unnamed or named data stream (or any other attribute value) of a file
+pub struct DataStream<'f, R: ReadAt> {
+    fs: &'f NtfsFileSystem<R>,
+    attribute: Attribute,
+}
+
+impl<'f, R: ReadAt> DataStream<'f, R> {
+    pub(crate) fn new(fs: &'f NtfsFileSystem<R>, attribute: Attribute) -> Self {
+        Self { fs, attribute }
+    }
+
+    pub fn attribute(&self) -> &Attribute {
+        &self.attribute
+    }
+
+    pub fn size(&self) -> u64 {
+        self.attribute.size()
+    }
+
+    /// Reads the whole value
+    pub fn read_all(&self) -> Result<Vec<u8>> {
+        let mut data = vec![0_u8; self.size() as usize];
+        self.read_exact_at(0, &mut data)?;
+        Ok(data)
+    }
+}
+
+impl<'f, R: ReadAt> ReadAt for DataStream<'f, R> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let cluster_size = self.fs.boot_sector().cluster_size() as u64;
+        read_value(self.fs.device(), cluster_size, &self.attribute, offset, buffer)
+    }
+}
@@ -0,0 +1,219 @@
+use super::*;
+
+/// USN records never cross a page boundary, the rest of a page is zero filled
+const USN_PAGE_SIZE: u64 = 0x1000;
+
+/// `$Extend\$UsnJrnl:$J` change record, versions 2 and 3
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct UsnRecord {
+    pub major_version: u16,
+    pub minor_version: u16,
+    /// Version 3 references are 128 bit, NTFS uses the low 64 bits only
+    pub reference: FileReference,
+    pub parent: FileReference,
+    pub usn: u64,
+    pub timestamp: FileTime,
+    /// `REASON_*` flags
+    pub reason: u32,
+    pub source_info: u32,
+    pub security_id: u32,
+    /// `FILE_ATTRIBUTE_*` flags
+    pub file_attributes: u32,
+    pub name: String,
+}
+
+impl UsnRecord {
+    pub const REASON_DATA_OVERWRITE: u32 = 0x0000_0001;
+    pub const REASON_DATA_EXTEND: u32 = 0x0000_0002;
+    pub const REASON_DATA_TRUNCATION: u32 = 0x0000_0004;
+    pub const REASON_NAMED_DATA_OVERWRITE: u32 = 0x0000_0010;
+    pub const REASON_NAMED_DATA_EXTEND: u32 = 0x0000_0020;
+    pub const REASON_NAMED_DATA_TRUNCATION: u32 = 0x0000_0040;
+    pub const REASON_FILE_CREATE: u32 = 0x0000_0100;
+    pub const REASON_FILE_DELETE: u32 = 0x0000_0200;
+    pub const REASON_EA_CHANGE: u32 = 0x0000_0400;
+    pub const REASON_SECURITY_CHANGE: u32 = 0x0000_0800;
+    pub const REASON_RENAME_OLD_NAME: u32 = 0x0000_1000;
+    pub const REASON_RENAME_NEW_NAME: u32 = 0x0000_2000;
+    pub const REASON_INDEXABLE_CHANGE: u32 = 0x0000_4000;
+    pub const REASON_BASIC_INFO_CHANGE: u32 = 0x0000_8000;
+    pub const REASON_HARD_LINK_CHANGE: u32 = 0x0001_0000;
+    pub const REASON_COMPRESSION_CHANGE: u32 = 0x0002_0000;
+    pub const REASON_ENCRYPTION_CHANGE: u32 = 0x0004_0000;
+    pub const REASON_OBJECT_ID_CHANGE: u32 = 0x0008_0000;
+    pub const REASON_REPARSE_POINT_CHANGE: u32 = 0x0010_0000;
+    pub const REASON_STREAM_CHANGE: u32 = 0x0020_0000;
+    pub const REASON_CLOSE: u32 = 0x8000_0000;
+
+    /// `bytes` starts with the record, `None` for unknown versions and damaged records
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None;
+        }
+
+        let length = le_u32(bytes, 0) as usize;
+        let major_version = le_u16(bytes, 4);
+        // offsets of the fields following the file references
+        let (reference, parent, pos) = match major_version {
+            2 => (le_u64(bytes, 8), le_u64(bytes, 16), 24),
+            3 => (le_u64(bytes, 8), le_u64(bytes, 24), 40),
+            _ => return None,
+        };
+
+        let record = bytes.get(..length).filter(|_| length & 7 == 0 && length >= pos + 36)?;
+        let name_length = le_u16(record, pos + 32) as usize;
+        let name_offset = le_u16(record, pos + 34) as usize;
+        let name = record.get(name_offset..name_offset + name_length)?;
+
+        Some(Self {
+            major_version,
+            minor_version: le_u16(record, 6),
+            reference: FileReference(reference),
+            parent: FileReference(parent),
+            usn: le_u64(record, pos),
+            timestamp: FileTime(le_u64(record, pos + 8)),
+            reason: le_u32(record, pos + 16),
+            source_info: le_u32(record, pos + 20),
+            security_id: le_u32(record, pos + 24),
+            file_attributes: le_u32(record, pos + 28),
+            name: utf16_string(name),
+        })
+    }
+}
+
+/// Iterator over the change journal records in the USN order.
+///
+/// The journal is sparse, purged ranges are skipped without reading.
+/// A damaged record is reported once and the rest of its page is skipped.
+pub struct UsnJournal<'f, R: ReadAt> {
+    stream: DataStream<'f, R>,
+    /// Allocated byte ranges of the stream
+    ranges: Vec<(u64, u64)>,
+    range: usize,
+    offset: u64,
+    page: Vec<u8>,
+    page_offset: Option<u64>,
+}
+
+impl<'f, R: ReadAt> UsnJournal<'f, R> {
+    fn new(stream: DataStream<'f, R>, cluster_size: u64) -> Self {
+        let size = stream.size();
+        let ranges = match &stream.attribute().value {
+            AttributeValue::Resident(data) => vec![(0, data.len() as u64)],
+            AttributeValue::NonResident { runs, .. } => runs
+                .iter()
+                .filter(|run| run.lcn.is_some())
+                .map(|run| (run.vcn * cluster_size, core::cmp::min((run.vcn + run.length) * cluster_size, size)))
+                .filter(|(start, end)| start < end)
+                .collect(),
+        };
+
+        Self {
+            stream,
+            ranges,
+            range: 0,
+            offset: 0,
+            page: Vec::new(),
+            page_offset: None,
+        }
+    }
+
+    fn load_page(&mut self, page_offset: u64) -> Result<()> {
+        let size = core::cmp::min(USN_PAGE_SIZE, self.stream.size() - page_offset);
+        self.page.resize(size as usize, 0);
+        self.page_offset = None;
+        self.stream.read_exact_at(page_offset, &mut self.page)?;
+        self.page_offset = Some(page_offset);
+        Ok(())
+    }
+}
+
+impl<'f, R: ReadAt> Iterator for UsnJournal<'f, R> {
+    type Item = Result<UsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let &(start, end) = self.ranges.get(self.range)?;
+            if self.offset >= end {
+                self.range += 1;
+                continue;
+            }
+            if self.offset < start {
+                self.offset = start;
+            }
+
+            let page_offset = self.offset & !(USN_PAGE_SIZE - 1);
+            let next_page = page_offset + USN_PAGE_SIZE;
+            if self.page_offset != Some(page_offset) {
+                if let Err(e) = self.load_page(page_offset) {
+                    self.offset = next_page;
+                    return Some(Err(e));
+                }
+            }
+
+            let bytes = &self.page[(self.offset - page_offset) as usize..];
+            if bytes.len() < 8 || le_u32(bytes, 0) == 0 {
+                self.offset = next_page; // unused tail of the page
+                continue;
+            }
+
+            return match UsnRecord::parse(bytes) {
+                Some(record) => {
+                    self.offset += le_u32(bytes, 0) as u64;
+                    Some(Ok(record))
+                }
+                None => {
+                    let error = NtfsError::InvalidUsnRecord(self.offset);
+                    self.offset = next_page;
+                    Some(Err(Error::from(error)))
+                }
+            };
+        }
+    }
+}
+
+impl<R: ReadAt> NtfsFileSystem<R> {
+    /// Opens `$Extend\$UsnJrnl:$J`, fails with `Error::NotFound` if the journal is not active
+    pub fn usn_journal(&self) -> Result<UsnJournal<'_, R>> {
+        let journal = self.open_path("$Extend/$UsnJrnl")?;
+        let stream = journal.data("$J")?;
+        Ok(UsnJournal::new(stream, self.boot_sector().cluster_size() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let name = [b'a', 0, b'.', 0, b'b', 0];
+        let mut bytes = vec![0_u8; 72];
+        bytes[0..4].copy_from_slice(&72_u32.to_le_bytes());
+        bytes[4..6].copy_from_slice(&2_u16.to_le_bytes());
+        bytes[8..16].copy_from_slice(&FileReference::new(40, 2).0.to_le_bytes());
+        bytes[16..24].copy_from_slice(&FileReference::new(5, 5).0.to_le_bytes());
+        bytes[24..32].copy_from_slice(&0x1234_u64.to_le_bytes());
+        bytes[40..44].copy_from_slice(&(UsnRecord::REASON_FILE_CREATE | UsnRecord::REASON_CLOSE).to_le_bytes());
+        bytes[56..58].copy_from_slice(&6_u16.to_le_bytes());
+        bytes[58..60].copy_from_slice(&60_u16.to_le_bytes());
+        bytes[60..66].copy_from_slice(&name);
+
+        let record = UsnRecord::parse(&bytes).unwrap();
+        assert_eq!(40, record.reference.record());
+        assert_eq!(2, record.reference.sequence());
+        assert_eq!(5, record.parent.record());
+        assert_eq!(0x1234, record.usn);
+        assert_eq!(UsnRecord::REASON_FILE_CREATE | UsnRecord::REASON_CLOSE, record.reason);
+        assert_eq!("a.b", record.name);
+
+        // name beyond the record
+        bytes[58..60].copy_from_slice(&68_u16.to_le_bytes());
+        assert!(UsnRecord::parse(&bytes).is_none());
+
+        // version 4 has no name, it only describes ranges
+        bytes[4..6].copy_from_slice(&4_u16.to_le_bytes());
+        assert!(UsnRecord::parse(&bytes).is_none());
+    }
+}
@@ -0,0 +1,53 @@
+/// How an image and its backing files are opened
+///
+/// A read-only image never touches its files: writes fail with [`Error::ReadOnly`](crate::Error::ReadOnly)
+/// and no footer or header is rewritten on flush or drop, so images on read-only media
+/// or evidence files can be inspected safely.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct OpenOptions {
+    pub read_only: bool,
+    /// Nobody else can open the backing files while the image is open
+    pub exclusive: bool,
+    /// The OS page cache is bypassed (`O_DIRECT`, `FILE_FLAG_NO_BUFFERING`).
+    /// Unaligned requests are bounced through an [`AlignedBuffer`](crate::AlignedBuffer) transparently.
+    pub direct: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::read_write()
+    }
+}
+
+impl OpenOptions {
+    pub const fn read_only() -> Self {
+        Self {
+            read_only: true,
+            exclusive: false,
+            direct: false,
+        }
+    }
+
+    pub const fn read_write() -> Self {
+        Self {
+            read_only: false,
+            exclusive: false,
+            direct: false,
+        }
+    }
+
+    /// Read-write access, the backing files are not shared
+    pub const fn exclusive() -> Self {
+        Self {
+            read_only: false,
+            exclusive: true,
+            direct: false,
+        }
+    }
+
+    /// Same options with direct I/O, for imaging jobs that would thrash the page cache
+    pub const fn direct(self) -> Self {
+        Self { direct: true, ..self }
+    }
+}
@@ -0,0 +1,391 @@
+use crate::prelude::*;
+use crate::sync::RwLock;
+use crate::vhd::{VhdError, VhdImage};
+use crate::{sizes, MemoryStream};
+
+/// The largest piece of the delta copied at once by [`commit`](OverlayDisk::commit)
+const COPY_CHUNK: u64 = sizes::MIB;
+
+#[derive(Default)]
+struct Delta {
+    /// Base disk sector to the position of its data in the delta stream
+    sectors: BTreeMap<u64, u64>,
+    /// End of the used part of the delta stream
+    end: u64,
+}
+
+/// Copy-on-write overlay over any disk
+///
+/// Reads come from the base disk unless the sectors were written, the written sectors are kept
+/// in the delta stream: memory by default, or a file for large changes. The base disk is never written
+/// until [`commit`](Self::commit). The delta index is kept in memory, a file-backed delta can't be reopened.
+pub struct OverlayDisk<Base: Disk, S: Stream = MemoryStream> {
+    base: Base,
+    delta: S,
+    capacity: u64,
+    sector_size: u64,
+    index: RwLock<Delta>,
+}
+
+impl<Base: Disk, S: Stream> ReadAt for OverlayDisk<Base, S> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = math::bound_to(self.capacity, offset, buffer.len()).ok_or(Error::ReadBeyondEOD)?;
+        let end = offset + len as u64;
+
+        let delta = self.index.read();
+        let mut pos = offset;
+        while pos < end {
+            let sector = pos / self.sector_size;
+            let buffer_pos = (pos - offset) as usize;
+            let n = match delta.sectors.get(&sector) {
+                Some(&data_pos) => {
+                    // the following written sectors are usually stored one after another
+                    let sector_start = sector * self.sector_size;
+                    let mut run_end = sector_start + self.sector_size;
+                    while run_end < end && delta.sectors.get(&(run_end / self.sector_size)) == Some(&(data_pos + run_end - sector_start)) {
+                        run_end += self.sector_size;
+                    }
+
+                    let n = (core::cmp::min(run_end, end) - pos) as usize;
+                    let in_sector = pos % self.sector_size;
+                    self.delta
+                        .read_exact_at(data_pos + in_sector, &mut buffer[buffer_pos..buffer_pos + n])?;
+                    n
+                }
+                None => {
+                    // up to the next written sector
+                    let next = match delta.sectors.range(sector..).next() {
+                        Some((&next, _)) => next * self.sector_size,
+                        None => end,
+                    };
+
+                    let n = (core::cmp::min(next, end) - pos) as usize;
+                    self.base.read_exact_at(pos, &mut buffer[buffer_pos..buffer_pos + n])?;
+                    n
+                }
+            };
+
+            pos += n as u64;
+        }
+
+        Ok(len)
+    }
+}
+
+impl<Base: Disk, S: Stream> WriteAt for OverlayDisk<Base, S> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let len = math::bound_to(self.capacity, offset, data.len()).ok_or(Error::WriteBeyondEOD)?;
+        let end = offset + len as u64;
+
+        let mut delta = self.index.write();
+        let mut pos = offset;
+        while pos < end {
+            let sector = pos / self.sector_size;
+            let in_sector = pos % self.sector_size;
+            let data_pos = (pos - offset) as usize;
+
+            if let Some(&sector_pos) = delta.sectors.get(&sector) {
+                let n = core::cmp::min(self.sector_size - in_sector, end - pos) as usize;
+                self.delta.write_all_at(sector_pos + in_sector, &data[data_pos..data_pos + n])?;
+                pos += n as u64;
+            } else if in_sector == 0 && end - pos >= self.sector_size {
+                // whole new sectors are appended at once
+                let mut count = 1;
+                while (sector + count + 1) * self.sector_size <= end && !delta.sectors.contains_key(&(sector + count)) {
+                    count += 1;
+                }
+
+                let n = (count * self.sector_size) as usize;
+                let sector_pos = delta.end;
+                self.delta.write_all_at(sector_pos, &data[data_pos..data_pos + n])?;
+                for i in 0..count {
+                    delta.sectors.insert(sector + i, sector_pos + i * self.sector_size);
+                }
+                delta.end += n as u64;
+                pos += n as u64;
+            } else {
+                // the rest of the new sector comes from the base
+                let n = core::cmp::min(self.sector_size - in_sector, end - pos) as usize;
+                let mut sector_data = unsafe { tools::alloc_buffer(self.sector_size as usize) };
+                self.base.read_exact_at(sector * self.sector_size, &mut sector_data)?;
+                let start = in_sector as usize;
+                sector_data[start..start + n].copy_from_slice(&data[data_pos..data_pos + n]);
+
+                let sector_pos = delta.end;
+                self.delta.write_all_at(sector_pos, &sector_data)?;
+                delta.sectors.insert(sector, sector_pos);
+                delta.end += self.sector_size;
+                pos += n as u64;
+            }
+        }
+
+        Ok(len)
+    }
+}
+
+/// Flushes the delta only, the base disk is untouched
+impl<Base: Disk, S: Stream> Flush for OverlayDisk<Base, S> {
+    fn flush(&self) -> Result<()> {
+        self.delta.flush()
+    }
+}
+
+/// Geometry and sector sizes are the ones of the base disk
+impl<Base: Disk, S: Stream> Disk for OverlayDisk<Base, S> {
+    fn geometry(&self) -> Result<Geometry> {
+        self.base.geometry()
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.capacity)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        self.base.physical_sector_size()
+    }
+
+    fn logical_sector_size(&self) -> Result<u32> {
+        self.base.logical_sector_size()
+    }
+
+    /// The whole written sectors of the range are dropped from the overlay, they read the base data again.
+    /// The base disk is untouched.
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        let first = math::ceil(offset, self.sector_size);
+        let end = (offset + len) / self.sector_size;
+        if first < end {
+            let mut delta = self.index.write();
+            let sectors: Vec<u64> = delta.sectors.range(first..end).map(|(&sector, _)| sector).collect();
+            for sector in sectors {
+                delta.sectors.remove(&sector);
+            }
+            if delta.sectors.is_empty() {
+                delta.end = 0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<Base: Disk> OverlayDisk<Base> {
+    /// The delta is kept in memory
+    pub fn new(base: Base) -> Result<Self> {
+        Self::with_delta(base, MemoryStream::new())
+    }
+}
+
+impl<Base: Disk, S: Stream> OverlayDisk<Base, S> {
+    /// The written sectors are stored in the `delta` stream, it should be empty
+    pub fn with_delta(base: Base, delta: S) -> Result<Self> {
+        Ok(Self {
+            capacity: base.capacity()?,
+            sector_size: base.logical_sector_size()? as u64,
+            base,
+            delta,
+            index: RwLock::default(),
+        })
+    }
+
+    pub fn base(&self) -> &Base {
+        &self.base
+    }
+
+    pub fn delta(&self) -> &S {
+        &self.delta
+    }
+
+    /// Number of the written sectors
+    pub fn changed_sectors(&self) -> u64 {
+        self.index.read().sectors.len() as u64
+    }
+
+    pub fn is_changed(&self) -> bool {
+        !self.index.read().sectors.is_empty()
+    }
+
+    /// Drops all the changes, the delta stream space is reused by the next writes
+    pub fn discard_changes(&self) {
+        let mut delta = self.index.write();
+        delta.sectors.clear();
+        delta.end = 0;
+    }
+
+    /// Writes the changes to the base disk and flushes it, the overlay is empty afterwards.
+    /// The changes are kept if the base can't be written, e.g. it is opened read-only.
+    pub fn commit(&self) -> Result<()> {
+        self.write_delta_to(&self.base)?;
+        self.base.flush()?;
+        self.discard_changes();
+        Ok(())
+    }
+
+    /// Writes the changed sectors to the same positions of the `target`, the overlay is kept as is
+    pub fn write_delta_to<T: WriteAt + ?Sized>(&self, target: &T) -> Result<()> {
+        let delta = self.index.read();
+        let mut sectors = delta.sectors.iter().peekable();
+        let mut buffer = Vec::new();
+        while let Some((&first, &first_pos)) = sectors.next() {
+            // sectors following each other both on the disk and in the delta are copied at once
+            let mut count = 1;
+            while count * self.sector_size < COPY_CHUNK
+                && sectors.peek() == Some(&(&(first + count), &(first_pos + count * self.sector_size)))
+            {
+                sectors.next();
+                count += 1;
+            }
+
+            buffer.resize((count * self.sector_size) as usize, 0);
+            self.delta.read_exact_at(first_pos, &mut buffer)?;
+            target.write_all_at(first * self.sector_size, &buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the base disk, the changes are dropped
+    pub fn into_base(self) -> Base {
+        self.base
+    }
+}
+
+impl<S: Stream> OverlayDisk<VhdImage, S> {
+    /// Saves the changes as a new differencing VHD, its parent is the base image file.
+    /// The base image is opened once more (read-only) as the parent of the new one, the overlay is kept as is.
+    pub fn save_as_differencing<P: Into<String>>(&self, path: P) -> Result<VhdImage> {
+        let parent_path = match self.base.backing_files().next() {
+            Some(parent_path) => parent_path,
+            None => return Err(Error::from(VhdError::NoBackingFile)),
+        };
+
+        let image = VhdImage::create_differencing(path.into(), parent_path)?;
+        self.write_delta_to(&image)?;
+        image.flush()?;
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::pattern;
+    use crate::MemoryDisk;
+
+    #[test]
+    fn overlay_io_test() {
+        let size = 64 * 1024;
+        let base = MemoryDisk::from_vec(pattern(size, 0));
+        let mut mirror = pattern(size, 0);
+        let disk = OverlayDisk::new(base).unwrap();
+        assert_eq!(size as u64, disk.capacity().unwrap());
+
+        let mut seed = 12345_u64;
+        for _ in 0..1000 {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            let offset = (seed >> 16) % size as u64;
+            let len = core::cmp::min((seed >> 40) as usize % 3000, size - offset as usize);
+            if seed & 1 == 0 {
+                let mut buffer = vec![0_u8; len];
+                assert_eq!(len, disk.read_at(offset, &mut buffer).unwrap());
+                assert_eq!(&mirror[offset as usize..offset as usize + len], buffer.as_slice());
+            } else {
+                let data: Vec<u8> = (0..len).map(|i| (seed as usize + i) as u8).collect();
+                assert_eq!(len, disk.write_at(offset, &data).unwrap());
+                mirror[offset as usize..offset as usize + len].copy_from_slice(&data);
+            }
+        }
+
+        // the base is untouched
+        assert!(disk.is_changed());
+        assert_eq!(pattern(size, 0), disk.base().to_vec());
+        match disk.write_at(size as u64 + 1, b"x") {
+            Err(Error::WriteBeyondEOD) => (),
+            _ => panic!("write beyond the end expected"),
+        }
+
+        disk.commit().unwrap();
+        assert!(!disk.is_changed());
+        assert_eq!(mirror, disk.base().to_vec());
+    }
+
+    #[test]
+    fn overlay_discard_test() {
+        let disk = OverlayDisk::new(MemoryDisk::from_vec(pattern(8192, 0))).unwrap();
+        disk.write_all_at(100, b"partial").unwrap();
+        disk.write_all_at(1024, &[0xEE; 1024]).unwrap();
+        assert_eq!(3, disk.changed_sectors());
+        assert_eq!(3 * sizes::SECTOR_U64, disk.delta().size().unwrap());
+
+        let mut buffer = vec![0_u8; 8];
+        disk.read_exact_at(1020, &mut buffer).unwrap();
+        assert_eq!(&pattern(1024, 0)[1020..], &buffer[..4]);
+        assert_eq!(&[0xEE; 4], &buffer[4..]);
+
+        disk.discard_changes();
+        assert_eq!(0, disk.changed_sectors());
+        disk.read_exact_at(100, &mut buffer).unwrap();
+        assert_eq!(&pattern(108, 0)[100..], buffer.as_slice());
+
+        // the delta space is reused
+        disk.write_all_at(4096, &[0xAA; 512]).unwrap();
+        assert_eq!(3 * sizes::SECTOR_U64, disk.delta().size().unwrap());
+        assert_eq!(pattern(8192, 0), disk.into_base().to_vec());
+    }
+
+    #[test]
+    fn overlay_range_discard_test() {
+        let disk = OverlayDisk::new(MemoryDisk::from_vec(pattern(8192, 0))).unwrap();
+        disk.write_all_at(0, &[0xEE; 2048]).unwrap();
+        assert_eq!(4, disk.changed_sectors());
+
+        // the partial sectors stay in the overlay
+        disk.discard(100, 1500).unwrap();
+        assert_eq!(2, disk.changed_sectors());
+        let mut buffer = vec![0_u8; 2048];
+        disk.read_exact_at(0, &mut buffer).unwrap();
+        assert!(buffer[..512].iter().all(|&b| b == 0xEE));
+        assert_eq!(&pattern(1536, 0)[512..], &buffer[512..1536]);
+        assert!(buffer[1536..].iter().all(|&b| b == 0xEE));
+        assert!(matches!(disk.discard(8000, 500), Err(Error::WriteBeyondEOD)));
+
+        disk.discard(0, 8192).unwrap();
+        assert!(!disk.is_changed());
+        assert_eq!(pattern(8192, 0), disk.base().to_vec());
+    }
+
+    #[test]
+    fn overlay_over_read_only_vhd_test() {
+        let stream = MemoryStream::new();
+        let base = VhdImage::create_dynamic_stream(stream.clone(), 4 * sizes::MIB).unwrap();
+        base.write_all_at(sizes::MIB, b"base").unwrap();
+        drop(base);
+
+        let base = VhdImage::open_stream_read_only(stream.clone()).unwrap();
+        let disk = OverlayDisk::new(base).unwrap();
+        disk.write_all_at(sizes::MIB + 2, b"se data").unwrap();
+        let mut buffer = vec![0_u8; 9];
+        disk.read_exact_at(sizes::MIB, &mut buffer).unwrap();
+        assert_eq!(b"base data", buffer.as_slice());
+
+        // the delta can be saved as a differencing image
+        let child_stream = MemoryStream::new();
+        let parent = VhdImage::open_stream_read_only(stream.clone()).unwrap();
+        let child = VhdImage::create_differencing_stream(child_stream.clone(), parent).unwrap();
+        disk.write_delta_to(&child).unwrap();
+        drop(child);
+
+        let parent = VhdImage::open_stream_read_only(stream).unwrap();
+        let child = VhdImage::open_differencing_stream(child_stream, parent).unwrap();
+        assert!(crate::vhd::VhdKind::Differencing == child.kind());
+        child.read_exact_at(sizes::MIB, &mut buffer).unwrap();
+        assert_eq!(b"base data", buffer.as_slice());
+
+        // the read-only base can't take the changes
+        assert!(matches!(disk.commit(), Err(Error::ReadOnly)));
+        assert!(disk.is_changed());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::prelude::*;
-use crate::{PartitionInfo, PartitionKind};
+use crate::{FsInfo, PartitionInfo, PartitionKind};
 
 pub struct Partition<'d, D: Disk + 'd> {
     disk: &'d D,
@@ -8,7 +8,60 @@ pub struct Partition<'d, D: Disk + 'd> {
 
 impl<'d, D: Disk + 'd> ReadAt for Partition<'d, D> {
     fn read_at(&self, offset: u64, data: &mut [u8]) -> Result<usize> {
-        self.disk.read_at(self.info.offset + offset, data)
+        match math::bound_to(self.info.length, offset, data.len()) {
+            Some(data_len) => self.disk.read_at(self.info.offset + offset, &mut data[..data_len]),
+            None => Err(Error::ReadBeyondEOD),
+        }
+    }
+}
+
+impl<'d, D: Disk + 'd> WriteAt for Partition<'d, D> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        match math::bound_to(self.info.length, offset, data.len()) {
+            Some(data_len) => self.disk.write_at(self.info.offset + offset, &data[..data_len]),
+            None => Err(Error::WriteBeyondEOD),
+        }
+    }
+}
+
+impl<'d, D: Disk + 'd> Flush for Partition<'d, D> {
+    fn flush(&self) -> Result<()> {
+        self.disk.flush()
+    }
+}
+
+/// Geometry and sector sizes are the ones of the underlying disk
+impl<'d, D: Disk + 'd> Disk for Partition<'d, D> {
+    fn geometry(&self) -> Result<Geometry> {
+        self.disk.geometry()
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.info.length)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        self.disk.physical_sector_size()
+    }
+
+    fn logical_sector_size(&self) -> Result<u32> {
+        self.disk.logical_sector_size()
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.info.length, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        self.disk.discard(self.info.offset + offset, len)
+    }
+
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.info.length, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        self.disk.write_zeroes(self.info.offset + offset, len)
     }
 }
 
@@ -31,4 +84,9 @@ impl<'d, D: Disk + 'd> Partition<'d, D> {
     pub fn kind(&self) -> &PartitionKind {
         &self.info.kind
     }
+
+    /// Detects the filesystem by its signature, the partition kind may be misleading
+    pub fn filesystem(&self) -> Option<FsInfo> {
+        crate::probe_filesystem(self)
+    }
 }
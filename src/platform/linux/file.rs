@@ -0,0 +1,373 @@
+use crate::aligned::{padded_cut, read_bounced, write_bounced, DirectWriteLock};
+use crate::{math, Flush, OpenOptions, ReadAt, Result, Stream, WriteAt};
+use std::os::unix::fs::{FileExt, FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+
+/// `O_DIRECT` I/O has to be aligned to the logical block size of the device, 4 KiB covers both 512 and 4K sectors
+const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct File {
+    file: Arc<std::fs::File>,
+    /// Opened with `O_DIRECT`, the requests are bounced to this alignment
+    direct_alignment: Option<usize>,
+    /// Shared by the clones
+    direct_lock: Arc<DirectWriteLock>,
+}
+
+impl ReadAt for File {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        match self.direct_alignment {
+            Some(align) => read_bounced(align, offset, buffer, |offset, buffer| self.read_raw(offset, buffer)),
+            None => self.read_raw(offset, buffer),
+        }
+    }
+}
+
+impl WriteAt for File {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        match self.direct_alignment {
+            Some(align) => self.write_direct(align, offset, data),
+            None => self.write_raw(offset, data),
+        }
+    }
+}
+
+impl Flush for File {
+    fn flush(&self) -> Result<()> {
+        Ok(self.file.sync_data()?)
+    }
+}
+
+impl Stream for File {
+    fn size(&self) -> Result<u64> {
+        File::size(self)
+    }
+
+    /// Punches a hole, a block device zeroes the whole logical sectors by `BLKZEROOUT`.
+    /// The file system may not support it and the zeroes are written then.
+    fn zero_range(&self, offset: u64, len: u64) -> Result<()> {
+        if is_block_device(&self.file)? {
+            return zero_block_range(&self.file, offset, len, |offset, len| crate::tools::write_zeroes(self, offset, len));
+        }
+        if !punch_hole(&self.file, offset, len)? {
+            crate::tools::write_zeroes(self, offset, len)?;
+        }
+
+        Ok(())
+    }
+
+    /// A block device discards the whole logical sectors by `BLKDISCARD`, a file is zeroed
+    fn discard_range(&self, offset: u64, len: u64) -> Result<()> {
+        if is_block_device(&self.file)? {
+            discard_block_range(&self.file, offset, len)
+        } else {
+            self.zero_range(offset, len)
+        }
+    }
+}
+
+/// `_IO(0x12, 119)` and `_IO(0x12, 127)`, the direction bits are those of `BLKSSZGET`, also an `_IO` request
+const BLKDISCARD: libc::Ioctl = libc::BLKSSZGET - 0x1268 + 0x1277;
+const BLKZEROOUT: libc::Ioctl = libc::BLKSSZGET - 0x1268 + 0x127F;
+
+pub(crate) fn is_block_device(file: &std::fs::File) -> Result<bool> {
+    Ok(file.metadata()?.file_type().is_block_device())
+}
+
+/// The part of the range made of whole logical sectors of the block device, `None` if there is no such part
+fn sector_range(file: &std::fs::File, offset: u64, len: u64) -> Result<Option<(u64, u64)>> {
+    let mut sector_size: libc::c_int = 0;
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::BLKSSZGET, &mut sector_size) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let sector_size = sector_size as u64;
+    let start = math::round_up(offset, sector_size);
+    let end = math::round_down(offset + len, sector_size);
+    Ok(Some((start, end)).filter(|_| start < end))
+}
+
+fn block_ioctl(file: &std::fs::File, request: libc::Ioctl, offset: u64, len: u64) -> std::io::Result<()> {
+    let range = [offset, len];
+    if unsafe { libc::ioctl(file.as_raw_fd(), request, range.as_ptr()) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Zeroes the whole logical sectors by `BLKZEROOUT`, the partial sectors at the edges are zeroed by `write_zeroes`
+pub(crate) fn zero_block_range(file: &std::fs::File, offset: u64, len: u64, write_zeroes: impl Fn(u64, u64) -> Result<()>) -> Result<()> {
+    match sector_range(file, offset, len)? {
+        Some((start, end)) => {
+            write_zeroes(offset, start - offset)?;
+            block_ioctl(file, BLKZEROOUT, start, end - start)?;
+            write_zeroes(end, offset + len - end)
+        }
+        None => write_zeroes(offset, len),
+    }
+}
+
+/// Discards the whole logical sectors by `BLKDISCARD`, nothing is done if the device has no discard support
+pub(crate) fn discard_block_range(file: &std::fs::File, offset: u64, len: u64) -> Result<()> {
+    if let Some((start, end)) = sector_range(file, offset, len)? {
+        match block_ioctl(file, BLKDISCARD, start, end - start) {
+            Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => (),
+            res => res?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `false` if the file system can not punch holes
+pub(crate) fn punch_hole(file: &std::fs::File, offset: u64, len: u64) -> Result<bool> {
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    if unsafe { libc::fallocate(file.as_raw_fd(), mode, offset as libc::off_t, len as libc::off_t) } == 0 {
+        return Ok(true);
+    }
+
+    match std::io::Error::last_os_error() {
+        e if e.raw_os_error() == Some(libc::EOPNOTSUPP) => Ok(false),
+        e => Err(e.into()),
+    }
+}
+
+/// Creates a new file of `size` bytes, fails if the file exists
+pub(crate) fn create_preallocated_std(path: &str, size: u64) -> Result<std::fs::File> {
+    let file = std::fs::OpenOptions::new().read(true).write(true).create_new(true).open(path)?;
+    if size != 0 && unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, size as libc::off_t) } != 0 {
+        // not supported by the file system, the file is sparse then
+        file.set_len(size)?;
+    }
+
+    Ok(file)
+}
+
+/// Opens an existing file: [`OpenOptions::direct`] adds `O_DIRECT`,
+/// [`OpenOptions::exclusive`] takes an advisory `flock` the other rdisk instances respect
+pub(crate) fn open_std(path: &str, options: &OpenOptions) -> Result<std::fs::File> {
+    let mut open = std::fs::OpenOptions::new();
+    open.read(true).write(!options.read_only);
+    if options.direct {
+        open.custom_flags(libc::O_DIRECT);
+    }
+
+    let file = open.open(path)?;
+    if options.exclusive && unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(file)
+}
+
+impl File {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::open_with(path, &OpenOptions::default())
+    }
+
+    pub fn open_with(path: &str, options: &OpenOptions) -> Result<Self> {
+        Ok(Self {
+            file: Arc::new(open_std(path, options)?),
+            direct_alignment: if options.direct { Some(DIRECT_IO_ALIGNMENT) } else { None },
+            direct_lock: Arc::default(),
+        })
+    }
+
+    /// Fails if the file exists
+    pub fn create_preallocated(path: &str, size: u64) -> Result<Self> {
+        Ok(Self::from_std(create_preallocated_std(path, size)?))
+    }
+
+    pub fn overwrite_or_create(path: &str) -> Result<(Self, bool)> {
+        let already_exists = std::path::Path::new(path).exists();
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        Ok((Self::from_std(file), already_exists))
+    }
+
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    /// Opened with [`OpenOptions::direct`](crate::OpenOptions::direct)
+    pub fn is_direct(&self) -> bool {
+        self.direct_alignment.is_some()
+    }
+
+    fn from_std(file: std::fs::File) -> Self {
+        Self {
+            file: Arc::new(file),
+            direct_alignment: None,
+            direct_lock: Arc::default(),
+        }
+    }
+
+    fn read_raw(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        Ok(FileExt::read_at(&*self.file, buffer, offset)?)
+    }
+
+    fn write_raw(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        Ok(FileExt::write_at(&*self.file, data, offset)?)
+    }
+
+    /// The padding of a bounced write past the end of the file is cut off, the file ends where a buffered write
+    /// would end it: the images keeping a footer at the end depend on it
+    fn write_direct(&self, align: usize, offset: u64, data: &[u8]) -> Result<usize> {
+        self.direct_lock
+            .write(align, offset, data.len(), || self.write_direct_locked(align, offset, data))
+    }
+
+    fn write_direct_locked(&self, align: usize, offset: u64, data: &[u8]) -> Result<usize> {
+        let end = offset + data.len() as u64;
+        let cut = if end.is_multiple_of(align as u64) {
+            None
+        } else {
+            // a block device has no length to change
+            let metadata = self.file.metadata()?;
+            padded_cut(align, end, metadata.len()).filter(|_| metadata.is_file())
+        };
+
+        let written = write_bounced(
+            align,
+            offset,
+            data,
+            |offset, buffer| self.read_raw(offset, buffer),
+            |offset, data| self.write_raw(offset, data),
+        )?;
+        if let Some(end) = cut {
+            self.file.set_len(end)?;
+        }
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_options_test() {
+        let path = std::env::temp_dir().join(format!("rdisk_linux_file_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let file = File::create_preallocated(&path, 4096).unwrap();
+        file.write_all_at(100, b"file").unwrap();
+        drop(file);
+
+        let file = File::open_with(&path, &OpenOptions::read_only()).unwrap();
+        let mut buffer = [0_u8; 4];
+        file.read_exact_at(100, &mut buffer).unwrap();
+        assert_eq!(b"file", &buffer);
+        assert!(file.write_at(0, b"x").is_err());
+
+        let exclusive_read = OpenOptions {
+            exclusive: true,
+            ..OpenOptions::read_only()
+        };
+        let exclusive = File::open_with(&path, &OpenOptions::exclusive()).unwrap();
+        assert!(File::open_with(&path, &exclusive_read).is_err());
+        drop(exclusive);
+        assert!(File::open_with(&path, &exclusive_read).is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn direct_write_test() {
+        let path = std::env::temp_dir().join(format!("rdisk_linux_direct_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        File::create_preallocated(&path, 5000).unwrap();
+
+        // the padding of the last block is cut off, the file grows up to the request end only
+        let file = File::open_with(&path, &OpenOptions::default().direct()).unwrap();
+        assert!(file.is_direct());
+        file.write_all_at(4990, b"tail!").unwrap();
+        assert_eq!(5000, file.size().unwrap());
+        file.write_all_at(4998, b"end").unwrap();
+        assert_eq!(5001, file.size().unwrap());
+        let mut buffer = [0_u8; 11];
+        file.read_exact_at(4990, &mut buffer).unwrap();
+        assert_eq!(b"tail!\0\0\0end", &buffer);
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+
+        // the dynamic VHD footer stays the last sector
+        let vhd_path = format!("{}.vhd", path);
+        drop(crate::vhd::VhdImage::create_dynamic(vhd_path.as_str(), 16 * crate::sizes::MIB).unwrap());
+        let disk = crate::vhd::VhdImage::open_with(vhd_path.as_str(), &OpenOptions::default().direct()).unwrap();
+        disk.write_all_at(12345, b"direct").unwrap();
+        drop(disk);
+
+        let disk = crate::vhd::VhdImage::open(vhd_path.as_str()).unwrap();
+        let mut buffer = [0_u8; 6];
+        disk.read_exact_at(12345, &mut buffer).unwrap();
+        assert_eq!(b"direct", &buffer);
+        drop(disk);
+        std::fs::remove_file(&vhd_path).unwrap();
+    }
+
+    #[test]
+    fn direct_concurrent_write_test() {
+        let path = std::env::temp_dir().join(format!("rdisk_linux_direct_threads_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        File::create_preallocated(&path, 2 * 4096).unwrap();
+
+        // every byte of the two blocks is written once by one of the threads, a lost read-modify-write leaves a zero
+        let file = File::open_with(&path, &OpenOptions::default().direct()).unwrap();
+        let threads: Vec<_> = (0..8_u64)
+            .map(|id| {
+                let file = file.clone();
+                std::thread::spawn(move || {
+                    for offset in (id..2 * 4096).step_by(8) {
+                        file.write_all_at(offset, &[id as u8 + 1]).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let mut data = vec![0_u8; 2 * 4096];
+        file.read_exact_at(0, &mut data).unwrap();
+        for (offset, &byte) in data.iter().enumerate() {
+            assert_eq!(offset as u8 % 8 + 1, byte, "offset {}", offset);
+        }
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Runs on a scratch block device named by `RDISK_TEST_BLOCK_DEVICE`, its contents are destroyed
+    #[test]
+    fn block_device_zero_range_test() {
+        let path = match std::env::var("RDISK_TEST_BLOCK_DEVICE") {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+
+        let file = File::open(&path).unwrap();
+        assert!(is_block_device(&file.file).unwrap());
+        file.write_all_at(0, &vec![0xAB_u8; 8192]).unwrap();
+        file.zero_range(100, 5000).unwrap();
+        let mut buffer = vec![0_u8; 8192];
+        file.read_exact_at(0, &mut buffer).unwrap();
+        assert!(buffer[..100].iter().all(|&b| b == 0xAB));
+        assert!(buffer[100..5100].iter().all(|&b| b == 0));
+        assert!(buffer[5100..].iter().all(|&b| b == 0xAB));
+
+        // the discarded contents are undefined, only the edges are kept
+        file.discard_range(100, 5000).unwrap();
+        file.read_exact_at(0, &mut buffer).unwrap();
+        assert!(buffer[..100].iter().all(|&b| b == 0xAB));
+        assert!(buffer[5100..].iter().all(|&b| b == 0xAB));
+    }
+}
@@ -0,0 +1,7 @@
+mod file;
+pub use file::File;
+#[cfg(feature = "io-uring")]
+pub(crate) use file::{create_preallocated_std, discard_block_range, is_block_device, open_std, punch_hole, zero_block_range};
+
+/// `std::io::Error` converts to [`Error::Io`](crate::Error::Io)
+pub type Error = std::io::Error;
@@ -3,5 +3,8 @@ cfg_if::cfg_if! {
     if #[cfg(windows)] {
         mod windows;
         pub use windows::*;
+    } else if #[cfg(all(target_os = "linux", feature = "std"))] {
+        mod linux;
+        pub use linux::*;
     }
 }
@@ -1,49 +1,200 @@
-use crate::{Flush, ReadAt, Result, WriteAt};
-use nt_native::NtString;
+use crate::aligned::{padded_cut, read_bounced, write_bounced, DirectWriteLock};
+use crate::{Flush, OpenOptions, ReadAt, Result, Stream, WriteAt};
+use alloc::sync::Arc;
+use nt_native::{Access, CreateDisposition, NewHandle, NtString, Options, ShareAccess};
 
 type NtFile = nt_native::File;
 
+/// Unbuffered I/O has to be aligned to the volume sector size, 4 KiB covers both 512 and 4K sectors
+const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+// from winioctl.h and ntstatus.h, nt_native does not wrap them
+#[cfg(feature = "std")]
+const FSCTL_SET_ZERO_DATA: u32 = 0x0009_80C8;
+#[cfg(feature = "std")]
+const STATUS_INVALID_DEVICE_REQUEST: i32 = 0xC000_0010_u32 as i32;
+#[cfg(feature = "std")]
+const STATUS_NOT_SUPPORTED: i32 = 0xC000_00BB_u32 as i32;
+
 #[derive(Clone)]
 #[cfg_attr(any(feature = "std", test), derive(Debug))]
-pub struct File(NtFile);
+pub struct File {
+    file: NtFile,
+    /// Opened without the cache manager, the requests are bounced to this alignment
+    direct_alignment: Option<usize>,
+    /// Shared by the clones
+    direct_lock: Arc<DirectWriteLock>,
+}
+
+// Positioned I/O on a kernel handle has no thread affinity, the images share files between threads
+unsafe impl Send for File {}
+unsafe impl Sync for File {}
 
 impl ReadAt for File {
     fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
-        nt_native::ReadAt::read_at(&self.0, offset, buffer).map_err(From::from)
+        match self.direct_alignment {
+            Some(align) => read_bounced(align, offset, buffer, |offset, buffer| self.read_raw(offset, buffer)),
+            None => self.read_raw(offset, buffer),
+        }
     }
 }
 
 impl WriteAt for File {
     fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
-        nt_native::WriteAt::write_at(&self.0, offset, data).map_err(From::from)
+        match self.direct_alignment {
+            Some(align) => self.write_direct(align, offset, data),
+            None => self.write_raw(offset, data),
+        }
     }
 }
 
 impl Flush for File {
     fn flush(&self) -> Result<()> {
-        nt_native::Flush::flush(&self.0).map_err(From::from)
+        nt_native::Flush::flush(&self.file).map_err(From::from)
+    }
+}
+
+impl Stream for File {
+    fn size(&self) -> Result<u64> {
+        File::size(self)
+    }
+
+    /// Zeroes the range by the file system, a sparse file deallocates it.
+    /// The zeroes are written if the file system can't do it.
+    fn zero_range(&self, offset: u64, len: u64) -> Result<()> {
+        if !self.set_zero_data(offset, len)? {
+            crate::tools::write_zeroes(self, offset, len)?;
+        }
+
+        Ok(())
     }
 }
 
 impl File {
     pub fn open(path: &str) -> Result<Self> {
         let nt_path = NtString::from(path);
-        NtFile::open(&nt_path).map(File).map_err(From::from)
+        NtFile::open(&nt_path).map(File::buffered).map_err(From::from)
+    }
+
+    pub fn open_with(path: &str, options: &OpenOptions) -> Result<Self> {
+        let nt_path = NtString::from(path);
+        if !options.exclusive && !options.direct {
+            return if options.read_only {
+                NtFile::open_readonly(&nt_path).map(File::buffered).map_err(From::from)
+            } else {
+                NtFile::open(&nt_path).map(File::buffered).map_err(From::from)
+            };
+        }
+
+        let mut new_handle = NewHandle::with_cd(CreateDisposition::Open);
+        new_handle.access = if options.read_only {
+            Access::GENERIC_READ | Access::SYNCHRONIZE
+        } else {
+            Access::GENERIC_READ | Access::GENERIC_WRITE | Access::SYNCHRONIZE
+        };
+        if options.exclusive {
+            new_handle.share_access = ShareAccess::empty();
+        }
+        if options.direct {
+            new_handle.options |= Options::NO_BUFFERING;
+        }
+        let (handle, _) = new_handle.build(&nt_path)?;
+
+        Ok(File {
+            file: NtFile::from(handle),
+            direct_alignment: if options.direct { Some(DIRECT_IO_ALIGNMENT) } else { None },
+            direct_lock: Arc::default(),
+        })
     }
 
     pub fn create_preallocated(path: &str, size: u64) -> Result<Self> {
         let nt_path = NtString::from(path);
-        NtFile::create_preallocated(&nt_path, size).map(File).map_err(From::from)
+        NtFile::create_preallocated(&nt_path, size).map(File::buffered).map_err(From::from)
     }
 
-    pub fn owerwrite_or_create(path: &str) -> Result<(Self, bool)> {
+    pub fn overwrite_or_create(path: &str) -> Result<(Self, bool)> {
         let nt_path = NtString::from(path);
         NtFile::owerwrite_or_create(&nt_path)
-            .map(|(nt_file, already_exists)| (File(nt_file), already_exists))
+            .map(|(nt_file, already_exists)| (File::buffered(nt_file), already_exists))
             .map_err(From::from)
     }
 
     pub fn size(&self) -> Result<u64> {
-        self.0.size().map_err(From::from)
+        self.file.size().map_err(From::from)
+    }
+
+    /// Opened with [`OpenOptions::direct`](crate::OpenOptions::direct)
+    pub fn is_direct(&self) -> bool {
+        self.direct_alignment.is_some()
+    }
+
+    fn buffered(file: NtFile) -> Self {
+        Self {
+            file,
+            direct_alignment: None,
+            direct_lock: Arc::default(),
+        }
+    }
+
+    /// Returns `false` if the file system has no `FSCTL_SET_ZERO_DATA`
+    #[cfg(feature = "std")]
+    fn set_zero_data(&self, offset: u64, len: u64) -> Result<bool> {
+        use std::os::windows::io::{AsRawHandle, FromRawHandle};
+
+        // FILE_ZERO_DATA_INFORMATION: FileOffset, BeyondFinalZero
+        let mut input = [0_u8; 16];
+        input[..8].copy_from_slice(&offset.to_le_bytes());
+        input[8..].copy_from_slice(&(offset + len).to_le_bytes());
+
+        // the handle is borrowed, it is closed with `self.file`
+        let handle = core::mem::ManuallyDrop::new(unsafe { nt_native::Handle::from_raw_handle(self.file.as_raw_handle()) });
+        match handle.ioctl_raw(FSCTL_SET_ZERO_DATA, &input, &mut []) {
+            (STATUS_INVALID_DEVICE_REQUEST, _) | (STATUS_NOT_SUPPORTED, _) => Ok(false),
+            (status, _) if status < 0 => Err(crate::Error::from(nt_native::Error::from(status))),
+            _ => Ok(true),
+        }
+    }
+
+    /// The raw handle is only available with `std`
+    #[cfg(not(feature = "std"))]
+    fn set_zero_data(&self, _offset: u64, _len: u64) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn read_raw(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        nt_native::ReadAt::read_at(&self.file, offset, buffer).map_err(From::from)
+    }
+
+    fn write_raw(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        nt_native::WriteAt::write_at(&self.file, offset, data).map_err(From::from)
+    }
+
+    /// The padding of a bounced write past the end of the file is cut off, the file ends where a buffered write
+    /// would end it: the images keeping a footer at the end depend on it
+    fn write_direct(&self, align: usize, offset: u64, data: &[u8]) -> Result<usize> {
+        self.direct_lock
+            .write(align, offset, data.len(), || self.write_direct_locked(align, offset, data))
+    }
+
+    fn write_direct_locked(&self, align: usize, offset: u64, data: &[u8]) -> Result<usize> {
+        let end = offset + data.len() as u64;
+        let cut = if end.is_multiple_of(align as u64) {
+            None
+        } else {
+            padded_cut(align, end, self.size()?)
+        };
+
+        let written = write_bounced(
+            align,
+            offset,
+            data,
+            |offset, buffer| self.read_raw(offset, buffer),
+            |offset, data| self.write_raw(offset, data),
+        )?;
+        if let Some(end) = cut {
+            self.file.set_end_of_file(end)?;
+        }
+
+        Ok(written)
     }
 }
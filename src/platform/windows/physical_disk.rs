@@ -1,32 +1,67 @@
+use crate::aligned::{read_bounced, write_bounced};
 use crate::prelude::*;
 use crate::StorageDeviceInfo;
-use nt_native::NtString;
+use nt_native::{Access, CreateDisposition, Handle, NewHandle, NtString, ShareAccess};
+
+// device I/O controls nt_native does not wrap, from winioctl.h
+const IOCTL_STORAGE_QUERY_PROPERTY: u32 = 0x002D_1400;
+const IOCTL_STORAGE_MANAGE_DATA_SET_ATTRIBUTES: u32 = 0x002D_9404;
+const STORAGE_ACCESS_ALIGNMENT_PROPERTY: u32 = 6;
+const DEVICE_DSM_ACTION_TRIM: u32 = 1;
+/// `DEVICE_MANAGE_DATA_SET_ATTRIBUTES`, 7 DWORDs
+const DSM_ATTRIBUTES_SIZE: usize = 28;
+/// The `DEVICE_DATA_SET_RANGE` follows the attributes at an 8 bytes aligned offset
+const DSM_RANGE_OFFSET: usize = 32;
 
 #[derive(Clone)]
 #[cfg_attr(any(feature = "std", test), derive(Debug))]
-pub struct PhysicalDisk(nt_native::Disk);
+pub struct PhysicalDisk {
+    disk: nt_native::Disk,
+    /// One more handle to the device for the I/O controls [`nt_native::Disk`] has no methods for
+    control: Handle,
+    /// Device I/O is never cached, but it has to be sector aligned. Unaligned requests are bounced
+    /// to this alignment when the disk is opened with [`OpenOptions::direct`].
+    direct_alignment: Option<usize>,
+    read_only: bool,
+}
 
 impl ReadAt for PhysicalDisk {
     fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
-        nt_native::ReadAt::read_at(&self.0, offset, buffer).map_err(From::from)
+        match self.direct_alignment {
+            Some(align) => read_bounced(align, offset, buffer, |offset, buffer| self.read_raw(offset, buffer)),
+            None => self.read_raw(offset, buffer),
+        }
     }
 }
 
 impl WriteAt for PhysicalDisk {
     fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
-        nt_native::WriteAt::write_at(&self.0, offset, data).map_err(From::from)
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        match self.direct_alignment {
+            Some(align) => write_bounced(
+                align,
+                offset,
+                data,
+                |offset, buffer| self.read_raw(offset, buffer),
+                |offset, data| self.write_raw(offset, data),
+            ),
+            None => self.write_raw(offset, data),
+        }
     }
 }
 
 impl Flush for PhysicalDisk {
     fn flush(&self) -> Result<()> {
-        nt_native::Flush::flush(&self.0).map_err(From::from)
+        nt_native::Flush::flush(&self.disk).map_err(From::from)
     }
 }
 
 impl Disk for PhysicalDisk {
     fn geometry(&self) -> crate::Result<Geometry> {
-        self.0.geometry().map_err(From::from).map(|raw| Geometry {
+        self.disk.geometry().map_err(From::from).map(|raw| Geometry {
             bytes_per_sector: raw.BytesPerSector,
             sectors_per_track: raw.SectorsPerTrack,
             heads_per_cylinder: raw.TracksPerCylinder,
@@ -34,49 +69,148 @@ impl Disk for PhysicalDisk {
         })
     }
     fn capacity(&self) -> crate::Result<u64> {
-        self.0.capacity().map_err(From::from)
+        self.disk.capacity().map_err(From::from)
     }
+    /// Reported by the storage access alignment property, the logical sector size if the device has no such property
     fn physical_sector_size(&self) -> crate::Result<u32> {
-        unimplemented!()
+        // STORAGE_PROPERTY_QUERY with PropertyStandardQuery
+        let mut query = [0_u8; 12];
+        query[..4].copy_from_slice(&STORAGE_ACCESS_ALIGNMENT_PROPERTY.to_le_bytes());
+
+        // STORAGE_ACCESS_ALIGNMENT_DESCRIPTOR, BytesPerPhysicalSector is the 6th DWORD
+        let mut descriptor = [0_u8; 28];
+        let (status, size) = self.control.ioctl_raw(IOCTL_STORAGE_QUERY_PROPERTY, &query, &mut descriptor);
+        if status < 0 || size < 24 {
+            return self.logical_sector_size();
+        }
+
+        let mut bytes = [0_u8; 4];
+        bytes.copy_from_slice(&descriptor[20..24]);
+        match u32::from_le_bytes(bytes) {
+            0 => self.logical_sector_size(),
+            size => Ok(size),
+        }
+    }
+
+    /// TRIM of the whole logical sectors of the range, nothing is done if the device has no TRIM support
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if !math::is_range_valid(self.capacity()?, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        let sector_size = self.logical_sector_size()? as u64;
+        let start = math::round_up(offset, sector_size);
+        let end = math::round_down(offset + len, sector_size);
+        if start >= end || !self.is_trim_enabled()? {
+            return Ok(());
+        }
+
+        let mut input = [0_u8; DSM_RANGE_OFFSET + 16];
+        // Size, Action, Flags, ParameterBlockOffset, ParameterBlockLength, DataSetRangesOffset, DataSetRangesLength
+        let attributes = [
+            DSM_ATTRIBUTES_SIZE as u32,
+            DEVICE_DSM_ACTION_TRIM,
+            0,
+            0,
+            0,
+            DSM_RANGE_OFFSET as u32,
+            16,
+        ];
+        for (i, value) in attributes.iter().enumerate() {
+            input[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        // StartingOffset, LengthInBytes
+        input[DSM_RANGE_OFFSET..DSM_RANGE_OFFSET + 8].copy_from_slice(&start.to_le_bytes());
+        input[DSM_RANGE_OFFSET + 8..].copy_from_slice(&(end - start).to_le_bytes());
+
+        match self.control.ioctl_raw(IOCTL_STORAGE_MANAGE_DATA_SET_ATTRIBUTES, &input, &mut []) {
+            (status, _) if status < 0 => Err(Error::from(nt_native::Error::from(status))),
+            _ => Ok(()),
+        }
     }
+
+    // write_zeroes writes the zeroes: the data of the trimmed sectors is not guaranteed to read as zeroes
+    // and no device I/O control zeroes a range without the data
 }
 
 impl PhysicalDisk {
     pub fn open(index: u32) -> Result<Self> {
+        Self::open_with(index, &OpenOptions::default())
+    }
+
+    /// Device handles are always shared, [`OpenOptions::exclusive`] is ignored
+    pub fn open_with(index: u32, options: &OpenOptions) -> Result<Self> {
         let name = format!("\\\\.\\PhysicalDrive{}", index);
-        Self::open_by_name(name.as_str())
+        Self::open_by_name_with(name.as_str(), options)
     }
 
     /// Platform specific name like `\\.\PhysicalDrive0`  
     pub fn open_by_name(name: &str) -> Result<Self> {
+        Self::open_by_name_with(name, &OpenOptions::default())
+    }
+
+    pub fn open_by_name_with(name: &str, options: &OpenOptions) -> Result<Self> {
         let nt_name = NtString::from(name);
-        nt_native::Disk::open(&nt_name).map_err(From::from).map(Self)
+        let mut control = NewHandle::with_cd(CreateDisposition::Open);
+        control.access = Access::GENERIC_READ | Access::GENERIC_WRITE | Access::SYNCHRONIZE;
+        control.share_access = ShareAccess::READ | ShareAccess::WRITE;
+        let (control, _) = control.build(&nt_name)?;
+
+        let mut disk = Self {
+            disk: nt_native::Disk::open(&nt_name)?,
+            control,
+            direct_alignment: None,
+            read_only: options.read_only,
+        };
+
+        if options.direct {
+            disk.direct_alignment = Some(disk.logical_sector_size()? as usize);
+        }
+
+        Ok(disk)
     }
 
+    /// The device is write protected or opened read-only
     pub fn is_readonly(&self) -> Result<bool> {
-        self.0.is_readonly().map_err(From::from)
+        if self.read_only {
+            return Ok(true);
+        }
+
+        self.disk.is_readonly().map_err(From::from)
     }
 
+
     pub fn is_offline(&self) -> Result<bool> {
-        self.0.is_offline().map_err(From::from)
+        self.disk.is_offline().map_err(From::from)
     }
 
     pub fn is_removable(&self) -> Result<bool> {
-        self.0.is_removable().map_err(From::from)
+        self.disk.is_removable().map_err(From::from)
     }
 
     pub fn is_trim_enabled(&self) -> Result<bool> {
-        self.0.is_trim_enabled().map_err(From::from)
+        self.disk.is_trim_enabled().map_err(From::from)
     }
     pub fn has_seek_penalty(&self) -> Result<bool> {
-        self.0.has_seek_penalty().map_err(From::from)
+        self.disk.has_seek_penalty().map_err(From::from)
     }
 
     pub fn device_number(&self) -> Result<u32> {
-        self.0.device_number().map_err(From::from)
+        self.disk.device_number().map_err(From::from)
     }
 
     pub fn device_info(&self) -> Result<StorageDeviceInfo> {
         todo!()
     }
+
+    fn read_raw(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        nt_native::ReadAt::read_at(&self.disk, offset, buffer).map_err(From::from)
+    }
+
+    fn write_raw(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        nt_native::WriteAt::write_at(&self.disk, offset, data).map_err(From::from)
+    }
 }
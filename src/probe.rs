@@ -0,0 +1,592 @@
+//! Filesystem detection by superblock signatures
+//!
+//! Partition types are only hints: MBR 0x07 holds NTFS or exFAT, 0x83 any Linux filesystem.
+use crate::prelude::*;
+use crate::tools::{be_u32, be_u64, le_u16, le_u32, le_u64};
+use crate::{exfat, ext, fat, lvm, ntfs};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FsKind {
+    Fat12,
+    Fat16,
+    Fat32,
+    ExFat,
+    Ntfs,
+    ReFS,
+    BitLocker,
+    Ext2,
+    Ext3,
+    Ext4,
+    Xfs,
+    Btrfs,
+    F2fs,
+    Squashfs,
+    Swap,
+    Luks,
+    LinuxRaid,
+    LvmPhysicalVolume,
+    HfsPlus,
+    Hfsx,
+    Apfs,
+    Iso9660,
+    Udf,
+}
+
+impl core::fmt::Display for FsKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            FsKind::Fat12 => "FAT12",
+            FsKind::Fat16 => "FAT16",
+            FsKind::Fat32 => "FAT32",
+            FsKind::ExFat => "exFAT",
+            FsKind::Ntfs => "NTFS",
+            FsKind::ReFS => "ReFS",
+            FsKind::BitLocker => "BitLocker",
+            FsKind::Ext2 => "ext2",
+            FsKind::Ext3 => "ext3",
+            FsKind::Ext4 => "ext4",
+            FsKind::Xfs => "XFS",
+            FsKind::Btrfs => "Btrfs",
+            FsKind::F2fs => "F2FS",
+            FsKind::Squashfs => "SquashFS",
+            FsKind::Swap => "Linux swap",
+            FsKind::Luks => "LUKS",
+            FsKind::LinuxRaid => "Linux RAID member",
+            FsKind::LvmPhysicalVolume => "LVM2 physical volume",
+            FsKind::HfsPlus => "HFS+",
+            FsKind::Hfsx => "HFSX",
+            FsKind::Apfs => "APFS",
+            FsKind::Iso9660 => "ISO 9660",
+            FsKind::Udf => "UDF",
+        })
+    }
+}
+
+/// What the signature tells about the volume, fields are `None` if the format does not store them
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct FsInfo {
+    pub kind: FsKind,
+    pub label: Option<String>,
+    pub uuid: Option<Uuid>,
+    /// FAT, exFAT, NTFS, ReFS and HFS+ volume serial number
+    pub serial: Option<u64>,
+    /// Cluster or block size in bytes
+    pub block_size: Option<u32>,
+    /// Bytes
+    pub total_size: Option<u64>,
+    /// Bytes
+    pub used_size: Option<u64>,
+}
+
+impl FsInfo {
+    fn new(kind: FsKind) -> Self {
+        Self {
+            kind,
+            label: None,
+            uuid: None,
+            serial: None,
+            block_size: None,
+            total_size: None,
+            used_size: None,
+        }
+    }
+}
+
+/// Detects the filesystem, containers like LUKS, RAID members and LVM physical volumes are reported too
+pub fn probe_filesystem(device: &impl ReadAt) -> Option<FsInfo> {
+    const PROBES: &[fn(&dyn ReadAt) -> Option<FsInfo>] = &[
+        probe_luks,
+        probe_bitlocker,
+        probe_ntfs,
+        probe_refs,
+        probe_exfat,
+        probe_xfs,
+        probe_apfs,
+        probe_squashfs,
+        probe_linux_raid,
+        probe_lvm,
+        probe_btrfs,
+        probe_ext,
+        probe_f2fs,
+        probe_hfs_plus,
+        probe_swap,
+        probe_udf,
+        probe_iso9660,
+        probe_fat, // the least specific signature
+    ];
+
+    PROBES.iter().find_map(|probe| probe(device))
+}
+
+/// `None` if the device is too small
+fn read(device: &dyn ReadAt, offset: u64, len: usize) -> Option<Vec<u8>> {
+    let mut bytes = vec![0_u8; len];
+    device.read_exact_at(offset, &mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn uuid_at(bytes: &[u8], pos: usize) -> Option<Uuid> {
+    let mut raw = [0_u8; 16];
+    raw.copy_from_slice(&bytes[pos..pos + 16]);
+    Some(Uuid::from_bytes(raw)).filter(|uuid| !uuid.is_nil())
+}
+
+fn label(label: String) -> Option<String> {
+    Some(label).filter(|label| !label.is_empty())
+}
+
+fn ascii_label(bytes: &[u8]) -> Option<String> {
+    label(tools::string_from_ascii_z(bytes))
+}
+
+fn probe_luks(device: &dyn ReadAt) -> Option<FsInfo> {
+    let header = read(device, 0, 512)?;
+    if &header[..6] != b"LUKS\xBA\xBE" {
+        return None;
+    }
+
+    // the version 2 binary header has a label where version 1 has the cipher name
+    let mut info = FsInfo::new(FsKind::Luks);
+    if u16::from_be_bytes([header[6], header[7]]) == 2 {
+        info.label = ascii_label(&header[24..72]);
+    }
+    info.uuid = Uuid::parse_str(&tools::string_from_ascii_z(&header[168..208])).ok();
+    Some(info)
+}
+
+fn probe_bitlocker(device: &dyn ReadAt) -> Option<FsInfo> {
+    let boot = read(device, 0, 512)?;
+    if &boot[3..11] != b"-FVE-FS-" {
+        return None;
+    }
+
+    let mut info = FsInfo::new(FsKind::BitLocker);
+    info.block_size = Some(le_u16(&boot, 0x0B) as u32 * boot[0x0D] as u32).filter(|&size| size != 0);
+    // Windows 7 and later, mixed endian GUID
+    let mut raw = [0_u8; 16];
+    raw.copy_from_slice(&boot[0xA0..0xB0]);
+    info.uuid = Some(Uuid::from_bytes(raw).swap_bytes()).filter(|uuid| !uuid.is_nil());
+    Some(info)
+}
+
+fn probe_ntfs(device: &dyn ReadAt) -> Option<FsInfo> {
+    let boot = ntfs::BootSector::parse(&read(device, 0, 512)?).ok()?;
+
+    let mut info = FsInfo::new(FsKind::Ntfs);
+    info.serial = Some(boot.serial_number);
+    info.block_size = Some(boot.cluster_size());
+    info.total_size = Some(boot.total_sectors.checked_mul(boot.bytes_per_sector as u64)?);
+    // the label is a $Volume attribute
+    info.label = ntfs::read_volume_label(&device, &boot).ok().and_then(label);
+    Some(info)
+}
+
+fn probe_refs(device: &dyn ReadAt) -> Option<FsInfo> {
+    let boot = read(device, 0, 512)?;
+    if &boot[3..11] != b"ReFS\0\0\0\0" || &boot[0x10..0x14] != b"FSRS" {
+        return None;
+    }
+
+    let bytes_per_sector = le_u32(&boot, 0x20);
+    let mut info = FsInfo::new(FsKind::ReFS);
+    info.serial = Some(le_u64(&boot, 0x38));
+    info.block_size = Some(bytes_per_sector.checked_mul(le_u32(&boot, 0x24))?);
+    info.total_size = Some(le_u64(&boot, 0x18).checked_mul(bytes_per_sector as u64)?);
+    Some(info)
+}
+
+fn probe_exfat(device: &dyn ReadAt) -> Option<FsInfo> {
+    if !exfat::is_exfat(&read(device, 0, 512)?) {
+        return None;
+    }
+    let boot = exfat::BootSector::read(&device).ok()?;
+
+    let cluster_size = boot.cluster_size();
+    let mut info = FsInfo::new(FsKind::ExFat);
+    info.serial = Some(boot.serial_number as u64);
+    info.block_size = Some(cluster_size);
+    info.total_size = Some(boot.volume_length.checked_mul(boot.bytes_per_sector as u64)?);
+    // the label is in the root directory, the boot sector keeps the usage as a rounded down percentage
+    info.label = exfat::read_volume_label(&device, &boot).ok().and_then(label);
+    let heap_size = boot.cluster_count as u64 * cluster_size as u64;
+    info.used_size = Some(boot.percent_in_use as u64)
+        .filter(|&percent| percent <= 100)
+        .map(|percent| heap_size / 100 * percent);
+    Some(info)
+}
+
+fn probe_xfs(device: &dyn ReadAt) -> Option<FsInfo> {
+    let sb = read(device, 0, 512)?;
+    if &sb[..4] != b"XFSB" {
+        return None;
+    }
+
+    let block_size = be_u32(&sb, 4);
+    let blocks = be_u64(&sb, 8);
+    let mut info = FsInfo::new(FsKind::Xfs);
+    info.label = ascii_label(&sb[0x6C..0x78]);
+    info.uuid = uuid_at(&sb, 0x20);
+    info.block_size = Some(block_size);
+    info.total_size = Some(blocks.checked_mul(block_size as u64)?);
+    info.used_size = blocks.checked_sub(be_u64(&sb, 0x90)).map(|used| used * block_size as u64);
+    Some(info)
+}
+
+fn probe_apfs(device: &dyn ReadAt) -> Option<FsInfo> {
+    // container superblock after the object header
+    let sb = read(device, 0, 512)?;
+    if &sb[32..36] != b"NXSB" {
+        return None;
+    }
+
+    let block_size = le_u32(&sb, 36);
+    let mut info = FsInfo::new(FsKind::Apfs);
+    info.uuid = uuid_at(&sb, 72);
+    info.block_size = Some(block_size);
+    info.total_size = Some(le_u64(&sb, 40).checked_mul(block_size as u64)?);
+    Some(info)
+}
+
+fn probe_squashfs(device: &dyn ReadAt) -> Option<FsInfo> {
+    let sb = read(device, 0, 96)?;
+    if &sb[..4] != b"hsqs" {
+        return None;
+    }
+
+    let mut info = FsInfo::new(FsKind::Squashfs);
+    info.block_size = Some(le_u32(&sb, 12));
+    info.total_size = Some(le_u64(&sb, 40));
+    info.used_size = info.total_size;
+    Some(info)
+}
+
+/// Version 1.1 and 1.2 superblocks, 0.90 and 1.0 are at the end of the device
+fn probe_linux_raid(device: &dyn ReadAt) -> Option<FsInfo> {
+    const MD_MAGIC: u32 = 0xA92B_4EFC;
+    [0, 4096].iter().find_map(|&offset| {
+        let sb = read(device, offset, 256)?;
+        if le_u32(&sb, 0) != MD_MAGIC || le_u32(&sb, 4) != 1 {
+            return None;
+        }
+
+        let mut info = FsInfo::new(FsKind::LinuxRaid);
+        info.label = ascii_label(&sb[32..64]);
+        info.uuid = uuid_at(&sb, 16);
+        Some(info)
+    })
+}
+
+fn probe_lvm(device: &dyn ReadAt) -> Option<FsInfo> {
+    let label = lvm::Label::read(&device).ok()??;
+
+    let mut info = FsInfo::new(FsKind::LvmPhysicalVolume);
+    info.total_size = Some(label.device_size).filter(|&size| size != 0);
+    Some(info)
+}
+
+fn probe_btrfs(device: &dyn ReadAt) -> Option<FsInfo> {
+    let sb = read(device, 0x1_0000, 4096)?;
+    if &sb[0x40..0x48] != b"_BHRfS_M" {
+        return None;
+    }
+
+    let mut info = FsInfo::new(FsKind::Btrfs);
+    info.label = ascii_label(&sb[0x12B..0x22B]);
+    info.uuid = uuid_at(&sb, 0x20);
+    info.block_size = Some(le_u32(&sb, 0x90));
+    info.total_size = Some(le_u64(&sb, 0x70));
+    info.used_size = Some(le_u64(&sb, 0x78));
+    Some(info)
+}
+
+fn probe_ext(device: &dyn ReadAt) -> Option<FsInfo> {
+    let sb = ext::Superblock::parse(&read(device, 1024, 1024)?).ok()?;
+
+    let block_size = sb.block_size() as u64;
+    let mut info = FsInfo::new(match sb.version() {
+        2 => FsKind::Ext2,
+        3 => FsKind::Ext3,
+        _ => FsKind::Ext4,
+    });
+    info.uuid = Some(sb.uuid).filter(|uuid| !uuid.is_nil());
+    info.block_size = Some(sb.block_size());
+    info.total_size = Some(sb.blocks_count.checked_mul(block_size)?);
+    info.used_size = sb.blocks_count.checked_sub(sb.free_blocks_count).map(|used| used * block_size);
+    info.label = label(sb.volume_name);
+    Some(info)
+}
+
+fn probe_f2fs(device: &dyn ReadAt) -> Option<FsInfo> {
+    const F2FS_MAGIC: u32 = 0xF2F5_2010;
+    let sb = read(device, 1024, 1024)?;
+    if le_u32(&sb, 0) != F2FS_MAGIC {
+        return None;
+    }
+
+    let block_size = 1_u32.checked_shl(le_u32(&sb, 16))?;
+    let name: Vec<u16> = sb[124..124 + 512]
+        .chunks_exact(2)
+        .map(|c| le_u16(c, 0))
+        .take_while(|&c| c != 0)
+        .collect();
+    let mut info = FsInfo::new(FsKind::F2fs);
+    info.label = label(String::from_utf16_lossy(&name));
+    info.uuid = uuid_at(&sb, 108);
+    info.block_size = Some(block_size);
+    info.total_size = Some(le_u64(&sb, 36).checked_mul(block_size as u64)?);
+    Some(info)
+}
+
+fn probe_hfs_plus(device: &dyn ReadAt) -> Option<FsInfo> {
+    let header = read(device, 1024, 512)?;
+    let kind = match &header[..2] {
+        b"H+" => FsKind::HfsPlus,
+        b"HX" => FsKind::Hfsx,
+        _ => return None,
+    };
+
+    // the label is the name of the root folder in the catalog file
+    let block_size = be_u32(&header, 0x28);
+    let blocks = be_u32(&header, 0x2C) as u64;
+    let mut info = FsInfo::new(kind);
+    info.serial = Some(be_u64(&header, 0x50 + 24)).filter(|&serial| serial != 0);
+    info.block_size = Some(block_size);
+    info.total_size = Some(blocks * block_size as u64);
+    info.used_size = blocks
+        .checked_sub(be_u32(&header, 0x30) as u64)
+        .map(|used| used * block_size as u64);
+    Some(info)
+}
+
+fn probe_swap(device: &dyn ReadAt) -> Option<FsInfo> {
+    [4096_u64, 8192, 16384, 65536].iter().find_map(|&page_size| {
+        let page = read(device, 0, page_size as usize)?;
+        let signature = &page[page_size as usize - 10..];
+        if signature != b"SWAPSPACE2" && signature != b"SWAP-SPACE" {
+            return None;
+        }
+
+        let mut info = FsInfo::new(FsKind::Swap);
+        info.block_size = Some(page_size as u32);
+        if signature == b"SWAPSPACE2" {
+            info.label = ascii_label(&page[1052..1068]);
+            info.uuid = uuid_at(&page, 1036);
+            info.total_size = Some((le_u32(&page, 1028) as u64 + 1) * page_size);
+        }
+        Some(info)
+    })
+}
+
+/// Volume descriptors start at sector 16 of 2048 bytes
+const VOLUME_DESCRIPTORS: u64 = 16 * 2048;
+
+fn probe_udf(device: &dyn ReadAt) -> Option<FsInfo> {
+    // the extended area: BEA01, NSR02 or NSR03, TEA01
+    for index in 0..16 {
+        let descriptor = read(device, VOLUME_DESCRIPTORS + index * 2048, 8)?;
+        match &descriptor[1..6] {
+            b"NSR02" | b"NSR03" => {
+                let mut info = FsInfo::new(FsKind::Udf);
+                info.block_size = Some(2048);
+                return Some(info);
+            }
+            b"BEA01" | b"CD001" | b"CDW02" | b"BOOT2" => (),
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+fn probe_iso9660(device: &dyn ReadAt) -> Option<FsInfo> {
+    let descriptor = read(device, VOLUME_DESCRIPTORS, 2048)?;
+    if descriptor[0] != 1 || &descriptor[1..6] != b"CD001" {
+        return None;
+    }
+
+    let block_size = le_u16(&descriptor, 128) as u32;
+    let mut info = FsInfo::new(FsKind::Iso9660);
+    info.label = ascii_label(&descriptor[40..72]);
+    info.block_size = Some(block_size);
+    info.total_size = Some(le_u32(&descriptor, 80) as u64 * block_size as u64);
+    info.used_size = info.total_size;
+    Some(info)
+}
+
+fn probe_fat(device: &dyn ReadAt) -> Option<FsInfo> {
+    let boot = fat::BootSector::parse(&read(device, 0, 512)?).ok()?;
+
+    let cluster_size = boot.cluster_size();
+    let mut info = FsInfo::new(match boot.kind {
+        fat::FatKind::Fat12 => FsKind::Fat12,
+        fat::FatKind::Fat16 => FsKind::Fat16,
+        fat::FatKind::Fat32 => FsKind::Fat32,
+    });
+    info.serial = boot.volume_id.map(|id| id as u64);
+    info.block_size = Some(cluster_size);
+    info.total_size = Some(boot.total_sectors as u64 * boot.bytes_per_sector as u64);
+    info.label = label(boot.volume_label.clone()).filter(|label| label != "NO NAME");
+    // the root directory label wins over the boot sector one
+    if let Ok(fs) = fat::FatFileSystem::open(device) {
+        info.label = label(fs.volume_label().to_string()).filter(|label| label != "NO NAME");
+        let free = match fs.fs_info().and_then(|hints| hints.free_count) {
+            Some(free) => Some(free),
+            None => fs.free_clusters().ok(),
+        };
+        info.used_size = free.map(|free| boot.cluster_count.saturating_sub(free) as u64 * cluster_size as u64);
+    }
+    Some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+    use crate::{Partition, PartitionInfo, PartitionKind};
+
+    const UUID: [u8; 16] = [
+        0x0F, 0x1E, 0x2D, 0x3C, 0x4B, 0x5A, 0x69, 0x78, 0x87, 0x96, 0xA5, 0xB4, 0xC3, 0xD2, 0xE1, 0xF0,
+    ];
+
+    fn probe(image: Vec<u8>) -> Option<FsInfo> {
+        probe_filesystem(&MemoryDisk::from_vec(image))
+    }
+
+    #[test]
+    fn probe_test() {
+        assert!(probe(vec![0; 128 * 1024]).is_none());
+        assert!(probe(vec![0; 100]).is_none());
+
+        // mkswap -L swp -U 0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0
+        let mut image = vec![0_u8; 1024 * 1024];
+        image[4086..4096].copy_from_slice(b"SWAPSPACE2");
+        image[1024] = 1;
+        image[1028..1032].copy_from_slice(&255_u32.to_le_bytes());
+        image[1036..1052].copy_from_slice(&UUID);
+        image[1052..1055].copy_from_slice(b"swp");
+        let info = probe(image).unwrap();
+        assert_eq!(FsKind::Swap, info.kind);
+        assert_eq!(Some("swp"), info.label.as_deref());
+        assert_eq!(Some(Uuid::from_bytes(UUID)), info.uuid);
+        assert_eq!(Some(1024 * 1024), info.total_size);
+
+        let mut image = vec![0_u8; 64 * 1024];
+        let sb = &mut image[1024..2048];
+        sb[0x04..0x08].copy_from_slice(&64_u32.to_le_bytes()); // blocks
+        sb[0x0C..0x10].copy_from_slice(&40_u32.to_le_bytes()); // free blocks
+        sb[0x14] = 1; // first data block
+        sb[0x20..0x24].copy_from_slice(&8192_u32.to_le_bytes());
+        sb[0x28..0x2C].copy_from_slice(&16_u32.to_le_bytes());
+        sb[0x38..0x3A].copy_from_slice(&0xEF53_u16.to_le_bytes());
+        sb[0x5C] = ext::Superblock::COMPAT_HAS_JOURNAL as u8;
+        sb[0x68..0x78].copy_from_slice(&UUID);
+        sb[0x78..0x7C].copy_from_slice(b"root");
+        let info = probe(image).unwrap();
+        assert_eq!(FsKind::Ext3, info.kind);
+        assert_eq!(Some("root"), info.label.as_deref());
+        assert_eq!(Some(Uuid::from_bytes(UUID)), info.uuid);
+        assert_eq!(Some(1024), info.block_size);
+        assert_eq!(Some(64 * 1024), info.total_size);
+        assert_eq!(Some(24 * 1024), info.used_size);
+
+        let mut image = vec![0_u8; 128 * 1024];
+        let sb = &mut image[0x1_0000..];
+        sb[0x20..0x30].copy_from_slice(&UUID);
+        sb[0x40..0x48].copy_from_slice(b"_BHRfS_M");
+        sb[0x70..0x78].copy_from_slice(&(1_u64 << 30).to_le_bytes());
+        sb[0x78..0x80].copy_from_slice(&(1_u64 << 20).to_le_bytes());
+        sb[0x90..0x94].copy_from_slice(&4096_u32.to_le_bytes());
+        sb[0x12B..0x12F].copy_from_slice(b"data");
+        let info = probe(image).unwrap();
+        assert_eq!(FsKind::Btrfs, info.kind);
+        assert_eq!(Some("data"), info.label.as_deref());
+        assert_eq!(Some(1 << 30), info.total_size);
+        assert_eq!(Some(1 << 20), info.used_size);
+
+        let mut image = vec![0_u8; 4096];
+        image[..4].copy_from_slice(b"XFSB");
+        image[4..8].copy_from_slice(&4096_u32.to_be_bytes());
+        image[8..16].copy_from_slice(&1000_u64.to_be_bytes());
+        image[0x20..0x30].copy_from_slice(&UUID);
+        image[0x6C..0x70].copy_from_slice(b"home");
+        image[0x90..0x98].copy_from_slice(&600_u64.to_be_bytes());
+        let info = probe(image).unwrap();
+        assert_eq!(FsKind::Xfs, info.kind);
+        assert_eq!(Some("home"), info.label.as_deref());
+        assert_eq!(Some(400 * 4096), info.used_size);
+
+        let mut image = vec![0_u8; 40 * 2048];
+        let descriptor = &mut image[16 * 2048..];
+        descriptor[..6].copy_from_slice(b"\x01CD001");
+        descriptor[40..50].copy_from_slice(b"CDROM     ");
+        descriptor[80..84].copy_from_slice(&40_u32.to_le_bytes());
+        descriptor[128..130].copy_from_slice(&2048_u16.to_le_bytes());
+        image[17 * 2048..17 * 2048 + 6].copy_from_slice(b"\xFFCD001");
+        let info = probe(image.clone()).unwrap();
+        assert_eq!(FsKind::Iso9660, info.kind);
+        assert_eq!(Some("CDROM"), info.label.as_deref());
+        assert_eq!(Some(40 * 2048), info.total_size);
+
+        // UDF bridge
+        image[18 * 2048..18 * 2048 + 6].copy_from_slice(b"\x00BEA01");
+        image[19 * 2048..19 * 2048 + 6].copy_from_slice(b"\x00NSR02");
+        assert_eq!(FsKind::Udf, probe(image).unwrap().kind);
+
+        let mut image = vec![0_u8; 4096];
+        image[..8].copy_from_slice(b"LUKS\xBA\xBE\x00\x02");
+        image[24..29].copy_from_slice(b"vault");
+        image[168..204].copy_from_slice(b"0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0");
+        let info = probe(image).unwrap();
+        assert_eq!(FsKind::Luks, info.kind);
+        assert_eq!(Some("vault"), info.label.as_deref());
+        assert_eq!(Some(Uuid::from_bytes(UUID)), info.uuid);
+
+        // no MFT, still NTFS
+        let mut image = vec![0_u8; 4096];
+        image[3..11].copy_from_slice(b"NTFS    ");
+        image[0x0B..0x0D].copy_from_slice(&512_u16.to_le_bytes());
+        image[0x0D] = 8;
+        image[0x28..0x30].copy_from_slice(&2048_u64.to_le_bytes());
+        image[0x40] = 0xF6; // 1024 byte records
+        image[0x44] = 1;
+        image[0x48..0x50].copy_from_slice(&0x1234_5678_9ABC_DEF0_u64.to_le_bytes());
+        image[510..512].copy_from_slice(&[0x55, 0xAA]);
+        let info = probe(image).unwrap();
+        assert_eq!(FsKind::Ntfs, info.kind);
+        assert_eq!(None, info.label);
+        assert_eq!(Some(0x1234_5678_9ABC_DEF0), info.serial);
+        assert_eq!(Some(4096), info.block_size);
+        assert_eq!(Some(1024 * 1024), info.total_size);
+    }
+
+    #[test]
+    fn partition_filesystem_test() {
+        const OFFSET: u64 = 1024 * 1024;
+        const LENGTH: u64 = 4 * 1024 * 1024;
+
+        let disk = MemoryDisk::from_vec(vec![0; (OFFSET + LENGTH) as usize]);
+        let info = PartitionInfo {
+            offset: OFFSET,
+            length: LENGTH,
+            kind: PartitionKind::Free,
+        };
+        let partition = Partition::new(&disk, info);
+        let options = fat::FormatOptions {
+            label: "PROBE".to_string(),
+            volume_id: Some(0xCAFE_F00D),
+            ..Default::default()
+        };
+        let boot = fat::format(&partition, &options).unwrap();
+
+        let info = partition.filesystem().unwrap();
+        assert_eq!(FsKind::Fat12, info.kind);
+        assert_eq!(Some("PROBE"), info.label.as_deref());
+        assert_eq!(Some(0xCAFE_F00D), info.serial);
+        assert_eq!(Some(boot.cluster_size()), info.block_size);
+        assert_eq!(Some(LENGTH), info.total_size);
+        assert_eq!(Some(0), info.used_size);
+
+        assert!(probe_filesystem(&disk).is_none());
+    }
+}
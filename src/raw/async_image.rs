@@ -0,0 +1,129 @@
+use crate::prelude::*;
+use crate::async_disk::{replay, ReplayDisk};
+use crate::{AsyncDisk, AsyncFlush, AsyncReadAt, AsyncStream, AsyncWriteAt};
+
+/// Raw disk image on an async stream, the whole stream is the disk
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct AsyncRawDiskImage<S> {
+    stream: S,
+    capacity: u64,
+    geometry: Geometry,
+    read_only: bool,
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRawDiskImage<crate::AsyncFile> {
+    pub async fn open(path: &str) -> Result<Self> {
+        Self::open_with(path, &OpenOptions::default()).await
+    }
+
+    pub async fn open_with(path: &str, options: &OpenOptions) -> Result<Self> {
+        let file = crate::AsyncFile::open_with(path, options).await?;
+        Self::open_stream_with(file, options.read_only).await
+    }
+}
+
+impl<S: AsyncStream> AsyncRawDiskImage<S> {
+    pub async fn open_stream(stream: S) -> Result<Self> {
+        Self::open_stream_with(stream, false).await
+    }
+
+    /// Like [`open_stream`](Self::open_stream), but the stream is never written
+    pub async fn open_stream_read_only(stream: S) -> Result<Self> {
+        Self::open_stream_with(stream, true).await
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    async fn open_stream_with(stream: S, read_only: bool) -> Result<Self> {
+        let capacity = stream.size().await?;
+
+        // Make a better guess about the geometry based on MBR data
+        let detected = replay(&stream, ReplayDisk::for_stream(capacity), Geometry::detect).await?;
+        let geometry = detected.unwrap_or_else(|| Geometry::with_vhd_capacity(capacity));
+
+        Ok(Self {
+            stream,
+            capacity,
+            geometry,
+            read_only,
+        })
+    }
+}
+
+impl<S: AsyncStream> AsyncReadAt for AsyncRawDiskImage<S> {
+    async fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let len = math::bound_to(self.capacity, offset, buffer.len()).ok_or(Error::ReadBeyondEOD)?;
+        self.stream.read_at(offset, &mut buffer[..len]).await
+    }
+}
+
+impl<S: AsyncStream> AsyncWriteAt for AsyncRawDiskImage<S> {
+    async fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let len = math::bound_to(self.capacity, offset, data.len()).ok_or(Error::WriteBeyondEOD)?;
+        self.stream.write_at(offset, &data[..len]).await
+    }
+}
+
+impl<S: AsyncStream> AsyncFlush for AsyncRawDiskImage<S> {
+    async fn flush(&self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        self.stream.flush().await
+    }
+}
+
+impl<S: AsyncStream> AsyncDisk for AsyncRawDiskImage<S> {
+    async fn geometry(&self) -> Result<Geometry> {
+        Ok(self.geometry)
+    }
+
+    async fn capacity(&self) -> Result<u64> {
+        Ok(self.capacity)
+    }
+
+    async fn physical_sector_size(&self) -> Result<u32> {
+        Ok(self.geometry.bytes_per_sector)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use crate::{Blocking, MemoryStream};
+
+    #[test]
+    fn async_raw_test() {
+        let stream = MemoryStream::from_vec(vec![0_u8; 1024 * 1024]);
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let image = AsyncRawDiskImage::open_stream(Blocking::new(stream.clone())).await.unwrap();
+            assert_eq!(1024 * 1024, image.capacity().await.unwrap());
+
+            image.write_all_at(4096, b"raw").await.unwrap();
+            assert_eq!(b"raw", &stream.to_vec()[4096..4099]);
+            assert!(matches!(image.write_at(1024 * 1024 + 1, b"x").await, Err(Error::WriteBeyondEOD)));
+            image.write_zeroes(4097, 1).await.unwrap();
+            assert_eq!(b"r\0w", &stream.to_vec()[4096..4099]);
+
+            let image = AsyncRawDiskImage::open_stream_read_only(Blocking::new(stream.clone())).await.unwrap();
+            assert!(image.is_read_only());
+            assert!(matches!(image.write_at(0, b"raw").await, Err(Error::ReadOnly)));
+            let mut buffer = [0_u8; 3];
+            image.read_exact_at(4096, &mut buffer).await.unwrap();
+            assert_eq!(b"r\0w", &buffer);
+        });
+    }
+}
@@ -1,33 +1,49 @@
 use crate::prelude::*;
+use crate::{sizes, ConcatDisk, StreamDisk};
 
+#[cfg(feature = "async")]
+mod async_image;
+#[cfg(feature = "async")]
+pub use async_image::AsyncRawDiskImage;
+
+/// Raw disk image, a whole file or split into segments (`disk.img.001`, `disk.img.002`, ...)
 #[derive(Clone)]
 #[cfg_attr(any(feature = "std", test), derive(Debug))]
-pub struct RawDiskImage {
-    file: File, // TODO: replace with transport
+pub struct RawDiskImage<S: Stream = File> {
+    segments: ConcatDisk<StreamDisk<S>>,
     capacity: u64,
     geometry: Geometry,
-    file_path: String,
+    file_paths: Vec<String>,
+    read_only: bool,
 }
 
-impl ReadAt for RawDiskImage {
+impl<S: Stream> ReadAt for RawDiskImage<S> {
     fn read_at(&self, offset: u64, data: &mut [u8]) -> Result<usize> {
-        self.file.read_at(offset, data)
+        self.segments.read_at(offset, data)
     }
 }
 
-impl WriteAt for RawDiskImage {
+impl<S: Stream> WriteAt for RawDiskImage<S> {
     fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
-        self.file.write_at(offset, data)
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        self.segments.write_at(offset, data)
     }
 }
 
-impl Flush for RawDiskImage {
+impl<S: Stream> Flush for RawDiskImage<S> {
     fn flush(&self) -> Result<()> {
-        self.file.flush().map_err(From::from)
+        if self.read_only {
+            return Ok(());
+        }
+
+        self.segments.flush()
     }
 }
 
-impl Disk for RawDiskImage {
+impl<S: Stream> Disk for RawDiskImage<S> {
     fn geometry(&self) -> Result<Geometry> {
         Ok(self.geometry)
     }
@@ -39,14 +55,31 @@ impl Disk for RawDiskImage {
     fn physical_sector_size(&self) -> Result<u32> {
         Ok(self.geometry.bytes_per_sector)
     }
+
+    /// The files may deallocate the range, block devices discard it
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        self.segments.discard(offset, len)
+    }
+
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        self.segments.write_zeroes(offset, len)
+    }
 }
 
-impl DiskImage for RawDiskImage {
+impl<S: Stream> DiskImage for RawDiskImage<S> {
     const NAME: &'static str = "RAW";
-    const EXT: &'static [&'static str] = &["dd", "img", "bin"];
+    const EXT: &'static [&'static str] = &["dd", "img", "bin", "iso"];
 
     fn backing_files(&self) -> Box<dyn core::iter::Iterator<Item = String>> {
-        Box::new(core::iter::once(self.file_path.clone()))
+        Box::new(self.file_paths.clone().into_iter())
     }
 
     fn storage_size(&self) -> Result<u64> {
@@ -56,30 +89,197 @@ impl DiskImage for RawDiskImage {
 
 impl RawDiskImage {
     pub fn open<S: Into<String>>(path: S) -> Result<Self> {
+        Self::open_with(path, &OpenOptions::default())
+    }
+
+    pub fn open_with<S: Into<String>>(path: S, options: &OpenOptions) -> Result<Self> {
         let path = path.into();
-        let file = File::open(&path)?;
-        let capacity = file.size()?;
+        let file = File::open_with(&path, options)?;
+        Self::open_segments(vec![file], vec![path], options.read_only)
+    }
+
+    /// Opens the first segment of a split image (`disk.img.001`) and all the following ones
+    pub fn open_split<S: Into<String>>(path: S) -> Result<Self> {
+        Self::open_split_with(path, &OpenOptions::default())
+    }
+
+    pub fn open_split_with<S: Into<String>>(path: S, options: &OpenOptions) -> Result<Self> {
+        let path = path.into();
+        let mut files = vec![File::open_with(&path, options)?];
+        let mut paths = vec![path];
+        // the numbering ends with the first missing segment
+        while let Some(next_path) = next_segment_path(paths.last().unwrap()) {
+            match File::open_with(&next_path, options) {
+                Ok(file) => {
+                    files.push(file);
+                    paths.push(next_path);
+                }
+                Err(_) => break,
+            }
+        }
+
+        Self::open_segments(files, paths, options.read_only)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl RawDiskImage<crate::uring::UringFile> {
+    /// Reads and writes through an io_uring with up to `queue_depth` operations in flight
+    pub fn open_uring<S: Into<String>>(path: S, options: &OpenOptions, queue_depth: u32) -> Result<Self> {
+        let path = path.into();
+        let file = crate::uring::UringFile::open_with(&path, options)?.with_queue_depth(queue_depth)?;
+        Self::open_segments(vec![file], vec![path], options.read_only)
+    }
+}
+
+/// `disk.img.001` -> `disk.img.002`, `None` if the extension is not a segment number or it overflows
+fn next_segment_path(path: &str) -> Option<String> {
+    let dot = path.rfind('.')?;
+    let number = &path[dot + 1..];
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let next = format!("{:0width$}", number.parse::<u64>().ok()? + 1, width = number.len());
+    if next.len() > number.len() {
+        return None;
+    }
+
+    Some(format!("{}.{}", &path[..dot], next))
+}
+
+impl<S: Stream> RawDiskImage<S> {
+    /// Uses the whole `stream` as the disk, [`backing_files`](DiskImage::backing_files) is empty then
+    pub fn open_stream(stream: S) -> Result<Self> {
+        Self::open_segments(vec![stream], Vec::new(), false)
+    }
+
+    /// Like [`open_stream`](Self::open_stream), but the stream is never written
+    pub fn open_stream_read_only(stream: S) -> Result<Self> {
+        Self::open_segments(vec![stream], Vec::new(), true)
+    }
+
+    /// The disk is the `streams` joined one after another, like the segments of a split image
+    pub fn open_split_streams(streams: Vec<S>) -> Result<Self> {
+        Self::open_segments(streams, Vec::new(), false)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Number of the image files or streams
+    pub fn segments_count(&self) -> usize {
+        self.segments.disks().len()
+    }
+
+    fn open_segments(streams: Vec<S>, file_paths: Vec<String>, read_only: bool) -> Result<Self> {
+        let mut disks = Vec::with_capacity(streams.len());
+        for stream in streams {
+            let size = stream.size()?;
+            disks.push(StreamDisk::new(stream, size));
+        }
+
+        let segments = ConcatDisk::new(disks)?;
+        let capacity = segments.capacity()?;
 
         let mut image = Self {
-            file,
+            segments,
             capacity,
             geometry: Geometry::with_vhd_capacity(capacity),
-            file_path: path,
+            file_paths,
+            read_only,
         };
 
-        // Make a better guess about the geometry based on MBR data
-        if let Some(geometry) = Geometry::detect(&image)? {
-            image.geometry = geometry;
+        // Make a better guess about the geometry based on MBR data.
+        // The geometry is only a hint: it may cover more than the image holds after rounding to whole cylinders,
+        // and an image that is not a multiple of the sector size keeps its partial last sector readable.
+        if capacity >= sizes::SECTOR_U64 {
+            if let Some(geometry) = Geometry::detect(&image)? {
+                image.geometry = geometry;
+            }
         }
 
-        if capacity % 512 != 0 {
-            todo!("log warning")
-        }
+        Ok(image)
+    }
+}
 
-        if capacity < image.geometry.capacity() {
-            todo!("log warning")
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStream;
 
-        Ok(image)
+    #[test]
+    fn raw_stream_test() {
+        let stream = MemoryStream::from_vec(vec![0_u8; 1024 * 1024]);
+        let image = RawDiskImage::open_stream(stream.clone()).unwrap();
+        assert_eq!(1024 * 1024, image.capacity().unwrap());
+        assert_eq!(None, image.backing_files().next());
+
+        image.write_all_at(4096, b"raw").unwrap();
+        assert_eq!(b"raw", &stream.to_vec()[4096..4099]);
+
+        let image = RawDiskImage::open_stream_read_only(stream.clone()).unwrap();
+        assert!(image.is_read_only());
+        assert!(matches!(image.write_at(0, b"raw"), Err(Error::ReadOnly)));
+        image.flush().unwrap();
+        assert_eq!(0, stream.to_vec()[0]);
+    }
+
+    #[test]
+    fn split_raw_test() {
+        let segments = vec![
+            MemoryStream::from_vec(vec![1_u8; 1024]),
+            MemoryStream::from_vec(vec![2_u8; 1024]),
+            MemoryStream::from_vec(vec![3_u8; 512]),
+        ];
+        let image = RawDiskImage::open_split_streams(segments.clone()).unwrap();
+        assert_eq!(2560, image.capacity().unwrap());
+        assert_eq!(2560, image.storage_size().unwrap());
+        assert_eq!(3, image.segments_count());
+
+        let mut buffer = vec![0_u8; 4];
+        image.read_exact_at(2046, &mut buffer).unwrap();
+        assert_eq!(&[2, 2, 3, 3], buffer.as_slice());
+
+        image.write_all_at(1022, b"join").unwrap();
+        assert_eq!(b"jo", &segments[0].to_vec()[1022..]);
+        assert_eq!(b"in", &segments[1].to_vec()[..2]);
+        assert!(matches!(image.write_at(2561, b"x"), Err(Error::WriteBeyondEOD)));
+
+        image.discard(1000, 1100).unwrap();
+        assert!(segments[0].to_vec()[1000..].iter().all(|&b| b == 0));
+        assert!(segments[1].to_vec().iter().all(|&b| b == 0));
+        assert!(segments[2].to_vec()[..52].iter().all(|&b| b == 0));
+        assert_eq!(3, segments[2].to_vec()[52]);
+        assert_eq!(512, segments[2].size().unwrap());
+    }
+
+    #[test]
+    fn odd_size_stream_test() {
+        let mut data = vec![7_u8; 1000];
+        // an MBR partition ending at head 15, sector 63, the geometry covers more than 1000 bytes
+        data[446 + 5] = 15;
+        data[446 + 6] = 63;
+        data[510] = 0x55;
+        data[511] = 0xAA;
+        let image = RawDiskImage::open_stream(MemoryStream::from_vec(data)).unwrap();
+        assert_eq!(1000, image.capacity().unwrap());
+        assert_eq!(16, image.geometry().unwrap().heads_per_cylinder);
+        let mut buffer = [0_u8; 8];
+        assert_eq!(8, image.read_at(992, &mut buffer).unwrap());
+        assert_eq!([7_u8; 8], buffer);
+
+        let image = RawDiskImage::open_stream(MemoryStream::from_vec(vec![1_u8; 100])).unwrap();
+        assert_eq!(100, image.capacity().unwrap());
+        assert!(matches!(image.read_at(101, &mut buffer), Err(Error::ReadBeyondEOD)));
+    }
+
+    #[test]
+    fn segment_path_test() {
+        assert_eq!(Some("disk.img.002".to_string()), next_segment_path("disk.img.001"));
+        assert_eq!(Some("c:\\disk.010".to_string()), next_segment_path("c:\\disk.009"));
+        assert_eq!(None, next_segment_path("disk.img.999"));
+        assert_eq!(None, next_segment_path("disk.img"));
     }
 }
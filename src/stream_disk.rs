@@ -0,0 +1,158 @@
+use crate::prelude::*;
+
+/// Disk on top of any random access storage
+///
+/// The storage may be shorter than the disk, the missing sectors read as zeroes
+/// and writes extend the storage.
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct StreamDisk<T> {
+    stream: T,
+    capacity: u64,
+    geometry: Geometry,
+    physical_sector_size: u32,
+}
+
+impl<T: ReadAt> ReadAt for StreamDisk<T> {
+    fn read_at(&self, offset: u64, data: &mut [u8]) -> Result<usize> {
+        let data_len = math::bound_to(self.capacity, offset, data.len()).ok_or(Error::ReadBeyondEOD)?;
+        let data = &mut data[..data_len];
+        match self.stream.read_at(offset, data)? {
+            0 => {
+                // past the end of the storage
+                for b in data.iter_mut() {
+                    *b = 0;
+                }
+
+                Ok(data_len)
+            }
+            n => Ok(n),
+        }
+    }
+}
+
+impl<T: WriteAt> WriteAt for StreamDisk<T> {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        match math::bound_to(self.capacity, offset, data.len()) {
+            Some(data_len) => self.stream.write_at(offset, &data[..data_len]),
+            None => Err(Error::WriteBeyondEOD),
+        }
+    }
+}
+
+impl<T: Flush> Flush for StreamDisk<T> {
+    fn flush(&self) -> Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<T: Stream> Disk for StreamDisk<T> {
+    fn geometry(&self) -> Result<Geometry> {
+        Ok(self.geometry)
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        Ok(self.capacity)
+    }
+
+    fn physical_sector_size(&self) -> Result<u32> {
+        Ok(self.physical_sector_size)
+    }
+
+    /// Only the stored part is discarded, a file may deallocate it
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        let size = self.stream.size()?;
+        if offset < size {
+            self.stream.discard_range(offset, core::cmp::min(len, size - offset))?;
+        }
+
+        Ok(())
+    }
+
+    /// Only the stored part is zeroed, the storage is not extended
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        let size = self.stream.size()?;
+        if offset < size {
+            self.stream.zero_range(offset, core::cmp::min(len, size - offset))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> StreamDisk<T> {
+    /// 512 byte sectors and the VHD geometry
+    pub fn new(stream: T, capacity: u64) -> Self {
+        Self {
+            stream,
+            capacity,
+            geometry: Geometry::with_vhd_capacity(capacity),
+            physical_sector_size: crate::sizes::SECTOR,
+        }
+    }
+
+    /// Sets the logical sector size (the geometry is recalculated) and the physical one
+    pub fn with_sector_size(mut self, logical: u32, physical: u32) -> Self {
+        self.geometry = Geometry::with_vhd_capacity_and_sector(self.capacity, logical);
+        self.physical_sector_size = physical;
+        self
+    }
+
+    /// The geometry also sets the logical sector size
+    pub fn with_geometry(mut self, geometry: Geometry) -> Self {
+        self.geometry = geometry;
+        self
+    }
+
+    pub fn stream(&self) -> &T {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> T {
+        self.stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStream;
+
+    #[test]
+    fn stream_disk_test() {
+        let stream = MemoryStream::from_vec(vec![0xAA_u8; 1000]);
+        let disk = StreamDisk::new(stream.clone(), 4096).with_sector_size(4096, 4096);
+        assert_eq!(4096, disk.logical_sector_size().unwrap());
+        assert_eq!(4096, disk.physical_sector_size().unwrap());
+        assert_eq!(4096, disk.capacity().unwrap());
+
+        // the storage is shorter than the disk
+        let mut buffer = vec![0x55_u8; 2000];
+        disk.read_exact_at(0, &mut buffer).unwrap();
+        assert!(buffer[..1000].iter().all(|&b| b == 0xAA));
+        assert!(buffer[1000..].iter().all(|&b| b == 0));
+
+        assert_eq!(1, disk.write_at(4095, b"xy").unwrap());
+        assert_eq!(4096, stream.size().unwrap());
+        match disk.write_at(4097, b"x") {
+            Err(Error::WriteBeyondEOD) => (),
+            _ => panic!("write beyond the end expected"),
+        }
+        match disk.read_at(4097, &mut buffer) {
+            Err(Error::ReadBeyondEOD) => (),
+            _ => panic!("read beyond the end expected"),
+        }
+
+        let disk = disk.with_geometry(Geometry::chs(2, 2, 2));
+        assert_eq!(512, disk.logical_sector_size().unwrap());
+        assert_eq!(2, disk.geometry().unwrap().cylinders);
+    }
+}
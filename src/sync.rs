@@ -0,0 +1,241 @@
+//! Locks for the shared image state
+//!
+//! With `std` these are the `std::sync` locks, a waiting thread blocks and a lock poisoned by a panic stays usable.
+//! `core` has none and the crate has to work without `std`, spin locks are used then.
+//! The critical sections are short (table lookups and bitmap updates), no lock is held during the file I/O
+//! except the read-modify-write of a partial sector on a direct file.
+#[cfg(not(feature = "std"))]
+pub(crate) use self::spin::{Mutex, RwLock};
+#[cfg(feature = "std")]
+pub(crate) use self::std_locks::{Mutex, RwLock};
+
+#[cfg(feature = "std")]
+mod std_locks {
+    use std::sync::{MutexGuard, PoisonError, RwLockReadGuard, RwLockWriteGuard};
+
+    #[derive(Default)]
+    pub(crate) struct Mutex<T>(std::sync::Mutex<T>);
+
+    impl<T> Mutex<T> {
+        pub fn new(value: T) -> Self {
+            Self(std::sync::Mutex::new(value))
+        }
+
+        pub fn lock(&self) -> MutexGuard<'_, T> {
+            self.0.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    /// Any number of readers or one writer
+    #[derive(Default)]
+    pub(crate) struct RwLock<T>(std::sync::RwLock<T>);
+
+    impl<T> RwLock<T> {
+        pub fn new(value: T) -> Self {
+            Self(std::sync::RwLock::new(value))
+        }
+
+        pub fn read(&self) -> RwLockReadGuard<'_, T> {
+            self.0.read().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+            self.0.write().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod spin {
+    use core::cell::UnsafeCell;
+    use core::ops::{Deref, DerefMut};
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    fn relax() {
+        core::hint::spin_loop();
+    }
+
+    pub(crate) struct Mutex<T> {
+        locked: AtomicBool,
+        value: UnsafeCell<T>,
+    }
+
+    unsafe impl<T: Send> Send for Mutex<T> {}
+    unsafe impl<T: Send> Sync for Mutex<T> {}
+
+    impl<T: Default> Default for Mutex<T> {
+        fn default() -> Self {
+            Self::new(T::default())
+        }
+    }
+
+    impl<T> Mutex<T> {
+        pub fn new(value: T) -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        pub fn lock(&self) -> MutexGuard<'_, T> {
+            while self
+                .locked
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                relax();
+            }
+
+            MutexGuard { lock: self }
+        }
+    }
+
+    pub(crate) struct MutexGuard<'a, T> {
+        lock: &'a Mutex<T>,
+    }
+
+    impl<T> Deref for MutexGuard<'_, T> {
+        type Target = T;
+        fn deref(&self) -> &T {
+            unsafe { &*self.lock.value.get() }
+        }
+    }
+
+    impl<T> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            unsafe { &mut *self.lock.value.get() }
+        }
+    }
+
+    impl<T> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            self.lock.locked.store(false, Ordering::Release);
+        }
+    }
+
+    const WRITER: usize = usize::MAX;
+
+    /// Any number of readers or one writer
+    pub(crate) struct RwLock<T> {
+        /// Readers count or `WRITER`
+        state: AtomicUsize,
+        value: UnsafeCell<T>,
+    }
+
+    unsafe impl<T: Send> Send for RwLock<T> {}
+    unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+    impl<T: Default> Default for RwLock<T> {
+        fn default() -> Self {
+            Self::new(T::default())
+        }
+    }
+
+    impl<T> RwLock<T> {
+        pub fn new(value: T) -> Self {
+            Self {
+                state: AtomicUsize::new(0),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        pub fn read(&self) -> ReadGuard<'_, T> {
+            loop {
+                let state = self.state.load(Ordering::Relaxed);
+                if state < WRITER - 1
+                    && self
+                        .state
+                        .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+                {
+                    return ReadGuard { lock: self };
+                }
+
+                relax();
+            }
+        }
+
+        pub fn write(&self) -> WriteGuard<'_, T> {
+            while self
+                .state
+                .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                relax();
+            }
+
+            WriteGuard { lock: self }
+        }
+    }
+
+    pub(crate) struct ReadGuard<'a, T> {
+        lock: &'a RwLock<T>,
+    }
+
+    impl<T> Deref for ReadGuard<'_, T> {
+        type Target = T;
+        fn deref(&self) -> &T {
+            unsafe { &*self.lock.value.get() }
+        }
+    }
+
+    impl<T> Drop for ReadGuard<'_, T> {
+        fn drop(&mut self) {
+            self.lock.state.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    pub(crate) struct WriteGuard<'a, T> {
+        lock: &'a RwLock<T>,
+    }
+
+    impl<T> Deref for WriteGuard<'_, T> {
+        type Target = T;
+        fn deref(&self) -> &T {
+            unsafe { &*self.lock.value.get() }
+        }
+    }
+
+    impl<T> DerefMut for WriteGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            unsafe { &mut *self.lock.value.get() }
+        }
+    }
+
+    impl<T> Drop for WriteGuard<'_, T> {
+        fn drop(&mut self) {
+            self.lock.state.store(0, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn locks_test() {
+        let counter = Arc::new(Mutex::new(0_u32));
+        let table = Arc::new(RwLock::new(vec![0_u32; 4]));
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let counter = counter.clone();
+                let table = table.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        *counter.lock() += 1;
+                        table.write()[i] += 1;
+                        assert!(table.read().iter().sum::<u32>() > 0);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(4000, *counter.lock());
+        assert_eq!(&[1000; 4], table.read().as_slice());
+    }
+}
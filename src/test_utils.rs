@@ -0,0 +1,6 @@
+//! Helpers shared by the unit tests
+
+/// Bytes with a period of 251, `seed` shifts the pattern so the adjacent parts of a disk can be built separately
+pub fn pattern(size: usize, seed: usize) -> Vec<u8> {
+    (0..size).map(|i| ((i + seed) * 7 % 251) as u8).collect()
+}
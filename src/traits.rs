@@ -4,11 +4,15 @@ pub trait ReadAt {
     fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize>;
 
     fn read_exact_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        let mut offset = offset;
         let mut buffer = buffer;
         while !buffer.is_empty() {
             match self.read_at(offset, buffer) {
                 Ok(0) => break, // EOF
-                Ok(n) => buffer = &mut buffer[n..],
+                Ok(n) => {
+                    buffer = &mut buffer[n..];
+                    offset += n as u64;
+                }
                 Err(e) => return Err(e),
             }
         }
@@ -19,6 +23,50 @@ pub trait ReadAt {
             Err(Error::UnexpectedEOD)
         }
     }
+
+    /// Fills the `buffers` one after another with the data at `offset`, returns the total read.
+    /// Stops early at the end of the data only.
+    fn read_vectored_at(&self, offset: u64, buffers: &mut [&mut [u8]]) -> Result<usize> {
+        let mut total = 0;
+        for buffer in buffers.iter_mut() {
+            let mut filled = 0;
+            while filled < buffer.len() {
+                match self.read_at(offset + (total + filled) as u64, &mut buffer[filled..])? {
+                    0 => return Ok(total + filled), // EOF
+                    n => filled += n,
+                }
+            }
+            total += filled;
+        }
+
+        Ok(total)
+    }
+
+    /// Reads every `(offset, buffer)` request in full, in any order.
+    /// Fails with [`Error::UnexpectedEOD`] if a request reaches the end of the data.
+    /// The backends that can submit the requests at once override it.
+    fn read_batch_at(&self, requests: &mut [(u64, &mut [u8])]) -> Result<()> {
+        for (offset, buffer) in requests.iter_mut() {
+            self.read_exact_at(*offset, buffer)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lets readers that take the device by value borrow it instead
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        (**self).read_at(offset, buffer)
+    }
+
+    fn read_vectored_at(&self, offset: u64, buffers: &mut [&mut [u8]]) -> Result<usize> {
+        (**self).read_vectored_at(offset, buffers)
+    }
+
+    fn read_batch_at(&self, requests: &mut [(u64, &mut [u8])]) -> Result<()> {
+        (**self).read_batch_at(requests)
+    }
 }
 
 pub trait WriteAt {
@@ -41,12 +89,50 @@ pub trait WriteAt {
         }
         Ok(())
     }
+
+    /// Writes the `buffers` one after another starting at `offset`, returns the total written
+    fn write_vectored_at(&self, offset: u64, buffers: &[&[u8]]) -> Result<usize> {
+        let mut total = 0;
+        for data in buffers {
+            self.write_all_at(offset + total as u64, data)?;
+            total += data.len();
+        }
+
+        Ok(total)
+    }
+
+    /// Writes every `(offset, data)` request in full, in any order
+    fn write_batch_at(&self, requests: &[(u64, &[u8])]) -> Result<()> {
+        for (offset, data) in requests {
+            self.write_all_at(*offset, data)?;
+        }
+
+        Ok(())
+    }
 }
 
 pub trait Flush {
     fn flush(&self) -> Result<()>;
 }
 
+/// Random access storage the disk images are kept in: a platform [`File`](crate::File), memory, etc.
+pub trait Stream: ReadAt + WriteAt + Flush {
+    /// Current size in bytes, writes past the end extend the stream
+    fn size(&self) -> Result<u64>;
+
+    /// Fills the range inside the stream with zeroes, the storage may be deallocated (a hole punched).
+    /// Writes zeroes by default.
+    fn zero_range(&self, offset: u64, len: u64) -> Result<()> {
+        tools::write_zeroes(self, offset, len)
+    }
+
+    /// Tells the storage the range inside the stream is no longer needed, its contents are undefined afterwards.
+    /// Zeroes the range by default.
+    fn discard_range(&self, offset: u64, len: u64) -> Result<()> {
+        self.zero_range(offset, len)
+    }
+}
+
 pub trait Disk: ReadAt + WriteAt + Flush {
     fn geometry(&self) -> Result<Geometry>;
     fn capacity(&self) -> Result<u64>;
@@ -55,6 +141,25 @@ pub trait Disk: ReadAt + WriteAt + Flush {
     fn logical_sector_size(&self) -> Result<u32> {
         Ok(self.geometry()?.bytes_per_sector)
     }
+
+    /// Tells the disk the range is no longer needed (TRIM), its contents are undefined afterwards.
+    /// Does nothing by default.
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity()?, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        Ok(())
+    }
+
+    /// Fills the range with zeroes, the disks that can do it without writing the data override it
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if !math::is_range_valid(self.capacity()?, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        tools::write_zeroes(self, offset, len)
+    }
 }
 
 pub trait DiskImage: Disk {
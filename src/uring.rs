@@ -0,0 +1,535 @@
+//! Linux io_uring file backend for the bulk conversion and hashing of large images
+//!
+//! [`UringFile`] splits the large requests and submits the batches at once, keeping up to the queue depth
+//! of operations in flight. [`copy`] pipelines the reads of one disk with the writes of another.
+use crate::aligned::{is_aligned, read_bounced, write_bounced, DirectWriteLock};
+use crate::platform::{create_preallocated_std, discard_block_range, is_block_device, open_std, punch_hole, zero_block_range};
+use crate::prelude::*;
+use crate::AlignedBuffer;
+use io_uring::{opcode, squeue, types, IoUring};
+use std::os::unix::io::AsRawFd;
+use std::sync::{mpsc, Mutex};
+
+/// Operations in flight by default
+pub const DEFAULT_QUEUE_DEPTH: u32 = 32;
+
+/// Larger requests are split into operations of this size
+const MAX_OPERATION_SIZE: usize = 1024 * 1024;
+
+/// `O_DIRECT` I/O has to be aligned to the logical block size of the device, 4 KiB covers both 512 and 4K sectors
+const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// From linux/io_uring.h, the io-uring crate does not export it
+const IORING_ENTER_GETEVENTS: u32 = 1;
+
+/// File read and written through an io_uring
+///
+/// The ring is locked for a whole request or batch, the concurrent requests are not merged.
+/// Opened with [`OpenOptions::direct`], the unaligned requests are bounced and not batched.
+pub struct UringFile {
+    file: std::fs::File,
+    ring: Mutex<IoUring>,
+    queue_depth: u32,
+    /// Opened with `O_DIRECT`, the requests are bounced to this alignment
+    direct_alignment: Option<usize>,
+    direct_lock: DirectWriteLock,
+}
+
+#[cfg(any(feature = "std", test))]
+impl core::fmt::Debug for UringFile {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("UringFile")
+            .field("file", &self.file)
+            .field("queue_depth", &self.queue_depth)
+            .field("direct_alignment", &self.direct_alignment)
+            .finish()
+    }
+}
+
+/// Part of a request submitted as one read or write
+struct Operation {
+    offset: u64,
+    ptr: *mut u8,
+    len: usize,
+    write: bool,
+    /// Less than `len` after a read reached the end of the file
+    done: usize,
+}
+
+impl Operation {
+    /// Splits the buffer, it has to outlive the operations
+    fn split(operations: &mut Vec<Operation>, offset: u64, ptr: *mut u8, len: usize, write: bool) {
+        let mut pos = 0;
+        while pos < len {
+            let n = core::cmp::min(len - pos, MAX_OPERATION_SIZE);
+            operations.push(Operation {
+                offset: offset + pos as u64,
+                ptr: unsafe { ptr.add(pos) },
+                len: n,
+                write,
+                done: 0,
+            });
+            pos += n;
+        }
+    }
+
+    /// The remainder of the operation
+    fn entry(&self, fd: types::Fd) -> squeue::Entry {
+        let ptr = unsafe { self.ptr.add(self.done) };
+        let len = (self.len - self.done) as u32;
+        let offset = self.offset + self.done as u64;
+        if self.write {
+            opcode::Write::new(fd, ptr, len).offset(offset).build()
+        } else {
+            opcode::Read::new(fd, ptr, len).offset(offset).build()
+        }
+    }
+}
+
+/// Bytes done before the first incomplete operation
+fn done_prefix(operations: &[Operation]) -> usize {
+    let mut total = 0;
+    for op in operations {
+        total += op.done;
+        if op.done < op.len {
+            break;
+        }
+    }
+
+    total
+}
+
+impl UringFile {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::open_with(path, &OpenOptions::default())
+    }
+
+    pub fn open_with(path: &str, options: &OpenOptions) -> Result<Self> {
+        let mut file = Self::from_std(open_std(path, options)?)?;
+        if options.direct {
+            file.direct_alignment = Some(DIRECT_IO_ALIGNMENT);
+        }
+
+        Ok(file)
+    }
+
+    /// Fails if the file exists
+    pub fn create_preallocated(path: &str, size: u64) -> Result<Self> {
+        Self::from_std(create_preallocated_std(path, size)?)
+    }
+
+    /// The file is used as opened, with the [default queue depth](DEFAULT_QUEUE_DEPTH)
+    pub fn from_std(file: std::fs::File) -> Result<Self> {
+        Ok(Self {
+            file,
+            ring: Mutex::new(IoUring::new(DEFAULT_QUEUE_DEPTH)?),
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            direct_alignment: None,
+            direct_lock: DirectWriteLock::default(),
+        })
+    }
+
+    /// Replaces the ring, the kernel rounds the depth up to a power of two and limits it
+    pub fn with_queue_depth(mut self, queue_depth: u32) -> Result<Self> {
+        self.ring = Mutex::new(IoUring::new(queue_depth)?);
+        self.queue_depth = queue_depth;
+        Ok(self)
+    }
+
+    pub fn queue_depth(&self) -> u32 {
+        self.queue_depth
+    }
+
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    /// Opened with [`OpenOptions::direct`]
+    pub fn is_direct(&self) -> bool {
+        self.direct_alignment.is_some()
+    }
+
+    fn read_raw(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let mut operations = Vec::new();
+        Operation::split(&mut operations, offset, buffer.as_mut_ptr(), buffer.len(), false);
+        self.run(&mut operations)?;
+        Ok(done_prefix(&operations))
+    }
+
+    fn write_raw(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        let mut operations = Vec::new();
+        Operation::split(&mut operations, offset, data.as_ptr() as *mut u8, data.len(), true);
+        self.run(&mut operations)?;
+        Ok(data.len())
+    }
+
+    /// Keeps up to the queue depth of `operations` in flight until all of them are done.
+    /// The interrupted and short ones are resubmitted, a read stops at the end of the file.
+    /// After an error nothing new is submitted, the operations in flight are waited for.
+    fn run(&self, operations: &mut [Operation]) -> Result<()> {
+        let fd = types::Fd(self.file.as_raw_fd());
+        let mut ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        if !ring.submission().is_empty() {
+            // left queued by a failed submission, the buffers of these entries are gone
+            *ring = IoUring::new(self.queue_depth)?;
+        }
+
+        let mut pending: Vec<usize> = (0..operations.len()).rev().collect();
+        let mut in_flight = 0_usize;
+        let mut error = None;
+        let mut submit_failed = false;
+
+        loop {
+            while error.is_none() && in_flight < self.queue_depth as usize {
+                let index = match pending.pop() {
+                    Some(index) => index,
+                    None => break,
+                };
+
+                let entry = operations[index].entry(fd).user_data(index as u64);
+                if unsafe { ring.submission().push(&entry) }.is_err() {
+                    // the kernel limited the queue below the depth
+                    pending.push(index);
+                    break;
+                }
+                in_flight += 1;
+            }
+
+            if in_flight == 0 {
+                break;
+            }
+
+            let waited = if submit_failed {
+                // the kernel still borrows the buffers of the operations it took, only their completions are waited for
+                unsafe { ring.submitter().enter::<libc::sigset_t>(0, 1, IORING_ENTER_GETEVENTS, None) }
+            } else {
+                ring.submit_and_wait(1)
+            };
+            match waited {
+                Ok(_) => (),
+                Err(e) if submit_failed || matches!(e.raw_os_error(), Some(libc::EINTR) | Some(libc::EAGAIN) | Some(libc::EBUSY)) => {
+                    continue
+                }
+                Err(e) => {
+                    // the entries the kernel did not take stay queued until the next run replaces the ring
+                    in_flight -= ring.submission().len();
+                    submit_failed = true;
+                    error.get_or_insert(Error::from(e));
+                }
+            }
+
+            for cqe in ring.completion() {
+                in_flight -= 1;
+                let index = cqe.user_data() as usize;
+                let op = &mut operations[index];
+                match cqe.result() {
+                    r if r == -libc::EINTR || r == -libc::EAGAIN => pending.push(index),
+                    r if r < 0 => {
+                        error.get_or_insert_with(|| Error::from(std::io::Error::from_raw_os_error(-r)));
+                    }
+                    0 if op.write => {
+                        error.get_or_insert(Error::WriteZero);
+                    }
+                    0 => (),
+                    n => {
+                        op.done += n as usize;
+                        if op.done < op.len {
+                            pending.push(index);
+                        }
+                    }
+                }
+            }
+        }
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ReadAt for UringFile {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        match self.direct_alignment {
+            Some(align) => read_bounced(align, offset, buffer, |offset, buffer| self.read_raw(offset, buffer)),
+            None => self.read_raw(offset, buffer),
+        }
+    }
+
+    /// Submits all the requests at once
+    fn read_batch_at(&self, requests: &mut [(u64, &mut [u8])]) -> Result<()> {
+        let mut operations = Vec::new();
+        for (offset, buffer) in requests.iter_mut() {
+            match self.direct_alignment {
+                Some(align) if !is_aligned(*offset, buffer, align) => self.read_exact_at(*offset, buffer)?,
+                _ => Operation::split(&mut operations, *offset, buffer.as_mut_ptr(), buffer.len(), false),
+            }
+        }
+
+        self.run(&mut operations)?;
+        if operations.iter().any(|op| op.done < op.len) {
+            return Err(Error::UnexpectedEOD);
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteAt for UringFile {
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        match self.direct_alignment {
+            Some(align) => self.direct_lock.write(align, offset, data.len(), || {
+                write_bounced(
+                    align,
+                    offset,
+                    data,
+                    |offset, buffer| self.read_raw(offset, buffer),
+                    |offset, data| self.write_raw(offset, data),
+                )
+            }),
+            None => self.write_raw(offset, data),
+        }
+    }
+
+    /// Submits all the requests at once
+    fn write_batch_at(&self, requests: &[(u64, &[u8])]) -> Result<()> {
+        let mut operations = Vec::new();
+        for (offset, data) in requests {
+            match self.direct_alignment {
+                Some(align) if !is_aligned(*offset, data, align) => self.write_all_at(*offset, data)?,
+                _ => Operation::split(&mut operations, *offset, data.as_ptr() as *mut u8, data.len(), true),
+            }
+        }
+
+        match self.direct_alignment {
+            // the whole sector writes, not to be lost by a concurrent read-modify-write
+            Some(align) => self.direct_lock.write(align, 0, 0, || self.run(&mut operations)),
+            None => self.run(&mut operations),
+        }
+    }
+}
+
+impl Flush for UringFile {
+    fn flush(&self) -> Result<()> {
+        Ok(self.file.sync_data()?)
+    }
+}
+
+impl Stream for UringFile {
+    fn size(&self) -> Result<u64> {
+        UringFile::size(self)
+    }
+
+    /// Punches a hole, a block device zeroes the whole logical sectors by `BLKZEROOUT`.
+    /// The file system may not support it and the zeroes are written then.
+    fn zero_range(&self, offset: u64, len: u64) -> Result<()> {
+        if is_block_device(&self.file)? {
+            return zero_block_range(&self.file, offset, len, |offset, len| tools::write_zeroes(self, offset, len));
+        }
+        if !punch_hole(&self.file, offset, len)? {
+            tools::write_zeroes(self, offset, len)?;
+        }
+
+        Ok(())
+    }
+
+    /// A block device discards the whole logical sectors by `BLKDISCARD`, a file is zeroed
+    fn discard_range(&self, offset: u64, len: u64) -> Result<()> {
+        if is_block_device(&self.file)? {
+            discard_block_range(&self.file, offset, len)
+        } else {
+            self.zero_range(offset, len)
+        }
+    }
+}
+
+/// Copies the whole `src` disk to the start of `dst` and flushes it, returns the number of bytes copied.
+/// Reads pieces of the [default queue depth](DEFAULT_QUEUE_DEPTH) of operations, see [`copy_with_queue_depth`].
+pub fn copy<S, D>(src: &S, dst: &D) -> Result<u64>
+where
+    S: Disk + Sync + ?Sized,
+    D: Disk + Sync + ?Sized,
+{
+    copy_with_queue_depth(src, dst, DEFAULT_QUEUE_DEPTH)
+}
+
+/// A reader thread fetches the next pieces while the current one is written,
+/// each piece is large enough to fill a queue of `queue_depth` operations.
+/// Fails with [`Error::WriteBeyondEOD`] if `dst` is smaller than `src`.
+pub fn copy_with_queue_depth<S, D>(src: &S, dst: &D, queue_depth: u32) -> Result<u64>
+where
+    S: Disk + Sync + ?Sized,
+    D: Disk + Sync + ?Sized,
+{
+    let capacity = src.capacity()?;
+    if dst.capacity()? < capacity {
+        return Err(Error::WriteBeyondEOD);
+    }
+
+    let piece = core::cmp::max(queue_depth, 1) as usize * MAX_OPERATION_SIZE;
+    let align = core::cmp::max(src.physical_sector_size()?, dst.physical_sector_size()?) as usize;
+
+    std::thread::scope(|scope| {
+        // one buffer is read, one waits in the channel and one is written
+        let (full_tx, full_rx) = mpsc::sync_channel::<(u64, usize, AlignedBuffer)>(1);
+        let (free_tx, free_rx) = mpsc::channel();
+        for _ in 0..3 {
+            free_tx.send(AlignedBuffer::new(piece, align)).unwrap();
+        }
+
+        let reader = scope.spawn(move || -> Result<()> {
+            let mut offset = 0;
+            while offset < capacity {
+                // the writer failed when the channels are closed
+                let mut buffer: AlignedBuffer = match free_rx.recv() {
+                    Ok(buffer) => buffer,
+                    Err(_) => return Ok(()),
+                };
+
+                let len = core::cmp::min(capacity - offset, piece as u64) as usize;
+                src.read_exact_at(offset, &mut buffer[..len])?;
+                if full_tx.send((offset, len, buffer)).is_err() {
+                    return Ok(());
+                }
+                offset += len as u64;
+            }
+
+            Ok(())
+        });
+
+        let mut written = 0;
+        let mut write_result = Ok(());
+        for (offset, len, buffer) in full_rx.iter() {
+            if let Err(e) = dst.write_all_at(offset, &buffer[..len]) {
+                write_result = Err(e);
+                break;
+            }
+
+            written += len as u64;
+            // the reader may be done already
+            let _ = free_tx.send(buffer);
+        }
+
+        // unblocks the reader after a write error
+        drop(full_rx);
+        drop(free_tx);
+        let read_result = reader.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+
+        read_result?;
+        write_result?;
+        dst.flush()?;
+        Ok(written)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::RawDiskImage;
+    use crate::test_utils::pattern;
+    use crate::vhd::VhdImage;
+    use crate::{sizes, MemoryDisk};
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("rdisk_uring_{}_{}.bin", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn uring_file_test() {
+        let path = temp_path("file");
+        let data = pattern(3 * MAX_OPERATION_SIZE + 100, 0);
+        {
+            let file = UringFile::create_preallocated(&path, 0).unwrap().with_queue_depth(2).unwrap();
+            assert_eq!(2, file.queue_depth());
+            file.write_all_at(512, &data).unwrap();
+            file.flush().unwrap();
+            assert_eq!(512 + data.len() as u64, file.size().unwrap());
+        }
+
+        let file = UringFile::open_with(&path, &OpenOptions::read_only()).unwrap();
+        let mut buffer = vec![0_u8; data.len()];
+        file.read_exact_at(512, &mut buffer).unwrap();
+        assert!(buffer == data);
+
+        // the read stops at the end of the file
+        assert_eq!(612, file.read_at(data.len() as u64 - 100, &mut buffer).unwrap());
+        assert_eq!(0, file.read_at(data.len() as u64 + 512, &mut buffer).unwrap());
+        assert!(file.write_at(0, b"x").is_err());
+
+        let (mut first, mut second) = ([0_u8; 10], vec![0_u8; 2 * MAX_OPERATION_SIZE]);
+        let mut batch = [(522, &mut first[..]), (1000, &mut second[..])];
+        file.read_batch_at(&mut batch).unwrap();
+        assert_eq!(&data[10..20], &first);
+        assert!(second[..] == data[488..488 + 2 * MAX_OPERATION_SIZE]);
+
+        let mut tail = [0_u8; 10];
+        assert!(matches!(
+            file.read_batch_at(&mut [(data.len() as u64 + 510, &mut tail[..])]),
+            Err(Error::UnexpectedEOD)
+        ));
+
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn uring_direct_concurrent_write_test() {
+        let path = temp_path("direct");
+        drop(UringFile::create_preallocated(&path, 2 * 4096).unwrap());
+
+        // every byte of the two blocks is written once by one of the threads, a lost read-modify-write leaves a zero
+        let file = UringFile::open_with(&path, &OpenOptions::default().direct()).unwrap();
+        assert!(file.is_direct());
+        std::thread::scope(|scope| {
+            for id in 0..8_u64 {
+                let file = &file;
+                scope.spawn(move || {
+                    for offset in (id..2 * 4096).step_by(8) {
+                        file.write_all_at(offset, &[id as u8 + 1]).unwrap();
+                    }
+                });
+            }
+        });
+
+        let mut data = vec![0_u8; 2 * 4096];
+        file.read_exact_at(0, &mut data).unwrap();
+        for (offset, &byte) in data.iter().enumerate() {
+            assert_eq!(offset as u8 % 8 + 1, byte, "offset {}", offset);
+        }
+
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn uring_images_test() {
+        let raw_path = temp_path("raw");
+        drop(UringFile::create_preallocated(&raw_path, 8 * sizes::MIB).unwrap());
+        let raw = RawDiskImage::open_uring(&raw_path, &OpenOptions::default(), 4).unwrap();
+        let data = pattern(5 * sizes::MIB as usize, 0);
+        raw.write_all_at(sizes::MIB, &data).unwrap();
+
+        let vhd_path = temp_path("vhd");
+        drop(VhdImage::create_dynamic(&vhd_path, 8 * sizes::MIB).unwrap());
+        let vhd = VhdImage::open_uring(&vhd_path, &OpenOptions::default(), 8).unwrap();
+        assert_eq!(8 * sizes::MIB, copy_with_queue_depth(&raw, &vhd, 2).unwrap());
+
+        // the sparse reads batch the runs of several blocks
+        let mut buffer = vec![0_u8; data.len() + 1000];
+        vhd.read_exact_at(sizes::MIB - 500, &mut buffer).unwrap();
+        assert!(buffer[..500].iter().all(|b| *b == 0));
+        assert!(buffer[500..500 + data.len()] == data[..]);
+        assert!(buffer[500 + data.len()..].iter().all(|b| *b == 0));
+
+        let small = MemoryDisk::with_capacity(4 * sizes::MIB);
+        assert!(matches!(copy(&vhd, &small), Err(Error::WriteBeyondEOD)));
+        let large = MemoryDisk::with_capacity(16 * sizes::MIB);
+        assert_eq!(8 * sizes::MIB, copy(&raw, &large).unwrap());
+        assert!(large.to_vec()[sizes::MIB as usize..][..data.len()] == data[..]);
+
+        drop((raw, vhd));
+        std::fs::remove_file(&raw_path).unwrap();
+        std::fs::remove_file(&vhd_path).unwrap();
+    }
+}
@@ -0,0 +1,72 @@
+use super::*;
+
+fn timestamp(time: exfat::Timestamp) -> Timestamp {
+    Timestamp::new(time.to_unix_time(), time.local.millisecond as u32 % 1000 * 1_000_000)
+}
+
+fn kind(entry: &exfat::DirEntry) -> FileKind {
+    if entry.is_dir() {
+        FileKind::Directory
+    } else {
+        FileKind::File
+    }
+}
+
+fn metadata(entry: &exfat::DirEntry) -> Metadata {
+    Metadata {
+        kind: kind(entry),
+        size: if entry.is_dir() { 0 } else { entry.size },
+        created: entry.created.map(timestamp),
+        modified: entry.modified.map(timestamp),
+        accessed: entry.accessed.map(timestamp),
+        attributes: Some(entry.attributes.0 as u32),
+        mode: None,
+        id: entry.first_cluster as u64,
+    }
+}
+
+impl<'f, R: ReadAt> File for exfat::File<'f, R> {
+    fn size(&self) -> u64 {
+        exfat::File::size(self)
+    }
+}
+
+impl<R: ReadAt> FileSystem for exfat::ExFatFileSystem<R> {
+    fn kind(&self) -> FsKind {
+        FsKind::ExFat
+    }
+
+    fn label(&self) -> Option<String> {
+        label(self.volume_label())
+    }
+
+    fn root(&self) -> Result<Metadata> {
+        Ok(metadata(&exfat::ExFatFileSystem::root(self)))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn File + '_>> {
+        Ok(Box::new(self.open_file(path)?))
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let entries = exfat::ExFatFileSystem::read_dir(self, path)?
+            .into_iter()
+            .map(|entry| DirEntry {
+                kind: kind(&entry),
+                id: entry.first_cluster as u64,
+                name: entry.name,
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(metadata(&self.entry(path)?))
+    }
+
+    fn read_link(&self, path: &str) -> Result<String> {
+        self.entry(path)?;
+        Err(Error::NotASymlink(path.to_string()))
+    }
+}
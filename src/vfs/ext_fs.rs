@@ -0,0 +1,86 @@
+use super::*;
+
+fn timestamp(time: ext::Timestamp) -> Timestamp {
+    Timestamp::new(time.seconds, time.nanoseconds)
+}
+
+fn kind(file_type: ext::FileType) -> FileKind {
+    match file_type {
+        ext::FileType::File => FileKind::File,
+        ext::FileType::Directory => FileKind::Directory,
+        ext::FileType::Symlink => FileKind::Symlink,
+        _ => FileKind::Other,
+    }
+}
+
+fn metadata(inode: &ext::Inode) -> Metadata {
+    Metadata {
+        kind: kind(inode.file_type()),
+        size: if inode.is_dir() { 0 } else { inode.size },
+        created: inode.created.map(timestamp),
+        modified: Some(timestamp(inode.modified)),
+        accessed: Some(timestamp(inode.accessed)),
+        attributes: None,
+        mode: Some(inode.mode as u32),
+        id: inode.number as u64,
+    }
+}
+
+impl<'f, R: ReadAt> File for ext::File<'f, R> {
+    fn size(&self) -> u64 {
+        ext::File::size(self)
+    }
+}
+
+impl<R: ReadAt> FileSystem for ext::ExtFileSystem<R> {
+    fn kind(&self) -> FsKind {
+        match self.superblock().version() {
+            2 => FsKind::Ext2,
+            3 => FsKind::Ext3,
+            _ => FsKind::Ext4,
+        }
+    }
+
+    fn label(&self) -> Option<String> {
+        label(self.volume_label())
+    }
+
+    fn root(&self) -> Result<Metadata> {
+        Ok(metadata(&ext::ExtFileSystem::root(self)?))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn File + '_>> {
+        Ok(Box::new(self.open_file(path)?))
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in ext::ExtFileSystem::read_dir(self, path)? {
+            // without the `filetype` feature only the inode has it
+            let file_type = match entry.file_type {
+                ext::FileType::Unknown => self.inode(entry.inode)?.file_type(),
+                file_type => file_type,
+            };
+            entries.push(DirEntry {
+                name: entry.name,
+                kind: kind(file_type),
+                id: entry.inode as u64,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(metadata(&self.resolve(path, false)?))
+    }
+
+    fn read_link(&self, path: &str) -> Result<String> {
+        let inode = self.resolve(path, false)?;
+        if !inode.is_symlink() {
+            return Err(Error::NotASymlink(path.to_string()));
+        }
+
+        self.read_link_inode(&inode)
+    }
+}
@@ -0,0 +1,76 @@
+use super::*;
+
+fn timestamp(time: fat::DateTime) -> Timestamp {
+    Timestamp::new(time.to_unix_time(), time.millisecond as u32 % 1000 * 1_000_000)
+}
+
+fn kind(entry: &fat::DirEntry) -> FileKind {
+    if entry.is_dir() {
+        FileKind::Directory
+    } else {
+        FileKind::File
+    }
+}
+
+fn metadata(entry: &fat::DirEntry) -> Metadata {
+    Metadata {
+        kind: kind(entry),
+        size: entry.size as u64,
+        created: entry.created.map(timestamp),
+        modified: entry.modified.map(timestamp),
+        accessed: entry.accessed.map(timestamp),
+        attributes: Some(entry.attributes.0 as u32),
+        mode: None,
+        id: entry.first_cluster as u64,
+    }
+}
+
+impl<'f, R: ReadAt> File for fat::File<'f, R> {
+    fn size(&self) -> u64 {
+        fat::File::size(self)
+    }
+}
+
+impl<R: ReadAt> FileSystem for fat::FatFileSystem<R> {
+    fn kind(&self) -> FsKind {
+        match fat::FatFileSystem::kind(self) {
+            fat::FatKind::Fat12 => FsKind::Fat12,
+            fat::FatKind::Fat16 => FsKind::Fat16,
+            fat::FatKind::Fat32 => FsKind::Fat32,
+        }
+    }
+
+    fn label(&self) -> Option<String> {
+        label(self.volume_label()).filter(|label| label != "NO NAME")
+    }
+
+    fn root(&self) -> Result<Metadata> {
+        Ok(metadata(&fat::FatFileSystem::root(self)))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn File + '_>> {
+        Ok(Box::new(self.open_file(path)?))
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let entries = fat::FatFileSystem::read_dir(self, path)?
+            .into_iter()
+            .map(|entry| DirEntry {
+                kind: kind(&entry),
+                id: entry.first_cluster as u64,
+                name: entry.name,
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(metadata(&self.entry(path)?))
+    }
+
+    fn read_link(&self, path: &str) -> Result<String> {
+        self.entry(path)?;
+        Err(Error::NotASymlink(path.to_string()))
+    }
+}
@@ -0,0 +1,74 @@
+use super::*;
+
+fn timestamp(time: iso9660::Timestamp) -> Timestamp {
+    Timestamp::new(time.to_unix_time(), time.local.millisecond as u32 * 1_000_000)
+}
+
+fn kind(entry: &iso9660::DirEntry) -> FileKind {
+    if entry.is_dir() {
+        FileKind::Directory
+    } else if entry.is_symlink() {
+        FileKind::Symlink
+    } else {
+        FileKind::File
+    }
+}
+
+fn metadata(entry: &iso9660::DirEntry) -> Metadata {
+    let rock_ridge = entry.rock_ridge.as_ref();
+    Metadata {
+        kind: kind(entry),
+        size: if entry.is_file() { entry.size() } else { 0 },
+        created: rock_ridge.and_then(|rr| rr.created).map(timestamp),
+        modified: rock_ridge.and_then(|rr| rr.modified).or(entry.recorded).map(timestamp),
+        accessed: rock_ridge.and_then(|rr| rr.accessed).map(timestamp),
+        attributes: None,
+        mode: rock_ridge.and_then(|rr| rr.mode),
+        id: entry.block() as u64,
+    }
+}
+
+impl<'f, R: ReadAt> File for iso9660::File<'f, R> {
+    fn size(&self) -> u64 {
+        iso9660::File::size(self)
+    }
+}
+
+impl<R: ReadAt> FileSystem for iso9660::IsoFileSystem<R> {
+    fn kind(&self) -> FsKind {
+        FsKind::Iso9660
+    }
+
+    fn label(&self) -> Option<String> {
+        label(self.volume_label())
+    }
+
+    fn root(&self) -> Result<Metadata> {
+        Ok(metadata(&iso9660::IsoFileSystem::root(self)?))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn File + '_>> {
+        Ok(Box::new(self.open_file(path)?))
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let entries = iso9660::IsoFileSystem::read_dir(self, path)?
+            .into_iter()
+            .map(|entry| DirEntry {
+                kind: kind(&entry),
+                id: entry.block() as u64,
+                name: entry.name,
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(metadata(&self.entry(path)?))
+    }
+
+    fn read_link(&self, path: &str) -> Result<String> {
+        iso9660::IsoFileSystem::read_link(self, path)
+    }
+}
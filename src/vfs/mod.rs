@@ -0,0 +1,310 @@
+//! Read-only access to any supported filesystem through one object-safe interface
+//!
+//! The drivers implement [`FileSystem`] directly, [`open_filesystem`] picks one by probing the device.
+//! Paths use `/` separators and are resolved from the root, the name matching rules
+//! (case sensitivity, `\` separators, NTFS alternate data streams) are those of the driver.
+use crate::prelude::*;
+use crate::{exfat, ext, fat, iso9660, ntfs, FsKind};
+use core::convert::TryFrom;
+
+mod exfat_fs;
+mod ext_fs;
+mod fat_fs;
+mod iso_fs;
+mod ntfs_fs;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    /// Devices, FIFOs and sockets
+    Other,
+}
+
+/// Seconds since 1970-01-01 UTC and nanoseconds
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Timestamp {
+    pub seconds: i64,
+    pub nanoseconds: u32,
+}
+
+impl Timestamp {
+    pub fn new(seconds: i64, nanoseconds: u32) -> Self {
+        Self { seconds, nanoseconds }
+    }
+}
+
+/// What the filesystem stores about the file, fields are `None` if the format does not have them
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct Metadata {
+    pub kind: FileKind,
+    /// Bytes, 0 for directories
+    pub size: u64,
+    pub created: Option<Timestamp>,
+    pub modified: Option<Timestamp>,
+    pub accessed: Option<Timestamp>,
+    /// FAT, exFAT and NTFS `FILE_ATTRIBUTE_*` flags
+    pub attributes: Option<u32>,
+    /// ext and Rock Ridge `st_mode`: file type and permission bits
+    pub mode: Option<u32>,
+    /// Inode or MFT record number, the first cluster on FAT and exFAT, the first block on ISO 9660
+    pub id: u64,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == FileKind::File
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.kind == FileKind::Symlink
+    }
+}
+
+/// Directory entry, `.` and `..` are not listed
+#[derive(Clone)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: FileKind,
+    /// The same as [`Metadata::id`]
+    pub id: u64,
+}
+
+/// Opened file contents
+pub trait File: ReadAt {
+    fn size(&self) -> u64;
+
+    /// Reads the whole file by 1 MiB pieces, a corrupted size fails at the first unreadable piece instead of allocating it all
+    fn read_all(&self) -> Result<Vec<u8>> {
+        const CHUNK: usize = 1024 * 1024;
+        let size = usize::try_from(self.size()).map_err(|_| Error::ReadBeyondEOD)?;
+        let mut data = Vec::new();
+        while data.len() < size {
+            let pos = data.len();
+            data.resize(pos + core::cmp::min(CHUNK, size - pos), 0);
+            self.read_exact_at(pos as u64, &mut data[pos..])?;
+        }
+
+        Ok(data)
+    }
+}
+
+/// Read-only filesystem
+pub trait FileSystem {
+    fn kind(&self) -> FsKind;
+
+    /// `None` if the volume has no label
+    fn label(&self) -> Option<String>;
+
+    fn root(&self) -> Result<Metadata>;
+
+    /// Opens a regular file, symbolic links are followed
+    fn open(&self, path: &str) -> Result<Box<dyn File + '_>>;
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>>;
+
+    /// The final symbolic link is not followed, like `lstat`
+    fn metadata(&self, path: &str) -> Result<Metadata>;
+
+    /// Target of the symbolic link as stored
+    fn read_link(&self, path: &str) -> Result<String>;
+}
+
+/// Detects the filesystem on the device (usually a [`Partition`](crate::Partition)) and opens it
+pub fn open_filesystem<'a, R: ReadAt + 'a>(device: R) -> Result<Box<dyn FileSystem + 'a>> {
+    let info = crate::probe_filesystem(&device).ok_or(Error::UnknownFileSystem)?;
+    let fs: Box<dyn FileSystem + 'a> = match info.kind {
+        FsKind::Fat12 | FsKind::Fat16 | FsKind::Fat32 => Box::new(fat::FatFileSystem::open(device)?),
+        FsKind::ExFat => Box::new(exfat::ExFatFileSystem::open(device)?),
+        FsKind::Ntfs => Box::new(ntfs::NtfsFileSystem::open(device)?),
+        FsKind::Ext2 | FsKind::Ext3 | FsKind::Ext4 => Box::new(ext::ExtFileSystem::open(device)?),
+        FsKind::Iso9660 => Box::new(iso9660::IsoFileSystem::open(device)?),
+        kind => return Err(Error::UnsupportedFileSystem(kind)),
+    };
+
+    Ok(fs)
+}
+
+fn label(label: &str) -> Option<String> {
+    let label = label.trim();
+    if label.is_empty() {
+        None
+    } else {
+        Some(label.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDisk;
+    use crate::{Partition, PartitionInfo, PartitionKind};
+
+    fn fat_volume() -> MemoryDisk {
+        let disk = MemoryDisk::from_vec(vec![0_u8; 4 * 1024 * 1024]);
+        let options = fat::FormatOptions {
+            label: "VFS".to_string(),
+            volume_id: Some(0x1234_5678),
+            ..Default::default()
+        };
+        fat::format(&disk, &options).unwrap();
+
+        let mut fs = fat::FatFileSystem::open(disk).unwrap();
+        fs.set_current_time(Some(fat::DateTime::from_unix_time(1_600_000_000)));
+        fs.create_dir("/docs").unwrap();
+        fs.write_file("/docs/readme.txt", b"hello vfs").unwrap();
+        fs.into_inner()
+    }
+
+    #[test]
+    fn fat_vfs_test() {
+        let volume = fat_volume();
+        let mut image = vec![0_u8; 1024 * 1024];
+        image.extend_from_slice(&volume.to_vec());
+        let disk = MemoryDisk::from_vec(image);
+        let partition = Partition::new(
+            &disk,
+            PartitionInfo {
+                offset: 1024 * 1024,
+                length: 4 * 1024 * 1024,
+                kind: PartitionKind::Free,
+            },
+        );
+
+        let fs = open_filesystem(&partition).unwrap();
+        assert_eq!(FsKind::Fat12, fs.kind());
+        assert_eq!(Some("VFS".to_string()), fs.label());
+        assert!(fs.root().unwrap().is_dir());
+
+        let entries = fs.read_dir("/").unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!("docs", entries[0].name);
+        assert_eq!(FileKind::Directory, entries[0].kind);
+        let entries = fs.read_dir("/docs").unwrap();
+        assert_eq!("readme.txt", entries[0].name);
+        assert_eq!(FileKind::File, entries[0].kind);
+
+        let metadata = fs.metadata("/docs/readme.txt").unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(9, metadata.size);
+        assert_eq!(entries[0].id, metadata.id);
+        assert_eq!(Some(Timestamp::new(1_600_000_000, 0)), metadata.modified);
+        assert_eq!(Some(fat::Attributes::ARCHIVE as u32), metadata.attributes);
+        assert_eq!(None, metadata.mode);
+
+        let file = fs.open("docs/readme.txt").unwrap();
+        assert_eq!(9, file.size());
+        assert_eq!(b"hello vfs".to_vec(), file.read_all().unwrap());
+
+        assert!(fs.open("/docs").is_err());
+        match fs.metadata("/missing") {
+            Err(Error::NotFound(_)) => (),
+            _ => panic!("not found expected"),
+        }
+        match fs.read_link("/docs/readme.txt") {
+            Err(Error::NotASymlink(_)) => (),
+            _ => panic!("not a symlink expected"),
+        }
+    }
+
+    #[test]
+    fn ext_vfs_test() {
+        let fs = open_filesystem(MemoryDisk::from_vec(ext::test_image())).unwrap();
+        assert_eq!(FsKind::Ext4, fs.kind());
+        assert_eq!(Some("rdisk".to_string()), fs.label());
+        assert_eq!(ext::ROOT_INODE as u64, fs.root().unwrap().id);
+
+        let mut entries = fs.read_dir("/").unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(vec!["hello.txt", "legacy", "link", "sub", "tree"], names);
+        assert_eq!(FileKind::Symlink, entries[2].kind);
+        assert_eq!(FileKind::Directory, entries[3].kind);
+
+        let link = fs.metadata("link").unwrap();
+        assert!(link.is_symlink());
+        assert_eq!(Some(0xA000), link.mode.map(|mode| mode & 0xF000));
+        assert_eq!(None, link.attributes);
+        assert_eq!("sub/../hello.txt", fs.read_link("link").unwrap());
+
+        let hello = fs.metadata("hello.txt").unwrap();
+        assert!(hello.is_file());
+        let file = fs.open("link").unwrap();
+        assert_eq!(hello.size, file.size());
+        assert_eq!(hello.size as usize, file.read_all().unwrap().len());
+
+        match fs.read_link("sub") {
+            Err(Error::NotASymlink(_)) => (),
+            _ => panic!("not a symlink expected"),
+        }
+    }
+
+    #[test]
+    fn iso9660_vfs_test() {
+        let fs = open_filesystem(MemoryDisk::from_vec(iso9660::test_image())).unwrap();
+        assert_eq!(FsKind::Iso9660, fs.kind());
+        assert_eq!(Some("RDISK_TEST".to_string()), fs.label());
+
+        let mut entries = fs.read_dir("/").unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let kinds: Vec<(&str, FileKind)> = entries.iter().map(|e| (e.name.as_str(), e.kind)).collect();
+        assert_eq!(("dir", FileKind::Directory), kinds[1]);
+        assert_eq!(("hello world.txt", FileKind::File), kinds[2]);
+        assert_eq!(("link", FileKind::Symlink), kinds[3]);
+
+        let hello = fs.metadata("hello world.txt").unwrap();
+        assert_eq!(Some(0o100_644), hello.mode);
+        assert_eq!(entries[2].id, hello.id);
+        assert!(hello.modified.is_some());
+        let link = fs.metadata("link").unwrap();
+        assert!(link.is_symlink());
+        assert_eq!("dir/../hello world.txt", fs.read_link("link").unwrap());
+        assert_eq!(hello.size, fs.open("link").unwrap().read_all().unwrap().len() as u64);
+    }
+
+    #[test]
+    fn unknown_filesystem_test() {
+        match open_filesystem(MemoryDisk::from_vec(vec![0_u8; 128 * 1024])) {
+            Err(Error::UnknownFileSystem) => (),
+            _ => panic!("unknown filesystem expected"),
+        }
+
+        // mkswap signature
+        let mut image = vec![0_u8; 128 * 1024];
+        image[4086..4096].copy_from_slice(b"SWAPSPACE2");
+        image[1024] = 1;
+        match open_filesystem(MemoryDisk::from_vec(image)) {
+            Err(Error::UnsupportedFileSystem(FsKind::Swap)) => (),
+            _ => panic!("unsupported filesystem expected"),
+        }
+    }
+
+    #[test]
+    fn read_all_corrupted_size_test() {
+        struct Corrupted(MemoryDisk);
+
+        impl ReadAt for Corrupted {
+            fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+                self.0.read_at(offset, buffer)
+            }
+        }
+
+        impl File for Corrupted {
+            fn size(&self) -> u64 {
+                1 << 40
+            }
+        }
+
+        let file = Corrupted(MemoryDisk::from_vec(vec![0_u8; 4096]));
+        assert!(file.read_all().is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use super::*;
+use crate::tools::le_u16;
+
+const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+const IO_REPARSE_TAG_SYMLINK: u32 = 0xA000_000C;
+/// Tag, data length and reserved fields
+const REPARSE_HEADER_SIZE: usize = 8;
+/// Substitute and print name offsets and lengths
+const NAMES_SIZE: usize = 8;
+/// Symbolic links also have flags before the path buffer
+const SYMLINK_FLAGS_SIZE: usize = 4;
+/// `FILE_ATTRIBUTE_REPARSE_POINT` as stored in file names and indexes
+const ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+fn timestamp(time: ntfs::FileTime) -> Timestamp {
+    Timestamp::new(time.to_unix_time(), (time.0 % 10_000_000) as u32 * 100)
+}
+
+/// The reparse point data if it is a symbolic link or a junction
+fn link_data<R: ReadAt>(file: &ntfs::File<'_, R>) -> Result<Option<Vec<u8>>> {
+    if file.attribute(ntfs::ATTR_REPARSE_POINT, "").is_none() {
+        return Ok(None);
+    }
+
+    let data = file.open_attribute(ntfs::ATTR_REPARSE_POINT, "")?.read_all()?;
+    if data.len() < REPARSE_HEADER_SIZE {
+        return Err(Error::from(ntfs::NtfsError::InvalidAttribute(file.number())));
+    }
+
+    let tag = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    if tag == IO_REPARSE_TAG_SYMLINK || tag == IO_REPARSE_TAG_MOUNT_POINT {
+        Ok(Some(data))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The print name, or the substitute one without the `\??\` prefix if there is none
+fn link_target(data: &[u8], record: u64) -> Result<String> {
+    let invalid = || Error::from(ntfs::NtfsError::InvalidAttribute(record));
+
+    let tag = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    let names = data
+        .get(REPARSE_HEADER_SIZE..REPARSE_HEADER_SIZE + NAMES_SIZE)
+        .ok_or_else(invalid)?;
+    let buffer_start = match tag {
+        IO_REPARSE_TAG_SYMLINK => REPARSE_HEADER_SIZE + NAMES_SIZE + SYMLINK_FLAGS_SIZE,
+        _ => REPARSE_HEADER_SIZE + NAMES_SIZE,
+    };
+    let name = |offset: u16, length: u16| {
+        let start = buffer_start + offset as usize;
+        let bytes = data.get(start..start + length as usize)?;
+        let chars: Vec<u16> = bytes.chunks_exact(2).map(|c| le_u16(c, 0)).collect();
+        Some(String::from_utf16_lossy(&chars))
+    };
+
+    let print_name = name(le_u16(names, 4), le_u16(names, 6)).ok_or_else(invalid)?;
+    if !print_name.is_empty() {
+        return Ok(print_name);
+    }
+
+    let substitute_name = name(le_u16(names, 0), le_u16(names, 2)).ok_or_else(invalid)?;
+    match substitute_name.strip_prefix("\\??\\") {
+        Some(name) => Ok(name.to_string()),
+        None => Ok(substitute_name),
+    }
+}
+
+fn metadata<R: ReadAt>(file: &ntfs::File<'_, R>) -> Result<Metadata> {
+    let kind = if link_data(file)?.is_some() {
+        FileKind::Symlink
+    } else if file.is_dir() {
+        FileKind::Directory
+    } else {
+        FileKind::File
+    };
+    let info = file.standard_information();
+
+    Ok(Metadata {
+        kind,
+        size: if file.is_dir() { 0 } else { file.size() },
+        created: info.as_ref().map(|info| timestamp(info.created)),
+        modified: info.as_ref().map(|info| timestamp(info.modified)),
+        accessed: info.as_ref().map(|info| timestamp(info.accessed)),
+        attributes: info.as_ref().map(|info| info.file_attributes),
+        mode: None,
+        id: file.number(),
+    })
+}
+
+impl<'f, R: ReadAt> File for ntfs::DataStream<'f, R> {
+    fn size(&self) -> u64 {
+        ntfs::DataStream::size(self)
+    }
+}
+
+impl<R: ReadAt> FileSystem for ntfs::NtfsFileSystem<R> {
+    fn kind(&self) -> FsKind {
+        FsKind::Ntfs
+    }
+
+    fn label(&self) -> Option<String> {
+        self.volume_label().ok().and_then(|name| label(&name))
+    }
+
+    fn root(&self) -> Result<Metadata> {
+        metadata(&ntfs::NtfsFileSystem::root(self)?)
+    }
+
+    /// `path:name` opens an alternate data stream
+    fn open(&self, path: &str) -> Result<Box<dyn File + '_>> {
+        Ok(Box::new(self.open_file(path)?))
+    }
+
+    fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in ntfs::NtfsFileSystem::read_dir(self, path)? {
+            let id = entry.reference.record();
+            // only the record tells a link from other reparse points
+            let kind = if entry.file_name.flags & ATTRIBUTE_REPARSE_POINT != 0 {
+                metadata(&self.file(id)?)?.kind
+            } else if entry.is_dir() {
+                FileKind::Directory
+            } else {
+                FileKind::File
+            };
+            entries.push(DirEntry {
+                name: entry.file_name.name,
+                kind,
+                id,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        metadata(&self.open_path(path)?)
+    }
+
+    fn read_link(&self, path: &str) -> Result<String> {
+        let file = self.open_path(path)?;
+        match link_data(&file)? {
+            Some(data) => link_target(&data, file.number()),
+            None => Err(Error::NotASymlink(path.to_string())),
+        }
+    }
+}
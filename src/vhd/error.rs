@@ -1,3 +1,5 @@
+use crate::xstd::String;
+
 #[derive(Debug)]
 pub enum VhdError {
     FileTooSmall,
@@ -10,6 +12,9 @@ pub enum VhdError {
     UnknownVhdType(u32),
     InvalidBlockIndex(usize),
     UnexpectedBlockId(usize, u32), // the value returend from Bat::block_id()
+    ParentNotFound(String),
+    ParentIdMismatch,
+    NoBackingFile,
 }
 
 impl core::fmt::Display for VhdError {
@@ -25,6 +30,9 @@ impl core::fmt::Display for VhdError {
             VhdError::UnknownVhdType(n) => write!(f, "Unknown VHD type '{}'", n),
             VhdError::InvalidBlockIndex(idx) => write!(f, "Invalid block index '{}'", idx),
             VhdError::UnexpectedBlockId(idx, id) => write!(f, "Unexpected '{}' block id '{:08X}'", idx, id),
+            VhdError::ParentNotFound(name) => write!(f, "Parent VHD '{}' not found", name),
+            VhdError::ParentIdMismatch => f.write_str("Parent VHD id does not match the differencing image"),
+            VhdError::NoBackingFile => f.write_str("The image is not kept in a file"),
         }
     }
 }
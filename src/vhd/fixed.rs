@@ -1,9 +1,9 @@
 use super::*;
 use crate::ImageExtent;
 
-pub struct FixedExtent {
-    file: File,
-    file_path: String,
+pub struct FixedExtent<S: Stream> {
+    file: S,
+    file_path: Option<String>,
 }
 
 // read_at and write_at offset args should be valid as they checked in the VhdImage
@@ -14,7 +14,7 @@ macro_rules! debug_check {
     };
 }
 
-impl ReadAt for FixedExtent {
+impl<S: Stream> ReadAt for FixedExtent<S> {
     fn read_at(&self, offset: u64, data: &mut [u8]) -> Result<usize> {
         debug_check!(self, offset, data);
 
@@ -22,7 +22,7 @@ impl ReadAt for FixedExtent {
     }
 }
 
-impl WriteAt for FixedExtent {
+impl<S: Stream> WriteAt for FixedExtent<S> {
     fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
         debug_check!(self, offset, data);
 
@@ -30,24 +30,24 @@ impl WriteAt for FixedExtent {
     }
 }
 
-impl Flush for FixedExtent {
+impl<S: Stream> Flush for FixedExtent<S> {
     fn flush(&self) -> Result<()> {
         self.file.flush()
     }
 }
 
-impl ImageExtent for FixedExtent {
+impl<S: Stream> ImageExtent for FixedExtent<S> {
     fn backing_files(&self) -> Box<dyn Iterator<Item = String>> {
-        Box::new(core::iter::once(self.file_path.clone()))
+        Box::new(self.file_path.clone().into_iter())
     }
     fn storage_size(&self) -> Result<u64> {
         self.file.size()
     }
 }
 
-impl ImageExtentOps for FixedExtent {}
+impl<S: Stream> ImageExtentOps for FixedExtent<S> {}
 
-impl VhdImageExtent for FixedExtent {
+impl<S: Stream + Send + Sync> VhdImageExtent for FixedExtent<S> {
     fn write_footer(&self, footer: &Footer) -> Result<()> {
         let bytes = footer.to_bytes();
         let pos = self.file.size()? - crate::sizes::SECTOR_U64;
@@ -58,10 +58,15 @@ impl VhdImageExtent for FixedExtent {
     fn sparse_header(&self) -> Option<&SparseHeader> {
         None
     }
+
+    /// The data is zeroed, the file may deallocate it
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        self.file.zero_range(offset, len)
+    }
 }
 
-impl FixedExtent {
-    pub(crate) fn new(file: File, file_path: String) -> Self {
+impl<S: Stream> FixedExtent<S> {
+    pub(crate) fn new(file: S, file_path: Option<String>) -> Self {
         Self { file, file_path }
     }
 }
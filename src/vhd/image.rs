@@ -1,11 +1,15 @@
 use super::*;
 use crate::{math, sizes};
+use core::sync::atomic::{AtomicBool, Ordering};
 
 pub use sparse::SparseHeader;
 
 pub struct VhdImage {
     footer: Footer,
     extent: Box<dyn VhdImageExtent>,
+    read_only: bool,
+    /// The footer has to be rewritten, a block allocation overwrites the trailing one
+    dirty: AtomicBool,
 }
 
 impl Drop for VhdImage {
@@ -26,8 +30,18 @@ impl ReadAt for VhdImage {
 
 impl WriteAt for VhdImage {
     fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
         match math::bound_to(self.capacity()?, offset, data.len()) {
-            Some(data_len) => self.extent.write_at(offset, &data[..data_len]),
+            Some(data_len) => {
+                let res = self.extent.write_at(offset, &data[..data_len]);
+                // even a failed write may have allocated a block,
+                // marking after the write keeps a concurrent flush from missing it
+                self.dirty.store(true, Ordering::Release);
+                res
+            }
             None => Err(Error::WriteBeyondEOD),
         }
     }
@@ -35,8 +49,16 @@ impl WriteAt for VhdImage {
 
 impl Flush for VhdImage {
     fn flush(&self) -> Result<()> {
-        self.extent.write_footer(&self.footer)?;
-        self.extent.flush()
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let res = self.extent.write_footer(&self.footer).and_then(|_| self.extent.flush());
+        if res.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+
+        res
     }
 }
 
@@ -52,6 +74,55 @@ impl Disk for VhdImage {
     fn physical_sector_size(&self) -> Result<u32> {
         Ok(sizes::SECTOR)
     }
+
+    /// Dynamic and differencing images forget the whole sectors of the range and free the whole blocks,
+    /// fixed ones zero them
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if !math::is_range_valid(self.capacity()?, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+
+        let start = math::round_up(offset, sizes::SECTOR_U64);
+        let end = math::round_down(offset + len, sizes::SECTOR_U64);
+        if start < end {
+            let res = self.extent.discard(start, end - start);
+            // the cleared bitmaps are written back by flush
+            self.dirty.store(true, Ordering::Release);
+            res?;
+        }
+
+        Ok(())
+    }
+
+    /// The whole sectors of fixed and dynamic images are zeroed without writing the data,
+    /// a differencing image has to store the zeroes to hide the parent data
+    fn write_zeroes(&self, offset: u64, len: u64) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if !math::is_range_valid(self.capacity()?, offset, len) {
+            return Err(Error::WriteBeyondEOD);
+        }
+        if self.kind() == VhdKind::Differencing {
+            return tools::write_zeroes(self, offset, len);
+        }
+
+        let end = offset + len;
+        let start = core::cmp::min(math::round_up(offset, sizes::SECTOR_U64), end);
+        let last = core::cmp::max(math::round_down(end, sizes::SECTOR_U64), start);
+        tools::write_zeroes(self, offset, start - offset)?;
+        tools::write_zeroes(self, last, end - last)?;
+        if start < last {
+            let res = self.extent.discard(start, last - start);
+            self.dirty.store(true, Ordering::Release);
+            res?;
+        }
+
+        Ok(())
+    }
 }
 
 impl DiskImage for VhdImage {
@@ -82,30 +153,118 @@ impl VhdImage {
 
         let path = path.into();
         let file = File::create_preallocated(&path, size + sizes::SECTOR_U64)?;
+        Self::create_fixed_extent(file, Some(path), size)
+    }
+
+    /// Creates the image in the `stream` instead of a file, the stream should be empty
+    pub fn create_fixed_stream<S: Stream + Send + Sync + 'static>(stream: S, size: u64) -> Result<Self> {
+        check_max_size(size)?;
+
+        Self::create_fixed_extent(stream, None, size)
+    }
+
+    fn create_fixed_extent<S: Stream + Send + Sync + 'static>(stream: S, path: Option<String>, size: u64) -> Result<Self> {
         let footer = Footer::new(size, VhdKind::Fixed);
-        let extent: Box<dyn VhdImageExtent> = Box::new(FixedExtent::new(file, path));
-        extent.write_footer(&footer)?;
+        // the footer is the last sector, writing it sets the stream size
+        stream.write_all_at(size, &footer.to_bytes())?;
+        let extent: Box<dyn VhdImageExtent> = Box::new(FixedExtent::new(stream, path));
 
-        Ok(Self { footer, extent })
+        Ok(Self::new(footer, extent, false, true))
     }
 
     pub fn create_dynamic<S: Into<String>>(path: S, size: u64) -> Result<Self> {
         check_max_size(size)?;
 
         let path = path.into();
+        let (file, _) = File::overwrite_or_create(&path)?;
+        Self::create_dynamic_extent(file, Some(path), size)
+    }
+
+    /// Creates the image in the `stream` instead of a file, the stream should be empty
+    pub fn create_dynamic_stream<S: Stream + Send + Sync + 'static>(stream: S, size: u64) -> Result<Self> {
+        check_max_size(size)?;
+
+        Self::create_dynamic_extent(stream, None, size)
+    }
+
+    fn create_dynamic_extent<S: Stream + Send + Sync + 'static>(stream: S, path: Option<String>, size: u64) -> Result<Self> {
         let footer = Footer::new(size, VhdKind::Dynamic);
-        let extent: Box<dyn VhdImageExtent> = Box::new(SparseExtent::create(path, &footer)?);
+        let extent: Box<dyn VhdImageExtent> = Box::new(SparseExtent::create(stream, path, &footer)?);
+
+        Ok(Self::new(footer, extent, false, true))
+    }
+
+    /// The `parent` image is opened read-only and kept open by the new image
+    pub fn create_differencing<S: Into<String>>(path: S, parent: S) -> Result<Self> {
+        let path = path.into();
+        let parent_path = parent.into();
+        let parent = Self::open_with(parent_path.as_str(), &OpenOptions::read_only())?;
 
-        Ok(Self { footer, extent })
+        let (file, _) = File::overwrite_or_create(&path)?;
+        Self::create_differencing_extent(file, Some(path), parent, Some(parent_path))
     }
 
-    pub fn create_differencing<S: Into<String>>(_path: S, _parent: S) -> Result<Self> {
-        todo!()
+    /// Creates the differencing image in the `stream`, the stream should be empty.
+    /// The parent locators point to the `parent` file, if it is kept in a file.
+    pub fn create_differencing_stream<S: Stream + Send + Sync + 'static>(stream: S, parent: VhdImage) -> Result<Self> {
+        let parent_path = parent.backing_files().next();
+        Self::create_differencing_extent(stream, None, parent, parent_path)
+    }
+
+    fn create_differencing_extent<S: Stream + Send + Sync + 'static>(
+        stream: S,
+        path: Option<String>,
+        parent: VhdImage,
+        parent_path: Option<String>,
+    ) -> Result<Self> {
+        let mut footer = Footer::new(parent.capacity()?, VhdKind::Differencing);
+        footer.geometry = parent.footer.geometry;
+        let extent = SparseExtent::create_differencing(stream, path, &footer, parent, parent_path.as_deref())?;
+
+        Ok(Self::new(footer, Box::new(extent), false, true))
     }
 
     pub fn open<S: Into<String>>(path: S) -> Result<Self> {
+        Self::open_with(path, &OpenOptions::default())
+    }
+
+    /// The parent of a differencing image is found by its parent locators and opened read-only
+    pub fn open_with<S: Into<String>>(path: S, options: &OpenOptions) -> Result<Self> {
         let path = path.into();
-        let file = File::open(&path)?;
+        let file = File::open_with(&path, options)?;
+        Self::open_extent(file, Some(path), options, None)
+    }
+
+    /// Reads and writes the data through an io_uring with up to `queue_depth` operations in flight,
+    /// the parent of a differencing image is opened with [`open_with`](Self::open_with)
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub fn open_uring<S: Into<String>>(path: S, options: &OpenOptions, queue_depth: u32) -> Result<Self> {
+        let path = path.into();
+        let file = crate::uring::UringFile::open_with(&path, options)?.with_queue_depth(queue_depth)?;
+        Self::open_extent(file, Some(path), options, None)
+    }
+
+    /// Opens the image kept in the `stream`, [`backing_files`](DiskImage::backing_files) is empty then
+    pub fn open_stream<S: Stream + Send + Sync + 'static>(stream: S) -> Result<Self> {
+        Self::open_extent(stream, None, &OpenOptions::read_write(), None)
+    }
+
+    /// Like [`open_stream`](Self::open_stream), but the stream is never written
+    pub fn open_stream_read_only<S: Stream + Send + Sync + 'static>(stream: S) -> Result<Self> {
+        Self::open_extent(stream, None, &OpenOptions::read_only(), None)
+    }
+
+    /// Opens the differencing image kept in the `stream` on top of its `parent`
+    pub fn open_differencing_stream<S: Stream + Send + Sync + 'static>(stream: S, parent: VhdImage) -> Result<Self> {
+        Self::open_extent(stream, None, &OpenOptions::read_write(), Some(parent))
+    }
+
+    fn open_extent<S: Stream + Send + Sync + 'static>(
+        file: S,
+        path: Option<String>,
+        options: &OpenOptions,
+        parent: Option<VhdImage>,
+    ) -> Result<Self> {
         let file_size = file.size()?;
 
         if file_size < sizes::SECTOR_U64 {
@@ -120,10 +279,52 @@ impl VhdImage {
 
         let extent: Box<dyn VhdImageExtent> = match footer.disk_type {
             VhdKind::Fixed => Box::new(FixedExtent::new(file, path)),
-            VhdKind::Dynamic | VhdKind::Differencing => Box::new(SparseExtent::open(file, path, footer.data_offset)?),
+            VhdKind::Dynamic => Box::new(SparseExtent::open(file, path, footer.data_offset)?),
+            VhdKind::Differencing => {
+                let mut extent = SparseExtent::open(file, path, footer.data_offset)?;
+                let parent = match parent {
+                    Some(parent) => parent,
+                    None => Self::open_parent(&extent, options)?,
+                };
+                extent.set_parent(parent)?;
+                Box::new(extent)
+            }
         };
 
-        Ok(Self { footer, extent })
+        // nothing to write back until the first write
+        Ok(Self::new(footer, extent, options.read_only, false))
+    }
+
+    fn open_parent<S: Stream + Send + Sync>(extent: &SparseExtent<S>, options: &OpenOptions) -> Result<Self> {
+        let parent_name = || extent.sparse_header().map(|header| header.parent_name.clone()).unwrap_or_default();
+        let path = match extent.file_path() {
+            Some(path) => path,
+            None => return Err(Error::from(VhdError::ParentNotFound(parent_name()))),
+        };
+
+        // the parent is never written through the child
+        let parent_options = OpenOptions {
+            read_only: true,
+            ..*options
+        };
+        for (absolute, parent_path) in extent.parent_paths()? {
+            let parent_path = if absolute { parent_path } else { join_relative(path, &parent_path) };
+
+            if let Ok(parent) = Self::open_with(parent_path, &parent_options) {
+                return Ok(parent);
+            }
+        }
+
+        Err(Error::from(VhdError::ParentNotFound(parent_name())))
+    }
+
+    fn new(footer: Footer, extent: Box<dyn VhdImageExtent>, read_only: bool, dirty: bool) -> Self {
+        Self {
+            footer,
+            extent,
+            read_only,
+            dirty: AtomicBool::new(dirty),
+        }
     }
 }
 
@@ -140,7 +341,243 @@ impl VhdImage {
         &self.footer
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Unflushed writes, the footer is only rewritten if the image is dirty
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
     pub fn sparse_header(&self) -> Option<&SparseHeader> {
         self.extent.sparse_header()
     }
+
+    /// Number of block bitmaps of a dynamic or differencing image kept in memory,
+    /// [`BITMAP_CACHE_SIZE`] by default. The dirty bitmaps are written back first.
+    pub fn set_bitmap_cache_size(&self, blocks: usize) -> Result<()> {
+        self.extent.set_bitmap_cache_size(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fat, MemoryStream};
+
+    #[test]
+    fn fixed_vhd_stream_test() {
+        let size = 2 * sizes::MIB;
+        let stream = MemoryStream::new();
+        let disk = VhdImage::create_fixed_stream(stream.clone(), size).unwrap();
+        disk.write_all_at(size / 2, b"asdf").unwrap();
+        drop(disk);
+        assert_eq!(size + sizes::SECTOR_U64, stream.size().unwrap());
+
+        let disk = VhdImage::open_stream(stream).unwrap();
+        assert!(VhdKind::Fixed == disk.kind());
+        assert_eq!(size, disk.capacity().unwrap());
+        assert_eq!(size + sizes::SECTOR_U64, disk.storage_size().unwrap());
+        assert_eq!(None, disk.backing_files().next());
+
+        let mut buffer = vec![0; 4];
+        disk.read_exact_at(size / 2, &mut buffer).unwrap();
+        assert_eq!(b"asdf", buffer.as_slice());
+        assert_eq!(0, disk.read_at(size, &mut buffer).unwrap());
+    }
+
+    #[test]
+    fn dynamic_vhd_stream_test() {
+        let size = 8 * sizes::MIB;
+        let stream = MemoryStream::new();
+        let disk = VhdImage::create_dynamic_stream(stream.clone(), size).unwrap();
+        let block_size = disk.sparse_header().unwrap().block_size as u64;
+        // unaligned and crossing the first block boundary
+        disk.write_all_at(block_size - 3, b"crossing").unwrap();
+        disk.write_all_at(size - 4, b"last").unwrap();
+        drop(disk);
+
+        let disk = VhdImage::open_stream(stream.clone()).unwrap();
+        assert!(VhdKind::Dynamic == disk.kind());
+        assert_eq!(size, disk.capacity().unwrap());
+        // two of four blocks are allocated
+        assert!(stream.size().unwrap() < size);
+
+        let mut buffer = vec![0xFF_u8; 8];
+        disk.read_exact_at(block_size - 3, &mut buffer).unwrap();
+        assert_eq!(b"crossing", buffer.as_slice());
+        disk.read_exact_at(size - 4, &mut buffer[..4]).unwrap();
+        assert_eq!(b"last", &buffer[..4]);
+        disk.read_exact_at(2 * block_size, &mut buffer).unwrap();
+        assert_eq!(&[0; 8], buffer.as_slice());
+    }
+
+    #[test]
+    fn format_on_vhd_stream_test() {
+        let stream = MemoryStream::new();
+        let disk = VhdImage::create_dynamic_stream(stream.clone(), 16 * sizes::MIB).unwrap();
+        let options = fat::FormatOptions {
+            label: "STAGED".to_string(),
+            volume_id: Some(0x1234_5678),
+            ..Default::default()
+        };
+        fat::format(&disk, &options).unwrap();
+        let mut fs = fat::FatFileSystem::open(disk).unwrap();
+        fs.write_file("/staged.txt", b"in memory").unwrap();
+        fs.flush().unwrap();
+        drop(fs);
+
+        let disk = VhdImage::open_stream(stream).unwrap();
+        let fs = fat::FatFileSystem::open(&disk).unwrap();
+        assert_eq!("STAGED", fs.volume_label());
+        let mut buffer = vec![0; 9];
+        fs.open_file("/staged.txt").unwrap().read_exact_at(0, &mut buffer).unwrap();
+        assert_eq!(b"in memory", buffer.as_slice());
+    }
+
+    #[test]
+    fn concurrent_dynamic_vhd_test() {
+        let stream = MemoryStream::new();
+        let disk = VhdImage::create_dynamic_stream(stream.clone(), 16 * sizes::MIB).unwrap();
+        // one cached bitmap, the threads evict each other's ones
+        disk.set_bitmap_cache_size(1).unwrap();
+
+        // the threads interleave their sectors over all blocks
+        std::thread::scope(|scope| {
+            for thread in 0..4_u64 {
+                let disk = &disk;
+                scope.spawn(move || {
+                    for sector in (thread..16 * sizes::MIB / 4096).step_by(4) {
+                        disk.write_all_at(sector * 4096 + thread * 512, &[thread as u8 + 1; 512]).unwrap();
+                        let mut buffer = [0_u8; 512];
+                        disk.read_exact_at(sector * 4096 + thread * 512, &mut buffer).unwrap();
+                        assert_eq!([thread as u8 + 1; 512], buffer);
+                    }
+                });
+            }
+        });
+        drop(disk);
+
+        let disk = VhdImage::open_stream(stream).unwrap();
+        let mut buffer = vec![0_u8; 4096];
+        for sector in 0..16 * sizes::MIB / 4096 {
+            disk.read_exact_at(sector * 4096, &mut buffer).unwrap();
+            let thread = sector % 4;
+            for (i, chunk) in buffer.chunks(512).enumerate() {
+                let expected = if i as u64 == thread { thread as u8 + 1 } else { 0 };
+                assert!(chunk.iter().all(|&b| b == expected), "sector {} chunk {}", sector, i);
+            }
+        }
+    }
+
+    /// Fails the test on any write, as a file on read-only media would
+    #[derive(Clone)]
+    struct WriteProtected(MemoryStream);
+
+    impl ReadAt for WriteProtected {
+        fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+            self.0.read_at(offset, buffer)
+        }
+    }
+
+    impl WriteAt for WriteProtected {
+        fn write_at(&self, _offset: u64, _data: &[u8]) -> Result<usize> {
+            panic!("write to a write protected stream")
+        }
+    }
+
+    impl Flush for WriteProtected {
+        fn flush(&self) -> Result<()> {
+            panic!("flush of a write protected stream")
+        }
+    }
+
+    impl Stream for WriteProtected {
+        fn size(&self) -> Result<u64> {
+            self.0.size()
+        }
+    }
+
+    #[test]
+    fn dynamic_vhd_discard_test() {
+        let stream = MemoryStream::new();
+        let disk = VhdImage::create_dynamic_stream(stream.clone(), 8 * sizes::MIB).unwrap();
+        disk.write_all_at(0, &vec![0xEE; 3 * sizes::MIB as usize]).unwrap();
+        let size = stream.size().unwrap();
+
+        // the first block is freed, the half of the second one is cleared
+        disk.discard(0, 3 * sizes::MIB).unwrap();
+        let mut buffer = vec![0xFF_u8; 4 * sizes::MIB as usize];
+        disk.read_exact_at(0, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|&b| b == 0));
+
+        // the zeroes are not written
+        disk.write_all_at(sizes::MIB, &[0xAA; 1024]).unwrap();
+        disk.write_zeroes(sizes::MIB + 100, 1000).unwrap();
+        disk.read_exact_at(sizes::MIB, &mut buffer[..1024]).unwrap();
+        assert!(buffer[..100].iter().all(|&b| b == 0xAA));
+        assert!(buffer[100..1024].iter().all(|&b| b == 0));
+        assert_eq!(size + 2 * sizes::MIB + 512, stream.size().unwrap());
+        assert!(matches!(disk.discard(8 * sizes::MIB, 1), Err(Error::WriteBeyondEOD)));
+        drop(disk);
+
+        let disk = VhdImage::open_stream(stream).unwrap();
+        disk.read_exact_at(0, &mut buffer).unwrap();
+        assert!(buffer[..sizes::MIB as usize].iter().all(|&b| b == 0));
+        assert!(buffer[sizes::MIB as usize..sizes::MIB as usize + 100].iter().all(|&b| b == 0xAA));
+        assert!(buffer[sizes::MIB as usize + 100..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn dynamic_vhd_discard_reopen_test() {
+        let stream = MemoryStream::new();
+        let disk = VhdImage::create_dynamic_stream(stream.clone(), 4 * sizes::MIB).unwrap();
+        disk.write_all_at(0, &[0xEE; 4096]).unwrap();
+        disk.flush().unwrap();
+
+        // only the bitmap changes, it is saved by flush
+        disk.discard(0, 1024).unwrap();
+        assert!(disk.is_dirty());
+        disk.flush().unwrap();
+        disk.write_zeroes(2048, 1024).unwrap();
+        assert!(disk.is_dirty());
+        drop(disk);
+
+        let disk = VhdImage::open_stream(stream).unwrap();
+        let mut buffer = vec![0xFF_u8; 4096];
+        disk.read_exact_at(0, &mut buffer).unwrap();
+        assert!(buffer[..1024].iter().all(|&b| b == 0));
+        assert!(buffer[1024..2048].iter().all(|&b| b == 0xEE));
+        assert!(buffer[2048..3072].iter().all(|&b| b == 0));
+        assert!(buffer[3072..].iter().all(|&b| b == 0xEE));
+    }
+
+    #[test]
+    fn read_only_vhd_test() {
+        let stream = MemoryStream::new();
+        let disk = VhdImage::create_dynamic_stream(stream.clone(), 4 * sizes::MIB).unwrap();
+        assert!(disk.is_dirty());
+        disk.write_all_at(0, b"data").unwrap();
+        disk.flush().unwrap();
+        assert!(!disk.is_dirty());
+        drop(disk);
+
+        // neither a clean flush nor drop touch the stream
+        let disk = VhdImage::open_stream_read_only(WriteProtected(stream.clone())).unwrap();
+        assert!(disk.is_read_only());
+        assert!(matches!(disk.write_at(0, b"new"), Err(Error::ReadOnly)));
+        assert!(!disk.is_dirty());
+        disk.flush().unwrap();
+        let mut buffer = vec![0; 4];
+        disk.read_exact_at(0, &mut buffer).unwrap();
+        assert_eq!(b"data", buffer.as_slice());
+        drop(disk);
+
+        let disk = VhdImage::open_stream(stream.clone()).unwrap();
+        assert!(!disk.is_read_only());
+        assert!(!disk.is_dirty());
+        disk.write_all_at(sizes::MIB * 3, b"data").unwrap();
+        assert!(disk.is_dirty());
+    }
 }
@@ -0,0 +1,126 @@
+use super::*;
+use crate::{math, sizes};
+use sparse::bat::{Bat, UNUSED_BLOCK_ID};
+
+/// Footer, sparse header and block allocation table of a VHD file, read without opening the image
+///
+/// Lets the callers locate the data blocks and do the I/O themselves, e.g. asynchronously.
+pub struct VhdMetadata {
+    pub footer: Footer,
+    pub sparse_header: Option<SparseHeader>,
+    bat: Option<Bat>,
+}
+
+impl VhdMetadata {
+    pub fn read(stream: &impl Stream) -> Result<Self> {
+        let file_size = stream.size()?;
+        if file_size < sizes::SECTOR_U64 {
+            return Err(Error::from(VhdError::FileTooSmall));
+        }
+
+        let footer = Footer::read(stream, file_size - sizes::SECTOR_U64)?;
+        if footer.disk_type == VhdKind::Fixed {
+            return Ok(Self {
+                footer,
+                sparse_header: None,
+                bat: None,
+            });
+        }
+
+        let header = SparseHeader::read(stream, footer.data_offset)?;
+        if header.table_offset >= file_size {
+            return Err(Error::from(VhdError::InvalidSparseHeaderOffset));
+        }
+
+        let bat = Bat::read(stream, header.table_offset, header.max_table_entries)?;
+        Ok(Self {
+            footer,
+            sparse_header: Some(header),
+            bat: Some(bat),
+        })
+    }
+
+    /// [`read`](Self::read) for async streams, only the metadata is fetched
+    #[cfg(feature = "async")]
+    pub async fn read_async(stream: &impl crate::AsyncStream) -> Result<Self> {
+        let replay = crate::async_disk::ReplayDisk::for_stream(stream.size().await?);
+        crate::async_disk::replay(stream, replay, Self::read).await
+    }
+
+    pub fn kind(&self) -> VhdKind {
+        self.footer.disk_type
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.footer.current_size
+    }
+
+    /// File position of the disk `offset`, `None` for an unallocated block.
+    ///
+    /// The sector bitmaps are not consulted: a differencing image takes the sectors
+    /// with a clear bit from its parent.
+    pub fn data_position(&self, offset: u64) -> Result<Option<u64>> {
+        if offset >= self.capacity() {
+            return Err(Error::ReadBeyondEOD);
+        }
+
+        let (header, bat) = match (&self.sparse_header, &self.bat) {
+            (Some(header), Some(bat)) => (header, bat),
+            _ => return Ok(Some(offset)),
+        };
+
+        let block_size = header.block_size as u64;
+        match bat.block_id((offset / block_size) as usize)? {
+            UNUSED_BLOCK_ID => Ok(None),
+            id => {
+                let bitmap_size = math::round_up(math::ceil(header.block_size, sizes::SECTOR * 8), sizes::SECTOR);
+                Ok(Some(id as u64 * sizes::SECTOR_U64 + bitmap_size as u64 + offset % block_size))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStream;
+
+    #[test]
+    fn vhd_metadata_test() {
+        let stream = MemoryStream::new();
+        {
+            let image = VhdImage::create_dynamic_stream(stream.clone(), 16 * sizes::MIB).unwrap();
+            image.write_all_at(3 * sizes::MIB + 10, b"block").unwrap();
+        }
+
+        let metadata = VhdMetadata::read(&stream).unwrap();
+        assert!(metadata.kind() == VhdKind::Dynamic);
+        assert_eq!(16 * sizes::MIB, metadata.capacity());
+        assert_eq!(None, metadata.data_position(0).unwrap());
+        let pos = metadata.data_position(3 * sizes::MIB + 10).unwrap().unwrap() as usize;
+        assert_eq!(b"block", &stream.to_vec()[pos..pos + 5]);
+        assert!(metadata.data_position(16 * sizes::MIB).is_err());
+
+        let fixed = MemoryStream::new();
+        drop(VhdImage::create_fixed_stream(fixed.clone(), sizes::MIB).unwrap());
+        let metadata = VhdMetadata::read(&fixed).unwrap();
+        assert!(metadata.sparse_header.is_none());
+        assert_eq!(Some(4096), metadata.data_position(4096).unwrap());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn vhd_metadata_async_test() {
+        let stream = MemoryStream::new();
+        {
+            let image = VhdImage::create_dynamic_stream(stream.clone(), 16 * sizes::MIB).unwrap();
+            image.write_all_at(5 * sizes::MIB, b"async").unwrap();
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let metadata = runtime.block_on(VhdMetadata::read_async(&crate::Blocking::new(stream.clone()))).unwrap();
+        let pos = metadata.data_position(5 * sizes::MIB).unwrap().unwrap();
+        assert_eq!(VhdMetadata::read(&stream).unwrap().data_position(5 * sizes::MIB).unwrap(), Some(pos));
+        assert_eq!(b"async", &stream.to_vec()[pos as usize..pos as usize + 5]);
+    }
+}
@@ -41,9 +41,19 @@ pub use fixed::*;
 mod sparse;
 pub use sparse::*;
 
-trait VhdImageExtent: ImageExtent + ImageExtentOps {
+mod metadata;
+pub use metadata::VhdMetadata;
+
+trait VhdImageExtent: ImageExtent + ImageExtentOps + Send + Sync {
     fn write_footer(&self, footer: &Footer) -> Result<()>;
     fn sparse_header(&self) -> Option<&SparseHeader>;
+
+    fn set_bitmap_cache_size(&self, _blocks: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// The whole sectors of the range are no longer needed
+    fn discard(&self, offset: u64, len: u64) -> Result<()>;
 }
 
 #[derive(Copy, Clone, FromPrimitive, ToPrimitive, Eq, PartialEq)]
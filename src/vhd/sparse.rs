@@ -1,47 +1,78 @@
-use core::cell::RefCell;
-
 use super::*;
+use crate::sync::{Mutex, RwLock};
 use crate::{math, sizes};
 
 mod header;
 pub use header::SparseHeader;
-use header::VhdSparseHeaderRecord;
-use rdisk_shared::StructBuffer;
-
-mod bat;
-
-pub struct SparseExtent {
-    file: File,
-    file_path: String,
+use header::{ParentLocator, VhdSparseHeaderRecord, PLATFORM_CODE_W2KU, PLATFORM_CODE_W2RU};
+
+pub(super) mod bat;
+
+mod bitmap;
+use bitmap::{Bitmap, BitmapCache};
+
+/// Number of block bitmaps kept in memory by default, 64 bitmaps of 2 MiB blocks cover 128 MiB
+pub const BITMAP_CACHE_SIZE: usize = 64;
+
+const DEFAULT_BLOCK_SIZE: u32 = 2 * 1024 * 1024; // 2 MiB
+const DEFAULT_HEADER_OFFSET: u64 = core::mem::size_of::<VhdFooterRecord>() as u64;
+const DEFAULT_TABLE_OFFSET: u64 = DEFAULT_HEADER_OFFSET + core::mem::size_of::<VhdSparseHeaderRecord>() as u64;
+/// The longest Windows path in UTF-16, longer locators are broken
+const MAX_LOCATOR_LENGTH: u32 = 64 * 1024;
+
+#[cfg(feature = "std")]
+const SEPARATOR: char = std::path::MAIN_SEPARATOR;
+#[cfg(not(feature = "std"))]
+const SEPARATOR: char = '\\';
+
+/// Dynamic or differencing VHD data
+///
+/// Reads and writes of allocated blocks run in parallel, the file I/O is done without locks.
+/// Only the bitmap cache access and the block position reservation are serialized.
+/// The recently used block bitmaps are cached and written back on eviction and flush.
+/// Concurrent writes to the same sector are not ordered.
+pub struct SparseExtent<S: Stream> {
+    file: S,
+    file_path: Option<String>,
     header: SparseHeader,
-    bat: RefCell<bat::Bat>,
-    cached_block_index: RefCell<usize>,
-    cached_bitmap: RefCell<Vec<u8>>,
-    cached_bitmap_dirty: RefCell<bool>,
-    next_block_pos: RefCell<u64>,
+    bitmap_size: u32,
+    /// Write locked only to add a block
+    bat: RwLock<bat::Bat>,
+    bitmaps: Mutex<BitmapCache>,
+    /// Serializes the bitmap loads and write-backs, a bitmap is not loaded while its previous version is being written
+    bitmap_io: Mutex<()>,
+    /// Also the second footer position, a block allocation reserves its space under the lock
+    next_block_pos: Mutex<u64>,
     parent: Option<VhdImage>,
 }
 
-impl ReadAt for SparseExtent {
-    fn read_at(&self, mut offset: u64, mut buffer: &mut [u8]) -> Result<usize> {
+impl<S: Stream> ReadAt for SparseExtent<S> {
+    fn read_at(&self, mut offset: u64, buffer: &mut [u8]) -> Result<usize> {
         // offset and buffer.len() are valid at this point, see VhdImage::read_at
-        let mut readed = 0_usize;
+        // the runs stored in this file are read in one batch, the backend may submit them at once
+        let readed = buffer.len();
+        let mut buffer = buffer;
+        let mut batch = Vec::new();
         while !buffer.is_empty() {
-            match self.read_block(offset, buffer)? {
-                0 => break,
-                n => {
-                    buffer = &mut buffer[n..];
-                    offset += n as u64;
-                    readed += n;
+            let (len, data_pos) = self.locate(offset, buffer.len())?;
+            let (run, rest) = core::mem::take(&mut buffer).split_at_mut(len);
+            match data_pos {
+                Some(pos) => batch.push((pos, run)),
+                None => {
+                    self.read_parent_or_zero(offset, run)?;
                 }
             }
+
+            buffer = rest;
+            offset += len as u64;
         }
 
+        self.file.read_batch_at(&mut batch)?;
         Ok(readed)
     }
 }
 
-impl WriteAt for SparseExtent {
+impl<S: Stream> WriteAt for SparseExtent<S> {
     fn write_at(&self, mut offset: u64, mut data: &[u8]) -> Result<usize> {
         // offset and buffer.len() are valid at this point, see VhdImage::write_at
         let mut written = 0_usize;
@@ -60,56 +91,100 @@ impl WriteAt for SparseExtent {
     }
 }
 
-impl Flush for SparseExtent {
+impl<S: Stream> Flush for SparseExtent<S> {
     fn flush(&self) -> Result<()> {
-        self.save_cached_bitmap()?;
+        self.save_bitmaps()?;
         self.file.flush()
     }
 }
 
-impl ImageExtent for SparseExtent {
+/// A differencing image also includes the parent chain
+impl<S: Stream> ImageExtent for SparseExtent<S> {
     fn backing_files(&self) -> Box<dyn Iterator<Item = String>> {
-        Box::new(core::iter::once(self.file_path.clone()))
+        let files = self.file_path.clone().into_iter();
+        match &self.parent {
+            Some(parent) => Box::new(files.chain(parent.backing_files())),
+            None => Box::new(files),
+        }
     }
     fn storage_size(&self) -> Result<u64> {
-        self.file.size()
+        let parent_size = match &self.parent {
+            Some(parent) => parent.storage_size()?,
+            None => 0,
+        };
+
+        Ok(self.file.size()? + parent_size)
     }
 }
 
-impl ImageExtentOps for SparseExtent {}
+impl<S: Stream> ImageExtentOps for SparseExtent<S> {}
 
-impl VhdImageExtent for SparseExtent {
+impl<S: Stream + Send + Sync> VhdImageExtent for SparseExtent<S> {
     fn write_footer(&self, footer: &Footer) -> Result<()> {
         let bytes = footer.to_bytes();
         // first footer
         self.file.write_all_at(0, &bytes)?;
 
-        // second footer
-        let next_block_pos = *self.next_block_pos.borrow();
-        self.file.write_all_at(next_block_pos, &bytes)
+        // second footer, written under the position lock: a block reserved here zeroes its bitmap over it afterwards
+        let next_block_pos = self.next_block_pos.lock();
+        self.file.write_all_at(*next_block_pos, &bytes)
     }
 
     fn sparse_header(&self) -> Option<&SparseHeader> {
         Some(&self.header)
     }
+
+    fn set_bitmap_cache_size(&self, blocks: usize) -> Result<()> {
+        let _io = self.bitmap_io.lock();
+        let dropped = self.bitmaps.lock().set_capacity(blocks);
+        self.write_back(dropped)
+    }
+
+    /// Clears the bitmap bits of the sectors, the blocks discarded entirely are freed.
+    /// A differencing image reads the discarded sectors from the parent then.
+    fn discard(&self, offset: u64, len: u64) -> Result<()> {
+        // offset and len are valid and sector aligned at this point, see VhdImage::discard
+        let block_size = self.header.block_size as u64;
+        let end = offset + len;
+        let mut pos = offset;
+        while pos < end {
+            let block_index = (pos / block_size) as usize;
+            let offset_in_block = pos % block_size;
+            let n = core::cmp::min(block_size - offset_in_block, end - pos);
+
+            let block_id = self.block_id(block_index)?;
+            if block_id != bat::UNUSED_BLOCK_ID {
+                if n == block_size {
+                    self.free_block(block_index)?;
+                } else {
+                    let sector_in_block = (offset_in_block / sizes::SECTOR_U64) as u32;
+                    self.clear_sectors(block_index, block_id, sector_in_block, (n / sizes::SECTOR_U64) as u32)?;
+                }
+            }
+
+            pos += n;
+        }
+
+        Ok(())
+    }
 }
 
-impl SparseExtent {
-    fn new(file: File, file_path: String, header: SparseHeader, bat: bat::Bat, bitmap_size: u32, next_block_pos: u64) -> Self {
+impl<S: Stream + Send + Sync> SparseExtent<S> {
+    fn new(file: S, file_path: Option<String>, header: SparseHeader, bat: bat::Bat, bitmap_size: u32, next_block_pos: u64) -> Self {
         Self {
             file,
             file_path,
             header,
-            bat: RefCell::new(bat),
-            cached_block_index: RefCell::new(INVALID_CACHE_INDEX),
-            cached_bitmap: RefCell::new(vec![0; bitmap_size as usize]),
+            bitmap_size,
+            bat: RwLock::new(bat),
+            bitmaps: Mutex::new(BitmapCache::new(BITMAP_CACHE_SIZE)),
+            bitmap_io: Mutex::default(),
+            next_block_pos: Mutex::new(next_block_pos),
             parent: None,
-            next_block_pos: RefCell::new(next_block_pos),
-            cached_bitmap_dirty: RefCell::new(false),
         }
     }
 
-    pub(crate) fn open(file: File, file_path: String, data_offset: u64) -> Result<Self> {
+    pub(crate) fn open(file: S, file_path: Option<String>, data_offset: u64) -> Result<Self> {
         let header = SparseHeader::read(&file, data_offset)?;
         let file_size = file.size()?;
 
@@ -124,16 +199,12 @@ impl SparseExtent {
         Ok(Self::new(file, file_path, header, bat, bitmap_size, next_block_pos))
     }
 
-    pub(crate) fn create(file_path: String, footer: &Footer) -> Result<Self> {
-        const DEFAULT_BLOCK_SIZE: u32 = 2 * 1024 * 1024; // 2 MiB
-        const DEFAULT_HEADER_OFFSET: u64 = core::mem::size_of::<VhdFooterRecord>() as u64;
-        const DEFAULT_TABLE_OFFSET: u64 = DEFAULT_HEADER_OFFSET + core::mem::size_of::<VhdSparseHeaderRecord>() as u64;
-
+    /// `file` should be empty
+    pub(crate) fn create(file: S, file_path: Option<String>, footer: &Footer) -> Result<Self> {
         let header = SparseHeader::new(footer.current_size, DEFAULT_TABLE_OFFSET, DEFAULT_BLOCK_SIZE);
         let bat = bat::Bat::new(header.max_table_entries);
         let bitmap_size = math::round_up(math::ceil(header.block_size, sizes::SECTOR * 8), sizes::SECTOR);
 
-        let (file, _) = File::owerwrite_or_create(&file_path)?;
         header.write(&file, DEFAULT_HEADER_OFFSET)?;
         let bat_size = bat.write(&file, DEFAULT_TABLE_OFFSET)?;
         let next_block_pos = DEFAULT_TABLE_OFFSET + bat_size as u64; // immediately after BAT
@@ -142,78 +213,175 @@ impl SparseExtent {
         this.write_footer(footer)?;
         Ok(this)
     }
-}
 
-const INVALID_CACHE_INDEX: usize = usize::max_value();
+    /// `file` should be empty, the parent locators are written if the `parent_path` is known
+    pub(crate) fn create_differencing(
+        file: S,
+        file_path: Option<String>,
+        footer: &Footer,
+        parent: VhdImage,
+        parent_path: Option<&str>,
+    ) -> Result<Self> {
+        // the same block size keeps the parent and child blocks aligned
+        let block_size = parent.sparse_header().map_or(DEFAULT_BLOCK_SIZE, |header| header.block_size);
+        let mut header = SparseHeader::new(footer.current_size, DEFAULT_TABLE_OFFSET, block_size);
+        header.parent_id = *parent.id();
+        header.parent_time_stamp = parent.footer().timestamp;
 
-fn calc_sector_mask(sector_in_block: usize) -> u8 {
-    1 << (7 - (sector_in_block % 8) as u8)
-}
+        let bat = bat::Bat::new(header.max_table_entries);
+        let bitmap_size = math::round_up(math::ceil(header.block_size, sizes::SECTOR * 8), sizes::SECTOR);
+        let bat_size = bat.write(&file, DEFAULT_TABLE_OFFSET)?;
 
-impl SparseExtent {
-    fn populate_block_bitmap(&self, index: usize) -> Result<bool> {
-        if *self.cached_block_index.borrow() == index {
-            return Ok(true);
-        }
+        // the locators data is placed immediately after BAT
+        let mut next_block_pos = DEFAULT_TABLE_OFFSET + bat_size as u64;
+        if let Some(parent_path) = parent_path {
+            header.parent_name = file_name(parent_path).to_string();
 
-        let block_id = self.bat.borrow().block_id(index)?;
-        if block_id == bat::UNUSED_BLOCK_ID {
-            return Ok(false);
+            let mut locators = vec![(PLATFORM_CODE_W2KU, parent_path.to_string())];
+            if let Some(relative) = file_path.as_deref().and_then(|path| relative_path(path, parent_path)) {
+                locators.push((PLATFORM_CODE_W2RU, relative));
+            }
+
+            for (index, (platform_code, path)) in locators.into_iter().enumerate() {
+                let data: Vec<u8> = path.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+                let mut buffer = vec![0_u8; math::round_up(data.len(), sizes::SECTOR as usize)];
+                buffer[..data.len()].copy_from_slice(&data);
+                file.write_all_at(next_block_pos, &buffer)?;
+
+                let locator = ParentLocator {
+                    platform_code,
+                    data_length: data.len() as u32,
+                    data_offset: next_block_pos,
+                };
+                header.set_locator(index, locator);
+                next_block_pos += buffer.len() as u64;
+            }
         }
 
-        self.save_cached_bitmap()?;
+        header.write(&file, DEFAULT_HEADER_OFFSET)?;
 
-        let bitmap_pos = block_id as u64 * sizes::SECTOR_U64;
-        self.file
-            .read_exact_at(bitmap_pos, self.cached_bitmap.borrow_mut().as_mut_slice())?;
-        *self.cached_block_index.borrow_mut() = index;
+        let mut this = Self::new(file, file_path, header, bat, bitmap_size, next_block_pos);
+        this.parent = Some(parent);
+        this.write_footer(footer)?;
+        Ok(this)
+    }
+
+    /// Sectors missing in this file are read from the `parent`
+    pub(crate) fn set_parent(&mut self, parent: VhdImage) -> Result<()> {
+        if *parent.id() != self.header.parent_id {
+            return Err(Error::from(VhdError::ParentIdMismatch));
+        }
+
+        self.parent = Some(parent);
+        Ok(())
+    }
 
-        Ok(true)
+    pub(crate) fn file_path(&self) -> Option<&str> {
+        self.file_path.as_deref()
     }
 
-    fn check_sector_mask(&self, index: usize, sector_in_block: u32) -> Result<bool> {
-        if *self.cached_block_index.borrow() != index {
-            let res = self.populate_block_bitmap(index)?;
-            if !res {
-                return Ok(false);
+    /// Paths of the parent image from the parent locators, the absolute ones first
+    pub(crate) fn parent_paths(&self) -> Result<Vec<(bool, String)>> {
+        let mut paths = Vec::new();
+        for locator in self.header.locators() {
+            let absolute = match locator.platform_code {
+                PLATFORM_CODE_W2KU => true,
+                PLATFORM_CODE_W2RU => false,
+                _ => continue, // Mac OS locators
+            };
+            if locator.data_length > MAX_LOCATOR_LENGTH {
+                continue;
             }
+
+            let mut data = vec![0_u8; locator.data_length as usize];
+            self.file.read_exact_at(locator.data_offset, &mut data)?;
+            let chars: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            paths.push((absolute, String::from_utf16_lossy(&chars).trim_end_matches('\0').to_string()));
         }
 
-        debug_assert_eq!(*self.cached_block_index.borrow(), index);
+        paths.sort_by_key(|(absolute, _)| !absolute);
+        Ok(paths)
+    }
+}
 
-        let sector_in_block = sector_in_block as usize;
-        let sector_mask = calc_sector_mask(sector_in_block);
-        let is_bit_set = self.cached_bitmap.borrow()[sector_in_block / 8] & sector_mask != 0;
-        Ok(is_bit_set)
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind(['\\', '/']) {
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("", path),
     }
+}
 
-    fn read_sectors(&self, to_read: u32, block_index: usize, sector_in_block: u32) -> Result<(bool, usize)> {
-        let to_read_in_sectors = to_read / sizes::SECTOR;
-        // remember first sector bit (valid data\parent or not)
-        let first_sector_bit = self.check_sector_mask(block_index, sector_in_block)?;
+fn file_name(path: &str) -> &str {
+    split_path(path).1
+}
 
-        // now look for subsequent sectors bits and stop if sector bit is different (or no more sectors)
-        let mut sectors_count = 1_u32;
-        while sectors_count < to_read_in_sectors {
-            let sector_bit = self.check_sector_mask(block_index, sector_in_block + sectors_count)?;
-            if sector_bit != first_sector_bit {
-                break;
-            }
+/// `.\name` if both files are in the same directory
+fn relative_path(path: &str, parent_path: &str) -> Option<String> {
+    let (dir, _) = split_path(path);
+    let (parent_dir, name) = split_path(parent_path);
+    if dir.eq_ignore_ascii_case(parent_dir) {
+        Some(format!(".\\{}", name))
+    } else {
+        None
+    }
+}
 
-            sectors_count += 1;
-        }
+/// Resolves the relative parent locator path against the child image `path`, with the host separators
+pub(crate) fn join_relative(path: &str, relative: &str) -> String {
+    let (dir, _) = split_path(path);
+    let relative: String = relative
+        .trim_start_matches(".\\")
+        .trim_start_matches("./")
+        .chars()
+        .map(|c| if c == '\\' || c == '/' { SEPARATOR } else { c })
+        .collect();
+    if dir.is_empty() {
+        relative
+    } else {
+        format!("{}{}{}", dir, SEPARATOR, relative)
+    }
+}
 
-        Ok((first_sector_bit, (sectors_count * sizes::SECTOR) as usize))
+impl<S: Stream> SparseExtent<S> {
+    fn block_id(&self, index: usize) -> Result<u32> {
+        self.bat.read().block_id(index)
     }
 
-    fn calc_sector_pos(&self, block_index: usize, sector_in_block: u32) -> Result<u64> {
-        let block_id = self.bat.borrow().block_id(block_index)?;
+    /// Loads the bitmap of the allocated block into the cache and runs `f` on it
+    fn with_bitmap<T>(&self, index: usize, block_id: u32, f: impl FnOnce(&mut Bitmap) -> T) -> Result<T> {
+        if let Some(bitmap) = self.bitmaps.lock().get(index, block_id) {
+            return Ok(f(bitmap));
+        }
 
-        if block_id == bat::UNUSED_BLOCK_ID {
-            return Err(Error::from(VhdError::UnexpectedBlockId(block_index, block_id)));
+        let _io = self.bitmap_io.lock();
+        if let Some(bitmap) = self.bitmaps.lock().get(index, block_id) {
+            // loaded by another thread meanwhile
+            return Ok(f(bitmap));
         }
 
-        Ok(((block_id + sector_in_block) as u64) * sizes::SECTOR_U64 + self.cached_bitmap.borrow().len() as u64)
+        let mut data = vec![0_u8; self.bitmap_size as usize];
+        self.file.read_exact_at(block_id as u64 * sizes::SECTOR_U64, &mut data)?;
+
+        let (result, evicted) = {
+            let mut bitmaps = self.bitmaps.lock();
+            let (bitmap, evicted) = bitmaps.insert(index, block_id, data);
+            (f(bitmap), evicted)
+        };
+
+        self.write_back(evicted)?;
+        Ok(result)
+    }
+
+    /// Returns whether the first sector has data in this file and the number of the following sectors
+    /// (up to `max_sectors`) in the same state
+    fn sector_run(&self, block_index: usize, block_id: u32, sector_in_block: u32, max_sectors: u32) -> Result<(bool, u32)> {
+        self.with_bitmap(block_index, block_id, |bitmap| {
+            bitmap::sector_run(&bitmap.data, sector_in_block, max_sectors)
+        })
+    }
+
+    fn calc_sector_pos(&self, block_id: u32, sector_in_block: u32) -> u64 {
+        ((block_id + sector_in_block) as u64) * sizes::SECTOR_U64 + self.bitmap_size as u64
     }
 
     fn read_parent_or_zero(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
@@ -229,24 +397,24 @@ impl SparseExtent {
         }
     }
 
-    fn read_block_data(&self, block_index: usize, offset_in_block: u32, buffer: &mut [u8]) -> Result<(bool, usize)> {
+    fn read_block_data(&self, block_index: usize, block_id: u32, offset_in_block: u32, buffer: &mut [u8]) -> Result<(bool, usize)> {
         let sector_in_block = offset_in_block / sizes::SECTOR;
         let offset_in_sector = offset_in_block % sizes::SECTOR;
         let to_read = buffer.len() as u32;
 
         let (data_exist, data_buffer) = if offset_in_sector != 0 || to_read < sizes::SECTOR {
-            // read at non sector boundary
-            let data_exist = self.check_sector_mask(block_index, sector_in_block)?;
-            (data_exist, buffer)
+            // read at non sector boundary, up to the end of the sector
+            let data_exist = self.sector_run(block_index, block_id, sector_in_block, 1)?.0;
+            let valid_len = core::cmp::min(to_read, sizes::SECTOR - offset_in_sector);
+            (data_exist, &mut buffer[..valid_len as usize])
         } else {
             // read as many full sectors as possible
-            let (data_exist, valid_len) = self.read_sectors(to_read, block_index, sector_in_block)?;
-            (data_exist, &mut buffer[..valid_len as usize])
+            let (data_exist, sectors_count) = self.sector_run(block_index, block_id, sector_in_block, to_read / sizes::SECTOR)?;
+            (data_exist, &mut buffer[..(sectors_count * sizes::SECTOR) as usize])
         };
 
         if data_exist {
-            let sector_pos = self.calc_sector_pos(block_index, sector_in_block)?;
-            let data_offset = sector_pos + offset_in_sector as u64;
+            let data_offset = self.calc_sector_pos(block_id, sector_in_block) + offset_in_sector as u64;
             self.file.read_at(data_offset, data_buffer).map(|sz| (true, sz))
         } else {
             let offset = block_index as u64 * self.header.block_size as u64 + offset_in_block as u64;
@@ -254,28 +422,41 @@ impl SparseExtent {
         }
     }
 
-    fn read_block(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+    /// Returns the length of the run at `offset` (up to `max_len`) in the same state
+    /// and its file position, `None` if the data is in the parent or zeroes
+    fn locate(&self, offset: u64, max_len: usize) -> Result<(usize, Option<u64>)> {
         let block_size = self.header.block_size as u64;
         let block_index = (offset / block_size) as usize;
         let offset_in_block = (offset % block_size) as u32;
-        let to_read = core::cmp::min(buffer.len() as u32, self.header.block_size - offset_in_block);
-        let block_buffer = &mut buffer[..to_read as usize];
+        let to_read = core::cmp::min(max_len as u64, (self.header.block_size - offset_in_block) as u64) as u32;
 
-        let block_in_current_file = self.populate_block_bitmap(block_index)?;
-        if block_in_current_file {
-            self.read_block_data(block_index, offset_in_block, block_buffer).map(|r| r.1)
-        } else {
-            self.read_parent_or_zero(offset, block_buffer)
+        let block_id = self.block_id(block_index)?;
+        if block_id == bat::UNUSED_BLOCK_ID {
+            return Ok((to_read as usize, None));
         }
+
+        let sector_in_block = offset_in_block / sizes::SECTOR;
+        let offset_in_sector = offset_in_block % sizes::SECTOR;
+        let (data_exist, len) = if offset_in_sector != 0 || to_read < sizes::SECTOR {
+            // up to the end of the sector
+            let data_exist = self.sector_run(block_index, block_id, sector_in_block, 1)?.0;
+            (data_exist, core::cmp::min(to_read, sizes::SECTOR - offset_in_sector))
+        } else {
+            let (data_exist, sectors_count) = self.sector_run(block_index, block_id, sector_in_block, to_read / sizes::SECTOR)?;
+            (data_exist, sectors_count * sizes::SECTOR)
+        };
+
+        let data_pos = self.calc_sector_pos(block_id, sector_in_block) + offset_in_sector as u64;
+        Ok((len as usize, if data_exist { Some(data_pos) } else { None }))
     }
 
     fn write_block(&self, offset: u64, data: &[u8]) -> Result<usize> {
         let block_size = self.header.block_size as u64;
         let block_index = (offset / block_size) as usize;
 
-        let block_in_current_file = self.populate_block_bitmap(block_index)?;
-        if !block_in_current_file {
-            self.allocate_block(block_index)?;
+        let mut block_id = self.block_id(block_index)?;
+        if block_id == bat::UNUSED_BLOCK_ID {
+            block_id = self.allocate_block(block_index)?;
         }
 
         let offset_in_block = (offset % block_size) as u32;
@@ -290,7 +471,7 @@ impl SparseExtent {
             // read the sector
             let mut sector_buffer = unsafe { tools::alloc_buffer(sizes::SECTOR as usize) };
             let sector_offset_in_block = math::round_down(offset_in_block, sizes::SECTOR);
-            let (data_exists, _) = self.read_block_data(block_index, sector_offset_in_block, &mut sector_buffer)?;
+            let (data_exists, _) = self.read_block_data(block_index, block_id, sector_offset_in_block, &mut sector_buffer)?;
 
             // update it
             let start = offset_in_sector as usize;
@@ -298,61 +479,59 @@ impl SparseExtent {
             sector_buffer[start..end].copy_from_slice(&data[..to_write]);
 
             // and write back
-            let pos = self.calc_sector_pos(block_index, sector_in_block)?;
+            let pos = self.calc_sector_pos(block_id, sector_in_block);
             self.file.write_all_at(pos, &sector_buffer)?;
 
             if !data_exists {
                 // the sector was read from the parent
-                self.mark_cached_bitmap_dirty(sector_in_block as usize);
+                self.mark_sectors(block_index, block_id, sector_in_block, 1)?;
             }
         } else {
             // write as much whole sectors as possible
             to_write = math::round_down(to_write, sizes::SECTOR as usize);
-            let pos = self.calc_sector_pos(block_index, sector_in_block)?;
+            let pos = self.calc_sector_pos(block_id, sector_in_block);
             self.file.write_all_at(pos, &data[..to_write])?;
 
-            // update bitmap bits for written sectors
-            let mut i = 0;
-            let mut sector_in_block = sector_in_block as usize;
-            while i < to_write {
-                self.mark_cached_bitmap_dirty(sector_in_block);
-                sector_in_block += 1;
-                i += sizes::SECTOR as usize;
-            }
+            // update bitmap bits for written sectors, the data is already there for concurrent readers
+            self.mark_sectors(block_index, block_id, sector_in_block, (to_write / sizes::SECTOR as usize) as u32)?;
         }
 
         Ok(to_write)
     }
 
-    fn allocate_block(&self, block_index: usize) -> Result<()> {
-        let block_id = self.bat.borrow().block_id(block_index)?;
-        if block_id != bat::UNUSED_BLOCK_ID {
-            return Err(Error::from(VhdError::UnexpectedBlockId(block_index, block_id)));
-        }
-
-        self.save_cached_bitmap()?;
-
-        let mut bitmap = self.cached_bitmap.borrow_mut();
-        // initial block bitmap should be zeroed
-        unsafe { core::ptr::write_bytes(bitmap.as_mut_ptr(), 0, bitmap.len()) };
+    /// Returns the new block id, or the existing one if another thread has allocated the block meanwhile
+    fn allocate_block(&self, block_index: usize) -> Result<u32> {
+        let block_pos = {
+            let mut next_block_pos = self.next_block_pos.lock();
+            let block_id = self.block_id(block_index)?;
+            if block_id != bat::UNUSED_BLOCK_ID {
+                return Ok(block_id);
+            }
 
-        let mut next_block_pos = self.next_block_pos.borrow_mut();
-        let block_pos = *next_block_pos;
-        *next_block_pos += bitmap.len() as u64 + self.header.block_size as u64;
-        *self.cached_block_index.borrow_mut() = block_index;
+            let block_pos = *next_block_pos;
+            *next_block_pos += self.bitmap_size as u64 + self.header.block_size as u64;
+            block_pos
+        };
+        let block_end = block_pos + self.bitmap_size as u64 + self.header.block_size as u64;
 
-        if block_pos < self.file.size()? {
-            // The footer is here! Have to override it with zeroes.
-            let zeroes = StructBuffer::<super::VhdFooterRecord>::zeroed();
-            self.file.write_all_at(block_pos, zeroes.buffer())?;
-        }
+        // The footer may be here! The block bitmap is zeroed over it.
+        self.file.write_all_at(block_pos, &vec![0_u8; self.bitmap_size as usize])?;
 
         // write one byte at the end of the block to expand the file (OS will fill it with zeroes)
-        self.file.write_all_at(*next_block_pos - 1, unsafe { 0_u8.as_byte_slice() })?;
+        self.file.write_all_at(block_end - 1, unsafe { 0_u8.as_byte_slice() })?;
 
-        // update BAT in memory...
+        // update BAT in memory, the block is visible to readers from now on...
         let block_pos_in_sectors = (block_pos / sizes::SECTOR_U64) as u32;
-        self.bat.borrow_mut().set_block_id(block_index, block_pos_in_sectors);
+        {
+            let mut bat = self.bat.write();
+            let block_id = bat.block_id(block_index)?;
+            if block_id != bat::UNUSED_BLOCK_ID {
+                // another thread has allocated the block after the reservation, the reserved space stays unused
+                return Ok(block_id);
+            }
+
+            bat.set_block_id(block_index, block_pos_in_sectors);
+        }
 
         // ...and in the file
         let swapped_id = block_pos_in_sectors.swap_bytes();
@@ -363,32 +542,60 @@ impl SparseExtent {
         // TODO: It might be usefull to write VHD footer after each block allocation.
         //       This will reduce speed but greatly increase error tolerance.
 
-        Ok(())
+        Ok(block_pos_in_sectors)
     }
 
-    fn save_cached_bitmap(&self) -> Result<()> {
-        let cached_block_index = *self.cached_block_index.borrow();
-        let mut cached_bitmap_dirty = self.cached_bitmap_dirty.borrow_mut();
-        if cached_block_index == INVALID_CACHE_INDEX || !(*cached_bitmap_dirty) {
-            // no bitmap cached or no changes since previous write
-            return Ok(());
+    /// Writes the bitmaps taken out of the cache, the ones failed to write are put back.
+    /// The caller holds the bitmap I/O lock.
+    fn write_back(&self, bitmaps: impl IntoIterator<Item = Bitmap>) -> Result<()> {
+        let mut result = Ok(());
+        for bitmap in bitmaps {
+            let bitmap_pos = bitmap.block_id as u64 * sizes::SECTOR_U64;
+            if let Err(err) = self.file.write_all_at(bitmap_pos, bitmap.data.as_slice()) {
+                self.bitmaps.lock().restore(bitmap);
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
         }
 
-        let cached_block_id = self.bat.borrow().block_id(cached_block_index)?;
-        if cached_block_id == bat::UNUSED_BLOCK_ID {
-            return Err(Error::from(VhdError::UnexpectedBlockId(cached_block_index, cached_block_id)));
-        }
+        result
+    }
+
+    fn save_bitmaps(&self) -> Result<()> {
+        let _io = self.bitmap_io.lock();
+        let dirty = self.bitmaps.lock().take_dirty();
+        self.write_back(dirty)
+    }
 
-        let bitmap_pos = cached_block_id as u64 * sizes::SECTOR_U64;
-        self.file.write_all_at(bitmap_pos, self.cached_bitmap.borrow_mut().as_mut_slice())?;
-        *cached_bitmap_dirty = false;
+    fn clear_sectors(&self, block_index: usize, block_id: u32, sector_in_block: u32, count: u32) -> Result<()> {
+        self.with_bitmap(block_index, block_id, |bitmap| {
+            if bitmap::sector_run(&bitmap.data, sector_in_block, count) != (false, count) {
+                bitmap::clear_sectors(&mut bitmap.data, sector_in_block, count);
+                bitmap.dirty = true;
+            }
+        })
+    }
+
+    /// Marks the block as not allocated, its space stays in the file as VHD blocks can only be appended
+    fn free_block(&self, block_index: usize) -> Result<()> {
+        let raw_block_pos_in_sectors_pos = self.header.table_offset + (block_index as u64 * 4);
+        self.file
+            .write_all_at(raw_block_pos_in_sectors_pos, unsafe { bat::UNUSED_BLOCK_ID.as_byte_slice() })?;
+        self.bat.write().set_block_id(block_index, bat::UNUSED_BLOCK_ID);
 
+        // the cached bitmap is dropped with the block, a new block of the same index starts clean
+        self.bitmaps.lock().invalidate(block_index);
         Ok(())
     }
 
-    fn mark_cached_bitmap_dirty(&self, sector_in_block: usize) {
-        let sector_mask = calc_sector_mask(sector_in_block);
-        self.cached_bitmap.borrow_mut()[sector_in_block / 8] |= sector_mask;
-        *self.cached_bitmap_dirty.borrow_mut() = true;
+    fn mark_sectors(&self, block_index: usize, block_id: u32, sector_in_block: u32, count: u32) -> Result<()> {
+        self.with_bitmap(block_index, block_id, |bitmap| {
+            // rewrites of the data already in the file do not change the bitmap
+            if bitmap::sector_run(&bitmap.data, sector_in_block, count) != (true, count) {
+                bitmap::set_sectors(&mut bitmap.data, sector_in_block, count);
+                bitmap.dirty = true;
+            }
+        })
     }
 }
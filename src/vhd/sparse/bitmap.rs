@@ -0,0 +1,282 @@
+use crate::prelude::*;
+
+/// Sector bitmap of one block, the first sector is the most significant bit of the first byte
+pub struct Bitmap {
+    pub index: usize,
+    /// Block the bitmap is loaded from and written back to
+    pub block_id: u32,
+    pub data: Vec<u8>,
+    pub dirty: bool,
+    last_used: u64,
+}
+
+pub const INVALID_INDEX: usize = usize::MAX;
+
+/// Least recently used block bitmaps, the dirty ones are written back by the extent on eviction and flush
+///
+/// The cache does no I/O, the evicted dirty bitmaps are handed to the extent to write them out of the cache lock.
+pub struct BitmapCache {
+    entries: Vec<Bitmap>,
+    capacity: usize,
+    tick: u64,
+}
+
+impl BitmapCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+            capacity: core::cmp::max(capacity, 1),
+            tick: 0,
+        }
+    }
+
+    /// A bitmap of another block with the same index is stale, the block was freed meanwhile
+    pub fn get(&mut self, index: usize, block_id: u32) -> Option<&mut Bitmap> {
+        self.tick += 1;
+        let bitmap = self.entries.iter_mut().find(|bitmap| bitmap.index == index)?;
+        if bitmap.block_id != block_id {
+            bitmap.index = INVALID_INDEX;
+            bitmap.dirty = false;
+            bitmap.last_used = 0;
+            return None;
+        }
+
+        bitmap.last_used = self.tick;
+        Some(bitmap)
+    }
+
+    /// Adds the clean `data` of the `index` bitmap, returns it with the evicted one if that was dirty
+    pub fn insert(&mut self, index: usize, block_id: u32, data: Vec<u8>) -> (&mut Bitmap, Option<Bitmap>) {
+        self.tick += 1;
+        let bitmap = Bitmap {
+            index,
+            block_id,
+            data,
+            dirty: false,
+            last_used: self.tick,
+        };
+
+        if self.entries.len() < self.capacity {
+            self.entries.push(bitmap);
+            return (self.entries.last_mut().unwrap(), None);
+        }
+
+        let (position, _) = self.entries.iter().enumerate().min_by_key(|(_, bitmap)| bitmap.last_used).unwrap();
+        let victim = core::mem::replace(&mut self.entries[position], bitmap);
+        let evicted = if victim.dirty && victim.index != INVALID_INDEX {
+            Some(victim)
+        } else {
+            None
+        };
+        (&mut self.entries[position], evicted)
+    }
+
+    /// Marks the bitmap dirty again after a failed write, an evicted one is put back over the capacity
+    pub fn restore(&mut self, bitmap: Bitmap) {
+        match self.entries.iter_mut().find(|cached| cached.index == bitmap.index) {
+            Some(cached) => cached.dirty |= cached.block_id == bitmap.block_id,
+            None => {
+                self.tick += 1;
+                self.entries.push(Bitmap {
+                    dirty: true,
+                    last_used: self.tick,
+                    ..bitmap
+                });
+            }
+        }
+    }
+
+    /// Drops the bitmap of the freed block
+    pub fn invalidate(&mut self, index: usize) {
+        for bitmap in self.entries.iter_mut().filter(|bitmap| bitmap.index == index) {
+            bitmap.index = INVALID_INDEX;
+            bitmap.dirty = false;
+            bitmap.last_used = 0;
+        }
+    }
+
+    /// Copies of the dirty bitmaps to be written back, the cached ones are clean from now on
+    pub fn take_dirty(&mut self) -> Vec<Bitmap> {
+        self.entries
+            .iter_mut()
+            .filter(|bitmap| bitmap.dirty && bitmap.index != INVALID_INDEX)
+            .map(|bitmap| {
+                bitmap.dirty = false;
+                Bitmap {
+                    index: bitmap.index,
+                    block_id: bitmap.block_id,
+                    data: bitmap.data.clone(),
+                    dirty: true,
+                    last_used: bitmap.last_used,
+                }
+            })
+            .collect()
+    }
+
+    /// The least recently used bitmaps are dropped, the dirty ones are returned to be written back
+    pub fn set_capacity(&mut self, capacity: usize) -> Vec<Bitmap> {
+        self.capacity = core::cmp::max(capacity, 1);
+        if self.entries.len() <= self.capacity {
+            return Vec::new();
+        }
+
+        self.entries.sort_by_key(|bitmap| core::cmp::Reverse(bitmap.last_used));
+        self.entries
+            .split_off(self.capacity)
+            .into_iter()
+            .filter(|bitmap| bitmap.dirty && bitmap.index != INVALID_INDEX)
+            .collect()
+    }
+}
+
+fn word(bitmap: &[u8], word_index: usize) -> u64 {
+    let mut bytes = [0_u8; 8];
+    bytes.copy_from_slice(&bitmap[word_index * 8..word_index * 8 + 8]);
+    u64::from_be_bytes(bytes)
+}
+
+pub fn is_sector_set(bitmap: &[u8], sector: u32) -> bool {
+    bitmap[sector as usize / 8] & (0x80 >> (sector % 8)) != 0
+}
+
+/// Returns the state of the `first` sector and the number of the following sectors (up to `max_sectors`) in the same state.
+/// The bitmap is scanned 64 sectors at a time, its size is a multiple of a sector.
+pub fn sector_run(bitmap: &[u8], first: u32, max_sectors: u32) -> (bool, u32) {
+    let set = is_sector_set(bitmap, first);
+    let end = first + max_sectors;
+
+    let mut sector = first;
+    while sector < end {
+        let word_index = sector / 64;
+        let word = word(bitmap, word_index as usize);
+        // the sectors in the other state become 1 bits, the ones before `sector` are shifted out
+        let other = (if set { !word } else { word }) << (sector % 64);
+        if other != 0 {
+            let other_sector = sector + other.leading_zeros();
+            return (set, core::cmp::min(other_sector, end) - first);
+        }
+
+        sector = (word_index + 1) * 64;
+    }
+
+    (set, max_sectors)
+}
+
+/// Sets the bits of `count` sectors starting from `first`
+pub fn set_sectors(bitmap: &mut [u8], first: u32, count: u32) {
+    let mut sector = first;
+    let end = first + count;
+    while sector < end {
+        if sector & 7 == 0 && end - sector >= 8 {
+            // whole bytes
+            let bytes = ((end - sector) / 8) as usize;
+            let start = sector as usize / 8;
+            for b in bitmap[start..start + bytes].iter_mut() {
+                *b = 0xFF;
+            }
+
+            sector += bytes as u32 * 8;
+        } else {
+            bitmap[sector as usize / 8] |= 0x80 >> (sector % 8);
+            sector += 1;
+        }
+    }
+}
+
+/// Clears the bits of `count` sectors starting from `first`
+pub fn clear_sectors(bitmap: &mut [u8], first: u32, count: u32) {
+    let mut sector = first;
+    let end = first + count;
+    while sector < end {
+        if sector & 7 == 0 && end - sector >= 8 {
+            // whole bytes
+            let bytes = ((end - sector) / 8) as usize;
+            let start = sector as usize / 8;
+            for b in bitmap[start..start + bytes].iter_mut() {
+                *b = 0;
+            }
+
+            sector += bytes as u32 * 8;
+        } else {
+            bitmap[sector as usize / 8] &= !(0x80 >> (sector % 8));
+            sector += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sector_run_test() {
+        let mut bitmap = vec![0_u8; 512];
+        assert_eq!((false, 4096), sector_run(&bitmap, 0, 4096));
+        assert_eq!((false, 10), sector_run(&bitmap, 100, 10));
+
+        set_sectors(&mut bitmap, 5, 200);
+        assert_eq!(0b0000_0111, bitmap[0]);
+        assert_eq!(0xFF, bitmap[24]);
+        assert_eq!(0b1111_1000, bitmap[25]);
+        assert_eq!(0, bitmap[26]);
+
+        assert_eq!((false, 5), sector_run(&bitmap, 0, 4096));
+        assert_eq!((false, 3), sector_run(&bitmap, 2, 3));
+        assert_eq!((true, 200), sector_run(&bitmap, 5, 4091));
+        assert_eq!((true, 137), sector_run(&bitmap, 68, 4000));
+        assert_eq!((true, 1), sector_run(&bitmap, 204, 1));
+        assert_eq!((false, 3891), sector_run(&bitmap, 205, 4096 - 205));
+
+        set_sectors(&mut bitmap, 4095, 1);
+        assert_eq!((false, 3890), sector_run(&bitmap, 205, 4096 - 205));
+        assert_eq!((true, 1), sector_run(&bitmap, 4095, 1));
+
+        clear_sectors(&mut bitmap, 6, 100);
+        assert_eq!(0b0000_0100, bitmap[0]);
+        assert_eq!(0, bitmap[12]);
+        assert_eq!(0b0011_1111, bitmap[13]);
+        assert_eq!((false, 100), sector_run(&bitmap, 6, 200));
+        assert_eq!((true, 99), sector_run(&bitmap, 106, 200));
+    }
+
+    #[test]
+    fn bitmap_cache_test() {
+        let mut cache = BitmapCache::new(2);
+        assert!(cache.get(1, 10).is_none());
+        cache.insert(1, 10, vec![1; 512]);
+        let (bitmap, evicted) = cache.insert(2, 20, vec![0; 512]);
+        bitmap.dirty = true;
+        assert!(evicted.is_none());
+
+        // 1 is used after 2, so the dirty 2 is evicted
+        assert_eq!(1, cache.get(1, 10).unwrap().data[0]);
+        let (bitmap, evicted) = cache.insert(3, 30, vec![3; 512]);
+        assert!(!bitmap.dirty);
+        let evicted = evicted.unwrap();
+        assert_eq!((2, 20), (evicted.index, evicted.block_id));
+        assert!(cache.get(2, 20).is_none());
+
+        // the failed write puts it back over the capacity
+        cache.restore(evicted);
+        assert!(cache.get(2, 20).unwrap().dirty);
+        let dirty = cache.take_dirty();
+        assert_eq!(1, dirty.len());
+        assert!(!cache.get(2, 20).unwrap().dirty);
+        cache.restore(dirty.into_iter().next().unwrap());
+        assert!(cache.get(2, 20).unwrap().dirty);
+
+        // the block 10 was freed and the index reallocated
+        assert!(cache.get(1, 40).is_none());
+        assert!(cache.get(1, 10).is_none());
+
+        cache.invalidate(3);
+        assert!(cache.get(3, 30).is_none());
+
+        cache.insert(4, 40, vec![4; 512]).0.dirty = true;
+        assert!(cache.get(2, 20).is_some());
+        let dropped = cache.set_capacity(1);
+        assert_eq!(1, dropped.len());
+        assert_eq!(4, dropped[0].index);
+        assert_eq!(1, cache.take_dirty().len());
+    }
+}
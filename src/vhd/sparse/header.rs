@@ -32,6 +32,17 @@ pub(crate) struct VhdSparseHeaderRecord {
 
 const SPARSE_COOKIE_ID: u64 = 0x6573_7261_7073_7863; // big endian "cxsparse"
 
+pub(crate) const PLATFORM_CODE_W2RU: u32 = 0x5732_7275; // "W2ru", Windows relative path, UTF-16LE
+pub(crate) const PLATFORM_CODE_W2KU: u32 = 0x5732_6b75; // "W2ku", Windows absolute path, UTF-16LE
+
+/// Where the parent locator data of the differencing image is kept in the file
+#[derive(Copy, Clone)]
+pub(crate) struct ParentLocator {
+    pub platform_code: u32,
+    pub data_length: u32,
+    pub data_offset: u64,
+}
+
 impl VhdSparseHeaderRecord {
     fn swap_bytes(&mut self) {
         self.data_offset = self.data_offset.swap_bytes();
@@ -61,8 +72,8 @@ pub struct SparseHeader {
     pub block_size: u32,
     pub parent_id: Uuid,
     pub parent_name: String,
-    pub(crate) _parent_time_stamp: u32,
-    pub(crate) _parent_locators: [ParentLocatorRecord; 8],
+    pub parent_time_stamp: u32,
+    pub(crate) parent_locators: [ParentLocatorRecord; 8],
 }
 
 impl SparseHeader {
@@ -80,8 +91,8 @@ impl SparseHeader {
             block_size,
             parent_id: Uuid::nil(),
             parent_name: String::new(),
-            _parent_time_stamp: 0,
-            _parent_locators: unsafe { core::mem::zeroed() },
+            parent_time_stamp: 0,
+            parent_locators: unsafe { core::mem::zeroed() },
         }
     }
 
@@ -100,8 +111,12 @@ impl SparseHeader {
             return Err(Error::from(VhdError::InvalidHeaderChecksum));
         }
 
-        let safe_copy = unsafe { &header.parent_unicode_name }; // parent_unicode_name is inside packed struct and requires unsafe block to borrow
-        let parent_name = String::from_utf16_lossy(safe_copy).trim_end_matches('\0').to_string();
+        // parent_unicode_name is inside packed struct and can't be borrowed, and it is big endian UTF-16
+        let mut name = header.parent_unicode_name;
+        for c in name.iter_mut() {
+            *c = u16::from_be(*c);
+        }
+        let parent_name = String::from_utf16_lossy(&name).trim_end_matches('\0').to_string();
 
         Ok(Self {
             data_offset: header.data_offset,
@@ -110,9 +125,9 @@ impl SparseHeader {
             max_table_entries: header.max_table_entries,
             block_size: header.block_size,
             parent_id: header.parent_id,
-            _parent_time_stamp: header.parent_time_stamp,
+            parent_time_stamp: header.parent_time_stamp,
             parent_name,
-            _parent_locators: header.parent_locators,
+            parent_locators: header.parent_locators,
         })
     }
 
@@ -124,8 +139,16 @@ impl SparseHeader {
         header.header_version = self.header_version;
         header.max_table_entries = self.max_table_entries;
         header.block_size = self.block_size;
-
-        // TODO: differencing
+        header.parent_id = self.parent_id;
+        header.parent_time_stamp = self.parent_time_stamp;
+        header.parent_locators = self.parent_locators;
+
+        // the last character is always zero
+        let mut name = [0_u16; 256];
+        for (c, n) in self.parent_name.encode_utf16().take(255).zip(name.iter_mut()) {
+            *n = c.to_be();
+        }
+        header.parent_unicode_name = name;
 
         let checksum = super::calc_header_bytes_checksum(&header);
         header.checksum = checksum;
@@ -133,4 +156,25 @@ impl SparseHeader {
 
         stream.write_all_at(pos, header.buffer())
     }
+
+    /// The used parent locators
+    pub(crate) fn locators(&self) -> impl Iterator<Item = ParentLocator> + '_ {
+        self.parent_locators
+            .iter()
+            .filter(|entry| entry.platform_code != 0 && entry.platform_data_length != 0)
+            .map(|entry| ParentLocator {
+                platform_code: entry.platform_code,
+                data_length: entry.platform_data_length,
+                data_offset: entry.platform_data_offset,
+            })
+    }
+
+    pub(crate) fn set_locator(&mut self, index: usize, locator: ParentLocator) {
+        let entry = &mut self.parent_locators[index];
+        entry.platform_code = locator.platform_code;
+        // Windows keeps the space in bytes, not in sectors as the specification says
+        entry.platform_data_space = math::round_up(locator.data_length, crate::sizes::SECTOR);
+        entry.platform_data_length = locator.data_length;
+        entry.platform_data_offset = locator.data_offset;
+    }
 }
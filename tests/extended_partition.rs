@@ -38,6 +38,7 @@ fn dump_image_info<T: DiskImage>(image: &T) {
 fn extended_partition() {
     println!();
 
+    #[cfg(windows)]
     if let Ok(d) = PhysicalDisk::open(1) {
         let disk = PartitionedDisk::new(d).unwrap();
         dump_layout(disk.layout());
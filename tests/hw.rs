@@ -1,3 +1,4 @@
+#![cfg(windows)]
 mod shared;
 use rdisk::{PartitionedDisk, PhysicalDisk};
 use shared::*;
@@ -182,3 +182,121 @@ fn dynamic_vhd_create() {
 
     let _ = std::fs::remove_file(&name);
 }
+
+#[test]
+fn differencing_vhd_moved() {
+    let (old_dir, new_dir) = ("diff_old", "diff_new");
+    let _ = std::fs::remove_dir_all(old_dir);
+    let _ = std::fs::remove_dir_all(new_dir);
+    std::fs::create_dir(old_dir).unwrap();
+
+    let parent = VhdImage::create_dynamic(format!("{}/parent.vhd", old_dir), 3 * 1024 * 1024).unwrap();
+    parent.write_all_at(0, b"asdf").unwrap();
+    drop(parent);
+    let child = VhdImage::create_differencing(format!("{}/child.vhd", old_dir), format!("{}/parent.vhd", old_dir)).unwrap();
+    drop(child);
+
+    // only the relative locator still points to the parent
+    std::fs::rename(old_dir, new_dir).unwrap();
+    let child = VhdImage::open(format!("{}/child.vhd", new_dir)).unwrap();
+    let mut buffer = vec![0; 4];
+    child.read_exact_at(0, &mut buffer).unwrap();
+    assert_eq!(buffer, b"asdf");
+    drop(child);
+
+    let _ = std::fs::remove_dir_all(new_dir);
+}
+
+#[test]
+fn partition_bounded_io() {
+    if let Some((disk, _full_path)) = open_test_vhd_copy("vhd_dynamic_small.vhd") {
+        let disk = PartitionedDisk::new(disk).unwrap();
+        let partition = disk.partitions().next().unwrap();
+        let length = partition.length();
+        assert_eq!(length, partition.capacity().unwrap());
+        assert_eq!(disk.geometry().unwrap().bytes_per_sector, partition.logical_sector_size().unwrap());
+
+        // the write is cut at the partition end
+        assert_eq!(2, partition.write_at(length - 2, b"asdf").unwrap());
+        partition.flush().unwrap();
+
+        let mut buffer = vec![0; 4];
+        assert_eq!(2, partition.read_at(length - 2, &mut buffer).unwrap());
+        assert_eq!(&buffer[..2], b"as");
+
+        disk.read_exact_at(partition.offset() + length - 2, &mut buffer[..2]).unwrap();
+        assert_eq!(&buffer[..2], b"as");
+
+        assert_eq!(0, partition.read_at(length, &mut buffer).unwrap());
+        assert!(matches!(partition.read_at(length + 1, &mut buffer), Err(Error::ReadBeyondEOD)));
+        assert!(matches!(partition.write_at(length + 1, b"asdf"), Err(Error::WriteBeyondEOD)));
+    }
+}
+
+#[test]
+fn vhd_parallel_io() {
+    use rdisk::MemoryStream;
+    use std::sync::Arc;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<VhdImage>();
+    assert_send_sync::<PartitionedDisk<VhdImage>>();
+
+    const THREADS: u64 = 8;
+    const CHUNK: u64 = 64 * 1024;
+    let size = 32 * 1024 * 1024;
+
+    let stream = MemoryStream::new();
+    let disk = VhdImage::create_dynamic_stream(stream.clone(), size).unwrap();
+    // the first half is allocated before the threads start
+    let pattern = |thread: u64, chunk: u64| vec![(thread * 31 + chunk) as u8; CHUNK as usize];
+    for chunk in 0..size / 2 / CHUNK {
+        disk.write_all_at(chunk * CHUNK, &pattern(0, chunk)).unwrap();
+    }
+
+    let disk = Arc::new(disk);
+    let threads: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let disk = disk.clone();
+            std::thread::spawn(move || {
+                let mut buffer = vec![0_u8; CHUNK as usize];
+                for chunk in 0..size / 2 / CHUNK {
+                    // readers of the allocated half...
+                    disk.read_exact_at(chunk * CHUNK, &mut buffer).unwrap();
+                    assert_eq!(pattern(0, chunk), buffer);
+
+                    // ...mixed with writes allocating blocks in the second half, each thread has its own chunks
+                    if chunk % THREADS == thread {
+                        let offset = size / 2 + chunk * CHUNK;
+                        disk.write_all_at(offset + 7, &pattern(thread, chunk)[7..]).unwrap();
+                        disk.write_all_at(offset, &pattern(thread, chunk)[..7]).unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // the last reference, the image is flushed
+    drop(disk);
+
+    let disk = PartitionedDisk::new(VhdImage::open_stream(stream).unwrap()).unwrap();
+    let disk = Arc::new(disk);
+    let threads: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let disk = disk.clone();
+            std::thread::spawn(move || {
+                let mut buffer = vec![0_u8; CHUNK as usize];
+                for chunk in (0..size / 2 / CHUNK).filter(|chunk| chunk % THREADS == thread) {
+                    disk.read_exact_at(size / 2 + chunk * CHUNK, &mut buffer).unwrap();
+                    assert_eq!(pattern(thread, chunk), buffer);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}